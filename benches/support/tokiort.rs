@@ -97,6 +97,10 @@ impl<T> TokioIo<T> {
     pub fn inner(self) -> T {
         self.inner
     }
+
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
 }
 
 impl<T> hyper::rt::Stats for TokioIo<T> {
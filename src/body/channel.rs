@@ -0,0 +1,238 @@
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use bytes::Bytes;
+use futures_channel::{mpsc, oneshot};
+use futures_core::{ready, Stream};
+use http::HeaderMap;
+use http_body::{Body, Frame};
+
+/// Creates a body that is sent from a [`Sender`], such as from another task.
+///
+/// At most `capacity` chunks of data wait in the channel. Past that, the
+/// sender waits for the body to be read. A connection only reads a body as it
+/// can send it, when the HTTP/1 transport can be written to or the HTTP/2
+/// stream has flow control capacity, so a slow peer slows down the sender
+/// instead of data building up in memory.
+///
+/// ```
+/// use hyper::body::Bytes;
+///
+/// # async fn run() -> hyper::Result<()> {
+/// let (mut tx, body) = hyper::body::channel(4);
+/// let res = hyper::Response::new(body);
+///
+/// tokio::spawn(async move {
+///     tx.send_data(Bytes::from("hello ")).await?;
+///     tx.send_data(Bytes::from("world")).await?;
+///     Ok::<_, hyper::Error>(())
+/// });
+/// # drop(res);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Panics
+///
+/// The capacity must be at least 1.
+pub fn channel(capacity: usize) -> (Sender, Channel) {
+    assert!(capacity > 0, "body channel capacity must be at least 1");
+    // The sender has a slot of its own.
+    let (data_tx, data_rx) = mpsc::channel(capacity - 1);
+    let (trailers_tx, trailers_rx) = oneshot::channel();
+    let tx = Sender {
+        data_tx,
+        trailers_tx,
+    };
+    let rx = Channel {
+        data_rx,
+        data_done: false,
+        trailers_rx: Some(trailers_rx),
+    };
+    (tx, rx)
+}
+
+/// Sends the data and trailers of a [`Channel`] body.
+///
+/// Dropping the sender ends the body. Use [`abort`](Sender::abort) to end it
+/// with an error instead, so a truncated body isn't mistaken for a complete
+/// one.
+pub struct Sender {
+    data_tx: mpsc::Sender<Result<Bytes, crate::Error>>,
+    trailers_tx: oneshot::Sender<HeaderMap>,
+}
+
+/// A body sent from a [`Sender`].
+///
+/// Created with [`channel`].
+pub struct Channel {
+    data_rx: mpsc::Receiver<Result<Bytes, crate::Error>>,
+    data_done: bool,
+    trailers_rx: Option<oneshot::Receiver<HeaderMap>>,
+}
+
+// ===== impl Sender =====
+
+impl Sender {
+    /// Checks whether the channel has room for another chunk of data.
+    ///
+    /// This errors if the body has been dropped.
+    pub fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<crate::Result<()>> {
+        self.data_tx
+            .poll_ready(cx)
+            .map_err(|_| crate::Error::new_closed())
+    }
+
+    /// Sends a chunk of data, once the channel has room for it.
+    pub async fn send_data(&mut self, chunk: Bytes) -> crate::Result<()> {
+        crate::common::future::poll_fn(|cx| self.poll_ready(cx)).await?;
+        self.data_tx
+            .try_send(Ok(chunk))
+            .map_err(|_| crate::Error::new_closed())
+    }
+
+    /// Tries to send a chunk of data without waiting.
+    ///
+    /// The chunk is given back if the channel is full, or the body has been
+    /// dropped. This is mostly useful outside of an async context.
+    pub fn try_send_data(&mut self, chunk: Bytes) -> Result<(), Bytes> {
+        self.data_tx
+            .try_send(Ok(chunk))
+            .map_err(|err| err.into_inner().unwrap_or_else(|_| unreachable!()))
+    }
+
+    /// Sends the trailers, ending the body once the data sent so far has been
+    /// read.
+    pub fn send_trailers(self, trailers: HeaderMap) -> crate::Result<()> {
+        self.trailers_tx
+            .send(trailers)
+            .map_err(|_| crate::Error::new_closed())
+    }
+
+    /// Ends the body with an error, after the data sent so far.
+    ///
+    /// [`Error::is_body_write_aborted`](crate::Error::is_body_write_aborted)
+    /// returns true for the error.
+    pub fn abort(self) {
+        let _ = self
+            .data_tx
+            // clone so the send works even if the channel is full
+            .clone()
+            .try_send(Err(crate::Error::new_body_write_aborted()));
+    }
+
+    /// Returns whether the body has been dropped, so nothing more can be
+    /// sent.
+    pub fn is_closed(&self) -> bool {
+        self.data_tx.is_closed()
+    }
+}
+
+impl fmt::Debug for Sender {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sender").finish()
+    }
+}
+
+// ===== impl Channel =====
+
+impl Body for Channel {
+    type Data = Bytes;
+    type Error = crate::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        if !self.data_done {
+            match ready!(Pin::new(&mut self.data_rx).poll_next(cx)) {
+                Some(Ok(chunk)) => return Poll::Ready(Some(Ok(Frame::data(chunk)))),
+                Some(Err(err)) => {
+                    self.data_done = true;
+                    self.trailers_rx = None;
+                    return Poll::Ready(Some(Err(err)));
+                }
+                None => self.data_done = true,
+            }
+        }
+
+        let rx = match self.trailers_rx {
+            Some(ref mut rx) => rx,
+            None => return Poll::Ready(None),
+        };
+        let trailers = ready!(Pin::new(rx).poll(cx));
+        self.trailers_rx = None;
+        // A dropped sender means there are no trailers.
+        Poll::Ready(trailers.ok().map(|trailers| Ok(Frame::trailers(trailers))))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.data_done && self.trailers_rx.is_none()
+    }
+}
+
+impl fmt::Debug for Channel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Channel")
+            .field("data_done", &self.data_done)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http_body_util::BodyExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn data_then_trailers() {
+        let (mut tx, body) = channel(2);
+        tx.send_data(Bytes::from("hello")).await.unwrap();
+        tx.send_data(Bytes::from(" world")).await.unwrap();
+        let mut trailers = HeaderMap::new();
+        trailers.insert("chunky-trailer", "data".parse().unwrap());
+        tx.send_trailers(trailers.clone()).unwrap();
+
+        let collected = body.collect().await.unwrap();
+        assert_eq!(collected.trailers(), Some(&trailers));
+        assert_eq!(collected.to_bytes(), "hello world");
+    }
+
+    #[tokio::test]
+    async fn capacity_bounds_sender() {
+        let (mut tx, mut body) = channel(1);
+        tx.try_send_data(Bytes::from("one")).unwrap();
+        assert_eq!(
+            tx.try_send_data(Bytes::from("two")).unwrap_err(),
+            Bytes::from("two")
+        );
+
+        let frame = body.frame().await.unwrap().unwrap();
+        assert_eq!(frame.into_data().unwrap(), "one");
+        tx.try_send_data(Bytes::from("two")).unwrap();
+    }
+
+    #[tokio::test]
+    async fn abort_errors_body() {
+        let (mut tx, mut body) = channel(1);
+        tx.send_data(Bytes::from("hello")).await.unwrap();
+        tx.abort();
+
+        let frame = body.frame().await.unwrap().unwrap();
+        assert_eq!(frame.into_data().unwrap(), "hello");
+        let err = body.frame().await.unwrap().unwrap_err();
+        assert!(err.is_body_write_aborted());
+        assert!(body.frame().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn dropped_body_closes_sender() {
+        let (mut tx, body) = channel(1);
+        drop(body);
+        assert!(tx.is_closed());
+        assert!(tx.send_data(Bytes::from("hello")).await.is_err());
+    }
+}
@@ -0,0 +1,437 @@
+use std::fmt;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use bytes::Buf;
+use futures_core::ready;
+use http::HeaderMap;
+use http_body::{Body, Frame, SizeHint};
+use pin_project_lite::pin_project;
+
+pin_project! {
+    /// A body that maps each frame of another body with a function.
+    ///
+    /// ```
+    /// use hyper::body::{Bytes, Frame, Full, MapFrame};
+    ///
+    /// let body = MapFrame::new(Full::new(Bytes::from("hello")), |frame: Frame<Bytes>| {
+    ///     frame.map_data(|data| Bytes::from(data.to_ascii_uppercase()))
+    /// });
+    /// # drop(body);
+    /// ```
+    #[derive(Clone, Copy)]
+    pub struct MapFrame<B, F> {
+        #[pin]
+        body: B,
+        f: F,
+    }
+}
+
+pin_project! {
+    /// A body that maps the error of another body with a function.
+    ///
+    /// ```
+    /// use std::convert::Infallible;
+    ///
+    /// use hyper::body::{Bytes, Full, MapErr};
+    ///
+    /// let body = MapErr::new(Full::new(Bytes::from("hello")), |e: Infallible| {
+    ///     std::io::Error::new(std::io::ErrorKind::Other, e)
+    /// });
+    /// # drop(body);
+    /// ```
+    #[derive(Clone, Copy)]
+    pub struct MapErr<B, F> {
+        #[pin]
+        body: B,
+        f: F,
+    }
+}
+
+/// Creates a body that sends `first`, then `second`.
+///
+/// Trailers of `first` are held back until `second` has ended, and sent
+/// then, merged with those of `second`: a field in both takes its values
+/// from `second`. This can put a preamble, such as bytes read with
+/// [`Incoming::peek`](super::Incoming::peek), in front of a body that has
+/// trailers.
+///
+/// ```
+/// use hyper::body::{Bytes, Full};
+///
+/// let body = hyper::body::chain(
+///     Full::new(Bytes::from("hello ")),
+///     Full::new(Bytes::from("world")),
+/// );
+/// # drop(body);
+/// ```
+pub fn chain<A, B>(first: A, second: B) -> Chain<A, B>
+where
+    A: Body,
+    B: Body<Data = A::Data, Error = A::Error>,
+{
+    Chain {
+        first,
+        second,
+        first_done: false,
+        trailers: None,
+    }
+}
+
+pin_project! {
+    /// A body that sends one body, then another.
+    ///
+    /// Created with [`chain`].
+    pub struct Chain<A, B> {
+        #[pin]
+        first: A,
+        #[pin]
+        second: B,
+        first_done: bool,
+        trailers: Option<HeaderMap>,
+    }
+}
+
+/// A boxed body, for when the concrete type of a body can't be named, or
+/// differs between branches.
+///
+/// The body is `Send` and `Sync`. Use [`UnsyncBoxBody`] for one that isn't
+/// `Sync`.
+pub struct BoxBody<D, E> {
+    inner: Pin<Box<dyn Body<Data = D, Error = E> + Send + Sync + 'static>>,
+}
+
+/// A boxed body that is `Send`, but not `Sync`.
+pub struct UnsyncBoxBody<D, E> {
+    inner: Pin<Box<dyn Body<Data = D, Error = E> + Send + 'static>>,
+}
+
+// ===== impl MapFrame =====
+
+impl<B, F> MapFrame<B, F> {
+    /// Wraps a body, mapping each of its frames with `f`.
+    pub fn new(body: B, f: F) -> Self {
+        MapFrame { body, f }
+    }
+
+    /// Returns the wrapped body.
+    pub fn into_inner(self) -> B {
+        self.body
+    }
+}
+
+impl<B, F, D> Body for MapFrame<B, F>
+where
+    B: Body,
+    F: FnMut(Frame<B::Data>) -> Frame<D>,
+    D: Buf,
+{
+    type Data = D;
+    type Error = B::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let me = self.project();
+        let frame = ready!(me.body.poll_frame(cx));
+        Poll::Ready(frame.map(|res| res.map(me.f)))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.body.is_end_stream()
+    }
+
+    // The size can change, so it isn't passed on.
+}
+
+impl<B, F> fmt::Debug for MapFrame<B, F>
+where
+    B: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MapFrame")
+            .field("body", &self.body)
+            .finish()
+    }
+}
+
+// ===== impl MapErr =====
+
+impl<B, F> MapErr<B, F> {
+    /// Wraps a body, mapping its errors with `f`.
+    pub fn new(body: B, f: F) -> Self {
+        MapErr { body, f }
+    }
+
+    /// Returns the wrapped body.
+    pub fn into_inner(self) -> B {
+        self.body
+    }
+}
+
+impl<B, F, E> Body for MapErr<B, F>
+where
+    B: Body,
+    F: FnMut(B::Error) -> E,
+{
+    type Data = B::Data;
+    type Error = E;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let me = self.project();
+        let frame = ready!(me.body.poll_frame(cx));
+        Poll::Ready(frame.map(|res| res.map_err(me.f)))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.body.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.body.size_hint()
+    }
+}
+
+impl<B, F> fmt::Debug for MapErr<B, F>
+where
+    B: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MapErr").field("body", &self.body).finish()
+    }
+}
+
+// ===== impl Chain =====
+
+impl<A, B> Chain<A, B> {
+    /// Returns the two bodies.
+    pub fn into_inner(self) -> (A, B) {
+        (self.first, self.second)
+    }
+}
+
+impl<A, B> Body for Chain<A, B>
+where
+    A: Body,
+    B: Body<Data = A::Data, Error = A::Error>,
+{
+    type Data = A::Data;
+    type Error = A::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let me = self.project();
+        if !*me.first_done {
+            match ready!(me.first.poll_frame(cx)) {
+                Some(Ok(frame)) => match frame.into_trailers() {
+                    Ok(trailers) => {
+                        *me.trailers = Some(trailers);
+                        *me.first_done = true;
+                    }
+                    Err(frame) => return Poll::Ready(Some(Ok(frame))),
+                },
+                Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+                None => *me.first_done = true,
+            }
+        }
+
+        match ready!(me.second.poll_frame(cx)) {
+            Some(Ok(frame)) => match frame.into_trailers() {
+                Ok(trailers) => {
+                    let trailers = match me.trailers.take() {
+                        Some(mut first) => {
+                            first.extend(trailers);
+                            first
+                        }
+                        None => trailers,
+                    };
+                    Poll::Ready(Some(Ok(Frame::trailers(trailers))))
+                }
+                Err(frame) => Poll::Ready(Some(Ok(frame))),
+            },
+            Some(Err(e)) => Poll::Ready(Some(Err(e))),
+            None => Poll::Ready(me.trailers.take().map(|t| Ok(Frame::trailers(t)))),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        (self.first_done || self.first.is_end_stream())
+            && self.second.is_end_stream()
+            && self.trailers.is_none()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        let second = self.second.size_hint();
+        if self.first_done {
+            return second;
+        }
+        let first = self.first.size_hint();
+        let mut hint = SizeHint::new();
+        if let (Some(a), Some(b)) = (first.upper(), second.upper()) {
+            hint.set_upper(a + b);
+        }
+        hint.set_lower(first.lower() + second.lower());
+        hint
+    }
+}
+
+impl<A, B> fmt::Debug for Chain<A, B>
+where
+    A: fmt::Debug,
+    B: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Chain")
+            .field("first", &self.first)
+            .field("second", &self.second)
+            .finish()
+    }
+}
+
+// ===== impl BoxBody =====
+
+impl<D, E> BoxBody<D, E> {
+    /// Boxes a body.
+    pub fn new<B>(body: B) -> Self
+    where
+        B: Body<Data = D, Error = E> + Send + Sync + 'static,
+        D: Buf,
+    {
+        BoxBody {
+            inner: Box::pin(body),
+        }
+    }
+}
+
+impl<D: Buf, E> Body for BoxBody<D, E> {
+    type Data = D;
+    type Error = E;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        self.inner.as_mut().poll_frame(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl<D, E> fmt::Debug for BoxBody<D, E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BoxBody").finish()
+    }
+}
+
+// ===== impl UnsyncBoxBody =====
+
+impl<D, E> UnsyncBoxBody<D, E> {
+    /// Boxes a body.
+    pub fn new<B>(body: B) -> Self
+    where
+        B: Body<Data = D, Error = E> + Send + 'static,
+        D: Buf,
+    {
+        UnsyncBoxBody {
+            inner: Box::pin(body),
+        }
+    }
+}
+
+impl<D: Buf, E> Body for UnsyncBoxBody<D, E> {
+    type Data = D;
+    type Error = E;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        self.inner.as_mut().poll_frame(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl<D, E> fmt::Debug for UnsyncBoxBody<D, E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UnsyncBoxBody").finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use http_body_util::{BodyExt, Full, StreamBody};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn map_frame_and_box() {
+        let body = MapFrame::new(Full::new(Bytes::from("hello")), |frame: Frame<Bytes>| {
+            frame.map_data(|data| Bytes::from(data.to_ascii_uppercase()))
+        });
+        let body = BoxBody::new(body);
+        assert!(!body.is_end_stream());
+        let bytes = body.collect().await.unwrap().to_bytes();
+        assert_eq!(bytes, "HELLO");
+    }
+
+    #[tokio::test]
+    async fn chain_merges_trailers() {
+        let mut first_trailers = HeaderMap::new();
+        first_trailers.insert("x-first", "1".parse().unwrap());
+        first_trailers.insert("x-both", "first".parse().unwrap());
+        let first = StreamBody::new(futures_util::stream::iter(vec![
+            Ok::<_, std::convert::Infallible>(Frame::data(Bytes::from("hello "))),
+            Ok(Frame::trailers(first_trailers)),
+        ]));
+        let mut second_trailers = HeaderMap::new();
+        second_trailers.insert("x-both", "second".parse().unwrap());
+        let second = StreamBody::new(futures_util::stream::iter(vec![
+            Ok(Frame::data(Bytes::from("world"))),
+            Ok(Frame::trailers(second_trailers)),
+        ]));
+
+        let collected = chain(first, second).collect().await.unwrap();
+        let trailers = collected.trailers().unwrap().clone();
+        assert_eq!(collected.to_bytes(), "hello world");
+        assert_eq!(trailers["x-first"], "1");
+        assert_eq!(trailers["x-both"], "second");
+        assert_eq!(trailers.len(), 2);
+
+        let body = chain(
+            Full::new(Bytes::from("hello ")),
+            Full::new(Bytes::from("world")),
+        );
+        assert_eq!(body.size_hint().exact(), Some(11));
+    }
+
+    #[tokio::test]
+    async fn map_err_keeps_size() {
+        let body = MapErr::new(Full::new(Bytes::from("hello")), |e| -> std::io::Error {
+            match e {}
+        });
+        assert_eq!(body.size_hint().exact(), Some(5));
+        let body = UnsyncBoxBody::new(body);
+        let bytes = body.collect().await.unwrap().to_bytes();
+        assert_eq!(bytes, "hello");
+    }
+}
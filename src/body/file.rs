@@ -0,0 +1,297 @@
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::os::unix::fs::FileExt;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use bytes::{Buf, Bytes, BytesMut};
+use http_body::{Body, Frame, SizeHint};
+
+// How much the fallback path reads per frame.
+const READ_CHUNK_SIZE: usize = 64 * 1024;
+
+/// A range of bytes in a file.
+///
+/// This is what a [`FileBody`] sends. A transport whose
+/// [`Write::is_write_file`](crate::rt::Write::is_write_file) returns `true`
+/// is handed the region itself, and can write it with `sendfile`, without
+/// copying the bytes through userspace.
+#[derive(Clone)]
+pub struct FileRegion {
+    file: Arc<File>,
+    offset: u64,
+    len: u64,
+}
+
+/// A body that sends a range of bytes from a file.
+///
+/// When a `Response<FileBody>` is served over an HTTP/1 connection whose
+/// transport supports it, the [`FileRegion`] is written directly to the
+/// transport. This still works when the body is wrapped in another body,
+/// such as a [`BoxBody`](crate::body::BoxBody), that passes its
+/// [`FileData`] on unchanged. Otherwise, such as over HTTP/2 or with a
+/// chunked response, the file is read into frames of up to 64KiB.
+///
+/// # Blocking
+///
+/// Those reads happen in `poll_frame`, and block the thread polling the
+/// body, like any read of a regular file. Where that matters, such as when
+/// files may be on slow or network storage, read them on a blocking thread
+/// instead and send the data through a [`channel`](crate::body::channel).
+///
+/// ```no_run
+/// use hyper::body::FileBody;
+///
+/// # fn run() -> std::io::Result<()> {
+/// let file = std::fs::File::open("index.html")?;
+/// let body = FileBody::new(file)?;
+/// let res = hyper::Response::new(body);
+/// # drop(res);
+/// # Ok(())
+/// # }
+/// ```
+pub struct FileBody {
+    region: Option<FileRegion>,
+    // Set once the region has been offered, to tell whether it was taken.
+    taken: Option<Arc<AtomicBool>>,
+}
+
+/// The data of a [`FileBody`].
+///
+/// The first frame of a `FileBody` has no bytes, and offers its
+/// [`FileRegion`] instead. An HTTP/1 connection whose transport can write
+/// files takes the region from it, which ends the body. Everything else
+/// skips the empty frame, and gets the bytes read from the file after it.
+pub struct FileData {
+    bytes: Bytes,
+    offer: Option<(FileRegion, Arc<AtomicBool>)>,
+}
+
+// ===== impl FileRegion =====
+
+impl FileRegion {
+    /// Creates a region of `len` bytes, starting at `offset` in `file`.
+    pub fn new(file: impl Into<Arc<File>>, offset: u64, len: u64) -> FileRegion {
+        FileRegion {
+            file: file.into(),
+            offset,
+            len,
+        }
+    }
+
+    /// Returns the file the region is in.
+    pub fn file(&self) -> &File {
+        &self.file
+    }
+
+    /// Returns the offset in the file of the next byte to send.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Returns how many bytes are left to send.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns whether there are no bytes left to send.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Writes as much of the region as it can to the file descriptor `fd`,
+    /// without copying it through userspace.
+    ///
+    /// This is meant for implementations of
+    /// [`Write::poll_write_file`](crate::rt::Write::poll_write_file), once
+    /// `fd` is ready for writing. A non-blocking `fd` that isn't returns an
+    /// error of kind `WouldBlock`.
+    ///
+    /// The region itself is not advanced; hyper does that with the number of
+    /// bytes returned. This uses `sendfile` on Linux, and returns an error of
+    /// kind `Unsupported` on other systems.
+    pub fn send_to(&self, fd: RawFd) -> io::Result<usize> {
+        send_file(fd, self.file.as_raw_fd(), self.offset, self.len)
+    }
+
+    pub(crate) fn advance(&mut self, n: u64) {
+        debug_assert!(n <= self.len);
+        self.offset += n;
+        self.len -= n;
+    }
+
+    #[cfg(all(feature = "http1", any(feature = "client", feature = "server")))]
+    pub(crate) fn truncate(&mut self, len: u64) {
+        self.len = self.len.min(len);
+    }
+}
+
+impl fmt::Debug for FileRegion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FileRegion")
+            .field("fd", &self.file.as_raw_fd())
+            .field("offset", &self.offset)
+            .field("len", &self.len)
+            .finish()
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn send_file(out_fd: RawFd, in_fd: RawFd, offset: u64, len: u64) -> io::Result<usize> {
+    // Linux never transfers more than this in one call.
+    const MAX_SEND: u64 = 0x7fff_f000;
+
+    let mut off = offset as libc::off64_t;
+    let count = len.min(MAX_SEND) as usize;
+    // SAFETY: `off` is a valid pointer for the duration of the call, and
+    // `sendfile64` doesn't take ownership of either descriptor.
+    let ret = unsafe { libc::sendfile64(out_fd, in_fd, &mut off, count) };
+    if ret < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(ret as usize)
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn send_file(_out_fd: RawFd, _in_fd: RawFd, _offset: u64, _len: u64) -> io::Result<usize> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+// ===== impl FileBody =====
+
+impl FileBody {
+    /// Creates a body that sends the whole file.
+    ///
+    /// The length is read from the file's metadata, so the body has an exact
+    /// size.
+    pub fn new(file: File) -> io::Result<FileBody> {
+        let len = file.metadata()?.len();
+        Ok(FileBody::from_region(FileRegion::new(file, 0, len)))
+    }
+
+    /// Creates a body that sends a region of a file.
+    pub fn from_region(region: FileRegion) -> FileBody {
+        FileBody {
+            region: Some(region),
+            taken: None,
+        }
+    }
+
+    fn is_taken(&self) -> bool {
+        self.taken
+            .as_ref()
+            .map_or(false, |taken| taken.load(Ordering::Acquire))
+    }
+}
+
+impl Body for FileBody {
+    type Data = FileData;
+    type Error = io::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        if self.is_taken() {
+            self.region = None;
+        }
+        let me = &mut *self;
+        let region = match me.region {
+            Some(ref mut region) if !region.is_empty() => region,
+            _ => return Poll::Ready(None),
+        };
+
+        if me.taken.is_none() {
+            let taken = Arc::new(AtomicBool::new(false));
+            me.taken = Some(taken.clone());
+            return Poll::Ready(Some(Ok(Frame::data(FileData {
+                bytes: Bytes::new(),
+                offer: Some((region.clone(), taken)),
+            }))));
+        }
+
+        let size = region.len.min(READ_CHUNK_SIZE as u64) as usize;
+        let mut buf = BytesMut::zeroed(size);
+        let n = match region.file.read_at(&mut buf, region.offset) {
+            Ok(0) => {
+                me.region = None;
+                return Poll::Ready(Some(Err(io::ErrorKind::UnexpectedEof.into())));
+            }
+            Ok(n) => n,
+            Err(e) => return Poll::Ready(Some(Err(e))),
+        };
+        buf.truncate(n);
+        region.advance(n as u64);
+        Poll::Ready(Some(Ok(Frame::data(buf.freeze().into()))))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.is_taken() || self.region.as_ref().map_or(true, FileRegion::is_empty)
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        if self.is_taken() {
+            return SizeHint::with_exact(0);
+        }
+        SizeHint::with_exact(self.region.as_ref().map_or(0, FileRegion::len))
+    }
+}
+
+impl fmt::Debug for FileBody {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FileBody")
+            .field("region", &self.region)
+            .finish()
+    }
+}
+
+// ===== impl FileData =====
+
+impl FileData {
+    /// Takes the region this frame offers, if it does, so that the body
+    /// ends without reading it.
+    #[cfg(all(feature = "http1", any(feature = "client", feature = "server")))]
+    pub(crate) fn take_region(&mut self) -> Option<FileRegion> {
+        let (region, taken) = self.offer.take()?;
+        taken.store(true, Ordering::Release);
+        Some(region)
+    }
+}
+
+impl Buf for FileData {
+    fn remaining(&self) -> usize {
+        self.bytes.remaining()
+    }
+
+    fn chunk(&self) -> &[u8] {
+        self.bytes.chunk()
+    }
+
+    fn advance(&mut self, cnt: usize) {
+        self.bytes.advance(cnt)
+    }
+
+    fn copy_to_bytes(&mut self, len: usize) -> Bytes {
+        self.bytes.copy_to_bytes(len)
+    }
+}
+
+impl From<Bytes> for FileData {
+    fn from(bytes: Bytes) -> FileData {
+        FileData { bytes, offer: None }
+    }
+}
+
+impl fmt::Debug for FileData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FileData")
+            .field("len", &self.bytes.len())
+            .field("offer", &self.offer.as_ref().map(|(region, _)| region))
+            .finish()
+    }
+}
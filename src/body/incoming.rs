@@ -3,8 +3,14 @@ use std::fmt;
 use std::future::Future;
 use std::pin::Pin;
 use std::task::{Context, Poll};
+use std::time::Instant;
 
 use bytes::Bytes;
+#[cfg(all(
+    any(feature = "http1", feature = "http2"),
+    any(feature = "client", feature = "server")
+))]
+use bytes::BytesMut;
 #[cfg(all(feature = "http1", any(feature = "client", feature = "server")))]
 use futures_channel::{mpsc, oneshot};
 #[cfg(all(
@@ -14,7 +20,10 @@ use futures_channel::{mpsc, oneshot};
 use futures_core::ready;
 #[cfg(all(feature = "http1", any(feature = "client", feature = "server")))]
 use futures_core::{stream::FusedStream, Stream}; // for mpsc::Receiver
-#[cfg(all(feature = "http1", any(feature = "client", feature = "server")))]
+#[cfg(all(
+    any(feature = "http1", feature = "http2"),
+    any(feature = "client", feature = "server")
+))]
 use http::HeaderMap;
 use http_body::{Body, Frame, SizeHint};
 
@@ -51,6 +60,43 @@ type TrailersSender = oneshot::Sender<HeaderMap>;
 #[must_use = "streams do nothing unless polled"]
 pub struct Incoming {
     kind: Kind,
+    body_bytes: u64,
+    extra: Option<Box<Extra>>,
+}
+
+/// The parts of an `Incoming` that only some bodies need, such as those
+/// whose stats are collected, kept out of line so that the body stays small.
+///
+/// The times data arrived are only kept once a body has one.
+#[derive(Default)]
+struct Extra {
+    first_body_byte_time: Option<Instant>,
+    last_body_byte_time: Option<Instant>,
+    #[cfg(all(feature = "http2", feature = "client"))]
+    h2_stream: Option<crate::proto::h2::StreamFrames>,
+    #[cfg(all(feature = "http2", feature = "client"))]
+    deadline: Option<Pin<Box<dyn crate::rt::Sleep>>>,
+    // Bytes an h2 body may still have before it's too large.
+    #[cfg(all(feature = "http2", feature = "server"))]
+    max_left: Option<u64>,
+    #[cfg(all(
+        any(feature = "http1", feature = "http2"),
+        any(feature = "client", feature = "server")
+    ))]
+    peeked: Option<Box<Peeked>>,
+    #[cfg(all(feature = "client", any(feature = "http1", feature = "http2")))]
+    on_end: Option<crate::common::stats::OnBodyEnd>,
+}
+
+/// What [`Incoming::peek`] read ahead of the caller.
+#[cfg(all(
+    any(feature = "http1", feature = "http2"),
+    any(feature = "client", feature = "server")
+))]
+struct Peeked {
+    data: BytesMut,
+    // What the body returned after the data, if peeking got that far.
+    after: Option<Option<Result<Frame<Bytes>, crate::Error>>>,
 }
 
 enum Kind {
@@ -68,6 +114,7 @@ enum Kind {
         data_done: bool,
         ping: ping::Recorder,
         recv: h2::RecvStream,
+        window: crate::ext::RecvWindow,
     },
     #[cfg(feature = "ffi")]
     Ffi(crate::ffi::UserBody),
@@ -137,7 +184,19 @@ impl Incoming {
     }
 
     fn new(kind: Kind) -> Incoming {
-        Incoming { kind }
+        Incoming {
+            kind,
+            body_bytes: 0,
+            extra: None,
+        }
+    }
+
+    #[cfg(all(
+        any(feature = "http1", feature = "http2"),
+        any(feature = "client", feature = "server")
+    ))]
+    fn extra_mut(&mut self) -> &mut Extra {
+        self.extra.get_or_insert_with(Box::default)
     }
 
     #[allow(dead_code)]
@@ -152,7 +211,7 @@ impl Incoming {
 
     #[cfg(all(feature = "http2", any(feature = "client", feature = "server")))]
     pub(crate) fn h2(
-        recv: h2::RecvStream,
+        mut recv: h2::RecvStream,
         mut content_length: DecodedLength,
         ping: ping::Recorder,
     ) -> Self {
@@ -162,14 +221,339 @@ impl Incoming {
             content_length = DecodedLength::ZERO;
         }
 
+        let window = crate::ext::RecvWindow::new(recv.flow_control().clone());
         Incoming::new(Kind::H2 {
             data_done: false,
             ping,
             content_length,
             recv,
+            window,
         })
     }
 
+    /// Limits the size of this HTTP/2 body, failing it once it's larger.
+    #[cfg(all(feature = "http2", feature = "server"))]
+    pub(crate) fn set_h2_max_len(&mut self, max: u64) {
+        if let Kind::H2 { .. } = self.kind {
+            self.extra_mut().max_left = Some(max);
+        }
+    }
+
+    /// Returns a handle to the receive window of this body's HTTP/2 stream.
+    #[cfg(all(feature = "http2", any(feature = "client", feature = "server")))]
+    pub(crate) fn h2_flow_control(&self) -> Option<crate::ext::FlowControl> {
+        match self.kind {
+            Kind::H2 { ref window, .. } => Some(window.handle()),
+            _ => None,
+        }
+    }
+
+    /// Returns the approximate instant the final body frame (or trailers)
+    /// was received.
+    ///
+    /// This is `None` until the body has been polled to the end, and for
+    /// bodies whose stats aren't collected, such as those of a client
+    /// connection with stats collection disabled.
+    pub fn last_body_byte_time(&self) -> Option<Instant> {
+        self.extra.as_ref()?.last_body_byte_time
+    }
+
+    /// Returns how many bytes of body data have been polled so far.
+    pub fn body_bytes_received(&self) -> u64 {
+        self.body_bytes
+    }
+
+    /// Returns the length of the whole body, if hyper knows it.
+    ///
+    /// This is the length a `Content-Length` header declared, and is `None`
+    /// for a chunked HTTP/1 body. An HTTP/2 body without `content-length` is
+    /// known once its stream has ended. Unlike the
+    /// [`size_hint`](Body::size_hint), which counts what is left to read, this
+    /// doesn't change as the body is read.
+    pub fn content_length(&self) -> Option<u64> {
+        let remaining = match self.kind {
+            Kind::Empty => Some(0),
+            #[cfg(all(feature = "http1", any(feature = "client", feature = "server")))]
+            Kind::Chan { content_length, .. } => content_length.into_opt(),
+            #[cfg(all(feature = "http2", any(feature = "client", feature = "server")))]
+            Kind::H2 {
+                content_length,
+                ref recv,
+                ..
+            } => content_length
+                .into_opt()
+                .or_else(|| Some(0).filter(|_| recv.is_end_stream())),
+            #[cfg(feature = "ffi")]
+            Kind::Ffi(..) => None,
+        };
+        remaining.map(|n| n + self.body_bytes_received())
+    }
+
+    /// Collects the data of the whole body into one buffer, allowing at most
+    /// `max` bytes.
+    ///
+    /// Once the body is known to be larger, this fails with an error for
+    /// which [`Error::is_body_too_large`](crate::Error::is_body_too_large)
+    /// returns true, and what was buffered is freed. A body whose length is
+    /// known fails before anything is read. Trailers are discarded, use
+    /// [`collect_up_to_with_trailers`](Incoming::collect_up_to_with_trailers)
+    /// to keep them.
+    ///
+    /// Prefer this to collecting a body from an untrusted peer without a
+    /// limit, which could use up any amount of memory.
+    ///
+    /// ```
+    /// # async fn run(res: hyper::Response<hyper::body::Incoming>) -> hyper::Result<()> {
+    /// let body = res.into_body().collect_up_to(64 * 1024).await?;
+    /// # drop(body);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(all(
+        any(feature = "http1", feature = "http2"),
+        any(feature = "client", feature = "server")
+    ))]
+    pub async fn collect_up_to(self, max: usize) -> crate::Result<Bytes> {
+        let (data, _trailers) = self.collect_up_to_with_trailers(max).await?;
+        Ok(data)
+    }
+
+    /// Collects the data of the whole body into one buffer, allowing at most
+    /// `max` bytes, and returns it with the trailers, if there were any.
+    ///
+    /// This fails like [`collect_up_to`](Incoming::collect_up_to) does.
+    ///
+    /// ```
+    /// # async fn run(res: hyper::Response<hyper::body::Incoming>) -> hyper::Result<()> {
+    /// let (data, trailers) = res.into_body().collect_up_to_with_trailers(64 * 1024).await?;
+    /// let status = trailers.as_ref().and_then(|t| t.get("grpc-status"));
+    /// # drop((data, status));
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(all(
+        any(feature = "http1", feature = "http2"),
+        any(feature = "client", feature = "server")
+    ))]
+    pub async fn collect_up_to_with_trailers(
+        mut self,
+        max: usize,
+    ) -> crate::Result<(Bytes, Option<HeaderMap>)> {
+        if self.size_hint().lower() > max as u64 {
+            return Err(crate::Error::new_body_too_large());
+        }
+
+        let mut buf = BytesMut::new();
+        let mut trailers = None;
+        while let Some(frame) =
+            crate::common::future::poll_fn(|cx| Pin::new(&mut self).poll_frame(cx)).await
+        {
+            let frame = match frame?.into_data() {
+                Ok(data) => {
+                    if buf.len() + data.len() > max {
+                        return Err(crate::Error::new_body_too_large());
+                    }
+                    buf.extend_from_slice(&data);
+                    continue;
+                }
+                Err(frame) => frame,
+            };
+            if let Ok(t) = frame.into_trailers() {
+                trailers = Some(t);
+            }
+        }
+        Ok((buf.freeze(), trailers))
+    }
+
+    /// Reads the rest of the body, discarding its data, and returns the
+    /// trailers, if there were any.
+    ///
+    /// ```
+    /// # async fn run(res: hyper::Response<hyper::body::Incoming>) -> hyper::Result<()> {
+    /// let trailers = res.into_body().trailers().await?;
+    /// # drop(trailers);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(all(
+        any(feature = "http1", feature = "http2"),
+        any(feature = "client", feature = "server")
+    ))]
+    pub async fn trailers(mut self) -> crate::Result<Option<HeaderMap>> {
+        let mut trailers = None;
+        while let Some(frame) =
+            crate::common::future::poll_fn(|cx| Pin::new(&mut self).poll_frame(cx)).await
+        {
+            if let Ok(t) = frame?.into_trailers() {
+                trailers = Some(t);
+            }
+        }
+        Ok(trailers)
+    }
+
+    /// Reads ahead until at least `n` bytes of data are buffered, and returns
+    /// up to `n` of them, without consuming them.
+    ///
+    /// The data is still returned by [`poll_frame`](Body::poll_frame)
+    /// afterwards, so the body can be sniffed for magic bytes or a charset
+    /// and then passed on whole. Fewer bytes are returned if the body ends,
+    /// fails, or has trailers first; a failure is returned when reading the
+    /// body after the peeked data.
+    ///
+    /// ```
+    /// # async fn run(res: hyper::Response<hyper::body::Incoming>) -> hyper::Result<()> {
+    /// let mut body = res.into_body();
+    /// let is_gzip = body.peek(2).await? == [0x1f, 0x8b];
+    /// # drop(is_gzip);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(all(
+        any(feature = "http1", feature = "http2"),
+        any(feature = "client", feature = "server")
+    ))]
+    pub async fn peek(&mut self, n: usize) -> crate::Result<&[u8]> {
+        let mut peeked = self.extra_mut().peeked.take().unwrap_or_else(|| {
+            Box::new(Peeked {
+                data: BytesMut::new(),
+                after: None,
+            })
+        });
+        while peeked.data.len() < n && peeked.after.is_none() {
+            let frame = crate::common::future::poll_fn(|cx| self.poll_inner(cx)).await;
+            match frame {
+                Some(Ok(frame)) if frame.is_data() => {
+                    let data = frame.into_data().unwrap_or_else(|_| unreachable!());
+                    peeked.data.extend_from_slice(&data);
+                }
+                other => peeked.after = Some(other),
+            }
+        }
+        let len = n.min(peeked.data.len());
+        let peeked = self.extra_mut().peeked.insert(peeked);
+        Ok(&peeked.data[..len])
+    }
+
+    #[cfg(all(feature = "http2", feature = "client"))]
+    pub(crate) fn with_h2_stream(mut self, stream: Option<crate::proto::h2::StreamFrames>) -> Self {
+        if stream.is_some() {
+            self.extra_mut().h2_stream = stream;
+        }
+        self
+    }
+
+    #[cfg(all(feature = "http2", feature = "client"))]
+    pub(crate) fn with_deadline(
+        mut self,
+        deadline: Option<Pin<Box<dyn crate::rt::Sleep>>>,
+    ) -> Self {
+        if deadline.is_some() {
+            self.extra_mut().deadline = deadline;
+        }
+        self
+    }
+
+    /// Has the body keep the times its data arrived, for the stats of its
+    /// request.
+    #[cfg(all(feature = "client", feature = "http1"))]
+    pub(crate) fn with_stats(mut self) -> Self {
+        self.extra_mut();
+        self
+    }
+
+    /// Reports the request's stats once the body ends.
+    #[cfg(all(feature = "client", any(feature = "http1", feature = "http2")))]
+    pub(crate) fn with_on_end(mut self, on_end: crate::common::stats::OnBodyEnd) -> Self {
+        if self.is_end_stream() {
+            on_end.call(|_| {});
+        } else {
+            self.extra_mut().on_end = Some(on_end);
+        }
+        self
+    }
+
+    /// Gives up on the body if its request's deadline has passed.
+    #[cfg(all(feature = "http2", feature = "client"))]
+    fn poll_deadline(&mut self, cx: &mut Context<'_>) -> Poll<crate::Error> {
+        let extra = match self.extra {
+            Some(ref mut extra) => extra,
+            None => return Poll::Pending,
+        };
+        let deadline = match extra.deadline.as_mut() {
+            Some(deadline) => deadline,
+            None => return Poll::Pending,
+        };
+        ready!(deadline.as_mut().poll(cx));
+        debug!("request deadline passed while receiving body");
+        extra.deadline = None;
+        // Dropping the stream resets it.
+        self.kind = Kind::Empty;
+        Poll::Ready(crate::Error::new_deadline_exceeded())
+    }
+
+    /// Returns when the frames of the HTTP/2 stream this body belongs to
+    /// were received.
+    ///
+    /// This is only available for HTTP/2 client responses with stats
+    /// collection enabled. Unlike the body itself, it is updated as frames
+    /// arrive on the connection, whether or not the body is being polled.
+    pub fn h2_stream_stats(&self) -> Option<crate::H2StreamStats> {
+        #[cfg(all(feature = "http2", feature = "client"))]
+        {
+            self.extra.as_ref()?.h2_stream.as_ref().map(|s| s.stats())
+        }
+        #[cfg(not(all(feature = "http2", feature = "client")))]
+        {
+            None
+        }
+    }
+
+    fn poll_inner(&mut self, cx: &mut Context<'_>) -> Poll<Option<crate::Result<Frame<Bytes>>>> {
+        #[cfg(all(feature = "http2", feature = "server"))]
+        let max_left = self
+            .extra
+            .as_mut()
+            .and_then(|extra| extra.max_left.as_mut());
+        #[cfg(not(all(feature = "http2", feature = "server")))]
+        let max_left = None;
+        let frame = match self.kind.poll_frame(cx, max_left) {
+            Poll::Ready(frame) => frame,
+            Poll::Pending => {
+                #[cfg(all(feature = "http2", feature = "client"))]
+                if let Poll::Ready(err) = self.poll_deadline(cx) {
+                    if let Some(ref mut extra) = self.extra {
+                        extra.end(self.body_bytes);
+                    }
+                    return Poll::Ready(Some(Err(err)));
+                }
+                return Poll::Pending;
+            }
+        };
+        let is_last = match frame {
+            None => true,
+            Some(Ok(ref frame)) => {
+                if let Some(data) = frame.data_ref() {
+                    self.body_bytes += data.len() as u64;
+                    if let Some(ref mut extra) = self.extra {
+                        extra.first_body_byte_time.get_or_insert_with(Instant::now);
+                    }
+                }
+                // A reader may stop here without polling for the end.
+                frame.is_trailers() || self.is_end_stream()
+            }
+            Some(Err(_)) => false,
+        };
+        if let Some(ref mut extra) = self.extra {
+            if is_last && extra.last_body_byte_time.is_none() {
+                extra.last_body_byte_time = Some(Instant::now());
+            }
+            if is_last || matches!(frame, Some(Err(_))) {
+                extra.end(self.body_bytes);
+            }
+        }
+        Poll::Ready(frame)
+    }
+
     #[cfg(feature = "ffi")]
     pub(crate) fn as_ffi_mut(&mut self) -> &mut crate::ffi::UserBody {
         match self.kind {
@@ -186,29 +570,57 @@ impl Incoming {
     }
 }
 
-impl Body for Incoming {
-    type Data = Bytes;
-    type Error = crate::Error;
+impl Extra {
+    /// The body has ended, or won't go on, after `body_bytes` of data.
+    #[cfg_attr(
+        not(all(feature = "client", any(feature = "http1", feature = "http2"))),
+        allow(unused_variables)
+    )]
+    fn end(&mut self, body_bytes: u64) {
+        #[cfg(all(feature = "client", any(feature = "http1", feature = "http2")))]
+        if let Some(on_end) = self.on_end.take() {
+            on_end.call(|stats| {
+                if stats.first_body_byte_time.is_none() {
+                    stats.first_body_byte_time = self.first_body_byte_time;
+                }
+                stats.last_body_byte_time = self.last_body_byte_time;
+                stats.response_body_bytes = body_bytes;
+                #[cfg(feature = "http2")]
+                if let Some(ref stream) = self.h2_stream {
+                    stats.h2_stream = Some(stream.stats());
+                }
+            });
+        }
+    }
+}
+
+impl Drop for Incoming {
+    fn drop(&mut self) {
+        if let Some(ref mut extra) = self.extra {
+            extra.end(self.body_bytes);
+        }
+    }
+}
 
+impl Kind {
     fn poll_frame(
+        &mut self,
         #[cfg_attr(
             not(all(
                 any(feature = "http1", feature = "http2"),
                 any(feature = "client", feature = "server")
             )),
-            allow(unused_mut)
+            allow(unused_variables)
         )]
-        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        // Bytes the body may still have before it's too large.
         #[cfg_attr(
-            not(all(
-                any(feature = "http1", feature = "http2"),
-                any(feature = "client", feature = "server")
-            )),
+            not(all(feature = "http2", feature = "server")),
             allow(unused_variables)
         )]
-        cx: &mut Context<'_>,
-    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
-        match self.kind {
+        max_left: Option<&mut u64>,
+    ) -> Poll<Option<Result<Frame<Bytes>, crate::Error>>> {
+        match *self {
             Kind::Empty => Poll::Ready(None),
             #[cfg(all(feature = "http1", any(feature = "client", feature = "server")))]
             Kind::Chan {
@@ -238,11 +650,27 @@ impl Body for Incoming {
                 ref ping,
                 recv: ref mut h2,
                 content_length: ref mut len,
+                ref window,
             } => {
                 if !*data_done {
                     match ready!(h2.poll_data(cx)) {
                         Some(Ok(bytes)) => {
-                            let _ = h2.flow_control().release_capacity(bytes.len());
+                            #[cfg(feature = "server")]
+                            if let Some(left) = max_left {
+                                match left.checked_sub(bytes.len() as u64) {
+                                    Some(rest) => *left = rest,
+                                    None => {
+                                        debug!("request body over limit");
+                                        // Dropping the stream stops it, without
+                                        // giving the peer window for more.
+                                        *self = Kind::Empty;
+                                        return Poll::Ready(Some(Err(
+                                            crate::Error::new_body_too_large(),
+                                        )));
+                                    }
+                                }
+                            }
+                            window.release(bytes.len());
                             len.sub_if(bytes.len() as u64);
                             ping.record_data(bytes.len());
                             return Poll::Ready(Some(Ok(Frame::data(bytes))));
@@ -278,8 +706,48 @@ impl Body for Incoming {
             Kind::Ffi(ref mut body) => body.poll_data(cx),
         }
     }
+}
+
+impl Body for Incoming {
+    type Data = Bytes;
+    type Error = crate::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        #[cfg(all(
+            any(feature = "http1", feature = "http2"),
+            any(feature = "client", feature = "server")
+        ))]
+        if let Some(ref mut extra) = self.extra {
+            if let Some(ref mut peeked) = extra.peeked {
+                if !peeked.data.is_empty() {
+                    return Poll::Ready(Some(Ok(Frame::data(peeked.data.split().freeze()))));
+                }
+                let after = peeked.after.take();
+                extra.peeked = None;
+                if let Some(after) = after {
+                    return Poll::Ready(after);
+                }
+            }
+        }
+        self.poll_inner(cx)
+    }
 
     fn is_end_stream(&self) -> bool {
+        #[cfg(all(
+            any(feature = "http1", feature = "http2"),
+            any(feature = "client", feature = "server")
+        ))]
+        if let Some(peeked) = self.extra.as_ref().and_then(|extra| extra.peeked.as_ref()) {
+            if !peeked.data.is_empty() {
+                return false;
+            }
+            if let Some(ref after) = peeked.after {
+                return after.is_none();
+            }
+        }
         match self.kind {
             Kind::Empty => true,
             #[cfg(all(feature = "http1", any(feature = "client", feature = "server")))]
@@ -304,7 +772,7 @@ impl Body for Incoming {
             }
         }
 
-        match self.kind {
+        let hint = match self.kind {
             Kind::Empty => SizeHint::with_exact(0),
             #[cfg(all(feature = "http1", any(feature = "client", feature = "server")))]
             Kind::Chan { content_length, .. } => opt_len(content_length),
@@ -312,7 +780,26 @@ impl Body for Incoming {
             Kind::H2 { content_length, .. } => opt_len(content_length),
             #[cfg(feature = "ffi")]
             Kind::Ffi(..) => SizeHint::default(),
+        };
+
+        #[cfg(all(
+            any(feature = "http1", feature = "http2"),
+            any(feature = "client", feature = "server")
+        ))]
+        if let Some(peeked) = self.extra.as_ref().and_then(|extra| extra.peeked.as_ref()) {
+            let len = peeked.data.len() as u64;
+            let mut hint = hint;
+            if peeked.after.is_some() {
+                // The body ended, or stopped with an error, after the data.
+                return SizeHint::with_exact(len);
+            }
+            if let Some(upper) = hint.upper() {
+                hint.set_upper(upper + len);
+            }
+            hint.set_lower(hint.lower() + len);
+            return hint;
         }
+        hint
     }
 }
 
@@ -473,7 +960,10 @@ mod tests {
         // the size by too much.
 
         let body_size = mem::size_of::<Incoming>();
-        let body_expected_size = mem::size_of::<u64>() * 5;
+        // Includes the h2 ping recorder's share of the connection's open
+        // streams, the h2 stream's receive window, the count of body bytes,
+        // and the box of `Extra`.
+        let body_expected_size = mem::size_of::<u64>() * 9;
         assert!(
             body_size <= body_expected_size,
             "Body size = {} <= {}",
@@ -516,6 +1006,31 @@ mod tests {
         );
     }
 
+    #[cfg(all(feature = "http1", any(feature = "client", feature = "server")))]
+    #[cfg(not(miri))]
+    #[tokio::test]
+    async fn collect_up_to() {
+        let (mut tx, rx) = Incoming::channel();
+        tokio::spawn(async move {
+            tx.send_data("hello ".into()).await.unwrap();
+            tx.send_data("world".into()).await.unwrap();
+        });
+        assert_eq!(rx.collect_up_to(11).await.unwrap(), "hello world");
+
+        let (mut tx, rx) = Incoming::channel();
+        tokio::spawn(async move {
+            tx.send_data("hello ".into()).await.unwrap();
+            let _ = tx.send_data("world".into()).await;
+        });
+        let err = rx.collect_up_to(10).await.unwrap_err();
+        assert!(err.is_body_too_large(), "{:?}", err);
+
+        // A known length over the limit fails without reading.
+        let (_tx, rx) = Incoming::new_channel(DecodedLength::new(11), /*wanter =*/ false);
+        let err = rx.collect_up_to(10).await.unwrap_err();
+        assert!(err.is_body_too_large(), "{:?}", err);
+    }
+
     #[cfg(all(feature = "http1", any(feature = "client", feature = "server")))]
     #[cfg(not(miri))]
     #[tokio::test]
@@ -572,6 +1087,122 @@ mod tests {
         assert!(rx.frame().await.is_none());
     }
 
+    #[cfg(all(feature = "http1", feature = "client"))]
+    #[tokio::test]
+    async fn channel_records_last_body_byte_time() {
+        let (mut tx, rx) = Incoming::new_channel(DecodedLength::CHUNKED, false);
+        let mut rx = rx.with_stats();
+
+        tx.try_send_data("chunk".into()).unwrap();
+        drop(tx);
+
+        assert!(rx.frame().await.unwrap().unwrap().is_data());
+        assert!(rx.last_body_byte_time().is_none());
+        assert_eq!(rx.body_bytes_received(), 5);
+
+        assert!(rx.frame().await.is_none());
+        assert!(rx.last_body_byte_time().is_some());
+    }
+
+    #[cfg(all(feature = "http1", feature = "client"))]
+    #[tokio::test]
+    async fn channel_ends_with_its_content_length() {
+        let (mut tx, rx) = Incoming::new_channel(DecodedLength::new(5), false);
+        let mut rx = rx.with_stats();
+
+        tx.try_send_data("chunk".into()).unwrap();
+
+        assert!(rx.frame().await.unwrap().unwrap().is_data());
+        assert!(rx.is_end_stream());
+        assert!(rx.last_body_byte_time().is_some());
+    }
+
+    #[cfg(all(feature = "http1", any(feature = "client", feature = "server")))]
+    #[tokio::test]
+    async fn channel_without_stats_keeps_no_extra() {
+        let (mut tx, mut rx) = Incoming::new_channel(DecodedLength::CHUNKED, false);
+
+        tx.try_send_data("chunk".into()).unwrap();
+        drop(tx);
+
+        assert!(rx.frame().await.unwrap().unwrap().is_data());
+        assert!(rx.frame().await.is_none());
+        assert_eq!(rx.body_bytes_received(), 5);
+        assert!(rx.last_body_byte_time().is_none());
+        assert!(rx.extra.is_none());
+    }
+
+    #[cfg(all(feature = "http1", any(feature = "client", feature = "server")))]
+    #[cfg(not(miri))]
+    #[tokio::test]
+    async fn channel_content_length() {
+        assert_eq!(Incoming::empty().content_length(), Some(0));
+
+        let (mut tx, mut rx) = Incoming::new_channel(DecodedLength::new(5), false);
+        assert_eq!(rx.content_length(), Some(5));
+        tx.try_send_data("hello".into()).unwrap();
+        assert!(rx.frame().await.unwrap().unwrap().is_data());
+        assert_eq!(rx.size_hint().exact(), Some(0));
+        assert_eq!(rx.content_length(), Some(5));
+
+        let (_tx, rx) = Incoming::new_channel(DecodedLength::CHUNKED, false);
+        assert_eq!(rx.content_length(), None);
+    }
+
+    #[cfg(all(feature = "http1", any(feature = "client", feature = "server")))]
+    #[cfg(not(miri))]
+    #[tokio::test]
+    async fn channel_peek() {
+        let (mut tx, mut rx) = Incoming::new_channel(DecodedLength::new(11), false);
+        tokio::spawn(async move {
+            tx.send_data("hello ".into()).await.unwrap();
+            tx.send_data("world".into()).await.unwrap();
+        });
+
+        assert_eq!(rx.peek(3).await.unwrap(), b"hel");
+        assert_eq!(rx.peek(8).await.unwrap(), b"hello wo");
+        assert_eq!(rx.size_hint().exact(), Some(11));
+        assert!(!rx.is_end_stream());
+        assert_eq!(rx.peek(64).await.unwrap(), b"hello world");
+        assert_eq!(rx.size_hint().exact(), Some(11));
+
+        let frame = rx.frame().await.unwrap().unwrap();
+        assert_eq!(frame.into_data().unwrap(), "hello world");
+        assert!(rx.is_end_stream());
+        assert!(rx.frame().await.is_none());
+        assert_eq!(rx.body_bytes_received(), 11);
+    }
+
+    #[cfg(all(feature = "http1", any(feature = "client", feature = "server")))]
+    #[cfg(not(miri))]
+    #[tokio::test]
+    async fn channel_trailers() {
+        let mut trailers = http::HeaderMap::new();
+        trailers.insert("grpc-status", "0".parse().unwrap());
+
+        let (mut tx, rx) = Incoming::channel();
+        let sent = trailers.clone();
+        tokio::spawn(async move {
+            tx.send_data("hello".into()).await.unwrap();
+            tx.send_trailers(sent).await.unwrap();
+        });
+        assert_eq!(rx.trailers().await.unwrap(), Some(trailers.clone()));
+
+        let (mut tx, rx) = Incoming::channel();
+        let sent = trailers.clone();
+        tokio::spawn(async move {
+            tx.send_data("hello".into()).await.unwrap();
+            tx.send_trailers(sent).await.unwrap();
+        });
+        let (data, got) = rx.collect_up_to_with_trailers(5).await.unwrap();
+        assert_eq!(data, "hello");
+        assert_eq!(got, Some(trailers));
+
+        let (tx, rx) = Incoming::channel();
+        drop(tx);
+        assert_eq!(rx.trailers().await.unwrap(), None);
+    }
+
     #[cfg(all(feature = "http1", any(feature = "client", feature = "server")))]
     #[test]
     fn channel_ready() {
@@ -0,0 +1,181 @@
+use std::fmt;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use bytes::Buf;
+use futures_core::ready;
+use http_body::{Body, Frame, SizeHint};
+use pin_project_lite::pin_project;
+
+pin_project! {
+    /// A body that reports its progress as it is read.
+    ///
+    /// After each frame of data, the callback is given the number of bytes of
+    /// data so far, and the time since the body was wrapped. This works the
+    /// same for bodies being downloaded, and bodies being uploaded as a
+    /// connection sends them.
+    ///
+    /// Wrapping a response's [`Incoming`](crate::body::Incoming) body leaves
+    /// the request's stats to it: the body fields of the [`RequestStats`]
+    /// given to the connection's `on_stats` callback are filled in as it is
+    /// read through the wrapper.
+    ///
+    /// [`RequestStats`]: crate::RequestStats
+    ///
+    /// ```
+    /// # async fn run(res: hyper::Response<hyper::body::Incoming>) {
+    /// use hyper::body::Instrumented;
+    ///
+    /// let total = res.body().content_length();
+    /// let body = Instrumented::new(res.into_body(), move |bytes, elapsed| {
+    ///     println!("{}/{:?} bytes after {:?}", bytes, total, elapsed);
+    /// });
+    /// # drop(body);
+    /// # }
+    /// ```
+    pub struct Instrumented<B, F> {
+        #[pin]
+        body: B,
+        on_progress: F,
+        start: Instant,
+        bytes: u64,
+        first_body_byte_time: Option<Instant>,
+        last_body_byte_time: Option<Instant>,
+    }
+}
+
+impl<B, F> Instrumented<B, F>
+where
+    F: FnMut(u64, Duration),
+{
+    /// Wraps a body, calling `on_progress` after each frame of data.
+    pub fn new(body: B, on_progress: F) -> Self {
+        Instrumented {
+            body,
+            on_progress,
+            start: Instant::now(),
+            bytes: 0,
+            first_body_byte_time: None,
+            last_body_byte_time: None,
+        }
+    }
+}
+
+impl<B, F> Instrumented<B, F> {
+    /// Returns how many bytes of data have been read so far.
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Returns the approximate instant the first frame of data was read.
+    pub fn first_body_byte_time(&self) -> Option<Instant> {
+        self.first_body_byte_time
+    }
+
+    /// Returns the approximate instant the body ended.
+    ///
+    /// This is `None` until the body has been read to the end, which may be
+    /// the frame after which [`is_end_stream`](Body::is_end_stream) is true.
+    pub fn last_body_byte_time(&self) -> Option<Instant> {
+        self.last_body_byte_time
+    }
+
+    /// Returns the wrapped body.
+    pub fn into_inner(self) -> B {
+        self.body
+    }
+}
+
+impl<B, F> Body for Instrumented<B, F>
+where
+    B: Body,
+    F: FnMut(u64, Duration),
+{
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let mut me = self.project();
+        let frame = ready!(me.body.as_mut().poll_frame(cx));
+        let now = Instant::now();
+        let is_last = match frame {
+            Some(Ok(ref frame)) => {
+                if let Some(data) = frame.data_ref() {
+                    *me.bytes += data.remaining() as u64;
+                    me.first_body_byte_time.get_or_insert(now);
+                    (me.on_progress)(*me.bytes, now - *me.start);
+                }
+                frame.is_trailers() || me.body.is_end_stream()
+            }
+            Some(Err(_)) => false,
+            None => true,
+        };
+        if is_last && me.last_body_byte_time.is_none() {
+            *me.last_body_byte_time = Some(now);
+        }
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.body.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.body.size_hint()
+    }
+}
+
+impl<B, F> fmt::Debug for Instrumented<B, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Instrumented")
+            .field("bytes", &self.bytes)
+            .field("first_body_byte_time", &self.first_body_byte_time)
+            .field("last_body_byte_time", &self.last_body_byte_time)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use bytes::Bytes;
+    use http_body_util::{BodyExt, Full, StreamBody};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn reports_progress() {
+        let chunks = ["hello ", "world"]
+            .iter()
+            .map(|&c| Ok::<_, Infallible>(Frame::data(Bytes::from(c))));
+        let mut progress = Vec::new();
+        let mut body = Instrumented::new(
+            StreamBody::new(futures_util::stream::iter(chunks)),
+            |bytes, _| progress.push(bytes),
+        );
+
+        while let Some(frame) = body.frame().await {
+            frame.unwrap();
+        }
+        assert_eq!(body.bytes(), 11);
+        assert!(body.last_body_byte_time() >= body.first_body_byte_time());
+        assert!(body.last_body_byte_time().is_some());
+
+        drop(body);
+        assert_eq!(progress, [6, 11]);
+    }
+
+    #[tokio::test]
+    async fn ends_with_is_end_stream() {
+        let mut body = Instrumented::new(Full::new(Bytes::from("hello")), |_, _| {});
+
+        body.frame().await.unwrap().unwrap();
+        assert!(body.is_end_stream());
+        assert!(body.last_body_byte_time().is_some());
+    }
+}
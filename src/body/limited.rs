@@ -0,0 +1,134 @@
+use std::error::Error as StdError;
+use std::fmt;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use bytes::Buf;
+use futures_core::ready;
+use http_body::{Body, Frame, SizeHint};
+use pin_project_lite::pin_project;
+
+pin_project! {
+    /// A body that errors once more than a given number of bytes of data
+    /// have been read from the wrapped body.
+    ///
+    /// The error is a boxed [`LengthLimitError`]. This bounds the memory used
+    /// to collect a body from an untrusted peer.
+    ///
+    /// ```
+    /// use http_body_util::BodyExt;
+    /// use hyper::body::{Bytes, Full, LengthLimitError, Limited};
+    ///
+    /// # async fn run() {
+    /// let body = Limited::new(Full::new(Bytes::from("hello world")), 5);
+    /// let err = body.collect().await.unwrap_err();
+    /// assert!(err.is::<LengthLimitError>());
+    /// # }
+    /// ```
+    #[derive(Clone, Copy, Debug)]
+    pub struct Limited<B> {
+        remaining: usize,
+        #[pin]
+        body: B,
+    }
+}
+
+/// The error of a [`Limited`] body that went over its limit.
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct LengthLimitError;
+
+impl<B> Limited<B> {
+    /// Wraps a body, allowing at most `limit` bytes of data.
+    pub fn new(body: B, limit: usize) -> Self {
+        Limited {
+            remaining: limit,
+            body,
+        }
+    }
+}
+
+impl<B> Body for Limited<B>
+where
+    B: Body,
+    B::Error: Into<Box<dyn StdError + Send + Sync>>,
+{
+    type Data = B::Data;
+    type Error = Box<dyn StdError + Send + Sync>;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let me = self.project();
+        let frame = match ready!(me.body.poll_frame(cx)) {
+            Some(Ok(frame)) => frame,
+            Some(Err(e)) => return Poll::Ready(Some(Err(e.into()))),
+            None => return Poll::Ready(None),
+        };
+        if let Some(data) = frame.data_ref() {
+            if data.remaining() > *me.remaining {
+                *me.remaining = 0;
+                return Poll::Ready(Some(Err(LengthLimitError.into())));
+            }
+            *me.remaining -= data.remaining();
+        }
+        Poll::Ready(Some(Ok(frame)))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.body.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        let remaining = self.remaining as u64;
+        let hint = self.body.size_hint();
+        match hint.upper() {
+            Some(upper) if upper <= remaining => hint,
+            // Past the limit, the body errors instead of sending the rest.
+            _ => {
+                let mut hint = SizeHint::new();
+                hint.set_upper(remaining);
+                hint
+            }
+        }
+    }
+}
+
+impl fmt::Display for LengthLimitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("length limit exceeded")
+    }
+}
+
+impl StdError for LengthLimitError {}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use http_body_util::{BodyExt, Full, StreamBody};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn under_limit() {
+        let body = Limited::new(Full::new(Bytes::from("hello")), 5);
+        assert_eq!(body.size_hint().exact(), Some(5));
+        let bytes = body.collect().await.unwrap().to_bytes();
+        assert_eq!(bytes, "hello");
+    }
+
+    #[tokio::test]
+    async fn over_limit() {
+        let chunks = ["hello ", "world"]
+            .iter()
+            .map(|&c| Ok::<_, std::convert::Infallible>(Frame::data(Bytes::from(c))));
+        let mut body = Limited::new(StreamBody::new(futures_util::stream::iter(chunks)), 8);
+        assert_eq!(body.size_hint().upper(), Some(8));
+
+        let first = body.frame().await.unwrap().unwrap();
+        assert_eq!(first.into_data().unwrap(), "hello ");
+        let err = body.frame().await.unwrap().unwrap_err();
+        assert!(err.is::<LengthLimitError>());
+    }
+}
@@ -14,8 +14,33 @@
 //!   of `Body`, and returned by hyper as a "receive stream" (so, for server
 //!   requests and client responses).
 //!
-//! There are additional implementations available in [`http-body-util`][],
-//! such as a `Full` or `Empty` body.
+//! hyper also provides [`Full`] and [`Empty`] bodies for data that is all
+//! known up front, [`WithTrailers`], which adds trailers to the end of
+//! another body, and some common combinators: [`Limited`] caps how much data
+//! a body may send, [`MapFrame`] and [`MapErr`] transform a body's frames or
+//! errors, [`chain`] sends one body after another, and [`BoxBody`] erases a
+//! body's type.
+//!
+//! A body can also be sent from another task, through a [`channel`], or read
+//! from a [`Read`](crate::rt::Read) with [`read_to_body`], or written to
+//! through a [`Write`](crate::rt::Write) with [`writer`]. In the other
+//! direction, a [`Reader`] reads the data of a body as a
+//! [`Read`](crate::rt::Read).
+//! [`Instrumented`] reports the progress of a body as it is read.
+//!
+//! A body whose data is [`Segments`] can send a frame made of several
+//! buffers, which are written with vectored IO instead of being copied
+//! together.
+//!
+//! With the `sendfile` feature on unix, `FileBody` sends a range of a file,
+//! without copying it through userspace when the transport supports it.
+//!
+//! A [`Frame`] is either data or trailers today, but more kinds may be added
+//! to it later. hyper skips frames of a kind it doesn't know when sending a
+//! body, and the combinators here pass them through unchanged, so a body
+//! doesn't have to change when it starts to carry them.
+//!
+//! There are additional implementations available in [`http-body-util`][].
 //!
 //! [`http-body-util`]: https://docs.rs/http-body-util
 
@@ -24,22 +49,120 @@ pub use http_body::Body;
 pub use http_body::Frame;
 pub use http_body::SizeHint;
 
+#[cfg(all(
+    any(feature = "http1", feature = "http2"),
+    any(feature = "client", feature = "server")
+))]
+pub use self::channel::{channel, Channel, Sender};
+#[cfg(all(
+    any(feature = "http1", feature = "http2"),
+    any(feature = "client", feature = "server")
+))]
+pub use self::combinators::{chain, BoxBody, Chain, MapErr, MapFrame, UnsyncBoxBody};
+pub use self::empty::Empty;
+#[cfg(all(feature = "sendfile", unix))]
+pub use self::file::{FileBody, FileData, FileRegion};
+pub use self::full::Full;
 pub use self::incoming::Incoming;
+#[cfg(all(
+    any(feature = "http1", feature = "http2"),
+    any(feature = "client", feature = "server")
+))]
+pub use self::instrumented::Instrumented;
+#[cfg(all(
+    any(feature = "http1", feature = "http2"),
+    any(feature = "client", feature = "server")
+))]
+pub use self::limited::{LengthLimitError, Limited};
+#[cfg(all(
+    any(feature = "http1", feature = "http2"),
+    any(feature = "client", feature = "server")
+))]
+pub use self::read::{read_to_body, ReadBody};
+#[cfg(all(
+    any(feature = "http1", feature = "http2"),
+    any(feature = "client", feature = "server")
+))]
+pub use self::reader::Reader;
+#[cfg(all(
+    any(feature = "http1", feature = "http2"),
+    any(feature = "client", feature = "server")
+))]
+pub use self::segments::Segments;
+#[cfg(all(
+    any(feature = "http1", feature = "http2"),
+    any(feature = "client", feature = "server")
+))]
+pub use self::trailers::{TrailersSender, WithTrailers};
+#[cfg(all(
+    any(feature = "http1", feature = "http2"),
+    any(feature = "client", feature = "server")
+))]
+pub use self::writer::{writer, BodyWriter};
 
 #[cfg(all(any(feature = "client", feature = "server"), feature = "http1"))]
-pub(crate) use self::incoming::Sender;
+pub(crate) use self::incoming::Sender as IncomingSender;
 #[cfg(all(
     any(feature = "http1", feature = "http2"),
     any(feature = "client", feature = "server")
 ))]
 pub(crate) use self::length::DecodedLength;
 
+#[cfg(all(
+    any(feature = "http1", feature = "http2"),
+    any(feature = "client", feature = "server")
+))]
+mod channel;
+#[cfg(all(
+    any(feature = "http1", feature = "http2"),
+    any(feature = "client", feature = "server")
+))]
+mod combinators;
+mod empty;
+#[cfg(all(feature = "sendfile", unix))]
+mod file;
+mod full;
 mod incoming;
 #[cfg(all(
     any(feature = "http1", feature = "http2"),
     any(feature = "client", feature = "server")
 ))]
+mod instrumented;
+#[cfg(all(
+    any(feature = "http1", feature = "http2"),
+    any(feature = "client", feature = "server")
+))]
 mod length;
+#[cfg(all(
+    any(feature = "http1", feature = "http2"),
+    any(feature = "client", feature = "server")
+))]
+mod limited;
+#[cfg(all(
+    any(feature = "http1", feature = "http2"),
+    any(feature = "client", feature = "server")
+))]
+mod read;
+#[cfg(all(
+    any(feature = "http1", feature = "http2"),
+    any(feature = "client", feature = "server")
+))]
+mod reader;
+#[cfg(all(
+    any(feature = "http1", feature = "http2"),
+    any(feature = "client", feature = "server")
+))]
+mod segments;
+#[cfg(all(
+    any(feature = "http1", feature = "http2"),
+    any(feature = "client", feature = "server")
+))]
+mod trailers;
+#[cfg(all(
+    any(feature = "http1", feature = "http2"),
+    any(feature = "client", feature = "server")
+))]
+mod writer;
 
 fn _assert_send_sync() {
     fn _assert_send<T: Send>() {}
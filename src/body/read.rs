@@ -0,0 +1,121 @@
+use std::fmt;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use bytes::{Bytes, BytesMut};
+use futures_core::ready;
+use http_body::{Body, Frame};
+use pin_project_lite::pin_project;
+
+use crate::rt::{Read, ReadBuf};
+
+/// Creates a body that streams what is read from `io`, until it reads EOF.
+///
+/// Each frame holds the bytes of one read, of up to `chunk_size` bytes. This
+/// bridges byte streams, such as pipes or upgraded connections, into a body.
+///
+/// ```
+/// # use hyper::rt::Read;
+/// # fn run<R: Read + Send + 'static>(some_reader: R) {
+/// let body = hyper::body::read_to_body(some_reader, 8 * 1024);
+/// let res = hyper::Response::new(body);
+/// # drop(res);
+/// # }
+/// ```
+///
+/// # Panics
+///
+/// The chunk size must be at least 1.
+pub fn read_to_body<R: Read>(io: R, chunk_size: usize) -> ReadBody<R> {
+    assert!(chunk_size > 0, "chunk size must be at least 1");
+    ReadBody {
+        io,
+        chunk_size,
+        eof: false,
+    }
+}
+
+pin_project! {
+    /// A body that streams what is read from a [`Read`].
+    ///
+    /// Created with [`read_to_body`].
+    pub struct ReadBody<R> {
+        #[pin]
+        io: R,
+        chunk_size: usize,
+        eof: bool,
+    }
+}
+
+impl<R> ReadBody<R> {
+    /// Returns the reader.
+    pub fn into_inner(self) -> R {
+        self.io
+    }
+}
+
+impl<R: Read> Body for ReadBody<R> {
+    type Data = Bytes;
+    type Error = io::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let me = self.project();
+        if *me.eof {
+            return Poll::Ready(None);
+        }
+
+        let mut chunk = BytesMut::zeroed(*me.chunk_size);
+        let n = {
+            let mut buf = ReadBuf::new(&mut chunk);
+            ready!(me.io.poll_read(cx, buf.unfilled()))?;
+            buf.filled().len()
+        };
+        if n == 0 {
+            *me.eof = true;
+            return Poll::Ready(None);
+        }
+        chunk.truncate(n);
+        Poll::Ready(Some(Ok(Frame::data(chunk.freeze()))))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.eof
+    }
+}
+
+impl<R> fmt::Debug for ReadBody<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReadBody")
+            .field("chunk_size", &self.chunk_size)
+            .field("eof", &self.eof)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http_body_util::BodyExt;
+
+    use super::*;
+    use crate::common::io::Compat;
+
+    #[tokio::test]
+    async fn reads_in_chunks() {
+        let io = tokio_test::io::Builder::new()
+            .read(b"hello ")
+            .read(b"world")
+            .build();
+        let mut body = read_to_body(Compat::new(io), 4);
+
+        let mut chunks = Vec::new();
+        while let Some(frame) = body.frame().await {
+            chunks.push(frame.unwrap().into_data().unwrap());
+        }
+        assert_eq!(chunks, ["hell", "o ", "worl", "d"]);
+        assert!(body.is_end_stream());
+    }
+}
@@ -0,0 +1,208 @@
+use std::error::Error as StdError;
+use std::fmt;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use bytes::Buf;
+use futures_core::ready;
+use http::HeaderMap;
+use http_body::Body;
+use pin_project_lite::pin_project;
+
+use crate::rt::{Read, ReadBufCursor};
+
+pin_project! {
+    /// Reads the data of a body, through [`Read`], or a chunk at a time with
+    /// [`poll_fill_buf`](Reader::poll_fill_buf).
+    ///
+    /// This lets a streamed format, such as NDJSON or multipart, be parsed
+    /// from a body as it arrives, with any runtime. Each chunk of data the
+    /// body sends is read without being copied into another buffer.
+    ///
+    /// An error of the body is returned as an [`io::Error`] with
+    /// [`io::ErrorKind::Other`], which wraps it. Trailers are kept, for
+    /// [`trailers`](Reader::trailers).
+    ///
+    /// ```
+    /// # async fn run(res: hyper::Response<hyper::body::Incoming>) {
+    /// let reader = hyper::body::Reader::new(res.into_body());
+    /// # drop(reader);
+    /// # }
+    /// ```
+    pub struct Reader<B: Body> {
+        #[pin]
+        body: B,
+        chunk: Option<B::Data>,
+        done: bool,
+        trailers: Option<HeaderMap>,
+    }
+}
+
+impl<B: Body> Reader<B> {
+    /// Wraps a body to read its data.
+    pub fn new(body: B) -> Self {
+        Reader {
+            body,
+            chunk: None,
+            done: false,
+            trailers: None,
+        }
+    }
+
+    /// Returns the trailers of the body, once they have been read.
+    pub fn trailers(&self) -> Option<&HeaderMap> {
+        self.trailers.as_ref()
+    }
+
+    /// Returns the wrapped body.
+    ///
+    /// Data that was read from the body, but not consumed, is lost.
+    pub fn into_inner(self) -> B {
+        self.body
+    }
+}
+
+impl<B> Reader<B>
+where
+    B: Body,
+    B::Error: Into<Box<dyn StdError + Send + Sync>>,
+{
+    /// Returns the data buffered from the body, reading the next chunk if
+    /// there is none.
+    ///
+    /// An empty slice means the body has ended. The data stays buffered
+    /// until it is passed to [`consume`](Reader::consume).
+    pub fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        let mut me = self.project();
+        while !*me.done && !me.chunk.as_ref().map_or(false, Buf::has_remaining) {
+            match ready!(me.body.as_mut().poll_frame(cx)) {
+                Some(Ok(frame)) => match frame.into_data() {
+                    Ok(data) => *me.chunk = Some(data),
+                    Err(frame) => {
+                        if let Ok(trailers) = frame.into_trailers() {
+                            *me.trailers = Some(trailers);
+                        }
+                    }
+                },
+                Some(Err(e)) => {
+                    // Some bodies panic if polled again after an error.
+                    *me.done = true;
+                    return Poll::Ready(Err(io::Error::new(io::ErrorKind::Other, e)));
+                }
+                None => *me.done = true,
+            }
+        }
+        let chunk: &mut Option<B::Data> = me.chunk;
+        Poll::Ready(Ok(chunk.as_ref().map_or(&[], Buf::chunk)))
+    }
+
+    /// Marks `amt` bytes of the data returned by
+    /// [`poll_fill_buf`](Reader::poll_fill_buf) as read.
+    ///
+    /// `amt` must not be more than the length of that data.
+    pub fn consume(self: Pin<&mut Self>, amt: usize) {
+        if let Some(chunk) = self.project().chunk {
+            chunk.advance(amt);
+        }
+    }
+}
+
+impl<B> Read for Reader<B>
+where
+    B: Body,
+    B::Error: Into<Box<dyn StdError + Send + Sync>>,
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        mut buf: ReadBufCursor<'_>,
+    ) -> Poll<io::Result<()>> {
+        let n = {
+            let data = ready!(self.as_mut().poll_fill_buf(cx))?;
+            let n = data.len().min(buf.remaining());
+            buf.put_slice(&data[..n]);
+            n
+        };
+        self.consume(n);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<B> fmt::Debug for Reader<B>
+where
+    B: Body + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Reader")
+            .field("body", &self.body)
+            .field("done", &self.done)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use bytes::Bytes;
+    use http_body::Frame;
+    use http_body_util::StreamBody;
+
+    use super::*;
+    use crate::common::future::poll_fn;
+    use crate::rt::ReadBuf;
+
+    #[tokio::test]
+    async fn error_ends_reader() {
+        let frames = vec![
+            Err(std::io::Error::new(io::ErrorKind::Other, "oops")),
+            Ok(Frame::data(Bytes::from("never"))),
+        ];
+        let mut reader = Reader::new(StreamBody::new(futures_util::stream::iter(frames)));
+        let mut reader = Pin::new(&mut reader);
+
+        let err = poll_fn(|cx| reader.as_mut().poll_fill_buf(cx).map_ok(<[u8]>::to_vec))
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Other);
+        let rest = poll_fn(|cx| reader.as_mut().poll_fill_buf(cx).map_ok(<[u8]>::to_vec))
+            .await
+            .unwrap();
+        assert!(rest.is_empty());
+    }
+
+    #[tokio::test]
+    async fn reads_lines_and_trailers() {
+        let mut trailers = HeaderMap::new();
+        trailers.insert("x-lines", "2".parse().unwrap());
+        let frames = vec![
+            Ok::<_, Infallible>(Frame::data(Bytes::from("{\"a\":1}\n{\"b\""))),
+            Ok(Frame::data(Bytes::from(":2}\n"))),
+            Ok(Frame::trailers(trailers.clone())),
+        ];
+        let mut reader = Reader::new(StreamBody::new(futures_util::stream::iter(frames)));
+        let mut reader = Pin::new(&mut reader);
+
+        let chunk = poll_fn(|cx| reader.as_mut().poll_fill_buf(cx).map_ok(<[u8]>::to_vec))
+            .await
+            .unwrap();
+        assert_eq!(chunk, b"{\"a\":1}\n{\"b\"");
+        reader.as_mut().consume(8);
+
+        let mut out = Vec::new();
+        loop {
+            let mut bytes = [0; 3];
+            let mut buf = ReadBuf::new(&mut bytes);
+            poll_fn(|cx| reader.as_mut().poll_read(cx, buf.unfilled()))
+                .await
+                .unwrap();
+            if buf.filled().is_empty() {
+                break;
+            }
+            out.extend_from_slice(buf.filled());
+        }
+        assert_eq!(out, b"{\"b\":2}\n");
+        assert_eq!(reader.trailers(), Some(&trailers));
+    }
+}
@@ -0,0 +1,135 @@
+use std::fmt;
+use std::io::IoSlice;
+use std::iter::FromIterator;
+
+use bytes::{Buf, Bytes};
+
+use crate::common::buf::BufList;
+
+/// A chunk of data made of several `Bytes` segments.
+///
+/// A body with `Segments` as its data can send a frame made of separate
+/// buffers, such as a header block and a payload, without copying them into
+/// one. When the transport supports vectored writes, the segments are
+/// written with a single vectored write.
+///
+/// ```
+/// use hyper::body::{Bytes, Frame, Segments};
+///
+/// let preamble = Bytes::from_static(b"\x00\x00\x00\x00\x05");
+/// let payload = Bytes::from("hello");
+/// let frame = Frame::data(Segments::from(vec![preamble, payload]));
+/// # drop(frame);
+/// ```
+pub struct Segments {
+    bufs: BufList<Bytes>,
+}
+
+impl Segments {
+    /// Creates an empty chunk.
+    pub fn new() -> Segments {
+        Segments {
+            bufs: BufList::new(),
+        }
+    }
+
+    /// Appends a segment to the end of the chunk.
+    pub fn push(&mut self, segment: Bytes) {
+        if !segment.is_empty() {
+            self.bufs.push(segment);
+        }
+    }
+}
+
+impl Buf for Segments {
+    #[inline]
+    fn remaining(&self) -> usize {
+        self.bufs.remaining()
+    }
+
+    #[inline]
+    fn chunk(&self) -> &[u8] {
+        self.bufs.chunk()
+    }
+
+    #[inline]
+    fn advance(&mut self, cnt: usize) {
+        self.bufs.advance(cnt)
+    }
+
+    #[inline]
+    fn chunks_vectored<'t>(&'t self, dst: &mut [IoSlice<'t>]) -> usize {
+        self.bufs.chunks_vectored(dst)
+    }
+
+    #[inline]
+    fn copy_to_bytes(&mut self, len: usize) -> Bytes {
+        self.bufs.copy_to_bytes(len)
+    }
+}
+
+impl Default for Segments {
+    fn default() -> Segments {
+        Segments::new()
+    }
+}
+
+impl From<Bytes> for Segments {
+    fn from(segment: Bytes) -> Segments {
+        let mut segments = Segments::new();
+        segments.push(segment);
+        segments
+    }
+}
+
+impl From<Vec<Bytes>> for Segments {
+    fn from(segments: Vec<Bytes>) -> Segments {
+        segments.into_iter().collect()
+    }
+}
+
+impl FromIterator<Bytes> for Segments {
+    fn from_iter<I: IntoIterator<Item = Bytes>>(iter: I) -> Segments {
+        let mut segments = Segments::new();
+        for segment in iter {
+            segments.push(segment);
+        }
+        segments
+    }
+}
+
+impl fmt::Debug for Segments {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Segments")
+            .field("remaining", &self.remaining())
+            .field("segments", &self.bufs.bufs_cnt())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn segments_are_vectored() {
+        let mut buf: Segments = vec![
+            Bytes::from("hello"),
+            Bytes::new(),
+            Bytes::from(" "),
+            Bytes::from("world"),
+        ]
+        .into();
+        assert_eq!(buf.remaining(), 11);
+
+        let mut iovs = [IoSlice::new(&[]); 4];
+        assert_eq!(buf.chunks_vectored(&mut iovs), 3);
+        assert_eq!(&*iovs[0], b"hello");
+        assert_eq!(&*iovs[2], b"world");
+
+        buf.advance(6);
+        assert_eq!(buf.chunk(), b"world");
+        assert_eq!(buf.copy_to_bytes(5), "world");
+        assert!(!buf.has_remaining());
+    }
+}
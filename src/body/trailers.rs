@@ -0,0 +1,165 @@
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_channel::oneshot;
+use futures_core::ready;
+use http::HeaderMap;
+use http_body::{Body, Frame, SizeHint};
+use pin_project_lite::pin_project;
+
+pin_project! {
+    /// A body that sends another body, followed by trailers given to its
+    /// [`TrailersSender`].
+    ///
+    /// This saves writing a `Body` just to yield a trailers frame at the
+    /// end. The trailers can be sent at any time, such as once a checksum of
+    /// the body is known: the body waits for them after the wrapped body
+    /// ends. If the sender is dropped instead, the body ends without
+    /// trailers.
+    ///
+    /// The length of the body is never reported as exact, since HTTP/1
+    /// only sends trailers on a chunked body. An HTTP/1 server also only
+    /// sends them if the request had `TE: trailers`, and only the fields
+    /// named in the response's `Trailer` header.
+    ///
+    /// ```
+    /// use hyper::body::{Bytes, Full, WithTrailers};
+    /// use hyper::HeaderMap;
+    ///
+    /// let (body, sender) = WithTrailers::new(Full::new(Bytes::from("hello")));
+    /// let res = hyper::Response::builder()
+    ///     .header("trailer", "grpc-status")
+    ///     .body(body)
+    ///     .unwrap();
+    ///
+    /// let mut trailers = HeaderMap::new();
+    /// trailers.insert("grpc-status", "0".parse().unwrap());
+    /// sender.send_trailers(trailers).unwrap();
+    /// # drop(res);
+    /// ```
+    pub struct WithTrailers<B> {
+        #[pin]
+        body: B,
+        body_done: bool,
+        trailers: Option<oneshot::Receiver<HeaderMap>>,
+    }
+}
+
+/// Sends the trailers of a [`WithTrailers`] body.
+pub struct TrailersSender {
+    tx: oneshot::Sender<HeaderMap>,
+}
+
+impl<B> WithTrailers<B> {
+    /// Wraps a body, returning the sender for its trailers.
+    pub fn new(body: B) -> (Self, TrailersSender) {
+        let (tx, rx) = oneshot::channel();
+        let body = WithTrailers {
+            body,
+            body_done: false,
+            trailers: Some(rx),
+        };
+        (body, TrailersSender { tx })
+    }
+}
+
+impl<B: Body> Body for WithTrailers<B> {
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let me = self.project();
+        if !*me.body_done {
+            match ready!(me.body.poll_frame(cx)) {
+                Some(frame) => return Poll::Ready(Some(frame)),
+                None => *me.body_done = true,
+            }
+        }
+
+        let rx = match me.trailers {
+            Some(rx) => rx,
+            None => return Poll::Ready(None),
+        };
+        let trailers = ready!(Pin::new(rx).poll(cx));
+        *me.trailers = None;
+        // A dropped sender means there are no trailers.
+        Poll::Ready(trailers.ok().map(|trailers| Ok(Frame::trailers(trailers))))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.trailers.is_none() && (self.body_done || self.body.is_end_stream())
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        let mut hint = SizeHint::new();
+        hint.set_lower(self.body.size_hint().lower());
+        hint
+    }
+}
+
+impl<B> fmt::Debug for WithTrailers<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WithTrailers")
+            .field("body_done", &self.body_done)
+            .finish()
+    }
+}
+
+impl TrailersSender {
+    /// Sends the trailers, to follow the body.
+    ///
+    /// If the body was already dropped, the trailers are given back.
+    pub fn send_trailers(self, trailers: HeaderMap) -> Result<(), HeaderMap> {
+        self.tx.send(trailers)
+    }
+
+    /// Returns whether the body has been dropped, so trailers would go
+    /// nowhere.
+    pub fn is_closed(&self) -> bool {
+        self.tx.is_canceled()
+    }
+}
+
+impl fmt::Debug for TrailersSender {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TrailersSender").finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use http_body_util::{BodyExt, Full};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn trailers_follow_body() {
+        let (body, sender) = WithTrailers::new(Full::new(Bytes::from("hello")));
+        assert_eq!(body.size_hint().exact(), None);
+        assert!(!body.is_end_stream());
+
+        let mut trailers = HeaderMap::new();
+        trailers.insert("chunky-trailer", "data".parse().unwrap());
+        sender.send_trailers(trailers.clone()).unwrap();
+
+        let collected = body.collect().await.unwrap();
+        assert_eq!(collected.trailers(), Some(&trailers));
+        assert_eq!(collected.to_bytes(), "hello");
+    }
+
+    #[tokio::test]
+    async fn dropped_sender_ends_body() {
+        let (body, sender) = WithTrailers::new(Full::new(Bytes::from("hello")));
+        drop(sender);
+
+        let collected = body.collect().await.unwrap();
+        assert_eq!(collected.trailers(), None);
+        assert_eq!(collected.to_bytes(), "hello");
+    }
+}
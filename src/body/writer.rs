@@ -0,0 +1,135 @@
+use std::fmt;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use bytes::Bytes;
+use futures_core::ready;
+
+use super::{channel, Channel, Sender};
+
+/// Creates a body that is written to through a [`BodyWriter`].
+///
+/// This lets code that produces output through a writer, such as a
+/// serializer or a compressor, stream it straight into a body. Each write
+/// becomes a frame of data, and at most `capacity` of them wait in the
+/// channel, as with [`channel`].
+///
+/// ```
+/// # async fn serialize_into<W: hyper::rt::Write + Unpin>(_: W) {}
+/// # async fn run() {
+/// let (writer, body) = hyper::body::writer(4);
+/// let res = hyper::Response::new(body);
+///
+/// tokio::spawn(serialize_into(writer));
+/// # drop(res);
+/// # }
+/// ```
+///
+/// # Panics
+///
+/// The capacity must be at least 1.
+pub fn writer(capacity: usize) -> (BodyWriter, Channel) {
+    let (tx, body) = channel(capacity);
+    (BodyWriter { tx: Some(tx) }, body)
+}
+
+/// Writes the data of a [`Channel`] body, through [`rt::Write`].
+///
+/// Shutting the writer down, or dropping it, ends the body. Use
+/// [`abort`](BodyWriter::abort) to end it with an error instead.
+///
+/// Writes fail with [`io::ErrorKind::BrokenPipe`] once the body has been
+/// dropped.
+///
+/// [`rt::Write`]: crate::rt::Write
+pub struct BodyWriter {
+    tx: Option<Sender>,
+}
+
+impl BodyWriter {
+    /// Ends the body with an error, after the data written so far.
+    pub fn abort(self) {
+        if let Some(tx) = self.tx {
+            tx.abort();
+        }
+    }
+
+    /// Returns the [`Sender`] of the body, to send trailers with it.
+    ///
+    /// This is `None` once the writer has been shut down.
+    pub fn into_sender(self) -> Option<Sender> {
+        self.tx
+    }
+}
+
+impl crate::rt::Write for BodyWriter {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let tx = match self.tx {
+            Some(ref mut tx) => tx,
+            None => return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into())),
+        };
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        ready!(tx.poll_ready(cx)).map_err(|e| io::Error::new(io::ErrorKind::BrokenPipe, e))?;
+        tx.try_send_data(Bytes::copy_from_slice(buf))
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        // Written data is already in the channel, for the body to be read.
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.tx = None;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl fmt::Debug for BodyWriter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BodyWriter")
+            .field("closed", &self.tx.is_none())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http_body_util::BodyExt;
+
+    use super::*;
+    use crate::common::future::poll_fn;
+    use crate::rt::Write;
+
+    #[tokio::test]
+    async fn writes_become_frames() {
+        let (mut writer, body) = writer(2);
+        let task = tokio::spawn(async move {
+            for chunk in [&b"hello "[..], b"world"] {
+                let n = poll_fn(|cx| Pin::new(&mut writer).poll_write(cx, chunk))
+                    .await
+                    .unwrap();
+                assert_eq!(n, chunk.len());
+            }
+            poll_fn(|cx| Pin::new(&mut writer).poll_shutdown(cx))
+                .await
+                .unwrap();
+            let err = poll_fn(|cx| Pin::new(&mut writer).poll_write(cx, b"!"))
+                .await
+                .unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+        });
+
+        let bytes = body.collect().await.unwrap().to_bytes();
+        assert_eq!(bytes, "hello world");
+        task.await.unwrap();
+    }
+}
@@ -4,17 +4,28 @@ use std::error::Error as StdError;
 use std::fmt;
 use std::future::Future;
 use std::pin::Pin;
+use std::sync::Arc;
 use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 
-use crate::rt::{Read, Stats, Write};
+use crate::rt::{Read, Stats, Timer, Write};
 use bytes::Bytes;
 use futures_core::ready;
-use http::{Request, Response};
+use http::header::{HeaderValue, HOST};
+use http::{Method, Request, Response, Uri};
 use httparse::ParserConfig;
 
 use super::super::dispatch::{self, TrySendError};
 use crate::body::{Body, Incoming as IncomingBody};
-use crate::{proto, HttpConnectionStats};
+use crate::common::stats::{CheckoutStart, Clock, Observer, OnStats};
+use crate::common::time::{Deadline, Dur, Time};
+use crate::stats::{ConnectionObserver, StatsClock};
+use crate::upgrade::Upgraded;
+use crate::{proto, HttpConnectionStats, RequestStats};
+
+/// The `expect_continue_timeout` used when `auto_expect_continue` is enabled
+/// without one.
+const DEFAULT_AUTO_EXPECT_CONTINUE_TIMEOUT: Duration = Duration::from_secs(1);
 
 type Dispatcher<T, B> =
     proto::dispatch::Dispatcher<proto::dispatch::Client<B>, B, T, proto::h1::ClientTransaction>;
@@ -22,6 +33,8 @@ type Dispatcher<T, B> =
 /// The sender side of an established connection.
 pub struct SendRequest<B> {
     dispatch: dispatch::Sender<Request<B>, (HttpConnectionStats, Response<IncomingBody>)>,
+    collect_stats: bool,
+    clock: Clock,
 }
 
 /// Deconstructed parts of a `Connection`.
@@ -57,6 +70,29 @@ where
     B: Body + 'static,
 {
     inner: Dispatcher<T, B>,
+    graceful_shutdown: bool,
+}
+
+/// Why an HTTP/1 client connection closed.
+///
+/// Returned by [`Connection::drain`], so that a pool can decide whether
+/// and how soon to retry on a new connection.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum CloseReason {
+    /// The server closed the connection while it was idle.
+    PeerClosed,
+    /// A message exchange finished without keep-alive, such as after a
+    /// `Connection: close` header or an HTTP/1.0 response.
+    KeepAliveDisabled,
+    /// The connection failed.
+    ///
+    /// This is `None` if the error was already given to the request that
+    /// was in flight.
+    Error(Option<crate::Error>),
+    /// The connection was closed locally, either by
+    /// [`Connection::graceful_shutdown`] or by dropping every `SendRequest`.
+    LocalShutdown,
 }
 
 impl<T, B> Connection<T, B>
@@ -113,11 +149,25 @@ pub struct Builder {
     h1_writev: Option<bool>,
     h1_title_case_headers: bool,
     h1_preserve_header_case: bool,
+    h1_preserve_raw_headers: bool,
     h1_max_headers: Option<usize>,
+    h1_max_header_size: Option<usize>,
     #[cfg(feature = "ffi")]
     h1_preserve_header_order: bool,
     h1_read_buf_exact_size: Option<usize>,
+    h1_initial_read_buf_size: Option<usize>,
+    h1_max_read_buf_size: Option<usize>,
     h1_max_buf_size: Option<usize>,
+    h1_expect_continue_timeout: Dur,
+    h1_abort_on_expect_continue_timeout: bool,
+    h1_auto_expect_continue: Option<u64>,
+    h1_response_header_timeout: Dur,
+    h1_max_pipelined_requests: usize,
+    timer: Time,
+    on_stats: Option<OnStats>,
+    observer: Option<Observer>,
+    collect_stats: bool,
+    stats_clock: Clock,
 }
 
 /// Returns a handshake future over some IO.
@@ -185,13 +235,17 @@ where
     /// - Usually you want origin-form (`/path?query`).
     /// - For sending to an HTTP proxy, you want to send in absolute-form
     ///   (`https://hyper.rs/guides`).
+    /// - For a server-wide `OPTIONS` request, you want asterisk-form (`*`).
     ///
     /// This is however not enforced or validated and it is up to the user
     /// of this method to ensure the `Uri` is correct for their intended purpose.
     pub fn send_request(
         &mut self,
-        req: Request<B>,
+        mut req: Request<B>,
     ) -> impl Future<Output = crate::Result<(HttpConnectionStats, Response<IncomingBody>)>> {
+        if self.collect_stats {
+            CheckoutStart::mark(&mut req, &self.clock);
+        }
         let sent = self.dispatch.send(req);
 
         async move {
@@ -210,6 +264,33 @@ where
         }
     }
 
+    /// Sends a `Request` on the associated connection, failing it if it
+    /// isn't done by `deadline`.
+    ///
+    /// The deadline covers the response head and body. It starts to count
+    /// once the connection picks up the request. If it passes before the
+    /// response head arrives, the returned future fails with an error for
+    /// which [`Error::is_timeout`](crate::Error::is_timeout) returns true,
+    /// and whose [`stats`](crate::Error::stats) hold what was recorded so
+    /// far. If it passes while the body is being read, the body yields that
+    /// error instead.
+    ///
+    /// Since an HTTP/1 connection can't drop a request halfway, it is
+    /// closed when the deadline passes.
+    ///
+    /// # Panics
+    ///
+    /// The connection panics if a deadline is set but a `timer` was not
+    /// given to its `Builder`.
+    pub fn send_request_with_deadline(
+        &mut self,
+        mut req: Request<B>,
+        deadline: Instant,
+    ) -> impl Future<Output = crate::Result<(HttpConnectionStats, Response<IncomingBody>)>> {
+        req.extensions_mut().insert(Deadline(deadline));
+        self.send_request(req)
+    }
+
     /// Sends a `Request` on the associated connection.
     ///
     /// Returns a future that if successful, yields the `Response`.
@@ -220,16 +301,19 @@ where
     /// connection, the message will be returned as part of this error.
     pub fn try_send_request(
         &mut self,
-        req: Request<B>,
+        mut req: Request<B>,
     ) -> impl Future<
         Output = Result<(HttpConnectionStats, Response<IncomingBody>), TrySendError<Request<B>>>,
     > {
+        if self.collect_stats {
+            CheckoutStart::mark(&mut req, &self.clock);
+        }
         let sent = self.dispatch.try_send(req);
         async move {
             match sent {
                 Ok(rx) => match rx.await {
                     Ok(Ok(res)) => Ok(res),
-                    Ok(Err(err)) => Err(err),
+                    Ok(Err(err)) => Err(err.record_retry()),
                     // this is definite bug if it happens, but it shouldn't happen!
                     Err(_) => panic!("dispatch dropped without returning error"),
                 },
@@ -244,6 +328,55 @@ where
             }
         }
     }
+
+    /// Opens a tunnel to `uri` with a `CONNECT` request.
+    ///
+    /// `uri` must be in authority-form (`example.com:443`); it is sent as
+    /// the request target and as the `Host` header, along with an empty
+    /// body. Once the peer answers with a 2xx status, the returned future
+    /// yields the response head and the [`Upgraded`] IO of the tunnel.
+    ///
+    /// The `Connection` must be polled with
+    /// [`with_upgrades`](Connection::with_upgrades) for the tunnel to be
+    /// handed over.
+    ///
+    /// # Error
+    ///
+    /// If the peer answers with any other status, the future fails with an
+    /// error for which [`Error::is_connect_rejected`](crate::Error::is_connect_rejected)
+    /// returns true. To read a rejected response, such as the challenge of a
+    /// `407 Proxy Authentication Required`, send the request with
+    /// [`send_request`](SendRequest::send_request) instead.
+    pub fn connect(
+        &mut self,
+        uri: Uri,
+    ) -> impl Future<Output = crate::Result<(Response<()>, Upgraded)>>
+    where
+        B: Default,
+    {
+        let host = uri
+            .authority()
+            .filter(|_| uri.scheme().is_none() && uri.path_and_query().is_none())
+            .and_then(|authority| HeaderValue::from_str(authority.as_str()).ok());
+        let sent = host
+            .ok_or_else(crate::Error::new_user_invalid_connect_uri)
+            .map(|host| {
+                let mut req = Request::new(B::default());
+                *req.method_mut() = Method::CONNECT;
+                *req.uri_mut() = uri;
+                req.headers_mut().insert(HOST, host);
+                self.send_request(req)
+            });
+
+        async move {
+            let (_, mut res) = sent?.await?;
+            if !res.status().is_success() {
+                return Err(crate::Error::new_connect_rejected(res.status()));
+            }
+            let upgraded = crate::upgrade::on(&mut res).await?;
+            Ok((res.map(|_| ()), upgraded))
+        }
+    }
 }
 
 impl<B> fmt::Debug for SendRequest<B> {
@@ -268,6 +401,49 @@ where
     }
 }
 
+impl<T, B> Connection<T, B>
+where
+    T: Read + Write + Stats + Unpin,
+    B: Body + 'static,
+    B::Data: Send,
+    B::Error: Into<Box<dyn StdError + Send + Sync>>,
+{
+    /// Start a graceful shutdown process for this connection.
+    ///
+    /// A request in flight is allowed to finish, after which the connection
+    /// closes instead of waiting for the next request. This `Connection`
+    /// should continue to be polled until shutdown can finish.
+    pub fn graceful_shutdown(&mut self) {
+        self.graceful_shutdown = true;
+        self.inner.disable_keep_alive();
+    }
+
+    /// Poll the connection until it has fully closed, resolving with the
+    /// reason it closed.
+    ///
+    /// Like polling the `Connection` as a future, but the outcome is
+    /// reported as a [`CloseReason`] instead of a `Result`.
+    pub fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<CloseReason> {
+        if let Err(err) = ready!(Pin::new(&mut *self).poll(cx)) {
+            return Poll::Ready(CloseReason::Error(Some(err)));
+        }
+        Poll::Ready(match self.inner.close_cause() {
+            Some(proto::h1::CloseCause::Error) => CloseReason::Error(None),
+            Some(proto::h1::CloseCause::Peer) => CloseReason::PeerClosed,
+            Some(proto::h1::CloseCause::KeepAliveDisabled) if !self.graceful_shutdown => {
+                CloseReason::KeepAliveDisabled
+            }
+            _ => CloseReason::LocalShutdown,
+        })
+    }
+
+    /// Run the connection until it has fully closed, and return why it
+    /// closed. This is a convenience wrapper over `poll_drain`.
+    pub async fn drain(mut self) -> CloseReason {
+        crate::common::future::poll_fn(move |cx| self.poll_drain(cx)).await
+    }
+}
+
 impl<T, B> fmt::Debug for Connection<T, B>
 where
     T: Read + Write + fmt::Debug,
@@ -312,13 +488,27 @@ impl Builder {
             h09_responses: false,
             h1_writev: None,
             h1_read_buf_exact_size: None,
+            h1_initial_read_buf_size: None,
+            h1_max_read_buf_size: None,
             h1_parser_config: Default::default(),
             h1_title_case_headers: false,
             h1_preserve_header_case: false,
+            h1_preserve_raw_headers: false,
             h1_max_headers: None,
+            h1_max_header_size: None,
             #[cfg(feature = "ffi")]
             h1_preserve_header_order: false,
             h1_max_buf_size: None,
+            h1_expect_continue_timeout: Dur::Default(None),
+            h1_abort_on_expect_continue_timeout: false,
+            h1_response_header_timeout: Dur::Default(None),
+            h1_max_pipelined_requests: 0,
+            h1_auto_expect_continue: None,
+            timer: Time::Empty,
+            on_stats: None,
+            observer: None,
+            collect_stats: true,
+            stats_clock: Clock::default(),
         }
     }
 
@@ -432,13 +622,10 @@ impl Builder {
 
     /// Set whether to support preserving original header cases.
     ///
-    /// Currently, this will record the original cases received, and store them
-    /// in a private extension on the `Response`. It will also look for and use
-    /// such an extension in any provided `Request`.
-    ///
-    /// Since the relevant extension is still private, there is no way to
-    /// interact with the original cases. The only effect this can have now is
-    /// to forward the cases in a proxy-like fashion.
+    /// This will record the original cases received, and store them in a
+    /// [`HeaderCaseMap`](crate::ext::HeaderCaseMap) extension on the `Response`.
+    /// Such an extension in any provided `Request` is used to write its header
+    /// names, whether or not this is set.
     ///
     /// Default is false.
     pub fn preserve_header_case(&mut self, enabled: bool) -> &mut Builder {
@@ -446,6 +633,20 @@ impl Builder {
         self
     }
 
+    /// Set whether to keep the header lines of responses as they were
+    /// received.
+    ///
+    /// If enabled, each `Response` carries a [`RawHeaders`](crate::ext::RawHeaders)
+    /// extension with the bytes of its head and where each header line sits
+    /// in them. This is meant for debugging a peer's output, and costs a copy
+    /// of each response head.
+    ///
+    /// Default is false.
+    pub fn preserve_raw_headers(&mut self, enabled: bool) -> &mut Builder {
+        self.h1_preserve_raw_headers = enabled;
+        self
+    }
+
     /// Set the maximum number of headers.
     ///
     /// When a response is received, the parser will reserve a buffer to store headers for optimal
@@ -464,6 +665,24 @@ impl Builder {
         self
     }
 
+    /// Set the maximum size in bytes of a response head.
+    ///
+    /// This counts the status line and all header lines, and separately
+    /// limits the size of any trailers. A response with a larger head fails
+    /// with an error for which
+    /// [`Error::is_parse_header_size_too_large`](crate::Error::is_parse_header_size_too_large)
+    /// returns true, and the connection is closed.
+    ///
+    /// Unlike [`max_buf_size`](Builder::max_buf_size), this doesn't change
+    /// how much is buffered while reading. A head still has to fit in the
+    /// read buffer, so the smaller of the two limits applies.
+    ///
+    /// Default is no limit beyond the read buffer's.
+    pub fn max_response_header_size(&mut self, max: usize) -> &mut Self {
+        self.h1_max_header_size = Some(max);
+        self
+    }
+
     /// Set whether to support preserving original header order.
     ///
     /// Currently, this will record the order in which headers are received, and store this
@@ -479,12 +698,64 @@ impl Builder {
 
     /// Sets the exact size of the read buffer to *always* use.
     ///
-    /// Note that setting this option unsets the `max_buf_size` option.
+    /// Note that setting this option unsets the `max_buf_size`,
+    /// `initial_read_buf_size` and `max_read_buf_size` options.
     ///
     /// Default is an adaptive read buffer.
     pub fn read_buf_exact_size(&mut self, sz: Option<usize>) -> &mut Builder {
         self.h1_read_buf_exact_size = sz;
         self.h1_max_buf_size = None;
+        self.h1_initial_read_buf_size = None;
+        self.h1_max_read_buf_size = None;
+        self
+    }
+
+    /// Sets the size the adaptive read buffer starts at.
+    ///
+    /// The buffer grows from here as reads fill it, up to the maximum read
+    /// buffer size, and shrinks back no further than this.
+    ///
+    /// Note that setting this option unsets the `read_buf_exact_size` option.
+    ///
+    /// Default is 8kb.
+    ///
+    /// # Panics
+    ///
+    /// The minimum value allowed is 64. This method panics if the passed `sz` is less than the minimum.
+    pub fn initial_read_buf_size(&mut self, sz: usize) -> &mut Self {
+        assert!(
+            sz >= proto::h1::MINIMUM_INIT_READ_BUF_SIZE,
+            "the initial_read_buf_size cannot be smaller than the minimum that h1 specifies."
+        );
+
+        self.h1_initial_read_buf_size = Some(sz);
+        self.h1_read_buf_exact_size = None;
+        self
+    }
+
+    /// Sets the size the adaptive read buffer may grow to.
+    ///
+    /// A response head still incomplete once the buffer is this big fails
+    /// with a "message head is too large" error.
+    ///
+    /// Unlike [`max_buf_size`](Builder::max_buf_size), this leaves the write
+    /// buffer alone. If both are set, this one applies to the read buffer.
+    ///
+    /// Note that setting this option unsets the `read_buf_exact_size` option.
+    ///
+    /// Default is ~400kb.
+    ///
+    /// # Panics
+    ///
+    /// The minimum value allowed is 8192. This method panics if the passed `max` is less than the minimum.
+    pub fn max_read_buf_size(&mut self, max: usize) -> &mut Self {
+        assert!(
+            max >= proto::h1::MINIMUM_MAX_BUFFER_SIZE,
+            "the max_read_buf_size cannot be smaller than the minimum that h1 specifies."
+        );
+
+        self.h1_max_read_buf_size = Some(max);
+        self.h1_read_buf_exact_size = None;
         self
     }
 
@@ -508,11 +779,179 @@ impl Builder {
         self
     }
 
+    /// Set how long a request with an `Expect: 100-continue` header waits
+    /// for a `100 Continue` before its body is sent.
+    ///
+    /// The body is sent as soon as the `100 Continue` arrives. If the server
+    /// answers with a final response instead, the body is never sent, and
+    /// the connection is closed once that response has been read.
+    ///
+    /// Requires a [`Timer`] set by [`Builder::timer`] to take effect. Panics if `expect_continue_timeout` is configured
+    /// without a [`Timer`].
+    ///
+    /// Pass `None` to send the body right away, without waiting.
+    ///
+    /// Default is `None`.
+    pub fn expect_continue_timeout(&mut self, timeout: impl Into<Option<Duration>>) -> &mut Self {
+        self.h1_expect_continue_timeout = Dur::Configured(timeout.into());
+        self
+    }
+
+    /// Set whether a request fails when its `expect_continue_timeout`
+    /// elapses.
+    ///
+    /// If disabled, the body is sent anyway once the timeout elapses, as
+    /// [RFC 9110 Section 10.1.1] suggests for servers that don't know about
+    /// `Expect`. If enabled, the request fails with an error for which
+    /// [`Error::is_timeout`](crate::Error::is_timeout) returns true, and the
+    /// connection is closed.
+    ///
+    /// Default is false.
+    ///
+    /// [RFC 9110 Section 10.1.1]: https://www.rfc-editor.org/rfc/rfc9110#section-10.1.1
+    pub fn abort_on_expect_continue_timeout(&mut self, enabled: bool) -> &mut Self {
+        self.h1_abort_on_expect_continue_timeout = enabled;
+        self
+    }
+
+    /// Set a body size at which requests get an `Expect: 100-continue`
+    /// header added automatically.
+    ///
+    /// Requests whose body's size hint is exactly at least `min_body_size`
+    /// bytes, and that don't already have an `Expect` header, get one. Their
+    /// body is then held back as described in
+    /// [`expect_continue_timeout`](Builder::expect_continue_timeout), so a
+    /// server that rejects the request does so before the body is uploaded.
+    /// Bodies without an exact size hint are left alone.
+    ///
+    /// If `expect_continue_timeout` isn't set, it defaults to 1 second while
+    /// this is enabled.
+    ///
+    /// Pass `None` to disable.
+    ///
+    /// Default is `None`.
+    pub fn auto_expect_continue(&mut self, min_body_size: impl Into<Option<u64>>) -> &mut Self {
+        self.h1_auto_expect_continue = min_body_size.into();
+        self
+    }
+
+    /// Set how long a request waits for its response headers once it has
+    /// been fully written.
+    ///
+    /// If they don't arrive in time, the request fails with an error for
+    /// which [`Error::is_timeout`](crate::Error::is_timeout) returns true,
+    /// and the connection is closed. Unlike a deadline, this doesn't limit
+    /// how long reading the response body may take. A single request can
+    /// override it with
+    /// [`ext::set_response_header_timeout`](crate::ext::set_response_header_timeout).
+    ///
+    /// Requires a [`Timer`] set by [`Builder::timer`] to take effect. Panics if `response_header_timeout` is configured
+    /// without a [`Timer`].
+    ///
+    /// Pass `None` to disable.
+    ///
+    /// Default is `None`.
+    pub fn response_header_timeout(&mut self, timeout: impl Into<Option<Duration>>) -> &mut Self {
+        self.h1_response_header_timeout = Dur::Configured(timeout.into());
+        self
+    }
+
+    /// Set how many requests may be sent while an earlier response is still
+    /// outstanding.
+    ///
+    /// With HTTP/1.1 pipelining, `SendRequest` becomes ready again as soon as
+    /// a request has been written, instead of once its response has been
+    /// read. Responses are still matched to requests in the order they were
+    /// sent.
+    ///
+    /// Nothing is pipelined after a `CONNECT` or upgrade request, and a
+    /// pipelined request with `Expect: 100-continue` sends its body without
+    /// waiting. If the server closes the connection while requests are
+    /// pipelined, the ones it didn't answer fail with an error for which
+    /// [`Error::is_canceled`](crate::Error::is_canceled) returns true, so they
+    /// can be sent again on a new connection. Once the server is seen to
+    /// speak HTTP/1.0, no more requests are pipelined.
+    ///
+    /// Default is 0, which disables pipelining.
+    pub fn max_pipelined_requests(&mut self, max: usize) -> &mut Self {
+        self.h1_max_pipelined_requests = max;
+        self
+    }
+
+    /// Set a callback to receive the stats of every completed request.
+    ///
+    /// The callback is given the [`RequestStats`] of each request on this
+    /// connection once its response body has been read to the end, with the
+    /// body's `last_body_byte_time` and `response_body_bytes` filled in. A
+    /// request that fails, a body that fails, and a response dropped before
+    /// its body ends are reported then instead.
+    ///
+    /// Default is no callback.
+    pub fn on_stats<F>(&mut self, callback: F) -> &mut Builder
+    where
+        F: FnMut(RequestStats) + Send + 'static,
+    {
+        self.on_stats = Some(OnStats::new(callback));
+        self
+    }
+
+    /// Set an observer to receive stats events as they happen.
+    ///
+    /// See [`ConnectionObserver`] for the events. The observer is not called
+    /// while [stats collection](Builder::collect_stats) is disabled.
+    ///
+    /// Default is no observer.
+    pub fn observer<O>(&mut self, observer: O) -> &mut Builder
+    where
+        O: ConnectionObserver + 'static,
+    {
+        self.observer = Some(Observer::new(observer));
+        self
+    }
+
+    /// Set whether to collect stats for requests on this connection.
+    ///
+    /// Disabling this skips reading the clock for each request, and leaves
+    /// out the timings and connection stats in the returned
+    /// [`HttpConnectionStats`]. The [`Builder::on_stats`] callback is not
+    /// called while disabled.
+    ///
+    /// Default is true.
+    pub fn collect_stats(&mut self, enabled: bool) -> &mut Builder {
+        self.collect_stats = enabled;
+        self
+    }
+
+    /// Set the clock that stats timestamps are read from.
+    ///
+    /// Default is the [`SystemClock`](crate::stats::SystemClock).
+    pub fn stats_clock<C>(&mut self, clock: C) -> &mut Builder
+    where
+        C: StatsClock + 'static,
+    {
+        self.stats_clock = Clock::new(clock);
+        self
+    }
+
+    /// Set the timer used in background tasks.
+    pub fn timer<M>(&mut self, timer: M) -> &mut Self
+    where
+        M: Timer + Send + Sync + 'static,
+    {
+        self.timer = Time::Timer(Arc::new(timer));
+        self
+    }
+
     /// Constructs a connection with the configured options and IO.
     /// See [`client::conn`](crate::client::conn) for more.
     ///
     /// Note, if [`Connection`] is not `await`-ed, [`SendRequest`] will
     /// do nothing.
+    ///
+    /// # Panics
+    ///
+    /// If a timeout option has been configured, but a `timer` has not been
+    /// provided, the returned future will panic.
     pub fn handshake<T, B>(
         &self,
         io: T,
@@ -528,9 +967,40 @@ impl Builder {
         async move {
             trace!("client handshake HTTP/1");
 
+            let observer = opts.observer.filter(|_| opts.collect_stats);
+            let mut io = io;
+            if let Some(ref observer) = observer {
+                observer.connected(&mut io);
+            }
+
             let (tx, rx) = dispatch::channel();
             let mut conn = proto::Conn::new(io);
             conn.set_h1_parser_config(opts.h1_parser_config);
+            conn.set_timer(opts.timer.clone());
+            let expect_continue_timeout = match opts.h1_expect_continue_timeout {
+                Dur::Default(None) if opts.h1_auto_expect_continue.is_some() => {
+                    Dur::Default(Some(DEFAULT_AUTO_EXPECT_CONTINUE_TIMEOUT))
+                }
+                dur => dur,
+            };
+            if let Some(dur) = opts
+                .timer
+                .check(expect_continue_timeout, "expect_continue_timeout")
+            {
+                conn.set_expect_continue_timeout(dur);
+            }
+            if let Some(min_body_size) = opts.h1_auto_expect_continue {
+                conn.set_auto_expect_continue(min_body_size);
+            }
+            if opts.h1_abort_on_expect_continue_timeout {
+                conn.set_abort_on_expect_continue_timeout();
+            }
+            if let Some(dur) = opts
+                .timer
+                .check(opts.h1_response_header_timeout, "response_header_timeout")
+            {
+                conn.set_response_header_timeout(dur);
+            }
             if let Some(writev) = opts.h1_writev {
                 if writev {
                     conn.set_write_strategy_queue();
@@ -544,9 +1014,15 @@ impl Builder {
             if opts.h1_preserve_header_case {
                 conn.set_preserve_header_case();
             }
+            if opts.h1_preserve_raw_headers {
+                conn.set_raw_headers();
+            }
             if let Some(max_headers) = opts.h1_max_headers {
                 conn.set_http1_max_headers(max_headers);
             }
+            if let Some(max_header_size) = opts.h1_max_header_size {
+                conn.set_http1_max_header_size(max_header_size);
+            }
             #[cfg(feature = "ffi")]
             if opts.h1_preserve_header_order {
                 conn.set_preserve_header_order();
@@ -562,10 +1038,39 @@ impl Builder {
             if let Some(max) = opts.h1_max_buf_size {
                 conn.set_max_buf_size(max);
             }
-            let cd = proto::h1::dispatch::Client::new(rx);
+            if opts.h1_initial_read_buf_size.is_some() || opts.h1_max_read_buf_size.is_some() {
+                conn.set_read_buf_sizes(opts.h1_initial_read_buf_size, opts.h1_max_read_buf_size);
+            }
+            let mut cd = proto::h1::dispatch::Client::new(rx);
+            if opts.h1_max_pipelined_requests > 0 {
+                conn.set_pipelining();
+                cd.set_max_pipelined(opts.h1_max_pipelined_requests);
+            }
+            if let Some(on_stats) = opts.on_stats {
+                cd.set_on_stats(on_stats);
+            }
+            if let Some(observer) = observer {
+                cd.set_observer(observer);
+            }
+            if !opts.collect_stats {
+                conn.disable_stats();
+                cd.disable_stats();
+            }
+            conn.set_clock(opts.stats_clock.clone());
+            cd.set_clock(opts.stats_clock.clone());
             let proto = proto::h1::Dispatcher::new(cd, conn);
 
-            Ok((SendRequest { dispatch: tx }, Connection { inner: proto }))
+            Ok((
+                SendRequest {
+                    dispatch: tx,
+                    collect_stats: opts.collect_stats,
+                    clock: opts.stats_clock,
+                },
+                Connection {
+                    inner: proto,
+                    graceful_shutdown: false,
+                },
+            ))
         }
     }
 }
@@ -602,8 +1107,12 @@ mod upgrades {
             match ready!(Pin::new(&mut self.inner.as_mut().unwrap().inner).poll(cx)) {
                 Ok(proto::Dispatched::Shutdown) => Poll::Ready(Ok(())),
                 Ok(proto::Dispatched::Upgrade(pending)) => {
-                    let Parts { io, read_buf } = self.inner.take().unwrap().into_parts();
-                    pending.fulfill(Upgraded::new(io, read_buf));
+                    let (io, read_buf, dispatch) = self.inner.take().unwrap().inner.into_inner();
+                    let mut upgraded = Upgraded::new(io, read_buf);
+                    if let Some(start) = dispatch.tunnel_start() {
+                        upgraded.set_tunnel_start(start);
+                    }
+                    pending.fulfill(upgraded);
                     Poll::Ready(Ok(()))
                 }
                 Err(e) => Poll::Ready(Err(e)),
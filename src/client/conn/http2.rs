@@ -7,28 +7,38 @@ use std::marker::PhantomData;
 use std::pin::Pin;
 use std::sync::Arc;
 use std::task::{Context, Poll};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use crate::rt::{Read, Stats, Write};
+use bytes::Bytes;
 use futures_core::ready;
 use http::{Request, Response};
 
 use super::super::dispatch::{self, TrySendError};
 use crate::body::{Body, Incoming as IncomingBody};
-use crate::common::time::Time;
+use crate::ext::AltSvc;
+use crate::common::stats::{CheckoutStart, Clock, Observer, OnStats};
+use crate::common::time::{Deadline, Time};
 use crate::rt::bounds::Http2ClientConnExec;
 use crate::rt::Timer;
-use crate::{proto, HttpConnectionStats};
+use crate::stats::{ConnectionObserver, StatsClock};
+use crate::{proto, H2Settings, HttpConnectionStats, RequestStats};
 
 /// The sender side of an established connection.
 pub struct SendRequest<B> {
     dispatch: dispatch::UnboundedSender<Request<B>, (HttpConnectionStats, Response<IncomingBody>)>,
+    collect_stats: bool,
+    clock: Clock,
+    streams: proto::h2::client::Streams,
 }
 
 impl<B> Clone for SendRequest<B> {
     fn clone(&self) -> SendRequest<B> {
         SendRequest {
             dispatch: self.dispatch.clone(),
+            collect_stats: self.collect_stats,
+            clock: self.clock.clone(),
+            streams: self.streams.clone(),
         }
     }
 }
@@ -50,6 +60,46 @@ where
     inner: (PhantomData<T>, proto::h2::ClientTask<B, E, T>),
 }
 
+/// The outcome of an `h2c` upgrade, from [`Builder::handshake_h2c`].
+#[cfg(feature = "http1")]
+// It's only ever matched right away, so boxing a variant buys nothing.
+#[allow(clippy::large_enum_variant)]
+pub enum H2cUpgrade<T, B, E>
+where
+    T: Read + Write + Stats + Unpin,
+    B: Body + 'static,
+    E: Http2ClientConnExec<B, T> + Unpin,
+    B::Error: Into<Box<dyn Error + Send + Sync>>,
+{
+    /// The server switched to HTTP/2.
+    Upgraded {
+        /// The sender for further requests.
+        sender: SendRequest<B>,
+        /// The connection, which must be polled for the response body to be
+        /// read.
+        conn: Connection<T, B, E>,
+        /// The response to the upgrade request, read from stream 1.
+        response: (HttpConnectionStats, Response<IncomingBody>),
+    },
+    /// The server ignored the upgrade, and answered over HTTP/1.1.
+    NotUpgraded {
+        /// The sender for further requests.
+        sender: super::http1::SendRequest<B>,
+        /// The connection, which must be polled for the response body to be
+        /// read.
+        conn: super::http1::Connection<T, B>,
+        /// The response to the upgrade request.
+        response: (HttpConnectionStats, Response<IncomingBody>),
+    },
+}
+
+/// A `GOAWAY` the server sent, from [`Connection::on_draining`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GoAway {
+    last_stream_id: u32,
+    reason: u32,
+}
+
 /// A builder to configure an HTTP connection.
 ///
 /// After setting options, the builder is used to create a handshake future.
@@ -117,6 +167,103 @@ impl<B> SendRequest<B> {
     pub fn is_closed(&self) -> bool {
         self.dispatch.is_closed()
     }
+
+    /// Returns how many streams the server currently lets this connection
+    /// have open at once.
+    ///
+    /// This is the server's `SETTINGS_MAX_CONCURRENT_STREAMS`. Until its
+    /// settings arrive, it's the value of
+    /// [`Builder::initial_max_send_streams`].
+    pub fn max_concurrent_streams(&self) -> usize {
+        self.streams.max_concurrent()
+    }
+
+    /// Returns how many streams this connection currently has open for
+    /// requests.
+    ///
+    /// A stream counts from when the connection starts sending its request
+    /// until the request body is written and the response body is done,
+    /// either received along with the response head or dropped. Requests
+    /// still queued for the connection don't count.
+    ///
+    /// Together with [`max_concurrent_streams`](Self::max_concurrent_streams),
+    /// this tells whether another request can be sent right away, or
+    /// whether it would wait for a stream to close.
+    pub fn current_streams(&self) -> usize {
+        self.streams.current()
+    }
+
+    /// Returns the settings the server has sent on this connection.
+    ///
+    /// Every SETTINGS frame received so far is applied, so this is what the
+    /// server currently allows. It's `None` until the server's first
+    /// SETTINGS frame has been read.
+    ///
+    /// The settings as they were when each response arrived are also added
+    /// to the extensions of the `Response`.
+    pub fn peer_settings(&self) -> Option<H2Settings> {
+        self.streams.peer_settings()
+    }
+
+    /// Returns whether the server allows the [extended CONNECT
+    /// protocol][rfc], such as for WebSockets.
+    ///
+    /// It's `None` until the server's first SETTINGS frame has been read,
+    /// after which it is whether that frame, or a later one, set
+    /// `SETTINGS_ENABLE_CONNECT_PROTOCOL`. A client that gets `Some(false)`
+    /// should fall back to HTTP/1 before building an extended `CONNECT`
+    /// request.
+    ///
+    /// [rfc]: https://datatracker.ietf.org/doc/html/rfc8441
+    pub fn is_extended_connect_enabled(&self) -> Option<bool> {
+        self.peer_settings()
+            .map(|settings| settings.enable_connect_protocol == Some(1))
+    }
+
+    /// Returns the origin set the server has advertised in [`ORIGIN`
+    /// frames][rfc], if it sent any.
+    ///
+    /// Every `ORIGIN` frame received so far adds to the set, up to 64
+    /// origins. Each origin is as the server serialized it, such as
+    /// `https://example.com`. A client
+    /// that coalesces connections can use this to decide which origins it
+    /// may send requests for on this connection. `ORIGIN` frames should only
+    /// be trusted on TLS connections, whose certificate also covers the
+    /// origin.
+    ///
+    /// [rfc]: https://www.rfc-editor.org/rfc/rfc8336
+    pub fn peer_origin_set(&self) -> Option<Vec<String>> {
+        self.streams.origin_set()
+    }
+
+    /// Returns the alternative services the server has advertised in
+    /// [`ALTSVC` frames][rfc], such as an HTTP/3 endpoint.
+    ///
+    /// There is one for each origin, from the latest frame naming it, for up
+    /// to 32 origins. A
+    /// value of `clear` means the server withdrew its alternatives for that
+    /// origin. `ALTSVC` frames sent on a request's stream, which apply to
+    /// the origin of that request, are not collected.
+    ///
+    /// [rfc]: https://www.rfc-editor.org/rfc/rfc7838#section-4
+    pub fn peer_alt_svc(&self) -> Vec<AltSvc> {
+        self.streams.alt_svc()
+    }
+
+    /// Sends the server a `PING` and measures the round trip time.
+    ///
+    /// The returned future resolves once the server acknowledges the
+    /// `PING`, and fails if the connection closes first. If a `PING` is
+    /// already in flight, such as one sent for keep-alive or the adaptive
+    /// window, its acknowledgement is used instead of sending another.
+    ///
+    /// When [`collect_stats`](Builder::collect_stats) is enabled, the
+    /// sample is also recorded in [`H2ConnectionStats::ping_rtts`].
+    ///
+    /// [`H2ConnectionStats::ping_rtts`]: crate::H2ConnectionStats::ping_rtts
+    pub fn ping(&self) -> impl Future<Output = crate::Result<Duration>> {
+        self.streams.ping()
+    }
 }
 
 impl<B> SendRequest<B>
@@ -131,10 +278,22 @@ where
     ///
     /// Absolute-form `Uri`s are not required. If received, they will be serialized
     /// as-is.
+    ///
+    /// # CONNECT
+    ///
+    /// A `CONNECT` request opens a tunnel on its own stream. Once a 2xx
+    /// response arrives, pass it to [`hyper::upgrade::on`](crate::upgrade::on)
+    /// to get the tunnel as an [`Upgraded`](crate::upgrade::Upgraded) IO,
+    /// whose bytes are sent and received as DATA frames. The request body
+    /// must be empty. Tunnels share the connection with each other and with
+    /// ordinary requests, as a forward proxy would need.
     pub fn send_request(
         &mut self,
-        req: Request<B>,
+        mut req: Request<B>,
     ) -> impl Future<Output = crate::Result<(HttpConnectionStats, Response<IncomingBody>)>> {
+        if self.collect_stats {
+            CheckoutStart::mark(&mut req, &self.clock);
+        }
         let sent = self.dispatch.send(req);
 
         async move {
@@ -154,6 +313,33 @@ where
         }
     }
 
+    /// Sends a `Request` on the associated connection, failing it if it
+    /// isn't done by `deadline`.
+    ///
+    /// The deadline covers the response head and body. It starts to count
+    /// once the connection picks up the request. If it passes before the
+    /// response head arrives, the returned future fails with an error for
+    /// which [`Error::is_timeout`](crate::Error::is_timeout) returns true,
+    /// and whose [`stats`](crate::Error::stats) hold what was recorded so
+    /// far. If it passes while the body is being read, the body yields that
+    /// error instead.
+    ///
+    /// The request's stream is reset when the deadline passes, leaving the
+    /// connection usable for other requests.
+    ///
+    /// # Panics
+    ///
+    /// The connection panics if a deadline is set but a `timer` was not
+    /// given to its `Builder`.
+    pub fn send_request_with_deadline(
+        &mut self,
+        mut req: Request<B>,
+        deadline: Instant,
+    ) -> impl Future<Output = crate::Result<(HttpConnectionStats, Response<IncomingBody>)>> {
+        req.extensions_mut().insert(Deadline(deadline));
+        self.send_request(req)
+    }
+
     /// Sends a `Request` on the associated connection.
     ///
     /// Returns a future that if successful, yields the `Response`.
@@ -164,15 +350,18 @@ where
     /// connection, the message will be returned as part of this error.
     pub fn try_send_request(
         &mut self,
-        req: Request<B>,
+        mut req: Request<B>,
     ) -> impl Future<Output = Result<(HttpConnectionStats, Response<IncomingBody>), TrySendError<Request<B>>>>
     {
+        if self.collect_stats {
+            CheckoutStart::mark(&mut req, &self.clock);
+        }
         let sent = self.dispatch.try_send(req);
         async move {
             match sent {
                 Ok(rx) => match rx.await {
                     Ok(Ok(res)) => Ok(res),
-                    Ok(Err(err)) => Err(err),
+                    Ok(Err(err)) => Err(err.record_retry()),
                     // this is definite bug if it happens, but it shouldn't happen!
                     Err(_) => panic!("dispatch dropped without returning error"),
                 },
@@ -217,6 +406,66 @@ where
     pub fn is_extended_connect_protocol_enabled(&self) -> bool {
         self.inner.1.is_extended_connect_protocol_enabled()
     }
+
+    /// Returns a future that resolves once the server sends a `GOAWAY`.
+    ///
+    /// A `GOAWAY` means the connection is draining: requests already sent
+    /// on streams up to its last stream ID may still be answered, but no new
+    /// ones will be. Once it resolves, stop sending requests on this
+    /// connection, and open a replacement.
+    ///
+    /// Only the first `GOAWAY` is given. A graceful shutdown usually starts
+    /// with one whose last stream ID is the largest possible, before the
+    /// server sends the real one.
+    ///
+    /// The future doesn't borrow the `Connection`, so it can be taken before
+    /// the `Connection` is spawned. It resolves to `None` if the connection
+    /// closes without a `GOAWAY`.
+    pub fn on_draining(&self) -> impl Future<Output = Option<GoAway>> {
+        let go_away = self.inner.1.streams().on_go_away();
+        async move {
+            let (last_stream_id, reason) = go_away.await.ok()?;
+            Some(GoAway {
+                last_stream_id,
+                reason,
+            })
+        }
+    }
+}
+
+// ===== impl GoAway
+
+impl GoAway {
+    /// Returns the highest stream ID the server may have processed.
+    ///
+    /// Requests sent on streams above this weren't processed, and can be
+    /// retried on another connection.
+    pub fn last_stream_id(&self) -> u32 {
+        self.last_stream_id
+    }
+
+    /// Returns the HTTP/2 error code, such as `NO_ERROR` (`0x0`) for a
+    /// graceful shutdown.
+    pub fn reason(&self) -> u32 {
+        self.reason
+    }
+}
+
+#[cfg(feature = "http1")]
+impl<T, B, E> fmt::Debug for H2cUpgrade<T, B, E>
+where
+    T: Read + Write + Stats + Unpin,
+    B: Body + 'static,
+    E: Http2ClientConnExec<B, T> + Unpin,
+    B::Error: Into<Box<dyn Error + Send + Sync>>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (name, response) = match self {
+            H2cUpgrade::Upgraded { response, .. } => ("Upgraded", &response.1),
+            H2cUpgrade::NotUpgraded { response, .. } => ("NotUpgraded", &response.1),
+        };
+        f.debug_struct(name).field("response", response).finish()
+    }
 }
 
 impl<T, B, E> fmt::Debug for Connection<T, B, E>
@@ -326,7 +575,10 @@ where
     ///
     /// Enabling this will override the limits set in
     /// `initial_stream_window_size` and
-    /// `initial_connection_window_size`.
+    /// `initial_connection_window_size`. Windows then start at the spec
+    /// default and grow with the measured bandwidth-delay product, see
+    /// [`adaptive_window_max`](Self::adaptive_window_max) and
+    /// [`adaptive_window_ping_interval`](Self::adaptive_window_ping_interval).
     pub fn adaptive_window(&mut self, enabled: bool) -> &mut Self {
         use proto::h2::SPEC_WINDOW_SIZE;
 
@@ -338,6 +590,35 @@ where
         self
     }
 
+    /// Sets the largest window adaptive flow control will grow to.
+    ///
+    /// Windows sized to the bandwidth-delay product of a fast, distant link
+    /// can take a lot of memory per connection; this caps them. It has no
+    /// effect unless [`adaptive_window`](Self::adaptive_window) is enabled.
+    ///
+    /// Passing `None` restores the default, which is currently 16MB.
+    pub fn adaptive_window_max(&mut self, max: impl Into<Option<u32>>) -> &mut Self {
+        self.h2_builder.adaptive_window_max = max.into();
+        self
+    }
+
+    /// Sets the shortest interval between the PING frames adaptive flow
+    /// control sends to estimate the bandwidth-delay product.
+    ///
+    /// Pings start this far apart, and are sent less often, up to every 10
+    /// seconds, while the estimate holds steady. It has no effect unless
+    /// [`adaptive_window`](Self::adaptive_window) is enabled.
+    ///
+    /// Passing `None` restores the default: pings start 100ms apart, and may
+    /// be sent more often while the estimate is still growing.
+    pub fn adaptive_window_ping_interval(
+        &mut self,
+        interval: impl Into<Option<Duration>>,
+    ) -> &mut Self {
+        self.h2_builder.adaptive_window_ping_interval = interval.into();
+        self
+    }
+
     /// Sets the maximum frame size to use for HTTP2.
     ///
     /// Default is currently 16KB, but can change.
@@ -466,6 +747,60 @@ where
         self
     }
 
+    /// Set a callback to receive the stats of every completed request.
+    ///
+    /// The callback is given the [`RequestStats`] of each request on this
+    /// connection once its response body has been read to the end, with the
+    /// body's `last_body_byte_time` and `response_body_bytes` filled in. A
+    /// request that fails, a body that fails, and a response dropped before
+    /// its body ends are reported then instead.
+    ///
+    /// Default is no callback.
+    pub fn on_stats<F>(&mut self, callback: F) -> &mut Self
+    where
+        F: FnMut(RequestStats) + Send + 'static,
+    {
+        self.h2_builder.on_stats = Some(OnStats::new(callback));
+        self
+    }
+
+    /// Set an observer to receive stats events as they happen.
+    ///
+    /// See [`ConnectionObserver`] for the events. The observer is not called
+    /// while [stats collection](Builder::collect_stats) is disabled.
+    ///
+    /// Default is no observer.
+    pub fn observer<O>(&mut self, observer: O) -> &mut Self
+    where
+        O: ConnectionObserver + 'static,
+    {
+        self.h2_builder.observer = Some(Observer::new(observer));
+        self
+    }
+
+    /// Set whether to collect stats for requests on this connection.
+    ///
+    /// Disabling this skips reading the clock for each request, and leaves
+    /// out the timings in the returned [`HttpConnectionStats`]. The
+    /// [`Builder::on_stats`] callback is not called while disabled.
+    ///
+    /// Default is true.
+    pub fn collect_stats(&mut self, enabled: bool) -> &mut Self {
+        self.h2_builder.collect_stats = enabled;
+        self
+    }
+
+    /// Set the clock that stats timestamps are read from.
+    ///
+    /// Default is the [`SystemClock`](crate::stats::SystemClock).
+    pub fn stats_clock<C>(&mut self, clock: C) -> &mut Self
+    where
+        C: StatsClock + 'static,
+    {
+        self.h2_builder.stats_clock = Clock::new(clock);
+        self
+    }
+
     /// Constructs a connection with the configured options and IO.
     /// See [`client::conn`](crate::client::conn) for more.
     ///
@@ -475,6 +810,109 @@ where
         &self,
         io: T,
     ) -> impl Future<Output = crate::Result<(SendRequest<B>, Connection<T, B, Ex>)>>
+    where
+        T: Read + Write + Stats + Unpin,
+        B: Body + 'static,
+        B::Data: Send,
+        B::Error: Into<Box<dyn Error + Send + Sync>>,
+        Ex: Http2ClientConnExec<B, T> + Unpin,
+    {
+        self.handshake_with(io, None)
+    }
+
+    /// Upgrades an HTTP/1.1 connection to HTTP/2, sending `req` with
+    /// `Upgrade: h2c` as described in [RFC 7540 section 3.2][1].
+    ///
+    /// `req` is sent over HTTP/1.1, along with the `HTTP2-Settings` of this
+    /// builder, and its body is sent in full before the upgrade. If the
+    /// server switches protocols, the connection carries on as HTTP/2, and
+    /// the response to `req` is read from stream 1. If it doesn't, the
+    /// response came over HTTP/1.1, and the connection is handed back as
+    /// such.
+    ///
+    /// This is meant for servers reached without TLS, where ALPN can't pick
+    /// the protocol.
+    ///
+    /// A `HEAD` request isn't offered the upgrade, and is sent over
+    /// HTTP/1.1 as is.
+    ///
+    /// [1]: https://datatracker.ietf.org/doc/html/rfc7540#section-3.2
+    #[cfg(feature = "http1")]
+    pub fn handshake_h2c<T, B>(
+        &self,
+        io: T,
+        req: Request<B>,
+    ) -> impl Future<Output = crate::Result<H2cUpgrade<T, B, Ex>>>
+    where
+        T: Read + Write + Stats + Unpin + 'static,
+        B: Body + Default + Unpin + 'static,
+        B::Data: Send,
+        B::Error: Into<Box<dyn Error + Send + Sync>>,
+        Ex: Http2ClientConnExec<B, T> + Unpin,
+    {
+        use http::header::{HeaderName, HeaderValue, CONNECTION, UPGRADE};
+        use http::{Method, StatusCode};
+
+        let opts = self.clone();
+
+        async move {
+            trace!("client handshake h2c upgrade");
+
+            // h2 opens stream 1 with this request, which isn't sent. Its
+            // header block is dropped, so it must not add to the HPACK
+            // table the server decodes with: `GET /` with no headers is
+            // encoded from the static table alone. The server answers the
+            // upgrade request itself, whose body has gone over HTTP/1.1
+            // already.
+            let replay = Request::new(B::default());
+
+            // The response to a HEAD can't be read as the response to a GET.
+            let offer = req.method() != Method::HEAD;
+            let mut req = req;
+            if offer {
+                let headers = req.headers_mut();
+                headers.insert(
+                    CONNECTION,
+                    HeaderValue::from_static("Upgrade, HTTP2-Settings"),
+                );
+                headers.insert(UPGRADE, HeaderValue::from_static("h2c"));
+                headers.insert(
+                    HeaderName::from_static("http2-settings"),
+                    proto::h2::client::h2c_settings(&opts.h2_builder),
+                );
+            }
+
+            let (mut tx, mut conn) = super::http1::handshake(io).await?;
+            let (response, conn_done) =
+                drive(tx.send_request(req), |cx| conn.poll_without_shutdown(cx)).await?;
+            if !offer || response.1.status() != StatusCode::SWITCHING_PROTOCOLS {
+                return Ok(H2cUpgrade::NotUpgraded {
+                    sender: tx,
+                    conn,
+                    response,
+                });
+            }
+            if !conn_done {
+                crate::common::future::poll_fn(|cx| conn.poll_without_shutdown(cx)).await?;
+            }
+            let parts = conn.into_parts();
+
+            let (mut sender, mut conn) = opts.handshake_with(parts.io, Some(parts.read_buf)).await?;
+            let (response, _) =
+                drive(sender.send_request(replay), |cx| Pin::new(&mut conn).poll(cx)).await?;
+            Ok(H2cUpgrade::Upgraded {
+                sender,
+                conn,
+                response,
+            })
+        }
+    }
+
+    fn handshake_with<T, B>(
+        &self,
+        io: T,
+        h2c_read_buf: Option<Bytes>,
+    ) -> impl Future<Output = crate::Result<(SendRequest<B>, Connection<T, B, Ex>)>>
     where
         T: Read + Write + Stats + Unpin,
         B: Body + 'static,
@@ -488,11 +926,21 @@ where
             trace!("client handshake HTTP/2");
 
             let (tx, rx) = dispatch::channel();
-            let h2 = proto::h2::client::handshake(io, rx, &opts.h2_builder, opts.exec, opts.timer)
-                .await?;
+            let h2 = proto::h2::client::handshake(
+                io,
+                h2c_read_buf,
+                rx,
+                &opts.h2_builder,
+                opts.exec,
+                opts.timer,
+            )
+            .await?;
             Ok((
                 SendRequest {
                     dispatch: tx.unbound(),
+                    collect_stats: opts.h2_builder.collect_stats,
+                    clock: opts.h2_builder.stats_clock.clone(),
+                    streams: h2.streams(),
                 },
                 Connection {
                     inner: (PhantomData, h2),
@@ -502,6 +950,30 @@ where
     }
 }
 
+/// Polls a connection along with a response future until the response is
+/// ready, returning it and whether the connection finished on the way.
+#[cfg(feature = "http1")]
+async fn drive<R>(
+    res: impl Future<Output = crate::Result<R>>,
+    mut poll_conn: impl FnMut(&mut Context<'_>) -> Poll<crate::Result<()>>,
+) -> crate::Result<(R, bool)> {
+    let mut res = Box::pin(res);
+    let mut conn_done = false;
+    crate::common::future::poll_fn(move |cx| {
+        if let Poll::Ready(res) = res.as_mut().poll(cx) {
+            return Poll::Ready(res.map(|res| (res, conn_done)));
+        }
+        if !conn_done {
+            if let Poll::Ready(res) = poll_conn(cx) {
+                res?;
+                conn_done = true;
+            }
+        }
+        Poll::Pending
+    })
+    .await
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -298,7 +298,10 @@ impl<T> TrySendError<T> {
     ///
     /// The message will not always have been recovered. If an error occurs
     /// after the message has been serialized onto the connection, it will not
-    /// be available here.
+    /// be available here. A recovered request has the failed attempt recorded
+    /// for its stats, as by [`RetryStats::record_on`].
+    ///
+    /// [`RetryStats::record_on`]: crate::RetryStats::record_on
     pub fn take_message(&mut self) -> Option<T> {
         self.message.take()
     }
@@ -375,6 +378,18 @@ where
     }
 }
 
+impl<B> TrySendError<http::Request<B>> {
+    /// Records the failed attempt on the request handed back, if any.
+    pub(crate) fn record_retry(mut self) -> Self {
+        if let Some(ref mut req) = self.message {
+            if let Some(retry) = crate::RetryStats::from_error(&self.error) {
+                retry.record_on(req);
+            }
+        }
+        self
+    }
+}
+
 #[cfg(test)]
 mod tests {
     #[cfg(feature = "nightly")]
@@ -1,6 +1,7 @@
 //! HTTP Client
 //!
 //! hyper provides HTTP over a single connection. See the [`conn`] module.
+//! Connections can be kept around and reused with a [`pool::Pool`].
 //!
 //! ## Examples
 //!
@@ -19,4 +20,5 @@ cfg_feature! {
 
     pub mod conn;
     pub(super) mod dispatch;
+    pub mod pool;
 }
@@ -0,0 +1,577 @@
+//! A keyed pool of client connections.
+//!
+//! The [`conn`](super::conn) module hands out one [`SendRequest`] per
+//! connection, and leaves it to the caller to decide when to open another one
+//! or to reuse an existing one. A [`Pool`] keeps those senders around per key
+//! (usually the scheme and authority the connection was made to), so requests
+//! for the same key can reuse them.
+//!
+//! Requests sent through a [`Pooled`] sender report how long they waited for
+//! that sender in their [`HttpConnectionStats`], from the moment the
+//! [`Checkout`] was started, instead of from the moment the request reached
+//! the connection.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! # #[cfg(feature = "http1")]
+//! # async fn run<T>(io: T, req: hyper::Request<http_body_util::Empty<bytes::Bytes>>) -> hyper::Result<()>
+//! # where
+//! #     T: hyper::rt::Read + hyper::rt::Write + hyper::rt::Stats + Send + Unpin + 'static,
+//! # {
+//! use hyper::client::conn::http1;
+//! use hyper::client::pool::Pool;
+//!
+//! let pool = Pool::<&str, http1::SendRequest<_>>::new();
+//! let checkout = pool.checkout("example.com:80");
+//!
+//! // Without an idle connection, `checkout` would wait for one to be given
+//! // back. A real client would race it against connecting instead.
+//! let (sender, conn) = http1::handshake(io).await?;
+//! tokio::spawn(conn);
+//! let mut sender = checkout.connected(sender);
+//!
+//! let (stats, res) = sender.send_request(req).await?;
+//! println!("waited {:?} for a connection", stats.get_pool_wait());
+//! # Ok(())
+//! # }
+//! # fn main() {}
+//! ```
+//!
+//! [`SendRequest`]: super::conn::http1::SendRequest
+//! [`HttpConnectionStats`]: crate::HttpConnectionStats
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::future::Future;
+use std::hash::Hash;
+use std::ops::{Deref, DerefMut};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+use http::Request;
+
+#[cfg(feature = "http1")]
+use super::conn::http1;
+#[cfg(feature = "http2")]
+use super::conn::http2;
+use crate::body::{Body, Incoming as IncomingBody};
+use crate::common::stats::{CheckoutStart, Clock};
+use crate::stats::StatsClock;
+use crate::HttpConnectionStats;
+
+/// A sender that can be kept in a [`Pool`].
+///
+/// This is implemented for the `SendRequest` types of the
+/// [`conn`](super::conn) module, and can't be implemented outside of hyper.
+pub trait Poolable: sealed::Sealed + Send + 'static {}
+
+mod sealed {
+    pub trait Sealed: Sized {
+        /// Whether the connection can still take requests.
+        fn is_open(&self) -> bool;
+
+        /// Whether the connection can take a request right now, as opposed to
+    /// still finishing the last one.
+        fn is_ready(&self) -> bool;
+
+        /// Returns another sender for the same connection, if it can carry
+        /// more than one request at a time.
+        fn share(&self) -> Option<Self>;
+    }
+}
+
+#[cfg(feature = "http1")]
+impl<B: Send + 'static> Poolable for http1::SendRequest<B> {}
+
+#[cfg(feature = "http1")]
+impl<B: Send + 'static> sealed::Sealed for http1::SendRequest<B> {
+    fn is_open(&self) -> bool {
+        !self.is_closed()
+    }
+
+    fn is_ready(&self) -> bool {
+        http1::SendRequest::is_ready(self)
+    }
+
+    fn share(&self) -> Option<Self> {
+        None
+    }
+}
+
+#[cfg(feature = "http2")]
+impl<B: Send + 'static> Poolable for http2::SendRequest<B> {}
+
+#[cfg(feature = "http2")]
+impl<B: Send + 'static> sealed::Sealed for http2::SendRequest<B> {
+    fn is_open(&self) -> bool {
+        !self.is_closed()
+    }
+
+    fn is_ready(&self) -> bool {
+        http2::SendRequest::is_ready(self)
+    }
+
+    fn share(&self) -> Option<Self> {
+        Some(self.clone())
+    }
+}
+
+/// A pool of connections, keyed by `K`.
+///
+/// Cloning a `Pool` gives another handle to the same connections.
+///
+/// HTTP/1 senders are handed out to one [`Pooled`] at a time, and go back
+/// into the pool when it is dropped. Checkouts prefer senders that are ready
+/// for another request, but may get one whose connection is still finishing
+/// the previous response, so wait on its `ready` before sending. HTTP/2
+/// senders are shared by every checkout of their key for as long as the
+/// connection is open.
+pub struct Pool<K, T> {
+    inner: Arc<Mutex<Inner<K, T>>>,
+}
+
+/// A builder to configure a [`Pool`].
+#[derive(Clone, Debug)]
+pub struct Builder {
+    idle_timeout: Option<Duration>,
+    max_idle_per_key: usize,
+    clock: Clock,
+}
+
+struct Inner<K, T> {
+    idle: HashMap<K, VecDeque<Idle<T>>>,
+    waiters: HashMap<K, Vec<(u64, Waker)>>,
+    idle_timeout: Option<Duration>,
+    max_idle_per_key: usize,
+    clock: Clock,
+}
+
+struct Idle<T> {
+    value: T,
+    idle_at: Instant,
+}
+
+/// A future waiting for a connection from a [`Pool`].
+///
+/// It resolves once a connection for its key is idle in the pool. If there is
+/// none, call [`Checkout::connected`] with a new connection instead.
+#[must_use = "futures do nothing unless polled"]
+pub struct Checkout<K: Eq + Hash + Clone, T: Poolable> {
+    pool: Pool<K, T>,
+    key: K,
+    id: u64,
+    started: Instant,
+}
+
+/// A connection checked out of a [`Pool`].
+///
+/// This derefs to the underlying `SendRequest`. Its own `send_request`
+/// records the time spent in the [`Checkout`] in the request's stats.
+pub struct Pooled<K: Eq + Hash + Clone, T: Poolable> {
+    value: Option<T>,
+    key: K,
+    pool: Pool<K, T>,
+    checkout_start: Instant,
+    reused: bool,
+}
+
+// ===== impl Pool
+
+impl<K, T> Pool<K, T>
+where
+    K: Eq + Hash + Clone,
+    T: Poolable,
+{
+    /// Creates a new pool with the default configuration.
+    pub fn new() -> Pool<K, T> {
+        Builder::new().build()
+    }
+
+    /// Creates a new pool builder.
+    pub fn builder() -> Builder {
+        Builder::new()
+    }
+
+    /// Starts waiting for a connection to `key`.
+    ///
+    /// The returned [`Checkout`] remembers when it was started, so requests
+    /// sent on the connection it yields count the wait as checkout time.
+    pub fn checkout(&self, key: K) -> Checkout<K, T> {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+        let started = self.lock().clock.now();
+        Checkout {
+            pool: self.clone(),
+            key,
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            started,
+        }
+    }
+
+    /// Adds a new connection to `key`, and checks it out.
+    ///
+    /// This is the same as [`Checkout::connected`], with the checkout
+    /// starting now.
+    pub fn insert(&self, key: K, value: T) -> Pooled<K, T> {
+        self.checkout(key).connected(value)
+    }
+
+    /// Returns the number of idle connections to `key`.
+    ///
+    /// Shared HTTP/2 connections count as idle even while they are in use.
+    pub fn idle_count(&self, key: &K) -> usize {
+        self.lock().idle.get(key).map_or(0, VecDeque::len)
+    }
+
+    /// Drops idle connections that have closed, or have been idle for longer
+    /// than the idle timeout.
+    ///
+    /// Checkouts already skip those; this frees them without waiting for
+    /// another checkout of their key.
+    pub fn clear_expired(&self) {
+        let mut inner = self.lock();
+        let now = inner.clock.now();
+        let timeout = inner.idle_timeout;
+        inner.idle.retain(|_, list| {
+            list.retain(|idle| !idle.expired(now, timeout));
+            !list.is_empty()
+        });
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner<K, T>> {
+        self.inner.lock().unwrap()
+    }
+
+    fn put(&self, key: K, value: T) {
+        let mut inner = self.lock();
+        let idle_at = inner.clock.now();
+        let max = inner.max_idle_per_key;
+        let list = inner.idle.entry(key.clone()).or_default();
+        if list.len() >= max {
+            trace!("pool: max idle per key reached, dropping connection");
+            return;
+        }
+        list.push_back(Idle { value, idle_at });
+        if let Some(waiters) = inner.waiters.remove(&key) {
+            for (_, waker) in waiters {
+                waker.wake();
+            }
+        }
+    }
+}
+
+impl<K, T> Clone for Pool<K, T> {
+    fn clone(&self) -> Pool<K, T> {
+        Pool {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<K, T> Default for Pool<K, T>
+where
+    K: Eq + Hash + Clone,
+    T: Poolable,
+{
+    fn default() -> Pool<K, T> {
+        Pool::new()
+    }
+}
+
+impl<K, T> fmt::Debug for Pool<K, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Pool").finish()
+    }
+}
+
+// ===== impl Inner
+
+impl<K: Eq + Hash + Clone, T: Poolable> Inner<K, T> {
+    /// Takes a connection to `key`, preferring one that is ready, and drops
+    /// any that have closed or expired on the way.
+    fn take(&mut self, key: &K) -> Option<T> {
+        let now = self.clock.now();
+        let timeout = self.idle_timeout;
+        let list = self.idle.get_mut(key)?;
+        list.retain(|idle| !idle.expired(now, timeout));
+        if list.is_empty() {
+            self.idle.remove(key);
+            return None;
+        }
+
+        let i = list
+            .iter()
+            .position(|idle| idle.value.is_ready())
+            .unwrap_or(0);
+        let taken = match list[i].value.share() {
+            Some(shared) => shared,
+            None => list.remove(i).expect("in bounds").value,
+        };
+        if list.is_empty() {
+            self.idle.remove(key);
+        }
+        Some(taken)
+    }
+}
+
+// ===== impl Idle
+
+impl<T: Poolable> Idle<T> {
+    fn expired(&self, now: Instant, timeout: Option<Duration>) -> bool {
+        if !self.value.is_open() {
+            return true;
+        }
+        // shared connections are never really idle
+        if self.value.share().is_some() {
+            return false;
+        }
+        match timeout {
+            Some(timeout) => now.saturating_duration_since(self.idle_at) > timeout,
+            None => false,
+        }
+    }
+}
+
+// ===== impl Builder
+
+impl Builder {
+    /// Creates a new pool builder with the default configuration.
+    pub fn new() -> Builder {
+        Builder {
+            idle_timeout: Some(Duration::from_secs(90)),
+            max_idle_per_key: usize::MAX,
+            clock: Clock::default(),
+        }
+    }
+
+    /// Sets how long an HTTP/1 connection may sit idle before it is dropped.
+    ///
+    /// Pass `None` to keep idle connections around until they close.
+    ///
+    /// Default is 90 seconds.
+    pub fn idle_timeout(&mut self, timeout: impl Into<Option<Duration>>) -> &mut Builder {
+        self.idle_timeout = timeout.into();
+        self
+    }
+
+    /// Sets the maximum number of idle HTTP/1 connections kept per key.
+    ///
+    /// Connections given back beyond this are dropped.
+    ///
+    /// Default is no limit.
+    pub fn max_idle_per_key(&mut self, max: usize) -> &mut Builder {
+        self.max_idle_per_key = max;
+        self
+    }
+
+    /// Sets the clock checkout times and idle timeouts are measured with.
+    ///
+    /// This should be the same clock as the connections in the pool use, so
+    /// the checkout times line up with the rest of their stats.
+    ///
+    /// Default is the [`SystemClock`](crate::stats::SystemClock).
+    pub fn stats_clock<C>(&mut self, clock: C) -> &mut Builder
+    where
+        C: StatsClock + 'static,
+    {
+        self.clock = Clock::new(clock);
+        self
+    }
+
+    /// Builds a pool with this configuration.
+    pub fn build<K, T>(&self) -> Pool<K, T>
+    where
+        K: Eq + Hash + Clone,
+        T: Poolable,
+    {
+        Pool {
+            inner: Arc::new(Mutex::new(Inner {
+                idle: HashMap::new(),
+                waiters: HashMap::new(),
+                idle_timeout: self.idle_timeout,
+                max_idle_per_key: self.max_idle_per_key,
+                clock: self.clock.clone(),
+            })),
+        }
+    }
+}
+
+impl Default for Builder {
+    fn default() -> Builder {
+        Builder::new()
+    }
+}
+
+// ===== impl Checkout
+
+impl<K, T> Checkout<K, T>
+where
+    K: Eq + Hash + Clone,
+    T: Poolable,
+{
+    /// Finishes this checkout with a new connection.
+    ///
+    /// An HTTP/2 connection is also added to the pool, to be shared with
+    /// other checkouts of the same key.
+    pub fn connected(self, value: T) -> Pooled<K, T> {
+        if let Some(shared) = value.share() {
+            self.pool.put(self.key.clone(), shared);
+        }
+        self.pooled(value, false)
+    }
+
+    fn pooled(&self, value: T, reused: bool) -> Pooled<K, T> {
+        Pooled {
+            value: Some(value),
+            key: self.key.clone(),
+            pool: self.pool.clone(),
+            checkout_start: self.started,
+            reused,
+        }
+    }
+}
+
+impl<K, T> Future for Checkout<K, T>
+where
+    K: Eq + Hash + Clone + Unpin,
+    T: Poolable,
+{
+    type Output = Pooled<K, T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let mut inner = this.pool.lock();
+        if let Some(value) = inner.take(&this.key) {
+            drop(inner);
+            return Poll::Ready(this.pooled(value, true));
+        }
+
+        let waiters = inner.waiters.entry(this.key.clone()).or_default();
+        match waiters.iter_mut().find(|(id, _)| *id == this.id) {
+            Some((_, waker)) => waker.clone_from(cx.waker()),
+            None => waiters.push((this.id, cx.waker().clone())),
+        }
+        Poll::Pending
+    }
+}
+
+impl<K: Eq + Hash + Clone, T: Poolable> Drop for Checkout<K, T> {
+    fn drop(&mut self) {
+        let mut inner = match self.pool.inner.lock() {
+            Ok(inner) => inner,
+            Err(_) => return,
+        };
+        if let Some(waiters) = inner.waiters.get_mut(&self.key) {
+            waiters.retain(|(id, _)| *id != self.id);
+            if waiters.is_empty() {
+                inner.waiters.remove(&self.key);
+            }
+        }
+    }
+}
+
+impl<K: Eq + Hash + Clone, T: Poolable> fmt::Debug for Checkout<K, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Checkout").finish()
+    }
+}
+
+// ===== impl Pooled
+
+impl<K: Eq + Hash + Clone, T: Poolable> Pooled<K, T> {
+    /// Returns whether this connection came out of the pool, rather than
+    /// being passed to [`Checkout::connected`].
+    pub fn is_reused(&self) -> bool {
+        self.reused
+    }
+
+    /// Returns the key this connection is pooled under.
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    fn mark<B>(&self, req: &mut Request<B>) {
+        if req.extensions().get::<CheckoutStart>().is_none() {
+            req.extensions_mut()
+                .insert(CheckoutStart(self.checkout_start));
+        }
+    }
+}
+
+#[cfg(feature = "http1")]
+impl<K, B> Pooled<K, http1::SendRequest<B>>
+where
+    K: Eq + Hash + Clone,
+    B: Body + Send + 'static,
+{
+    /// Sends a `Request` on the pooled connection.
+    ///
+    /// This is [`http1::SendRequest::send_request`], with the checkout time
+    /// counted from the start of the [`Checkout`].
+    pub fn send_request(
+        &mut self,
+        mut req: Request<B>,
+    ) -> impl Future<Output = crate::Result<(HttpConnectionStats, http::Response<IncomingBody>)>>
+    {
+        self.mark(&mut req);
+        DerefMut::deref_mut(self).send_request(req)
+    }
+}
+
+#[cfg(feature = "http2")]
+impl<K, B> Pooled<K, http2::SendRequest<B>>
+where
+    K: Eq + Hash + Clone,
+    B: Body + Send + 'static,
+{
+    /// Sends a `Request` on the pooled connection.
+    ///
+    /// This is [`http2::SendRequest::send_request`], with the checkout time
+    /// counted from the start of the [`Checkout`].
+    pub fn send_request(
+        &mut self,
+        mut req: Request<B>,
+    ) -> impl Future<Output = crate::Result<(HttpConnectionStats, http::Response<IncomingBody>)>>
+    {
+        self.mark(&mut req);
+        DerefMut::deref_mut(self).send_request(req)
+    }
+}
+
+impl<K: Eq + Hash + Clone, T: Poolable> Deref for Pooled<K, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value.as_ref().expect("not dropped")
+    }
+}
+
+impl<K: Eq + Hash + Clone, T: Poolable> DerefMut for Pooled<K, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.value.as_mut().expect("not dropped")
+    }
+}
+
+impl<K: Eq + Hash + Clone, T: Poolable> Drop for Pooled<K, T> {
+    fn drop(&mut self) {
+        let value = match self.value.take() {
+            Some(value) => value,
+            None => return,
+        };
+        // Shared connections stay in the pool the whole time.
+        if value.share().is_none() && value.is_open() {
+            self.pool.put(self.key.clone(), value);
+        }
+    }
+}
+
+impl<K: Eq + Hash + Clone, T: Poolable> fmt::Debug for Pooled<K, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Pooled")
+            .field("reused", &self.reused)
+            .finish()
+    }
+}
@@ -1,9 +1,9 @@
 use std::cell::RefCell;
 use std::fmt::{self, Write};
 use std::str;
+use std::sync::Arc;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-#[cfg(feature = "http2")]
 use http::header::HeaderValue;
 use httpdate::HttpDate;
 
@@ -24,7 +24,6 @@ pub(crate) fn update() {
     })
 }
 
-#[cfg(feature = "http2")]
 pub(crate) fn update_and_header_value() -> HeaderValue {
     CACHED.with(|cache| {
         let mut cache = cache.borrow_mut();
@@ -33,10 +32,67 @@ pub(crate) fn update_and_header_value() -> HeaderValue {
     })
 }
 
+/// Provides the `Date` header of responses.
+///
+/// Install one with `date_provider` on the server connection builders, to
+/// make the header deterministic, such as for tests or record and replay
+/// proxies. It's asked for a value for each response that doesn't already
+/// have a `Date` header, unless the header is turned off with
+/// `auto_date_header(false)`.
+pub trait DateProvider: Send + Sync {
+    /// Returns the `Date` header value for a response being sent now.
+    fn date(&self) -> HeaderValue;
+}
+
+/// The default provider, formatting the system time.
+///
+/// The formatted date is cached, and only redone once a second.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemDate;
+
+impl DateProvider for SystemDate {
+    fn date(&self) -> HeaderValue {
+        update_and_header_value()
+    }
+}
+
+/// Where a connection gets its `Date` header from: the cached system date,
+/// unless a provider was installed.
+#[derive(Clone, Default)]
+pub(crate) struct DateSource(Option<Arc<dyn DateProvider>>);
+
+impl DateSource {
+    pub(crate) fn new<P>(provider: P) -> DateSource
+    where
+        P: DateProvider + 'static,
+    {
+        DateSource(Some(Arc::new(provider)))
+    }
+
+    /// The installed provider's value, if there is one.
+    #[cfg(feature = "http1")]
+    pub(crate) fn custom(&self) -> Option<HeaderValue> {
+        self.0.as_ref().map(|provider| provider.date())
+    }
+
+    #[cfg(feature = "http2")]
+    pub(crate) fn value(&self) -> HeaderValue {
+        match self.0 {
+            Some(ref provider) => provider.date(),
+            None => update_and_header_value(),
+        }
+    }
+}
+
+impl fmt::Debug for DateSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DateSource").finish()
+    }
+}
+
 struct CachedDate {
     bytes: [u8; DATE_VALUE_LENGTH],
     pos: usize,
-    #[cfg(feature = "http2")]
     header_value: HeaderValue,
     next_update: SystemTime,
 }
@@ -48,7 +104,6 @@ impl CachedDate {
         let mut cache = CachedDate {
             bytes: [0; DATE_VALUE_LENGTH],
             pos: 0,
-            #[cfg(feature = "http2")]
             header_value: HeaderValue::from_static(""),
             next_update: SystemTime::now(),
         };
@@ -81,17 +136,9 @@ impl CachedDate {
         self.pos = 0;
         let _ = write!(self, "{}", HttpDate::from(now));
         debug_assert!(self.pos == DATE_VALUE_LENGTH);
-        self.render_http2();
-    }
-
-    #[cfg(feature = "http2")]
-    fn render_http2(&mut self) {
         self.header_value = HeaderValue::from_bytes(self.buffer())
             .expect("Date format should be valid HeaderValue");
     }
-
-    #[cfg(not(feature = "http2"))]
-    fn render_http2(&mut self) {}
 }
 
 impl fmt::Write for CachedDate {
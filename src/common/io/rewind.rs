@@ -4,7 +4,7 @@ use std::{cmp, io};
 
 use bytes::{Buf, Bytes};
 
-use crate::rt::{Read, ReadBufCursor, Write};
+use crate::rt::{Read, ReadBufCursor, Stats, Write};
 
 /// Combine a buffer with an IO, rewinding reads to use the buffer.
 #[derive(Debug)]
@@ -44,6 +44,15 @@ impl<T> Rewind<T> {
     // }
 }
 
+impl<T> Stats for Rewind<T>
+where
+    T: Stats,
+{
+    fn stats(&mut self) -> Option<crate::rt::ConnectionStats> {
+        self.inner.stats()
+    }
+}
+
 impl<T> Read for Rewind<T>
 where
     T: Read + Unpin,
@@ -103,6 +112,20 @@ where
     fn is_write_vectored(&self) -> bool {
         self.inner.is_write_vectored()
     }
+
+    #[cfg(all(feature = "sendfile", unix))]
+    fn is_write_file(&self) -> bool {
+        self.inner.is_write_file()
+    }
+
+    #[cfg(all(feature = "sendfile", unix))]
+    fn poll_write_file(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        file: &crate::body::FileRegion,
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write_file(cx, file)
+    }
 }
 
 #[cfg(all(
@@ -1,20 +1,28 @@
-#[cfg(all(any(feature = "client", feature = "server"), feature = "http1"))]
+#[cfg(all(
+    any(feature = "client", feature = "server"),
+    any(feature = "http1", feature = "http2")
+))]
 pub(crate) mod buf;
 #[cfg(all(feature = "server", any(feature = "http1", feature = "http2")))]
 pub(crate) mod date;
-#[cfg(all(feature = "client", feature = "http2"))]
-pub(crate) mod either;
-#[cfg(any(
-    all(feature = "client", any(feature = "http1", feature = "http2")),
-    all(feature = "server", feature = "http1"),
+#[cfg(all(
+    any(feature = "client", feature = "server"),
+    any(feature = "http1", feature = "http2"),
 ))]
 pub(crate) mod future;
 pub(crate) mod io;
+#[cfg(all(
+    any(feature = "client", feature = "server"),
+    any(feature = "http1", feature = "http2"),
+))]
+// An HTTP/2 server only needs the clock.
+#[cfg_attr(not(any(feature = "client", feature = "http1")), allow(dead_code))]
+pub(crate) mod stats;
 #[cfg(all(any(feature = "client", feature = "server"), feature = "http1"))]
 pub(crate) mod task;
-#[cfg(any(
-    all(feature = "server", feature = "http1"),
-    all(any(feature = "client", feature = "server"), feature = "http2"),
+#[cfg(all(
+    any(feature = "client", feature = "server"),
+    any(feature = "http1", feature = "http2"),
 ))]
 pub(crate) mod time;
 #[cfg(all(any(feature = "client", feature = "server"), feature = "http1"))]
@@ -0,0 +1,199 @@
+use std::fmt;
+#[cfg(feature = "client")]
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+#[cfg(feature = "client")]
+use crate::rt::Stats;
+#[cfg(feature = "client")]
+use crate::stats::ConnectionObserver;
+use crate::stats::StatsClock;
+use crate::RequestStats;
+
+/// A user callback that receives the stats of every completed request.
+pub(crate) struct OnStats<T = RequestStats>(Arc<Mutex<dyn FnMut(T) + Send>>);
+
+impl<T> OnStats<T> {
+    pub(crate) fn new<F>(callback: F) -> OnStats<T>
+    where
+        F: FnMut(T) + Send + 'static,
+    {
+        OnStats(Arc::new(Mutex::new(callback)))
+    }
+
+    pub(crate) fn call(&self, stats: T) {
+        // A callback that panicked once shouldn't stop every later request
+        // on the connection from reporting.
+        let mut callback = match self.0.lock() {
+            Ok(callback) => callback,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        (callback)(stats);
+    }
+}
+
+impl<T> Clone for OnStats<T> {
+    fn clone(&self) -> OnStats<T> {
+        OnStats(self.0.clone())
+    }
+}
+
+impl<T> fmt::Debug for OnStats<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OnStats").finish()
+    }
+}
+
+/// The [`StatsClock`] a connection records stats with.
+///
+/// Without one set, this reads `Instant::now()` directly.
+#[derive(Clone, Default)]
+pub(crate) struct Clock(Option<Arc<dyn StatsClock>>);
+
+impl Clock {
+    pub(crate) fn new<C>(clock: C) -> Clock
+    where
+        C: StatsClock + 'static,
+    {
+        Clock(Some(Arc::new(clock)))
+    }
+
+    pub(crate) fn now(&self) -> Instant {
+        match self.0 {
+            Some(ref clock) => clock.now(),
+            None => Instant::now(),
+        }
+    }
+
+    #[cfg(any(feature = "client", feature = "http2"))]
+    pub(crate) fn elapsed(&self, since: Instant) -> std::time::Duration {
+        self.now().saturating_duration_since(since)
+    }
+}
+
+impl fmt::Debug for Clock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Clock").finish()
+    }
+}
+
+/// A user [`ConnectionObserver`], shared by everything on a connection.
+#[cfg(feature = "client")]
+#[derive(Clone)]
+pub(crate) struct Observer(Arc<dyn ConnectionObserver>);
+
+#[cfg(feature = "client")]
+impl Observer {
+    pub(crate) fn new<O>(observer: O) -> Observer
+    where
+        O: ConnectionObserver + 'static,
+    {
+        Observer(Arc::new(observer))
+    }
+
+    /// Reports the setup steps the transport went through before hyper got
+    /// it.
+    pub(crate) fn connected<T: Stats>(&self, io: &mut T) {
+        let stats = match io.stats() {
+            Some(stats) => stats,
+            None => return,
+        };
+        if let Some(at) = stats.connect_start {
+            self.0.on_connect_start(at);
+        }
+        if let Some(at) = stats.connect_end {
+            self.0.on_connect_done(at);
+        }
+        if let Some(at) = stats.tls_connect_end {
+            self.0.on_tls_done(at);
+        }
+    }
+}
+
+#[cfg(feature = "client")]
+impl std::ops::Deref for Observer {
+    type Target = dyn ConnectionObserver;
+
+    fn deref(&self) -> &Self::Target {
+        &*self.0
+    }
+}
+
+#[cfg(feature = "client")]
+impl fmt::Debug for Observer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Observer").finish()
+    }
+}
+
+/// Calls `OnStats` with a request's stats once its response body has ended.
+///
+/// A body that fails, or is dropped before it ends, reports then instead.
+#[cfg(feature = "client")]
+pub(crate) struct OnBodyEnd {
+    stats: RequestStats,
+    on_stats: OnStats,
+}
+
+#[cfg(feature = "client")]
+impl OnBodyEnd {
+    pub(crate) fn new(stats: RequestStats, on_stats: OnStats) -> OnBodyEnd {
+        OnBodyEnd { stats, on_stats }
+    }
+
+    /// Calls back, after `fill` adds what the body has learned.
+    pub(crate) fn call<F>(mut self, fill: F)
+    where
+        F: FnOnce(&mut crate::HttpConnectionStats),
+    {
+        fill(&mut self.stats.http_stats);
+        if let Some(at) = self.stats.http_stats.last_body_byte_time {
+            self.stats.finish = self.stats.finish.max(at);
+        }
+        self.on_stats.call(self.stats);
+    }
+}
+
+#[cfg(feature = "client")]
+impl fmt::Debug for OnBodyEnd {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OnBodyEnd").finish()
+    }
+}
+
+/// The instant a request started waiting for a connection.
+///
+/// Set as a request extension by the sending side, and picked off by the
+/// connection's dispatcher once it takes the request.
+#[cfg(feature = "client")]
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct CheckoutStart(pub(crate) Instant);
+
+#[cfg(feature = "client")]
+impl CheckoutStart {
+    pub(crate) fn mark<B>(req: &mut http::Request<B>, clock: &Clock) {
+        if req.extensions().get::<CheckoutStart>().is_none() {
+            req.extensions_mut().insert(CheckoutStart(clock.now()));
+        }
+    }
+}
+
+/// The failed attempts at a request that is being sent again.
+///
+/// Set as a request extension by [`RetryStats::record_on`], and picked off
+/// by the connection's dispatcher once it takes the request.
+///
+/// [`RetryStats::record_on`]: crate::RetryStats::record_on
+#[cfg(feature = "client")]
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Retries(pub(crate) Vec<crate::RetryStats>);
+
+/// Hands out a new id for an established connection.
+///
+/// Ids are unique within the process, and increase with every connection.
+#[cfg(feature = "client")]
+pub(crate) fn next_connection_id() -> u64 {
+    static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+    NEXT_ID.fetch_add(1, Ordering::Relaxed)
+}
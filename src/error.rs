@@ -35,6 +35,8 @@ pub struct Error {
 struct ErrorImpl {
     kind: Kind,
     cause: Option<Cause>,
+    #[cfg(all(feature = "client", any(feature = "http1", feature = "http2")))]
+    stats: Option<Box<crate::HttpConnectionStats>>,
 }
 
 #[derive(Debug)]
@@ -52,7 +54,7 @@ pub(super) enum Kind {
     /// Indicates a channel (client or body sender) is closed.
     #[cfg(any(
         all(feature = "http1", any(feature = "client", feature = "server")),
-        all(feature = "http2", feature = "client")
+        all(feature = "http2", any(feature = "client", feature = "server"))
     ))]
     ChannelClosed,
     /// An `io::Error` that occurred while trying to read or write to a network stream.
@@ -64,6 +66,27 @@ pub(super) enum Kind {
     /// User took too long to send headers
     #[cfg(all(feature = "http1", feature = "server"))]
     HeaderTimeout,
+    /// Client sent `Expect: 100-continue` to a server rejecting it
+    #[cfg(all(feature = "http1", feature = "server"))]
+    ExpectationFailed,
+    /// A body was larger than allowed
+    #[cfg(all(
+        any(feature = "client", feature = "server"),
+        any(feature = "http1", feature = "http2")
+    ))]
+    BodyTooLarge,
+    /// Server took too long to send a 100 Continue
+    #[cfg(all(feature = "http1", feature = "client"))]
+    ExpectContinueTimeout,
+    /// Server took too long to send response headers
+    #[cfg(all(feature = "http1", feature = "client"))]
+    ResponseHeaderTimeout,
+    /// A request wasn't done by its deadline
+    #[cfg(all(feature = "client", any(feature = "http1", feature = "http2")))]
+    DeadlineExceeded,
+    /// Peer answered a CONNECT request with a non-2xx status
+    #[cfg(all(feature = "http1", feature = "client"))]
+    ConnectRejected,
     /// Error while reading a body from connection.
     #[cfg(all(
         any(feature = "client", feature = "server"),
@@ -100,6 +123,8 @@ pub(super) enum Parse {
     #[cfg(any(feature = "http1", feature = "http2"))]
     #[cfg_attr(feature = "http2", allow(unused))]
     TooLarge,
+    #[cfg(all(any(feature = "client", feature = "server"), feature = "http1"))]
+    HeaderSizeTooLarge,
     Status,
     #[cfg(all(any(feature = "client", feature = "server"), feature = "http1"))]
     Internal,
@@ -128,12 +153,16 @@ pub(super) enum User {
     /// The user aborted writing of the outgoing body.
     #[cfg(any(
         all(feature = "http1", any(feature = "client", feature = "server")),
+        all(feature = "http2", any(feature = "client", feature = "server")),
         feature = "ffi"
     ))]
     BodyWriteAborted,
     /// User tried to send a connect request with a nonzero body
     #[cfg(all(feature = "client", feature = "http2"))]
     InvalidConnectWithBody,
+    /// User tried to send a connect request without an authority
+    #[cfg(all(feature = "client", feature = "http1"))]
+    InvalidConnectUri,
     /// Error from future of user's Service.
     #[cfg(any(
         all(any(feature = "client", feature = "server"), feature = "http1"),
@@ -186,6 +215,24 @@ impl Error {
         )
     }
 
+    /// Returns true if a body was larger than allowed, such as a request body
+    /// over the server's `max_request_body_size`, or a body collected with
+    /// [`Incoming::collect_up_to`](crate::body::Incoming::collect_up_to).
+    #[cfg(all(
+        any(feature = "client", feature = "server"),
+        any(feature = "http1", feature = "http2")
+    ))]
+    pub fn is_body_too_large(&self) -> bool {
+        matches!(self.inner.kind, Kind::BodyTooLarge)
+    }
+
+    /// Returns true if this was an HTTP parse error caused by a response head
+    /// larger than the client's `max_response_header_size`.
+    #[cfg(all(feature = "http1", feature = "client"))]
+    pub fn is_parse_header_size_too_large(&self) -> bool {
+        matches!(self.inner.kind, Kind::Parse(Parse::HeaderSizeTooLarge))
+    }
+
     /// Returns true if this was an HTTP parse error caused by an invalid response status code or
     /// reason phrase.
     pub fn is_parse_status(&self) -> bool {
@@ -206,13 +253,13 @@ impl Error {
     pub fn is_closed(&self) -> bool {
         #[cfg(not(any(
             all(feature = "http1", any(feature = "client", feature = "server")),
-            all(feature = "http2", feature = "client")
+            all(feature = "http2", any(feature = "client", feature = "server"))
         )))]
         return false;
 
         #[cfg(any(
             all(feature = "http1", any(feature = "client", feature = "server")),
-            all(feature = "http2", feature = "client")
+            all(feature = "http2", any(feature = "client", feature = "server"))
         ))]
         matches!(self.inner.kind, Kind::ChannelClosed)
     }
@@ -226,16 +273,24 @@ impl Error {
         matches!(self.inner.kind, Kind::IncompleteMessage)
     }
 
+    /// Returns true if a CONNECT request was answered with a non-2xx status.
+    #[cfg(all(feature = "http1", feature = "client"))]
+    pub fn is_connect_rejected(&self) -> bool {
+        matches!(self.inner.kind, Kind::ConnectRejected)
+    }
+
     /// Returns true if the body write was aborted.
     pub fn is_body_write_aborted(&self) -> bool {
         #[cfg(not(any(
             all(feature = "http1", any(feature = "client", feature = "server")),
+            all(feature = "http2", any(feature = "client", feature = "server")),
             feature = "ffi"
         )))]
         return false;
 
         #[cfg(any(
             all(feature = "http1", any(feature = "client", feature = "server")),
+            all(feature = "http2", any(feature = "client", feature = "server")),
             feature = "ffi"
         ))]
         matches!(self.inner.kind, Kind::User(User::BodyWriteAborted))
@@ -247,12 +302,58 @@ impl Error {
         if matches!(self.inner.kind, Kind::HeaderTimeout) {
             return true;
         }
+        #[cfg(all(feature = "http1", feature = "client"))]
+        if matches!(
+            self.inner.kind,
+            Kind::ExpectContinueTimeout | Kind::ResponseHeaderTimeout
+        ) {
+            return true;
+        }
+        #[cfg(all(feature = "client", any(feature = "http1", feature = "http2")))]
+        if matches!(self.inner.kind, Kind::DeadlineExceeded) {
+            return true;
+        }
         self.find_source::<TimedOut>().is_some()
     }
 
+    /// Returns the HTTP/2 error code behind this error, if there is one.
+    ///
+    /// This is the code of the `RST_STREAM` that reset the request's
+    /// stream, or of the `GOAWAY` that closed its connection, whichever
+    /// side sent it. The codes are listed in [RFC 9113][codes].
+    ///
+    /// A `REFUSED_STREAM` (`0x7`) from the peer means it didn't process
+    /// the request at all, so it is safe to retry, even if the request isn't
+    /// idempotent.
+    ///
+    /// [codes]: https://www.rfc-editor.org/rfc/rfc9113#section-7
+    #[cfg(feature = "http2")]
+    pub fn h2_reason(&self) -> Option<u32> {
+        self.find_source::<h2::Error>()
+            .and_then(|h2_err| h2_err.reason())
+            .map(u32::from)
+    }
+
+    /// Returns the stats recorded for a request before this error ended it,
+    /// if any.
+    ///
+    /// These are only partial stats. Only an error that stops a request
+    /// partway through, such as a missed deadline or the connection closing
+    /// before the request was answered, carries them. They go with the
+    /// failed attempt in [`RetryStats::from_error`](crate::RetryStats::from_error).
+    #[cfg(all(feature = "client", any(feature = "http1", feature = "http2")))]
+    pub fn stats(&self) -> Option<&crate::HttpConnectionStats> {
+        self.inner.stats.as_deref()
+    }
+
     pub(super) fn new(kind: Kind) -> Error {
         Error {
-            inner: Box::new(ErrorImpl { kind, cause: None }),
+            inner: Box::new(ErrorImpl {
+                kind,
+                cause: None,
+                #[cfg(all(feature = "client", any(feature = "http1", feature = "http2")))]
+                stats: None,
+            }),
         }
     }
 
@@ -261,6 +362,17 @@ impl Error {
         self
     }
 
+    #[cfg(all(feature = "client", any(feature = "http1", feature = "http2")))]
+    pub(super) fn with_stats(mut self, stats: crate::HttpConnectionStats) -> Error {
+        self.inner.stats = Some(Box::new(stats));
+        self
+    }
+
+    #[cfg(all(feature = "client", feature = "http1"))]
+    pub(super) fn stats_mut(&mut self) -> Option<&mut crate::HttpConnectionStats> {
+        self.inner.stats.as_deref_mut()
+    }
+
     #[cfg(any(all(feature = "http1", feature = "server"), feature = "ffi"))]
     pub(super) fn kind(&self) -> &Kind {
         &self.inner.kind
@@ -280,7 +392,7 @@ impl Error {
     }
 
     #[cfg(all(any(feature = "client", feature = "server"), feature = "http2"))]
-    pub(super) fn h2_reason(&self) -> h2::Reason {
+    pub(super) fn h2_reason_or_internal(&self) -> h2::Reason {
         // Find an h2::Reason somewhere in the cause stack, if it exists,
         // otherwise assume an INTERNAL_ERROR.
         self.find_source::<h2::Error>()
@@ -302,6 +414,11 @@ impl Error {
         Error::new(Kind::Parse(Parse::TooLarge))
     }
 
+    #[cfg(all(any(feature = "client", feature = "server"), feature = "http1"))]
+    pub(super) fn new_header_size_too_large() -> Error {
+        Error::new(Kind::Parse(Parse::HeaderSizeTooLarge))
+    }
+
     #[cfg(all(any(feature = "client", feature = "server"), feature = "http1"))]
     pub(super) fn new_version_h2() -> Error {
         Error::new(Kind::Parse(Parse::VersionH2))
@@ -322,7 +439,7 @@ impl Error {
 
     #[cfg(any(
         all(feature = "http1", any(feature = "client", feature = "server")),
-        all(feature = "http2", feature = "client")
+        all(feature = "http2", any(feature = "client", feature = "server"))
     ))]
     pub(super) fn new_closed() -> Error {
         Error::new(Kind::ChannelClosed)
@@ -346,6 +463,7 @@ impl Error {
 
     #[cfg(any(
         all(feature = "http1", any(feature = "client", feature = "server")),
+        all(feature = "http2", any(feature = "client", feature = "server")),
         feature = "ffi"
     ))]
     pub(super) fn new_body_write_aborted() -> Error {
@@ -367,6 +485,51 @@ impl Error {
         Error::new(Kind::HeaderTimeout)
     }
 
+    #[cfg(all(feature = "http1", feature = "server"))]
+    pub(super) fn new_expectation_failed() -> Error {
+        Error::new(Kind::ExpectationFailed)
+    }
+
+    #[cfg(all(
+        any(feature = "client", feature = "server"),
+        any(feature = "http1", feature = "http2")
+    ))]
+    pub(super) fn new_body_too_large() -> Error {
+        Error::new(Kind::BodyTooLarge)
+    }
+
+    /// Wraps an error reading a body, unless it already is one of ours.
+    #[cfg(all(any(feature = "client", feature = "server"), feature = "http1"))]
+    pub(super) fn new_body_read(cause: std::io::Error) -> Error {
+        if !cause.get_ref().map_or(false, |e| e.is::<Error>()) {
+            return Error::new_body(cause);
+        }
+        match cause.into_inner().map(|e| e.downcast::<Error>()) {
+            Some(Ok(err)) => *err,
+            _ => unreachable!("checked above"),
+        }
+    }
+
+    #[cfg(all(feature = "http1", feature = "client"))]
+    pub(super) fn new_expect_continue_timeout() -> Error {
+        Error::new(Kind::ExpectContinueTimeout)
+    }
+
+    #[cfg(all(feature = "http1", feature = "client"))]
+    pub(super) fn new_response_header_timeout() -> Error {
+        Error::new(Kind::ResponseHeaderTimeout)
+    }
+
+    #[cfg(all(feature = "client", any(feature = "http1", feature = "http2")))]
+    pub(super) fn new_deadline_exceeded() -> Error {
+        Error::new(Kind::DeadlineExceeded)
+    }
+
+    #[cfg(all(feature = "http1", feature = "client"))]
+    pub(super) fn new_connect_rejected(status: http::StatusCode) -> Error {
+        Error::new(Kind::ConnectRejected).with(format!("response status: {}", status))
+    }
+
     #[cfg(feature = "http1")]
     #[cfg(feature = "server")]
     pub(super) fn new_user_unsupported_status_code() -> Error {
@@ -403,6 +566,11 @@ impl Error {
         Error::new_user(User::InvalidConnectWithBody)
     }
 
+    #[cfg(all(feature = "client", feature = "http1"))]
+    pub(super) fn new_user_invalid_connect_uri() -> Error {
+        Error::new_user(User::InvalidConnectUri)
+    }
+
     #[cfg(all(any(feature = "client", feature = "server"), feature = "http1"))]
     pub(super) fn new_shutdown(cause: std::io::Error) -> Error {
         Error::new(Kind::Shutdown).with(cause)
@@ -453,6 +621,10 @@ impl Error {
             }
             #[cfg(any(feature = "http1", feature = "http2"))]
             Kind::Parse(Parse::TooLarge) => "message head is too large",
+            #[cfg(all(any(feature = "client", feature = "server"), feature = "http1"))]
+            Kind::Parse(Parse::HeaderSizeTooLarge) => {
+                "message head is larger than the max header size"
+            }
             Kind::Parse(Parse::Status) => "invalid HTTP status-code parsed",
             #[cfg(all(any(feature = "client", feature = "server"), feature = "http1"))]
             Kind::Parse(Parse::Internal) => {
@@ -464,12 +636,27 @@ impl Error {
             Kind::UnexpectedMessage => "received unexpected message from connection",
             #[cfg(any(
                 all(feature = "http1", any(feature = "client", feature = "server")),
-                all(feature = "http2", feature = "client")
+                all(feature = "http2", any(feature = "client", feature = "server"))
             ))]
             Kind::ChannelClosed => "channel closed",
             Kind::Canceled => "operation was canceled",
             #[cfg(all(feature = "http1", feature = "server"))]
             Kind::HeaderTimeout => "read header from client timeout",
+            #[cfg(all(feature = "http1", feature = "server"))]
+            Kind::ExpectationFailed => "rejected request expecting 100-continue",
+            #[cfg(all(
+                any(feature = "client", feature = "server"),
+                any(feature = "http1", feature = "http2")
+            ))]
+            Kind::BodyTooLarge => "body too large",
+            #[cfg(all(feature = "http1", feature = "client"))]
+            Kind::ExpectContinueTimeout => "timed out waiting for 100 Continue",
+            #[cfg(all(feature = "http1", feature = "client"))]
+            Kind::ResponseHeaderTimeout => "timed out waiting for response headers",
+            #[cfg(all(feature = "client", any(feature = "http1", feature = "http2")))]
+            Kind::DeadlineExceeded => "request deadline exceeded",
+            #[cfg(all(feature = "http1", feature = "client"))]
+            Kind::ConnectRejected => "CONNECT request rejected by peer",
             #[cfg(all(
                 any(feature = "client", feature = "server"),
                 any(feature = "http1", feature = "http2")
@@ -497,6 +684,7 @@ impl Error {
             Kind::User(User::Body) => "error from user's Body stream",
             #[cfg(any(
                 all(feature = "http1", any(feature = "client", feature = "server")),
+                all(feature = "http2", any(feature = "client", feature = "server")),
                 feature = "ffi"
             ))]
             Kind::User(User::BodyWriteAborted) => "user body write aborted",
@@ -504,6 +692,8 @@ impl Error {
             Kind::User(User::InvalidConnectWithBody) => {
                 "user sent CONNECT request with non-zero body"
             }
+            #[cfg(all(feature = "client", feature = "http1"))]
+            Kind::User(User::InvalidConnectUri) => "user sent CONNECT request without an authority",
             #[cfg(any(
                 all(any(feature = "client", feature = "server"), feature = "http1"),
                 all(feature = "server", feature = "http2")
@@ -649,14 +839,17 @@ mod tests {
     #[test]
     fn h2_reason_unknown() {
         let closed = Error::new_closed();
-        assert_eq!(closed.h2_reason(), h2::Reason::INTERNAL_ERROR);
+        assert_eq!(closed.h2_reason_or_internal(), h2::Reason::INTERNAL_ERROR);
     }
 
     #[cfg(feature = "http2")]
     #[test]
     fn h2_reason_one_level() {
         let body_err = Error::new_user_body(h2::Error::from(h2::Reason::ENHANCE_YOUR_CALM));
-        assert_eq!(body_err.h2_reason(), h2::Reason::ENHANCE_YOUR_CALM);
+        assert_eq!(
+            body_err.h2_reason_or_internal(),
+            h2::Reason::ENHANCE_YOUR_CALM
+        );
     }
 
     #[cfg(feature = "http2")]
@@ -665,6 +858,17 @@ mod tests {
         let recvd = Error::new_h2(h2::Error::from(h2::Reason::HTTP_1_1_REQUIRED));
         // Suppose a user were proxying the received error
         let svc_err = Error::new_user_service(recvd);
-        assert_eq!(svc_err.h2_reason(), h2::Reason::HTTP_1_1_REQUIRED);
+        assert_eq!(
+            svc_err.h2_reason_or_internal(),
+            h2::Reason::HTTP_1_1_REQUIRED
+        );
+    }
+
+    #[cfg(feature = "http2")]
+    #[test]
+    fn h2_reason_public() {
+        let reset = Error::new_h2(h2::Error::from(h2::Reason::REFUSED_STREAM));
+        assert_eq!(reset.h2_reason(), Some(0x7));
+        assert_eq!(Error::new_closed().h2_reason(), None);
     }
 }
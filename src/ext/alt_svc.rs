@@ -0,0 +1,41 @@
+/// An alternative service for an origin, as advertised in an HTTP/2
+/// [`ALTSVC` frame][rfc].
+///
+/// The value is in the syntax of the `Alt-Svc` header, such as
+/// `h3=":443"; ma=86400`, or `clear` to drop the alternatives advertised
+/// before. It isn't parsed or checked.
+///
+/// ```
+/// use hyper::ext::AltSvc;
+///
+/// let alt_svc = AltSvc::new("https://example.com", "h3=\":443\"; ma=86400");
+/// assert_eq!(alt_svc.origin(), "https://example.com");
+/// ```
+///
+/// [rfc]: https://www.rfc-editor.org/rfc/rfc7838#section-4
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct AltSvc {
+    origin: String,
+    value: String,
+}
+
+impl AltSvc {
+    /// Creates an alternative service from an origin, serialized as a
+    /// scheme, host and optional port, and an `Alt-Svc` field value.
+    pub fn new(origin: impl Into<String>, value: impl Into<String>) -> Self {
+        AltSvc {
+            origin: origin.into(),
+            value: value.into(),
+        }
+    }
+
+    /// Returns the origin the alternative service is for.
+    pub fn origin(&self) -> &str {
+        &self.origin
+    }
+
+    /// Returns the `Alt-Svc` field value.
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+}
@@ -0,0 +1,41 @@
+use http::HeaderValue;
+
+/// The `Content-Length` values of an HTTP/1 request whose framing headers
+/// conflict.
+///
+/// With [`conflicting_framing`][policy] set to
+/// [`Passthrough`][passthrough], an HTTP/1 server serves requests with both
+/// `Transfer-Encoding` and `Content-Length`, or with differing
+/// `Content-Length` values, instead of responding with `400 Bad Request`.
+/// The `Content-Length` headers are removed from the `http::Request` handed
+/// to the service, and their values, in the order received, are in a
+/// `ConflictingContentLength` in its extensions.
+///
+/// Requests whose framing headers don't conflict don't have one.
+///
+/// ```
+/// # #[cfg(all(feature = "server", feature = "http1"))]
+/// # fn inspect(req: &http::Request<hyper::body::Incoming>) {
+/// use hyper::ext::ConflictingContentLength;
+///
+/// if let Some(conflict) = req.extensions().get::<ConflictingContentLength>() {
+///     println!("conflicting content-length: {:?}", conflict.values());
+/// }
+/// # }
+/// ```
+///
+/// [policy]: crate::server::conn::http1::Builder::conflicting_framing
+/// [passthrough]: crate::server::conn::http1::ConflictingFraming::Passthrough
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConflictingContentLength(Vec<HeaderValue>);
+
+impl ConflictingContentLength {
+    pub(crate) fn new(values: Vec<HeaderValue>) -> Self {
+        ConflictingContentLength(values)
+    }
+
+    /// Gets the `Content-Length` values, in the order received.
+    pub fn values(&self) -> &[HeaderValue] {
+        &self.0
+    }
+}
@@ -0,0 +1,104 @@
+use std::ops::Range;
+
+use bytes::Bytes;
+
+/// The header lines of an HTTP/1 response, exactly as they were received.
+///
+/// A `HeaderMap` normalizes what it is given: names are lowercased, values
+/// lose their surrounding whitespace, and repeated headers are grouped. When
+/// debugging a misbehaving peer, those details are often the interesting
+/// part.
+///
+/// A `RawHeaders` will be present in the extensions of the `http::Response`
+/// returned for a request if
+/// [`preserve_raw_headers`](crate::client::conn::http1::Builder::preserve_raw_headers)
+/// was enabled on the connection. It holds the bytes of the whole response
+/// head, along with where each header line sits within them.
+///
+/// ```no_run
+/// # #[cfg(all(feature = "client", feature = "http1"))]
+/// # fn print_raw(res: &http::Response<()>) {
+/// use hyper::ext::RawHeaders;
+///
+/// if let Some(raw) = res.extensions().get::<RawHeaders>() {
+///     for line in raw.iter() {
+///         println!("{:?}: {:?}", line.name(), line.value());
+///     }
+/// }
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct RawHeaders {
+    head: Bytes,
+    lines: Vec<RawHeaderIndices>,
+}
+
+/// A single header line in [`RawHeaders`].
+#[derive(Clone, Copy, Debug)]
+pub struct RawHeaderLine<'a> {
+    head: &'a [u8],
+    indices: &'a RawHeaderIndices,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct RawHeaderIndices {
+    pub(crate) name: Range<usize>,
+    pub(crate) value: Range<usize>,
+    pub(crate) line: Range<usize>,
+}
+
+impl RawHeaders {
+    pub(crate) fn new(head: Bytes, lines: Vec<RawHeaderIndices>) -> Self {
+        Self { head, lines }
+    }
+
+    /// Gets the whole response head, from the status line up to and
+    /// including the empty line that ends it.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.head
+    }
+
+    /// Returns the number of header lines.
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    /// Returns true if the response had no header lines.
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    /// Iterates over the header lines, in the order they were received.
+    pub fn iter(&self) -> impl Iterator<Item = RawHeaderLine<'_>> + '_ {
+        self.lines.iter().map(move |indices| RawHeaderLine {
+            head: &self.head,
+            indices,
+        })
+    }
+}
+
+impl<'a> RawHeaderLine<'a> {
+    /// Gets the header name, in its original case.
+    pub fn name(&self) -> &'a [u8] {
+        &self.head[self.indices.name.clone()]
+    }
+
+    /// Gets the header value.
+    ///
+    /// This has no surrounding whitespace, but is otherwise untouched. It is
+    /// not checked for valid UTF-8, and keeps any obsolete line folding.
+    pub fn value(&self) -> &'a [u8] {
+        &self.head[self.indices.value.clone()]
+    }
+
+    /// Gets the whole line, including the whitespace around the colon and
+    /// the line ending.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        &self.head[self.range()]
+    }
+
+    /// Returns where the whole line sits in [`RawHeaders::as_bytes`].
+    pub fn range(&self) -> Range<usize> {
+        self.indices.line.clone()
+    }
+}
@@ -0,0 +1,39 @@
+use bytes::Bytes;
+
+/// The request target of an HTTP/1 request, as received, when it isn't a
+/// valid URI.
+///
+/// With [`allow_invalid_request_target`][allow] enabled, an HTTP/1 server
+/// serves requests whose target can't be parsed as an `http::Uri`, such as
+/// one with unbalanced brackets or userinfo, instead of responding with `400
+/// Bad Request` and closing the connection. The `http::Request` handed to
+/// the service has a URI of `/`, and a `RawRequestTarget` in its extensions
+/// with the target's bytes.
+///
+/// Requests with a valid target don't have one.
+///
+/// ```
+/// # #[cfg(all(feature = "server", feature = "http1"))]
+/// # fn inspect(req: &http::Request<hyper::body::Incoming>) {
+/// use hyper::ext::RawRequestTarget;
+///
+/// if let Some(target) = req.extensions().get::<RawRequestTarget>() {
+///     println!("invalid request target: {:?}", target.as_bytes());
+/// }
+/// # }
+/// ```
+///
+/// [allow]: crate::server::conn::http1::Builder::allow_invalid_request_target
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RawRequestTarget(Bytes);
+
+impl RawRequestTarget {
+    pub(crate) fn new(target: Bytes) -> Self {
+        RawRequestTarget(target)
+    }
+
+    /// Gets the request target as bytes.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
@@ -0,0 +1,122 @@
+use std::fmt;
+use std::sync::{Arc, Mutex, Weak};
+
+/// A handle to the receive window of a single HTTP/2 stream.
+///
+/// One is present in the extensions of every `http::Request` an HTTP/2
+/// server hands to its service, and of every `http::Response` an HTTP/2
+/// client receives. It applies to that message's body.
+///
+/// Normally, a stream's window is the connection's initial stream window
+/// size, and capacity is handed back to the peer as soon as body data is
+/// read. With a target window, capacity is held back while the window is at
+/// or above the target, so the peer can't send more than the target ahead
+/// of the reader.
+///
+/// HTTP/2 lets a receiver only give back what it has been sent, so a target
+/// can't raise a stream's window past the initial stream window size. To give
+/// some streams larger windows than others on one connection, such as large
+/// uploads next to small API calls, configure the initial stream window size
+/// for the largest, and set smaller targets on the rest.
+///
+/// The handle doesn't keep the stream alive: once the body is dropped,
+/// setting a target does nothing.
+///
+/// ```
+/// # #[cfg(all(feature = "server", feature = "http2"))]
+/// # fn limit(req: &http::Request<hyper::body::Incoming>) {
+/// use hyper::ext::FlowControl;
+///
+/// if req.uri().path() != "/upload" {
+///     if let Some(flow) = req.extensions().get::<FlowControl>() {
+///         flow.set_target_window_size(64 * 1024);
+///     }
+/// }
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct FlowControl {
+    shared: Weak<Mutex<Window>>,
+}
+
+/// The body's side of a [`FlowControl`].
+pub(crate) struct RecvWindow {
+    shared: Arc<Mutex<Window>>,
+}
+
+struct Window {
+    flow: h2::FlowControl,
+    target: Option<u32>,
+    /// Capacity read but not yet released to the peer.
+    held: usize,
+}
+
+impl FlowControl {
+    /// Sets the size this stream's receive window is kept at.
+    ///
+    /// Lowering the target takes effect as body data arrives. Raising it
+    /// releases any capacity held back right away.
+    pub fn set_target_window_size(&self, size: u32) {
+        if let Some(shared) = self.shared.upgrade() {
+            let mut window = shared.lock().unwrap();
+            window.target = Some(size);
+            window.release(0);
+        }
+    }
+
+    /// Returns the target window size, if one was set.
+    pub fn target_window_size(&self) -> Option<u32> {
+        self.shared.upgrade()?.lock().unwrap().target
+    }
+}
+
+impl RecvWindow {
+    pub(crate) fn new(flow: h2::FlowControl) -> Self {
+        RecvWindow {
+            shared: Arc::new(Mutex::new(Window {
+                flow,
+                target: None,
+                held: 0,
+            })),
+        }
+    }
+
+    pub(crate) fn handle(&self) -> FlowControl {
+        FlowControl {
+            shared: Arc::downgrade(&self.shared),
+        }
+    }
+
+    /// Gives back the capacity of body data that has been read, as much as
+    /// the target allows.
+    pub(crate) fn release(&self, len: usize) {
+        self.shared.lock().unwrap().release(len);
+    }
+}
+
+impl Window {
+    fn release(&mut self, len: usize) {
+        self.held += len;
+        let release = match self.target {
+            Some(target) => {
+                let available = self.flow.available_capacity().max(0) as usize;
+                self.held.min((target as usize).saturating_sub(available))
+            }
+            None => self.held,
+        };
+        if release == 0 {
+            return;
+        }
+        if self.flow.release_capacity(release).is_ok() {
+            self.held -= release;
+        }
+    }
+}
+
+impl fmt::Debug for FlowControl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FlowControl")
+            .field("target_window_size", &self.target_window_size())
+            .finish()
+    }
+}
@@ -0,0 +1,59 @@
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use futures_channel::oneshot;
+
+/// A handle to reset the HTTP/2 stream of a request.
+///
+/// One is present in the extensions of every `http::Request` an HTTP/2
+/// server hands to its service. Resetting sends the client a `RST_STREAM`
+/// with the chosen error code, such as `CANCEL` (`0x8`), and stops the
+/// stream, whether the service is still working on the response or its body
+/// is being sent. Other streams on the connection are not affected.
+///
+/// Without it, a service that gives up on a request can only return an
+/// error, or have its response future dropped, both of which reset the
+/// stream with `INTERNAL_ERROR`.
+///
+/// Only the first reset counts. Once the stream is done, or has been handed
+/// off as a `CONNECT` tunnel, resetting does nothing.
+///
+/// ```
+/// # #[cfg(all(feature = "server", feature = "http2"))]
+/// # fn cancel(req: &http::Request<hyper::body::Incoming>) {
+/// use hyper::ext::ResetStream;
+///
+/// if let Some(reset) = req.extensions().get::<ResetStream>() {
+///     // CANCEL
+///     reset.reset(0x8);
+/// }
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct ResetStream {
+    tx: Arc<Mutex<Option<oneshot::Sender<u32>>>>,
+}
+
+impl ResetStream {
+    /// Creates a handle, and the receiver the stream waits on for a reset.
+    pub(crate) fn new() -> (Self, oneshot::Receiver<u32>) {
+        let (tx, rx) = oneshot::channel();
+        let handle = ResetStream {
+            tx: Arc::new(Mutex::new(Some(tx))),
+        };
+        (handle, rx)
+    }
+
+    /// Resets the stream with the given HTTP/2 error code.
+    pub fn reset(&self, error_code: u32) {
+        if let Some(tx) = self.tx.lock().unwrap().take() {
+            let _ = tx.send(error_code);
+        }
+    }
+}
+
+impl fmt::Debug for ResetStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResetStream").finish()
+    }
+}
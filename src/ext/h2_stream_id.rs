@@ -0,0 +1,42 @@
+use std::fmt;
+
+/// The identifier of the HTTP/2 stream a message was exchanged on.
+///
+/// One is present in the extensions of every `http::Request` an HTTP/2
+/// server hands to its service, and of every `http::Response` an HTTP/2
+/// client receives. It is the same number that appears in frame logs and
+/// packet captures of the connection, so it ties a request seen by hyper to
+/// the frames that carried it.
+///
+/// Stream IDs are only unique within a connection.
+///
+/// ```
+/// # #[cfg(all(feature = "server", feature = "http2"))]
+/// # fn log(req: &http::Request<hyper::body::Incoming>) {
+/// use hyper::ext::StreamId;
+///
+/// if let Some(id) = req.extensions().get::<StreamId>() {
+///     println!("{} {} on stream {}", req.method(), req.uri(), id);
+/// }
+/// # }
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StreamId(u32);
+
+impl StreamId {
+    #[cfg(any(feature = "client", feature = "server"))]
+    pub(crate) fn from_h2(id: h2::StreamId) -> Self {
+        StreamId(id.as_u32())
+    }
+
+    /// Returns the stream ID as a number.
+    pub fn as_u32(&self) -> u32 {
+        self.0
+    }
+}
+
+impl fmt::Display for StreamId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
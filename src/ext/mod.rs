@@ -8,17 +8,36 @@ use bytes::Bytes;
 ))]
 use http::header::HeaderName;
 #[cfg(all(any(feature = "client", feature = "server"), feature = "http1"))]
-use http::header::{HeaderMap, IntoHeaderName, ValueIter};
-#[cfg(feature = "ffi")]
+use http::header::{HeaderMap, IntoHeaderName, InvalidHeaderName, ValueIter};
+#[cfg(any(feature = "ffi", all(feature = "http1", feature = "client")))]
 use std::collections::HashMap;
 #[cfg(feature = "http2")]
 use std::fmt;
+#[cfg(all(feature = "http1", feature = "client"))]
+use std::time::Duration;
 
 #[cfg(any(feature = "http1", feature = "ffi"))]
 mod h1_reason_phrase;
 #[cfg(any(feature = "http1", feature = "ffi"))]
 pub use h1_reason_phrase::ReasonPhrase;
 
+#[cfg(all(feature = "http1", feature = "client"))]
+mod h1_raw_headers;
+#[cfg(all(feature = "http1", feature = "client"))]
+pub(crate) use h1_raw_headers::RawHeaderIndices;
+#[cfg(all(feature = "http1", feature = "client"))]
+pub use h1_raw_headers::{RawHeaderLine, RawHeaders};
+
+#[cfg(all(feature = "http1", feature = "server"))]
+mod h1_conflicting_content_length;
+#[cfg(all(feature = "http1", feature = "server"))]
+pub use h1_conflicting_content_length::ConflictingContentLength;
+
+#[cfg(all(feature = "http1", feature = "server"))]
+mod h1_raw_request_target;
+#[cfg(all(feature = "http1", feature = "server"))]
+pub use h1_raw_request_target::RawRequestTarget;
+
 #[cfg(all(feature = "http1", feature = "client"))]
 mod informational;
 #[cfg(all(feature = "http1", feature = "client"))]
@@ -28,6 +47,38 @@ pub(crate) use informational::OnInformational;
 #[cfg(all(feature = "http1", feature = "client", feature = "ffi"))]
 pub(crate) use informational::{on_informational_raw, OnInformationalCallback};
 
+#[cfg(all(feature = "server", any(feature = "http1", feature = "http2")))]
+mod send_informational;
+#[cfg(all(feature = "server", any(feature = "http1", feature = "http2")))]
+pub use send_informational::SendInformational;
+
+#[cfg(all(feature = "http2", any(feature = "client", feature = "server")))]
+mod h2_flow_control;
+#[cfg(all(feature = "http2", any(feature = "client", feature = "server")))]
+pub use h2_flow_control::FlowControl;
+#[cfg(all(feature = "http2", any(feature = "client", feature = "server")))]
+pub(crate) use h2_flow_control::RecvWindow;
+
+#[cfg(all(feature = "http2", feature = "server"))]
+mod h2_reset_stream;
+#[cfg(all(feature = "http2", feature = "server"))]
+pub use h2_reset_stream::ResetStream;
+
+#[cfg(feature = "http2")]
+mod h2_stream_id;
+#[cfg(feature = "http2")]
+pub use h2_stream_id::StreamId;
+
+#[cfg(feature = "http2")]
+mod alt_svc;
+#[cfg(feature = "http2")]
+pub use alt_svc::AltSvc;
+
+#[cfg(feature = "http2")]
+mod priority;
+#[cfg(feature = "http2")]
+pub use priority::Priority;
+
 #[cfg(feature = "http2")]
 /// Represents the `:protocol` pseudo-header used by
 /// the [Extended CONNECT Protocol].
@@ -86,61 +137,86 @@ impl fmt::Debug for Protocol {
     }
 }
 
-/// A map from header names to their original casing as received in an HTTP message.
+/// A map from header names to their original casing in an HTTP/1 message.
 ///
-/// If an HTTP/1 response `res` is parsed on a connection whose option
-/// [`preserve_header_case`] was set to true and the response included
-/// the following headers:
+/// If an HTTP/1 message is parsed on a connection whose option
+/// `preserve_header_case` was set to true, and it included the following
+/// headers:
 ///
-/// ```ignore
+/// ```text
 /// x-Bread: Baguette
 /// X-BREAD: Pain
 /// x-bread: Ficelle
 /// ```
 ///
-/// Then `res.extensions().get::<HeaderCaseMap>()` will return a map with:
+/// Then `extensions().get::<HeaderCaseMap>()` will return a map with:
 ///
-/// ```ignore
+/// ```text
 /// HeaderCaseMap({
 ///     "x-bread": ["x-Bread", "X-BREAD", "x-bread"],
 /// })
 /// ```
 ///
-/// [`preserve_header_case`]: /client/struct.Client.html#method.preserve_header_case
+/// A map in the extensions of a message being sent is used to write its
+/// header names, whether or not `preserve_header_case` is set. The Nth
+/// value of a header is written with the Nth spelling of its name; values
+/// without one fall back to the connection's `title_case_headers` option,
+/// or lowercase. This lets a server answer a client that expects particular
+/// casing:
+///
+/// ```
+/// use hyper::ext::HeaderCaseMap;
+///
+/// let mut case = HeaderCaseMap::new();
+/// case.append_spelling("X-Request-ID").unwrap();
+///
+/// let res = http::Response::builder()
+///     .header("x-request-id", "42")
+///     .extension(case)
+///     .body(())
+///     .unwrap();
+/// # drop(res);
+/// ```
 #[cfg(all(any(feature = "client", feature = "server"), feature = "http1"))]
-#[derive(Clone, Debug)]
-pub(crate) struct HeaderCaseMap(HeaderMap<Bytes>);
+#[derive(Clone, Debug, Default)]
+pub struct HeaderCaseMap(HeaderMap<Bytes>);
 
 #[cfg(all(any(feature = "client", feature = "server"), feature = "http1"))]
 impl HeaderCaseMap {
-    /// Returns a view of all spellings associated with that header name,
-    /// in the order they were found.
-    #[cfg(feature = "client")]
-    pub(crate) fn get_all<'a>(
-        &'a self,
-        name: &HeaderName,
-    ) -> impl Iterator<Item = impl AsRef<[u8]> + 'a> + 'a {
-        self.get_all_internal(name)
+    /// Creates an empty map.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns all spellings associated with that header name, in the order
+    /// they were found or appended.
+    pub fn get_all<'a>(&'a self, name: &HeaderName) -> impl Iterator<Item = &'a [u8]> + 'a {
+        self.get_all_internal(name).map(|orig| &orig[..])
+    }
+
+    /// Appends a spelling of a header name, such as `Content-Type`.
+    ///
+    /// The spelling is stored for the header name it spells, and used for
+    /// the next value of that header that doesn't have one yet. It fails if
+    /// the spelling isn't a valid header name.
+    pub fn append_spelling(&mut self, spelling: &str) -> Result<(), InvalidHeaderName> {
+        let name = HeaderName::from_bytes(spelling.as_bytes())?;
+        self.0
+            .append(name, Bytes::copy_from_slice(spelling.as_bytes()));
+        Ok(())
     }
 
     /// Returns a view of all spellings associated with that header name,
     /// in the order they were found.
-    #[cfg(any(feature = "client", feature = "server"))]
     pub(crate) fn get_all_internal(&self, name: &HeaderName) -> ValueIter<'_, Bytes> {
         self.0.get_all(name).into_iter()
     }
 
-    #[cfg(any(feature = "client", feature = "server"))]
-    pub(crate) fn default() -> Self {
-        Self(Default::default())
-    }
-
     #[cfg(any(test, feature = "ffi"))]
     pub(crate) fn insert(&mut self, name: HeaderName, orig: Bytes) {
         self.0.insert(name, orig);
     }
 
-    #[cfg(any(feature = "client", feature = "server"))]
     pub(crate) fn append<N>(&mut self, name: N, orig: Bytes)
     where
         N: IntoHeaderName,
@@ -149,7 +225,7 @@ impl HeaderCaseMap {
     }
 }
 
-#[cfg(feature = "ffi")]
+#[cfg(any(feature = "ffi", all(feature = "http1", feature = "client")))]
 #[derive(Clone, Debug)]
 /// Hashmap<Headername, numheaders with that name>
 pub(crate) struct OriginalHeaderOrder {
@@ -164,7 +240,7 @@ pub(crate) struct OriginalHeaderOrder {
     entry_order: Vec<(HeaderName, usize)>,
 }
 
-#[cfg(all(feature = "http1", feature = "ffi"))]
+#[cfg(all(feature = "http1", any(feature = "ffi", feature = "client")))]
 impl OriginalHeaderOrder {
     pub(crate) fn default() -> Self {
         OriginalHeaderOrder {
@@ -173,6 +249,7 @@ impl OriginalHeaderOrder {
         }
     }
 
+    #[cfg(feature = "ffi")]
     pub(crate) fn insert(&mut self, name: HeaderName) {
         if !self.num_entries.contains_key(&name) {
             let idx = 0;
@@ -200,14 +277,12 @@ impl OriginalHeaderOrder {
         self.entry_order.push((name, idx));
     }
 
-    // No doc test is run here because `RUSTFLAGS='--cfg hyper_unstable_ffi'`
-    // is needed to compile. Once ffi is stabilized `no_run` should be removed
-    // here.
+    // No doc test is run here because the type isn't public.
     /// This returns an iterator that provides header names and indexes
     /// in the original order received.
     ///
     /// # Examples
-    /// ```no_run
+    /// ```ignore
     /// use hyper::ext::OriginalHeaderOrder;
     /// use hyper::header::{HeaderName, HeaderValue, HeaderMap};
     ///
@@ -244,3 +319,118 @@ impl OriginalHeaderOrder {
         self.entry_order.iter()
     }
 }
+
+/// How long a request waits for response headers, overriding the
+/// connection's `response_header_timeout`.
+#[cfg(all(feature = "http1", feature = "client"))]
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ResponseHeaderTimeout(pub(crate) Option<Duration>);
+
+/// Sets how long this request waits for response headers once it has been
+/// fully written, overriding the connection's
+/// [`response_header_timeout`](crate::client::conn::http1::Builder::response_header_timeout).
+///
+/// Pass `None` to wait without a limit for this request only.
+///
+/// This only has an effect on HTTP/1 client connections. The connection
+/// panics if a timeout is set but a [`Timer`](crate::rt::Timer) was not given
+/// to its `Builder`.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+///
+/// # let some_body = ();
+/// let mut req = hyper::Request::new(some_body);
+/// hyper::ext::set_response_header_timeout(&mut req, Duration::from_secs(5));
+///
+/// // send request on a client connection...
+/// ```
+#[cfg(all(feature = "http1", feature = "client"))]
+pub fn set_response_header_timeout<B>(
+    req: &mut http::Request<B>,
+    timeout: impl Into<Option<Duration>>,
+) {
+    req.extensions_mut()
+        .insert(ResponseHeaderTimeout(timeout.into()));
+}
+
+/// Marks a request whose framing headers are sent exactly as set.
+#[cfg(all(feature = "http1", feature = "client"))]
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct VerbatimFraming;
+
+/// Stops an HTTP/1 client from adding, removing or fixing up the request's
+/// `Content-Length` and `Transfer-Encoding` headers.
+///
+/// Normally the client fills these in from the body's size hint, and
+/// repairs ones that don't make sense. With this set, the headers are
+/// written as they are, and the body is framed by what they say: chunked
+/// if the last `Transfer-Encoding` is `chunked`, otherwise by a valid
+/// `Content-Length`. When they don't say where the body ends, it is written
+/// as it is, and the connection isn't used for another request.
+///
+/// This is for proxies and test tools that need to reproduce framing
+/// exactly, odd or not. It's easy to send a request the server reads
+/// differently than intended with it.
+///
+/// HTTP/2 connections ignore this.
+///
+/// # Example
+///
+/// ```
+/// use hyper::header::{HeaderValue, CONTENT_LENGTH, TRANSFER_ENCODING};
+///
+/// # let some_body = ();
+/// let mut req = hyper::Request::new(some_body);
+/// req.headers_mut().insert(CONTENT_LENGTH, HeaderValue::from_static("5"));
+/// req.headers_mut().insert(TRANSFER_ENCODING, HeaderValue::from_static("chunked"));
+///
+/// // both headers are sent, and the body is chunked
+/// hyper::ext::set_verbatim_framing(&mut req);
+///
+/// // send request on a client connection...
+/// ```
+#[cfg(all(feature = "http1", feature = "client"))]
+pub fn set_verbatim_framing<B>(req: &mut http::Request<B>) {
+    req.extensions_mut().insert(VerbatimFraming);
+}
+
+/// Sets the order an HTTP/1 client writes a request's headers in.
+///
+/// Each name in `order` stands for the next value of that header in the
+/// request's `HeaderMap`, so a name can be repeated to interleave its values
+/// with other headers. Headers left out of `order` are written after the
+/// ordered ones, in the `HeaderMap`'s order.
+///
+/// HTTP/2 connections ignore this.
+///
+/// # Example
+///
+/// ```
+/// use hyper::header::{HeaderValue, ACCEPT, COOKIE, USER_AGENT};
+///
+/// # let some_body = ();
+/// let mut req = hyper::Request::new(some_body);
+/// req.headers_mut().insert(USER_AGENT, HeaderValue::from_static("demo"));
+/// req.headers_mut().append(COOKIE, HeaderValue::from_static("a=1"));
+/// req.headers_mut().insert(ACCEPT, HeaderValue::from_static("*/*"));
+/// req.headers_mut().append(COOKIE, HeaderValue::from_static("b=2"));
+///
+/// // cookie: a=1, accept, cookie: b=2, user-agent
+/// hyper::ext::set_header_order(&mut req, [COOKIE, ACCEPT, COOKIE, USER_AGENT]);
+///
+/// // send request on a client connection...
+/// ```
+#[cfg(all(feature = "http1", feature = "client"))]
+pub fn set_header_order<B, I>(req: &mut http::Request<B>, order: I)
+where
+    I: IntoIterator<Item = HeaderName>,
+{
+    let mut header_order = OriginalHeaderOrder::default();
+    for name in order {
+        header_order.append(name);
+    }
+    req.extensions_mut().insert(header_order);
+}
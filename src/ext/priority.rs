@@ -0,0 +1,139 @@
+#[cfg(any(feature = "client", feature = "server"))]
+use http::header::HeaderValue;
+
+/// The priority of a request, as signaled with the [`priority` header][rfc].
+///
+/// # Clients
+///
+/// When a `Priority` is present in the extensions of an `http::Request` sent
+/// over HTTP/2, it is written as the request's `priority` header, unless the
+/// request already has one. This lets a client ask the server to serve
+/// interactive requests ahead of prefetches on the same connection.
+///
+/// `PRIORITY_UPDATE` frames, which change the priority of a request already
+/// in flight, are not supported.
+///
+/// # Servers
+///
+/// When an HTTP/2 request carries a `priority` header, a `Priority` with the
+/// values it signals is added to the extensions of the `http::Request` handed
+/// to the service. Parameters that are missing or invalid keep their
+/// defaults. If the header is absent, so is the extension.
+///
+/// ```
+/// use hyper::ext::Priority;
+///
+/// let mut req = http::Request::new(());
+/// req.extensions_mut().insert(Priority::new(6, true));
+/// ```
+///
+/// [rfc]: https://www.rfc-editor.org/rfc/rfc9218#section-5
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Priority {
+    urgency: u8,
+    incremental: bool,
+}
+
+const DEFAULT_URGENCY: u8 = 3;
+const MAX_URGENCY: u8 = 7;
+
+impl Priority {
+    /// Creates a `Priority` from an urgency and incremental flag.
+    ///
+    /// The urgency goes from 0, the most urgent, to 7.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `urgency` is greater than 7.
+    pub fn new(urgency: u8, incremental: bool) -> Self {
+        assert!(urgency <= MAX_URGENCY, "priority urgency must be 0 to 7");
+        Self {
+            urgency,
+            incremental,
+        }
+    }
+
+    /// Returns the urgency, from 0, the most urgent, to 7.
+    pub fn urgency(&self) -> u8 {
+        self.urgency
+    }
+
+    /// Returns whether the response can be processed incrementally.
+    pub fn is_incremental(&self) -> bool {
+        self.incremental
+    }
+
+    #[cfg(feature = "server")]
+    pub(crate) fn from_header(value: &HeaderValue) -> Self {
+        let mut priority = Self::default();
+        let value = match value.to_str() {
+            Ok(value) => value,
+            Err(_) => return priority,
+        };
+        // the header is a structured field dictionary; only its `u` and `i`
+        // members are meaningful, and anything unknown is ignored
+        for member in value.split(',') {
+            let mut parts = member.trim().splitn(2, '=');
+            let key = parts.next().unwrap_or("");
+            let val = parts.next();
+            match (key, val) {
+                ("u", Some(val)) => {
+                    if let Ok(urgency) = val.parse::<u8>() {
+                        if urgency <= MAX_URGENCY {
+                            priority.urgency = urgency;
+                        }
+                    }
+                }
+                ("i", None) | ("i", Some("?1")) => priority.incremental = true,
+                ("i", Some("?0")) => priority.incremental = false,
+                _ => (),
+            }
+        }
+        priority
+    }
+
+    #[cfg(feature = "client")]
+    pub(crate) fn to_header(self) -> HeaderValue {
+        let value = if self.incremental {
+            format!("u={}, i", self.urgency)
+        } else {
+            format!("u={}", self.urgency)
+        };
+        HeaderValue::from_str(&value).expect("priority is a valid header value")
+    }
+}
+
+impl Default for Priority {
+    /// The priority of a request that doesn't signal one: an urgency of 3,
+    /// not incremental.
+    fn default() -> Self {
+        Self {
+            urgency: DEFAULT_URGENCY,
+            incremental: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "server")]
+    #[test]
+    fn from_header() {
+        let parse = |s| Priority::from_header(&HeaderValue::from_static(s));
+        assert_eq!(parse("u=5, i"), Priority::new(5, true));
+        assert_eq!(parse("i=?1,u=0"), Priority::new(0, true));
+        assert_eq!(parse("u=1, i=?0"), Priority::new(1, false));
+        assert_eq!(parse("u=9, x=1"), Priority::default());
+        assert_eq!(parse("u=abc"), Priority::default());
+    }
+
+    #[cfg(feature = "client")]
+    #[test]
+    fn to_header() {
+        assert_eq!(Priority::default().to_header(), "u=3");
+        assert_eq!(Priority::new(6, true).to_header(), "u=6, i");
+        assert_eq!(Priority::new(0, false).to_header(), "u=0");
+    }
+}
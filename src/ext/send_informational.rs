@@ -0,0 +1,71 @@
+use std::fmt;
+
+use futures_channel::mpsc;
+use http::{Response, StatusCode};
+
+/// A handle to send 1xx informational responses ahead of the final response
+/// to a request.
+///
+/// One is present in the extensions of every `http::Request` a server hands
+/// to its service, except HTTP/1.0 requests, since HTTP/1.0 clients don't
+/// expect informational responses. Each one sent is written before the final
+/// response, in order, such as a `103 Early Hints` with `Link` headers so the
+/// client can start loading resources while the response is being made.
+///
+/// Once the service's response future resolves, sending does nothing but
+/// give the response back.
+///
+/// ```
+/// # #[cfg(feature = "server")]
+/// # fn hint(req: &http::Request<hyper::body::Incoming>) {
+/// use hyper::ext::SendInformational;
+///
+/// if let Some(informational) = req.extensions().get::<SendInformational>() {
+///     let hints = http::Response::builder()
+///         .status(http::StatusCode::EARLY_HINTS)
+///         .header("link", "</style.css>; rel=preload; as=style")
+///         .body(())
+///         .unwrap();
+///     let _ = informational.send(hints);
+/// }
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct SendInformational {
+    tx: mpsc::UnboundedSender<Response<()>>,
+}
+
+impl SendInformational {
+    /// Creates a handle, and the receiver the connection takes responses
+    /// from.
+    pub(crate) fn new() -> (Self, mpsc::UnboundedReceiver<Response<()>>) {
+        let (tx, rx) = mpsc::unbounded();
+        (SendInformational { tx }, rx)
+    }
+
+    /// Sends an informational response.
+    ///
+    /// The response is given back if its status isn't 1xx, if it's `101
+    /// Switching Protocols`, which can only be a final response, or if the
+    /// final response has already been returned. Only the status and headers
+    /// are sent.
+    pub fn send(&self, res: Response<()>) -> Result<(), Response<()>> {
+        let status = res.status();
+        if !status.is_informational() || status == StatusCode::SWITCHING_PROTOCOLS {
+            return Err(res);
+        }
+        self.tx.unbounded_send(res).map_err(|e| e.into_inner())
+    }
+
+    /// Returns whether the final response has been returned, so sending
+    /// would do nothing.
+    pub fn is_closed(&self) -> bool {
+        self.tx.is_closed()
+    }
+}
+
+impl fmt::Debug for SendInformational {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SendInformational").finish()
+    }
+}
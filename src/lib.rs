@@ -49,6 +49,8 @@
 //! - `http2`: Enables HTTP/2 support.
 //! - `client`: Enables the HTTP `client`.
 //! - `server`: Enables the HTTP `server`.
+//! - `sendfile`: Enables file bodies that can be written without copying
+//!   them through userspace, on unix.
 //!
 //! [feature flags]: https://doc.rust-lang.org/cargo/reference/manifest.html#the-features-section
 //!
@@ -104,7 +106,7 @@ pub use http::{header, HeaderMap, Method, Request, Response, StatusCode, Uri, Ve
 pub use crate::error::{Error, Result};
 use crate::rt::ConnectionStats;
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Default)]
 /// Http-related request stats (including connection stats)
 pub struct HttpConnectionStats {
     /// The approximate instant the first body byte was received.
@@ -113,9 +115,87 @@ pub struct HttpConnectionStats {
     /// The approximate instant the first header byte was received.
     pub first_header_byte_time: Option<std::time::Instant>,
 
-    /// The connection stats for this http request (if the connection was
-    /// not pooled.)
+    /// The approximate instant the final body frame (or trailers) was
+    /// received.
+    ///
+    /// The response is handed back before its body is read, so this starts
+    /// out empty; fill it in from [`body::Incoming::last_body_byte_time`]
+    /// once the body has been consumed, or read the body through a
+    /// [`body::Instrumented`] that fills it in.
+    pub last_body_byte_time: Option<std::time::Instant>,
+
+    /// The connection stats for this http request, as the transport
+    /// reported them through [`rt::Stats`], along with the bytes read and
+    /// written so far, which are counted even if the transport reports
+    /// nothing.
+    ///
+    /// Only the first request on a connection has the timestamps of setting
+    /// it up; later requests keep the rest, such as the addresses and the
+    /// negotiated protocols.
     pub connection_stats: Option<ConnectionStats>,
+
+    /// An id for the connection that served this request, unique within the
+    /// process.
+    pub connection_id: Option<u64>,
+
+    /// How many requests the connection has served, including this one.
+    pub connection_request_count: u64,
+
+    /// How long the connection had been idle before it picked up this
+    /// request, if it had served requests before.
+    ///
+    /// A long idle time followed by a reset usually means the peer's
+    /// keep-alive timeout closed the connection first.
+    pub idle_before_reuse: Option<core::time::Duration>,
+
+    /// The approximate instant the request started waiting for a connection.
+    pub checkout_start: Option<std::time::Instant>,
+
+    /// The approximate instant the connection picked up the request.
+    pub checkout_end: Option<std::time::Instant>,
+
+    /// The approximate instant the request head was written out.
+    ///
+    /// For HTTP/1 this is when the head was flushed to the transport; for
+    /// HTTP/2 it is when the head was handed to the connection.
+    pub headers_written: Option<std::time::Instant>,
+
+    /// The approximate instant the last byte of the request body was written
+    /// out.
+    ///
+    /// This stays empty if the response arrived before the body was done.
+    pub body_written: Option<std::time::Instant>,
+
+    /// The size of the request head, in bytes.
+    ///
+    /// For HTTP/1 this is the request line and headers as written; for
+    /// HTTP/2 it is the HPACK-encoded header block.
+    pub request_header_bytes: u64,
+
+    /// How many bytes of request body had been written out when the
+    /// response arrived, not counting any chunked framing.
+    pub request_body_bytes: u64,
+
+    /// The size of the response head, in bytes, measured like
+    /// `request_header_bytes`.
+    pub response_header_bytes: u64,
+
+    /// How many bytes of response body were received.
+    ///
+    /// Like `last_body_byte_time`, this starts out at zero; fill it in from
+    /// [`body::Incoming::body_bytes_received`] once the body has been
+    /// consumed.
+    pub response_body_bytes: u64,
+
+    /// HTTP/2 connection-level stats, as of when the response arrived.
+    pub h2: Option<H2ConnectionStats>,
+
+    /// When the frames of the HTTP/2 stream were received, as of when the
+    /// response arrived.
+    ///
+    /// Frames that arrive later can be read from
+    /// [`body::Incoming::h2_stream_stats`].
+    pub h2_stream: Option<H2StreamStats>,
 }
 
 impl HttpConnectionStats {
@@ -130,41 +210,390 @@ impl HttpConnectionStats {
                 connect_end: Some(now),
                 ..Default::default()
             }),
-            first_body_byte_time: None,
-            first_header_byte_time: None,
+            ..Default::default()
+        }
+    }
+
+    /// Returns how long it took to write out the request body, after the
+    /// head.
+    pub fn get_upload_duration(&self) -> Option<core::time::Duration> {
+        match (self.headers_written, self.body_written) {
+            (Some(s), Some(e)) => Some(e.saturating_duration_since(s)),
+            _ => None,
+        }
+    }
+
+    /// Returns how long the request waited before a connection picked it up.
+    pub fn get_pool_wait(&self) -> Option<core::time::Duration> {
+        match (self.checkout_start, self.checkout_end) {
+            (Some(s), Some(e)) => Some(e.saturating_duration_since(s)),
+            _ => None,
+        }
+    }
+
+    /// Writes the lines about the connection and getting the request onto
+    /// it, shared by the `Display` of these and of `RequestStats`.
+    fn fmt_connection(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(ref c) = self.connection_stats {
+            c.fmt(f)?;
+        }
+
+        if let Some(id) = self.connection_id {
+            f.write_fmt(format_args!(
+                "connection: #{} (request {})\n",
+                id, self.connection_request_count
+            ))?;
+        }
+
+        if let Some(w) = self.get_pool_wait() {
+            f.write_fmt(format_args!("pool wait: {:?}\n", w))?;
+        }
+
+        if let Some(u) = self.get_upload_duration() {
+            f.write_fmt(format_args!("upload: {:?}\n", u))?;
         }
+
+        Ok(())
     }
 }
 
 impl std::fmt::Display for HttpConnectionStats {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        if let Some(c) = self.connection_stats {
-            c.fmt(f)?;
+        self.fmt_connection(f)?;
+
+        if let Some(idle) = self.idle_before_reuse {
+            f.write_fmt(format_args!("idle before reuse: {:?}\n", idle))?;
+        }
+
+        if self.request_header_bytes > 0 || self.response_header_bytes > 0 {
+            f.write_fmt(format_args!(
+                "bytes: sent {} + {}, received {} + {}\n",
+                self.request_header_bytes,
+                self.request_body_bytes,
+                self.response_header_bytes,
+                self.response_body_bytes
+            ))?;
+        }
+
+        if let Some(ref h2) = self.h2 {
+            h2.fmt(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+/// Connection-level stats for a HTTP/2 connection, from the frames sent and
+/// received on it.
+pub struct H2ConnectionStats {
+    /// The settings from the first SETTINGS frame the peer sent.
+    pub peer_settings: H2Settings,
+
+    /// The connection-level window for sending DATA.
+    pub send_window: u32,
+
+    /// The connection-level window for receiving DATA.
+    pub recv_window: u32,
+
+    /// The number of WINDOW_UPDATE frames received, on any stream.
+    pub window_updates_received: u64,
+
+    /// The number of WINDOW_UPDATE frames sent, on any stream.
+    pub window_updates_sent: u64,
+
+    /// Round-trip times of PING frames sent on this connection, oldest
+    /// first.
+    ///
+    /// Only the most recent samples are kept.
+    pub ping_rtts: PingRtts,
+
+    /// How long request bodies on this connection spent, in total, waiting
+    /// for flow-control capacity to send.
+    pub send_stalled: std::time::Duration,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+/// The most recent round-trip times of HTTP/2 PINGs, oldest first.
+///
+/// At most [`PingRtts::CAPACITY`] samples are kept; older ones are dropped.
+pub struct PingRtts {
+    samples: [std::time::Duration; PingRtts::CAPACITY],
+    len: usize,
+}
+
+impl PingRtts {
+    /// How many samples are kept.
+    pub const CAPACITY: usize = 8;
+
+    /// Returns how many samples there are.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns whether there are no samples.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the most recent sample.
+    pub fn last(&self) -> Option<std::time::Duration> {
+        self.as_slice().last().copied()
+    }
+
+    /// Returns the samples, oldest first.
+    pub fn as_slice(&self) -> &[std::time::Duration] {
+        &self.samples[..self.len]
+    }
+
+    /// Adds a sample, dropping the oldest one if full.
+    #[cfg(all(feature = "http2", any(feature = "client", feature = "server")))]
+    pub(crate) fn push(&mut self, rtt: std::time::Duration) {
+        if self.len == Self::CAPACITY {
+            self.samples.copy_within(1.., 0);
+            self.len -= 1;
+        }
+        self.samples[self.len] = rtt;
+        self.len += 1;
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+/// When the frames of a HTTP/2 stream were received.
+pub struct H2StreamStats {
+    /// The approximate instant the response HEADERS frame was received.
+    pub headers: Option<std::time::Instant>,
+
+    /// The approximate instant the first DATA frame was received.
+    pub first_data: Option<std::time::Instant>,
+
+    /// The approximate instant the frame ending the stream was received.
+    pub end_stream: Option<std::time::Instant>,
+
+    /// How long the request body spent waiting for flow-control capacity to
+    /// send, on either the stream or the connection window.
+    pub send_stalled: std::time::Duration,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+/// The values of a HTTP/2 SETTINGS frame. Settings the frame didn't include
+/// are empty.
+///
+/// The peer's settings are also available while a connection is open, with
+/// every SETTINGS frame received so far applied: from the client's
+/// `SendRequest::peer_settings` and the server's `Connection::peer_settings`,
+/// and as an extension on each HTTP/2 response a client receives and each
+/// request a server hands to its service.
+pub struct H2Settings {
+    /// SETTINGS_HEADER_TABLE_SIZE
+    pub header_table_size: Option<u32>,
+
+    /// SETTINGS_ENABLE_PUSH
+    pub enable_push: Option<u32>,
+
+    /// SETTINGS_MAX_CONCURRENT_STREAMS
+    pub max_concurrent_streams: Option<u32>,
+
+    /// SETTINGS_INITIAL_WINDOW_SIZE
+    pub initial_window_size: Option<u32>,
+
+    /// SETTINGS_MAX_FRAME_SIZE
+    pub max_frame_size: Option<u32>,
+
+    /// SETTINGS_MAX_HEADER_LIST_SIZE
+    pub max_header_list_size: Option<u32>,
+
+    /// SETTINGS_ENABLE_CONNECT_PROTOCOL
+    pub enable_connect_protocol: Option<u32>,
+}
+
+impl std::fmt::Display for H2ConnectionStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!(
+            "h2 windows: send {} / recv {} ({} updates received, {} sent)\n",
+            self.send_window,
+            self.recv_window,
+            self.window_updates_received,
+            self.window_updates_sent
+        ))?;
+        if let Some(rtt) = self.ping_rtts.last() {
+            f.write_fmt(format_args!("h2 ping rtt: {:?}\n", rtt))?;
+        }
+        if !self.send_stalled.is_zero() {
+            f.write_fmt(format_args!("h2 send stalled: {:?}\n", self.send_stalled))?;
         }
         Ok(())
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
 /// Container struct for redirect stats, which are just http connection stats,
 /// along with the time the redirect finished.
+///
+/// Unlike the other stats, this isn't `Copy`, since it holds the `Location`
+/// of the redirect.
 pub struct RedirectStats {
     /// The approximate instant the redirect finished.
     pub finished: std::time::Instant,
 
     /// HTTP stats.
     pub connection_stats: HttpConnectionStats,
+
+    /// The status code of the redirect response.
+    pub status: StatusCode,
+
+    /// The HTTP version of the redirect response.
+    pub version: Version,
+
+    /// Where the redirect pointed to, from its `Location` header.
+    ///
+    /// This is the header as sent, so it may be relative. It is empty if the
+    /// header was missing or not a valid URI.
+    pub location: Option<Uri>,
+}
+
+impl RedirectStats {
+    /// Creates the stats for a redirect hop from its response.
+    ///
+    /// The status, version and `Location` target are taken from `response`.
+    pub fn from_response<B>(
+        response: &Response<B>,
+        connection_stats: HttpConnectionStats,
+        finished: std::time::Instant,
+    ) -> Self {
+        let location = response
+            .headers()
+            .get(header::LOCATION)
+            .and_then(|v| Uri::try_from(v.as_bytes()).ok());
+        RedirectStats {
+            finished,
+            connection_stats,
+            status: response.status(),
+            version: response.version(),
+            location,
+        }
+    }
 }
 
 impl std::fmt::Display for RedirectStats {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         self.connection_stats.fmt(f)?;
 
+        f.write_fmt(format_args!("redirect: {} {:?}", self.status, self.version))?;
+        if let Some(ref location) = self.location {
+            f.write_fmt(format_args!(" -> {}", location))?;
+        }
+        f.write_str("\n")?;
+
         f.write_fmt(format_args!("next redirect: {:?}", self.finished))?;
         Ok(())
     }
 }
 
+/// Why an attempt at a request was given up on and the request sent again.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RetryReason {
+    /// The pooled connection turned out to be closed already.
+    ConnectionClosed,
+
+    /// The HTTP/2 connection was going away (GOAWAY) before the request was
+    /// processed.
+    GoAway,
+
+    /// The HTTP/2 server refused the stream (`REFUSED_STREAM`) without
+    /// processing the request.
+    Refused,
+}
+
+impl std::fmt::Display for RetryReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            RetryReason::ConnectionClosed => "connection closed",
+            RetryReason::GoAway => "goaway",
+            RetryReason::Refused => "refused",
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+/// Container struct for the stats of a failed attempt at a request, which
+/// are the http connection stats of that attempt, along with why and when it
+/// failed.
+pub struct RetryStats {
+    /// Why the attempt was retried.
+    pub reason: RetryReason,
+
+    /// The approximate instant the attempt failed.
+    pub finished: std::time::Instant,
+
+    /// HTTP stats of the failed attempt.
+    pub connection_stats: HttpConnectionStats,
+}
+
+#[cfg(all(feature = "client", any(feature = "http1", feature = "http2")))]
+impl RetryStats {
+    /// Returns the stats of an attempt that failed with `err`, if it is an
+    /// error a request is sent again after.
+    ///
+    /// Those are the errors of a connection that closed before the request
+    /// was answered, and for HTTP/2, of a stream the server didn't process.
+    /// The stats are the partial ones the error carries, see
+    /// [`Error::stats`].
+    pub fn from_error(err: &Error) -> Option<RetryStats> {
+        let reason = Self::reason(err)?;
+        Some(RetryStats {
+            reason,
+            finished: std::time::Instant::now(),
+            connection_stats: err.stats().copied().unwrap_or_default(),
+        })
+    }
+
+    fn reason(err: &Error) -> Option<RetryReason> {
+        #[cfg(feature = "http2")]
+        if let Some(h2_err) = err.find_source::<h2::Error>() {
+            if h2_err.is_go_away() {
+                return Some(RetryReason::GoAway);
+            }
+            if h2_err.reason() == Some(h2::Reason::REFUSED_STREAM) {
+                return Some(RetryReason::Refused);
+            }
+        }
+        if err.is_canceled() || err.is_closed() || err.is_incomplete_message() {
+            return Some(RetryReason::ConnectionClosed);
+        }
+        None
+    }
+
+    /// Records this failed attempt on the request sent again in its place,
+    /// so that the stats of that request list it among their
+    /// [`retries`](RequestStats::retries).
+    ///
+    /// A request handed back by `try_send_request` already has its failed
+    /// attempt recorded.
+    pub fn record_on<B>(self, req: &mut Request<B>) {
+        match req.extensions_mut().get_mut::<common::stats::Retries>() {
+            Some(retries) => retries.0.push(self),
+            None => {
+                req.extensions_mut()
+                    .insert(common::stats::Retries(vec![self]));
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for RetryStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.connection_stats.fmt(f)?;
+
+        f.write_fmt(format_args!(
+            "retried ({}): {:?}\n",
+            self.reason, self.finished
+        ))?;
+        Ok(())
+    }
+}
+
 #[derive(Clone, Debug)]
 /// Connection and request-level stats for a http request.
 pub struct RequestStats {
@@ -174,6 +603,14 @@ pub struct RequestStats {
     /// Stats for all the redirects (save the final request.)
     pub redirects: Vec<RedirectStats>,
 
+    /// Stats for every earlier attempt at this request that failed and was
+    /// sent again, oldest first.
+    ///
+    /// hyper doesn't retry requests itself, but whatever layer does can
+    /// record each failed attempt with [`RetryStats::record_on`] before
+    /// sending the request again.
+    pub retries: Vec<RetryStats>,
+
     /// The approximate moment we started this request.
     pub poll_start: std::time::Instant,
 
@@ -186,17 +623,20 @@ impl RequestStats {
     /// for unsupported http 2 stats.
     pub fn empty() -> Self {
         RequestStats {
-            http_stats: HttpConnectionStats {
-                first_body_byte_time: None,
-                first_header_byte_time: None,
-                connection_stats: None,
-            },
+            http_stats: HttpConnectionStats::default(),
             redirects: vec![],
+            retries: vec![],
             poll_start: std::time::Instant::now(),
             finish: std::time::Instant::now(),
         }
     }
 
+    /// Returns how many times the request was sent again after a failed
+    /// attempt.
+    pub fn get_retry_count(&self) -> usize {
+        self.retries.len()
+    }
+
     fn get_request_start(&self) -> std::time::Instant {
         self.poll_start
     }
@@ -216,6 +656,13 @@ impl RequestStats {
             .map(|t| t.duration_since(self.get_request_start()))
     }
 
+    /// Gets the time (relative to get_request_start) that the last body byte was received.
+    pub fn get_body_end(&self) -> Option<core::time::Duration> {
+        self.http_stats
+            .last_body_byte_time
+            .map(|t| t.duration_since(self.get_request_start()))
+    }
+
     /// Returns the time (relative to get_request_start) that the last redirection
     /// began (this would be the final request made in a chain of redirections)
     pub fn get_last_redirect_start(&self) -> Option<core::time::Duration> {
@@ -224,6 +671,164 @@ impl RequestStats {
             .map(|r| r.finished.duration_since(self.get_request_start()))
     }
 
+    /// Formats these stats as a [`Server-Timing`] header value.
+    ///
+    /// The `dns`, `connect`, `tls` and `ttfb` metrics are only included when
+    /// they were recorded; `total` is always present. Durations are in
+    /// milliseconds.
+    ///
+    /// [`Server-Timing`]: https://www.w3.org/TR/server-timing/
+    pub fn to_server_timing(&self) -> String {
+        let conn = self.http_stats.connection_stats.as_ref();
+        let metrics = [
+            ("dns", conn.and_then(|c| c.get_dns_duration())),
+            ("connect", conn.and_then(|c| c.get_connect_duration())),
+            ("tls", conn.and_then(|c| c.get_tls_duration())),
+            ("ttfb", self.get_header_ttfb()),
+            ("total", Some(self.get_request_end())),
+        ];
+
+        let mut value = String::new();
+        for (name, dur) in metrics {
+            let dur = match dur {
+                Some(dur) => dur,
+                None => continue,
+            };
+            if !value.is_empty() {
+                value.push_str(", ");
+            }
+            value.push_str(&format!("{};dur={:.3}", name, dur.as_secs_f64() * 1000.0));
+        }
+        value
+    }
+
+    /// Flattens these stats into metric names and values, for structured
+    /// logging.
+    ///
+    /// The names are stable. Metrics that weren't recorded for this request
+    /// are left out, except for `total`, `redirects` and `retries`, which are always
+    /// present.
+    pub fn fields(&self) -> impl Iterator<Item = (&'static str, StatValue)> {
+        let conn = self.http_stats.connection_stats.as_ref();
+        let durations = [
+            ("dns", conn.and_then(|c| c.get_dns_duration())),
+            ("connect", conn.and_then(|c| c.get_connect_duration())),
+            ("tls", conn.and_then(|c| c.get_tls_duration())),
+            ("pool_wait", self.http_stats.get_pool_wait()),
+            ("upload", self.http_stats.get_upload_duration()),
+            ("redirect_start", self.get_last_redirect_start()),
+            ("header_ttfb", self.get_header_ttfb()),
+            ("body_ttfb", self.get_body_ttfb()),
+            ("body_end", self.get_body_end()),
+            ("total", Some(self.get_request_end())),
+        ];
+        let counts = [
+            ("redirects", Some(self.redirects.len() as u64)),
+            ("retries", Some(self.retries.len() as u64)),
+            ("connection_id", self.http_stats.connection_id),
+            (
+                "connection_request_count",
+                self.http_stats
+                    .connection_id
+                    .map(|_| self.http_stats.connection_request_count),
+            ),
+            ("bytes_read", conn.map(|c| c.bytes_read)),
+            ("bytes_written", conn.map(|c| c.bytes_written)),
+        ];
+
+        let durations = durations
+            .into_iter()
+            .filter_map(|(name, d)| d.map(|d| (name, StatValue::Duration(d))));
+        let counts = counts
+            .into_iter()
+            .filter_map(|(name, c)| c.map(|c| (name, StatValue::Count(c))));
+        durations.chain(counts)
+    }
+
+    /// Splits this request into the phases it went through, ordered by when
+    /// they started.
+    ///
+    /// A phase is left out if the timestamps bounding it weren't recorded:
+    /// a pooled connection has no `dns`, `connect` or `tls` phase, and there
+    /// is no `download` phase until `last_body_byte_time` has been filled
+    /// in. The queue only counts time not spent setting up a connection.
+    pub fn phases(&self) -> Vec<stats::PhaseTiming> {
+        use stats::{PhaseTiming, RequestPhase};
+
+        let http = &self.http_stats;
+        let conn = http.connection_stats.as_ref();
+        let span = |phase, start: Option<std::time::Instant>, end: Option<std::time::Instant>| {
+            let (start, end) = (start?, end?);
+            Some(PhaseTiming {
+                phase,
+                start,
+                duration: end.saturating_duration_since(start),
+            })
+        };
+
+        let dns =
+            conn.and_then(|c| span(RequestPhase::Dns, c.dns_resolve_start, c.dns_resolve_end));
+        let connect =
+            conn.and_then(|c| span(RequestPhase::Connect, c.connect_start, c.connect_end));
+        let tls =
+            conn.and_then(|c| span(RequestPhase::Tls, c.tls_connect_start, c.tls_connect_end));
+
+        // A connection set up while the request was queued ends the queue.
+        let setup_start = [dns, connect, tls].iter().flatten().map(|p| p.start).min();
+        let queue_end = match (setup_start, http.checkout_start, http.checkout_end) {
+            (Some(setup), Some(start), Some(end)) if setup > start && setup < end => Some(setup),
+            (_, _, end) => end,
+        };
+        let queue = span(RequestPhase::Queue, http.checkout_start, queue_end);
+
+        let write_end = http.body_written.or(http.headers_written);
+        let write = span(RequestPhase::RequestWrite, Some(self.poll_start), write_end);
+        let waiting = span(
+            RequestPhase::Waiting,
+            Some(write_end.unwrap_or(self.poll_start)),
+            http.first_header_byte_time,
+        );
+        let download = span(
+            RequestPhase::Download,
+            http.first_header_byte_time,
+            http.last_body_byte_time,
+        );
+
+        let mut phases = [queue, dns, connect, tls, write, waiting, download]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>();
+        // stable, so phases starting together keep the order above
+        phases.sort_by_key(|p| p.start);
+        phases
+    }
+
+    /// Converts the timestamps of these stats to wall-clock times.
+    ///
+    /// `now` should be the current wall-clock time, usually
+    /// `SystemTime::now()`; every `Instant` is placed relative to it.
+    ///
+    /// This pairs `now` with `Instant::now()`, so it only makes sense for
+    /// stats recorded with the system clock. Use
+    /// [`anchored_at`](RequestStats::anchored_at) for stats recorded through
+    /// a custom [`StatsClock`](stats::StatsClock).
+    pub fn anchored(&self, now: std::time::SystemTime) -> stats::AnchoredRequestStats {
+        self.anchored_at(std::time::Instant::now(), now)
+    }
+
+    /// Converts the timestamps of these stats to wall-clock times, where
+    /// `instant` and `now` are the same moment.
+    ///
+    /// With a custom [`StatsClock`](stats::StatsClock), `instant` is read
+    /// from that clock.
+    pub fn anchored_at(
+        &self,
+        instant: std::time::Instant,
+        now: std::time::SystemTime,
+    ) -> stats::AnchoredRequestStats {
+        stats::Anchor::new(instant, now).request(self)
+    }
+
     /// Returns the time the request end (this does not include body time!)
     pub fn get_request_end(&self) -> core::time::Duration {
         self.finish.duration_since(self.get_request_start())
@@ -241,16 +846,37 @@ impl RequestStats {
     }
 }
 
+/// A single value from [`RequestStats::fields`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StatValue {
+    /// A duration, relative to the start of the request or of its phase.
+    Duration(core::time::Duration),
+
+    /// A count or identifier.
+    Count(u64),
+}
+
+impl Display for StatValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StatValue::Duration(d) => f.write_fmt(format_args!("{:?}", d)),
+            StatValue::Count(c) => c.fmt(f),
+        }
+    }
+}
+
 impl Display for RequestStats {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        for r in &self.redirects {
+        for r in &self.retries {
             r.fmt(f)?;
         }
 
-        if let Some(c) = self.http_stats.connection_stats {
-            c.fmt(f)?;
+        for r in &self.redirects {
+            r.fmt(f)?;
         }
 
+        self.http_stats.fmt_connection(f)?;
+
         if let Some(e) = self.get_last_redirect_start() {
             f.write_fmt(format_args!("redirection: {:?}\n", e))?;
         }
@@ -263,12 +889,91 @@ impl Display for RequestStats {
             f.write_fmt(format_args!("time to first body byte: {:?}\n", e))?;
         }
 
+        if let Some(e) = self.get_body_end() {
+            f.write_fmt(format_args!("time to last body byte: {:?}\n", e))?;
+        }
+
         f.write_fmt(format_args!("total time: {:?}\n", self.get_request_end()))?;
 
         Ok(())
     }
 }
 
+#[derive(Clone, Debug)]
+/// Server-side stats for a single request.
+///
+/// When enabled on the server connection builder, a copy is inserted into
+/// the request extensions as soon as the head has been parsed; only
+/// `headers_parsed` is filled in at that point. The completed stats are
+/// delivered to the builder's `on_stats` callback once the response has been
+/// flushed.
+pub struct ServerRequestStats {
+    /// The request method.
+    pub method: Method,
+
+    /// The request target.
+    pub uri: Uri,
+
+    /// The response status, once the service has responded.
+    pub status: Option<StatusCode>,
+
+    /// The approximate instant the request head was parsed.
+    pub headers_parsed: std::time::Instant,
+
+    /// The approximate instant the whole request body had been read.
+    ///
+    /// This stays empty if the response finished before the body was read.
+    pub body_complete: Option<std::time::Instant>,
+
+    /// The approximate instant the last byte of the response was flushed.
+    pub response_flushed: Option<std::time::Instant>,
+
+    /// How many bytes of chunk extensions the request body had.
+    pub chunk_extension_bytes: u64,
+}
+
+impl ServerRequestStats {
+    /// Returns how long it took to read the request body, after the head.
+    pub fn get_body_duration(&self) -> Option<core::time::Duration> {
+        self.body_complete
+            .map(|t| t.saturating_duration_since(self.headers_parsed))
+    }
+
+    /// Returns the time from parsing the request head to flushing the
+    /// response.
+    pub fn get_total_duration(&self) -> Option<core::time::Duration> {
+        self.response_flushed
+            .map(|t| t.saturating_duration_since(self.headers_parsed))
+    }
+}
+
+impl Display for ServerRequestStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!("request: {} {}\n", self.method, self.uri))?;
+
+        if let Some(s) = self.status {
+            f.write_fmt(format_args!("status: {}\n", s.as_u16()))?;
+        }
+
+        if let Some(e) = self.get_body_duration() {
+            f.write_fmt(format_args!("request body: {:?}\n", e))?;
+        }
+
+        if self.chunk_extension_bytes > 0 {
+            f.write_fmt(format_args!(
+                "chunk extensions: {} bytes\n",
+                self.chunk_extension_bytes
+            ))?;
+        }
+
+        if let Some(e) = self.get_total_duration() {
+            f.write_fmt(format_args!("total time: {:?}\n", e))?;
+        }
+
+        Ok(())
+    }
+}
+
 #[macro_use]
 mod cfg;
 
@@ -283,6 +988,7 @@ pub mod ext;
 mod mock;
 pub mod rt;
 pub mod service;
+pub mod stats;
 pub mod upgrade;
 
 #[cfg(feature = "ffi")]
@@ -305,3 +1011,207 @@ cfg_feature! {
 
     pub mod server;
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{
+        HttpConnectionStats, RedirectStats, RequestStats, RetryReason, RetryStats, StatValue,
+    };
+    use crate::rt::ConnectionStats;
+
+    #[test]
+    fn server_timing_header_value() {
+        let start = Instant::now();
+        let ms = Duration::from_millis;
+        let stats = RequestStats {
+            http_stats: HttpConnectionStats {
+                first_header_byte_time: Some(start + ms(40)),
+                connection_stats: Some(ConnectionStats {
+                    start_time: Some(start),
+                    dns_resolve_start: Some(start),
+                    dns_resolve_end: Some(start + ms(5)),
+                    connect_start: Some(start + ms(5)),
+                    connect_end: Some(start + ms(15)),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            redirects: Vec::new(),
+            retries: Vec::new(),
+            poll_start: start,
+            finish: start + ms(50),
+        };
+
+        assert_eq!(
+            stats.to_server_timing(),
+            "dns;dur=5.000, connect;dur=10.000, ttfb;dur=40.000, total;dur=50.000"
+        );
+    }
+
+    #[test]
+    fn flattened_fields() {
+        let start = Instant::now();
+        let ms = Duration::from_millis;
+        let stats = RequestStats {
+            http_stats: HttpConnectionStats {
+                first_header_byte_time: Some(start + ms(40)),
+                connection_id: Some(7),
+                connection_request_count: 2,
+                ..Default::default()
+            },
+            redirects: Vec::new(),
+            retries: Vec::new(),
+            poll_start: start,
+            finish: start + ms(50),
+        };
+
+        let fields = stats.fields().collect::<Vec<_>>();
+        assert_eq!(
+            fields,
+            [
+                ("header_ttfb", StatValue::Duration(ms(40))),
+                ("total", StatValue::Duration(ms(50))),
+                ("redirects", StatValue::Count(0)),
+                ("retries", StatValue::Count(0)),
+                ("connection_id", StatValue::Count(7)),
+                ("connection_request_count", StatValue::Count(2)),
+            ]
+        );
+    }
+
+    #[test]
+    fn request_phases() {
+        use crate::stats::RequestPhase;
+
+        let start = Instant::now();
+        let ms = Duration::from_millis;
+        let mut stats = RequestStats {
+            http_stats: HttpConnectionStats {
+                checkout_start: Some(start),
+                checkout_end: Some(start + ms(20)),
+                headers_written: Some(start + ms(22)),
+                body_written: Some(start + ms(25)),
+                first_header_byte_time: Some(start + ms(40)),
+                last_body_byte_time: Some(start + ms(45)),
+                connection_stats: Some(ConnectionStats {
+                    dns_resolve_start: Some(start + ms(2)),
+                    dns_resolve_end: Some(start + ms(5)),
+                    connect_start: Some(start + ms(5)),
+                    connect_end: Some(start + ms(15)),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            redirects: Vec::new(),
+            retries: Vec::new(),
+            poll_start: start + ms(20),
+            finish: start + ms(50),
+        };
+
+        let phases = stats
+            .phases()
+            .iter()
+            .map(|p| (p.phase, p.start - start, p.duration))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            phases,
+            [
+                (RequestPhase::Queue, ms(0), ms(2)),
+                (RequestPhase::Dns, ms(2), ms(3)),
+                (RequestPhase::Connect, ms(5), ms(10)),
+                (RequestPhase::RequestWrite, ms(20), ms(5)),
+                (RequestPhase::Waiting, ms(25), ms(15)),
+                (RequestPhase::Download, ms(40), ms(5)),
+            ]
+        );
+
+        // a pooled connection, with the body still being read
+        stats.http_stats.connection_stats = None;
+        stats.http_stats.last_body_byte_time = None;
+        let names = stats
+            .phases()
+            .iter()
+            .map(|p| p.phase.name())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["queue", "request_write", "waiting"]);
+        assert_eq!(stats.phases()[0].end(), start + ms(20));
+    }
+
+    #[test]
+    fn retries_are_reported() {
+        let start = Instant::now();
+        let mut stats = RequestStats::empty();
+        stats.retries.push(RetryStats {
+            reason: RetryReason::GoAway,
+            finished: start,
+            connection_stats: HttpConnectionStats::default(),
+        });
+
+        assert_eq!(stats.get_retry_count(), 1);
+        assert!(stats
+            .fields()
+            .any(|f| f == ("retries", StatValue::Count(1))));
+        assert!(stats.to_string().contains("retried (goaway)"));
+    }
+
+    #[cfg(all(feature = "client", feature = "http2"))]
+    #[test]
+    fn retry_stats_from_error() {
+        let canceled = crate::Error::new_canceled().with_stats(HttpConnectionStats {
+            connection_id: Some(7),
+            ..Default::default()
+        });
+        let retry = RetryStats::from_error(&canceled).expect("canceled is retried");
+        assert_eq!(retry.reason, RetryReason::ConnectionClosed);
+        assert_eq!(retry.connection_stats.connection_id, Some(7));
+
+        let refused = crate::Error::new_h2(h2::Reason::REFUSED_STREAM.into());
+        let retry = RetryStats::from_error(&refused).expect("refused is retried");
+        assert_eq!(retry.reason, RetryReason::Refused);
+
+        let protocol = crate::Error::new_h2(h2::Reason::PROTOCOL_ERROR.into());
+        assert!(RetryStats::from_error(&protocol).is_none());
+
+        let mut req = crate::Request::new(());
+        retry.clone().record_on(&mut req);
+        retry.record_on(&mut req);
+        let retries = req
+            .extensions()
+            .get::<crate::common::stats::Retries>()
+            .unwrap();
+        assert_eq!(retries.0.len(), 2);
+    }
+
+    #[cfg(all(feature = "http2", any(feature = "client", feature = "server")))]
+    #[test]
+    fn ping_rtts_keep_most_recent() {
+        let mut rtts = crate::PingRtts::default();
+        assert!(rtts.is_empty());
+        for ms in 0..10 {
+            rtts.push(std::time::Duration::from_millis(ms));
+        }
+        assert_eq!(rtts.len(), crate::PingRtts::CAPACITY);
+        assert_eq!(rtts.as_slice()[0], std::time::Duration::from_millis(2));
+        assert_eq!(rtts.last(), Some(std::time::Duration::from_millis(9)));
+    }
+
+    #[test]
+    fn redirect_from_response() {
+        let res = crate::Response::builder()
+            .status(301)
+            .header("location", "/next")
+            .body(())
+            .unwrap();
+        let redirect =
+            RedirectStats::from_response(&res, HttpConnectionStats::default(), Instant::now());
+
+        assert_eq!(redirect.status, crate::StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(redirect.version, crate::Version::HTTP_11);
+        assert_eq!(redirect.location, Some("/next".parse().unwrap()));
+        assert!(redirect
+            .to_string()
+            .contains("redirect: 301 Moved Permanently HTTP/1.1 -> /next"));
+    }
+}
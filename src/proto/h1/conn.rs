@@ -1,28 +1,36 @@
+#[cfg(feature = "client")]
+use std::collections::VecDeque;
 use std::fmt;
-#[cfg(feature = "server")]
 use std::future::Future;
 use std::io;
 use std::marker::{PhantomData, Unpin};
 use std::pin::Pin;
 use std::task::{Context, Poll};
-#[cfg(feature = "server")]
 use std::time::{Duration, Instant};
 
 use crate::rt::{Read, Stats, Write};
 use bytes::{Buf, Bytes};
 use futures_core::ready;
+#[cfg(feature = "client")]
+use http::header::EXPECT;
+#[cfg(feature = "server")]
+use http::header::DATE;
 use http::header::{HeaderValue, CONNECTION, TE};
 use http::{HeaderMap, Method, Version};
 use http_body::Frame;
 use httparse::ParserConfig;
 
+use super::decode::ChunkExtensionConfig;
 use super::io::Buffered;
 use super::{Decoder, Encode, EncodedBuf, Encoder, Http1Transaction, ParseContext, Wants};
 use crate::body::DecodedLength;
 #[cfg(feature = "server")]
+use crate::common::date::DateSource;
+use crate::common::stats::Clock;
+#[cfg(feature = "client")]
+use crate::common::time::Deadline;
 use crate::common::time::Time;
 use crate::proto::{BodyLength, MessageHead};
-#[cfg(feature = "server")]
 use crate::rt::Sleep;
 use crate::{headers, HttpConnectionStats};
 
@@ -42,6 +50,13 @@ pub(crate) struct Conn<I, B, T> {
 
     first_header_byte_time: Option<std::time::Instant>,
     first_body_byte_time: Option<std::time::Instant>,
+    idle_before_reuse: Option<std::time::Duration>,
+    // Sizes of the current message heads and outgoing body.
+    sent_head_bytes: u64,
+    sent_body_bytes: u64,
+    received_head_bytes: u64,
+    received_chunk_extension_bytes: u64,
+    collect_stats: bool,
 }
 
 impl<I, B, T> Conn<I, B, T>
@@ -61,6 +76,8 @@ where
                 method: None,
                 h1_parser_config: ParserConfig::default(),
                 h1_max_headers: None,
+                h1_max_header_size: None,
+                chunk_extensions: ChunkExtensionConfig::default(),
                 #[cfg(feature = "server")]
                 h1_header_read_timeout: None,
                 #[cfg(feature = "server")]
@@ -68,8 +85,23 @@ where
                 #[cfg(feature = "server")]
                 h1_header_read_timeout_running: false,
                 #[cfg(feature = "server")]
+                keep_alive_timeout: None,
+                #[cfg(feature = "server")]
+                keep_alive_timeout_fut: None,
+                #[cfg(feature = "server")]
+                keep_alive_timed_out: false,
+                #[cfg(feature = "server")]
                 date_header: true,
                 #[cfg(feature = "server")]
+                date_source: DateSource::default(),
+                #[cfg(feature = "server")]
+                requests_left: None,
+                #[cfg(feature = "server")]
+                reject_expect_continue: false,
+                #[cfg(feature = "server")]
+                max_request_body_size: None,
+                #[cfg(feature = "server")]
+                body_bytes_read: 0,
                 timer: Time::Empty,
                 preserve_header_case: false,
                 #[cfg(feature = "ffi")]
@@ -78,6 +110,42 @@ where
                 h09_responses: false,
                 #[cfg(feature = "client")]
                 on_informational: None,
+                #[cfg(feature = "client")]
+                idle_since: None,
+                #[cfg(feature = "client")]
+                expect_continue_timeout: None,
+                #[cfg(feature = "client")]
+                abort_on_expect_continue_timeout: false,
+                #[cfg(feature = "client")]
+                auto_expect_continue: None,
+                #[cfg(feature = "client")]
+                expect_continue_fut: None,
+                #[cfg(feature = "client")]
+                continue_received: false,
+                #[cfg(feature = "client")]
+                raw_headers: false,
+                #[cfg(feature = "server")]
+                allow_invalid_request_target: false,
+                #[cfg(feature = "server")]
+                conflicting_framing: Default::default(),
+                #[cfg(feature = "server")]
+                error_responder: None,
+                #[cfg(feature = "client")]
+                deadline_fut: None,
+                #[cfg(feature = "client")]
+                response_header_timeout: None,
+                #[cfg(feature = "client")]
+                pending_response_header_timeout: None,
+                #[cfg(feature = "client")]
+                response_header_timeout_fut: None,
+                #[cfg(feature = "client")]
+                close_cause: None,
+                #[cfg(feature = "client")]
+                pipelining: false,
+                #[cfg(feature = "client")]
+                pipelined: VecDeque::new(),
+                #[cfg(feature = "client")]
+                request_bytes: None,
                 notify_read: false,
                 reading: Reading::Init,
                 writing: Writing::Init,
@@ -86,14 +154,20 @@ where
                 // If they tell us otherwise, we'll downgrade in `read_head`.
                 version: Version::HTTP_11,
                 allow_trailer_fields: false,
+                clock: Clock::default(),
             },
             first_body_byte_time: None,
             first_header_byte_time: None,
+            idle_before_reuse: None,
+            sent_head_bytes: 0,
+            sent_body_bytes: 0,
+            received_head_bytes: 0,
+            received_chunk_extension_bytes: 0,
+            collect_stats: true,
             _marker: PhantomData,
         }
     }
 
-    #[cfg(feature = "server")]
     pub(crate) fn set_timer(&mut self, timer: Time) {
         self.state.timer = timer;
     }
@@ -116,14 +190,71 @@ where
         self.io.set_read_buf_exact_size(sz);
     }
 
+    #[cfg(feature = "client")]
+    pub(crate) fn set_read_buf_sizes(&mut self, initial: Option<usize>, max: Option<usize>) {
+        self.io.set_read_buf_sizes(initial, max);
+    }
+
+    pub(crate) fn disable_stats(&mut self) {
+        self.collect_stats = false;
+    }
+
+    #[cfg(feature = "client")]
+    pub(crate) fn set_clock(&mut self, clock: Clock) {
+        self.state.clock = clock;
+    }
+
+    #[cfg(feature = "client")]
+    pub(crate) fn set_expect_continue_timeout(&mut self, val: Duration) {
+        self.state.expect_continue_timeout = Some(val);
+    }
+
+    #[cfg(feature = "client")]
+    pub(crate) fn set_pipelining(&mut self) {
+        self.state.pipelining = true;
+    }
+
+    #[cfg(feature = "client")]
+    pub(crate) fn set_response_header_timeout(&mut self, val: Duration) {
+        self.state.response_header_timeout = Some(val);
+    }
+
+    #[cfg(feature = "client")]
+    pub(crate) fn set_abort_on_expect_continue_timeout(&mut self) {
+        self.state.abort_on_expect_continue_timeout = true;
+    }
+
+    #[cfg(feature = "client")]
+    pub(crate) fn set_auto_expect_continue(&mut self, min_body_size: u64) {
+        self.state.auto_expect_continue = Some(min_body_size);
+    }
+
     pub(crate) fn http_connection_stats(&mut self) -> HttpConnectionStats {
+        if !self.collect_stats {
+            return HttpConnectionStats::default();
+        }
+        let request_bytes = (self.sent_head_bytes, self.sent_body_bytes);
+        // A pipelined request may be written after the one being answered.
+        #[cfg(feature = "client")]
+        let request_bytes = self.state.request_bytes.unwrap_or(request_bytes);
         HttpConnectionStats {
-            connection_stats: self.io.connection_stats(),
+            connection_stats: Some(self.io.connection_stats()),
             first_body_byte_time: self.first_body_byte_time,
             first_header_byte_time: self.first_header_byte_time,
+            idle_before_reuse: self.idle_before_reuse,
+            request_header_bytes: request_bytes.0,
+            request_body_bytes: request_bytes.1,
+            response_header_bytes: self.received_head_bytes,
+            ..Default::default()
         }
     }
 
+    /// Returns how many bytes of chunk extensions the current incoming body
+    /// has had.
+    pub(crate) fn received_chunk_extension_bytes(&self) -> u64 {
+        self.received_chunk_extension_bytes
+    }
+
     pub(crate) fn set_first_byte_of_body(&mut self, time: Option<std::time::Instant>) {
         self.first_body_byte_time = time;
     }
@@ -158,15 +289,45 @@ where
         self.state.h09_responses = true;
     }
 
+    #[cfg(feature = "client")]
+    pub(crate) fn set_raw_headers(&mut self) {
+        self.state.raw_headers = true;
+    }
+
     pub(crate) fn set_http1_max_headers(&mut self, val: usize) {
         self.state.h1_max_headers = Some(val);
     }
 
+    #[cfg(feature = "client")]
+    pub(crate) fn set_http1_max_header_size(&mut self, val: usize) {
+        self.state.h1_max_header_size = Some(val);
+    }
+
+    #[cfg(feature = "server")]
+    pub(crate) fn set_max_chunk_extension_size(&mut self, max: u64) {
+        self.state.chunk_extensions.max_size = Some(max);
+    }
+
+    #[cfg(feature = "server")]
+    pub(crate) fn set_reject_chunk_extensions(&mut self) {
+        self.state.chunk_extensions.reject = true;
+    }
+
     #[cfg(feature = "server")]
     pub(crate) fn set_http1_header_read_timeout(&mut self, val: Duration) {
         self.state.h1_header_read_timeout = Some(val);
     }
 
+    #[cfg(feature = "server")]
+    pub(crate) fn set_keep_alive_timeout(&mut self, val: Duration) {
+        self.state.keep_alive_timeout = Some(val);
+    }
+
+    #[cfg(feature = "server")]
+    pub(crate) fn keep_alive_timed_out(&self) -> bool {
+        self.state.keep_alive_timed_out
+    }
+
     #[cfg(feature = "server")]
     pub(crate) fn set_allow_half_close(&mut self) {
         self.state.allow_half_close = true;
@@ -177,6 +338,44 @@ where
         self.state.date_header = false;
     }
 
+    #[cfg(feature = "server")]
+    pub(crate) fn set_date_source(&mut self, source: DateSource) {
+        self.state.date_source = source;
+    }
+
+    #[cfg(feature = "server")]
+    pub(crate) fn set_max_requests(&mut self, max: usize) {
+        self.state.requests_left = Some(max);
+    }
+
+    #[cfg(feature = "server")]
+    pub(crate) fn set_allow_invalid_request_target(&mut self) {
+        self.state.allow_invalid_request_target = true;
+    }
+
+    #[cfg(feature = "server")]
+    pub(crate) fn set_conflicting_framing(
+        &mut self,
+        policy: crate::server::conn::http1::ConflictingFraming,
+    ) {
+        self.state.conflicting_framing = policy;
+    }
+
+    #[cfg(feature = "server")]
+    pub(crate) fn set_error_responder(&mut self, responder: super::ErrorResponder) {
+        self.state.error_responder = Some(responder);
+    }
+
+    #[cfg(feature = "server")]
+    pub(crate) fn set_reject_expect_continue(&mut self) {
+        self.state.reject_expect_continue = true;
+    }
+
+    #[cfg(feature = "server")]
+    pub(crate) fn set_max_request_body_size(&mut self, max: u64) {
+        self.state.max_request_body_size = Some(max);
+    }
+
     pub(crate) fn into_inner(self) -> (I, Bytes) {
         self.io.into_inner()
     }
@@ -193,6 +392,16 @@ where
         self.state.is_write_closed()
     }
 
+    #[cfg(feature = "client")]
+    pub(crate) fn close_cause(&self) -> Option<CloseCause> {
+        self.state.close_cause
+    }
+
+    #[cfg(feature = "client")]
+    pub(crate) fn set_close_cause(&mut self, cause: CloseCause) {
+        self.state.set_close_cause(cause);
+    }
+
     pub(crate) fn can_read_head(&self) -> bool {
         if !matches!(self.state.reading, Reading::Init) {
             return false;
@@ -212,7 +421,6 @@ where
         )
     }
 
-    #[cfg(feature = "server")]
     pub(crate) fn has_initial_read_write_state(&self) -> bool {
         matches!(self.state.reading, Reading::Init)
             && matches!(self.state.writing, Writing::Init)
@@ -236,8 +444,26 @@ where
         debug_assert!(self.can_read_head());
         trace!("Conn::read_head");
 
+        // An idle connection waits for the first byte of the next request
+        // under the keep-alive timeout, and only then the header one.
         #[cfg(feature = "server")]
-        if !self.state.h1_header_read_timeout_running {
+        let awaiting_request = match self.state.keep_alive_timeout {
+            Some(timeout) if self.state.is_idle() && self.io.read_buf().is_empty() => {
+                if self.state.keep_alive_timeout_fut.is_none() {
+                    trace!("setting h1 keep-alive timeout timer");
+                    self.state.keep_alive_timeout_fut =
+                        Some(self.state.timer.sleep_until(Instant::now() + timeout));
+                }
+                true
+            }
+            _ => {
+                self.state.keep_alive_timeout_fut = None;
+                false
+            }
+        };
+
+        #[cfg(feature = "server")]
+        if !awaiting_request && !self.state.h1_header_read_timeout_running {
             if let Some(h1_header_read_timeout) = self.state.h1_header_read_timeout {
                 let deadline = Instant::now() + h1_header_read_timeout;
                 self.state.h1_header_read_timeout_running = true;
@@ -255,25 +481,62 @@ where
             }
         }
 
-        let (fbt, msg) = match self.io.parse::<T>(
-            true,
+        let clock = if self.collect_stats {
+            Some(&self.state.clock)
+        } else {
+            None
+        };
+        let parsed = self.io.parse::<T>(
+            clock,
             cx,
             ParseContext {
                 cached_headers: &mut self.state.cached_headers,
                 req_method: &mut self.state.method,
                 h1_parser_config: self.state.h1_parser_config.clone(),
                 h1_max_headers: self.state.h1_max_headers,
+                h1_max_header_size: self.state.h1_max_header_size,
                 preserve_header_case: self.state.preserve_header_case,
                 #[cfg(feature = "ffi")]
                 preserve_header_order: self.state.preserve_header_order,
                 h09_responses: self.state.h09_responses,
                 #[cfg(feature = "client")]
                 on_informational: &mut self.state.on_informational,
+                #[cfg(feature = "client")]
+                continue_received: &mut self.state.continue_received,
+                #[cfg(feature = "client")]
+                raw_headers: self.state.raw_headers,
+                #[cfg(feature = "server")]
+                allow_invalid_request_target: self.state.allow_invalid_request_target,
+                #[cfg(feature = "server")]
+                conflicting_framing: self.state.conflicting_framing,
             },
-        ) {
+        );
+        #[cfg(feature = "client")]
+        if self.state.continue_received {
+            self.state.continue_received = false;
+            if self.state.expect_continue_fut.take().is_some() {
+                trace!("received 100 Continue, sending body");
+            }
+        }
+        let (fbt, head_len, msg) = match parsed {
             Poll::Ready(Ok(msg)) => msg,
             Poll::Ready(Err(e)) => return self.on_read_head_error(e),
             Poll::Pending => {
+                #[cfg(feature = "server")]
+                if let Some(ref mut keep_alive_timeout_fut) = self.state.keep_alive_timeout_fut {
+                    if !self.io.read_buf().is_empty() {
+                        // Read again, to start the header read timeout.
+                        self.state.keep_alive_timeout_fut = None;
+                        self.state.notify_read = true;
+                    } else if Pin::new(keep_alive_timeout_fut).poll(cx).is_ready() {
+                        debug!("keep-alive connection idle too long, closing");
+                        self.state.keep_alive_timeout_fut = None;
+                        self.state.keep_alive_timed_out = true;
+                        self.state.close();
+                        return Poll::Ready(None);
+                    }
+                }
+
                 #[cfg(feature = "server")]
                 if self.state.h1_header_read_timeout_running {
                     if let Some(ref mut h1_header_read_timeout_fut) =
@@ -296,6 +559,7 @@ where
         {
             self.state.h1_header_read_timeout_running = false;
             self.state.h1_header_read_timeout_fut = None;
+            self.state.keep_alive_timeout_fut = None;
         }
 
         // Note: don't deconstruct `msg` into local variables, it appears
@@ -310,12 +574,60 @@ where
         #[cfg(feature = "client")]
         {
             self.state.on_informational = None;
+            self.state.pending_response_header_timeout = None;
+            self.state.response_header_timeout_fut = None;
+        }
+
+        // A final response before the 100 Continue means the server doesn't
+        // want the body, and without it the connection can't be reused.
+        #[cfg(feature = "client")]
+        if self.state.expect_continue_fut.take().is_some() {
+            debug!("response received before 100 Continue, not sending body");
+            self.state.close_write();
+        }
+
+        // Requests pipelined behind a response that closes the connection
+        // won't be answered, so stop writing them.
+        #[cfg(feature = "client")]
+        if !msg.keep_alive && !self.state.pipelined.is_empty() {
+            debug!(
+                "connection closing with {} pipelined requests unanswered",
+                self.state.pipelined.len()
+            );
+            self.state.pipelined.clear();
+            self.state.close_write();
         }
 
         self.state.busy();
         self.state.keep_alive &= msg.keep_alive;
         self.state.version = msg.head.version;
 
+        #[cfg(feature = "server")]
+        if let Some(ref mut left) = self.state.requests_left {
+            *left = left.saturating_sub(1);
+            if *left == 0 {
+                debug!("max requests reached, closing after this response");
+                self.state.disable_keep_alive();
+            }
+        }
+
+        #[cfg(feature = "server")]
+        {
+            self.state.body_bytes_read = 0;
+            if let (Some(max), Some(len)) =
+                (self.state.max_request_body_size, msg.decode.into_opt())
+            {
+                if len > max {
+                    debug!("request content-length {} over limit {}", len, max);
+                    self.close_read();
+                    return match self.on_parse_error(crate::Error::new_body_too_large()) {
+                        Ok(()) => Poll::Pending,
+                        Err(e) => Poll::Ready(Some(Err(e))),
+                    };
+                }
+            }
+        }
+
         let mut wants = if msg.wants_upgrade {
             Wants::UPGRADE
         } else {
@@ -331,19 +643,28 @@ where
                 self.try_keep_alive(cx);
             }
         } else if msg.expect_continue && msg.head.version.gt(&Version::HTTP_10) {
-            let h1_max_header_size = None; // TODO: remove this when we land h1_max_header_size support
+            #[cfg(feature = "server")]
+            if self.state.reject_expect_continue {
+                debug!("rejecting request expecting 100-continue");
+                self.close_read();
+                return match self.on_parse_error(crate::Error::new_expectation_failed()) {
+                    Ok(()) => Poll::Pending,
+                    Err(e) => Poll::Ready(Some(Err(e))),
+                };
+            }
             self.state.reading = Reading::Continue(Decoder::new(
                 msg.decode,
                 self.state.h1_max_headers,
-                h1_max_header_size,
+                self.state.h1_max_header_size,
+                self.state.chunk_extensions,
             ));
             wants = wants.add(Wants::EXPECT);
         } else {
-            let h1_max_header_size = None; // TODO: remove this when we land h1_max_header_size support
             self.state.reading = Reading::Body(Decoder::new(
                 msg.decode,
                 self.state.h1_max_headers,
-                h1_max_header_size,
+                self.state.h1_max_header_size,
+                self.state.chunk_extensions,
             ));
         }
 
@@ -354,6 +675,8 @@ where
             .map_or(false, |te_header| te_header == "trailers");
 
         self.set_first_byte_of_header(fbt);
+        self.received_head_bytes = head_len as u64;
+        self.received_chunk_extension_bytes = 0;
         Poll::Ready(Some(Ok((msg.head, msg.decode, wants))))
     }
 
@@ -391,11 +714,29 @@ where
 
         let (reading, ret, fbt) = match self.state.reading {
             Reading::Body(ref mut decoder) => {
-                match ready!(decoder.decode(cx, &mut self.io)) {
+                let decoded = ready!(decoder.decode(cx, &mut self.io));
+                self.received_chunk_extension_bytes = decoder.chunk_extension_bytes();
+                match decoded {
                     Ok(frame) => {
-                        let fbt = std::time::Instant::now();
+                        let fbt = if self.collect_stats {
+                            Some(self.state.clock.now())
+                        } else {
+                            None
+                        };
                         if frame.is_data() {
                             let slice = frame.data_ref().unwrap_or_else(|| unreachable!());
+                            #[cfg(feature = "server")]
+                            if let Some(max) = self.state.max_request_body_size {
+                                self.state.body_bytes_read += slice.len() as u64;
+                                if self.state.body_bytes_read > max {
+                                    debug!("request body over limit {}", max);
+                                    self.state.close_read();
+                                    return Poll::Ready(Some(Err(io::Error::new(
+                                        io::ErrorKind::InvalidData,
+                                        crate::Error::new_body_too_large(),
+                                    ))));
+                                }
+                            }
                             let (reading, maybe_frame) = if decoder.is_eof() {
                                 debug!("incoming body completed");
                                 (
@@ -413,15 +754,15 @@ where
                                 // an empty slice...
                                 (Reading::Closed, None)
                             } else {
-                                self.set_first_byte_of_body(Some(fbt));
+                                self.set_first_byte_of_body(fbt);
                                 return Poll::Ready(Some(Ok(frame)));
                             };
-                            (reading, Poll::Ready(maybe_frame), Some(fbt))
+                            (reading, Poll::Ready(maybe_frame), fbt)
                         } else if frame.is_trailers() {
-                            (Reading::Closed, Poll::Ready(Some(Ok(frame))), Some(fbt))
+                            (Reading::Closed, Poll::Ready(Some(Ok(frame))), fbt)
                         } else {
                             trace!("discarding unknown frame");
-                            (Reading::Closed, Poll::Ready(None), Some(fbt))
+                            (Reading::Closed, Poll::Ready(None), fbt)
                         }
                     }
                     Err(e) => {
@@ -498,6 +839,8 @@ where
                 Poll::Ready(Err(crate::Error::new_incomplete()))
             } else {
                 trace!("found EOF on idle connection, closing");
+                #[cfg(feature = "client")]
+                self.state.set_close_cause(CloseCause::Peer);
                 Poll::Ready(Ok(()))
             };
 
@@ -535,7 +878,7 @@ where
     fn force_io_read(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<usize>> {
         debug_assert!(!self.state.is_read_closed());
 
-        let result = ready!(self.io.poll_read_from_io(false, cx).1);
+        let result = ready!(self.io.poll_read_from_io(None, cx).1);
         Poll::Ready(result.map_err(|e| {
             trace!(error = %e, "force_io_read; io error");
             self.state.close();
@@ -563,11 +906,15 @@ where
 
         if !self.io.is_read_blocked() {
             if self.io.read_buf().is_empty() {
-                match self.io.poll_read_from_io(false, cx).1 {
+                match self.io.poll_read_from_io(None, cx).1 {
                     Poll::Ready(Ok(n)) => {
                         if n == 0 {
                             trace!("maybe_notify; read eof");
                             if self.state.is_idle() {
+                                #[cfg(feature = "client")]
+                                if T::is_client() {
+                                    self.state.set_close_cause(CloseCause::Peer);
+                                }
                                 self.state.close();
                             } else {
                                 self.close_read()
@@ -602,10 +949,96 @@ where
 
         match self.state.writing {
             Writing::Init => self.io.can_headers_buf(),
+            #[cfg(feature = "client")]
+            Writing::KeepAlive if self.can_pipeline() => self.io.can_headers_buf(),
             _ => false,
         }
     }
 
+    /// Whether a client can write another request before the response to
+    /// the previous one has been read.
+    #[cfg(feature = "client")]
+    fn can_pipeline(&self) -> bool {
+        !T::should_read_first()
+            && self.state.pipelining
+            && self.state.version == Version::HTTP_11
+            && matches!(self.state.keep_alive.status(), KA::Busy)
+            && matches!(self.state.reading, Reading::Init | Reading::Body(..))
+    }
+
+    /// Waits before writing the body of a request that sent
+    /// `Expect: 100-continue`, until the 100 Continue arrives or the
+    /// expect-continue timeout elapses.
+    #[cfg(feature = "client")]
+    pub(crate) fn poll_expect_continue(&mut self, cx: &mut Context<'_>) -> Poll<crate::Result<()>> {
+        let expect_continue_fut = match self.state.expect_continue_fut {
+            Some(ref mut fut) => fut,
+            None => return Poll::Ready(Ok(())),
+        };
+        ready!(Pin::new(expect_continue_fut).poll(cx));
+        self.state.expect_continue_fut = None;
+
+        if self.state.abort_on_expect_continue_timeout {
+            debug!("timed out waiting for 100 Continue");
+            self.state.close();
+            return Poll::Ready(Err(crate::Error::new_expect_continue_timeout()));
+        }
+        trace!("timed out waiting for 100 Continue, sending body");
+        Poll::Ready(Ok(()))
+    }
+
+    /// Resolves once the current request is past its deadline, closing the
+    /// connection, since the request can't be finished on it anymore.
+    #[cfg(feature = "client")]
+    pub(crate) fn poll_deadline(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        let deadline_fut = match self.state.deadline_fut {
+            Some(ref mut fut) => fut,
+            None => return Poll::Pending,
+        };
+        ready!(Pin::new(deadline_fut).poll(cx));
+        debug!("request deadline exceeded");
+        self.state.deadline_fut = None;
+        self.state.expect_continue_fut = None;
+        self.state.close();
+        Poll::Ready(())
+    }
+
+    /// Resolves with an error once the current request has been fully
+    /// written and its response headers haven't arrived in time, closing
+    /// the connection.
+    #[cfg(feature = "client")]
+    pub(crate) fn poll_response_header_timeout(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<crate::Error> {
+        if self.state.response_header_timeout_fut.is_none() {
+            // The wait starts once the request is fully written, which it
+            // is if another was pipelined after it.
+            let written = matches!(self.state.writing, Writing::KeepAlive | Writing::Closed)
+                || !self.state.pipelined.is_empty();
+            if !written || !matches!(self.state.reading, Reading::Init) {
+                return Poll::Pending;
+            }
+            let timeout = match self.state.pending_response_header_timeout.take() {
+                Some(timeout) => timeout,
+                None => return Poll::Pending,
+            };
+            trace!("waiting up to {:?} for response headers", timeout);
+            let deadline = Instant::now() + timeout;
+            self.state.response_header_timeout_fut = Some(self.state.timer.sleep_until(deadline));
+        }
+        let fut = self
+            .state
+            .response_header_timeout_fut
+            .as_mut()
+            .expect("just set");
+        ready!(Pin::new(fut).poll(cx));
+        debug!("response header timeout exceeded");
+        self.state.response_header_timeout_fut = None;
+        self.state.close();
+        Poll::Ready(crate::Error::new_response_header_timeout())
+    }
+
     pub(crate) fn can_write_body(&self) -> bool {
         match self.state.writing {
             Writing::Body(..) => true,
@@ -629,6 +1062,31 @@ where
         }
     }
 
+    /// Writes a 1xx informational response, ahead of the response still
+    /// to be written.
+    pub(crate) fn write_informational(&mut self, res: http::Response<()>) {
+        debug_assert!(matches!(self.state.writing, Writing::Init));
+        trace!("writing informational response: {}", res.status());
+        let title_case_headers = self.state.title_case_headers;
+        let buf = self.io.headers_buf();
+        buf.extend_from_slice(b"HTTP/1.1 ");
+        buf.extend_from_slice(res.status().as_str().as_bytes());
+        buf.extend_from_slice(b" ");
+        buf.extend_from_slice(
+            res.status()
+                .canonical_reason()
+                .unwrap_or("<none>")
+                .as_bytes(),
+        );
+        buf.extend_from_slice(b"\r\n");
+        if title_case_headers {
+            super::role::write_headers_title_case(res.headers(), buf);
+        } else {
+            super::role::write_headers(res.headers(), buf);
+        }
+        buf.extend_from_slice(b"\r\n");
+    }
+
     fn encode_head(
         &mut self,
         mut head: MessageHead<T::Outgoing>,
@@ -636,20 +1094,83 @@ where
     ) -> Option<Encoder> {
         debug_assert!(self.can_write_head());
 
+        // Writing a request while the previous one is still being answered.
+        #[cfg(feature = "client")]
+        let pipelined = matches!(self.state.writing, Writing::KeepAlive);
+        #[cfg(feature = "client")]
+        if pipelined {
+            // The previous request is fully written, so its sizes are final.
+            let request_bytes = Some((self.sent_head_bytes, self.sent_body_bytes));
+            match self.state.pipelined.back_mut() {
+                Some(prev) => prev.request_bytes = request_bytes,
+                None => self.state.request_bytes = request_bytes,
+            }
+        }
+        #[cfg(not(feature = "client"))]
+        let pipelined = false;
+
         if !T::should_read_first() {
             self.state.busy();
+            #[cfg(feature = "client")]
+            {
+                let idle_since = self.state.idle_since.take();
+                if self.collect_stats {
+                    self.idle_before_reuse = idle_since.map(|since| self.state.clock.elapsed(since));
+                }
+            }
         }
 
         self.enforce_version(&mut head);
 
+        // The cached system date is written by the encoder, but a provider's
+        // goes in as a header.
+        #[cfg(feature = "server")]
+        if T::is_server() && self.state.date_header && !head.headers.contains_key(DATE) {
+            if let Some(date) = self.state.date_source.custom() {
+                head.headers.insert(DATE, date);
+            }
+        }
+
+        #[cfg(feature = "client")]
+        if let (Some(min_body_size), Some(&BodyLength::Known(len))) =
+            (self.state.auto_expect_continue, body.as_ref())
+        {
+            if !T::should_read_first()
+                && !pipelined
+                && head.version == Version::HTTP_11
+                && len >= min_body_size
+                && !head.headers.contains_key(EXPECT)
+            {
+                head.headers
+                    .insert(EXPECT, HeaderValue::from_static("100-continue"));
+            }
+        }
+
+        #[cfg(feature = "client")]
+        let expects_continue = !T::should_read_first()
+            && head.version == Version::HTTP_11
+            && head
+                .headers
+                .get(EXPECT)
+                .map_or(false, |v| v.as_bytes().eq_ignore_ascii_case(b"100-continue"));
+
+        #[cfg(feature = "server")]
+        let keep_alive = self.state.wants_keep_alive();
+        let mut pipelined_method = None;
+        let req_method = if pipelined {
+            &mut pipelined_method
+        } else {
+            &mut self.state.method
+        };
         let buf = self.io.headers_buf();
+        let buffered = buf.len();
         match super::role::encode_headers::<T>(
             Encode {
                 head: &mut head,
                 body,
                 #[cfg(feature = "server")]
-                keep_alive: self.state.wants_keep_alive(),
-                req_method: &mut self.state.method,
+                keep_alive,
+                req_method,
                 title_case_headers: self.state.title_case_headers,
                 #[cfg(feature = "server")]
                 date_header: self.state.date_header,
@@ -657,14 +1178,54 @@ where
             buf,
         ) {
             Ok(encoder) => {
-                debug_assert!(self.state.cached_headers.is_none());
+                // An earlier response may not have reused the cached map yet,
+                // if this request was pipelined.
+                debug_assert!(self.state.cached_headers.is_none() || pipelined);
                 debug_assert!(head.headers.is_empty());
-                self.state.cached_headers = Some(head.headers);
+                if self.state.cached_headers.is_none() {
+                    self.state.cached_headers = Some(head.headers);
+                }
+                self.sent_head_bytes = (self.io.headers_buf().len() - buffered) as u64;
+                self.sent_body_bytes = 0;
 
                 #[cfg(feature = "client")]
                 {
-                    self.state.on_informational =
+                    let on_informational =
                         head.extensions.remove::<crate::ext::OnInformational>();
+                    let deadline_fut = head
+                        .extensions
+                        .remove::<Deadline>()
+                        .map(|deadline| self.state.timer.sleep_until(deadline.0));
+                    let response_header_timeout = head
+                        .extensions
+                        .remove::<crate::ext::ResponseHeaderTimeout>()
+                        .map_or(self.state.response_header_timeout, |timeout| timeout.0);
+                    if pipelined {
+                        trace!("pipelining request, {} ahead", self.state.pipelined.len() + 1);
+                        self.state.pipelined.push_back(Pipelined {
+                            method: pipelined_method,
+                            on_informational,
+                            deadline_fut,
+                            response_header_timeout,
+                            request_bytes: None,
+                        });
+                    } else {
+                        self.state.on_informational = on_informational;
+                        self.state.deadline_fut = deadline_fut;
+                        self.state.pending_response_header_timeout = response_header_timeout;
+                    }
+                }
+
+                // A pipelined request can't wait for a 100 Continue, since
+                // earlier responses are still in the way.
+                #[cfg(feature = "client")]
+                if let Some(timeout) = self.state.expect_continue_timeout {
+                    if expects_continue && !pipelined && !encoder.is_eof() {
+                        trace!("waiting up to {:?} for 100 Continue", timeout);
+                        let deadline = Instant::now() + timeout;
+                        self.state.expect_continue_fut =
+                            Some(self.state.timer.sleep_until(deadline));
+                    }
                 }
 
                 Some(encoder)
@@ -731,6 +1292,7 @@ where
         // empty chunks should be discarded at Dispatcher level
         debug_assert!(chunk.remaining() != 0);
 
+        self.sent_body_bytes += chunk.remaining() as u64;
         let state = match self.state.writing {
             Writing::Body(ref mut encoder) => {
                 self.io.buffer(encoder.encode(chunk));
@@ -776,11 +1338,49 @@ where
         }
     }
 
+    /// Whether a file body can be written straight to the transport,
+    /// instead of being read into chunks.
+    #[cfg(all(feature = "sendfile", unix))]
+    pub(crate) fn can_write_file(&self) -> bool {
+        match self.state.writing {
+            Writing::Body(ref encoder) => !encoder.is_chunked() && self.io.is_write_file(),
+            _ => false,
+        }
+    }
+
+    #[cfg(all(feature = "sendfile", unix))]
+    pub(crate) fn poll_write_file(
+        &mut self,
+        cx: &mut Context<'_>,
+        file: &mut crate::body::FileRegion,
+    ) -> Poll<io::Result<()>> {
+        debug_assert!(self.can_write_file());
+        let encoder = match self.state.writing {
+            Writing::Body(ref mut encoder) => encoder,
+            _ => unreachable!("poll_write_file invalid state: {:?}", self.state.writing),
+        };
+        if let Some(max) = encoder.remaining_len() {
+            file.truncate(max);
+        }
+
+        while !file.is_empty() {
+            let n = ready!(self.io.poll_write_file(cx, file))? as u64;
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            file.advance(n);
+            self.sent_body_bytes += n;
+            encoder.written_directly(n);
+        }
+        Poll::Ready(Ok(()))
+    }
+
     pub(crate) fn write_body_and_end(&mut self, chunk: B) {
         debug_assert!(self.can_write_body() && self.can_buffer_body());
         // empty chunks should be discarded at Dispatcher level
         debug_assert!(chunk.remaining() != 0);
 
+        self.sent_body_bytes += chunk.remaining() as u64;
         let state = match self.state.writing {
             Writing::Body(ref encoder) => {
                 let can_keep_alive = encoder.encode_and_end(chunk, self.io.write_buf());
@@ -836,6 +1436,25 @@ where
             if self.has_h2_prefix() {
                 return Err(crate::Error::new_version_h2());
             }
+            #[cfg(feature = "server")]
+            if let Some(ref responder) = self.state.error_responder {
+                if let Some((msg, body)) = T::on_error_with(&err, responder) {
+                    self.state.cached_headers.take();
+                    let len = BodyLength::Known(body.len() as u64);
+                    if let Some(encoder) = self.encode_head(msg, Some(len)) {
+                        if !encoder.is_eof() {
+                            self.io.headers_buf().extend_from_slice(&body);
+                        }
+                        self.state.writing = if encoder.is_last() {
+                            Writing::Closed
+                        } else {
+                            Writing::KeepAlive
+                        };
+                    }
+                    self.state.error = Some(err);
+                    return Ok(());
+                }
+            }
             if let Some(msg) = T::on_error(&err) {
                 // Drop the cached headers so as to not trigger a debug
                 // assert in `write_head`...
@@ -897,7 +1516,6 @@ where
         self.state.close_write();
     }
 
-    #[cfg(feature = "server")]
     pub(crate) fn disable_keep_alive(&mut self) {
         if self.state.is_idle() {
             trace!("disable_keep_alive; closing idle connection");
@@ -950,15 +1568,41 @@ struct State {
     method: Option<Method>,
     h1_parser_config: ParserConfig,
     h1_max_headers: Option<usize>,
+    /// The most bytes a message head may take, including any trailers.
+    h1_max_header_size: Option<usize>,
+    /// How the chunk extensions of incoming bodies are limited.
+    chunk_extensions: ChunkExtensionConfig,
     #[cfg(feature = "server")]
     h1_header_read_timeout: Option<Duration>,
     #[cfg(feature = "server")]
     h1_header_read_timeout_fut: Option<Pin<Box<dyn Sleep>>>,
     #[cfg(feature = "server")]
     h1_header_read_timeout_running: bool,
+    /// How long a connection may sit idle between requests.
+    #[cfg(feature = "server")]
+    keep_alive_timeout: Option<Duration>,
+    /// Set while an idle connection waits for the next request.
+    #[cfg(feature = "server")]
+    keep_alive_timeout_fut: Option<Pin<Box<dyn Sleep>>>,
+    /// Whether the connection was closed for being idle too long.
+    #[cfg(feature = "server")]
+    keep_alive_timed_out: bool,
     #[cfg(feature = "server")]
     date_header: bool,
     #[cfg(feature = "server")]
+    date_source: DateSource,
+    /// Requests that may still be read before the connection closes.
+    #[cfg(feature = "server")]
+    requests_left: Option<usize>,
+    /// Answer `Expect: 100-continue` with 417 instead of reading the body.
+    #[cfg(feature = "server")]
+    reject_expect_continue: bool,
+    /// The largest request body that may be read.
+    #[cfg(feature = "server")]
+    max_request_body_size: Option<u64>,
+    /// Bytes of the current request body read so far.
+    #[cfg(feature = "server")]
+    body_bytes_read: u64,
     timer: Time,
     preserve_header_case: bool,
     #[cfg(feature = "ffi")]
@@ -970,6 +1614,69 @@ struct State {
     /// received.
     #[cfg(feature = "client")]
     on_informational: Option<crate::ext::OnInformational>,
+    /// When a client connection last went idle, waiting for the next
+    /// request.
+    #[cfg(feature = "client")]
+    idle_since: Option<std::time::Instant>,
+    /// How long a client request with `Expect: 100-continue` holds its body
+    /// back, waiting for a 100 Continue.
+    #[cfg(feature = "client")]
+    expect_continue_timeout: Option<Duration>,
+    /// Fail the request instead of sending the body once the
+    /// expect-continue timeout elapses.
+    #[cfg(feature = "client")]
+    abort_on_expect_continue_timeout: bool,
+    /// Requests with bodies of at least this many bytes get an
+    /// `Expect: 100-continue` header.
+    #[cfg(feature = "client")]
+    auto_expect_continue: Option<u64>,
+    /// Set while the current request's body is held back.
+    #[cfg(feature = "client")]
+    expect_continue_fut: Option<Pin<Box<dyn Sleep>>>,
+    /// Set by the parser when a 100 Continue arrives.
+    #[cfg(feature = "client")]
+    continue_received: bool,
+    /// Whether responses carry their header lines as received.
+    #[cfg(feature = "client")]
+    raw_headers: bool,
+    /// Whether requests with a target that isn't a valid URI are served.
+    #[cfg(feature = "server")]
+    allow_invalid_request_target: bool,
+    /// How requests with conflicting framing headers are handled.
+    #[cfg(feature = "server")]
+    conflicting_framing: crate::server::conn::http1::ConflictingFraming,
+    /// Builds the response to a request that failed to parse.
+    #[cfg(feature = "server")]
+    error_responder: Option<super::ErrorResponder>,
+    /// Fires when the current request is past its deadline.
+    #[cfg(feature = "client")]
+    deadline_fut: Option<Pin<Box<dyn Sleep>>>,
+    /// How long a client request waits for response headers once it has
+    /// been fully written, unless the request overrides it.
+    #[cfg(feature = "client")]
+    response_header_timeout: Option<Duration>,
+    /// The response header timeout of the request being written, started
+    /// once it has been fully written.
+    #[cfg(feature = "client")]
+    pending_response_header_timeout: Option<Duration>,
+    /// Fires when the response headers took too long to arrive.
+    #[cfg(feature = "client")]
+    response_header_timeout_fut: Option<Pin<Box<dyn Sleep>>>,
+    /// Why a client connection stopped being reusable, if it was anything
+    /// other than the user dropping it.
+    #[cfg(feature = "client")]
+    close_cause: Option<CloseCause>,
+    /// Whether a client may write requests before earlier responses arrive.
+    #[cfg(feature = "client")]
+    pipelining: bool,
+    /// Requests written after the one whose response is being read, oldest
+    /// first.
+    #[cfg(feature = "client")]
+    pipelined: VecDeque<Pipelined>,
+    /// Head and body sizes of the request whose response is being read,
+    /// once another request has been pipelined after it.
+    #[cfg(feature = "client")]
+    request_bytes: Option<(u64, u64)>,
     /// Set to true when the Dispatcher should poll read operations
     /// again. See the `maybe_notify` method for more.
     notify_read: bool,
@@ -983,6 +1690,31 @@ struct State {
     version: Version,
     /// Flag to track if trailer fields are allowed to be sent
     allow_trailer_fields: bool,
+    /// Where stats timestamps are read from.
+    clock: Clock,
+}
+
+/// What's needed to read the response to a pipelined request, set aside
+/// until the responses before it have been read.
+#[cfg(feature = "client")]
+struct Pipelined {
+    method: Option<Method>,
+    on_informational: Option<crate::ext::OnInformational>,
+    deadline_fut: Option<Pin<Box<dyn Sleep>>>,
+    response_header_timeout: Option<Duration>,
+    request_bytes: Option<(u64, u64)>,
+}
+
+/// The first thing that ended a client connection.
+#[cfg(feature = "client")]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum CloseCause {
+    /// The peer closed an idle connection.
+    Peer,
+    /// A message exchange finished with keep-alive disabled.
+    KeepAliveDisabled,
+    /// An error was handed to the in-flight request.
+    Error,
 }
 
 #[derive(Debug)]
@@ -1095,6 +1827,23 @@ impl State {
     }
 
     fn try_keep_alive<T: Http1Transaction>(&mut self) {
+        // A response was read, but more were pipelined behind it.
+        #[cfg(feature = "client")]
+        if matches!(self.reading, Reading::KeepAlive) {
+            if let Some(next) = self.pipelined.pop_front() {
+                trace!("try_keep_alive({}): reading next pipelined response", T::LOG);
+                self.method = next.method;
+                self.on_informational = next.on_informational;
+                self.deadline_fut = next.deadline_fut;
+                self.pending_response_header_timeout = next.response_header_timeout;
+                self.response_header_timeout_fut = None;
+                self.request_bytes = next.request_bytes;
+                self.reading = Reading::Init;
+                self.notify_read = true;
+                return;
+            }
+        }
+
         match (&self.reading, &self.writing) {
             (&Reading::KeepAlive, &Writing::KeepAlive) => {
                 if let KA::Busy = self.keep_alive.status() {
@@ -1105,6 +1854,10 @@ impl State {
                         T::LOG,
                         self.keep_alive
                     );
+                    #[cfg(feature = "client")]
+                    if T::is_client() {
+                        self.set_close_cause(CloseCause::KeepAliveDisabled);
+                    }
                     self.close();
                 }
             }
@@ -1119,6 +1872,13 @@ impl State {
         self.keep_alive.disable()
     }
 
+    #[cfg(feature = "client")]
+    fn set_close_cause(&mut self, cause: CloseCause) {
+        if self.close_cause.is_none() {
+            self.close_cause = Some(cause);
+        }
+    }
+
     fn busy(&mut self) {
         if let KA::Disabled = self.keep_alive.status() {
             return;
@@ -1147,10 +1907,18 @@ impl State {
         // pending requests stream.
         if !T::should_read_first() {
             self.notify_read = true;
+            #[cfg(feature = "client")]
+            {
+                self.idle_since = Some(self.clock.now());
+                self.deadline_fut = None;
+                self.pending_response_header_timeout = None;
+                self.response_header_timeout_fut = None;
+                self.request_bytes = None;
+            }
         }
 
         #[cfg(feature = "server")]
-        if self.h1_header_read_timeout.is_some() {
+        if self.h1_header_read_timeout.is_some() || self.keep_alive_timeout.is_some() {
             // Next read will start and poll the header read timeout,
             // so we can close the connection if another header isn't
             // received in a timely manner.
@@ -16,12 +16,12 @@ use self::Kind::{Chunked, Eof, Length};
 
 /// Maximum amount of bytes allowed in chunked extensions.
 ///
-/// This limit is currentlty applied for the entire body, not per chunk.
+/// This limit is applied for the entire body. A limit per chunk can be
+/// configured with [`ChunkExtensionConfig`].
 const CHUNKED_EXTENSIONS_LIMIT: u64 = 1024 * 16;
 
-/// Maximum number of bytes allowed for all trailer fields.
-///
-/// TODO: remove this when we land h1_max_header_size support
+/// Maximum number of bytes allowed for all trailer fields, unless a max
+/// header size is configured.
 const TRAILER_LIMIT: usize = 1024 * 16;
 
 /// Decoders to handle different Transfer-Encodings.
@@ -41,7 +41,7 @@ enum Kind {
     Chunked {
         state: ChunkedState,
         chunk_len: u64,
-        extensions_cnt: u64,
+        extensions: ChunkExtensions,
         trailers_buf: Option<BytesMut>,
         trailers_cnt: usize,
         h1_max_headers: Option<usize>,
@@ -66,6 +66,35 @@ enum Kind {
     Eof(bool),
 }
 
+/// How the chunk extensions of a chunked body are limited.
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub(crate) struct ChunkExtensionConfig {
+    /// Maximum amount of bytes allowed in the extensions of a single chunk.
+    pub(crate) max_size: Option<u64>,
+    /// Whether any chunk extension is an error.
+    pub(crate) reject: bool,
+}
+
+/// Counts the bytes of chunk extensions read.
+#[derive(Debug, PartialEq, Clone)]
+struct ChunkExtensions {
+    config: ChunkExtensionConfig,
+    /// Bytes read for the whole body.
+    body_cnt: u64,
+    /// Bytes read for the current chunk.
+    chunk_cnt: u64,
+}
+
+impl ChunkExtensions {
+    fn new(config: ChunkExtensionConfig) -> Self {
+        ChunkExtensions {
+            config,
+            body_cnt: 0,
+            chunk_cnt: 0,
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Clone, Copy)]
 enum ChunkedState {
     Start,
@@ -92,15 +121,28 @@ impl Decoder {
         }
     }
 
+    #[cfg(test)]
     pub(crate) fn chunked(
         h1_max_headers: Option<usize>,
         h1_max_header_size: Option<usize>,
+    ) -> Decoder {
+        Decoder::chunked_with_extensions(
+            h1_max_headers,
+            h1_max_header_size,
+            ChunkExtensionConfig::default(),
+        )
+    }
+
+    fn chunked_with_extensions(
+        h1_max_headers: Option<usize>,
+        h1_max_header_size: Option<usize>,
+        extensions: ChunkExtensionConfig,
     ) -> Decoder {
         Decoder {
             kind: Kind::Chunked {
                 state: ChunkedState::new(),
                 chunk_len: 0,
-                extensions_cnt: 0,
+                extensions: ChunkExtensions::new(extensions),
                 trailers_buf: None,
                 trailers_cnt: 0,
                 h1_max_headers,
@@ -119,9 +161,12 @@ impl Decoder {
         len: DecodedLength,
         h1_max_headers: Option<usize>,
         h1_max_header_size: Option<usize>,
+        chunk_extensions: ChunkExtensionConfig,
     ) -> Self {
         match len {
-            DecodedLength::CHUNKED => Decoder::chunked(h1_max_headers, h1_max_header_size),
+            DecodedLength::CHUNKED => {
+                Decoder::chunked_with_extensions(h1_max_headers, h1_max_header_size, chunk_extensions)
+            }
             DecodedLength::CLOSE_DELIMITED => Decoder::eof(),
             length => Decoder::length(length.danger_len()),
         }
@@ -129,6 +174,14 @@ impl Decoder {
 
     // methods
 
+    /// Returns how many bytes of chunk extensions have been read.
+    pub(crate) fn chunk_extension_bytes(&self) -> u64 {
+        match self.kind {
+            Chunked { ref extensions, .. } => extensions.body_cnt,
+            _ => 0,
+        }
+    }
+
     pub(crate) fn is_eof(&self) -> bool {
         matches!(
             self.kind,
@@ -171,7 +224,7 @@ impl Decoder {
             Chunked {
                 ref mut state,
                 ref mut chunk_len,
-                ref mut extensions_cnt,
+                ref mut extensions,
                 ref mut trailers_buf,
                 ref mut trailers_cnt,
                 ref h1_max_headers,
@@ -186,7 +239,7 @@ impl Decoder {
                         cx,
                         body,
                         chunk_len,
-                        extensions_cnt,
+                        extensions,
                         &mut buf,
                         trailers_buf,
                         trailers_cnt,
@@ -300,7 +353,7 @@ impl ChunkedState {
         cx: &mut Context<'_>,
         body: &mut R,
         size: &mut u64,
-        extensions_cnt: &mut u64,
+        extensions: &mut ChunkExtensions,
         buf: &mut Option<Bytes>,
         trailers_buf: &mut Option<BytesMut>,
         trailers_cnt: &mut usize,
@@ -309,10 +362,13 @@ impl ChunkedState {
     ) -> Poll<Result<ChunkedState, io::Error>> {
         use self::ChunkedState::*;
         match *self {
-            Start => ChunkedState::read_start(cx, body, size),
+            Start => {
+                extensions.chunk_cnt = 0;
+                ChunkedState::read_start(cx, body, size)
+            }
             Size => ChunkedState::read_size(cx, body, size),
             SizeLws => ChunkedState::read_size_lws(cx, body),
-            Extension => ChunkedState::read_extension(cx, body, extensions_cnt),
+            Extension => ChunkedState::read_extension(cx, body, extensions),
             SizeLf => ChunkedState::read_size_lf(cx, body, *size),
             Body => ChunkedState::read_body(cx, body, size, buf),
             BodyCr => ChunkedState::read_body_cr(cx, body),
@@ -416,9 +472,15 @@ impl ChunkedState {
     fn read_extension<R: MemRead>(
         cx: &mut Context<'_>,
         rdr: &mut R,
-        extensions_cnt: &mut u64,
+        extensions: &mut ChunkExtensions,
     ) -> Poll<Result<ChunkedState, io::Error>> {
         trace!("read_extension");
+        if extensions.config.reject {
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "chunk extensions not allowed",
+            )));
+        }
         // We don't care about extensions really at all. Just ignore them.
         // They "end" at the next CRLF.
         //
@@ -432,8 +494,13 @@ impl ChunkedState {
                 "invalid chunk extension contains newline",
             ))),
             _ => {
-                *extensions_cnt += 1;
-                if *extensions_cnt >= CHUNKED_EXTENSIONS_LIMIT {
+                extensions.body_cnt += 1;
+                extensions.chunk_cnt += 1;
+                let over_chunk_limit = extensions
+                    .config
+                    .max_size
+                    .map_or(false, |max| extensions.chunk_cnt > max);
+                if extensions.body_cnt >= CHUNKED_EXTENSIONS_LIMIT || over_chunk_limit {
                     Poll::Ready(Err(io::Error::new(
                         io::ErrorKind::InvalidData,
                         "chunk extensions over limit",
@@ -743,7 +810,7 @@ mod tests {
             let mut state = ChunkedState::new();
             let rdr = &mut s.as_bytes();
             let mut size = 0;
-            let mut ext_cnt = 0;
+            let mut extensions = ChunkExtensions::new(ChunkExtensionConfig::default());
             let mut trailers_cnt = 0;
             loop {
                 let result = futures_util::future::poll_fn(|cx| {
@@ -751,7 +818,7 @@ mod tests {
                         cx,
                         rdr,
                         &mut size,
-                        &mut ext_cnt,
+                        &mut extensions,
                         &mut None,
                         &mut None,
                         &mut trailers_cnt,
@@ -773,7 +840,7 @@ mod tests {
             let mut state = ChunkedState::new();
             let rdr = &mut s.as_bytes();
             let mut size = 0;
-            let mut ext_cnt = 0;
+            let mut extensions = ChunkExtensions::new(ChunkExtensionConfig::default());
             let mut trailers_cnt = 0;
             loop {
                 let result = futures_util::future::poll_fn(|cx| {
@@ -781,7 +848,7 @@ mod tests {
                         cx,
                         rdr,
                         &mut size,
-                        &mut ext_cnt,
+                        &mut extensions,
                         &mut None,
                         &mut None,
                         &mut trailers_cnt,
@@ -953,6 +1020,64 @@ mod tests {
         assert_eq!(err.to_string(), "chunk extensions over limit");
     }
 
+    #[tokio::test]
+    async fn test_read_chunked_extensions_over_chunk_limit() {
+        let config = ChunkExtensionConfig {
+            max_size: Some(4),
+            reject: false,
+        };
+
+        // each chunk may have up to the limit
+        let mut mock_buf = &b"1;abcd\r\nA\r\n1;efgh\r\nB\r\n0\r\n\r\n"[..];
+        let mut decoder = Decoder::chunked_with_extensions(None, None, config);
+        for expected in ["A", "B"] {
+            let buf = decoder
+                .decode_fut(&mut mock_buf)
+                .await
+                .expect("decode")
+                .into_data()
+                .expect("unknown frame type");
+            assert_eq!(buf, expected);
+        }
+        assert_eq!(decoder.chunk_extension_bytes(), 8);
+
+        let mut mock_buf = &b"1;abcde\r\nA\r\n0\r\n\r\n"[..];
+        let mut decoder = Decoder::chunked_with_extensions(None, None, config);
+        let err = decoder
+            .decode_fut(&mut mock_buf)
+            .await
+            .expect_err("decode");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "chunk extensions over limit");
+    }
+
+    #[tokio::test]
+    async fn test_read_chunked_extensions_rejected() {
+        let config = ChunkExtensionConfig {
+            max_size: None,
+            reject: true,
+        };
+
+        let mut mock_buf = &b"1\r\nA\r\n0\r\n\r\n"[..];
+        let mut decoder = Decoder::chunked_with_extensions(None, None, config);
+        let buf = decoder
+            .decode_fut(&mut mock_buf)
+            .await
+            .expect("decode")
+            .into_data()
+            .expect("unknown frame type");
+        assert_eq!(buf, "A");
+
+        let mut mock_buf = &b"1;\r\nA\r\n0\r\n\r\n"[..];
+        let mut decoder = Decoder::chunked_with_extensions(None, None, config);
+        let err = decoder
+            .decode_fut(&mut mock_buf)
+            .await
+            .expect_err("decode");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "chunk extensions not allowed");
+    }
+
     #[cfg(not(miri))]
     #[tokio::test]
     async fn test_read_chunked_trailer_with_missing_lf() {
@@ -18,6 +18,7 @@ use super::{Http1Transaction, Wants};
 use crate::body::{Body, DecodedLength, Incoming as IncomingBody};
 #[cfg(feature = "client")]
 use crate::client::dispatch::TrySendError;
+use crate::common::stats::{Clock, OnStats};
 use crate::common::task;
 use crate::proto::{BodyLength, Conn, Dispatched, MessageHead, RequestHead};
 use crate::upgrade::OnUpgrade;
@@ -25,8 +26,12 @@ use crate::upgrade::OnUpgrade;
 pub(crate) struct Dispatcher<D, Bs: Body, I, T> {
     conn: Conn<I, Bs::Data, T>,
     dispatch: D,
-    body_tx: Option<crate::body::Sender>,
+    body_tx: Option<crate::body::IncomingSender>,
     body_rx: Pin<Box<Option<Bs>>>,
+    /// The region of a file body, while it's written straight to the
+    /// transport.
+    #[cfg(all(feature = "sendfile", unix))]
+    file_rx: Option<crate::body::FileRegion>,
     is_closing: bool,
 }
 
@@ -45,30 +50,106 @@ pub(crate) trait Dispatch {
     ) -> crate::Result<()>;
     fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), ()>>;
     fn should_poll(&self) -> bool;
+    fn recv_body_end(&mut self) {}
+    /// Receives how many bytes of chunk extensions the current body has
+    /// had so far.
+    fn recv_chunk_extensions(&mut self, _bytes: u64) {}
+    fn flushed(&mut self, _msg_done: bool) {}
+    /// Polls for a 1xx response to write before the next message.
+    fn poll_informational(&mut self, _cx: &mut Context<'_>) -> Poll<Option<http::Response<()>>> {
+        Poll::Ready(None)
+    }
 }
 
 cfg_server! {
+    use futures_channel::mpsc;
+    use futures_core::Stream;
+
+    use crate::ext::SendInformational;
     use crate::service::HttpService;
+    use crate::ServerRequestStats;
 
     pub(crate) struct Server<S: HttpService<B>, B> {
         in_flight: Pin<Box<Option<S::Future>>>,
+        informational: Option<mpsc::UnboundedReceiver<http::Response<()>>>,
         pub(crate) service: S,
+        record_stats: bool,
+        on_stats: Option<OnStats<ServerRequestStats>>,
+        stats: Option<ServerRequestStats>,
+        clock: Clock,
+        /// Inserted into the extensions of every request.
+        connection_info: Option<http::Extensions>,
     }
 }
 
 cfg_client! {
     pin_project_lite::pin_project! {
         pub(crate) struct Client<B> {
-            callback: Option<crate::client::dispatch::Callback<Request<B>, (HttpConnectionStats, http::Response<IncomingBody>)>>,
+            in_flight: std::collections::VecDeque<InFlight<B>>,
+            max_pipelined: usize,
             #[pin]
             rx: ClientRx<B>,
             rx_closed: bool,
+            on_stats: Option<OnStats>,
+            observer: Option<crate::common::stats::Observer>,
+            connection_id: u64,
+            request_count: u64,
+            collect_stats: bool,
+            tunnel_start: Option<std::time::Instant>,
+            clock: Clock,
         }
+
+        impl<B> PinnedDrop for Client<B> {
+            fn drop(this: Pin<&mut Self>) {
+                let this = this.project();
+                // A request that wasn't pipelined is left to the callback's
+                // own error.
+                this.in_flight.retain(|req| req.pipelined);
+                cancel_pipelined(this.in_flight);
+                // Requests still queued were never sent, so they're handed
+                // back, with what is known of the connection they failed on.
+                let rx = this.rx.get_mut();
+                rx.close();
+                while let Some((req, cb)) = rx.try_recv() {
+                    cb.send(Err(TrySendError {
+                        error: crate::Error::new_canceled()
+                            .with("connection closed")
+                            .with_stats(queued_stats(*this.connection_id)),
+                        message: Some(req),
+                    }));
+                }
+            }
+        }
+    }
+
+    /// A request that has been picked up, waiting for its response.
+    struct InFlight<B> {
+        callback: crate::client::dispatch::Callback<Request<B>, (HttpConnectionStats, http::Response<IncomingBody>)>,
+        poll_start: Option<std::time::Instant>,
+        checkout_start: Option<std::time::Instant>,
+        headers_written: Option<std::time::Instant>,
+        body_written: Option<std::time::Instant>,
+        is_connect: bool,
+        // Picked up while earlier requests were still in flight.
+        pipelined: bool,
+        // CONNECT and upgrade requests take over the connection, so nothing
+        // can be pipelined after them.
+        ends_pipeline: bool,
+        retries: Vec<crate::RetryStats>,
     }
 
     type ClientRx<B> = crate::client::dispatch::Receiver<Request<B>, (HttpConnectionStats, http::Response<IncomingBody>)>;
 }
 
+/// Takes the region a `FileBody` offers in its first frame, to write it
+/// without reading it.
+#[cfg(all(feature = "sendfile", unix))]
+fn take_file_region<D: 'static>(chunk: &mut D) -> Option<crate::body::FileRegion> {
+    (chunk as &mut dyn std::any::Any)
+        .downcast_mut::<crate::body::FileData>()
+        .and_then(crate::body::FileData::take_region)
+}
+
 impl<D, Bs, I, T> Dispatcher<D, Bs, I, T>
 where
     D: Dispatch<
@@ -88,11 +169,12 @@ where
             dispatch,
             body_tx: None,
             body_rx: Box::pin(None),
+            #[cfg(all(feature = "sendfile", unix))]
+            file_rx: None,
             is_closing: false,
         }
     }
 
-    #[cfg(feature = "server")]
     pub(crate) fn disable_keep_alive(&mut self) {
         self.conn.disable_keep_alive();
 
@@ -104,6 +186,16 @@ where
         }
     }
 
+    #[cfg(feature = "server")]
+    pub(crate) fn keep_alive_timed_out(&self) -> bool {
+        self.conn.keep_alive_timed_out()
+    }
+
+    #[cfg(feature = "client")]
+    pub(crate) fn close_cause(&self) -> Option<super::CloseCause> {
+        self.conn.close_cause()
+    }
+
     pub(crate) fn into_inner(self) -> (I, Bytes, D) {
         let (io, buf) = self.conn.into_inner();
         (io, buf, self.dispatch)
@@ -140,6 +232,8 @@ where
             // and close the connection with an Ok. If we
             // cannot give it to the user, then return the Err.
             self.dispatch.recv_msg(Err(e))?;
+            #[cfg(feature = "client")]
+            self.conn.set_close_cause(super::CloseCause::Error);
             Ok(Dispatched::Shutdown)
         }))
     }
@@ -153,6 +247,37 @@ where
 
         ready!(self.poll_loop(cx))?;
 
+        // Checked after the loop, since writing the request head is what
+        // starts the deadline.
+        #[cfg(feature = "client")]
+        if !self.is_done() {
+            let awaiting_head = self.conn.can_read_head();
+            if self.conn.poll_deadline(cx).is_ready() {
+                if let Some(mut body) = self.body_tx.take() {
+                    body.send_error(crate::Error::new_deadline_exceeded());
+                }
+                self.body_rx.set(None);
+                self.close();
+                // Still waiting on the response head, so the request fails.
+                if awaiting_head {
+                    let stats = self.conn.http_connection_stats();
+                    return Poll::Ready(Err(
+                        crate::Error::new_deadline_exceeded().with_stats(stats)
+                    ));
+                }
+            }
+        }
+
+        #[cfg(feature = "client")]
+        if !self.is_done() {
+            if let Poll::Ready(err) = self.conn.poll_response_header_timeout(cx) {
+                self.body_rx.set(None);
+                self.close();
+                let stats = self.conn.http_connection_stats();
+                return Poll::Ready(Err(err.with_stats(stats)));
+            }
+        }
+
         if self.is_done() {
             if let Some(pending) = self.conn.pending_upgrade() {
                 self.conn.take_error()?;
@@ -219,7 +344,10 @@ where
                             continue;
                         }
                     }
-                    match self.conn.poll_read_body(cx) {
+                    let polled = self.conn.poll_read_body(cx);
+                    self.dispatch
+                        .recv_chunk_extensions(self.conn.received_chunk_extension_bytes());
+                    match polled {
                         Poll::Ready(Some(Ok(frame))) => {
                             if frame.is_data() {
                                 let chunk = frame.into_data().unwrap_or_else(|_| unreachable!());
@@ -255,17 +383,19 @@ where
                         }
                         Poll::Ready(None) => {
                             // just drop, the body will close automatically
+                            self.dispatch.recv_body_end();
                         }
                         Poll::Pending => {
                             self.body_tx = Some(body);
                             return Poll::Pending;
                         }
                         Poll::Ready(Some(Err(e))) => {
-                            body.send_error(crate::Error::new_body(e));
+                            body.send_error(crate::Error::new_body_read(e));
                         }
                     }
                 } else {
                     // just drop, the body will close automatically
+                    self.dispatch.recv_body_end();
                 }
             } else {
                 return self.conn.poll_read_keep_alive(cx);
@@ -287,6 +417,7 @@ where
         // dispatch is ready for a message, try to read one
         match ready!(self.conn.poll_read_head(cx)) {
             Some(Ok((mut head, body_len, wants))) => {
+                let is_empty = body_len == DecodedLength::ZERO;
                 let body = match body_len {
                     DecodedLength::ZERO => IncomingBody::empty(),
                     other => {
@@ -307,6 +438,9 @@ where
                 }
                 self.dispatch
                     .recv_msg(Ok((self.conn.http_connection_stats(), head, body)))?;
+                if is_empty {
+                    self.dispatch.recv_body_end();
+                }
                 Poll::Ready(Ok(()))
             }
             Some(Err(err)) => {
@@ -331,30 +465,53 @@ where
         }
     }
 
+    fn write_informational(&mut self, cx: &mut Context<'_>) {
+        while let Poll::Ready(Some(res)) = self.dispatch.poll_informational(cx) {
+            self.conn.write_informational(res);
+        }
+    }
+
     fn poll_write(&mut self, cx: &mut Context<'_>) -> Poll<crate::Result<()>> {
         loop {
             if self.is_closing {
                 return Poll::Ready(Ok(()));
-            } else if self.body_rx.is_none()
+            }
+
+            #[cfg(all(feature = "sendfile", unix))]
+            if let Some(ref mut file) = self.file_rx {
+                #[cfg(feature = "client")]
+                ready!(self.conn.poll_expect_continue(cx))?;
+
+                ready!(self.conn.poll_write_file(cx, file)).map_err(|err| {
+                    debug!("error writing file: {}", err);
+                    crate::Error::new_body_write(err)
+                })?;
+                // The body is left empty, and ends as usual.
+                self.file_rx = None;
+            }
+
+            if self.body_rx.is_none()
                 && self.conn.can_write_head()
                 && self.dispatch.should_poll()
             {
+                self.write_informational(cx);
                 if let Some(msg) = ready!(Pin::new(&mut self.dispatch).poll_msg(cx)) {
                     let (head, body) = msg.map_err(crate::Error::new_user_service)?;
+                    // Any sent before the message was ready still go first.
+                    self.write_informational(cx);
 
-                    let body_type = if body.is_end_stream() {
+                    if body.is_end_stream() {
                         self.body_rx.set(None);
-                        None
+                        self.conn.write_head(head, None);
                     } else {
                         let btype = body
                             .size_hint()
                             .exact()
                             .map(BodyLength::Known)
-                            .or(Some(BodyLength::Unknown));
+                            .unwrap_or(BodyLength::Unknown);
+                        self.conn.write_head(head, Some(btype));
                         self.body_rx.set(Some(body));
-                        btype
-                    };
-                    self.conn.write_head(head, body_type);
+                    }
                 } else {
                     self.close();
                     return Poll::Ready(Ok(()));
@@ -376,6 +533,9 @@ where
                         continue;
                     }
 
+                    #[cfg(feature = "client")]
+                    ready!(self.conn.poll_expect_continue(cx))?;
+
                     let item = ready!(body.as_mut().poll_frame(cx));
                     if let Some(item) = item {
                         let frame = item.map_err(|e| {
@@ -385,6 +545,15 @@ where
 
                         if frame.is_data() {
                             let chunk = frame.into_data().unwrap_or_else(|_| unreachable!());
+                            #[cfg(all(feature = "sendfile", unix))]
+                            let mut chunk = chunk;
+                            #[cfg(all(feature = "sendfile", unix))]
+                            if self.conn.can_write_file() {
+                                self.file_rx = take_file_region(&mut chunk);
+                                if self.file_rx.is_some() {
+                                    continue;
+                                }
+                            }
                             let eos = body.is_end_stream();
                             if eos {
                                 *clear_body = true;
@@ -427,10 +596,13 @@ where
     }
 
     fn poll_flush(&mut self, cx: &mut Context<'_>) -> Poll<crate::Result<()>> {
-        self.conn.poll_flush(cx).map_err(|err| {
+        ready!(self.conn.poll_flush(cx)).map_err(|err| {
             debug!("error writing: {}", err);
             crate::Error::new_body_write(err)
-        })
+        })?;
+        let msg_done = self.body_rx.is_none() && !self.conn.can_write_body();
+        self.dispatch.flushed(msg_done);
+        Poll::Ready(Ok(()))
     }
 
     fn close(&mut self) {
@@ -512,10 +684,26 @@ cfg_server! {
         pub(crate) fn new(service: S) -> Server<S, B> {
             Server {
                 in_flight: Box::pin(None),
+                informational: None,
                 service,
+                record_stats: false,
+                on_stats: None,
+                stats: None,
+                clock: Clock::default(),
+                connection_info: None,
             }
         }
 
+        pub(crate) fn set_connection_info(&mut self, info: http::Extensions) {
+            self.connection_info = Some(info);
+        }
+
+        pub(crate) fn enable_stats(&mut self, on_stats: Option<OnStats<ServerRequestStats>>, clock: Clock) {
+            self.record_stats = true;
+            self.on_stats = on_stats;
+            self.clock = clock;
+        }
+
         pub(crate) fn into_service(self) -> S {
             self.service
         }
@@ -543,6 +731,9 @@ cfg_server! {
             let ret = if let Some(ref mut fut) = this.in_flight.as_mut().as_pin_mut() {
                 let resp = ready!(fut.as_mut().poll(cx)?);
                 let (parts, body) = resp.into_parts();
+                if let Some(ref mut stats) = this.stats {
+                    stats.status = Some(parts.status);
+                }
                 let head = MessageHead {
                     version: parts.version,
                     subject: parts.status,
@@ -556,6 +747,11 @@ cfg_server! {
 
             // Since in_flight finished, remove it
             this.in_flight.set(None);
+            // Informational responses already sent can still be written,
+            // but no more can be.
+            if let Some(ref mut rx) = this.informational {
+                rx.close();
+            }
             ret
         }
 
@@ -567,6 +763,28 @@ cfg_server! {
             *req.headers_mut() = msg.headers;
             *req.version_mut() = msg.version;
             *req.extensions_mut() = msg.extensions;
+            if let Some(ref info) = self.connection_info {
+                req.extensions_mut().extend(info.clone());
+            }
+            if self.record_stats {
+                let stats = ServerRequestStats {
+                    method: req.method().clone(),
+                    uri: req.uri().clone(),
+                    status: None,
+                    headers_parsed: self.clock.now(),
+                    body_complete: None,
+                    response_flushed: None,
+                    chunk_extension_bytes: 0,
+                };
+                req.extensions_mut().insert(stats.clone());
+                self.stats = Some(stats);
+            }
+            self.informational = None;
+            if req.version() > http::Version::HTTP_10 {
+                let (informational, rx) = SendInformational::new();
+                req.extensions_mut().insert(informational);
+                self.informational = Some(rx);
+            }
             let fut = self.service.call(req);
             self.in_flight.set(Some(fut));
             Ok(())
@@ -583,6 +801,49 @@ cfg_server! {
         fn should_poll(&self) -> bool {
             self.in_flight.is_some()
         }
+
+        fn poll_informational(&mut self, cx: &mut Context<'_>) -> Poll<Option<http::Response<()>>> {
+            let rx = match self.informational {
+                Some(ref mut rx) => rx,
+                None => return Poll::Ready(None),
+            };
+            let ret = Pin::new(rx).poll_next(cx);
+            if let Poll::Ready(None) = ret {
+                self.informational = None;
+            }
+            ret
+        }
+
+        fn recv_chunk_extensions(&mut self, bytes: u64) {
+            if let Some(ref mut stats) = self.stats {
+                stats.chunk_extension_bytes = bytes;
+            }
+        }
+
+        fn recv_body_end(&mut self) {
+            if let Some(ref mut stats) = self.stats {
+                if stats.body_complete.is_none() {
+                    stats.body_complete = Some(self.clock.now());
+                }
+            }
+        }
+
+        fn flushed(&mut self, msg_done: bool) {
+            if !msg_done {
+                return;
+            }
+            // Only finish once the service has responded; flushes before
+            // that are for earlier writes, such as `100 Continue`.
+            if !matches!(self.stats, Some(ServerRequestStats { status: Some(_), .. })) {
+                return;
+            }
+            if let Some(mut stats) = self.stats.take() {
+                stats.response_flushed = Some(self.clock.now());
+                if let Some(ref on_stats) = self.on_stats {
+                    on_stats.call(stats);
+                }
+            }
+        }
     }
 }
 
@@ -591,13 +852,90 @@ cfg_server! {
 cfg_client! {
     use std::convert::Infallible;
 
+    use crate::common::stats::{next_connection_id, CheckoutStart, Observer, OnBodyEnd, Retries};
+    use crate::RequestStats;
+
     impl<B> Client<B> {
         pub(crate) fn new(rx: ClientRx<B>) -> Client<B> {
             Client {
-                callback: None,
+                in_flight: std::collections::VecDeque::new(),
+                max_pipelined: 0,
                 rx,
                 rx_closed: false,
+                on_stats: None,
+                observer: None,
+                connection_id: next_connection_id(),
+                request_count: 0,
+                collect_stats: true,
+                tunnel_start: None,
+                clock: Clock::default(),
+            }
+        }
+
+        pub(crate) fn set_max_pipelined(&mut self, max: usize) {
+            self.max_pipelined = max;
+        }
+
+        pub(crate) fn set_on_stats(&mut self, on_stats: OnStats) {
+            self.on_stats = Some(on_stats);
+        }
+
+        pub(crate) fn set_observer(&mut self, observer: Observer) {
+            self.observer = Some(observer);
+        }
+
+        pub(crate) fn set_clock(&mut self, clock: Clock) {
+            self.clock = clock;
+        }
+
+        pub(crate) fn disable_stats(&mut self) {
+            self.collect_stats = false;
+        }
+
+        /// When the CONNECT request that turned this connection into a
+        /// tunnel was picked up, if it did.
+        pub(crate) fn tunnel_start(&self) -> Option<std::time::Instant> {
+            self.tunnel_start
+        }
+
+        /// Fills in what only the dispatcher knows about a request in
+        /// flight, returning when it was picked up if stats are collected.
+        fn fill_stats(&mut self, req: &InFlight<B>, stats: &mut HttpConnectionStats) -> Option<std::time::Instant> {
+            self.request_count += 1;
+            stats.connection_id = Some(self.connection_id);
+            stats.connection_request_count = self.request_count;
+            if !self.collect_stats {
+                return None;
             }
+            let poll_start = req.poll_start.unwrap_or_else(|| self.clock.now());
+            stats.checkout_start = req.checkout_start;
+            stats.checkout_end = Some(poll_start);
+            stats.headers_written = req.headers_written;
+            stats.body_written = req.body_written;
+            if self.request_count > 1 {
+                stats.connection_stats = stats.connection_stats.map(crate::rt::ConnectionStats::reused);
+            }
+            Some(poll_start)
+        }
+    }
+
+    /// The stats of a request that was queued on a connection but never sent.
+    fn queued_stats(connection_id: u64) -> HttpConnectionStats {
+        HttpConnectionStats {
+            connection_id: Some(connection_id),
+            ..Default::default()
+        }
+    }
+
+    /// Fails the requests pipelined behind one that ended the connection.
+    /// The server never answered them.
+    fn cancel_pipelined<B>(in_flight: &mut std::collections::VecDeque<InFlight<B>>) {
+        for req in in_flight.drain(..) {
+            trace!("canceling unanswered pipelined request");
+            req.callback.send(Err(TrySendError {
+                error: crate::Error::new_canceled().with("connection closed before pipelined request was answered"),
+                message: None,
+            }));
         }
     }
 
@@ -625,14 +963,43 @@ cfg_client! {
                             Poll::Ready(None)
                         }
                         Poll::Pending => {
-                            let (parts, body) = req.into_parts();
+                            let (mut parts, body) = req.into_parts();
+                            let checkout_start = parts
+                                .extensions
+                                .remove::<CheckoutStart>()
+                                .map(|start| start.0);
+                            let retries = parts
+                                .extensions
+                                .remove::<Retries>()
+                                .map_or_else(Vec::new, |retries| retries.0);
                             let head = RequestHead {
                                 version: parts.version,
                                 subject: crate::proto::RequestLine(parts.method, parts.uri),
                                 headers: parts.headers,
                                 extensions: parts.extensions,
                             };
-                            this.callback = Some(cb);
+                            let is_connect = head.subject.0 == http::Method::CONNECT;
+                            let poll_start = if this.collect_stats {
+                                let now = this.clock.now();
+                                if let Some(ref observer) = this.observer {
+                                    observer.on_request_start(now);
+                                }
+                                Some(now)
+                            } else {
+                                None
+                            };
+                            let pipelined = !this.in_flight.is_empty();
+                            this.in_flight.push_back(InFlight {
+                                callback: cb,
+                                poll_start,
+                                checkout_start,
+                                headers_written: None,
+                                body_written: None,
+                                is_connect,
+                                pipelined,
+                                ends_pipeline: is_connect || head.headers.contains_key(http::header::UPGRADE),
+                                retries,
+                            });
                             Poll::Ready(Some(Ok((head, body))))
                         }
                     }
@@ -649,10 +1016,37 @@ cfg_client! {
 
         fn recv_msg(&mut self, msg: crate::Result<(HttpConnectionStats, Self::RecvItem, IncomingBody)>) -> crate::Result<()> {
             match msg {
-                Ok((stats, msg, body)) => {
-                    if let Some(cb) = self.callback.take() {
+                Ok((mut stats, msg, mut body)) => {
+                    if let Some(req) = self.in_flight.pop_front() {
+                        if let Some(poll_start) = self.fill_stats(&req, &mut stats) {
+                            body = body.with_stats();
+                            if req.is_connect && msg.extensions.get::<crate::upgrade::OnUpgrade>().is_some() {
+                                self.tunnel_start = Some(poll_start);
+                            }
+                            if let (Some(ref observer), Some(at)) =
+                                (&self.observer, stats.first_header_byte_time)
+                            {
+                                observer.on_first_header_byte(at);
+                            }
+                            if self.on_stats.is_some() || self.observer.is_some() {
+                                let request_stats = RequestStats {
+                                    http_stats: stats,
+                                    redirects: Vec::new(),
+                                    retries: req.retries.clone(),
+                                    poll_start,
+                                    finish: self.clock.now(),
+                                };
+                                if let Some(ref observer) = self.observer {
+                                    observer.on_complete(&request_stats);
+                                }
+                                if let Some(ref on_stats) = self.on_stats {
+                                    let on_end = OnBodyEnd::new(request_stats, on_stats.clone());
+                                    body = body.with_on_end(on_end);
+                                }
+                            }
+                        }
                         let res = msg.into_response(body);
-                        cb.send(Ok((stats, res)));
+                        req.callback.send(Ok((stats, res)));
                         Ok(())
                     } else {
                         // Getting here is likely a bug! An error should have happened
@@ -661,12 +1055,35 @@ cfg_client! {
                         Err(crate::Error::new_unexpected_message())
                     }
                 }
-                Err(err) => {
-                    if let Some(cb) = self.callback.take() {
-                        cb.send(Err(TrySendError {
+                Err(mut err) => {
+                    if let Some(req) = self.in_flight.pop_front() {
+                        // Errors that happen before anything was read carry no
+                        // stats of their own, but the request is still reported
+                        // as failed, and the error gets what is known of it.
+                        let mut no_stats = None;
+                        let stats = match err.stats_mut() {
+                            Some(stats) => stats,
+                            None => no_stats.insert(HttpConnectionStats::default()),
+                        };
+                        if let (Some(poll_start), Some(ref on_stats)) =
+                            (self.fill_stats(&req, stats), &self.on_stats)
+                        {
+                            on_stats.call(RequestStats {
+                                http_stats: *stats,
+                                redirects: Vec::new(),
+                                retries: req.retries.clone(),
+                                poll_start,
+                                finish: self.clock.now(),
+                            });
+                        }
+                        if let Some(stats) = no_stats {
+                            err = err.with_stats(stats);
+                        }
+                        req.callback.send(Err(TrySendError {
                             error: err,
                             message: None,
                         }));
+                        cancel_pipelined(&mut self.in_flight);
                         Ok(())
                     } else if !self.rx_closed {
                         self.rx.close();
@@ -675,7 +1092,9 @@ cfg_client! {
                             // in this case, the message was never even started, so it's safe to tell
                             // the user that the request was completely canceled
                             cb.send(Err(TrySendError {
-                                error: crate::Error::new_canceled().with(err),
+                                error: crate::Error::new_canceled()
+                                    .with(err)
+                                    .with_stats(queued_stats(self.connection_id)),
                                 message: Some(req),
                             }));
                             Ok(())
@@ -690,8 +1109,8 @@ cfg_client! {
         }
 
         fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), ()>> {
-            match self.callback {
-                Some(ref mut cb) => match cb.poll_canceled(cx) {
+            match self.in_flight.front_mut() {
+                Some(req) => match req.callback.poll_canceled(cx) {
                     Poll::Ready(()) => {
                         trace!("callback receiver has dropped");
                         Poll::Ready(Err(()))
@@ -703,7 +1122,38 @@ cfg_client! {
         }
 
         fn should_poll(&self) -> bool {
-            self.callback.is_none()
+            match self.in_flight.back() {
+                None => true,
+                Some(last) => !last.ends_pipeline && self.in_flight.len() <= self.max_pipelined,
+            }
+        }
+
+        fn flushed(&mut self, msg_done: bool) {
+            // Only while a request is in flight and its writes are still
+            // being timed. That's the newest, if several are pipelined.
+            if !self.collect_stats {
+                return;
+            }
+            let req = match self.in_flight.back_mut() {
+                Some(req) => req,
+                None => return,
+            };
+            if req.headers_written.is_some() && (!msg_done || req.body_written.is_some()) {
+                return;
+            }
+            let now = self.clock.now();
+            if req.headers_written.is_none() {
+                req.headers_written = Some(now);
+                if let Some(ref observer) = self.observer {
+                    observer.on_headers_written(now);
+                }
+            }
+            if msg_done && req.body_written.is_none() {
+                req.body_written = Some(now);
+                if let Some(ref observer) = self.observer {
+                    observer.on_body_written(now);
+                }
+            }
         }
     }
 }
@@ -44,7 +44,6 @@ enum Kind {
     ///
     /// This is mostly only used with HTTP/1.0 with a length. This kind requires
     /// the connection to be closed when the body is finished.
-    #[cfg(feature = "server")]
     CloseDelimited,
 }
 
@@ -72,7 +71,6 @@ impl Encoder {
         Encoder::new(Kind::Length(len))
     }
 
-    #[cfg(feature = "server")]
     pub(crate) fn close_delimited() -> Encoder {
         Encoder::new(Kind::CloseDelimited)
     }
@@ -102,24 +100,40 @@ impl Encoder {
     }
 
     pub(crate) fn is_close_delimited(&self) -> bool {
-        match self.kind {
-            #[cfg(feature = "server")]
-            Kind::CloseDelimited => true,
-            _ => false,
-        }
+        matches!(self.kind, Kind::CloseDelimited)
     }
 
     pub(crate) fn is_chunked(&self) -> bool {
         matches!(self.kind, Kind::Chunked(_))
     }
 
+    /// Returns how many more bytes a sized body allows.
+    #[cfg(all(feature = "sendfile", unix))]
+    pub(crate) fn remaining_len(&self) -> Option<u64> {
+        match self.kind {
+            Kind::Length(n) => Some(n),
+            _ => None,
+        }
+    }
+
+    /// Accounts for body bytes that were written without being encoded.
+    ///
+    /// Only sized and close-delimited bodies can be, since they send the
+    /// bytes as they are.
+    #[cfg(all(feature = "sendfile", unix))]
+    pub(crate) fn written_directly(&mut self, n: u64) {
+        debug_assert!(!self.is_chunked());
+        if let Kind::Length(ref mut remaining) = self.kind {
+            *remaining -= n;
+        }
+    }
+
     pub(crate) fn end<B>(&self) -> Result<Option<EncodedBuf<B>>, NotEof> {
         match self.kind {
             Kind::Length(0) => Ok(None),
             Kind::Chunked(_) => Ok(Some(EncodedBuf {
                 kind: BufKind::ChunkedEnd(b"0\r\n\r\n"),
             })),
-            #[cfg(feature = "server")]
             Kind::CloseDelimited => Ok(None),
             Kind::Length(n) => Err(NotEof(n)),
         }
@@ -151,7 +165,6 @@ impl Encoder {
                     BufKind::Exact(msg)
                 }
             }
-            #[cfg(feature = "server")]
             Kind::CloseDelimited => {
                 trace!("close delimited write {}B", len);
                 BufKind::Exact(msg)
@@ -251,7 +264,6 @@ impl Encoder {
                     }
                 }
             }
-            #[cfg(feature = "server")]
             Kind::CloseDelimited => {
                 trace!("close delimited write {}B", len);
                 dst.buffer(msg);
@@ -506,7 +518,6 @@ mod tests {
         assert!(encoder.end::<()>().unwrap().is_none());
     }
 
-    #[cfg(feature = "server")]
     #[test]
     fn eof() {
         let mut encoder = Encoder::close_delimited();
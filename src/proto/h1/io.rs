@@ -12,6 +12,7 @@ use futures_core::ready;
 
 use super::{Http1Transaction, ParseContext, ParsedMessage};
 use crate::common::buf::BufList;
+use crate::common::stats::Clock;
 
 /// The initial buffer size allocated before trying to read from IO.
 pub(crate) const INIT_BUFFER_SIZE: usize = 8192;
@@ -19,6 +20,10 @@ pub(crate) const INIT_BUFFER_SIZE: usize = 8192;
 /// The minimum value that can be set to max buffer size.
 pub(crate) const MINIMUM_MAX_BUFFER_SIZE: usize = INIT_BUFFER_SIZE;
 
+/// The minimum value that can be set to the initial read buffer size.
+#[cfg(feature = "client")]
+pub(crate) const MINIMUM_INIT_READ_BUF_SIZE: usize = 64;
+
 /// The default maximum read buffer size. If the buffer gets this big and
 /// a message is still not complete, a `TooLarge` error is triggered.
 // Note: if this changes, update server::conn::Http::max_buf_size docs.
@@ -32,6 +37,8 @@ pub(crate) const DEFAULT_MAX_BUFFER_SIZE: usize = 8192 + 4096 * 100;
 const MAX_BUF_LIST_BUFFERS: usize = 16;
 
 pub(crate) struct Buffered<T, B> {
+    bytes_read: u64,
+    bytes_written: u64,
     flush_pipeline: bool,
     io: T,
     partial_len: Option<usize>,
@@ -66,6 +73,8 @@ where
         };
         let write_buf = WriteBuf::new(strategy);
         Buffered {
+            bytes_read: 0,
+            bytes_written: 0,
             flush_pipeline: false,
             io,
             partial_len: None,
@@ -85,8 +94,12 @@ where
         }
     }
 
-    pub(crate) fn connection_stats(&mut self) -> Option<ConnectionStats> {
-        self.io.stats()
+    pub(crate) fn connection_stats(&mut self) -> ConnectionStats {
+        // The bytes are counted here whether or not the transport has stats.
+        let mut stats = self.io.stats().unwrap_or_default();
+        stats.bytes_read = self.bytes_read;
+        stats.bytes_written = self.bytes_written;
+        stats
     }
 
     pub(crate) fn set_max_buf_size(&mut self, max: usize) {
@@ -104,6 +117,15 @@ where
         self.read_buf_strategy = ReadStrategy::Exact(sz);
     }
 
+    /// Adapts the read buffer between `initial` and `max`, each defaulting
+    /// to what the current strategy uses.
+    #[cfg(feature = "client")]
+    pub(crate) fn set_read_buf_sizes(&mut self, initial: Option<usize>, max: Option<usize>) {
+        let initial = initial.unwrap_or(INIT_BUFFER_SIZE);
+        let max = max.unwrap_or_else(|| self.read_buf_strategy.max());
+        self.read_buf_strategy = ReadStrategy::adaptive(initial, max);
+    }
+
     pub(crate) fn set_write_strategy_flatten(&mut self) {
         // this should always be called only at construction time,
         // so this assert is here to catch myself
@@ -175,15 +197,16 @@ where
 
     pub(super) fn parse<S>(
         &mut self,
-        record_time: bool,
+        clock: Option<&Clock>,
         cx: &mut Context<'_>,
         parse_ctx: ParseContext<'_>,
-    ) -> Poll<crate::Result<(Option<std::time::Instant>, ParsedMessage<S::Incoming>)>>
+    ) -> Poll<crate::Result<(Option<std::time::Instant>, usize, ParsedMessage<S::Incoming>)>>
     where
         S: Http1Transaction,
     {
         let mut fbt = None;
         loop {
+            let buffered = self.read_buf.len();
             match super::role::parse_headers::<S>(
                 &mut self.read_buf,
                 self.partial_len,
@@ -192,22 +215,34 @@ where
                     req_method: parse_ctx.req_method,
                     h1_parser_config: parse_ctx.h1_parser_config.clone(),
                     h1_max_headers: parse_ctx.h1_max_headers,
+                    h1_max_header_size: parse_ctx.h1_max_header_size,
                     preserve_header_case: parse_ctx.preserve_header_case,
                     #[cfg(feature = "ffi")]
                     preserve_header_order: parse_ctx.preserve_header_order,
                     h09_responses: parse_ctx.h09_responses,
                     #[cfg(feature = "client")]
                     on_informational: parse_ctx.on_informational,
+                    #[cfg(feature = "client")]
+                    continue_received: parse_ctx.continue_received,
+                    #[cfg(feature = "client")]
+                    raw_headers: parse_ctx.raw_headers,
+                    #[cfg(feature = "server")]
+                    allow_invalid_request_target: parse_ctx.allow_invalid_request_target,
+                    #[cfg(feature = "server")]
+                    conflicting_framing: parse_ctx.conflicting_framing,
                 },
             )? {
                 Some(msg) => {
                     debug!("parsed {} headers", msg.head.headers.len());
                     self.partial_len = None;
-                    return Poll::Ready(Ok((fbt, msg)));
+                    let head_len = buffered - self.read_buf.len();
+                    check_header_size(head_len, parse_ctx.h1_max_header_size)?;
+                    return Poll::Ready(Ok((fbt, head_len, msg)));
                 }
                 None => {
                     let max = self.read_buf_strategy.max();
                     let curr_len = self.read_buf.len();
+                    check_header_size(curr_len, parse_ctx.h1_max_header_size)?;
                     if curr_len >= max {
                         debug!("max_buf_size ({}) reached, closing", max);
                         return Poll::Ready(Err(crate::Error::new_too_large()));
@@ -221,7 +256,7 @@ where
                     }
                 }
             }
-            let (maybe_time, r) = self.poll_read_from_io(record_time, cx);
+            let (maybe_time, r) = self.poll_read_from_io(clock, cx);
             if fbt.is_none() {
                 // Only set our time if we are None--this ensures that any non-None value we get is "sticky" (in case we
                 // do multiple rounds of this parse as we await complete headers.)
@@ -236,7 +271,7 @@ where
 
     pub(crate) fn poll_read_from_io(
         &mut self,
-        record_time: bool,
+        clock: Option<&Clock>,
         cx: &mut Context<'_>,
     ) -> (Option<std::time::Instant>, Poll<io::Result<usize>>) {
         self.read_blocked = false;
@@ -251,11 +286,7 @@ where
         let mut buf = ReadBuf::uninit(dst);
         match Pin::new(&mut self.io).poll_read(cx, buf.unfilled()) {
             Poll::Ready(Ok(_)) => {
-                let time = if record_time {
-                    Some(std::time::Instant::now())
-                } else {
-                    None
-                };
+                let time = clock.map(Clock::now);
                 let n = buf.filled().len();
                 trace!("received {} bytes", n);
                 unsafe {
@@ -265,6 +296,7 @@ where
                     self.read_buf.advance_mut(n);
                 }
                 self.read_buf_strategy.record(n);
+                self.bytes_read += n as u64;
                 (time, Poll::Ready(Ok(n)))
             }
             Poll::Pending => {
@@ -283,6 +315,32 @@ where
         &mut self.io
     }
 
+    #[cfg(all(feature = "sendfile", unix))]
+    pub(crate) fn is_write_file(&self) -> bool {
+        self.io.is_write_file()
+    }
+
+    /// Writes from a file region straight to the transport, after anything
+    /// still buffered.
+    #[cfg(all(feature = "sendfile", unix))]
+    pub(crate) fn poll_write_file(
+        &mut self,
+        cx: &mut Context<'_>,
+        file: &crate::body::FileRegion,
+    ) -> Poll<io::Result<usize>> {
+        if self.write_buf.remaining() > 0 {
+            // Even while pipelining, the buffered head must go first.
+            let flush_pipeline = std::mem::replace(&mut self.flush_pipeline, false);
+            let res = self.poll_flush(cx);
+            self.flush_pipeline = flush_pipeline;
+            ready!(res)?;
+        }
+        let n = ready!(Pin::new(&mut self.io).poll_write_file(cx, file))?;
+        self.bytes_written += n as u64;
+        debug!("wrote {} bytes from file", n);
+        Poll::Ready(Ok(n))
+    }
+
     pub(crate) fn is_read_blocked(&self) -> bool {
         self.read_blocked
     }
@@ -308,6 +366,7 @@ where
                 // `poll_write_buf` doesn't exist in Tokio 0.3 yet...when
                 // `poll_write_buf` comes back, the manual advance will need to leave!
                 self.write_buf.advance(n);
+                self.bytes_written += n as u64;
                 debug!("flushed {} bytes", n);
                 if self.write_buf.remaining() == 0 {
                     break;
@@ -332,6 +391,7 @@ where
             let n = ready!(Pin::new(&mut self.io).poll_write(cx, self.write_buf.headers.chunk()))?;
             debug!("flushed {} bytes", n);
             self.write_buf.headers.advance(n);
+            self.bytes_written += n as u64;
             if self.write_buf.headers.remaining() == 0 {
                 self.write_buf.headers.reset();
                 break;
@@ -370,7 +430,7 @@ where
             let n = std::cmp::min(len, self.read_buf.len());
             Poll::Ready(Ok(self.read_buf.split_to(n).freeze()))
         } else {
-            let (_, r) = self.poll_read_from_io(false, cx);
+            let (_, r) = self.poll_read_from_io(None, cx);
             let n = ready!(r)?;
             Poll::Ready(Ok(self.read_buf.split_to(::std::cmp::min(len, n)).freeze()))
         }
@@ -382,6 +442,8 @@ enum ReadStrategy {
     Adaptive {
         decrease_now: bool,
         next: usize,
+        /// Where the buffer starts, and the smallest it shrinks back to.
+        min: usize,
         max: usize,
     },
     #[cfg(feature = "client")]
@@ -390,9 +452,15 @@ enum ReadStrategy {
 
 impl ReadStrategy {
     fn with_max(max: usize) -> ReadStrategy {
+        ReadStrategy::adaptive(INIT_BUFFER_SIZE, max)
+    }
+
+    fn adaptive(initial: usize, max: usize) -> ReadStrategy {
+        let initial = cmp::min(initial, max);
         ReadStrategy::Adaptive {
             decrease_now: false,
-            next: INIT_BUFFER_SIZE,
+            next: initial,
+            min: initial,
             max,
         }
     }
@@ -418,8 +486,8 @@ impl ReadStrategy {
             ReadStrategy::Adaptive {
                 ref mut decrease_now,
                 ref mut next,
+                min,
                 max,
-                ..
             } => {
                 if bytes_read >= *next {
                     *next = cmp::min(incr_power_of_two(*next), max);
@@ -428,7 +496,7 @@ impl ReadStrategy {
                     let decr_to = prev_power_of_two(*next);
                     if bytes_read < decr_to {
                         if *decrease_now {
-                            *next = cmp::max(decr_to, INIT_BUFFER_SIZE);
+                            *next = cmp::max(decr_to, min);
                             *decrease_now = false;
                         } else {
                             // Decreasing is a two "record" process.
@@ -452,6 +520,17 @@ fn incr_power_of_two(n: usize) -> usize {
     n.saturating_mul(2)
 }
 
+/// Fails once a message head is past the configured max header size.
+fn check_header_size(len: usize, max: Option<usize>) -> crate::Result<()> {
+    match max {
+        Some(max) if len > max => {
+            debug!("max_header_size ({}) exceeded, closing", max);
+            Err(crate::Error::new_header_size_too_large())
+        }
+        _ => Ok(()),
+    }
+}
+
 fn prev_power_of_two(n: usize) -> usize {
     // Only way this shift can underflow is if n is less than 4.
     // (Which would means `usize::MAX >> 64` and underflowed!)
@@ -730,15 +809,24 @@ mod tests {
                 req_method: &mut None,
                 h1_parser_config: Default::default(),
                 h1_max_headers: None,
+                h1_max_header_size: None,
                 preserve_header_case: false,
                 #[cfg(feature = "ffi")]
                 preserve_header_order: false,
                 h09_responses: false,
                 #[cfg(feature = "client")]
                 on_informational: &mut None,
+                #[cfg(feature = "client")]
+                continue_received: &mut false,
+                #[cfg(feature = "client")]
+                raw_headers: false,
+                #[cfg(feature = "server")]
+                allow_invalid_request_target: false,
+                #[cfg(feature = "server")]
+                conflicting_framing: Default::default(),
             };
             assert!(buffered
-                .parse::<ClientTransaction>(false, cx, parse_ctx)
+                .parse::<ClientTransaction>(None, cx, parse_ctx)
                 .is_pending());
             Poll::Ready(())
         })
@@ -828,6 +916,24 @@ mod tests {
         );
     }
 
+    #[test]
+    fn read_strategy_adaptive_initial() {
+        let mut strategy = ReadStrategy::adaptive(1024, 4096);
+        assert_eq!(strategy.next(), 1024);
+
+        strategy.record(1024);
+        assert_eq!(strategy.next(), 2048);
+        strategy.record(2048);
+        assert_eq!(strategy.next(), 4096);
+        strategy.record(4096);
+        assert_eq!(strategy.next(), 4096, "never goes over max");
+
+        for _ in 0..8 {
+            strategy.record(1);
+        }
+        assert_eq!(strategy.next(), 1024, "shrinks back to the initial size");
+    }
+
     #[test]
     fn read_strategy_adaptive_max_fuzz() {
         fn fuzz(max: usize) {
@@ -973,6 +1079,28 @@ mod tests {
         assert_eq!(buffered.write_buf.queue.bufs_cnt(), 0);
     }
 
+    #[cfg(not(miri))]
+    #[tokio::test]
+    async fn counts_bytes_read_and_written() {
+        let _ = pretty_env_logger::try_init();
+
+        let mock = Mock::new()
+            .write(b"hello world")
+            .read(b"HTTP/1.1 200 OK\r\n")
+            .build();
+
+        let mut buffered = Buffered::<_, Cursor<Vec<u8>>>::new(Compat::new(mock));
+        buffered.headers_buf().extend(b"hello ");
+        buffered.buffer(Cursor::new(b"world".to_vec()));
+        buffered.flush().await.expect("flush");
+        assert_eq!(buffered.bytes_written, 11);
+
+        futures_util::future::poll_fn(|cx| buffered.poll_read_from_io(None, cx).1)
+            .await
+            .expect("read");
+        assert_eq!(buffered.bytes_read, 17);
+    }
+
     // #[cfg(feature = "nightly")]
     // #[bench]
     // fn bench_write_buf_flatten_buffer_chunk(b: &mut Bencher) {
@@ -1,3 +1,10 @@
+#[cfg(feature = "server")]
+use std::fmt;
+#[cfg(feature = "server")]
+use std::sync::Arc;
+
+#[cfg(feature = "server")]
+use bytes::Bytes;
 use bytes::BytesMut;
 use http::{HeaderMap, Method};
 use httparse::ParserConfig;
@@ -5,12 +12,16 @@ use httparse::ParserConfig;
 use crate::body::DecodedLength;
 use crate::proto::{BodyLength, MessageHead};
 
+#[cfg(feature = "client")]
+pub(crate) use self::conn::CloseCause;
 pub(crate) use self::conn::Conn;
 pub(crate) use self::decode::Decoder;
 pub(crate) use self::dispatch::Dispatcher;
 pub(crate) use self::encode::{EncodedBuf, Encoder};
 //TODO: move out of h1::io
 pub(crate) use self::io::MINIMUM_MAX_BUFFER_SIZE;
+#[cfg(feature = "client")]
+pub(crate) use self::io::MINIMUM_INIT_READ_BUF_SIZE;
 
 mod conn;
 mod decode;
@@ -37,6 +48,15 @@ pub(crate) trait Http1Transaction {
 
     fn on_error(err: &crate::Error) -> Option<MessageHead<Self::Outgoing>>;
 
+    /// Like `on_error`, but with the response an [`ErrorResponder`] gives.
+    #[cfg(feature = "server")]
+    fn on_error_with(
+        _err: &crate::Error,
+        _responder: &ErrorResponder,
+    ) -> Option<(MessageHead<Self::Outgoing>, Bytes)> {
+        None
+    }
+
     fn is_client() -> bool {
         !Self::is_server()
     }
@@ -56,6 +76,40 @@ pub(crate) trait Http1Transaction {
     fn update_date() {}
 }
 
+/// Builds the response written for a request that hyper rejects itself.
+#[cfg(feature = "server")]
+#[derive(Clone)]
+pub(crate) struct ErrorResponder(Arc<ErrorResponderFn>);
+
+#[cfg(feature = "server")]
+type ErrorResponderFn =
+    dyn Fn(&crate::Error, http::StatusCode) -> http::Response<Bytes> + Send + Sync;
+
+#[cfg(feature = "server")]
+impl ErrorResponder {
+    pub(crate) fn new<F>(responder: F) -> ErrorResponder
+    where
+        F: Fn(&crate::Error, http::StatusCode) -> http::Response<Bytes> + Send + Sync + 'static,
+    {
+        ErrorResponder(Arc::new(responder))
+    }
+
+    pub(crate) fn call(
+        &self,
+        err: &crate::Error,
+        status: http::StatusCode,
+    ) -> http::Response<Bytes> {
+        (self.0)(err, status)
+    }
+}
+
+#[cfg(feature = "server")]
+impl fmt::Debug for ErrorResponder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ErrorResponder").finish()
+    }
+}
+
 /// Result newtype for Http1Transaction::parse.
 pub(crate) type ParseResult<T> = Result<Option<ParsedMessage<T>>, crate::error::Parse>;
 
@@ -73,12 +127,22 @@ pub(crate) struct ParseContext<'a> {
     req_method: &'a mut Option<Method>,
     h1_parser_config: ParserConfig,
     h1_max_headers: Option<usize>,
+    h1_max_header_size: Option<usize>,
     preserve_header_case: bool,
     #[cfg(feature = "ffi")]
     preserve_header_order: bool,
     h09_responses: bool,
     #[cfg(feature = "client")]
     on_informational: &'a mut Option<crate::ext::OnInformational>,
+    /// Set when a 100 Continue is parsed.
+    #[cfg(feature = "client")]
+    continue_received: &'a mut bool,
+    #[cfg(feature = "client")]
+    raw_headers: bool,
+    #[cfg(feature = "server")]
+    allow_invalid_request_target: bool,
+    #[cfg(feature = "server")]
+    conflicting_framing: crate::server::conn::http1::ConflictingFraming,
 }
 
 /// Passed to Http1Transaction::encode
@@ -17,16 +17,26 @@ use crate::body::DecodedLength;
 #[cfg(feature = "server")]
 use crate::common::date;
 use crate::error::Parse;
+#[cfg(feature = "server")]
+use crate::ext::ConflictingContentLength;
 use crate::ext::HeaderCaseMap;
-#[cfg(feature = "ffi")]
+#[cfg(any(feature = "client", feature = "ffi"))]
 use crate::ext::OriginalHeaderOrder;
+#[cfg(feature = "client")]
+use crate::ext::VerbatimFraming;
+#[cfg(feature = "client")]
+use crate::ext::{RawHeaderIndices, RawHeaders};
 use crate::headers;
+#[cfg(feature = "server")]
+use crate::proto::h1::ErrorResponder;
 use crate::proto::h1::{
     Encode, Encoder, Http1Transaction, ParseContext, ParseResult, ParsedMessage,
 };
 #[cfg(feature = "client")]
 use crate::proto::RequestHead;
 use crate::proto::{BodyLength, MessageHead, RequestLine};
+#[cfg(feature = "server")]
+use crate::server::conn::http1::ConflictingFraming;
 
 pub(crate) const DEFAULT_MAX_HEADERS: usize = 100;
 const AVERAGE_HEADER_SIZE: usize = 30; // totally scientific
@@ -164,7 +174,10 @@ impl Http1Transaction for Server {
             trace!(bytes = buf.len(), "Request.parse");
             let mut req = httparse::Request::new(&mut []);
             let bytes = buf.as_ref();
-            match req.parse_with_uninit_headers(bytes, &mut headers) {
+            match ctx
+                .h1_parser_config
+                .parse_request_with_uninit_headers(&mut req, bytes, &mut headers)
+            {
                 Ok(httparse::Status::Complete(parsed_len)) => {
                     trace!("Request.parse Complete({})", parsed_len);
                     len = parsed_len;
@@ -206,11 +219,27 @@ impl Http1Transaction for Server {
         };
 
         let slice = buf.split_to(len).freeze();
+        let mut raw_target = None;
         let uri = {
-            let uri_bytes = slice.slice_ref(&slice[path_range]);
+            let uri_bytes = slice.slice_ref(&slice[path_range.clone()]);
             // TODO(lucab): switch to `Uri::from_shared()` once public.
-            http::Uri::from_maybe_shared(uri_bytes)?
+            http::Uri::from_maybe_shared(uri_bytes)
         };
+        let uri = match uri {
+            Ok(uri) => uri,
+            Err(_e) if ctx.allow_invalid_request_target => {
+                debug!("invalid request target, serving it as \"/\": {}", _e);
+                raw_target = Some(crate::ext::RawRequestTarget::new(slice.slice(path_range)));
+                http::Uri::from_static("/")
+            }
+            Err(e) => return Err(e.into()),
+        };
+        // asterisk-form is only meant for a server-wide OPTIONS request
+        // https://www.rfc-editor.org/rfc/rfc9112#section-3.2.4
+        if uri == "*" && method != Method::OPTIONS {
+            debug!("asterisk-form request target with method {}", method);
+            return Err(Parse::Uri);
+        }
         subject = RequestLine(method, uri);
 
         // According to https://tools.ietf.org/html/rfc7230#section-3.3.3
@@ -225,6 +254,8 @@ impl Http1Transaction for Server {
         let mut decoder = DecodedLength::ZERO;
         let mut expect_continue = false;
         let mut con_len = None;
+        let mut has_con_len = false;
+        let mut con_len_differs = false;
         let mut is_te = false;
         let mut is_te_chunked = false;
         let mut wants_upgrade = subject.0 == Method::CONNECT;
@@ -271,24 +302,23 @@ impl Http1Transaction for Server {
                     }
                 }
                 header::CONTENT_LENGTH => {
-                    if is_te {
-                        continue;
-                    }
-                    let len = headers::content_length_parse(&value)
-                        .ok_or_else(Parse::content_length_invalid)?;
-                    if let Some(prev) = con_len {
-                        if prev != len {
-                            debug!(
-                                "multiple Content-Length headers with different values: [{}, {}]",
-                                prev, len,
-                            );
-                            return Err(Parse::content_length_invalid());
+                    has_con_len = true;
+                    if !is_te {
+                        let len = headers::content_length_parse(&value)
+                            .ok_or_else(Parse::content_length_invalid)?;
+                        match con_len {
+                            Some(prev) if prev != len => {
+                                debug!(
+                                    "multiple Content-Length headers with different values: [{}, {}]",
+                                    prev, len,
+                                );
+                                con_len_differs = true;
+                            }
+                            // we don't need to append this secondary length
+                            Some(_) => continue,
+                            None => con_len = Some(len),
                         }
-                        // we don't need to append this secondary length
-                        continue;
                     }
-                    decoder = DecodedLength::checked_new(len)?;
-                    con_len = Some(len);
                 }
                 header::CONNECTION => {
                     // keep_alive was previously set to default for Version
@@ -331,12 +361,56 @@ impl Http1Transaction for Server {
             return Err(Parse::transfer_encoding_invalid());
         }
 
+        // https://www.rfc-editor.org/rfc/rfc9112#section-6.1
+        // A request with both Transfer-Encoding and Content-Length may be
+        // rejected, or framed by Transfer-Encoding alone, but the connection
+        // must be closed after responding either way.
+        let mut conflicting_con_len = None;
+        if (is_te && has_con_len) || con_len_differs {
+            let policy = ctx.conflicting_framing;
+            if policy == ConflictingFraming::Reject {
+                debug!("request with conflicting framing headers, bad request");
+                return Err(if is_te {
+                    Parse::transfer_encoding_unexpected()
+                } else {
+                    Parse::content_length_invalid()
+                });
+            }
+            if !is_te && policy != ConflictingFraming::Passthrough {
+                return Err(Parse::content_length_invalid());
+            }
+            debug!("request with conflicting framing headers, removing content-length");
+            keep_alive = false;
+            con_len = None;
+            let values = match headers.entry(header::CONTENT_LENGTH) {
+                header::Entry::Occupied(entry) => entry.remove_entry_mult().1.collect(),
+                header::Entry::Vacant(_) => Vec::new(),
+            };
+            if policy == ConflictingFraming::Passthrough {
+                conflicting_con_len = Some(ConflictingContentLength::new(values));
+            }
+        }
+
+        if let Some(len) = con_len {
+            if !is_te {
+                decoder = DecodedLength::checked_new(len)?;
+            }
+        }
+
         let mut extensions = http::Extensions::default();
 
+        if let Some(conflicting_con_len) = conflicting_con_len {
+            extensions.insert(conflicting_con_len);
+        }
+
         if let Some(header_case_map) = header_case_map {
             extensions.insert(header_case_map);
         }
 
+        if let Some(raw_target) = raw_target {
+            extensions.insert(raw_target);
+        }
+
         #[cfg(feature = "ffi")]
         if let Some(header_order) = header_order {
             extensions.insert(header_order);
@@ -368,10 +442,11 @@ impl Http1Transaction for Server {
 
         let mut wrote_len = false;
 
-        // hyper currently doesn't support returning 1xx status codes as a Response
+        // hyper doesn't support returning 1xx status codes as a Response
         // This is because Service only allows returning a single Response, and
         // so if you try to reply with a e.g. 100 Continue, you have no way of
-        // replying with the latter status code response.
+        // replying with the latter status code response. They are sent with
+        // `ext::SendInformational` instead.
         let (ret, is_last) = if msg.head.subject == StatusCode::SWITCHING_PROTOCOLS {
             (Ok(()), true)
         } else if msg.req_method == &Some(Method::CONNECT) && msg.head.subject.is_success() {
@@ -469,10 +544,12 @@ impl Http1Transaction for Server {
             | Kind::Parse(Parse::Version) => StatusCode::BAD_REQUEST,
             Kind::Parse(Parse::TooLarge) => StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE,
             Kind::Parse(Parse::UriTooLong) => StatusCode::URI_TOO_LONG,
+            Kind::ExpectationFailed => StatusCode::EXPECTATION_FAILED,
+            Kind::BodyTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
             _ => return None,
         };
 
-        debug!("sending automatic response ({}) for parse error", status);
+        debug!("sending automatic response ({}) for {}", status, err);
         let msg = MessageHead {
             subject: status,
             ..Default::default()
@@ -480,6 +557,25 @@ impl Http1Transaction for Server {
         Some(msg)
     }
 
+    fn on_error_with(
+        err: &crate::Error,
+        responder: &ErrorResponder,
+    ) -> Option<(MessageHead<Self::Outgoing>, Bytes)> {
+        let status = Self::on_error(err)?.subject;
+        let (res, body) = responder.call(err, status).into_parts();
+        let mut headers = res.headers;
+        // The length comes from the body the responder gave.
+        headers.remove(header::CONTENT_LENGTH);
+        headers.remove(header::TRANSFER_ENCODING);
+        let msg = MessageHead {
+            version: Version::HTTP_11,
+            subject: res.status,
+            headers,
+            extensions: res.extensions,
+        };
+        Some((msg, body))
+    }
+
     fn is_server() -> bool {
         true
     }
@@ -1057,6 +1153,12 @@ impl Http1Transaction for Client {
 
             let mut slice = buf.split_to(len);
 
+            let raw_headers = if ctx.raw_headers {
+                Some(Client::raw_headers(&slice, &headers_indices[..headers_len]))
+            } else {
+                None
+            };
+
             if ctx
                 .h1_parser_config
                 .obsolete_multiline_headers_in_responses_are_allowed()
@@ -1128,6 +1230,10 @@ impl Http1Transaction for Client {
                 extensions.insert(header_order);
             }
 
+            if let Some(raw_headers) = raw_headers {
+                extensions.insert(raw_headers);
+            }
+
             if let Some(reason) = reason {
                 // Safety: httparse ensures that only valid reason phrase bytes are present in this
                 // field.
@@ -1153,6 +1259,9 @@ impl Http1Transaction for Client {
                 }));
             }
 
+            if head.subject == StatusCode::CONTINUE {
+                *ctx.continue_received = true;
+            }
             if head.subject.is_informational() {
                 if let Some(callback) = ctx.on_informational {
                     callback.call(head.into_response(()));
@@ -1176,7 +1285,11 @@ impl Http1Transaction for Client {
 
         *msg.req_method = Some(msg.head.subject.0.clone());
 
-        let body = Client::set_length(msg.head, msg.body);
+        let body = if msg.head.extensions.get::<VerbatimFraming>().is_some() {
+            Client::verbatim_length(&msg.head.headers, msg.body)
+        } else {
+            Client::set_length(msg.head, msg.body)
+        };
 
         let init_cap = 30 + msg.head.headers.len() * AVERAGE_HEADER_SIZE;
         dst.reserve(init_cap);
@@ -1197,7 +1310,15 @@ impl Http1Transaction for Client {
         }
         extend(dst, b"\r\n");
 
-        if let Some(orig_headers) = msg.head.extensions.get::<HeaderCaseMap>() {
+        if let Some(order) = msg.head.extensions.get::<OriginalHeaderOrder>() {
+            write_headers_in_order(
+                &msg.head.headers,
+                order,
+                msg.head.extensions.get::<HeaderCaseMap>(),
+                dst,
+                msg.title_case_headers,
+            );
+        } else if let Some(orig_headers) = msg.head.extensions.get::<HeaderCaseMap>() {
             write_headers_original_case(
                 &msg.head.headers,
                 orig_headers,
@@ -1294,6 +1415,40 @@ impl Client {
             Ok(Some((DecodedLength::CLOSE_DELIMITED, false)))
         }
     }
+    /// Picks the body encoding from the framing headers the user set,
+    /// leaving them as they are.
+    fn verbatim_length(headers: &HeaderMap, body: Option<BodyLength>) -> Encoder {
+        if headers.contains_key(header::TRANSFER_ENCODING) {
+            return if headers::is_chunked(headers.get_all(header::TRANSFER_ENCODING).iter()) {
+                Client::allow_trailer_fields(Encoder::chunked(), headers)
+            } else {
+                // Nothing says where the body ends, so it goes out as it is,
+                // and the connection can't be used again.
+                Encoder::close_delimited()
+            };
+        }
+        if let Some(len) = headers::content_length_parse_all(headers) {
+            return Encoder::length(len);
+        }
+        match body {
+            None | Some(BodyLength::Known(0)) => Encoder::length(0),
+            Some(_) => Encoder::close_delimited(),
+        }
+    }
+
+    fn allow_trailer_fields(encoder: Encoder, headers: &HeaderMap) -> Encoder {
+        if encoder.is_chunked() {
+            let allowed_trailer_fields: Vec<HeaderValue> =
+                headers.get_all(header::TRAILER).iter().cloned().collect();
+
+            if !allowed_trailer_fields.is_empty() {
+                return encoder.into_chunked_with_trailing_fields(allowed_trailer_fields);
+            }
+        }
+
+        encoder
+    }
+
     fn set_length(head: &mut RequestHead, body: Option<BodyLength>) -> Encoder {
         let body = if let Some(body) = body {
             body
@@ -1385,18 +1540,7 @@ impl Client {
             }
         };
 
-        let encoder = encoder.map(|enc| {
-            if enc.is_chunked() {
-                let allowed_trailer_fields: Vec<HeaderValue> =
-                    headers.get_all(header::TRAILER).iter().cloned().collect();
-
-                if !allowed_trailer_fields.is_empty() {
-                    return enc.into_chunked_with_trailing_fields(allowed_trailer_fields);
-                }
-            }
-
-            enc
-        });
+        let encoder = encoder.map(|enc| Client::allow_trailer_fields(enc, headers));
 
         // This is because we need a second mutable borrow to remove
         // content-length header.
@@ -1419,6 +1563,28 @@ impl Client {
         set_content_length(headers, len)
     }
 
+    fn raw_headers(head: &[u8], headers: &[MaybeUninit<HeaderIndices>]) -> RawHeaders {
+        let lines = headers
+            .iter()
+            .map(|header| {
+                // SAFETY: caller only passes the initialized headers
+                let header = unsafe { header.assume_init_ref() };
+                // a line ends after the first newline past its value, which
+                // also covers any obs-folded text within the value
+                let end = head[header.value.1..]
+                    .iter()
+                    .position(|b| *b == b'\n')
+                    .map_or(head.len(), |i| header.value.1 + i + 1);
+                RawHeaderIndices {
+                    name: header.name.0..header.name.1,
+                    value: header.value.0..header.value.1,
+                    line: header.name.0..end,
+                }
+            })
+            .collect();
+        RawHeaders::new(Bytes::copy_from_slice(head), lines)
+    }
+
     fn obs_fold_line(all: &mut [u8], idx: &mut HeaderIndices) {
         // If the value has obs-folded text, then in-place shift the bytes out
         // of here.
@@ -1596,7 +1762,7 @@ fn write_headers_original_case(
 
         for value in headers.get_all(name) {
             if let Some(orig_name) = names.next() {
-                extend(dst, orig_name.as_ref());
+                extend(dst, orig_name);
             } else if title_case_headers {
                 title_case(dst, name.as_str().as_bytes());
             } else {
@@ -1615,6 +1781,52 @@ fn write_headers_original_case(
     }
 }
 
+#[cold]
+#[cfg(feature = "client")]
+fn write_headers_in_order(
+    headers: &HeaderMap,
+    order: &OriginalHeaderOrder,
+    orig_case: Option<&HeaderCaseMap>,
+    dst: &mut Vec<u8>,
+    title_case_headers: bool,
+) {
+    let write_header = |dst: &mut Vec<u8>, name: &HeaderName, idx: usize, value: &HeaderValue| {
+        if let Some(orig_name) = orig_case.and_then(|map| map.get_all(name).nth(idx)) {
+            extend(dst, orig_name);
+        } else if title_case_headers {
+            title_case(dst, name.as_str().as_bytes());
+        } else {
+            extend(dst, name.as_str().as_bytes());
+        }
+
+        if value.is_empty() {
+            extend(dst, b":\r\n");
+        } else {
+            extend(dst, b": ");
+            extend(dst, value.as_bytes());
+            extend(dst, b"\r\n");
+        }
+    };
+
+    // Entries of the order without a matching value are skipped, and
+    // values the order leaves out are written after the rest.
+    let mut written = std::collections::HashSet::new();
+    for (name, idx) in order.get_in_order() {
+        if let Some(value) = headers.get_all(name).iter().nth(*idx) {
+            if written.insert((name, *idx)) {
+                write_header(dst, name, *idx, value);
+            }
+        }
+    }
+    for name in headers.keys() {
+        for (idx, value) in headers.get_all(name).iter().enumerate() {
+            if !written.contains(&(name, idx)) {
+                write_header(dst, name, idx, value);
+            }
+        }
+    }
+}
+
 #[cfg(feature = "client")]
 struct FastWrite<'a>(&'a mut Vec<u8>);
 
@@ -1656,12 +1868,21 @@ mod tests {
                 req_method: &mut method,
                 h1_parser_config: Default::default(),
                 h1_max_headers: None,
+                h1_max_header_size: None,
                 preserve_header_case: false,
                 #[cfg(feature = "ffi")]
                 preserve_header_order: false,
                 h09_responses: false,
                 #[cfg(feature = "client")]
                 on_informational: &mut None,
+                #[cfg(feature = "client")]
+                continue_received: &mut false,
+                #[cfg(feature = "client")]
+                raw_headers: false,
+                #[cfg(feature = "server")]
+                allow_invalid_request_target: false,
+                #[cfg(feature = "server")]
+                conflicting_framing: Default::default(),
             },
         )
         .unwrap()
@@ -1675,6 +1896,67 @@ mod tests {
         assert_eq!(method, Some(crate::Method::GET));
     }
 
+    #[cfg(feature = "server")]
+    #[test]
+    fn test_parse_request_asterisk_form() {
+        let _ = pretty_env_logger::try_init();
+        let mut raw = BytesMut::from("OPTIONS * HTTP/1.1\r\nHost: hyper.rs\r\n\r\n");
+        let mut method = None;
+        let msg = Server::parse(
+            &mut raw,
+            ParseContext {
+                cached_headers: &mut None,
+                req_method: &mut method,
+                h1_parser_config: Default::default(),
+                h1_max_headers: None,
+                h1_max_header_size: None,
+                preserve_header_case: false,
+                #[cfg(feature = "ffi")]
+                preserve_header_order: false,
+                h09_responses: false,
+                #[cfg(feature = "client")]
+                on_informational: &mut None,
+                #[cfg(feature = "client")]
+                continue_received: &mut false,
+                #[cfg(feature = "client")]
+                raw_headers: false,
+                #[cfg(feature = "server")]
+                allow_invalid_request_target: false,
+                #[cfg(feature = "server")]
+                conflicting_framing: Default::default(),
+            },
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(msg.head.subject.0, crate::Method::OPTIONS);
+        assert_eq!(msg.head.subject.1, "*");
+        assert_eq!(msg.head.subject.1.path(), "*");
+
+        let mut raw = BytesMut::from("GET * HTTP/1.1\r\nHost: hyper.rs\r\n\r\n");
+        let ctx = ParseContext {
+            cached_headers: &mut None,
+            req_method: &mut None,
+            h1_parser_config: Default::default(),
+            h1_max_headers: None,
+            h1_max_header_size: None,
+            preserve_header_case: false,
+            #[cfg(feature = "ffi")]
+            preserve_header_order: false,
+            h09_responses: false,
+            #[cfg(feature = "client")]
+            on_informational: &mut None,
+            #[cfg(feature = "client")]
+            continue_received: &mut false,
+            #[cfg(feature = "client")]
+            raw_headers: false,
+            #[cfg(feature = "server")]
+            allow_invalid_request_target: false,
+            #[cfg(feature = "server")]
+            conflicting_framing: Default::default(),
+        };
+        Server::parse(&mut raw, ctx).unwrap_err();
+    }
+
     #[test]
     fn test_parse_response() {
         let _ = pretty_env_logger::try_init();
@@ -1684,12 +1966,21 @@ mod tests {
             req_method: &mut Some(crate::Method::GET),
             h1_parser_config: Default::default(),
             h1_max_headers: None,
+            h1_max_header_size: None,
             preserve_header_case: false,
             #[cfg(feature = "ffi")]
             preserve_header_order: false,
             h09_responses: false,
             #[cfg(feature = "client")]
             on_informational: &mut None,
+            #[cfg(feature = "client")]
+            continue_received: &mut false,
+            #[cfg(feature = "client")]
+            raw_headers: false,
+            #[cfg(feature = "server")]
+            allow_invalid_request_target: false,
+            #[cfg(feature = "server")]
+            conflicting_framing: Default::default(),
         };
         let msg = Client::parse(&mut raw, ctx).unwrap().unwrap();
         assert_eq!(raw.len(), 0);
@@ -1708,12 +1999,21 @@ mod tests {
             req_method: &mut None,
             h1_parser_config: Default::default(),
             h1_max_headers: None,
+            h1_max_header_size: None,
             preserve_header_case: false,
             #[cfg(feature = "ffi")]
             preserve_header_order: false,
             h09_responses: false,
             #[cfg(feature = "client")]
             on_informational: &mut None,
+            #[cfg(feature = "client")]
+            continue_received: &mut false,
+            #[cfg(feature = "client")]
+            raw_headers: false,
+            #[cfg(feature = "server")]
+            allow_invalid_request_target: false,
+            #[cfg(feature = "server")]
+            conflicting_framing: Default::default(),
         };
         Server::parse(&mut raw, ctx).unwrap_err();
     }
@@ -1729,12 +2029,21 @@ mod tests {
             req_method: &mut Some(crate::Method::GET),
             h1_parser_config: Default::default(),
             h1_max_headers: None,
+            h1_max_header_size: None,
             preserve_header_case: false,
             #[cfg(feature = "ffi")]
             preserve_header_order: false,
             h09_responses: true,
             #[cfg(feature = "client")]
             on_informational: &mut None,
+            #[cfg(feature = "client")]
+            continue_received: &mut false,
+            #[cfg(feature = "client")]
+            raw_headers: false,
+            #[cfg(feature = "server")]
+            allow_invalid_request_target: false,
+            #[cfg(feature = "server")]
+            conflicting_framing: Default::default(),
         };
         let msg = Client::parse(&mut raw, ctx).unwrap().unwrap();
         assert_eq!(raw, H09_RESPONSE);
@@ -1752,12 +2061,21 @@ mod tests {
             req_method: &mut Some(crate::Method::GET),
             h1_parser_config: Default::default(),
             h1_max_headers: None,
+            h1_max_header_size: None,
             preserve_header_case: false,
             #[cfg(feature = "ffi")]
             preserve_header_order: false,
             h09_responses: false,
             #[cfg(feature = "client")]
             on_informational: &mut None,
+            #[cfg(feature = "client")]
+            continue_received: &mut false,
+            #[cfg(feature = "client")]
+            raw_headers: false,
+            #[cfg(feature = "server")]
+            allow_invalid_request_target: false,
+            #[cfg(feature = "server")]
+            conflicting_framing: Default::default(),
         };
         Client::parse(&mut raw, ctx).unwrap_err();
         assert_eq!(raw, H09_RESPONSE);
@@ -1779,12 +2097,21 @@ mod tests {
             req_method: &mut Some(crate::Method::GET),
             h1_parser_config,
             h1_max_headers: None,
+            h1_max_header_size: None,
             preserve_header_case: false,
             #[cfg(feature = "ffi")]
             preserve_header_order: false,
             h09_responses: false,
             #[cfg(feature = "client")]
             on_informational: &mut None,
+            #[cfg(feature = "client")]
+            continue_received: &mut false,
+            #[cfg(feature = "client")]
+            raw_headers: false,
+            #[cfg(feature = "server")]
+            allow_invalid_request_target: false,
+            #[cfg(feature = "server")]
+            conflicting_framing: Default::default(),
         };
         let msg = Client::parse(&mut raw, ctx).unwrap().unwrap();
         assert_eq!(raw.len(), 0);
@@ -1794,6 +2121,64 @@ mod tests {
         assert_eq!(msg.head.headers["Access-Control-Allow-Credentials"], "true");
     }
 
+    #[test]
+    fn test_parse_response_raw_headers() {
+        use httparse::ParserConfig;
+
+        let _ = pretty_env_logger::try_init();
+        let head: &[u8] = b"HTTP/1.1 200 OK\r\n\
+            X-Dup: a\r\n\
+            x-dup:b  \r\n\
+            X-Folded: one\r\n two\r\n\
+            X-Bytes: \xff\r\n\
+            \r\n";
+        let mut raw = BytesMut::from(head);
+        let mut h1_parser_config = ParserConfig::default();
+        h1_parser_config.allow_obsolete_multiline_headers_in_responses(true);
+        let ctx = ParseContext {
+            cached_headers: &mut None,
+            req_method: &mut Some(crate::Method::GET),
+            h1_parser_config,
+            h1_max_headers: None,
+            h1_max_header_size: None,
+            preserve_header_case: false,
+            #[cfg(feature = "ffi")]
+            preserve_header_order: false,
+            h09_responses: false,
+            #[cfg(feature = "client")]
+            on_informational: &mut None,
+            #[cfg(feature = "client")]
+            continue_received: &mut false,
+            #[cfg(feature = "client")]
+            raw_headers: true,
+            #[cfg(feature = "server")]
+            allow_invalid_request_target: false,
+            #[cfg(feature = "server")]
+            conflicting_framing: Default::default(),
+        };
+        let msg = Client::parse(&mut raw, ctx).unwrap().unwrap();
+        assert_eq!(msg.head.headers["x-folded"], "one two");
+
+        let raw_headers = msg.head.extensions.get::<RawHeaders>().unwrap();
+        assert_eq!(raw_headers.as_bytes(), head);
+        assert_eq!(raw_headers.len(), 4);
+        let lines = raw_headers
+            .iter()
+            .map(|line| (line.name(), line.value(), line.as_bytes()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            [
+                (&b"X-Dup"[..], &b"a"[..], &b"X-Dup: a\r\n"[..]),
+                (b"x-dup", b"b", b"x-dup:b  \r\n"),
+                (b"X-Folded", b"one\r\n two", b"X-Folded: one\r\n two\r\n"),
+                (b"X-Bytes", b"\xff", b"X-Bytes: \xff\r\n"),
+            ]
+        );
+        let first = raw_headers.iter().next().unwrap();
+        assert_eq!(&head[first.range()], first.as_bytes());
+    }
+
     #[test]
     fn test_parse_reject_response_with_spaces_before_colons() {
         let _ = pretty_env_logger::try_init();
@@ -1803,12 +2188,21 @@ mod tests {
             req_method: &mut Some(crate::Method::GET),
             h1_parser_config: Default::default(),
             h1_max_headers: None,
+            h1_max_header_size: None,
             preserve_header_case: false,
             #[cfg(feature = "ffi")]
             preserve_header_order: false,
             h09_responses: false,
             #[cfg(feature = "client")]
             on_informational: &mut None,
+            #[cfg(feature = "client")]
+            continue_received: &mut false,
+            #[cfg(feature = "client")]
+            raw_headers: false,
+            #[cfg(feature = "server")]
+            allow_invalid_request_target: false,
+            #[cfg(feature = "server")]
+            conflicting_framing: Default::default(),
         };
         Client::parse(&mut raw, ctx).unwrap_err();
     }
@@ -1823,12 +2217,21 @@ mod tests {
             req_method: &mut None,
             h1_parser_config: Default::default(),
             h1_max_headers: None,
+            h1_max_header_size: None,
             preserve_header_case: true,
             #[cfg(feature = "ffi")]
             preserve_header_order: false,
             h09_responses: false,
             #[cfg(feature = "client")]
             on_informational: &mut None,
+            #[cfg(feature = "client")]
+            continue_received: &mut false,
+            #[cfg(feature = "client")]
+            raw_headers: false,
+            #[cfg(feature = "server")]
+            allow_invalid_request_target: false,
+            #[cfg(feature = "server")]
+            conflicting_framing: Default::default(),
         };
         let parsed_message = Server::parse(&mut raw, ctx).unwrap().unwrap();
         let orig_headers = parsed_message
@@ -1862,12 +2265,21 @@ mod tests {
                     req_method: &mut None,
                     h1_parser_config: Default::default(),
                     h1_max_headers: None,
+                    h1_max_header_size: None,
                     preserve_header_case: false,
                     #[cfg(feature = "ffi")]
                     preserve_header_order: false,
                     h09_responses: false,
                     #[cfg(feature = "client")]
                     on_informational: &mut None,
+                    #[cfg(feature = "client")]
+                    continue_received: &mut false,
+                    #[cfg(feature = "client")]
+                    raw_headers: false,
+                    #[cfg(feature = "server")]
+                    allow_invalid_request_target: false,
+                    #[cfg(feature = "server")]
+                    conflicting_framing: Default::default(),
                 },
             )
             .expect("parse ok")
@@ -1883,12 +2295,21 @@ mod tests {
                     req_method: &mut None,
                     h1_parser_config: Default::default(),
                     h1_max_headers: None,
+                    h1_max_header_size: None,
                     preserve_header_case: false,
                     #[cfg(feature = "ffi")]
                     preserve_header_order: false,
                     h09_responses: false,
                     #[cfg(feature = "client")]
                     on_informational: &mut None,
+                    #[cfg(feature = "client")]
+                    continue_received: &mut false,
+                    #[cfg(feature = "client")]
+                    raw_headers: false,
+                    #[cfg(feature = "server")]
+                    allow_invalid_request_target: false,
+                    #[cfg(feature = "server")]
+                    conflicting_framing: Default::default(),
                 },
             )
             .expect_err(comment)
@@ -2098,6 +2519,103 @@ mod tests {
         );
     }
 
+    #[cfg(feature = "server")]
+    #[test]
+    fn test_parse_request_conflicting_framing() {
+        fn parse(
+            s: &str,
+            policy: ConflictingFraming,
+        ) -> Result<ParsedMessage<RequestLine>, Parse> {
+            let mut bytes = BytesMut::from(s);
+            Server::parse(
+                &mut bytes,
+                ParseContext {
+                    cached_headers: &mut None,
+                    req_method: &mut None,
+                    h1_parser_config: Default::default(),
+                    h1_max_headers: None,
+                    h1_max_header_size: None,
+                    preserve_header_case: false,
+                    #[cfg(feature = "ffi")]
+                    preserve_header_order: false,
+                    h09_responses: false,
+                    #[cfg(feature = "client")]
+                    on_informational: &mut None,
+                    #[cfg(feature = "client")]
+                    continue_received: &mut false,
+                    #[cfg(feature = "client")]
+                    raw_headers: false,
+                    #[cfg(feature = "server")]
+                    allow_invalid_request_target: false,
+                    #[cfg(feature = "server")]
+                    conflicting_framing: policy,
+                },
+            )
+            .map(|msg| msg.expect("parse complete"))
+        }
+
+        let te_and_cl = "\
+            POST / HTTP/1.1\r\n\
+            content-length: 10\r\n\
+            transfer-encoding: chunked\r\n\
+            \r\n\
+            ";
+        let differing_cl = "\
+            POST / HTTP/1.1\r\n\
+            content-length: 10\r\n\
+            content-length: 11\r\n\
+            \r\n\
+            ";
+
+        parse(te_and_cl, ConflictingFraming::Reject).expect_err("reject te and cl");
+        parse(differing_cl, ConflictingFraming::Reject).expect_err("reject differing cl");
+
+        let msg = parse(te_and_cl, ConflictingFraming::StripContentLength).unwrap();
+        assert_eq!(msg.decode, DecodedLength::CHUNKED);
+        assert!(!msg.keep_alive);
+        assert!(!msg.head.headers.contains_key(header::CONTENT_LENGTH));
+        assert!(msg
+            .head
+            .extensions
+            .get::<ConflictingContentLength>()
+            .is_none());
+        parse(differing_cl, ConflictingFraming::StripContentLength)
+            .expect_err("strip differing cl");
+
+        let msg = parse(te_and_cl, ConflictingFraming::Passthrough).unwrap();
+        assert_eq!(msg.decode, DecodedLength::CHUNKED);
+        assert!(!msg.keep_alive);
+        assert!(!msg.head.headers.contains_key(header::CONTENT_LENGTH));
+        let conflict = msg.head.extensions.get::<ConflictingContentLength>().unwrap();
+        assert_eq!(conflict.values(), ["10"]);
+
+        let msg = parse(differing_cl, ConflictingFraming::Passthrough).unwrap();
+        assert_eq!(msg.decode, DecodedLength::ZERO);
+        assert!(!msg.keep_alive);
+        let conflict = msg.head.extensions.get::<ConflictingContentLength>().unwrap();
+        assert_eq!(conflict.values(), ["10", "11"]);
+
+        // agreeing framing headers are left alone
+        let msg = parse(
+            "\
+            POST / HTTP/1.1\r\n\
+            content-length: 10\r\n\
+            content-length: 10\r\n\
+            \r\n\
+            ",
+            ConflictingFraming::Passthrough,
+        )
+        .unwrap();
+        assert_eq!(msg.decode, DecodedLength::new(10));
+        assert!(msg.keep_alive);
+        assert_eq!(msg.head.headers[header::CONTENT_LENGTH], "10");
+        assert!(msg
+            .head
+            .extensions
+            .get::<ConflictingContentLength>()
+            .is_none());
+    }
+
     #[test]
     fn test_decoder_response() {
         fn parse(s: &str) -> ParsedMessage<StatusCode> {
@@ -2113,12 +2631,21 @@ mod tests {
                     req_method: &mut Some(Method::GET),
                     h1_parser_config: Default::default(),
                     h1_max_headers: None,
+                    h1_max_header_size: None,
                     preserve_header_case: false,
                     #[cfg(feature = "ffi")]
                     preserve_header_order: false,
                     h09_responses: false,
                     #[cfg(feature = "client")]
                     on_informational: &mut None,
+                    #[cfg(feature = "client")]
+                    continue_received: &mut false,
+                    #[cfg(feature = "client")]
+                    raw_headers: false,
+                    #[cfg(feature = "server")]
+                    allow_invalid_request_target: false,
+                    #[cfg(feature = "server")]
+                    conflicting_framing: Default::default(),
                 }
             )
             .expect("parse ok")
@@ -2134,12 +2661,21 @@ mod tests {
                     req_method: &mut Some(m),
                     h1_parser_config: Default::default(),
                     h1_max_headers: None,
+                    h1_max_header_size: None,
                     preserve_header_case: false,
                     #[cfg(feature = "ffi")]
                     preserve_header_order: false,
                     h09_responses: false,
                     #[cfg(feature = "client")]
                     on_informational: &mut None,
+                    #[cfg(feature = "client")]
+                    continue_received: &mut false,
+                    #[cfg(feature = "client")]
+                    raw_headers: false,
+                    #[cfg(feature = "server")]
+                    allow_invalid_request_target: false,
+                    #[cfg(feature = "server")]
+                    conflicting_framing: Default::default(),
                 },
             )
             .expect("parse ok")
@@ -2155,12 +2691,21 @@ mod tests {
                     req_method: &mut Some(Method::GET),
                     h1_parser_config: Default::default(),
                     h1_max_headers: None,
+                    h1_max_header_size: None,
                     preserve_header_case: false,
                     #[cfg(feature = "ffi")]
                     preserve_header_order: false,
                     h09_responses: false,
                     #[cfg(feature = "client")]
                     on_informational: &mut None,
+                    #[cfg(feature = "client")]
+                    continue_received: &mut false,
+                    #[cfg(feature = "client")]
+                    raw_headers: false,
+                    #[cfg(feature = "server")]
+                    allow_invalid_request_target: false,
+                    #[cfg(feature = "server")]
+                    conflicting_framing: Default::default(),
                 },
             )
             .expect_err("parse should err")
@@ -2475,6 +3020,32 @@ mod tests {
         assert_eq!(vec, b"GET / HTTP/1.1\r\nContent-Length: 10\r\nContent-Type: application/json\r\n*-*: o_o\r\n\r\n".to_vec());
     }
 
+    #[test]
+    fn test_client_request_encode_asterisk_form() {
+        let mut head = MessageHead {
+            subject: RequestLine(Method::OPTIONS, http::Uri::from_static("*")),
+            ..Default::default()
+        };
+
+        let mut vec = Vec::new();
+        Client::encode(
+            Encode {
+                head: &mut head,
+                body: None,
+                #[cfg(feature = "server")]
+                keep_alive: true,
+                req_method: &mut None,
+                title_case_headers: false,
+                #[cfg(feature = "server")]
+                date_header: true,
+            },
+            &mut vec,
+        )
+        .unwrap();
+
+        assert_eq!(vec, b"OPTIONS * HTTP/1.1\r\n\r\n".to_vec());
+    }
+
     #[test]
     fn test_client_request_encode_orig_case() {
         use crate::proto::BodyLength;
@@ -2512,6 +3083,124 @@ mod tests {
                 .as_ref(),
         );
     }
+
+    #[test]
+    fn test_client_request_encode_in_order() {
+        use crate::proto::BodyLength;
+        use http::header::{HeaderValue, ACCEPT, COOKIE, USER_AGENT};
+
+        let mut head = MessageHead::default();
+        head.headers
+            .insert(USER_AGENT, HeaderValue::from_static("hyper"));
+        head.headers.append(COOKIE, HeaderValue::from_static("a=1"));
+        head.headers.insert(ACCEPT, HeaderValue::from_static("*/*"));
+        head.headers.append(COOKIE, HeaderValue::from_static("b=2"));
+
+        let mut order = OriginalHeaderOrder::default();
+        order.append(COOKIE);
+        order.append(ACCEPT);
+        order.append(COOKIE);
+        order.append(USER_AGENT);
+        head.extensions.insert(order);
+
+        let mut orig_headers = HeaderCaseMap::default();
+        orig_headers.append(COOKIE, "Cookie".into());
+        orig_headers.append(COOKIE, "COOKIE".into());
+        head.extensions.insert(orig_headers);
+
+        let mut vec = Vec::new();
+        Client::encode(
+            Encode {
+                head: &mut head,
+                body: Some(BodyLength::Known(10)),
+                #[cfg(feature = "server")]
+                keep_alive: true,
+                req_method: &mut None,
+                title_case_headers: true,
+                #[cfg(feature = "server")]
+                date_header: true,
+            },
+            &mut vec,
+        )
+        .unwrap();
+
+        // content-length was added while encoding, so it comes last
+        assert_eq!(
+            &*vec,
+            b"GET / HTTP/1.1\r\nCookie: a=1\r\nAccept: */*\r\nCOOKIE: b=2\r\nUser-Agent: hyper\r\nContent-Length: 10\r\n\r\n"
+                .as_ref(),
+        );
+    }
+
+    #[test]
+    fn test_client_request_encode_verbatim_framing() {
+        use crate::ext::VerbatimFraming;
+        use crate::proto::BodyLength;
+        use http::header::{HeaderValue, CONTENT_LENGTH, TRANSFER_ENCODING};
+
+        fn encode(headers: &[(HeaderName, &'static str)], body: Option<BodyLength>) -> (Encoder, Vec<u8>) {
+            let mut head = RequestHead::default();
+            head.subject.0 = Method::POST;
+            for (name, value) in headers {
+                head.headers
+                    .append(name.clone(), HeaderValue::from_static(value));
+            }
+            head.extensions.insert(VerbatimFraming);
+
+            let mut vec = Vec::new();
+            let encoder = Client::encode(
+                Encode {
+                    head: &mut head,
+                    body,
+                    #[cfg(feature = "server")]
+                    keep_alive: true,
+                    req_method: &mut None,
+                    title_case_headers: false,
+                    #[cfg(feature = "server")]
+                    date_header: true,
+                },
+                &mut vec,
+            )
+            .unwrap();
+            (encoder, vec)
+        }
+
+        // both are kept, and chunked wins
+        let (encoder, vec) = encode(
+            &[(CONTENT_LENGTH, "5"), (TRANSFER_ENCODING, "chunked")],
+            Some(BodyLength::Known(10)),
+        );
+        assert!(encoder.is_chunked());
+        assert_eq!(
+            &*vec,
+            b"POST / HTTP/1.1\r\ncontent-length: 5\r\ntransfer-encoding: chunked\r\n\r\n"
+                .as_ref(),
+        );
+
+        // the given length is trusted over the body's
+        let (encoder, vec) = encode(&[(CONTENT_LENGTH, "5")], Some(BodyLength::Known(10)));
+        assert_eq!(encoder, Encoder::length(5));
+        assert_eq!(
+            &*vec,
+            b"POST / HTTP/1.1\r\ncontent-length: 5\r\n\r\n".as_ref()
+        );
+
+        // nothing says where the body ends
+        let (encoder, vec) = encode(&[(TRANSFER_ENCODING, "gzip")], Some(BodyLength::Unknown));
+        assert!(encoder.is_close_delimited());
+        assert_eq!(
+            &*vec,
+            b"POST / HTTP/1.1\r\ntransfer-encoding: gzip\r\n\r\n".as_ref()
+        );
+        let (encoder, vec) = encode(&[], Some(BodyLength::Unknown));
+        assert!(encoder.is_close_delimited());
+        assert_eq!(&*vec, b"POST / HTTP/1.1\r\n\r\n".as_ref());
+
+        let (encoder, vec) = encode(&[], None);
+        assert_eq!(encoder, Encoder::length(0));
+        assert_eq!(&*vec, b"POST / HTTP/1.1\r\n\r\n".as_ref());
+    }
+
     #[test]
     fn test_client_request_encode_orig_and_title_case() {
         use crate::proto::BodyLength;
@@ -2725,12 +3414,21 @@ mod tests {
                 req_method: &mut Some(Method::GET),
                 h1_parser_config: Default::default(),
                 h1_max_headers: None,
+                h1_max_header_size: None,
                 preserve_header_case: false,
                 #[cfg(feature = "ffi")]
                 preserve_header_order: false,
                 h09_responses: false,
                 #[cfg(feature = "client")]
                 on_informational: &mut None,
+                #[cfg(feature = "client")]
+                continue_received: &mut false,
+                #[cfg(feature = "client")]
+                raw_headers: false,
+                #[cfg(feature = "server")]
+                allow_invalid_request_target: false,
+                #[cfg(feature = "server")]
+                conflicting_framing: Default::default(),
             },
         )
         .expect("parse ok")
@@ -2769,12 +3467,21 @@ mod tests {
                         req_method: &mut None,
                         h1_parser_config: Default::default(),
                         h1_max_headers: max_headers,
+                        h1_max_header_size: None,
                         preserve_header_case: false,
                         #[cfg(feature = "ffi")]
                         preserve_header_order: false,
                         h09_responses: false,
                         #[cfg(feature = "client")]
                         on_informational: &mut None,
+                        #[cfg(feature = "client")]
+                        continue_received: &mut false,
+                        #[cfg(feature = "client")]
+                        raw_headers: false,
+                        #[cfg(feature = "server")]
+                        allow_invalid_request_target: false,
+                        #[cfg(feature = "server")]
+                        conflicting_framing: Default::default(),
                     },
                 );
                 if should_success {
@@ -2793,12 +3500,21 @@ mod tests {
                         req_method: &mut None,
                         h1_parser_config: Default::default(),
                         h1_max_headers: max_headers,
+                        h1_max_header_size: None,
                         preserve_header_case: false,
                         #[cfg(feature = "ffi")]
                         preserve_header_order: false,
                         h09_responses: false,
                         #[cfg(feature = "client")]
                         on_informational: &mut None,
+                        #[cfg(feature = "client")]
+                        continue_received: &mut false,
+                        #[cfg(feature = "client")]
+                        raw_headers: false,
+                        #[cfg(feature = "server")]
+                        allow_invalid_request_target: false,
+                        #[cfg(feature = "server")]
+                        conflicting_framing: Default::default(),
                     },
                 );
                 if should_success {
@@ -2962,12 +3678,21 @@ mod tests {
                     req_method: &mut None,
                     h1_parser_config: Default::default(),
                     h1_max_headers: None,
+                    h1_max_header_size: None,
                     preserve_header_case: false,
                     #[cfg(feature = "ffi")]
                     preserve_header_order: false,
                     h09_responses: false,
                     #[cfg(feature = "client")]
                     on_informational: &mut None,
+                    #[cfg(feature = "client")]
+                    continue_received: &mut false,
+                    #[cfg(feature = "client")]
+                    raw_headers: false,
+                    #[cfg(feature = "server")]
+                    allow_invalid_request_target: false,
+                    #[cfg(feature = "server")]
+                    conflicting_framing: Default::default(),
                 },
             )
             .unwrap()
@@ -3007,12 +3732,21 @@ mod tests {
                     req_method: &mut None,
                     h1_parser_config: Default::default(),
                     h1_max_headers: None,
+                    h1_max_header_size: None,
                     preserve_header_case: false,
                     #[cfg(feature = "ffi")]
                     preserve_header_order: false,
                     h09_responses: false,
                     #[cfg(feature = "client")]
                     on_informational: &mut None,
+                    #[cfg(feature = "client")]
+                    continue_received: &mut false,
+                    #[cfg(feature = "client")]
+                    raw_headers: false,
+                    #[cfg(feature = "server")]
+                    allow_invalid_request_target: false,
+                    #[cfg(feature = "server")]
+                    conflicting_framing: Default::default(),
                 },
             )
             .unwrap()
@@ -3,13 +3,14 @@ use std::{
     future::Future,
     marker::PhantomData,
     pin::Pin,
+    sync::{Arc, Mutex, Weak},
     task::{Context, Poll},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use crate::{
-    rt::{Read, Stats, Write},
-    HttpConnectionStats,
+    rt::{ConnectionStats, Read, Sleep, Stats, Write},
+    H2Settings, HttpConnectionStats, RequestStats, RetryStats,
 };
 use bytes::Bytes;
 use futures_channel::mpsc::{Receiver, Sender};
@@ -20,14 +21,16 @@ use h2::SendStream;
 use http::{Method, StatusCode};
 use pin_project_lite::pin_project;
 
-use super::ping::{Ponger, Recorder};
-use super::{ping, H2Upgraded, PipeToSendStream, SendBuf};
+use super::h2c::H2c;
+use super::ping::{LazyPonger, Recorder};
+use super::tap::{ByteCounts, FrameTap, Frames, SharedFrames, StreamFrames};
+use super::{ping, PRIORITY, H2Upgraded, PipeToSendStream, SendBuf};
 use crate::body::{Body, Incoming as IncomingBody};
 use crate::client::dispatch::{Callback, SendWhen, TrySendError};
-use crate::common::either::Either;
 use crate::common::io::Compat;
-use crate::common::time::Time;
-use crate::ext::Protocol;
+use crate::common::stats::{next_connection_id, CheckoutStart, Clock, Observer, OnBodyEnd, OnStats, Retries};
+use crate::common::time::{Deadline, Time};
+use crate::ext::{AltSvc, Priority, Protocol, StreamId};
 use crate::headers;
 use crate::proto::h2::UpgradedSendStream;
 use crate::proto::Dispatched;
@@ -68,6 +71,8 @@ const DEFAULT_INITIAL_MAX_SEND_STREAMS: usize = 100;
 #[derive(Clone, Debug)]
 pub(crate) struct Config {
     pub(crate) adaptive_window: bool,
+    pub(crate) adaptive_window_max: Option<u32>,
+    pub(crate) adaptive_window_ping_interval: Option<Duration>,
     pub(crate) initial_conn_window_size: u32,
     pub(crate) initial_stream_window_size: u32,
     pub(crate) initial_max_send_streams: usize,
@@ -81,12 +86,18 @@ pub(crate) struct Config {
     pub(crate) max_pending_accept_reset_streams: Option<usize>,
     pub(crate) header_table_size: Option<u32>,
     pub(crate) max_concurrent_streams: Option<u32>,
+    pub(crate) on_stats: Option<OnStats>,
+    pub(crate) observer: Option<Observer>,
+    pub(crate) collect_stats: bool,
+    pub(crate) stats_clock: Clock,
 }
 
 impl Default for Config {
     fn default() -> Config {
         Config {
             adaptive_window: false,
+            adaptive_window_max: None,
+            adaptive_window_ping_interval: None,
             initial_conn_window_size: DEFAULT_CONN_WINDOW,
             initial_stream_window_size: DEFAULT_STREAM_WINDOW,
             initial_max_send_streams: DEFAULT_INITIAL_MAX_SEND_STREAMS,
@@ -100,6 +111,10 @@ impl Default for Config {
             max_pending_accept_reset_streams: None,
             header_table_size: None,
             max_concurrent_streams: None,
+            on_stats: None,
+            observer: None,
+            collect_stats: true,
+            stats_clock: Clock::default(),
         }
     }
 }
@@ -131,6 +146,26 @@ fn new_builder(config: &Config) -> Builder {
     builder
 }
 
+/// Returns the `HTTP2-Settings` header value for an `h2c` upgrade, holding
+/// the settings `new_builder` will send.
+#[cfg(feature = "http1")]
+pub(crate) fn h2c_settings(config: &Config) -> http::HeaderValue {
+    let mut settings = Vec::new();
+    if let Some(size) = config.header_table_size {
+        settings.push((0x1, size));
+    }
+    settings.push((0x2, 0));
+    if let Some(max) = config.max_concurrent_streams {
+        settings.push((0x3, max));
+    }
+    settings.push((0x4, config.initial_stream_window_size));
+    if let Some(max) = config.max_frame_size {
+        settings.push((0x5, max));
+    }
+    settings.push((0x6, config.max_header_list_size));
+    http::HeaderValue::try_from(super::h2c::encode_settings(&settings)).expect("base64url is a valid header value")
+}
+
 fn new_ping_config(config: &Config) -> ping::Config {
     ping::Config {
         bdp_initial_window: if config.adaptive_window {
@@ -138,6 +173,8 @@ fn new_ping_config(config: &Config) -> ping::Config {
         } else {
             None
         },
+        bdp_max_window: config.adaptive_window_max,
+        bdp_ping_interval: config.adaptive_window_ping_interval,
         keep_alive_interval: config.keep_alive_interval,
         keep_alive_timeout: config.keep_alive_timeout,
         keep_alive_while_idle: config.keep_alive_while_idle,
@@ -145,7 +182,8 @@ fn new_ping_config(config: &Config) -> ping::Config {
 }
 
 pub(crate) async fn handshake<T, B, E>(
-    io: T,
+    mut io: T,
+    h2c_read_buf: Option<Bytes>,
     req_rx: ClientRx<B>,
     config: &Config,
     mut exec: E,
@@ -158,8 +196,23 @@ where
     E: Http2ClientConnExec<B, T> + Unpin,
     B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
 {
+    // The tap always runs, to keep up with the server's settings, but only
+    // looks at every frame for stats.
+    let frames = Frames::shared(config.stats_clock.clone());
+    let observer = config.observer.clone().filter(|_| config.collect_stats);
+    if let Some(ref observer) = observer {
+        observer.connected(&mut io);
+    }
+    // The transport is set up by now, so what it knows of itself is read
+    // once, for the stats of the streams to come.
+    let transport = if config.collect_stats { io.stats() } else { None };
+    let bytes = Arc::new(ByteCounts::default());
+    let io = match h2c_read_buf {
+        Some(read_buf) => H2c::upgraded(Compat::new(io), read_buf),
+        None => H2c::direct(Compat::new(io)),
+    };
     let (h2_tx, mut conn) = new_builder(config)
-        .handshake::<_, SendBuf<B::Data>>(Compat::new(io))
+        .handshake::<_, SendBuf<B::Data>>(FrameTap::client(io, frames.clone(), bytes.clone(), config.collect_stats))
         .await
         .map_err(crate::Error::new_h2)?;
 
@@ -172,15 +225,29 @@ where
 
     let ping_config = new_ping_config(config);
 
-    let (conn, ping) = if ping_config.is_enabled() {
-        let pp = conn.ping_pong().expect("conn.ping_pong");
-        let (recorder, ponger) = ping::channel(pp, ping_config, timer);
-
-        let conn: Conn<_, B> = Conn::new(ponger, conn);
-        (Either::left(conn), recorder)
+    let pp = conn.ping_pong().expect("conn.ping_pong");
+    let (ping, pinger, ponger) = if ping_config.is_enabled() {
+        let (recorder, ponger) = ping::channel(pp, ping_config, timer.clone());
+        (recorder, ponger.pinger(), LazyPonger::started(ponger))
     } else {
-        (Either::right(conn), ping::disabled())
+        // Nothing needs to ping until the user does.
+        let (pinger, ponger) = LazyPonger::new(pp, ping_config, timer.clone());
+        (ping::disabled(), pinger, ponger)
     };
+    let conn: Conn<_, B> = Conn::new(ponger, conn);
+
+    // Only the task's own recorder holds this until streams are opened.
+    let open_streams = Arc::new(());
+    let streams = Streams {
+        max: {
+            let h2_tx = h2_tx.clone();
+            Arc::new(move || h2_tx.current_max_send_streams())
+        },
+        open: Arc::downgrade(&open_streams),
+        frames: frames.clone(),
+        pinger,
+    };
+    let ping = ping.with_open_streams(open_streams);
     let conn: ConnMapErr<T, B> = ConnMapErr {
         conn,
         is_terminated: false,
@@ -198,6 +265,17 @@ where
         h2_tx,
         req_rx,
         fut_ctx: None,
+        on_stats: config.on_stats.clone(),
+        observer,
+        collect_stats: config.collect_stats,
+        clock: config.stats_clock.clone(),
+        frames: Some(frames).filter(|_| config.collect_stats),
+        transport,
+        bytes,
+        connection_id: next_connection_id(),
+        request_count: 0,
+        timer,
+        streams,
         marker: PhantomData,
     })
 }
@@ -207,10 +285,9 @@ pin_project! {
     where
         B: Body,
     {
+        ponger: LazyPonger,
         #[pin]
-        ponger: Ponger,
-        #[pin]
-        conn: Connection<Compat<T>, SendBuf<<B as Body>::Data>>,
+        conn: Connection<FrameTap<H2c<Compat<T>>>, SendBuf<<B as Body>::Data>>,
     }
 }
 
@@ -219,7 +296,7 @@ where
     B: Body,
     T: Read + Write + Stats + Unpin,
 {
-    fn new(ponger: Ponger, conn: Connection<Compat<T>, SendBuf<<B as Body>::Data>>) -> Self {
+    fn new(ponger: LazyPonger, conn: Connection<FrameTap<H2c<Compat<T>>>, SendBuf<<B as Body>::Data>>) -> Self {
         Conn { ponger, conn }
     }
 }
@@ -259,7 +336,7 @@ pin_project! {
         T: Unpin,
     {
         #[pin]
-        conn: Either<Conn<T, B>, Connection<Compat<T>, SendBuf<<B as Body>::Data>>>,
+        conn: Conn<T, B>,
         #[pin]
         is_terminated: bool,
     }
@@ -417,6 +494,14 @@ where
     body_tx: SendStream<SendBuf<B::Data>>,
     body: B,
     cb: Callback<Request<B>, (HttpConnectionStats, Response<IncomingBody>)>,
+    poll_start: Option<Instant>,
+    checkout_start: Option<Instant>,
+    headers_written: Option<Instant>,
+    idle_before_reuse: Option<Duration>,
+    stream: Option<StreamFrames>,
+    request_count: u64,
+    deadline: Option<Instant>,
+    retries: Vec<RetryStats>,
 }
 
 impl<B: Body> Unpin for FutCtx<B> {}
@@ -433,9 +518,62 @@ where
     h2_tx: SendRequest<SendBuf<B::Data>>,
     req_rx: ClientRx<B>,
     fut_ctx: Option<FutCtx<B>>,
+    on_stats: Option<OnStats>,
+    observer: Option<Observer>,
+    collect_stats: bool,
+    clock: Clock,
+    frames: Option<SharedFrames>,
+    transport: Option<ConnectionStats>,
+    bytes: Arc<ByteCounts>,
+    connection_id: u64,
+    request_count: u64,
+    timer: Time,
+    streams: Streams,
     marker: PhantomData<T>,
 }
 
+/// Lets the senders of a connection see how many streams it has open, how
+/// many it may have, and what else the server has settled.
+#[derive(Clone)]
+pub(crate) struct Streams {
+    max: Arc<dyn Fn() -> usize + Send + Sync>,
+    open: Weak<()>,
+    frames: SharedFrames,
+    pinger: ping::Pinger,
+}
+
+impl Streams {
+    pub(crate) fn max_concurrent(&self) -> usize {
+        (self.max)()
+    }
+
+    pub(crate) fn current(&self) -> usize {
+        // Every stream's recorders hold a reference, and so does the
+        // connection task's own.
+        self.open.strong_count().saturating_sub(1)
+    }
+
+    pub(crate) fn peer_settings(&self) -> Option<H2Settings> {
+        self.frames.lock().unwrap().current_settings()
+    }
+
+    pub(crate) fn origin_set(&self) -> Option<Vec<String>> {
+        self.frames.lock().unwrap().origin_set()
+    }
+
+    pub(crate) fn alt_svc(&self) -> Vec<AltSvc> {
+        self.frames.lock().unwrap().alt_svc()
+    }
+
+    pub(crate) fn on_go_away(&self) -> oneshot::Receiver<(u32, u32)> {
+        self.frames.lock().unwrap().on_go_away()
+    }
+
+    pub(crate) fn ping(&self) -> ping::Rtt {
+        self.pinger.ping()
+    }
+}
+
 impl<B, E, T> ClientTask<B, E, T>
 where
     B: Body + 'static,
@@ -446,6 +584,10 @@ where
     pub(crate) fn is_extended_connect_protocol_enabled(&self) -> bool {
         self.h2_tx.is_extended_connect_protocol_enabled()
     }
+
+    pub(crate) fn streams(&self) -> Streams {
+        self.streams.clone()
+    }
 }
 
 pin_project! {
@@ -459,6 +601,9 @@ pin_project! {
         conn_drop_ref: Option<Sender<Infallible>>,
         #[pin]
         ping: Option<Recorder>,
+        body_written: Option<Arc<Mutex<Option<Instant>>>>,
+        observer: Option<Observer>,
+        clock: Clock,
     }
 }
 
@@ -474,8 +619,19 @@ where
 
         match Pin::new(&mut this.pipe).poll(cx) {
             Poll::Ready(result) => {
-                if let Err(_e) = result {
-                    debug!("client request body error: {}", _e);
+                match result {
+                    Ok(()) => {
+                        if let Some(body_written) = this.body_written.take() {
+                            let now = this.clock.now();
+                            *body_written.lock().unwrap() = Some(now);
+                            if let Some(ref observer) = this.observer {
+                                observer.on_body_written(now);
+                            }
+                        }
+                    }
+                    Err(_e) => {
+                        debug!("client request body error: {}", _e);
+                    }
                 }
                 drop(this.conn_drop_ref.take().expect("Future polled twice"));
                 drop(this.ping.take().expect("Future polled twice"));
@@ -497,15 +653,28 @@ where
 {
     fn poll_pipe(&mut self, f: FutCtx<B>, cx: &mut Context<'_>) {
         let ping = self.ping.clone();
+        let collect_stats = f.poll_start.is_some();
+        let mut body_written = BodyWritten::At(None);
 
         let send_stream = if !f.is_connect {
             if !f.eos {
-                let mut pipe = PipeToSendStream::new(f.body, f.body_tx);
+                let on_stall = f.stream.as_ref().map(|s| s.on_stall());
+                let mut pipe = PipeToSendStream::new(f.body, f.body_tx)
+                    .with_on_stall(on_stall)
+                    .with_deadline(f.deadline.map(|at| self.timer.sleep_until(at)));
 
                 // eagerly see if the body pipe is ready and
                 // can thus skip allocating in the executor
                 match Pin::new(&mut pipe).poll(cx) {
-                    Poll::Ready(_) => (),
+                    Poll::Ready(result) => {
+                        if collect_stats && result.is_ok() {
+                            let now = self.clock.now();
+                            body_written = BodyWritten::At(Some(now));
+                            if let Some(ref observer) = self.observer {
+                                observer.on_body_written(now);
+                            }
+                        }
+                    }
                     Poll::Pending => {
                         let conn_drop_ref = self.conn_drop_ref.clone();
                         // keep the ping recorder's knowledge of an
@@ -513,16 +682,29 @@ where
                         // still sending...
                         let ping = ping.clone();
 
+                        let slot = if collect_stats {
+                            let slot = Arc::new(Mutex::new(None));
+                            body_written = BodyWritten::Pending(slot.clone());
+                            Some(slot)
+                        } else {
+                            None
+                        };
+
                         let pipe = PipeMap {
                             pipe,
                             conn_drop_ref: Some(conn_drop_ref),
                             ping: Some(ping),
+                            body_written: slot,
+                            observer: self.observer.clone(),
+                            clock: self.clock.clone(),
                         };
                         // Clear send task
                         self.executor
                             .execute_h2_future(H2ClientFuture::Pipe { pipe });
                     }
                 }
+            } else {
+                body_written = BodyWritten::At(f.headers_written);
             }
 
             None
@@ -536,6 +718,25 @@ where
                     fut: f.fut,
                     ping: Some(ping),
                     send_stream: Some(send_stream),
+                    deadline: f.deadline.map(|at| self.timer.sleep_until(at)),
+                    stats: StatsCtx {
+                        on_stats: self.on_stats.clone(),
+                        observer: self.observer.clone(),
+                        poll_start: f.poll_start,
+                        checkout_start: f.checkout_start,
+                        connection_id: self.connection_id,
+                        request_count: f.request_count,
+                        headers_written: f.headers_written,
+                        idle_before_reuse: f.idle_before_reuse,
+                        body_written,
+                        frames: self.frames.clone(),
+                        stream: f.stream,
+                        transport: self.transport,
+                        bytes: self.bytes.clone(),
+                        retries: f.retries,
+                        clock: self.clock.clone(),
+                    },
+                    streams: self.streams.clone(),
                 },
                 call_back: Some(f.cb),
             },
@@ -555,6 +756,45 @@ pin_project! {
         ping: Option<Recorder>,
         #[pin]
         send_stream: Option<Option<SendStream<SendBuf<<B as Body>::Data>>>>,
+        deadline: Option<Pin<Box<dyn Sleep>>>,
+        stats: StatsCtx,
+        streams: Streams,
+    }
+}
+
+/// Everything a request needs to fill in its stats once the response
+/// arrives.
+struct StatsCtx {
+    on_stats: Option<OnStats>,
+    observer: Option<Observer>,
+    poll_start: Option<Instant>,
+    checkout_start: Option<Instant>,
+    connection_id: u64,
+    request_count: u64,
+    headers_written: Option<Instant>,
+    idle_before_reuse: Option<Duration>,
+    body_written: BodyWritten,
+    frames: Option<SharedFrames>,
+    stream: Option<StreamFrames>,
+    transport: Option<ConnectionStats>,
+    bytes: Arc<ByteCounts>,
+    retries: Vec<RetryStats>,
+    clock: Clock,
+}
+
+/// When the request body finished writing, if known yet.
+enum BodyWritten {
+    At(Option<Instant>),
+    // The body is still being piped by a spawned task.
+    Pending(Arc<Mutex<Option<Instant>>>),
+}
+
+impl BodyWritten {
+    fn get(&self) -> Option<Instant> {
+        match self {
+            BodyWritten::At(at) => *at,
+            BodyWritten::Pending(slot) => *slot.lock().unwrap(),
+        }
     }
 }
 
@@ -570,16 +810,37 @@ where
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let mut this = self.project();
 
-        let result = ready!(this.fut.poll(cx));
+        let result = match this.fut.as_mut().poll(cx) {
+            Poll::Ready(result) => result,
+            Poll::Pending => {
+                if let Some(deadline) = this.deadline.as_mut() {
+                    if deadline.as_mut().poll(cx).is_ready() {
+                        debug!("request deadline passed before response");
+                        // Dropping the response future and send stream
+                        // resets the stream.
+                        let err = crate::Error::new_deadline_exceeded()
+                            .with_stats(this.stats.fail());
+                        return Poll::Ready(Err((err, None)));
+                    }
+                }
+                return Poll::Pending;
+            }
+        };
 
         let ping = this.ping.take().expect("Future polled twice");
         let send_stream = this.send_stream.take().expect("Future polled twice");
 
         match result {
-            Ok(res) => {
+            Ok(mut res) => {
                 // record that we got the response headers
                 ping.record_non_data();
 
+                if let Some(settings) = this.streams.peer_settings() {
+                    res.extensions_mut().insert(settings);
+                }
+                res.extensions_mut()
+                    .insert(StreamId::from_h2(this.fut.stream_id()));
+
                 let content_length = headers::content_length_parse_all(res.headers());
                 if let (Some(mut send_stream), StatusCode::OK) = (send_stream, res.status()) {
                     if content_length.map_or(false, |len| len != 0) {
@@ -601,30 +862,138 @@ where
                         recv_stream,
                         buf: Bytes::new(),
                     };
-                    let upgraded = Upgraded::new(io, Bytes::new());
+                    let mut upgraded = Upgraded::new(io, Bytes::new());
+                    if let Some(start) = this.stats.poll_start {
+                        upgraded.set_tunnel_start(start);
+                    }
 
                     pending.fulfill(upgraded);
                     res.extensions_mut().insert(on_upgrade);
 
-                    // TODO: to support request stats, we'll need to fork/hack the h2 crate
-                    Poll::Ready(Ok((HttpConnectionStats::new_http2(), res)))
+                    let (stats, on_end) = this.stats.finish();
+                    // The tunnel has no body to wait for.
+                    if let Some(on_end) = on_end {
+                        on_end.call(|_| {});
+                    }
+                    Poll::Ready(Ok((stats, res)))
                 } else {
-                    let res = res.map(|stream| {
+                    let (stats, on_end) = this.stats.finish();
+                    let frames = this.stats.stream.take();
+                    let mut res = res.map(|stream| {
                         let ping = ping.for_stream(&stream);
-                        IncomingBody::h2(stream, content_length.into(), ping)
+                        let body = IncomingBody::h2(stream, content_length.into(), ping)
+                            .with_h2_stream(frames)
+                            .with_deadline(this.deadline.take());
+                        match on_end {
+                            Some(on_end) => body.with_on_end(on_end),
+                            None => body,
+                        }
                     });
+                    if let Some(flow) = res.body().h2_flow_control() {
+                        res.extensions_mut().insert(flow);
+                    }
 
-                    // TODO: to support request stats, we'll need to fork/hack the h2 crate
-                    Poll::Ready(Ok((HttpConnectionStats::new_http2(), res)))
+                    Poll::Ready(Ok((stats, res)))
                 }
             }
             Err(err) => {
                 ping.ensure_not_timed_out().map_err(|e| (e, None))?;
 
                 debug!("client response error: {}", err);
-                Poll::Ready(Err((crate::Error::new_h2(err), None::<Request<B>>)))
+                let stats = this.stats.fail();
+                Poll::Ready(Err((
+                    crate::Error::new_h2(err).with_stats(stats),
+                    None::<Request<B>>,
+                )))
+            }
+        }
+    }
+}
+
+impl StatsCtx {
+    /// The stats recorded so far, without telling anyone about them.
+    fn snapshot(&self) -> HttpConnectionStats {
+        // No poll_start means stats collection is disabled.
+        let poll_start = match self.poll_start {
+            Some(poll_start) => poll_start,
+            None => {
+                return HttpConnectionStats {
+                    connection_id: Some(self.connection_id),
+                    connection_request_count: self.request_count,
+                    ..Default::default()
+                }
+            }
+        };
+        let h2_stream = self.stream.as_ref().map(|s| s.stats());
+        // Only the first stream waited for the connection to be set up.
+        let mut connection_stats = match self.transport {
+            Some(transport) if self.request_count > 1 => transport.reused(),
+            transport => transport.unwrap_or_default(),
+        };
+        connection_stats.bytes_read = self.bytes.read();
+        connection_stats.bytes_written = self.bytes.written();
+        let mut stats = HttpConnectionStats {
+            connection_stats: Some(connection_stats),
+            first_header_byte_time: h2_stream.and_then(|s| s.headers),
+            first_body_byte_time: h2_stream.and_then(|s| s.first_data),
+            connection_id: Some(self.connection_id),
+            connection_request_count: self.request_count,
+            idle_before_reuse: self.idle_before_reuse,
+            checkout_start: self.checkout_start,
+            checkout_end: Some(poll_start),
+            headers_written: self.headers_written,
+            body_written: self.body_written.get(),
+            h2: self.frames.as_ref().map(|f| f.lock().unwrap().stats()),
+            h2_stream,
+            ..Default::default()
+        };
+        if let Some(ref stream) = self.stream {
+            stream.fill_sizes(&mut stats);
+        }
+        stats
+    }
+
+    /// The stats once the response head has arrived, and what reports them
+    /// to the `on_stats` callback once the body ends.
+    fn finish(&self) -> (HttpConnectionStats, Option<OnBodyEnd>) {
+        let stats = self.snapshot();
+        let poll_start = match self.poll_start {
+            Some(poll_start) => poll_start,
+            None => return (stats, None),
+        };
+        if let (Some(ref observer), Some(at)) = (&self.observer, stats.first_header_byte_time) {
+            observer.on_first_header_byte(at);
+        }
+        let mut on_end = None;
+        if self.on_stats.is_some() || self.observer.is_some() {
+            let request_stats = self.request_stats(stats, poll_start);
+            if let Some(ref observer) = self.observer {
+                observer.on_complete(&request_stats);
+            }
+            if let Some(ref on_stats) = self.on_stats {
+                on_end = Some(OnBodyEnd::new(request_stats, on_stats.clone()));
             }
         }
+        (stats, on_end)
+    }
+
+    /// The stats of a request that failed, which are reported right away.
+    fn fail(&self) -> HttpConnectionStats {
+        let stats = self.snapshot();
+        if let (Some(poll_start), Some(ref on_stats)) = (self.poll_start, &self.on_stats) {
+            on_stats.call(self.request_stats(stats, poll_start));
+        }
+        stats
+    }
+
+    fn request_stats(&self, stats: HttpConnectionStats, poll_start: Instant) -> RequestStats {
+        RequestStats {
+            http_stats: stats,
+            redirects: Vec::new(),
+            retries: self.retries.clone(),
+            poll_start,
+            finish: self.clock.now(),
+        }
     }
 }
 
@@ -667,7 +1036,25 @@ where
                         trace!("request callback is canceled");
                         continue;
                     }
-                    let (head, body) = req.into_parts();
+                    let poll_start = if self.collect_stats {
+                        let now = self.clock.now();
+                        if let Some(ref observer) = self.observer {
+                            observer.on_request_start(now);
+                        }
+                        Some(now)
+                    } else {
+                        None
+                    };
+                    let (mut head, body) = req.into_parts();
+                    let checkout_start = head
+                        .extensions
+                        .remove::<CheckoutStart>()
+                        .map(|start| start.0);
+                    let deadline = head.extensions.remove::<Deadline>().map(|at| at.0);
+                    let retries = head
+                        .extensions
+                        .remove::<Retries>()
+                        .map_or_else(Vec::new, |retries| retries.0);
                     let mut req = ::http::Request::from_parts(head, ());
                     super::strip_connection_headers(req.headers_mut(), true);
                     if let Some(len) = body.size_hint().exact() {
@@ -695,6 +1082,13 @@ where
                         req.extensions_mut().insert(protocol.into_inner());
                     }
 
+                    if let Some(priority) = req.extensions().get::<Priority>() {
+                        if !req.headers().contains_key(PRIORITY) {
+                            let value = priority.to_header();
+                            req.headers_mut().insert(PRIORITY, value);
+                        }
+                    }
+
                     let (fut, body_tx) = match self.h2_tx.send_request(req, !is_connect && eos) {
                         Ok(ok) => ok,
                         Err(err) => {
@@ -706,6 +1100,19 @@ where
                             continue;
                         }
                     };
+                    self.request_count += 1;
+                    let headers_written = poll_start.map(|_| self.clock.now());
+                    if let (Some(ref observer), Some(at)) = (&self.observer, headers_written) {
+                        observer.on_headers_written(at);
+                    }
+                    let idle_before_reuse = self
+                        .frames
+                        .as_ref()
+                        .and_then(|frames| frames.lock().unwrap().idle_before_reuse());
+                    let stream = self
+                        .frames
+                        .as_ref()
+                        .map(|frames| StreamFrames::new(frames.clone(), fut.stream_id().as_u32()));
 
                     let f = FutCtx {
                         is_connect,
@@ -714,6 +1121,14 @@ where
                         body_tx,
                         body,
                         cb,
+                        poll_start,
+                        checkout_start,
+                        headers_written,
+                        idle_before_reuse,
+                        stream,
+                        request_count: self.request_count,
+                        deadline,
+                        retries,
                     };
 
                     // Check poll_ready() again.
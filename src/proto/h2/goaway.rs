@@ -0,0 +1,352 @@
+//! GOAWAY debug data, and ORIGIN and ALTSVC frames
+//!
+//! h2 doesn't let a user attach debug data to the GOAWAY frames it sends, so
+//! the server sits a `GoAwayIo` between h2 and the transport. It follows the
+//! frame headers written by h2, and once h2 has written a GOAWAY while debug
+//! data is armed, it writes a copy of that frame carrying the data right
+//! behind it, at the next frame boundary.
+//!
+//! h2 doesn't know ORIGIN (RFC 8336) or ALTSVC (RFC 7838) frames either, so
+//! the same `GoAwayIo` writes them right after the server's first SETTINGS
+//! frame.
+
+use std::io::IoSlice;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use futures_core::ready;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::ext::AltSvc;
+
+const FRAME_HEADER_LEN: usize = 9;
+const GOAWAY: u8 = 0x7;
+const ALTSVC: u8 = 0xa;
+const ORIGIN: u8 = 0xc;
+// A peer must always accept frames of this size.
+const MIN_MAX_FRAME_SIZE: usize = 16_384;
+
+pub(super) type SharedDebugData = Arc<Mutex<DebugData>>;
+
+/// What `GoAwayIo` has to send, shared with the server connection.
+#[derive(Default)]
+pub(super) struct DebugData {
+    /// The error code and debug data for the next GOAWAY h2 writes.
+    armed: Option<(u32, Bytes)>,
+    /// A frame ready to be written at the next frame boundary.
+    frame: Option<Bytes>,
+}
+
+impl DebugData {
+    pub(super) fn arm(&mut self, error_code: u32, debug_data: Bytes) {
+        self.armed = Some((error_code, debug_data));
+    }
+
+    fn goaway_written(&mut self, last_stream_id: [u8; 4]) {
+        if let Some((error_code, mut debug_data)) = self.armed.take() {
+            debug_data.truncate(MIN_MAX_FRAME_SIZE - 8);
+            let len = 8 + debug_data.len();
+            let mut frame = BytesMut::with_capacity(FRAME_HEADER_LEN + len);
+            frame.put_uint(len as u64, 3);
+            frame.put_u8(GOAWAY);
+            frame.put_u8(0);
+            frame.put_u32(0);
+            frame.put_slice(&last_stream_id);
+            frame.put_u32(error_code);
+            frame.put_slice(&debug_data);
+            self.frame = Some(frame.freeze());
+        }
+    }
+}
+
+/// Wraps the transport given to h2, adding debug data to its GOAWAY frames.
+pub(super) struct GoAwayIo<T> {
+    io: T,
+    shared: SharedDebugData,
+    header: [u8; FRAME_HEADER_LEN],
+    header_len: usize,
+    /// Payload bytes left of the frame being written.
+    remaining: usize,
+    /// The last stream ID of the GOAWAY being written, as it goes by.
+    last_stream_id: Option<([u8; 4], usize)>,
+    /// Frames to write once h2's SETTINGS frame is out.
+    after_settings: Option<Bytes>,
+    first_frame_done: bool,
+}
+
+/// Encodes an origin set as ORIGIN frames, as few as fit in minimum size
+/// frames.
+pub(super) fn encode_origin(origins: &[String]) -> Bytes {
+    let mut frames = BytesMut::new();
+    let mut payload = BytesMut::new();
+    for origin in origins {
+        let origin = origin.as_bytes();
+        if payload.len() + 2 + origin.len() > MIN_MAX_FRAME_SIZE {
+            put_frame(&mut frames, ORIGIN, &payload);
+            payload.clear();
+        }
+        payload.put_u16(origin.len() as u16);
+        payload.put_slice(origin);
+    }
+    if !payload.is_empty() {
+        put_frame(&mut frames, ORIGIN, &payload);
+    }
+    frames.freeze()
+}
+
+/// Encodes alternative services as ALTSVC frames on stream 0, one for each.
+///
+/// Ones that wouldn't fit in a minimum size frame are left out.
+pub(super) fn encode_alt_svc(alt_svcs: &[AltSvc]) -> Bytes {
+    let mut frames = BytesMut::new();
+    for alt_svc in alt_svcs {
+        let origin = alt_svc.origin().as_bytes();
+        let value = alt_svc.value().as_bytes();
+        if 2 + origin.len() + value.len() > MIN_MAX_FRAME_SIZE {
+            debug!("alt-svc too large for a frame: {:?}", alt_svc);
+            continue;
+        }
+        let mut payload = BytesMut::with_capacity(2 + origin.len() + value.len());
+        payload.put_u16(origin.len() as u16);
+        payload.put_slice(origin);
+        payload.put_slice(value);
+        put_frame(&mut frames, ALTSVC, &payload);
+    }
+    frames.freeze()
+}
+
+fn put_frame(frames: &mut BytesMut, kind: u8, payload: &[u8]) {
+    frames.put_uint(payload.len() as u64, 3);
+    frames.put_u8(kind);
+    frames.put_u8(0);
+    frames.put_u32(0);
+    frames.put_slice(payload);
+}
+
+impl<T> GoAwayIo<T> {
+    pub(super) fn new(io: T, shared: SharedDebugData) -> Self {
+        GoAwayIo {
+            io,
+            shared,
+            header: [0; FRAME_HEADER_LEN],
+            header_len: 0,
+            remaining: 0,
+            last_stream_id: None,
+            after_settings: None,
+            first_frame_done: false,
+        }
+    }
+
+    /// Sets frames to write after SETTINGS, such as from `encode_origin`.
+    pub(super) fn after_settings(mut self, frames: Bytes) -> Self {
+        self.after_settings = Some(frames).filter(|frames| !frames.is_empty());
+        self
+    }
+
+    fn track(&mut self, mut bytes: &[u8]) {
+        while !bytes.is_empty() {
+            if self.remaining == 0 {
+                let n = bytes.len().min(FRAME_HEADER_LEN - self.header_len);
+                self.header[self.header_len..self.header_len + n].copy_from_slice(&bytes[..n]);
+                self.header_len += n;
+                bytes = &bytes[n..];
+                if self.header_len == FRAME_HEADER_LEN {
+                    self.header_len = 0;
+                    self.remaining = (usize::from(self.header[0]) << 16)
+                        | (usize::from(self.header[1]) << 8)
+                        | usize::from(self.header[2]);
+                    if self.header[3] == GOAWAY {
+                        self.last_stream_id = Some(([0; 4], 0));
+                    }
+                    if self.remaining == 0 {
+                        self.first_frame_done = true;
+                    }
+                }
+                continue;
+            }
+
+            let n = bytes.len().min(self.remaining);
+            if let Some((ref mut id, ref mut filled)) = self.last_stream_id {
+                let m = n.min(id.len() - *filled);
+                id[*filled..*filled + m].copy_from_slice(&bytes[..m]);
+                *filled += m;
+            }
+            self.remaining -= n;
+            bytes = &bytes[n..];
+            if self.remaining == 0 {
+                self.first_frame_done = true;
+                if let Some((id, 4)) = self.last_stream_id.take() {
+                    self.shared.lock().unwrap().goaway_written(id);
+                }
+            }
+        }
+    }
+}
+
+impl<T: AsyncWrite + Unpin> GoAwayIo<T> {
+    /// Writes the pending frames after SETTINGS and GOAWAY, if any, when h2
+    /// isn't in the middle of a frame.
+    fn poll_frame(&mut self, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        if self.header_len != 0 || self.remaining != 0 {
+            return Poll::Ready(Ok(()));
+        }
+        // The server's connection preface has to start with SETTINGS.
+        if self.first_frame_done {
+            while let Some(mut frames) = self.after_settings.take() {
+                let res = poll_write_buf(&mut self.io, cx, &mut frames);
+                if !frames.is_empty() {
+                    self.after_settings = Some(frames);
+                }
+                ready!(res)?;
+            }
+        }
+        loop {
+            let mut frame = match self.shared.lock().unwrap().frame.take() {
+                Some(frame) => frame,
+                None => return Poll::Ready(Ok(())),
+            };
+            let res = poll_write_buf(&mut self.io, cx, &mut frame);
+            if !frame.is_empty() {
+                self.shared.lock().unwrap().frame = Some(frame);
+            }
+            ready!(res)?;
+        }
+    }
+}
+
+/// Writes some of `buf`, advancing past what was written.
+fn poll_write_buf<T: AsyncWrite + Unpin>(
+    io: &mut T,
+    cx: &mut Context<'_>,
+    buf: &mut Bytes,
+) -> Poll<std::io::Result<()>> {
+    match ready!(Pin::new(io).poll_write(cx, buf))? {
+        0 => Poll::Ready(Err(std::io::ErrorKind::WriteZero.into())),
+        n => {
+            buf.advance(n);
+            Poll::Ready(Ok(()))
+        }
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for GoAwayIo<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.io).poll_read(cx, buf)
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for GoAwayIo<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        ready!(self.poll_frame(cx))?;
+        let n = ready!(Pin::new(&mut self.io).poll_write(cx, buf))?;
+        self.track(&buf[..n]);
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<std::io::Result<usize>> {
+        ready!(self.poll_frame(cx))?;
+        let n = ready!(Pin::new(&mut self.io).poll_write_vectored(cx, bufs))?;
+        let mut left = n;
+        for buf in bufs {
+            if left == 0 {
+                break;
+            }
+            let m = left.min(buf.len());
+            self.track(&buf[..m]);
+            left -= m;
+        }
+        Poll::Ready(Ok(n))
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.io.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        ready!(self.poll_frame(cx))?;
+        Pin::new(&mut self.io).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        ready!(self.poll_frame(cx))?;
+        Pin::new(&mut self.io).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(kind: u8, stream_id: u32, payload: &[u8]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.put_uint(payload.len() as u64, 3);
+        buf.put_u8(kind);
+        buf.put_u8(0);
+        buf.put_u32(stream_id);
+        buf.put_slice(payload);
+        buf
+    }
+
+    #[tokio::test]
+    async fn debug_data_follows_goaway() {
+        use tokio::io::AsyncWriteExt;
+
+        let shared = SharedDebugData::default();
+        let mut io = GoAwayIo::new(Vec::new(), shared.clone());
+
+        let ping = frame(0x6, 0, &[0; 8]);
+        io.write_all(&ping).await.unwrap();
+        shared.lock().unwrap().arm(0xb, Bytes::from_static(b"draining"));
+
+        let goaway = frame(GOAWAY, 0, &[0, 0, 0, 5, 0, 0, 0, 0xb]);
+        // written a byte at a time, so the frame is split everywhere
+        for b in &goaway {
+            io.write_all(&[*b]).await.unwrap();
+        }
+        io.flush().await.unwrap();
+
+        let mut expected = ping.clone();
+        expected.extend_from_slice(&goaway);
+        expected.extend_from_slice(&frame(GOAWAY, 0, b"\0\0\0\x05\0\0\0\x0bdraining"));
+        assert_eq!(io.io, expected);
+    }
+
+    #[tokio::test]
+    async fn origin_follows_settings() {
+        use tokio::io::AsyncWriteExt;
+
+        let origins = ["https://a.example".to_owned(), "https://b.example".to_owned()];
+        let mut io = GoAwayIo::new(Vec::new(), SharedDebugData::default())
+            .after_settings(encode_origin(&origins));
+
+        // an empty SETTINGS frame
+        let settings = frame(0x4, 0, &[]);
+        io.write_all(&settings).await.unwrap();
+        let ping = frame(0x6, 0, &[0; 8]);
+        io.write_all(&ping).await.unwrap();
+        io.flush().await.unwrap();
+
+        let mut expected = settings.clone();
+        expected.extend_from_slice(&frame(
+            ORIGIN,
+            0,
+            b"\0\x11https://a.example\0\x11https://b.example",
+        ));
+        expected.extend_from_slice(&ping);
+        assert_eq!(io.io, expected);
+    }
+}
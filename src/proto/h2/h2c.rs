@@ -0,0 +1,356 @@
+//! HTTP2 over an upgraded HTTP/1.1 connection
+//!
+//! After a `101 Switching Protocols` to `h2c`, the server answers the
+//! upgrade request on stream 1, which h2 doesn't know about. So the client
+//! opens stream 1 itself with a stand-in request, and `H2c` keeps it off
+//! the wire. The stand-in is encoded from the HPACK static table alone, so
+//! dropping it leaves both ends' dynamic tables the same. Reads are held
+//! back until then, so h2 never sees the response for a stream it hasn't
+//! opened.
+
+use std::io::IoSlice;
+use std::pin::Pin;
+use std::task::{Context, Poll, Waker};
+
+use bytes::Bytes;
+use futures_core::ready;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+const FRAME_HEADER_LEN: usize = 9;
+const PREFACE_LEN: usize = 24;
+
+const DATA: u8 = 0x0;
+const HEADERS: u8 = 0x1;
+const CONTINUATION: u8 = 0x9;
+
+const END_STREAM: u8 = 0x1;
+const END_HEADERS: u8 = 0x4;
+
+// The stream the upgrade request is answered on.
+const UPGRADE_STREAM_ID: u32 = 1;
+
+/// Wraps the transport given to h2, replaying what the server sent along
+/// with the upgrade, and dropping the replayed request.
+pub(super) struct H2c<T> {
+    io: T,
+    // Bytes read past the `101 Switching Protocols` response.
+    read_buf: Bytes,
+    // Until the replayed request is off the output.
+    replay: Option<Replay>,
+}
+
+/// Where the output is, while looking for the replayed request.
+struct Replay {
+    // Bytes to pass along before the next frame header.
+    skip: usize,
+    // Bytes of the current frame to leave out.
+    drop: usize,
+    head: [u8; FRAME_HEADER_LEN],
+    head_len: usize,
+    // Bytes of `head` still to be written.
+    unwritten: usize,
+    // Whether stream 1 has been opened, so reads can go ahead.
+    opened: bool,
+    // Whether stream 1 ended while a header block is still being sent.
+    ended: bool,
+    in_headers: bool,
+    read_waker: Option<Waker>,
+}
+
+impl<T> H2c<T> {
+    /// Wraps a connection that was not upgraded, which is passed through.
+    pub(super) fn direct(io: T) -> Self {
+        H2c {
+            io,
+            read_buf: Bytes::new(),
+            replay: None,
+        }
+    }
+
+    /// Wraps a connection upgraded to `h2c`, where `read_buf` was read
+    /// past the `101 Switching Protocols`.
+    pub(super) fn upgraded(io: T, read_buf: Bytes) -> Self {
+        H2c {
+            io,
+            read_buf,
+            replay: Some(Replay {
+                // The client connection preface isn't framed.
+                skip: PREFACE_LEN,
+                drop: 0,
+                head: [0; FRAME_HEADER_LEN],
+                head_len: 0,
+                unwritten: 0,
+                opened: false,
+                ended: false,
+                in_headers: false,
+                read_waker: None,
+            }),
+        }
+    }
+}
+
+impl<T: AsyncWrite + Unpin> H2c<T> {
+    /// Writes out a frame header that was held while deciding on the frame.
+    fn poll_write_head(&mut self, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        if let Some(ref mut replay) = self.replay {
+            while replay.unwritten > 0 {
+                let head = &replay.head[FRAME_HEADER_LEN - replay.unwritten..];
+                let n = ready!(Pin::new(&mut self.io).poll_write(cx, head))?;
+                if n == 0 {
+                    return Poll::Ready(Err(std::io::ErrorKind::WriteZero.into()));
+                }
+                replay.unwritten -= n;
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Replay {
+    /// Decides on the frame whose header was just completed.
+    fn start_frame(&mut self) {
+        let h = self.head;
+        self.head_len = 0;
+        let len = u32::from_be_bytes([0, h[0], h[1], h[2]]) as usize;
+        let kind = h[3];
+        let flags = h[4];
+        let stream_id = u32::from_be_bytes([h[5], h[6], h[7], h[8]]) & 0x7FFF_FFFF;
+
+        if stream_id != UPGRADE_STREAM_ID || !matches!(kind, DATA | HEADERS | CONTINUATION) {
+            self.unwritten = FRAME_HEADER_LEN;
+            self.skip = len;
+            return;
+        }
+
+        self.drop = len;
+        match kind {
+            HEADERS => {
+                self.opened = true;
+                if let Some(waker) = self.read_waker.take() {
+                    waker.wake();
+                }
+                self.ended |= flags & END_STREAM != 0;
+                self.in_headers = flags & END_HEADERS == 0;
+            }
+            CONTINUATION => self.in_headers = flags & END_HEADERS == 0,
+            _ => self.ended |= flags & END_STREAM != 0,
+        }
+    }
+
+    /// Whether the replayed request has been dropped in full.
+    fn is_done(&self) -> bool {
+        self.ended && !self.in_headers && self.drop == 0
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for H2c<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        if let Some(ref mut replay) = self.replay {
+            if !replay.opened {
+                replay.read_waker = Some(cx.waker().clone());
+                return Poll::Pending;
+            }
+        }
+        if !self.read_buf.is_empty() {
+            let n = self.read_buf.len().min(buf.remaining());
+            buf.put_slice(&self.read_buf.split_to(n));
+            return Poll::Ready(Ok(()));
+        }
+        Pin::new(&mut self.io).poll_read(cx, buf)
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for H2c<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        ready!(self.poll_write_head(cx))?;
+        let this = &mut *self;
+        let replay = match this.replay {
+            Some(ref mut replay) => replay,
+            None => return Pin::new(&mut this.io).poll_write(cx, buf),
+        };
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+
+        if replay.skip > 0 {
+            let n = replay.skip.min(buf.len());
+            let n = ready!(Pin::new(&mut this.io).poll_write(cx, &buf[..n]))?;
+            replay.skip -= n;
+            return Poll::Ready(Ok(n));
+        }
+
+        let n = if replay.drop > 0 {
+            let n = replay.drop.min(buf.len());
+            replay.drop -= n;
+            n
+        } else {
+            let n = (FRAME_HEADER_LEN - replay.head_len).min(buf.len());
+            replay.head[replay.head_len..replay.head_len + n].copy_from_slice(&buf[..n]);
+            replay.head_len += n;
+            if replay.head_len == FRAME_HEADER_LEN {
+                replay.start_frame();
+            }
+            n
+        };
+        if replay.unwritten == 0 && replay.is_done() {
+            trace!("h2c replayed request dropped");
+            this.replay = None;
+        }
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<std::io::Result<usize>> {
+        if self.replay.is_none() {
+            return Pin::new(&mut self.io).poll_write_vectored(cx, bufs);
+        }
+        let buf = bufs
+            .iter()
+            .find(|buf| !buf.is_empty())
+            .map_or(&[][..], |buf| &**buf);
+        self.poll_write(cx, buf)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.io.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        ready!(self.poll_write_head(cx))?;
+        Pin::new(&mut self.io).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        ready!(self.poll_write_head(cx))?;
+        Pin::new(&mut self.io).poll_shutdown(cx)
+    }
+}
+
+/// Encodes a SETTINGS payload for the `HTTP2-Settings` header, as base64url
+/// without padding.
+#[cfg(feature = "http1")]
+pub(super) fn encode_settings(settings: &[(u16, u32)]) -> String {
+    const ALPHABET: &[u8; 64] =
+        b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+    let mut payload = Vec::with_capacity(settings.len() * 6);
+    for &(id, value) in settings {
+        payload.extend_from_slice(&id.to_be_bytes());
+        payload.extend_from_slice(&value.to_be_bytes());
+    }
+
+    let mut out = String::with_capacity((payload.len() * 4 + 2) / 3);
+    for chunk in payload.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = u32::from_be_bytes([0, b[0], b[1], b[2]]);
+        for i in 0..=chunk.len() {
+            out.push(ALPHABET[(n >> (18 - 6 * i)) as usize & 0x3F] as char);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(kind: u8, flags: u8, stream_id: u32, payload: &[u8]) -> Vec<u8> {
+        let len = (payload.len() as u32).to_be_bytes();
+        let mut buf = vec![len[1], len[2], len[3], kind, flags];
+        buf.extend_from_slice(&stream_id.to_be_bytes());
+        buf.extend_from_slice(payload);
+        buf
+    }
+
+    #[cfg(feature = "http1")]
+    #[test]
+    fn encode_settings_base64url() {
+        // SETTINGS_ENABLE_PUSH = 0
+        assert_eq!(encode_settings(&[(0x2, 0)]), "AAIAAAAA");
+        assert_eq!(
+            encode_settings(&[(0x2, 0), (0x4, 0xFFFF_FFFF)]),
+            "AAIAAAAAAAT_____"
+        );
+        assert_eq!(encode_settings(&[]), "");
+    }
+
+    #[tokio::test]
+    async fn h2c_drops_replayed_request() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let mut sent = vec![b'P'; PREFACE_LEN];
+        let settings = frame(0x4, 0, 0, &[0, 2, 0, 0, 0, 0]);
+        sent.extend(&settings);
+        sent.extend(frame(HEADERS, 0, 1, &[1; 20]));
+        sent.extend(frame(CONTINUATION, END_HEADERS, 1, &[2; 5]));
+        sent.extend(frame(DATA, END_STREAM, 1, &[]));
+        let window_update = frame(0x8, 0, 0, &[0, 0, 1, 0]);
+        sent.extend(&window_update);
+        let second = frame(HEADERS, END_HEADERS | END_STREAM, 3, &[3; 10]);
+        sent.extend(&second);
+
+        let mut expected = vec![b'P'; PREFACE_LEN];
+        expected.extend(&settings);
+        expected.extend(&window_update);
+        expected.extend(&second);
+
+        let (mut client, server) = tokio::io::duplex(1024);
+        let mut io = H2c::upgraded(server, Bytes::from_static(b"early"));
+
+        // split writes anywhere
+        for chunk in sent.chunks(4) {
+            io.write_all(chunk).await.unwrap();
+        }
+        io.flush().await.unwrap();
+        assert!(io.replay.is_none());
+
+        let mut wire = vec![0; expected.len()];
+        client.read_exact(&mut wire).await.unwrap();
+        assert_eq!(wire, expected);
+
+        client.write_all(b" later").await.unwrap();
+        let mut read = [0; 11];
+        io.read_exact(&mut read).await.unwrap();
+        assert_eq!(&read, b"early later");
+    }
+
+    #[tokio::test]
+    async fn h2c_holds_reads_until_stream_opened() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let (_client, server) = tokio::io::duplex(1024);
+        let mut io = H2c::upgraded(server, Bytes::from_static(b"early"));
+
+        let mut read = [0; 5];
+        tokio::time::timeout(
+            std::time::Duration::from_millis(10),
+            io.read_exact(&mut read),
+        )
+        .await
+        .expect_err("read before stream 1 was opened");
+
+        io.write_all(&[b'P'; PREFACE_LEN]).await.unwrap();
+        io.write_all(&frame(HEADERS, END_HEADERS, 1, &[1; 20]))
+            .await
+            .unwrap();
+        io.read_exact(&mut read).await.unwrap();
+        assert_eq!(&read, b"early");
+        // stream 1 hasn't ended yet
+        assert!(io.replay.is_some());
+    }
+}
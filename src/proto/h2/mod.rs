@@ -14,16 +14,24 @@ use pin_project_lite::pin_project;
 
 use crate::body::Body;
 use crate::proto::h2::ping::Recorder;
-use crate::rt::{Read, ReadBufCursor, Write};
+use crate::rt::{Read, ReadBufCursor, Sleep, Write};
 
 pub(crate) mod ping;
 
 cfg_client! {
     pub(crate) mod client;
     pub(crate) use self::client::ClientTask;
+    mod h2c;
+    pub(crate) use self::tap::StreamFrames;
 }
 
+// The server only uses the tap for the client's settings.
+#[cfg(any(feature = "client", feature = "server"))]
+#[cfg_attr(not(feature = "client"), allow(dead_code))]
+mod tap;
+
 cfg_server! {
+    mod goaway;
     pub(crate) mod server;
     pub(crate) use self::server::Server;
 }
@@ -31,6 +39,10 @@ cfg_server! {
 /// Default initial stream window size defined in HTTP2 spec.
 pub(crate) const SPEC_WINDOW_SIZE: u32 = 65_535;
 
+// The priority header from RFC 9218 Section 5
+#[cfg(any(feature = "client", feature = "server"))]
+const PRIORITY: HeaderName = HeaderName::from_static("priority");
+
 // List of connection headers from RFC 9110 Section 7.6.1
 //
 // TE headers are allowed in HTTP/2 requests as long as the value is "trailers", so they're
@@ -90,11 +102,18 @@ pin_project! {
     {
         body_tx: SendStream<SendBuf<S::Data>>,
         data_done: bool,
+        stalled: bool,
+        on_stall: Option<OnStall>,
+        deadline: Option<Pin<Box<dyn Sleep>>>,
         #[pin]
         stream: S,
     }
 }
 
+/// Told `true` when the body starts waiting for send capacity, and `false`
+/// once it has some.
+type OnStall = Box<dyn FnMut(bool) + Send>;
+
 impl<S> PipeToSendStream<S>
 where
     S: Body,
@@ -103,9 +122,24 @@ where
         PipeToSendStream {
             body_tx: tx,
             data_done: false,
+            stalled: false,
+            on_stall: None,
+            deadline: None,
             stream,
         }
     }
+
+    #[cfg(feature = "client")]
+    fn with_on_stall(mut self, on_stall: Option<OnStall>) -> Self {
+        self.on_stall = on_stall;
+        self
+    }
+
+    #[cfg(feature = "client")]
+    fn with_deadline(mut self, deadline: Option<Pin<Box<dyn Sleep>>>) -> Self {
+        self.deadline = deadline;
+        self
+    }
 }
 
 impl<S> Future for PipeToSendStream<S>
@@ -117,6 +151,15 @@ where
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let mut me = self.project();
+        if let Some(deadline) = me.deadline.as_mut() {
+            if deadline.as_mut().poll(cx).is_ready() {
+                debug!("request deadline passed while sending body");
+                me.body_tx.send_reset(Reason::CANCEL);
+                return Poll::Ready(Err(crate::Error::new_body_write(::h2::Error::from(
+                    Reason::CANCEL,
+                ))));
+            }
+        }
         loop {
             // we don't have the next chunk of data yet, so just reserve 1 byte to make
             // sure there's some capacity available. h2 will handle the capacity management
@@ -124,6 +167,10 @@ where
             me.body_tx.reserve_capacity(1);
 
             if me.body_tx.capacity() == 0 {
+                if let (false, Some(on_stall)) = (*me.stalled, me.on_stall.as_mut()) {
+                    *me.stalled = true;
+                    on_stall(true);
+                }
                 loop {
                     match ready!(me.body_tx.poll_capacity(cx)) {
                         Some(Ok(0)) => {}
@@ -148,11 +195,22 @@ where
                 return Poll::Ready(Err(crate::Error::new_body_write(::h2::Error::from(reason))));
             }
 
+            if let (true, Some(on_stall)) = (*me.stalled, me.on_stall.as_mut()) {
+                *me.stalled = false;
+                on_stall(false);
+            }
+
             match ready!(me.stream.as_mut().poll_frame(cx)) {
                 Some(Ok(frame)) => {
                     if frame.is_data() {
                         let chunk = frame.into_data().unwrap_or_else(|_| unreachable!());
                         let is_eos = me.stream.is_end_stream();
+                        if !chunk.has_remaining() && !is_eos {
+                            // such as the offer of a file region, which
+                            // only an HTTP/1 connection takes up
+                            trace!("skipping empty body chunk");
+                            continue;
+                        }
                         trace!(
                             "send body chunk: {} bytes, eos={}",
                             chunk.remaining(),
@@ -205,7 +263,7 @@ impl<B: Buf> SendStreamExt for SendStream<SendBuf<B>> {
     {
         let err = crate::Error::new_user_body(err);
         debug!("send body user stream error: {}", err);
-        self.send_reset(err.h2_reason());
+        self.send_reset(err.h2_reason_or_internal());
         err
     }
 
@@ -1,11 +1,12 @@
 //! HTTP2 Ping usage
 //!
-//! hyper uses HTTP2 pings for two purposes:
+//! hyper uses HTTP2 pings for three purposes:
 //!
 //! 1. Adaptive flow control using BDP
 //! 2. Connection keep-alive
+//! 3. Measuring the round trip time when the user asks
 //!
-//! Both cases are optional.
+//! All cases are optional.
 //!
 //! # BDP Algorithm
 //!
@@ -18,14 +19,22 @@
 //!    3b. Merge RTT with a running average.
 //!    3c. Calculate bdp as bytes/rtt.
 //!    3d. If bdp is over 2/3 max, set new max to bdp and update windows.
+//!
+//! The window never grows past a configurable maximum, and the delay between
+//! BDP pings can be kept from shrinking below a configurable interval.
 
 use std::fmt;
 use std::future::Future;
 use std::pin::Pin;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, Weak};
 use std::task::{self, Poll};
 use std::time::{Duration, Instant};
 
+#[cfg(feature = "client")]
+use futures_channel::mpsc;
+use futures_channel::oneshot;
+#[cfg(feature = "client")]
+use futures_core::Stream;
 use h2::{Ping, PingPong};
 
 use crate::common::time::Time;
@@ -33,22 +42,33 @@ use crate::rt::Sleep;
 
 type WindowSize = u32;
 
+#[cfg(feature = "client")]
 pub(super) fn disabled() -> Recorder {
-    Recorder { shared: None }
+    Recorder {
+        shared: None,
+        _open_streams: None,
+    }
 }
 
+/// Creates the recorder and ponger for a connection.
+///
+/// The ponger is needed even without bdp or keep-alive, to answer the
+/// user's pings, but then the recorder is disabled.
 pub(super) fn channel(ping_pong: PingPong, config: Config, __timer: Time) -> (Recorder, Ponger) {
-    debug_assert!(
-        config.is_enabled(),
-        "ping channel requires bdp or keep-alive config",
-    );
-
-    let bdp = config.bdp_initial_window.map(|wnd| Bdp {
-        bdp: wnd,
-        max_bandwidth: 0.0,
-        rtt: 0.0,
-        ping_delay: Duration::from_millis(100),
-        stable_count: 0,
+    let bdp = config.bdp_initial_window.map(|wnd| {
+        let max_window = config.bdp_max_window.unwrap_or(BDP_LIMIT);
+        Bdp {
+            bdp: wnd,
+            // never below where the window starts
+            max_window: max_window.max(wnd),
+            max_bandwidth: 0.0,
+            rtt: 0.0,
+            ping_delay: config
+                .bdp_ping_interval
+                .unwrap_or(DEFAULT_BDP_PING_DELAY),
+            min_ping_delay: config.bdp_ping_interval.unwrap_or(Duration::ZERO),
+            stable_count: 0,
+        }
     });
 
     let (bytes, next_bdp_at) = if bdp.is_some() {
@@ -75,11 +95,13 @@ pub(super) fn channel(ping_pong: PingPong, config: Config, __timer: Time) -> (Re
         ping_pong,
         ping_sent_at: None,
         next_bdp_at,
+        rtt_waiters: Vec::new(),
     }));
 
     (
         Recorder {
-            shared: Some(shared.clone()),
+            shared: Some(shared.clone()).filter(|_| config.is_enabled()),
+            _open_streams: None,
         },
         Ponger {
             bdp,
@@ -92,6 +114,10 @@ pub(super) fn channel(ping_pong: PingPong, config: Config, __timer: Time) -> (Re
 #[derive(Clone)]
 pub(super) struct Config {
     pub(super) bdp_initial_window: Option<WindowSize>,
+    /// The largest window BDP may grow to, if not the default limit.
+    pub(super) bdp_max_window: Option<WindowSize>,
+    /// The shortest delay between BDP pings, if limited.
+    pub(super) bdp_ping_interval: Option<Duration>,
     /// If no frames are received in this amount of time, a PING frame is sent.
     pub(super) keep_alive_interval: Option<Duration>,
     /// After sending a keepalive PING, the connection will be closed if
@@ -104,6 +130,8 @@ pub(super) struct Config {
 #[derive(Clone)]
 pub(crate) struct Recorder {
     shared: Option<Arc<Mutex<Shared>>>,
+    /// Counts a client's open streams, by how many recorders hold it.
+    _open_streams: Option<Arc<()>>,
 }
 
 pub(super) struct Ponger {
@@ -112,6 +140,32 @@ pub(super) struct Ponger {
     shared: Arc<Mutex<Shared>>,
 }
 
+/// Sends pings for the user, to measure the round trip time.
+///
+/// This doesn't keep the connection's ping state alive, nor count as an
+/// open stream for keep-alive.
+#[derive(Clone)]
+pub(crate) struct Pinger {
+    shared: Weak<Mutex<Shared>>,
+    /// Hands the pings to a `LazyPonger` that hasn't started yet.
+    #[cfg(feature = "client")]
+    lazy: Option<mpsc::UnboundedSender<oneshot::Sender<Duration>>>,
+}
+
+/// Answers the user's pings on a client connection that doesn't ping
+/// otherwise, only starting a ponger once the first is sent.
+#[cfg(feature = "client")]
+pub(super) struct LazyPonger {
+    ponger: Option<Ponger>,
+    start: Option<(PingPong, Config, Time)>,
+    pings: Option<mpsc::UnboundedReceiver<oneshot::Sender<Duration>>>,
+}
+
+/// The round trip time of a user's ping, once it is acknowledged.
+///
+/// Fails if the connection closes first.
+pub(crate) struct Rtt(oneshot::Receiver<Duration>);
+
 struct Shared {
     ping_pong: PingPong,
     ping_sent_at: Option<Instant>,
@@ -130,11 +184,17 @@ struct Shared {
     last_read_at: Option<Instant>,
 
     is_keep_alive_timed_out: bool,
+
+    // user pings
+    /// Told the round trip time when the ping in flight is acknowledged.
+    rtt_waiters: Vec<oneshot::Sender<Duration>>,
 }
 
 struct Bdp {
     /// Current BDP in bytes
     bdp: u32,
+    /// The BDP won't grow past this.
+    max_window: u32,
     /// Largest bandwidth we've seen so far.
     max_bandwidth: f64,
     /// Round trip time in seconds
@@ -143,6 +203,8 @@ struct Bdp {
     ///
     /// This will change depending on how stable the current bandwidth is.
     ping_delay: Duration,
+    /// The ping delay won't shrink below this.
+    min_ping_delay: Duration,
     /// The count of ping round trips where BDP has stayed the same.
     stable_count: u32,
 }
@@ -231,6 +293,14 @@ impl Recorder {
         locked.update_last_read_at();
     }
 
+    /// Makes this recorder, and the ones cloned for each stream, count
+    /// towards `open_streams`.
+    #[cfg(feature = "client")]
+    pub(super) fn with_open_streams(mut self, open_streams: Arc<()>) -> Self {
+        self._open_streams = Some(open_streams);
+        self
+    }
+
     /// If the incoming stream is already closed, convert self into
     /// a disabled reporter.
     #[cfg(feature = "client")]
@@ -255,9 +325,121 @@ impl Recorder {
     }
 }
 
+// ===== impl Pinger =====
+
+impl Pinger {
+    /// Sends a PING, unless one is already in flight, and resolves with the
+    /// round trip time once it is acknowledged.
+    #[cfg(feature = "client")]
+    pub(crate) fn ping(&self) -> Rtt {
+        let (tx, rtt) = Rtt::channel();
+        self.ping_for(tx);
+        rtt
+    }
+
+    /// Like `ping`, telling the round trip time to `tx`.
+    pub(super) fn ping_for(&self, tx: oneshot::Sender<Duration>) {
+        #[cfg(feature = "client")]
+        if let Some(ref lazy) = self.lazy {
+            // if the connection is gone, dropping `tx` fails the wait
+            let _ = lazy.unbounded_send(tx);
+            return;
+        }
+        if let Some(shared) = self.shared.upgrade() {
+            let mut locked = shared.lock().unwrap();
+            if !locked.is_ping_sent() {
+                locked.send_ping();
+            }
+            // if the ping couldn't be sent, dropping `tx` fails the wait
+            if locked.is_ping_sent() {
+                locked.rtt_waiters.push(tx);
+            }
+        }
+    }
+}
+
+// ===== impl LazyPonger =====
+
+#[cfg(feature = "client")]
+impl LazyPonger {
+    /// Creates a ponger that starts with the user's first ping, and the
+    /// pinger that sends them.
+    pub(super) fn new(ping_pong: PingPong, config: Config, timer: Time) -> (Pinger, LazyPonger) {
+        let (tx, rx) = mpsc::unbounded();
+        let pinger = Pinger {
+            shared: Weak::new(),
+            lazy: Some(tx),
+        };
+        let ponger = LazyPonger {
+            ponger: None,
+            start: Some((ping_pong, config, timer)),
+            pings: Some(rx),
+        };
+        (pinger, ponger)
+    }
+
+    /// Wraps a ponger that has already started.
+    pub(super) fn started(ponger: Ponger) -> LazyPonger {
+        LazyPonger {
+            ponger: Some(ponger),
+            start: None,
+            pings: None,
+        }
+    }
+
+    pub(super) fn poll(&mut self, cx: &mut task::Context<'_>) -> Poll<Ponged> {
+        if let Some(ref mut pings) = self.pings {
+            while let Poll::Ready(Some(tx)) = Pin::new(&mut *pings).poll_next(cx) {
+                let ponger = match self.ponger {
+                    Some(ref ponger) => ponger,
+                    None => {
+                        trace!("starting ponger for user ping");
+                        let (ping_pong, config, timer) =
+                            self.start.take().expect("ponger started once");
+                        self.ponger.insert(channel(ping_pong, config, timer).1)
+                    }
+                };
+                ponger.pinger().ping_for(tx);
+            }
+        }
+
+        match self.ponger {
+            Some(ref mut ponger) => ponger.poll(cx),
+            None => Poll::Pending,
+        }
+    }
+}
+
+// ===== impl Rtt =====
+
+impl Rtt {
+    pub(super) fn channel() -> (oneshot::Sender<Duration>, Rtt) {
+        let (tx, rx) = oneshot::channel();
+        (tx, Rtt(rx))
+    }
+}
+
+impl Future for Rtt {
+    type Output = crate::Result<Duration>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.0)
+            .poll(cx)
+            .map_err(|_| crate::Error::new_canceled())
+    }
+}
+
 // ===== impl Ponger =====
 
 impl Ponger {
+    pub(super) fn pinger(&self) -> Pinger {
+        Pinger {
+            shared: Arc::downgrade(&self.shared),
+            #[cfg(feature = "client")]
+            lazy: None,
+        }
+    }
+
     pub(super) fn poll(&mut self, cx: &mut task::Context<'_>) -> Poll<Ponged> {
         let now = Instant::now();
         let mut locked = self.shared.lock().unwrap();
@@ -282,6 +464,10 @@ impl Ponger {
                 let rtt = now - start;
                 trace!("recv pong");
 
+                for tx in locked.rtt_waiters.drain(..) {
+                    let _ = tx.send(rtt);
+                }
+
                 if let Some(ref mut ka) = self.keep_alive {
                     locked.update_last_read_at();
                     ka.maybe_schedule(is_idle, &locked);
@@ -302,6 +488,7 @@ impl Ponger {
             }
             Poll::Ready(Err(_e)) => {
                 debug!("pong error: {}", _e);
+                locked.rtt_waiters.clear();
             }
             Poll::Pending => {
                 if let Some(ref mut ka) = self.keep_alive {
@@ -323,6 +510,15 @@ impl Ponger {
     }
 }
 
+impl Drop for Ponger {
+    fn drop(&mut self) {
+        // Nobody is left to see the pong.
+        if let Ok(mut locked) = self.shared.lock() {
+            locked.rtt_waiters.clear();
+        }
+    }
+}
+
 // ===== impl Shared =====
 
 impl Shared {
@@ -356,12 +552,14 @@ impl Shared {
 // ===== impl Bdp =====
 
 /// Any higher than this likely will be hitting the TCP flow control.
-const BDP_LIMIT: usize = 1024 * 1024 * 16;
+const BDP_LIMIT: WindowSize = 1024 * 1024 * 16;
+
+const DEFAULT_BDP_PING_DELAY: Duration = Duration::from_millis(100);
 
 impl Bdp {
     fn calculate(&mut self, bytes: usize, rtt: Duration) -> Option<WindowSize> {
         // No need to do any math if we're at the limit.
-        if self.bdp as usize == BDP_LIMIT {
+        if self.bdp >= self.max_window {
             self.stabilize_delay();
             return None;
         }
@@ -391,11 +589,11 @@ impl Bdp {
         // if the current `bytes` sample is at least 2/3 the previous
         // bdp, increase to double the current sample.
         if bytes >= self.bdp as usize * 2 / 3 {
-            self.bdp = (bytes * 2).min(BDP_LIMIT) as WindowSize;
+            self.bdp = (bytes * 2).min(self.max_window as usize) as WindowSize;
             trace!("BDP increased to {}", self.bdp);
 
             self.stable_count = 0;
-            self.ping_delay /= 2;
+            self.ping_delay = (self.ping_delay / 2).max(self.min_ping_delay);
             Some(self.bdp)
         } else {
             self.stabilize_delay();
@@ -508,3 +706,42 @@ impl std::error::Error for KeepAliveTimedOut {
         Some(&crate::error::TimedOut)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bdp(max_window: WindowSize, min_ping_delay: Duration) -> Bdp {
+        Bdp {
+            bdp: 65_535,
+            max_window,
+            max_bandwidth: 0.0,
+            rtt: 0.0,
+            ping_delay: DEFAULT_BDP_PING_DELAY,
+            min_ping_delay,
+            stable_count: 0,
+        }
+    }
+
+    #[test]
+    fn bdp_grows_up_to_max_window() {
+        let mut bdp = bdp(200_000, Duration::ZERO);
+        let rtt = Duration::from_millis(10);
+
+        assert_eq!(bdp.calculate(60_000, rtt), Some(120_000));
+        assert_eq!(bdp.calculate(100_000, rtt), Some(200_000));
+        // at the max, no more updates
+        assert_eq!(bdp.calculate(1_000_000, rtt), None);
+        assert_eq!(bdp.bdp, 200_000);
+    }
+
+    #[test]
+    fn bdp_ping_delay_stays_above_min() {
+        let mut bdp = bdp(BDP_LIMIT, Duration::from_millis(80));
+        let rtt = Duration::from_millis(10);
+
+        assert!(bdp.calculate(60_000, rtt).is_some());
+        assert!(bdp.calculate(100_000, rtt).is_some());
+        assert_eq!(bdp.ping_delay, Duration::from_millis(80));
+    }
+}
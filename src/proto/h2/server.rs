@@ -1,32 +1,38 @@
 use std::error::Error as StdError;
 use std::future::Future;
 use std::pin::Pin;
-use std::task::{Context, Poll};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
 use std::time::Duration;
 
-use bytes::Bytes;
-use futures_core::ready;
+use bytes::{Buf, Bytes, BytesMut};
+use futures_channel::{mpsc, oneshot};
+use futures_core::{ready, Stream};
 use h2::server::{Connection, Handshake, SendResponse};
 use h2::{Reason, RecvStream};
-use http::{Method, Request};
+use http::{Method, Request, StatusCode};
 use pin_project_lite::pin_project;
 
-use super::{ping, PipeToSendStream, SendBuf};
+use super::goaway::{self, GoAwayIo, SharedDebugData};
+use super::tap::{FrameTap, Frames, SharedFrames};
+use super::{ping, PRIORITY, PipeToSendStream, SendBuf};
 use crate::body::{Body, Incoming as IncomingBody};
-use crate::common::date;
+use crate::common::date::DateSource;
+use crate::common::stats::Clock;
 use crate::common::io::Compat;
 use crate::common::time::Time;
-use crate::ext::Protocol;
+use crate::ext::{AltSvc, Priority, Protocol, ResetStream, SendInformational, StreamId};
 use crate::headers;
 use crate::proto::h2::ping::Recorder;
 use crate::proto::h2::{H2Upgraded, UpgradedSendStream};
 use crate::proto::Dispatched;
 use crate::rt::bounds::Http2ServerConnExec;
 use crate::rt::{Read, Write};
+use crate::server::conn::http2::ResetStreamAction;
 use crate::service::HttpService;
 
 use crate::upgrade::{OnUpgrade, Pending, Upgraded};
-use crate::Response;
+use crate::{H2Settings, Response};
 
 // Our defaults are chosen for the "majority" case, which usually are not
 // resource constrained, and so the spec default of 64kb can be too limiting
@@ -44,6 +50,8 @@ const DEFAULT_MAX_LOCAL_ERROR_RESET_STREAMS: usize = 1024;
 #[derive(Clone, Debug)]
 pub(crate) struct Config {
     pub(crate) adaptive_window: bool,
+    pub(crate) adaptive_window_max: Option<u32>,
+    pub(crate) adaptive_window_ping_interval: Option<Duration>,
     pub(crate) initial_conn_window_size: u32,
     pub(crate) initial_stream_window_size: u32,
     pub(crate) max_frame_size: u32,
@@ -51,17 +59,29 @@ pub(crate) struct Config {
     pub(crate) max_concurrent_streams: Option<u32>,
     pub(crate) max_pending_accept_reset_streams: Option<usize>,
     pub(crate) max_local_error_reset_streams: Option<usize>,
+    pub(crate) max_reset_stream_rate: Option<(u32, Duration)>,
+    pub(crate) reset_stream_rate_action: ResetStreamAction,
     pub(crate) keep_alive_interval: Option<Duration>,
     pub(crate) keep_alive_timeout: Duration,
+    pub(crate) keep_alive_while_idle: bool,
     pub(crate) max_send_buffer_size: usize,
     pub(crate) max_header_list_size: u32,
+    pub(crate) max_headers: Option<usize>,
+    pub(crate) max_requests: Option<usize>,
+    pub(crate) max_request_body_size: Option<u64>,
+    pub(crate) max_concurrent_requests: Option<usize>,
     pub(crate) date_header: bool,
+    pub(crate) date_source: DateSource,
+    pub(crate) origin_set: Vec<String>,
+    pub(crate) alt_svc: Vec<AltSvc>,
 }
 
 impl Default for Config {
     fn default() -> Config {
         Config {
             adaptive_window: false,
+            adaptive_window_max: None,
+            adaptive_window_ping_interval: None,
             initial_conn_window_size: DEFAULT_CONN_WINDOW,
             initial_stream_window_size: DEFAULT_STREAM_WINDOW,
             max_frame_size: DEFAULT_MAX_FRAME_SIZE,
@@ -69,11 +89,21 @@ impl Default for Config {
             max_concurrent_streams: Some(200),
             max_pending_accept_reset_streams: None,
             max_local_error_reset_streams: Some(DEFAULT_MAX_LOCAL_ERROR_RESET_STREAMS),
+            max_reset_stream_rate: None,
+            reset_stream_rate_action: ResetStreamAction::default(),
             keep_alive_interval: None,
             keep_alive_timeout: Duration::from_secs(20),
+            keep_alive_while_idle: true,
             max_send_buffer_size: DEFAULT_MAX_SEND_BUF_SIZE,
             max_header_list_size: DEFAULT_SETTINGS_MAX_HEADER_LIST_SIZE,
+            max_headers: None,
+            max_requests: None,
+            max_request_body_size: None,
+            max_concurrent_requests: None,
             date_header: true,
+            date_source: DateSource::default(),
+            origin_set: Vec::new(),
+            alt_svc: Vec::new(),
         }
     }
 }
@@ -88,8 +118,21 @@ pin_project! {
         timer: Time,
         service: S,
         state: State<T, B>,
-        date_header: bool,
-        close_pending: bool
+        // None if the `Date` header is turned off.
+        date_header: Option<DateSource>,
+        close_pending: bool,
+        // Set with close_pending when the shutdown should be abrupt.
+        abrupt_pending: Option<Reason>,
+        debug_data: SharedDebugData,
+        frames: SharedFrames,
+        reset_stream_rate_action: ResetStreamAction,
+        max_headers: Option<usize>,
+        max_requests: Option<usize>,
+        max_request_body_size: Option<u64>,
+        max_concurrent_requests: Option<usize>,
+        enable_connect_protocol: bool,
+        // Pings asked for before the handshake finished.
+        pending_pings: Vec<oneshot::Sender<Duration>>,
     }
 }
 
@@ -97,9 +140,14 @@ enum State<T, B>
 where
     B: Body,
 {
+    // Not polled yet, so the settings can still be changed.
+    Pending {
+        ping_config: ping::Config,
+        start: Option<(h2::server::Builder, GoAwayIo<FrameTap<Compat<T>>>)>,
+    },
     Handshaking {
         ping_config: ping::Config,
-        hs: Handshake<Compat<T>, SendBuf<B::Data>>,
+        hs: Handshake<GoAwayIo<FrameTap<Compat<T>>>, SendBuf<B::Data>>,
     },
     Serving(Serving<T, B>),
 }
@@ -108,10 +156,35 @@ struct Serving<T, B>
 where
     B: Body,
 {
-    ping: Option<(ping::Recorder, ping::Ponger)>,
-    conn: Connection<Compat<T>, SendBuf<B::Data>>,
+    ping: (ping::Recorder, ping::Ponger),
+    conn: Connection<GoAwayIo<FrameTap<Compat<T>>>, SendBuf<B::Data>>,
     closing: Option<crate::Error>,
-    date_header: bool,
+    date_header: Option<DateSource>,
+    frames: SharedFrames,
+    reset_stream_rate_action: ResetStreamAction,
+    max_headers: Option<usize>,
+    max_request_body_size: Option<u64>,
+    // Requests that may still be accepted before shutting down.
+    requests_left: Option<usize>,
+    in_flight: Option<InFlight>,
+}
+
+/// Counts the service futures of a connection that haven't resolved, to
+/// stop accepting streams while there are too many.
+struct InFlight {
+    max: usize,
+    state: Arc<Mutex<InFlightState>>,
+}
+
+struct InFlightState {
+    count: usize,
+    // The connection task, waiting for a service future to resolve.
+    waker: Option<Waker>,
+}
+
+/// Held by an `H2Stream` until its service future resolves.
+struct InFlightPermit {
+    state: Arc<Mutex<InFlightState>>,
 }
 
 impl<T, S, B, E> Server<T, S, B, E>
@@ -146,7 +219,16 @@ where
         if config.enable_connect_protocol {
             builder.enable_connect_protocol();
         }
-        let handshake = builder.handshake(Compat::new(io));
+        let debug_data = SharedDebugData::default();
+        let frames = Frames::shared(Clock::default());
+        if let Some((max, interval)) = config.max_reset_stream_rate {
+            frames.lock().unwrap().limit_reset_rate(max, interval);
+        }
+        let io = FrameTap::server(Compat::new(io), frames.clone());
+        let mut after_settings = BytesMut::new();
+        after_settings.extend_from_slice(&goaway::encode_origin(&config.origin_set));
+        after_settings.extend_from_slice(&goaway::encode_alt_svc(&config.alt_svc));
+        let io = GoAwayIo::new(io, debug_data.clone()).after_settings(after_settings.freeze());
 
         let bdp = if config.adaptive_window {
             Some(config.initial_stream_window_size)
@@ -156,30 +238,52 @@ where
 
         let ping_config = ping::Config {
             bdp_initial_window: bdp,
+            bdp_max_window: config.adaptive_window_max,
+            bdp_ping_interval: config.adaptive_window_ping_interval,
             keep_alive_interval: config.keep_alive_interval,
             keep_alive_timeout: config.keep_alive_timeout,
-            // If keep-alive is enabled for servers, always enabled while
-            // idle, so it can more aggressively close dead connections.
-            keep_alive_while_idle: true,
+            keep_alive_while_idle: config.keep_alive_while_idle,
         };
 
         Server {
             exec,
             timer,
-            state: State::Handshaking {
+            state: State::Pending {
                 ping_config,
-                hs: handshake,
+                start: Some((builder, io)),
             },
             service,
-            date_header: config.date_header,
+            date_header: Some(config.date_source.clone()).filter(|_| config.date_header),
             close_pending: false,
+            abrupt_pending: None,
+            debug_data,
+            frames,
+            reset_stream_rate_action: config.reset_stream_rate_action,
+            max_headers: config.max_headers,
+            max_requests: config.max_requests,
+            max_request_body_size: config.max_request_body_size,
+            max_concurrent_requests: config.max_concurrent_requests,
+            enable_connect_protocol: config.enable_connect_protocol,
+            pending_pings: Vec::new(),
+        }
+    }
+
+    pub(crate) fn max_header_list_size(&mut self, max: u32) {
+        if let State::Pending {
+            start: Some((ref mut builder, _)),
+            ..
+        } = self.state
+        {
+            builder.max_header_list_size(max);
+        } else {
+            debug!("max_header_list_size ignored, connection already started");
         }
     }
 
     pub(crate) fn graceful_shutdown(&mut self) {
         trace!("graceful_shutdown");
         match self.state {
-            State::Handshaking { .. } => {
+            State::Pending { .. } | State::Handshaking { .. } => {
                 self.close_pending = true;
             }
             State::Serving(ref mut srv) => {
@@ -189,6 +293,48 @@ where
             }
         }
     }
+
+    pub(crate) fn ping(&mut self) -> ping::Rtt {
+        let (tx, rtt) = ping::Rtt::channel();
+        match self.state {
+            State::Pending { .. } | State::Handshaking { .. } => self.pending_pings.push(tx),
+            State::Serving(ref srv) => srv.ping.1.pinger().ping_for(tx),
+        }
+        rtt
+    }
+
+    pub(crate) fn peer_settings(&self) -> Option<H2Settings> {
+        self.frames.lock().unwrap().current_settings()
+    }
+
+    pub(crate) fn is_extended_connect_enabled(&self) -> bool {
+        self.enable_connect_protocol
+    }
+
+    pub(crate) fn go_away(&mut self, abrupt: Option<u32>, debug_data: Bytes) {
+        trace!("go_away");
+        if !debug_data.is_empty() {
+            let error_code = abrupt.unwrap_or(0);
+            self.debug_data.lock().unwrap().arm(error_code, debug_data);
+        }
+        let abrupt = abrupt.map(Reason::from);
+        match (&mut self.state, abrupt) {
+            (State::Pending { .. }, _) | (State::Handshaking { .. }, _) => {
+                self.close_pending = true;
+                self.abrupt_pending = abrupt.or(self.abrupt_pending);
+            }
+            (State::Serving(srv), Some(reason)) => {
+                if srv.closing.is_none() {
+                    srv.conn.abrupt_shutdown(reason);
+                }
+            }
+            (State::Serving(srv), None) => {
+                if srv.closing.is_none() {
+                    srv.conn.graceful_shutdown();
+                }
+            }
+        }
+    }
 }
 
 impl<T, S, B, E> Future for Server<T, S, B, E>
@@ -205,28 +351,47 @@ where
         let me = &mut *self;
         loop {
             let next = match me.state {
+                State::Pending {
+                    ref mut start,
+                    ref ping_config,
+                } => {
+                    let (builder, io) = start.take().expect("polled after start");
+                    State::Handshaking {
+                        ping_config: ping_config.clone(),
+                        hs: builder.handshake(io),
+                    }
+                }
                 State::Handshaking {
                     ref mut hs,
                     ref ping_config,
                 } => {
                     let mut conn = ready!(Pin::new(hs).poll(cx).map_err(crate::Error::new_h2))?;
-                    let ping = if ping_config.is_enabled() {
-                        let pp = conn.ping_pong().expect("conn.ping_pong");
-                        Some(ping::channel(pp, ping_config.clone(), me.timer.clone()))
-                    } else {
-                        None
-                    };
+                    let pp = conn.ping_pong().expect("conn.ping_pong");
+                    let ping = ping::channel(pp, ping_config.clone(), me.timer.clone());
+                    let pinger = ping.1.pinger();
+                    for tx in me.pending_pings.drain(..) {
+                        pinger.ping_for(tx);
+                    }
                     State::Serving(Serving {
                         ping,
                         conn,
                         closing: None,
-                        date_header: me.date_header,
+                        date_header: me.date_header.clone(),
+                        frames: me.frames.clone(),
+                        reset_stream_rate_action: me.reset_stream_rate_action,
+                        max_headers: me.max_headers,
+                        max_request_body_size: me.max_request_body_size,
+                        requests_left: me.max_requests,
+                        in_flight: me.max_concurrent_requests.map(InFlight::new),
                     })
                 }
                 State::Serving(ref mut srv) => {
                     // graceful_shutdown was called before handshaking finished,
                     if me.close_pending && srv.closing.is_none() {
-                        srv.conn.graceful_shutdown();
+                        match me.abrupt_pending.take() {
+                            Some(reason) => srv.conn.abrupt_shutdown(reason),
+                            None => srv.conn.graceful_shutdown(),
+                        }
                     }
                     ready!(srv.poll_server(cx, &mut me.service, &mut me.exec))?;
                     return Poll::Ready(Ok(Dispatched::Shutdown));
@@ -257,15 +422,73 @@ where
             loop {
                 self.poll_ping(cx);
 
-                match ready!(self.conn.poll_accept(cx)) {
+                let accepted = if self.in_flight.as_ref().map_or(true, |f| f.poll_ready(cx)) {
+                    self.conn.poll_accept(cx)
+                } else {
+                    // Keep driving the connection, leaving new streams
+                    // queued until a service future resolves.
+                    match self.conn.poll_closed(cx) {
+                        Poll::Ready(Ok(())) => Poll::Ready(None),
+                        Poll::Ready(Err(e)) => Poll::Ready(Some(Err(e))),
+                        Poll::Pending => Poll::Pending,
+                    }
+                };
+                if self.frames.lock().unwrap().is_reset_rate_exceeded() {
+                    debug!("client reset too many streams, closing connection");
+                    let err = crate::Error::new_h2(Reason::ENHANCE_YOUR_CALM.into());
+                    match self.reset_stream_rate_action {
+                        ResetStreamAction::GoAway => {
+                            self.conn.abrupt_shutdown(Reason::ENHANCE_YOUR_CALM);
+                            self.closing = Some(err);
+                            break;
+                        }
+                        ResetStreamAction::Drop => return Poll::Ready(Err(err)),
+                    }
+                }
+
+                match ready!(accepted) {
                     Some(Ok((req, mut respond))) => {
                         trace!("incoming request");
+                        if let Some(ref mut left) = self.requests_left {
+                            if *left == 0 {
+                                // sent before the GOAWAY arrived, and safe
+                                // for the client to retry
+                                respond.send_reset(Reason::REFUSED_STREAM);
+                                self.conn.graceful_shutdown();
+                                continue;
+                            }
+                            *left -= 1;
+                            if *left == 0 {
+                                debug!("max requests reached, shutting down gracefully");
+                                self.conn.graceful_shutdown();
+                            }
+                        }
+                        if self.max_headers.map_or(false, |max| req.headers().len() > max) {
+                            debug!("request has too many headers");
+                            let res = Response::builder()
+                                .status(StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE)
+                                .body(())
+                                .expect("static response");
+                            if let Err(_e) = respond.send_response(res, true) {
+                                debug!("error sending 431 response: {}", _e);
+                            }
+                            continue;
+                        }
                         let content_length = headers::content_length_parse_all(req.headers());
-                        let ping = self
-                            .ping
-                            .as_ref()
-                            .map(|ping| ping.0.clone())
-                            .unwrap_or_else(ping::disabled);
+                        if let (Some(max), Some(len)) = (self.max_request_body_size, content_length) {
+                            if len > max {
+                                debug!("request content-length {} over limit {}", len, max);
+                                let res = Response::builder()
+                                    .status(StatusCode::PAYLOAD_TOO_LARGE)
+                                    .body(())
+                                    .expect("static response");
+                                if let Err(_e) = respond.send_response(res, true) {
+                                    debug!("error sending 413 response: {}", _e);
+                                }
+                                continue;
+                            }
+                        }
+                        let ping = self.ping.0.clone();
 
                         // Record the headers received
                         ping.record_non_data();
@@ -273,13 +496,11 @@ where
                         let is_connect = req.method() == Method::CONNECT;
                         let (mut parts, stream) = req.into_parts();
                         let (mut req, connect_parts) = if !is_connect {
-                            (
-                                Request::from_parts(
-                                    parts,
-                                    IncomingBody::h2(stream, content_length.into(), ping),
-                                ),
-                                None,
-                            )
+                            let mut body = IncomingBody::h2(stream, content_length.into(), ping);
+                            if let Some(max) = self.max_request_body_size {
+                                body.set_h2_max_len(max);
+                            }
+                            (Request::from_parts(parts, body), None)
                         } else {
                             if content_length.map_or(false, |len| len != 0) {
                                 warn!("h2 connect request with non-zero body not supported");
@@ -303,11 +524,36 @@ where
                             req.extensions_mut().insert(Protocol::from_inner(protocol));
                         }
 
+                        if let Some(value) = req.headers().get(PRIORITY) {
+                            let priority = Priority::from_header(value);
+                            req.extensions_mut().insert(priority);
+                        }
+
+                        if let Some(flow) = req.body().h2_flow_control() {
+                            req.extensions_mut().insert(flow);
+                        }
+
+                        if let Some(settings) = self.frames.lock().unwrap().current_settings() {
+                            req.extensions_mut().insert(settings);
+                        }
+
+                        let (reset, on_reset) = ResetStream::new();
+                        req.extensions_mut().insert(reset);
+
+                        let (informational, informational_rx) = SendInformational::new();
+                        req.extensions_mut().insert(informational);
+
+                        req.extensions_mut()
+                            .insert(StreamId::from_h2(respond.stream_id()));
+
                         let fut = H2Stream::new(
                             service.call(req),
                             connect_parts,
                             respond,
-                            self.date_header,
+                            on_reset,
+                            informational_rx,
+                            self.date_header.clone(),
+                            self.in_flight.as_ref().map(InFlight::acquire),
                         );
 
                         exec.execute_h2stream(fut);
@@ -317,9 +563,7 @@ where
                     }
                     None => {
                         // no more incoming streams...
-                        if let Some((ref ping, _)) = self.ping {
-                            ping.ensure_not_timed_out()?;
-                        }
+                        self.ping.0.ensure_not_timed_out()?;
 
                         trace!("incoming connection complete");
                         return Poll::Ready(Ok(()));
@@ -339,18 +583,62 @@ where
     }
 
     fn poll_ping(&mut self, cx: &mut Context<'_>) {
-        if let Some((_, ref mut estimator)) = self.ping {
-            match estimator.poll(cx) {
-                Poll::Ready(ping::Ponged::SizeUpdate(wnd)) => {
-                    self.conn.set_target_window_size(wnd);
-                    let _ = self.conn.set_initial_window_size(wnd);
-                }
-                Poll::Ready(ping::Ponged::KeepAliveTimedOut) => {
-                    debug!("keep-alive timed out, closing connection");
-                    self.conn.abrupt_shutdown(h2::Reason::NO_ERROR);
-                }
-                Poll::Pending => {}
+        match self.ping.1.poll(cx) {
+            Poll::Ready(ping::Ponged::SizeUpdate(wnd)) => {
+                self.conn.set_target_window_size(wnd);
+                let _ = self.conn.set_initial_window_size(wnd);
+            }
+            Poll::Ready(ping::Ponged::KeepAliveTimedOut) => {
+                debug!("keep-alive timed out, closing connection");
+                self.conn.abrupt_shutdown(h2::Reason::NO_ERROR);
             }
+            Poll::Pending => {}
+        }
+    }
+}
+
+// ===== impl InFlight =====
+
+impl InFlight {
+    fn new(max: usize) -> InFlight {
+        InFlight {
+            max,
+            state: Arc::new(Mutex::new(InFlightState {
+                count: 0,
+                waker: None,
+            })),
+        }
+    }
+
+    /// Returns whether another service future may start, or else waits for
+    /// one to resolve.
+    fn poll_ready(&self, cx: &mut Context<'_>) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.count < self.max {
+            return true;
+        }
+        trace!("max concurrent requests reached ({})", self.max);
+        state.waker = Some(cx.waker().clone());
+        false
+    }
+
+    fn acquire(&self) -> InFlightPermit {
+        self.state.lock().unwrap().count += 1;
+        InFlightPermit {
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl Drop for InFlightPermit {
+    fn drop(&mut self) {
+        let waker = {
+            let mut state = self.state.lock().unwrap();
+            state.count -= 1;
+            state.waker.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
         }
     }
 }
@@ -362,9 +650,15 @@ pin_project! {
         B: Body,
     {
         reply: SendResponse<SendBuf<B::Data>>,
+        // Resolves if the service resets the stream.
+        on_reset: oneshot::Receiver<u32>,
+        // Informational responses from the service, until its response.
+        informational: Option<mpsc::UnboundedReceiver<::http::Response<()>>>,
         #[pin]
         state: H2StreamState<F, B>,
-        date_header: bool,
+        date_header: Option<DateSource>,
+        // Released once the service future resolves.
+        in_flight: Option<InFlightPermit>,
     }
 }
 
@@ -400,12 +694,18 @@ where
         fut: F,
         connect_parts: Option<ConnectParts>,
         respond: SendResponse<SendBuf<B::Data>>,
-        date_header: bool,
+        on_reset: oneshot::Receiver<u32>,
+        informational: mpsc::UnboundedReceiver<::http::Response<()>>,
+        date_header: Option<DateSource>,
+        in_flight: Option<InFlightPermit>,
     ) -> H2Stream<F, B> {
         H2Stream {
             reply: respond,
+            on_reset,
+            informational: Some(informational),
             state: H2StreamState::Service { fut, connect_parts },
             date_header,
+            in_flight,
         }
     }
 }
@@ -423,6 +723,31 @@ macro_rules! reply {
     }};
 }
 
+/// Sends the informational responses the service has queued.
+fn send_informational<B: Buf>(
+    reply: &mut SendResponse<B>,
+    informational: &mut Option<mpsc::UnboundedReceiver<::http::Response<()>>>,
+    cx: &mut Context<'_>,
+) {
+    let rx = match informational {
+        Some(rx) => rx,
+        None => return,
+    };
+    while let Poll::Ready(res) = Pin::new(&mut *rx).poll_next(cx) {
+        let mut res = match res {
+            Some(res) => res,
+            None => {
+                *informational = None;
+                return;
+            }
+        };
+        super::strip_connection_headers(res.headers_mut(), false);
+        if let Err(_e) = reply.send_informational(res) {
+            debug!("send informational response error: {}", _e);
+        }
+    }
+}
+
 impl<F, B, E> H2Stream<F, B>
 where
     F: Future<Output = Result<Response<B>, E>>,
@@ -433,14 +758,29 @@ where
 {
     fn poll2(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<crate::Result<()>> {
         let mut me = self.project();
+        if let Poll::Ready(Ok(code)) = Pin::new(&mut *me.on_reset).poll(cx) {
+            debug!("service reset the stream: {:?}", Reason::from(code));
+            me.reply.send_reset(Reason::from(code));
+            return Poll::Ready(Ok(()));
+        }
         loop {
             let next = match me.state.as_mut().project() {
                 H2StreamStateProj::Service {
                     fut: h,
                     connect_parts,
                 } => {
+                    send_informational(me.reply, me.informational, cx);
                     let res = match h.poll(cx) {
-                        Poll::Ready(Ok(r)) => r,
+                        Poll::Ready(Ok(r)) => {
+                            *me.in_flight = None;
+                            // Any sent before the response was ready still
+                            // go first.
+                            if let Some(ref mut rx) = me.informational {
+                                rx.close();
+                            }
+                            send_informational(me.reply, me.informational, cx);
+                            r
+                        }
                         Poll::Pending => {
                             // Response is not yet ready, so we want to check if the client has sent a
                             // RST_STREAM frame which would cancel the current request.
@@ -455,7 +795,7 @@ where
                         Poll::Ready(Err(e)) => {
                             let err = crate::Error::new_user_service(e);
                             warn!("http2 service errored: {}", err);
-                            me.reply.send_reset(err.h2_reason());
+                            me.reply.send_reset(err.h2_reason_or_internal());
                             return Poll::Ready(Err(err));
                         }
                     };
@@ -465,10 +805,10 @@ where
                     super::strip_connection_headers(res.headers_mut(), false);
 
                     // set Date header if it isn't already set if instructed
-                    if *me.date_header {
+                    if let Some(ref date) = me.date_header {
                         res.headers_mut()
                             .entry(::http::header::DATE)
-                            .or_insert_with(date::update_and_header_value);
+                            .or_insert_with(|| date.value());
                     }
 
                     if let Some(connect_parts) = connect_parts.take() {
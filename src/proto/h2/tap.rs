@@ -0,0 +1,1164 @@
+//! HTTP2 frame stats
+//!
+//! h2 doesn't expose what it sends and receives, so for stats the client
+//! sits a `FrameTap` between h2 and the transport. It parses just the frame
+//! headers, and the few payloads it cares about, as the bytes go by.
+//!
+//! The server uses one too, to learn the client's settings and to count the
+//! streams it resets. Without stats, only the few frames that settle such
+//! state are handed to `Frames`, so the rest go by without taking its lock.
+//!
+//! The bytes going by are always counted, in `ByteCounts` rather than
+//! `Frames` for the same reason.
+
+use std::collections::{HashMap, VecDeque};
+use std::io::IoSlice;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use futures_channel::oneshot;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use super::{OnStall, SPEC_WINDOW_SIZE};
+use crate::common::stats::Clock;
+use crate::ext::AltSvc;
+use crate::{H2ConnectionStats, H2Settings, H2StreamStats, HttpConnectionStats, PingRtts};
+
+const FRAME_HEADER_LEN: usize = 9;
+const PREFACE_LEN: usize = 24;
+
+const DATA: u8 = 0x0;
+const HEADERS: u8 = 0x1;
+const RST_STREAM: u8 = 0x3;
+const ALTSVC: u8 = 0xa;
+const SETTINGS: u8 = 0x4;
+const PING: u8 = 0x6;
+const GOAWAY: u8 = 0x7;
+const WINDOW_UPDATE: u8 = 0x8;
+const CONTINUATION: u8 = 0x9;
+const ORIGIN: u8 = 0xc;
+
+const ACK: u8 = 0x1;
+const END_STREAM: u8 = 0x1;
+
+// How many unanswered PINGs to remember.
+const MAX_PINGS_IN_FLIGHT: usize = 8;
+// How many origins of ORIGIN frames to remember.
+const MAX_ORIGINS: usize = 64;
+// How many origins of ALTSVC frames to remember.
+const MAX_ALT_SVC: usize = 32;
+
+pub(super) type SharedFrames = Arc<Mutex<Frames>>;
+
+/// How many bytes a `FrameTap` has read and written.
+#[derive(Default)]
+pub(super) struct ByteCounts {
+    read: AtomicU64,
+    written: AtomicU64,
+}
+
+impl ByteCounts {
+    #[cfg_attr(not(feature = "client"), allow(dead_code))]
+    pub(super) fn read(&self) -> u64 {
+        self.read.load(Ordering::Relaxed)
+    }
+
+    #[cfg_attr(not(feature = "client"), allow(dead_code))]
+    pub(super) fn written(&self) -> u64 {
+        self.written.load(Ordering::Relaxed)
+    }
+}
+
+/// Wraps the transport given to h2, feeding the frames read and written
+/// through `Frames`.
+pub(super) struct FrameTap<T> {
+    io: T,
+    frames: SharedFrames,
+    bytes: Arc<ByteCounts>,
+    recv: Parser,
+    send: Parser,
+    // Whether every frame is wanted, or only those settling the state kept
+    // without stats.
+    stats: bool,
+}
+
+impl<T> FrameTap<T> {
+    /// Taps the client side of a connection.
+    #[cfg(feature = "client")]
+    pub(super) fn client(io: T, frames: SharedFrames, bytes: Arc<ByteCounts>, stats: bool) -> Self {
+        FrameTap {
+            io,
+            frames,
+            bytes,
+            recv: Parser::new(0),
+            // The client connection preface isn't framed.
+            send: Parser::new(PREFACE_LEN),
+            stats,
+        }
+    }
+
+    /// Taps the server side of a connection, where it's the client's
+    /// preface that goes by unframed.
+    #[cfg(feature = "server")]
+    pub(super) fn server(io: T, frames: SharedFrames) -> Self {
+        FrameTap {
+            io,
+            frames,
+            bytes: Arc::default(),
+            recv: Parser::new(PREFACE_LEN),
+            send: Parser::new(0),
+            stats: false,
+        }
+    }
+
+    fn read(&mut self, mut bytes: &[u8]) {
+        self.bytes.read.fetch_add(bytes.len() as u64, Ordering::Relaxed);
+        let mut locked = None;
+        while !bytes.is_empty() {
+            let (used, frame) = self.recv.feed(bytes);
+            bytes = &bytes[used..];
+            match frame {
+                Some(frame) if self.stats || !frame.is_only_for_stats() => {
+                    locked
+                        .get_or_insert_with(|| self.frames.lock().unwrap())
+                        .read(frame);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn write(&mut self, mut bytes: &[u8]) {
+        self.bytes.written.fetch_add(bytes.len() as u64, Ordering::Relaxed);
+        // Nothing else sent matters but for stats.
+        if !self.stats {
+            return;
+        }
+        let mut locked = None;
+        while !bytes.is_empty() {
+            let (used, frame) = self.send.feed(bytes);
+            bytes = &bytes[used..];
+            if let Some(frame) = frame {
+                locked
+                    .get_or_insert_with(|| self.frames.lock().unwrap())
+                    .write(frame);
+            }
+        }
+    }
+}
+
+impl<T> Drop for FrameTap<T> {
+    fn drop(&mut self) {
+        // Nothing more will be read, so a GOAWAY isn't coming.
+        if let Ok(mut frames) = self.frames.lock() {
+            frames.go_away_waiters = None;
+        }
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for FrameTap<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let filled = buf.filled().len();
+        let res = Pin::new(&mut self.io).poll_read(cx, buf);
+        self.read(&buf.filled()[filled..]);
+        res
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for FrameTap<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let res = Pin::new(&mut self.io).poll_write(cx, buf);
+        if let Poll::Ready(Ok(n)) = res {
+            self.write(&buf[..n]);
+        }
+        res
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<std::io::Result<usize>> {
+        let res = Pin::new(&mut self.io).poll_write_vectored(cx, bufs);
+        if let Poll::Ready(Ok(mut n)) = res {
+            for buf in bufs {
+                if n == 0 {
+                    break;
+                }
+                let len = buf.len().min(n);
+                self.write(&buf[..len]);
+                n -= len;
+            }
+        }
+        res
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.io.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.io).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.io).poll_shutdown(cx)
+    }
+}
+
+/// What has been seen so far, in both directions.
+pub(super) struct Frames {
+    peer_settings: Option<H2Settings>,
+    // The peer's settings with every SETTINGS frame applied.
+    current_settings: Option<H2Settings>,
+    // The origins from every ORIGIN frame, once there has been one.
+    origin_set: Option<Vec<String>>,
+    // The latest alternative services for each origin, from ALTSVC frames.
+    alt_svc: Vec<AltSvc>,
+    // The last stream ID and error code of the first GOAWAY received.
+    #[cfg_attr(not(feature = "client"), allow(dead_code))]
+    go_away: Option<(u32, u32)>,
+    // Waiting for a GOAWAY, until the transport is dropped.
+    go_away_waiters: Option<Vec<oneshot::Sender<(u32, u32)>>>,
+    send_window: u32,
+    recv_window: u32,
+    window_updates_received: u64,
+    window_updates_sent: u64,
+    pings_sent: VecDeque<([u8; 8], Instant)>,
+    ping_rtts: PingRtts,
+    // Streams someone is waiting on stats for.
+    streams: HashMap<u32, Stream>,
+    // When the last watched stream went away.
+    idle_since: Option<Instant>,
+    send_stalled: Duration,
+    // Limits how fast the peer may reset streams, if set.
+    reset_rate: Option<ResetRate>,
+    clock: Clock,
+}
+
+/// Counts the peer's RST_STREAM frames, in windows of `interval`.
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
+struct ResetRate {
+    max: u32,
+    interval: Duration,
+    window_start: Option<Instant>,
+    count: u32,
+    exceeded: bool,
+}
+
+/// What has been seen of one watched stream.
+#[derive(Default)]
+struct Stream {
+    stats: H2StreamStats,
+    stalled_since: Option<Instant>,
+    head_sent: u64,
+    body_sent: u64,
+    head_received: u64,
+}
+
+/// A frame, or the part of one that was needed.
+enum Frame<'a> {
+    Data {
+        stream_id: u32,
+        len: u32,
+        end_stream: bool,
+    },
+    // CONTINUATION frames are passed along as more HEADERS.
+    Headers {
+        stream_id: u32,
+        len: u32,
+        end_stream: bool,
+    },
+    Settings { ack: bool, payload: &'a [u8] },
+    Ping { ack: bool, payload: [u8; 8] },
+    WindowUpdate { stream_id: u32, increment: u32 },
+    GoAway { last_stream_id: u32, error_code: u32 },
+    Reset,
+    Origin { payload: &'a [u8] },
+    AltSvc { payload: &'a [u8] },
+}
+
+impl Frame<'_> {
+    /// Whether the frame only matters for stats.
+    fn is_only_for_stats(&self) -> bool {
+        match *self {
+            Frame::Data { .. } | Frame::Headers { .. } | Frame::Ping { .. } => true,
+            Frame::WindowUpdate { .. } => true,
+            Frame::Settings { ack, .. } => ack,
+            Frame::GoAway { .. } | Frame::Reset | Frame::Origin { .. } | Frame::AltSvc { .. } => {
+                false
+            }
+        }
+    }
+}
+
+impl Frames {
+    pub(super) fn new(clock: Clock) -> Self {
+        Frames {
+            peer_settings: None,
+            current_settings: None,
+            origin_set: None,
+            alt_svc: Vec::new(),
+            go_away: None,
+            go_away_waiters: Some(Vec::new()),
+            send_window: SPEC_WINDOW_SIZE,
+            recv_window: SPEC_WINDOW_SIZE,
+            window_updates_received: 0,
+            window_updates_sent: 0,
+            pings_sent: VecDeque::new(),
+            ping_rtts: PingRtts::default(),
+            streams: HashMap::new(),
+            idle_since: None,
+            send_stalled: Duration::ZERO,
+            reset_rate: None,
+            clock,
+        }
+    }
+
+    pub(super) fn shared(clock: Clock) -> SharedFrames {
+        Arc::new(Mutex::new(Frames::new(clock)))
+    }
+
+    /// Starts telling when the peer resets more than `max` streams in one
+    /// `interval`.
+    #[cfg(feature = "server")]
+    pub(super) fn limit_reset_rate(&mut self, max: u32, interval: Duration) {
+        self.reset_rate = Some(ResetRate {
+            max,
+            interval,
+            window_start: None,
+            count: 0,
+            exceeded: false,
+        });
+    }
+
+    /// Whether the peer has gone over the reset rate limit.
+    #[cfg(feature = "server")]
+    pub(super) fn is_reset_rate_exceeded(&self) -> bool {
+        self.reset_rate.as_ref().map_or(false, |rate| rate.exceeded)
+    }
+
+    /// The peer's current settings, once it has sent any.
+    pub(super) fn current_settings(&self) -> Option<H2Settings> {
+        self.current_settings
+    }
+
+    /// The origin set the peer has advertised, if it sent ORIGIN frames.
+    #[cfg(feature = "client")]
+    pub(super) fn origin_set(&self) -> Option<Vec<String>> {
+        self.origin_set.clone()
+    }
+
+    /// The alternative services the peer has advertised for any origin.
+    #[cfg(feature = "client")]
+    pub(super) fn alt_svc(&self) -> Vec<AltSvc> {
+        self.alt_svc.clone()
+    }
+
+    /// Waits for the peer's first GOAWAY, giving its last stream ID and
+    /// error code. Fails if the transport is dropped without one.
+    #[cfg(feature = "client")]
+    pub(super) fn on_go_away(&mut self) -> oneshot::Receiver<(u32, u32)> {
+        let (tx, rx) = oneshot::channel();
+        if let Some(go_away) = self.go_away {
+            let _ = tx.send(go_away);
+        } else if let Some(ref mut waiters) = self.go_away_waiters {
+            waiters.push(tx);
+        }
+        rx
+    }
+
+    pub(super) fn stats(&self) -> H2ConnectionStats {
+        H2ConnectionStats {
+            peer_settings: self.peer_settings.unwrap_or_default(),
+            send_window: self.send_window,
+            recv_window: self.recv_window,
+            window_updates_received: self.window_updates_received,
+            window_updates_sent: self.window_updates_sent,
+            ping_rtts: self.ping_rtts,
+            send_stalled: self.send_stalled,
+        }
+    }
+
+    /// Returns how long the connection has had no watched streams, if it
+    /// had any before, and marks it busy again.
+    pub(super) fn idle_before_reuse(&mut self) -> Option<Duration> {
+        if !self.streams.is_empty() {
+            return None;
+        }
+        let since = self.idle_since.take()?;
+        Some(self.clock.elapsed(since))
+    }
+
+    /// Marks a stream's body as waiting for send capacity, or done waiting.
+    fn stall(&mut self, id: u32, stalled: bool) {
+        let now = self.clock.now();
+        let since = match self.streams.get_mut(&id) {
+            Some(stream) if stalled => {
+                stream.stalled_since.get_or_insert(now);
+                return;
+            }
+            Some(stream) => stream.stalled_since.take(),
+            None => None,
+        };
+        if let Some(since) = since {
+            self.add_send_stall(id, now.saturating_duration_since(since));
+        }
+    }
+
+    /// Adds to how long a stream's body waited for send capacity.
+    fn add_send_stall(&mut self, id: u32, stalled: Duration) {
+        self.send_stalled += stalled;
+        if let Some(stream) = self.streams.get_mut(&id) {
+            stream.stats.send_stalled += stalled;
+        }
+    }
+
+    fn read(&mut self, frame: Frame<'_>) {
+        match frame {
+            Frame::Data {
+                stream_id,
+                len,
+                end_stream,
+            } => {
+                self.recv_window = self.recv_window.saturating_sub(len);
+                if let Some(stream) = self.streams.get_mut(&stream_id) {
+                    let now = self.clock.now();
+                    stream.stats.first_data.get_or_insert(now);
+                    if end_stream {
+                        stream.stats.end_stream = Some(now);
+                    }
+                }
+            }
+            Frame::Headers {
+                stream_id,
+                len,
+                end_stream,
+            } => {
+                if let Some(stream) = self.streams.get_mut(&stream_id) {
+                    let now = self.clock.now();
+                    stream.stats.headers.get_or_insert(now);
+                    stream.head_received += u64::from(len);
+                    if end_stream {
+                        stream.stats.end_stream = Some(now);
+                    }
+                }
+            }
+            Frame::Settings { ack: false, payload } => {
+                if self.peer_settings.is_none() {
+                    self.peer_settings = Some(parse_settings(H2Settings::default(), payload));
+                }
+                let current = self.current_settings.unwrap_or_default();
+                self.current_settings = Some(parse_settings(current, payload));
+            }
+            Frame::Ping { ack: true, payload } => {
+                if let Some(pos) = self.pings_sent.iter().position(|p| p.0 == payload) {
+                    let (_, sent_at) = self.pings_sent.remove(pos).expect("position");
+                    self.ping_rtts.push(self.clock.elapsed(sent_at));
+                }
+            }
+            Frame::WindowUpdate {
+                stream_id,
+                increment,
+            } => {
+                self.window_updates_received += 1;
+                if stream_id == 0 {
+                    self.send_window = self.send_window.saturating_add(increment);
+                }
+            }
+            Frame::Origin { payload } => {
+                let origin_set = self.origin_set.get_or_insert_with(Vec::new);
+                parse_origin(origin_set, payload);
+            }
+            Frame::AltSvc { payload } => {
+                if let Some(alt_svc) = parse_alt_svc(payload) {
+                    self.alt_svc.retain(|old| old.origin() != alt_svc.origin());
+                    if self.alt_svc.len() < MAX_ALT_SVC {
+                        self.alt_svc.push(alt_svc);
+                    } else {
+                        trace!("ALTSVC frame over the origin limit");
+                    }
+                }
+            }
+            Frame::GoAway {
+                last_stream_id,
+                error_code,
+            } => {
+                if self.go_away.is_none() {
+                    self.go_away = Some((last_stream_id, error_code));
+                    for tx in self.go_away_waiters.iter_mut().flat_map(|w| w.drain(..)) {
+                        let _ = tx.send((last_stream_id, error_code));
+                    }
+                }
+            }
+            Frame::Reset => {
+                if let Some(ref mut rate) = self.reset_rate {
+                    let now = self.clock.now();
+                    match rate.window_start {
+                        Some(start) if now.saturating_duration_since(start) < rate.interval => {}
+                        _ => {
+                            rate.window_start = Some(now);
+                            rate.count = 0;
+                        }
+                    }
+                    rate.count += 1;
+                    if rate.count > rate.max {
+                        rate.exceeded = true;
+                    }
+                }
+            }
+            Frame::Settings { .. } | Frame::Ping { .. } => {}
+        }
+    }
+
+    fn write(&mut self, frame: Frame<'_>) {
+        match frame {
+            Frame::Data { stream_id, len, .. } => {
+                self.send_window = self.send_window.saturating_sub(len);
+                if let Some(stream) = self.streams.get_mut(&stream_id) {
+                    stream.body_sent += u64::from(len);
+                }
+            }
+            Frame::Headers { stream_id, len, .. } => {
+                if let Some(stream) = self.streams.get_mut(&stream_id) {
+                    stream.head_sent += u64::from(len);
+                }
+            }
+            Frame::Ping { ack: false, payload } => {
+                if self.pings_sent.len() == MAX_PINGS_IN_FLIGHT {
+                    self.pings_sent.pop_front();
+                }
+                self.pings_sent.push_back((payload, self.clock.now()));
+            }
+            Frame::WindowUpdate {
+                stream_id,
+                increment,
+            } => {
+                self.window_updates_sent += 1;
+                if stream_id == 0 {
+                    self.recv_window = self.recv_window.saturating_add(increment);
+                }
+            }
+            Frame::Settings { .. }
+            | Frame::Ping { .. }
+            | Frame::GoAway { .. }
+            | Frame::Reset
+            | Frame::Origin { .. }
+            | Frame::AltSvc { .. } => {}
+        }
+    }
+}
+
+/// Watches the frames received on one stream, until dropped.
+pub(crate) struct StreamFrames {
+    frames: SharedFrames,
+    id: u32,
+}
+
+impl StreamFrames {
+    pub(super) fn new(frames: SharedFrames, id: u32) -> Self {
+        frames
+            .lock()
+            .unwrap()
+            .streams
+            .insert(id, Stream::default());
+        StreamFrames { frames, id }
+    }
+
+    /// Returns a callback adding to the stream's send stall time.
+    pub(super) fn on_stall(&self) -> OnStall {
+        let frames = self.frames.clone();
+        let id = self.id;
+        Box::new(move |stalled| frames.lock().unwrap().stall(id, stalled))
+    }
+
+    pub(crate) fn stats(&self) -> H2StreamStats {
+        self.frames
+            .lock()
+            .unwrap()
+            .streams
+            .get(&self.id)
+            .map(|stream| stream.stats)
+            .unwrap_or_default()
+    }
+
+    /// Fills in the sizes of the request and response heads, and of the
+    /// request body, as seen on the wire so far.
+    pub(super) fn fill_sizes(&self, stats: &mut HttpConnectionStats) {
+        if let Some(stream) = self.frames.lock().unwrap().streams.get(&self.id) {
+            stats.request_header_bytes = stream.head_sent;
+            stats.request_body_bytes = stream.body_sent;
+            stats.response_header_bytes = stream.head_received;
+        }
+    }
+}
+
+impl Drop for StreamFrames {
+    fn drop(&mut self) {
+        if let Ok(mut frames) = self.frames.lock() {
+            frames.streams.remove(&self.id);
+            if frames.streams.is_empty() {
+                frames.idle_since = Some(frames.clock.now());
+            }
+        }
+    }
+}
+
+/// Applies the settings in a SETTINGS payload on top of `settings`.
+fn parse_settings(mut settings: H2Settings, payload: &[u8]) -> H2Settings {
+    for entry in payload.chunks_exact(6) {
+        let id = u16::from_be_bytes([entry[0], entry[1]]);
+        let value = u32::from_be_bytes([entry[2], entry[3], entry[4], entry[5]]);
+        let slot = match id {
+            0x1 => &mut settings.header_table_size,
+            0x2 => &mut settings.enable_push,
+            0x3 => &mut settings.max_concurrent_streams,
+            0x4 => &mut settings.initial_window_size,
+            0x5 => &mut settings.max_frame_size,
+            0x6 => &mut settings.max_header_list_size,
+            0x8 => &mut settings.enable_connect_protocol,
+            _ => continue,
+        };
+        *slot = Some(value);
+    }
+    settings
+}
+
+/// Adds the origins in an ORIGIN frame's payload to `origin_set`, skipping
+/// ones already in it, and any past `MAX_ORIGINS`.
+fn parse_origin(origin_set: &mut Vec<String>, mut payload: &[u8]) {
+    while payload.len() >= 2 {
+        let len = usize::from(u16::from_be_bytes([payload[0], payload[1]]));
+        let origin = match payload.get(2..2 + len) {
+            Some(origin) => origin,
+            None => return,
+        };
+        if let Ok(origin) = std::str::from_utf8(origin) {
+            if origin_set.len() == MAX_ORIGINS {
+                trace!("ORIGIN frame over the origin set limit");
+                return;
+            }
+            if !origin_set.iter().any(|o| o == origin) {
+                origin_set.push(origin.to_owned());
+            }
+        }
+        payload = &payload[2 + len..];
+    }
+}
+
+/// Parses the payload of an ALTSVC frame on stream 0, which names its origin.
+fn parse_alt_svc(payload: &[u8]) -> Option<AltSvc> {
+    let len = usize::from(u16::from_be_bytes([*payload.first()?, *payload.get(1)?]));
+    let origin = std::str::from_utf8(payload.get(2..2 + len)?).ok()?;
+    let value = std::str::from_utf8(&payload[2 + len..]).ok()?;
+    if origin.is_empty() {
+        return None;
+    }
+    Some(AltSvc::new(origin, value))
+}
+
+/// Splits one direction of the byte stream back into frames.
+struct Parser {
+    // Bytes to pass over before the first frame.
+    skip: usize,
+    head: [u8; FRAME_HEADER_LEN],
+    head_len: usize,
+    // The type, flags and stream id of the frame being read.
+    kind: u8,
+    flags: u8,
+    stream_id: u32,
+    len: u32,
+    // Payload bytes of the current frame not yet seen.
+    remaining: usize,
+    // The start of the payload, for the frames whose payload matters.
+    payload: Vec<u8>,
+    want: usize,
+}
+
+// Nobody sends more settings than this.
+const MAX_SETTINGS_PAYLOAD: usize = 6 * 16;
+// ORIGIN and ALTSVC payloads past this much are dropped.
+const MAX_FRAME_PAYLOAD: usize = 16_384;
+
+impl Parser {
+    fn new(skip: usize) -> Self {
+        Parser {
+            skip,
+            head: [0; FRAME_HEADER_LEN],
+            head_len: 0,
+            kind: 0,
+            flags: 0,
+            stream_id: 0,
+            len: 0,
+            remaining: 0,
+            payload: Vec::new(),
+            want: 0,
+        }
+    }
+
+    /// Consumes some of `bytes`, returning how many were used, and the frame
+    /// if one was completed.
+    fn feed<'a>(&'a mut self, bytes: &[u8]) -> (usize, Option<Frame<'a>>) {
+        if self.skip > 0 {
+            let n = self.skip.min(bytes.len());
+            self.skip -= n;
+            return (n, None);
+        }
+
+        if self.head_len < FRAME_HEADER_LEN {
+            let n = (FRAME_HEADER_LEN - self.head_len).min(bytes.len());
+            self.head[self.head_len..self.head_len + n].copy_from_slice(&bytes[..n]);
+            self.head_len += n;
+            if self.head_len < FRAME_HEADER_LEN {
+                return (n, None);
+            }
+            let h = self.head;
+            self.len = u32::from_be_bytes([0, h[0], h[1], h[2]]);
+            self.kind = h[3];
+            self.flags = h[4];
+            self.stream_id = u32::from_be_bytes([h[5], h[6], h[7], h[8]]) & 0x7FFF_FFFF;
+            self.remaining = self.len as usize;
+            self.payload.clear();
+            self.want = match self.kind {
+                SETTINGS => self.remaining.min(MAX_SETTINGS_PAYLOAD),
+                PING | GOAWAY => 8,
+                ORIGIN | ALTSVC if self.stream_id == 0 => self.remaining.min(MAX_FRAME_PAYLOAD),
+                WINDOW_UPDATE => 4,
+                _ => 0,
+            };
+            return (n, self.finish());
+        }
+
+        let n = self.remaining.min(bytes.len());
+        let keep = (self.want - self.payload.len()).min(n);
+        self.payload.extend_from_slice(&bytes[..keep]);
+        self.remaining -= n;
+        (n, self.finish())
+    }
+
+    fn finish<'a>(&'a mut self) -> Option<Frame<'a>> {
+        if self.remaining > 0 {
+            return None;
+        }
+        self.head_len = 0;
+
+        let ack = self.flags & ACK != 0;
+        let end_stream = self.flags & END_STREAM != 0;
+        match self.kind {
+            DATA => Some(Frame::Data {
+                stream_id: self.stream_id,
+                len: self.len,
+                end_stream,
+            }),
+            HEADERS => Some(Frame::Headers {
+                stream_id: self.stream_id,
+                len: self.len,
+                end_stream,
+            }),
+            CONTINUATION => Some(Frame::Headers {
+                stream_id: self.stream_id,
+                len: self.len,
+                end_stream: false,
+            }),
+            SETTINGS => Some(Frame::Settings {
+                ack,
+                payload: &self.payload,
+            }),
+            PING if self.payload.len() == 8 => {
+                let mut payload = [0; 8];
+                payload.copy_from_slice(&self.payload);
+                Some(Frame::Ping { ack, payload })
+            }
+            GOAWAY if self.payload.len() == 8 => {
+                let p = &self.payload;
+                Some(Frame::GoAway {
+                    last_stream_id: u32::from_be_bytes([p[0], p[1], p[2], p[3]]) & 0x7FFF_FFFF,
+                    error_code: u32::from_be_bytes([p[4], p[5], p[6], p[7]]),
+                })
+            }
+            RST_STREAM => Some(Frame::Reset),
+            ORIGIN if self.stream_id == 0 => Some(Frame::Origin {
+                payload: &self.payload,
+            }),
+            // Ones on a stream are for the origin of its request, which
+            // isn't known here, and cut short ones would have a wrong value.
+            ALTSVC if self.stream_id == 0 && self.payload.len() == self.len as usize => {
+                Some(Frame::AltSvc {
+                    payload: &self.payload,
+                })
+            }
+            WINDOW_UPDATE if self.payload.len() == 4 => {
+                let p = &self.payload;
+                Some(Frame::WindowUpdate {
+                    stream_id: self.stream_id,
+                    increment: u32::from_be_bytes([p[0], p[1], p[2], p[3]]) & 0x7FFF_FFFF,
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A clock that only moves when told to.
+    #[derive(Clone)]
+    struct TestClock(Arc<Mutex<Instant>>);
+
+    impl TestClock {
+        fn new() -> Self {
+            TestClock(Arc::new(Mutex::new(Instant::now())))
+        }
+
+        fn advance(&self, by: Duration) {
+            *self.0.lock().unwrap() += by;
+        }
+    }
+
+    impl crate::stats::StatsClock for TestClock {
+        fn now(&self) -> Instant {
+            *self.0.lock().unwrap()
+        }
+    }
+
+    fn frame(kind: u8, flags: u8, stream_id: u32, payload: &[u8]) -> Vec<u8> {
+        let len = (payload.len() as u32).to_be_bytes();
+        let mut buf = vec![len[1], len[2], len[3], kind, flags];
+        buf.extend_from_slice(&stream_id.to_be_bytes());
+        buf.extend_from_slice(payload);
+        buf
+    }
+
+    #[cfg(feature = "client")]
+    #[test]
+    fn frames_tracks_settings_windows_and_pings() {
+        let frames = Frames::shared(Clock::default());
+        let mut tap = FrameTap::client((), frames.clone(), Arc::default(), true);
+
+        let mut sent = vec![0; PREFACE_LEN];
+        sent.extend(frame(PING, 0, 0, b"12345678"));
+        sent.extend(frame(WINDOW_UPDATE, 0, 0, &1000u32.to_be_bytes()));
+        sent.extend(frame(DATA, 0, 1, &[0; 100]));
+        // split writes anywhere
+        for chunk in sent.chunks(5) {
+            tap.write(chunk);
+        }
+
+        let mut recv = frame(SETTINGS, 0, 0, &[0, 3, 0, 0, 0, 100, 0, 4, 0, 1, 0, 0]);
+        recv.extend(frame(SETTINGS, ACK, 0, &[]));
+        recv.extend(frame(WINDOW_UPDATE, 0, 1, &50u32.to_be_bytes()));
+        recv.extend(frame(WINDOW_UPDATE, 0, 0, &50u32.to_be_bytes()));
+        recv.extend(frame(DATA, 0, 1, &[0; 10]));
+        recv.extend(frame(PING, ACK, 0, b"12345678"));
+        for chunk in recv.chunks(7) {
+            tap.read(chunk);
+        }
+
+        let stats = frames.lock().unwrap().stats();
+        assert_eq!(stats.peer_settings.max_concurrent_streams, Some(100));
+        assert_eq!(stats.peer_settings.initial_window_size, Some(65_536));
+        assert_eq!(stats.peer_settings.max_frame_size, None);
+        assert_eq!(stats.send_window, SPEC_WINDOW_SIZE + 50 - 100);
+        assert_eq!(stats.recv_window, SPEC_WINDOW_SIZE + 1000 - 10);
+        assert_eq!(stats.window_updates_received, 2);
+        assert_eq!(stats.window_updates_sent, 1);
+        assert_eq!(stats.ping_rtts.len(), 1);
+    }
+
+    #[cfg(feature = "client")]
+    #[test]
+    fn frames_without_stats_only_settle_state() {
+        let frames = Frames::shared(Clock::default());
+        let bytes = Arc::new(ByteCounts::default());
+        let mut tap = FrameTap::client((), frames.clone(), bytes.clone(), false);
+
+        let mut sent = vec![0; PREFACE_LEN];
+        sent.extend(frame(PING, 0, 0, b"12345678"));
+        sent.extend(frame(WINDOW_UPDATE, 0, 0, &1000u32.to_be_bytes()));
+        tap.write(&sent);
+
+        let mut recv = frame(SETTINGS, 0, 0, &[0, 3, 0, 0, 0, 100]);
+        recv.extend(frame(WINDOW_UPDATE, 0, 0, &50u32.to_be_bytes()));
+        recv.extend(frame(PING, ACK, 0, b"12345678"));
+        tap.read(&recv);
+
+        // the bytes are counted all the same
+        assert_eq!(bytes.written(), sent.len() as u64);
+        assert_eq!(bytes.read(), recv.len() as u64);
+
+        let frames = frames.lock().unwrap();
+        let current = frames.current_settings().expect("settings");
+        assert_eq!(current.max_concurrent_streams, Some(100));
+        let stats = frames.stats();
+        assert_eq!(stats.send_window, SPEC_WINDOW_SIZE);
+        assert_eq!(stats.recv_window, SPEC_WINDOW_SIZE);
+        assert_eq!(stats.window_updates_sent, 0);
+        assert_eq!(stats.window_updates_received, 0);
+        assert!(stats.ping_rtts.is_empty());
+    }
+
+    #[cfg(feature = "server")]
+    #[test]
+    fn frames_merges_later_settings() {
+        let frames = Frames::shared(Clock::default());
+        let mut tap = FrameTap::server((), frames.clone());
+        assert_eq!(frames.lock().unwrap().current_settings(), None);
+
+        let mut recv = vec![0; PREFACE_LEN];
+        recv.extend(frame(SETTINGS, 0, 0, &[0, 3, 0, 0, 0, 100, 0, 5, 0, 0, 64, 0]));
+        recv.extend(frame(SETTINGS, 0, 0, &[0, 3, 0, 0, 0, 10]));
+        tap.read(&recv);
+        let frames = frames.lock().unwrap();
+
+        let current = frames.current_settings().expect("settings");
+        assert_eq!(current.max_concurrent_streams, Some(10));
+        assert_eq!(current.max_frame_size, Some(16_384));
+        assert_eq!(current.initial_window_size, None);
+        // stats keep the first frame's
+        assert_eq!(frames.stats().peer_settings.max_concurrent_streams, Some(100));
+    }
+
+    #[cfg(feature = "client")]
+    #[test]
+    fn frames_collects_origin_set() {
+        let frames = Frames::shared(Clock::default());
+        let mut tap = FrameTap::client((), frames.clone(), Arc::default(), false);
+        assert_eq!(frames.lock().unwrap().origin_set(), None);
+
+        let mut recv = frame(ORIGIN, 0, 0, b"\0\x11https://a.example");
+        // only valid on stream 0
+        recv.extend(frame(ORIGIN, 0, 1, b"\0\x11https://x.example"));
+        recv.extend(frame(ORIGIN, 0, 0, b"\0\x11https://b.example\0\x09trunc"));
+        tap.read(&recv);
+        let frames = frames.lock().unwrap();
+
+        assert_eq!(
+            frames.origin_set(),
+            Some(vec![
+                "https://a.example".to_owned(),
+                "https://b.example".to_owned()
+            ])
+        );
+    }
+
+    #[cfg(feature = "client")]
+    #[test]
+    fn frames_bounds_origin_set() {
+        let frames = Frames::shared(Clock::default());
+        let mut tap = FrameTap::client((), frames.clone(), Arc::default(), false);
+
+        let mut recv = frame(ORIGIN, 0, 0, b"\0\x11https://a.example");
+        recv.extend(frame(ORIGIN, 0, 0, b"\0\x11https://a.example"));
+        tap.read(&recv);
+        assert_eq!(
+            frames.lock().unwrap().origin_set(),
+            Some(vec!["https://a.example".to_owned()])
+        );
+
+        for i in 0..MAX_ORIGINS * 2 {
+            let origin = format!("https://{}.example", i);
+            let mut payload = (origin.len() as u16).to_be_bytes().to_vec();
+            payload.extend_from_slice(origin.as_bytes());
+            tap.read(&frame(ORIGIN, 0, 0, &payload));
+        }
+        let origin_set = frames.lock().unwrap().origin_set().unwrap();
+        assert_eq!(origin_set.len(), MAX_ORIGINS);
+    }
+
+    #[cfg(feature = "client")]
+    #[test]
+    fn frames_gives_first_go_away() {
+        let frames = Frames::shared(Clock::default());
+        let mut tap = FrameTap::client((), frames.clone(), Arc::default(), false);
+        let mut waiting = frames.lock().unwrap().on_go_away();
+
+        let mut recv = frame(GOAWAY, 0, 0, b"\x7f\xff\xff\xff\0\0\0\0");
+        recv.extend(frame(GOAWAY, 0, 0, b"\0\0\0\x03\0\0\0\x02debug"));
+        tap.read(&recv);
+
+        assert_eq!(waiting.try_recv(), Ok(Some((0x7FFF_FFFF, 0))));
+        let mut late = frames.lock().unwrap().on_go_away();
+        assert_eq!(late.try_recv(), Ok(Some((0x7FFF_FFFF, 0))));
+
+        // without a GOAWAY, waiting ends with the transport
+        let frames = Frames::shared(Clock::default());
+        let tap = FrameTap::client((), frames.clone(), Arc::default(), false);
+        let mut waiting = frames.lock().unwrap().on_go_away();
+        drop(tap);
+        assert!(waiting.try_recv().is_err());
+    }
+
+    #[cfg(feature = "client")]
+    #[test]
+    fn frames_keeps_latest_alt_svc() {
+        let frames = Frames::shared(Clock::default());
+        let mut tap = FrameTap::client((), frames.clone(), Arc::default(), false);
+
+        let mut recv = frame(ALTSVC, 0, 0, b"\0\x11https://a.exampleh3=\":443\"");
+        recv.extend(frame(ALTSVC, 0, 0, b"\0\x11https://b.exampleh3=\":443\""));
+        recv.extend(frame(ALTSVC, 0, 0, b"\0\x11https://a.exampleclear"));
+        // on a stream, the origin is that of the request
+        recv.extend(frame(ALTSVC, 0, 1, b"\0\0h3=\":8443\""));
+        tap.read(&recv);
+
+        assert_eq!(
+            frames.lock().unwrap().alt_svc(),
+            vec![
+                AltSvc::new("https://b.example", "h3=\":443\""),
+                AltSvc::new("https://a.example", "clear"),
+            ]
+        );
+    }
+
+    #[cfg(feature = "client")]
+    #[test]
+    fn frames_bounds_alt_svc() {
+        let frames = Frames::shared(Clock::default());
+        let mut tap = FrameTap::client((), frames.clone(), Arc::default(), false);
+
+        for i in 0..MAX_ALT_SVC * 2 {
+            let origin = format!("https://{}.example", i);
+            let mut payload = (origin.len() as u16).to_be_bytes().to_vec();
+            payload.extend_from_slice(origin.as_bytes());
+            payload.extend_from_slice(b"h3=\":443\"");
+            tap.read(&frame(ALTSVC, 0, 0, &payload));
+        }
+        let alt_svc = frames.lock().unwrap().alt_svc();
+        assert_eq!(alt_svc.len(), MAX_ALT_SVC);
+
+        // a known origin can still be updated
+        tap.read(&frame(ALTSVC, 0, 0, b"\0\x11https://0.exampleclear"));
+        let alt_svc = frames.lock().unwrap().alt_svc();
+        assert_eq!(alt_svc.len(), MAX_ALT_SVC);
+        assert_eq!(
+            alt_svc.last(),
+            Some(&AltSvc::new("https://0.example", "clear"))
+        );
+    }
+
+    #[cfg(feature = "server")]
+    #[test]
+    fn frames_limits_reset_rate() {
+        let clock = TestClock::new();
+        let frames = Frames::shared(Clock::new(clock.clone()));
+        let mut tap = FrameTap::server((), frames.clone());
+        frames
+            .lock()
+            .unwrap()
+            .limit_reset_rate(2, Duration::from_secs(1));
+        let exceeded = || frames.lock().unwrap().is_reset_rate_exceeded();
+        let reset = |id| frame(RST_STREAM, 0, id, &[0, 0, 0, 8]);
+
+        let mut recv = vec![0; PREFACE_LEN];
+        recv.extend(reset(1));
+        recv.extend(reset(3));
+        tap.read(&recv);
+        assert!(!exceeded());
+
+        // a new interval starts the count over
+        clock.advance(Duration::from_secs(1));
+        tap.read(&reset(5));
+        tap.read(&reset(7));
+        assert!(!exceeded());
+        tap.read(&reset(9));
+        assert!(exceeded());
+    }
+
+    #[cfg(feature = "client")]
+    #[test]
+    fn stream_frames_are_timed() {
+        let clock = TestClock::new();
+        let frames = Frames::shared(Clock::new(clock.clone()));
+        let mut tap = FrameTap::client((), frames.clone(), Arc::default(), true);
+        let stream = StreamFrames::new(frames.clone(), 1);
+
+        let mut recv = frame(HEADERS, 0, 1, &[0; 3]);
+        recv.extend(frame(HEADERS, 0, 3, &[0; 3]));
+        recv.extend(frame(DATA, 0, 1, &[0; 10]));
+        tap.read(&recv);
+
+        let stats = stream.stats();
+        assert!(stats.headers.is_some());
+        assert!(stats.first_data.is_some());
+        assert!(stats.end_stream.is_none());
+
+        tap.read(&frame(DATA, END_STREAM, 1, &[]));
+        assert!(stream.stats().end_stream.is_some());
+
+        let mut on_stall = stream.on_stall();
+        on_stall(true);
+        clock.advance(Duration::from_millis(5));
+        on_stall(false);
+        // not a stall, no time passed
+        on_stall(true);
+        on_stall(false);
+        frames
+            .lock()
+            .unwrap()
+            .add_send_stall(3, Duration::from_millis(5));
+        assert_eq!(stream.stats().send_stalled, Duration::from_millis(5));
+        assert_eq!(
+            frames.lock().unwrap().stats().send_stalled,
+            Duration::from_millis(10)
+        );
+
+        drop(stream);
+        assert!(frames.lock().unwrap().streams.is_empty());
+    }
+
+    #[cfg(feature = "client")]
+    #[test]
+    fn stream_frames_count_sizes() {
+        let frames = Frames::shared(Clock::default());
+        let mut tap = FrameTap::client((), frames.clone(), Arc::default(), true);
+        let stream = StreamFrames::new(frames.clone(), 1);
+
+        let mut sent = vec![0; PREFACE_LEN];
+        sent.extend(frame(HEADERS, 0, 1, &[0; 20]));
+        sent.extend(frame(CONTINUATION, 0, 1, &[0; 5]));
+        sent.extend(frame(DATA, 0, 1, &[0; 100]));
+        sent.extend(frame(DATA, 0, 3, &[0; 100]));
+        tap.write(&sent);
+
+        let mut recv = frame(HEADERS, 0, 1, &[0; 12]);
+        recv.extend(frame(DATA, END_STREAM, 1, &[0; 10]));
+        tap.read(&recv);
+
+        let mut stats = HttpConnectionStats::default();
+        stream.fill_sizes(&mut stats);
+        assert_eq!(stats.request_header_bytes, 25);
+        assert_eq!(stats.request_body_bytes, 100);
+        assert_eq!(stats.response_header_bytes, 12);
+    }
+
+    #[test]
+    fn stream_frames_idle_before_reuse() {
+        let clock = TestClock::new();
+        let frames = Frames::shared(Clock::new(clock.clone()));
+        assert_eq!(frames.lock().unwrap().idle_before_reuse(), None);
+        let first = StreamFrames::new(frames.clone(), 1);
+        let second = StreamFrames::new(frames.clone(), 3);
+
+        drop(first);
+        // Another stream is still open.
+        assert_eq!(frames.lock().unwrap().idle_before_reuse(), None);
+        drop(second);
+        clock.advance(Duration::from_millis(5));
+        let idle = frames.lock().unwrap().idle_before_reuse();
+        assert_eq!(idle, Some(Duration::from_millis(5)));
+        assert_eq!(frames.lock().unwrap().idle_before_reuse(), None);
+    }
+}
@@ -71,6 +71,29 @@ pub struct ConnectionStats {
 
     /// The approximate instant after we have finished upgrading a connection to TLS.
     pub tls_connect_end: Option<std::time::Instant>,
+
+    /// The name of the cipher suite negotiated during the TLS handshake, if any.
+    pub tls_cipher_suite: Option<&'static str>,
+
+    /// The TLS protocol version negotiated during the TLS handshake, if any.
+    pub tls_version: Option<&'static str>,
+
+    /// The application protocol negotiated via ALPN, if any (e.g. `h2`).
+    ///
+    /// See [`set_alpn`](ConnectionStats::set_alpn) for how it is recorded.
+    pub alpn: Option<&'static str>,
+
+    /// The address of the remote peer this connection landed on.
+    pub peer_addr: Option<std::net::SocketAddr>,
+
+    /// The local address this connection was bound to.
+    pub local_addr: Option<std::net::SocketAddr>,
+
+    /// The total number of bytes read from this connection so far.
+    pub bytes_read: u64,
+
+    /// The total number of bytes written to this connection so far.
+    pub bytes_written: u64,
 }
 
 impl fmt::Display for ConnectionStats {
@@ -93,6 +116,33 @@ impl fmt::Display for ConnectionStats {
             }
         }
 
+        if let Some(a) = self.peer_addr {
+            f.write_fmt(format_args!("peer address: {}\n", a))?;
+        }
+
+        if let Some(a) = self.local_addr {
+            f.write_fmt(format_args!("local address: {}\n", a))?;
+        }
+
+        if let Some(v) = self.tls_version {
+            f.write_fmt(format_args!("tls version: {}\n", v))?;
+        }
+
+        if let Some(a) = self.alpn {
+            f.write_fmt(format_args!("alpn: {}\n", a))?;
+        }
+
+        if let Some(c) = self.tls_cipher_suite {
+            f.write_fmt(format_args!("tls cipher suite: {}\n", c))?;
+        }
+
+        if self.bytes_read > 0 || self.bytes_written > 0 {
+            f.write_fmt(format_args!(
+                "bytes read: {}, bytes written: {}\n",
+                self.bytes_read, self.bytes_written
+            ))?;
+        }
+
         Ok(())
     }
 }
@@ -133,6 +183,100 @@ impl ConnectionStats {
         self.tls_connect_end
             .map(|t| self.start_time.map(|start| t.duration_since(start)))?
     }
+
+    /// Returns how long the dns resolve took
+    pub fn get_dns_duration(&self) -> Option<core::time::Duration> {
+        match (self.dns_resolve_start, self.dns_resolve_end) {
+            (Some(s), Some(e)) => Some(e.saturating_duration_since(s)),
+            _ => None,
+        }
+    }
+
+    /// Returns how long the socket took to connect
+    pub fn get_connect_duration(&self) -> Option<core::time::Duration> {
+        match (self.connect_start, self.connect_end) {
+            (Some(s), Some(e)) => Some(e.saturating_duration_since(s)),
+            _ => None,
+        }
+    }
+
+    /// Records the address of the remote peer.
+    ///
+    /// Meant to be called by the connector once the socket is connected.
+    pub fn set_peer_addr(&mut self, addr: std::net::SocketAddr) {
+        self.peer_addr = Some(addr);
+    }
+
+    /// Records the local address the socket was bound to.
+    pub fn set_local_addr(&mut self, addr: std::net::SocketAddr) {
+        self.local_addr = Some(addr);
+    }
+
+    /// Records the application protocol negotiated via ALPN.
+    ///
+    /// Meant to be called by the TLS glue once the handshake completes. So
+    /// that the stats stay `Copy`, each protocol ID other than the HTTP ones
+    /// is kept for the life of the process the first time it is seen, up to
+    /// a limit of distinct IDs; bytes that aren't UTF-8 are replaced.
+    pub fn set_alpn(&mut self, protocol: &[u8]) {
+        self.alpn = match protocol {
+            b"http/1.0" => Some("http/1.0"),
+            b"http/1.1" => Some("http/1.1"),
+            b"h2" => Some("h2"),
+            b"h2c" => Some("h2c"),
+            b"h3" => Some("h3"),
+            _ => intern_alpn(protocol),
+        };
+    }
+
+    /// Records the TLS protocol version negotiated during the handshake.
+    pub fn set_tls_version(&mut self, version: &'static str) {
+        self.tls_version = Some(version);
+    }
+
+    /// The stats of a connection that is being used again, which keep what
+    /// it is but not the timestamps of setting it up.
+    #[cfg(all(feature = "client", any(feature = "http1", feature = "http2")))]
+    pub(crate) fn reused(self) -> ConnectionStats {
+        ConnectionStats {
+            start_time: None,
+            dns_resolve_start: None,
+            dns_resolve_end: None,
+            connect_start: None,
+            connect_end: None,
+            tls_connect_start: None,
+            tls_connect_end: None,
+            ..self
+        }
+    }
+
+    /// Returns how long the tls negotiation took, separate from the TCP connect
+    pub fn get_tls_duration(&self) -> Option<core::time::Duration> {
+        match (self.tls_connect_start, self.tls_connect_end) {
+            (Some(s), Some(e)) => Some(e.saturating_duration_since(s)),
+            _ => None,
+        }
+    }
+}
+
+// ALPN IDs come from the protocols a client offers, so there are few of them,
+// but a bound keeps a misbehaving peer from growing this without end.
+const MAX_INTERNED_ALPN: usize = 64;
+
+static INTERNED_ALPN: std::sync::Mutex<Vec<&'static str>> = std::sync::Mutex::new(Vec::new());
+
+fn intern_alpn(protocol: &[u8]) -> Option<&'static str> {
+    let protocol = String::from_utf8_lossy(protocol);
+    let mut interned = INTERNED_ALPN.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(known) = interned.iter().find(|known| **known == protocol) {
+        return Some(known);
+    }
+    if interned.len() == MAX_INTERNED_ALPN {
+        return None;
+    }
+    let leaked: &'static str = Box::leak(protocol.into_owned().into_boxed_str());
+    interned.push(leaked);
+    Some(leaked)
 }
 
 /// Write bytes asynchronously.
@@ -190,6 +334,42 @@ pub trait Write {
             .map_or(&[][..], |b| &**b);
         self.poll_write(cx, buf)
     }
+
+    /// Returns whether this writer can write a [`FileRegion`] with
+    /// `poll_write_file`.
+    ///
+    /// The default implementation returns `false`.
+    ///
+    /// [`FileRegion`]: crate::body::FileRegion
+    #[cfg(all(feature = "sendfile", unix))]
+    #[cfg_attr(docsrs, doc(cfg(all(feature = "sendfile", unix))))]
+    fn is_write_file(&self) -> bool {
+        false
+    }
+
+    /// Attempts to write bytes from a region of a file, without copying them
+    /// through userspace.
+    ///
+    /// This is only called if `is_write_file` returns `true`. Like
+    /// `poll_write`, it returns how many bytes were written, starting at the
+    /// region's offset. An implementation over a socket would wait until it
+    /// is writable, and then call [`FileRegion::send_to`] with its file
+    /// descriptor.
+    ///
+    /// The default implementation returns an error of kind `Unsupported`.
+    ///
+    /// [`FileRegion::send_to`]: crate::body::FileRegion::send_to
+    #[cfg(all(feature = "sendfile", unix))]
+    #[cfg_attr(docsrs, doc(cfg(all(feature = "sendfile", unix))))]
+    fn poll_write_file(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        file: &crate::body::FileRegion,
+    ) -> Poll<Result<usize, std::io::Error>> {
+        let _ = cx;
+        let _ = file;
+        Poll::Ready(Err(std::io::ErrorKind::Unsupported.into()))
+    }
 }
 
 /// A wrapper around a byte buffer that is incrementally filled and initialized.
@@ -434,6 +614,20 @@ macro_rules! deref_async_write {
             (**self).is_write_vectored()
         }
 
+        #[cfg(all(feature = "sendfile", unix))]
+        fn is_write_file(&self) -> bool {
+            (**self).is_write_file()
+        }
+
+        #[cfg(all(feature = "sendfile", unix))]
+        fn poll_write_file(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            file: &crate::body::FileRegion,
+        ) -> Poll<std::io::Result<usize>> {
+            Pin::new(&mut **self).poll_write_file(cx, file)
+        }
+
         fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
             Pin::new(&mut **self).poll_flush(cx)
         }
@@ -480,6 +674,20 @@ where
         (**self).is_write_vectored()
     }
 
+    #[cfg(all(feature = "sendfile", unix))]
+    fn is_write_file(&self) -> bool {
+        (**self).is_write_file()
+    }
+
+    #[cfg(all(feature = "sendfile", unix))]
+    fn poll_write_file(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        file: &crate::body::FileRegion,
+    ) -> Poll<std::io::Result<usize>> {
+        pin_as_deref_mut(self).poll_write_file(cx, file)
+    }
+
     fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
         pin_as_deref_mut(self).poll_flush(cx)
     }
@@ -496,3 +704,48 @@ fn pin_as_deref_mut<P: DerefMut>(pin: Pin<&mut Pin<P>>) -> Pin<&mut P::Target> {
     // giving out the &mut Pin<P> in the process. See Pin::as_deref_mut() for more detail.
     unsafe { pin.get_unchecked_mut() }.as_mut()
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::ConnectionStats;
+
+    #[test]
+    fn connection_stats_tls() {
+        let start = Instant::now();
+        let stats = ConnectionStats {
+            start_time: Some(start),
+            tls_connect_start: Some(start),
+            tls_connect_end: Some(start + Duration::from_millis(5)),
+            tls_cipher_suite: Some("TLS13_AES_128_GCM_SHA256"),
+            tls_version: Some("TLSv1.3"),
+            ..Default::default()
+        };
+        assert_eq!(stats.get_tls_duration(), Some(Duration::from_millis(5)));
+
+        let shown = stats.to_string();
+        assert!(shown.contains("tls version: TLSv1.3\n"), "{}", shown);
+        assert!(
+            shown.contains("tls cipher suite: TLS13_AES_128_GCM_SHA256\n"),
+            "{}",
+            shown
+        );
+
+        assert_eq!(ConnectionStats::default().get_tls_duration(), None);
+    }
+
+    #[test]
+    fn connection_stats_alpn() {
+        let mut stats = ConnectionStats::default();
+        stats.set_alpn(b"h2");
+        let copy = stats;
+        assert_eq!(copy.alpn, Some("h2"));
+
+        stats.set_alpn(b"spdy/3");
+        assert_eq!(stats.alpn, Some("spdy/3"));
+        let first = stats.alpn.unwrap();
+        stats.set_alpn(b"spdy/3");
+        assert!(std::ptr::eq(first, stats.alpn.unwrap()));
+    }
+}
@@ -0,0 +1,307 @@
+//! HTTP/1 and HTTP/2 Server Connections
+//!
+//! A connection built here speaks whichever version the client does. If the
+//! transport reports an ALPN protocol in its [`ConnectionStats`], that picks
+//! the version. Otherwise the first bytes read are checked for the HTTP/2
+//! connection preface, which a client sends when it knows the server speaks
+//! HTTP/2, such as after negotiating it or with prior knowledge.
+//!
+//! [`ConnectionStats`]: crate::rt::ConnectionStats
+
+use std::error::Error as StdError;
+use std::fmt;
+use std::future::Future;
+use std::marker::Unpin;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use bytes::Bytes;
+use futures_core::ready;
+use pin_project_lite::pin_project;
+
+use super::{http1, http2};
+use crate::body::{Body, Incoming as IncomingBody};
+use crate::common::io::Rewind;
+use crate::rt::bounds::Http2ServerConnExec;
+use crate::rt::{Read, ReadBuf, Stats, Write};
+use crate::service::HttpService;
+
+const H2_PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+/// A configuration builder for connections that serve both HTTP/1 and
+/// HTTP/2.
+///
+/// It holds a builder for each version, whose options are used for the
+/// connections of that version.
+///
+/// **Note**: The default values of options are *not considered stable*. They
+/// are subject to change at any time.
+#[derive(Clone, Debug)]
+pub struct Builder<E> {
+    http1: http1::Builder,
+    http2: http2::Builder<E>,
+}
+
+pin_project! {
+    /// A [`Future`](core::future::Future) representing an HTTP/1 or HTTP/2
+    /// connection, bound to a [`Service`](crate::service::Service), returned
+    /// from [`Builder::serve_connection`](struct.Builder.html#method.serve_connection).
+    ///
+    /// To drive HTTP on this connection this future **must be polled**, typically with
+    /// `.await`. If it isn't polled, no progress will be made on this connection.
+    #[must_use = "futures do nothing unless polled"]
+    pub struct Connection<I, S, E>
+    where
+        S: HttpService<IncomingBody>,
+    {
+        #[pin]
+        state: State<I, S, E>,
+    }
+}
+
+pin_project! {
+    #[project = StateProj]
+    enum State<I, S, E>
+    where
+        S: HttpService<IncomingBody>,
+    {
+        // Reading the first bytes, to tell which version the client speaks.
+        ReadVersion {
+            io: Option<I>,
+            buf: [u8; 24],
+            filled: usize,
+            service: Option<S>,
+            builder: Builder<E>,
+        },
+        H1 {
+            #[pin]
+            conn: http1::Connection<Rewind<I>, S>,
+        },
+        H2 {
+            #[pin]
+            conn: http2::Connection<Rewind<I>, S, E>,
+        },
+        // Shut down before a version was picked.
+        Closed,
+    }
+}
+
+// ===== impl Connection =====
+
+impl<I, S, E> fmt::Debug for Connection<I, S, E>
+where
+    S: HttpService<IncomingBody>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Connection").finish()
+    }
+}
+
+impl<I, B, S, E> Connection<I, S, E>
+where
+    S: HttpService<IncomingBody, ResBody = B>,
+    S::Error: Into<Box<dyn StdError + Send + Sync>>,
+    I: Read + Write + Stats + Unpin,
+    B: Body + 'static,
+    B::Error: Into<Box<dyn StdError + Send + Sync>>,
+    E: Http2ServerConnExec<S::Future, B>,
+{
+    /// Start a graceful shutdown process for this connection.
+    ///
+    /// This `Connection` should continue to be polled until shutdown
+    /// can finish. If the version hasn't been picked yet, no request has
+    /// started, so the connection resolves right away.
+    ///
+    /// # Note
+    ///
+    /// This should only be called while the `Connection` future is still
+    /// pending. If called after `Connection::poll` has resolved, this does
+    /// nothing.
+    pub fn graceful_shutdown(self: Pin<&mut Self>) {
+        let mut state = self.project().state;
+        match state.as_mut().project() {
+            StateProj::ReadVersion { .. } => state.set(State::Closed),
+            StateProj::H1 { conn } => conn.graceful_shutdown(),
+            StateProj::H2 { conn } => conn.graceful_shutdown(),
+            StateProj::Closed => (),
+        }
+    }
+
+    /// Returns the HTTP version the client speaks, once it is known.
+    pub fn version(&self) -> Option<http::Version> {
+        match self.state {
+            State::H1 { .. } => Some(http::Version::HTTP_11),
+            State::H2 { .. } => Some(http::Version::HTTP_2),
+            State::ReadVersion { .. } | State::Closed => None,
+        }
+    }
+}
+
+impl<I, B, S, E> Future for Connection<I, S, E>
+where
+    S: HttpService<IncomingBody, ResBody = B>,
+    S::Error: Into<Box<dyn StdError + Send + Sync>>,
+    I: Read + Write + Stats + Unpin,
+    B: Body + 'static,
+    B::Error: Into<Box<dyn StdError + Send + Sync>>,
+    E: Http2ServerConnExec<S::Future, B>,
+{
+    type Output = crate::Result<()>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.project().state;
+        loop {
+            let next = match state.as_mut().project() {
+                StateProj::ReadVersion {
+                    io,
+                    buf,
+                    filled,
+                    service,
+                    builder,
+                } => {
+                    let is_h2 = {
+                        let io = io.as_mut().expect("polled after complete");
+                        ready!(read_version(io, buf, filled, cx))
+                            .map_err(crate::Error::new_io)?
+                    };
+                    let io = Rewind::new_buffered(
+                        io.take().expect("polled after complete"),
+                        Bytes::copy_from_slice(&buf[..*filled]),
+                    );
+                    let service = service.take().expect("polled after complete");
+                    if is_h2 {
+                        trace!("auto connection serving HTTP/2");
+                        State::H2 {
+                            conn: builder.http2.serve_connection(io, service),
+                        }
+                    } else {
+                        trace!("auto connection serving HTTP/1");
+                        State::H1 {
+                            conn: builder.http1.serve_connection(io, service),
+                        }
+                    }
+                }
+                StateProj::H1 { conn } => return conn.poll(cx),
+                StateProj::H2 { conn } => return conn.poll(cx),
+                StateProj::Closed => return Poll::Ready(Ok(())),
+            };
+            state.set(next);
+        }
+    }
+}
+
+/// Reads until the bytes read either are the HTTP/2 preface, or can't be.
+fn read_version<I>(
+    io: &mut I,
+    buf: &mut [u8; 24],
+    filled: &mut usize,
+    cx: &mut Context<'_>,
+) -> Poll<std::io::Result<bool>>
+where
+    I: Read + Unpin,
+{
+    while *filled < H2_PREFACE.len() {
+        let mut read_buf = ReadBuf::new(&mut buf[*filled..]);
+        ready!(Pin::new(&mut *io).poll_read(cx, read_buf.unfilled()))?;
+        let n = read_buf.filled().len();
+        if n == 0 {
+            // Closed early, let HTTP/1 report it.
+            return Poll::Ready(Ok(false));
+        }
+        *filled += n;
+        if buf[..*filled] != H2_PREFACE[..*filled] {
+            return Poll::Ready(Ok(false));
+        }
+    }
+    Poll::Ready(Ok(true))
+}
+
+// ===== impl Builder =====
+
+impl<E> Builder<E> {
+    /// Create a new connection builder.
+    ///
+    /// The executor is used for HTTP/2 connections, as with
+    /// [`http2::Builder::new`].
+    pub fn new(exec: E) -> Self {
+        Builder {
+            http1: http1::Builder::new(),
+            http2: http2::Builder::new(exec),
+        }
+    }
+
+    /// Returns the builder used for HTTP/1 connections, to set its options.
+    pub fn http1(&mut self) -> &mut http1::Builder {
+        &mut self.http1
+    }
+
+    /// Returns the builder used for HTTP/2 connections, to set its options.
+    pub fn http2(&mut self) -> &mut http2::Builder<E> {
+        &mut self.http2
+    }
+
+    /// Bind a connection together with a [`Service`](crate::service::Service).
+    ///
+    /// The version is picked from the ALPN protocol in the transport's
+    /// [`Stats`], if it is `h2` or `http/1.1`, and otherwise once the first
+    /// bytes have been read.
+    ///
+    /// HTTP/1 connections don't support upgrades, as with
+    /// [`http1::Builder::serve_connection`] without
+    /// [`with_upgrades`](http1::Connection::with_upgrades).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use hyper::{body::Incoming, Request, Response};
+    /// # use hyper::service::Service;
+    /// # use hyper::server::conn::auto::Builder;
+    /// # use hyper::rt::{Read, Stats, Write};
+    /// # async fn run<I, S, E>(some_io: I, some_service: S, exec: E)
+    /// # where
+    /// #     I: Read + Write + Stats + Unpin + Send + 'static,
+    /// #     S: Service<hyper::Request<Incoming>, Response=hyper::Response<Incoming>> + Send + 'static,
+    /// #     S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    /// #     S::Future: Send,
+    /// #     E: hyper::rt::bounds::Http2ServerConnExec<S::Future, Incoming> + Clone,
+    /// # {
+    /// let conn = Builder::new(exec).serve_connection(some_io, some_service);
+    ///
+    /// if let Err(e) = conn.await {
+    ///     eprintln!("server connection error: {}", e);
+    /// }
+    /// # }
+    /// # fn main() {}
+    /// ```
+    pub fn serve_connection<I, S, B>(&self, mut io: I, service: S) -> Connection<I, S, E>
+    where
+        S: HttpService<IncomingBody, ResBody = B>,
+        S::Error: Into<Box<dyn StdError + Send + Sync>>,
+        B: Body + 'static,
+        B::Error: Into<Box<dyn StdError + Send + Sync>>,
+        I: Read + Write + Stats + Unpin,
+        E: Http2ServerConnExec<S::Future, B>,
+    {
+        let alpn = io.stats().and_then(|stats| stats.alpn);
+        let state = match alpn {
+            Some("h2") => State::H2 {
+                conn: self
+                    .http2
+                    .serve_connection(Rewind::new_buffered(io, Bytes::new()), service),
+            },
+            Some("http/1.1") => State::H1 {
+                conn: self
+                    .http1
+                    .serve_connection(Rewind::new_buffered(io, Bytes::new()), service),
+            },
+            _ => State::ReadVersion {
+                io: Some(io),
+                buf: [0; 24],
+                filled: 0,
+                service: Some(service),
+                builder: self.clone(),
+            },
+        };
+        Connection { state }
+    }
+}
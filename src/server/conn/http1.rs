@@ -6,16 +6,21 @@ use std::future::Future;
 use std::pin::Pin;
 use std::sync::Arc;
 use std::task::{Context, Poll};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use crate::rt::{Read, Stats, Write};
-use crate::upgrade::Upgraded;
+use crate::rt::{Read, Sleep, Stats, Write};
+use crate::upgrade::{Tracker, Upgraded};
 use bytes::Bytes;
 use futures_core::ready;
 
+use super::DateProvider;
 use crate::body::{Body, Incoming as IncomingBody};
+use crate::common::date::DateSource;
+use crate::common::stats::{Clock, OnStats};
 use crate::proto;
 use crate::service::HttpService;
+use crate::stats::StatsClock;
+use crate::ServerRequestStats;
 use crate::{
     common::time::{Dur, Time},
     rt::Timer,
@@ -41,6 +46,8 @@ pin_project_lite::pin_project! {
         S: HttpService<IncomingBody>,
     {
         conn: Http1Dispatcher<T, S::ResBody, S>,
+        timer: Time,
+        upgrade_grace_period: Option<Duration>,
     }
 }
 
@@ -75,12 +82,72 @@ pub struct Builder {
     h1_keep_alive: bool,
     h1_title_case_headers: bool,
     h1_preserve_header_case: bool,
+    h1_allow_invalid_request_target: bool,
     h1_max_headers: Option<usize>,
     h1_header_read_timeout: Dur,
+    h1_keep_alive_timeout: Dur,
+    h1_max_requests: Option<usize>,
+    h1_upgrade_grace_period: Dur,
+    h1_expect_continue: ExpectContinue,
+    h1_conflicting_framing: ConflictingFraming,
+    h1_max_chunk_extension_size: Option<usize>,
+    h1_reject_chunk_extensions: bool,
+    max_request_body_size: Option<u64>,
+    error_responder: Option<proto::h1::ErrorResponder>,
     h1_writev: Option<bool>,
     max_buf_size: Option<usize>,
     pipeline_flush: bool,
     date_header: bool,
+    date_source: DateSource,
+    record_stats: bool,
+    on_stats: Option<OnStats<ServerRequestStats>>,
+    stats_clock: Clock,
+}
+
+/// How a server answers a request with an `Expect: 100-continue` header.
+///
+/// Set with [`Builder::expect_continue`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ExpectContinue {
+    /// Send `100 Continue` once the service first polls the request body.
+    ///
+    /// A service that responds without reading the body never sends the
+    /// interim response, so the client doesn't send the body either.
+    #[default]
+    OnBodyRead,
+    /// Respond with `417 Expectation Failed` without calling the service,
+    /// and close the connection.
+    Reject,
+}
+
+/// How a server handles a request whose framing headers conflict.
+///
+/// A request conflicts if it has both `Transfer-Encoding` and
+/// `Content-Length`, or several `Content-Length` values that differ. Set
+/// with [`Builder::conflicting_framing`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ConflictingFraming {
+    /// Respond with `400 Bad Request` without calling the service, and close
+    /// the connection.
+    Reject,
+    /// Remove the `Content-Length` headers, and read the body according to
+    /// `Transfer-Encoding`.
+    ///
+    /// Differing `Content-Length` values without a `Transfer-Encoding`
+    /// leave no way to tell the body length, so those requests are still
+    /// rejected.
+    #[default]
+    StripContentLength,
+    /// Like `StripContentLength`, but the removed values are handed to the
+    /// service in a [`ConflictingContentLength`] extension.
+    ///
+    /// Requests with differing `Content-Length` values and no
+    /// `Transfer-Encoding` are served too, with an empty body.
+    ///
+    /// [`ConflictingContentLength`]: crate::ext::ConflictingContentLength
+    Passthrough,
 }
 
 /// Deconstructed parts of a `Connection`.
@@ -138,6 +205,15 @@ where
         self.conn.disable_keep_alive();
     }
 
+    /// Returns whether the connection was closed for sitting idle past the
+    /// [`keep_alive_timeout`](Builder::keep_alive_timeout).
+    ///
+    /// Check this after [`poll_without_shutdown`](Connection::poll_without_shutdown)
+    /// resolves.
+    pub fn keep_alive_timed_out(&self) -> bool {
+        self.conn.keep_alive_timed_out()
+    }
+
     /// Return the inner IO object, and additional information.
     ///
     /// If the IO object has been "rewound" the io will not contain those bytes rewound.
@@ -192,7 +268,11 @@ where
     where
         I: Send,
     {
-        UpgradeableConnection { inner: Some(self) }
+        UpgradeableConnection {
+            inner: Some(self),
+            upgraded: None,
+            shutting_down: false,
+        }
     }
 }
 
@@ -238,12 +318,26 @@ impl Builder {
             h1_keep_alive: true,
             h1_title_case_headers: false,
             h1_preserve_header_case: false,
+            h1_allow_invalid_request_target: false,
             h1_max_headers: None,
             h1_header_read_timeout: Dur::Default(Some(Duration::from_secs(30))),
+            h1_keep_alive_timeout: Dur::Default(None),
+            h1_max_requests: None,
+            h1_upgrade_grace_period: Dur::Default(None),
+            h1_expect_continue: ExpectContinue::OnBodyRead,
+            h1_conflicting_framing: ConflictingFraming::StripContentLength,
+            h1_max_chunk_extension_size: None,
+            h1_reject_chunk_extensions: false,
+            max_request_body_size: None,
+            error_responder: None,
             h1_writev: None,
             max_buf_size: None,
             pipeline_flush: false,
             date_header: true,
+            date_source: DateSource::default(),
+            record_stats: false,
+            on_stats: None,
+            stats_clock: Clock::default(),
         }
     }
     /// Set whether HTTP/1 connections should support half-closures.
@@ -253,6 +347,15 @@ impl Builder {
     /// prevent closing the connection immediately if `read`
     /// detects an EOF in the middle of a request.
     ///
+    /// The request is still served, and the whole response written, even
+    /// when the EOF comes right after the request body, as with clients
+    /// that shut down their write side as soon as they're done sending.
+    /// The connection then closes after the response, since no more
+    /// requests can follow.
+    ///
+    /// With this `false`, an EOF while a request is being served drops the
+    /// response and ends the connection with an error.
+    ///
     /// Default is `false`.
     pub fn half_close(&mut self, val: bool) -> &mut Self {
         self.h1_half_close = val;
@@ -289,15 +392,68 @@ impl Builder {
         self
     }
 
-    /// Set whether to support preserving original header cases.
+    /// Set whether HTTP/1 connections will serve requests whose target isn't
+    /// a valid URI.
+    ///
+    /// If this is enabled and a request target, such as one with unbalanced
+    /// brackets or userinfo, can't be parsed as a URI, the request is still
+    /// handed to the service, with a URI of `/` and the target as received
+    /// in a [`RawRequestTarget`](crate::ext::RawRequestTarget) extension.
+    /// Otherwise, the connection responds with `400 Bad Request` and closes.
+    ///
+    /// Request lines that can't be parsed at all are still rejected.
+    ///
+    /// Default is false.
+    pub fn allow_invalid_request_target(&mut self, enabled: bool) -> &mut Self {
+        self.h1_allow_invalid_request_target = enabled;
+        self
+    }
+
+    /// Set whether HTTP/1 connections will accept multiple spaces between the
+    /// parts of a request line.
     ///
-    /// Currently, this will record the original cases received, and store them
-    /// in a private extension on the `Request`. It will also look for and use
-    /// such an extension in any provided `Response`.
+    /// [RFC 9112 Section 3] allows a server to accept them:
     ///
-    /// Since the relevant extension is still private, there is no way to
-    /// interact with the original cases. The only effect this can have now is
-    /// to forward the cases in a proxy-like fashion.
+    /// > Although the request-line grammar rule requires that each of the
+    /// > component elements be separated by a single SP octet, recipients MAY
+    /// > instead parse on whitespace-delimited word boundaries and, aside from
+    /// > the CRLF terminator, treat any form of whitespace as the SP separator
+    /// > while ignoring preceding or trailing whitespace [...]
+    ///
+    /// Only spaces are accepted, not the other forms of whitespace.
+    ///
+    /// Default is false.
+    ///
+    /// [RFC 9112 Section 3]: https://www.rfc-editor.org/rfc/rfc9112#section-3
+    pub fn allow_multiple_spaces_in_request_line_delimiters(&mut self, enabled: bool) -> &mut Self {
+        self.h1_parser_config
+            .allow_multiple_spaces_in_request_line_delimiters(enabled);
+        self
+    }
+
+    /// Set whether HTTP/1 connections will accept whitespace before the
+    /// first header name of a request.
+    ///
+    /// The spec doesn't allow it, and [RFC 9112 Section 2.2] says a
+    /// recipient must either reject the message or ignore the line it
+    /// starts. With this enabled, the whitespace is skipped and the line is
+    /// parsed as a header instead, as some clients expect.
+    ///
+    /// Default is false.
+    ///
+    /// [RFC 9112 Section 2.2]: https://www.rfc-editor.org/rfc/rfc9112#section-2.2
+    pub fn allow_space_before_first_header_name(&mut self, enabled: bool) -> &mut Self {
+        self.h1_parser_config
+            .allow_space_before_first_header_name(enabled);
+        self
+    }
+
+    /// Set whether to support preserving original header cases.
+    ///
+    /// This will record the original cases received, and store them in a
+    /// [`HeaderCaseMap`](crate::ext::HeaderCaseMap) extension on the `Request`.
+    /// Such an extension in any provided `Response` is used to write its header
+    /// names, whether or not this is set.
     ///
     /// Default is false.
     pub fn preserve_header_case(&mut self, enabled: bool) -> &mut Self {
@@ -326,6 +482,11 @@ impl Builder {
     /// Set a timeout for reading client request headers. If a client does not
     /// transmit the entire header within this time, the connection is closed.
     ///
+    /// The time starts when the connection begins waiting for a request, so
+    /// this also limits how long a kept-alive connection can sit idle, unless
+    /// [`Builder::keep_alive_timeout`] is set. The request body isn't
+    /// covered.
+    ///
     /// Requires a [`Timer`] set by [`Builder::timer`] to take effect. Panics if `header_read_timeout` is configured
     /// without a [`Timer`].
     ///
@@ -337,6 +498,184 @@ impl Builder {
         self
     }
 
+    /// Set how long a kept-alive connection may sit idle between requests.
+    ///
+    /// If the first byte of the next request doesn't arrive in time, the
+    /// connection is closed gracefully, and
+    /// [`Connection::keep_alive_timed_out`] returns true. Once it arrives,
+    /// the [`header_read_timeout`](Builder::header_read_timeout) starts.
+    ///
+    /// Requires a [`Timer`] set by [`Builder::timer`] to take effect. Panics
+    /// if `keep_alive_timeout` is configured while a timer has not been
+    /// provided.
+    ///
+    /// Pass `None` to disable.
+    ///
+    /// Default is `None`.
+    pub fn keep_alive_timeout(&mut self, timeout: impl Into<Option<Duration>>) -> &mut Self {
+        self.h1_keep_alive_timeout = Dur::Configured(timeout.into());
+        self
+    }
+
+    /// Set the maximum number of requests served on a connection.
+    ///
+    /// The response to the last one has a `Connection: close` header, and
+    /// the connection is closed once it is written. This spreads clients
+    /// across backends behind a load balancer, and bounds the state a
+    /// long-lived connection builds up.
+    ///
+    /// Default is no limit.
+    pub fn max_requests(&mut self, max: usize) -> &mut Self {
+        self.h1_max_requests = Some(max);
+        self
+    }
+
+    /// Set how long a graceful shutdown waits for a connection upgraded from
+    /// this one, before closing it.
+    ///
+    /// With this set, a connection served [`with_upgrades`] that gets
+    /// upgraded keeps tracking the [`Upgraded`] connection handed out by
+    /// [`upgrade::on`](crate::upgrade::on), and only resolves once it has
+    /// been dropped. If [`graceful_shutdown`] is called, it waits at most
+    /// this long. Then the connection resolves, and the transport is closed
+    /// the next time the upgraded connection is read or written, which
+    /// fails from then on. Tasks waiting on it are woken up for this.
+    ///
+    /// An upgraded connection taken apart with
+    /// [`Upgraded::downcast`](crate::upgrade::Upgraded::downcast) is tracked
+    /// by the [`ShutdownGuard`](crate::upgrade::ShutdownGuard) in its parts
+    /// instead, which tells when to close the IO.
+    ///
+    /// This only covers HTTP/1 upgrades. HTTP/2 extended CONNECT streams
+    /// are ordinary streams of their connection, which a graceful shutdown
+    /// of it waits for as for any other stream.
+    ///
+    /// Pass `None` to resolve as soon as the connection is upgraded.
+    ///
+    /// Requires a [`Timer`] set by [`Builder::timer`] to take effect. Panics
+    /// if `upgrade_grace_period` is configured while a timer has not been
+    /// provided.
+    ///
+    /// Default is `None`.
+    ///
+    /// [`with_upgrades`]: Connection::with_upgrades
+    /// [`graceful_shutdown`]: UpgradeableConnection::graceful_shutdown
+    pub fn upgrade_grace_period(&mut self, grace_period: impl Into<Option<Duration>>) -> &mut Self {
+        self.h1_upgrade_grace_period = Dur::Configured(grace_period.into());
+        self
+    }
+
+    /// Set how requests with an `Expect: 100-continue` header are answered.
+    ///
+    /// By default, `100 Continue` is sent once the service first polls the
+    /// request body, so a service can refuse an upload by responding before
+    /// reading it. With [`ExpectContinue::Reject`], such requests get a `417
+    /// Expectation Failed` response and never reach the service, and the
+    /// connection ends with an error after writing it.
+    ///
+    /// Requests without a body, or from HTTP/1.0 clients, are served as if
+    /// they had no `Expect` header.
+    ///
+    /// Default is [`ExpectContinue::OnBodyRead`].
+    pub fn expect_continue(&mut self, mode: ExpectContinue) -> &mut Self {
+        self.h1_expect_continue = mode;
+        self
+    }
+
+    /// Set how requests with conflicting framing headers are handled.
+    ///
+    /// Such requests are a common vector for request smuggling, as servers
+    /// and proxies may disagree on where their bodies end. Unless rejected,
+    /// the connection is closed after the response, as [RFC 9112] requires.
+    ///
+    /// Default is [`ConflictingFraming::StripContentLength`].
+    ///
+    /// [RFC 9112]: https://www.rfc-editor.org/rfc/rfc9112#section-6.1
+    pub fn conflicting_framing(&mut self, policy: ConflictingFraming) -> &mut Self {
+        self.h1_conflicting_framing = policy;
+        self
+    }
+
+    /// Set the maximum number of bytes of chunk extensions allowed on each
+    /// chunk of a request body.
+    ///
+    /// Chunk extensions are ignored, but still have to be read, so a client
+    /// can send many of them to use up server time without sending any body
+    /// data. Going over the limit ends the request body with an error, and
+    /// closes the connection.
+    ///
+    /// Regardless of this setting, a body may not have more than 16KiB of
+    /// chunk extensions in total.
+    ///
+    /// Default is no limit per chunk.
+    pub fn max_chunk_extension_size(&mut self, max: usize) -> &mut Self {
+        self.h1_max_chunk_extension_size = Some(max);
+        self
+    }
+
+    /// Set whether to reject request bodies that have any chunk extensions.
+    ///
+    /// A chunk extension in a request body ends the body with an error, and
+    /// closes the connection.
+    ///
+    /// Default is false.
+    pub fn reject_chunk_extensions(&mut self, enabled: bool) -> &mut Self {
+        self.h1_reject_chunk_extensions = enabled;
+        self
+    }
+
+    /// Set the maximum size of a request body, in bytes.
+    ///
+    /// A request with a larger `Content-Length` is answered with `413
+    /// Payload Too Large` without calling the service. A chunked body that
+    /// grows past the limit ends with an error for which
+    /// [`Error::is_body_too_large`](crate::Error::is_body_too_large)
+    /// returns true, and the connection is closed after the response.
+    ///
+    /// Default is no limit.
+    pub fn max_request_body_size(&mut self, max: u64) -> &mut Self {
+        self.max_request_body_size = Some(max);
+        self
+    }
+
+    /// Set a function to build the response to requests that hyper rejects
+    /// without calling the service.
+    ///
+    /// These are requests that fail to parse, such as with an invalid
+    /// method or a head that is too large, and ones rejected by options
+    /// like [`Builder::expect_continue`]. By default they get a response
+    /// with no body and just a status, such as `400 Bad Request`, `414 URI
+    /// Too Long` or `431 Request Header Fields Too Large`.
+    ///
+    /// The function is given the error and the status hyper would have
+    /// responded with, and returns the response to write instead, such as
+    /// an error page. Its `Content-Length` is set from the body. The
+    /// connection is still closed after writing it.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use hyper::server::conn::http1::Builder;
+    /// use hyper::body::Bytes;
+    /// use hyper::Response;
+    ///
+    /// let mut http = Builder::new();
+    /// http.error_response(|err, status| {
+    ///     Response::builder()
+    ///         .status(status)
+    ///         .header("content-type", "text/plain")
+    ///         .body(Bytes::from(format!("{}: {}\n", status, err)))
+    ///         .unwrap()
+    /// });
+    /// ```
+    pub fn error_response<F>(&mut self, responder: F) -> &mut Self
+    where
+        F: Fn(&crate::Error, http::StatusCode) -> http::Response<Bytes> + Send + Sync + 'static,
+    {
+        self.error_responder = Some(proto::h1::ErrorResponder::new(responder));
+        self
+    }
+
     /// Set whether HTTP/1 connections should try to use vectored writes,
     /// or always flatten into a single buffer.
     ///
@@ -380,6 +719,17 @@ impl Builder {
         self
     }
 
+    /// Set where the `date` header of responses comes from.
+    ///
+    /// Default is the [`SystemDate`](super::SystemDate).
+    pub fn date_provider<P>(&mut self, provider: P) -> &mut Self
+    where
+        P: DateProvider + 'static,
+    {
+        self.date_source = DateSource::new(provider);
+        self
+    }
+
     /// Aggregates flushes to better support pipelined responses.
     ///
     /// Experimental, may have bugs.
@@ -390,6 +740,45 @@ impl Builder {
         self
     }
 
+    /// Set whether to record [`ServerRequestStats`] for each request.
+    ///
+    /// When enabled, the stats are inserted into the request extensions
+    /// before the service is called, with only the time the head was parsed
+    /// filled in. The completed stats are passed to the [`Builder::on_stats`]
+    /// callback once the response has been flushed.
+    ///
+    /// Default is false.
+    pub fn record_stats(&mut self, enabled: bool) -> &mut Self {
+        self.record_stats = enabled;
+        self
+    }
+
+    /// Set a callback to receive the completed stats of every request.
+    ///
+    /// The callback is called once the response to a request has been
+    /// flushed, which makes it a good place for access logging. Stats are
+    /// only recorded when [`Builder::record_stats`] is enabled.
+    ///
+    /// Default is no callback.
+    pub fn on_stats<F>(&mut self, callback: F) -> &mut Self
+    where
+        F: FnMut(ServerRequestStats) + Send + 'static,
+    {
+        self.on_stats = Some(OnStats::new(callback));
+        self
+    }
+
+    /// Set the clock that [`ServerRequestStats`] timestamps are read from.
+    ///
+    /// Default is the [`SystemClock`](crate::stats::SystemClock).
+    pub fn stats_clock<C>(&mut self, clock: C) -> &mut Self
+    where
+        C: StatsClock + 'static,
+    {
+        self.stats_clock = Clock::new(clock);
+        self
+    }
+
     /// Set the timer used in background tasks.
     pub fn timer<M>(&mut self, timer: M) -> &mut Self
     where
@@ -433,6 +822,90 @@ impl Builder {
     /// # fn main() {}
     /// ```
     pub fn serve_connection<I, S>(&self, io: I, service: S) -> Connection<I, S>
+    where
+        S: HttpService<IncomingBody>,
+        S::Error: Into<Box<dyn StdError + Send + Sync>>,
+        S::ResBody: 'static,
+        <S::ResBody as Body>::Error: Into<Box<dyn StdError + Send + Sync>>,
+        I: Read + Write + Stats + Unpin,
+    {
+        self.serve(io, service, None)
+    }
+
+    /// Bind a connection together with a [`Service`](crate::service::Service),
+    /// inserting `info` into the extensions of every request.
+    ///
+    /// This hands the service data about the connection, such as the peer
+    /// address, a TLS client certificate, or when the connection was
+    /// accepted, without wrapping the service. Each request gets a clone of
+    /// `info`; to pass several values, put them in one type.
+    ///
+    /// # Panics
+    ///
+    /// If a timeout option has been configured, but a `timer` has not been
+    /// provided, calling `serve_connection_with_info` will panic.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use hyper::{body::Incoming, Request, Response};
+    /// # use hyper::service::Service;
+    /// # use hyper::server::conn::http1::Builder;
+    /// # use hyper::rt::{Read, Stats, Write};
+    /// use std::net::SocketAddr;
+    /// use std::time::Instant;
+    ///
+    /// #[derive(Clone)]
+    /// struct ConnInfo {
+    ///     peer_addr: SocketAddr,
+    ///     accepted_at: Instant,
+    /// }
+    ///
+    /// # async fn run<I, S>(some_io: I, some_service: S, peer_addr: SocketAddr)
+    /// # where
+    /// #     I: Read + Write + Stats + Unpin + Send + 'static,
+    /// #     S: Service<hyper::Request<Incoming>, Response=hyper::Response<Incoming>> + Send + 'static,
+    /// #     S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    /// #     S::Future: Send,
+    /// # {
+    /// let info = ConnInfo {
+    ///     peer_addr,
+    ///     accepted_at: Instant::now(),
+    /// };
+    /// // The service finds it with `req.extensions().get::<ConnInfo>()`.
+    /// let conn = Builder::new().serve_connection_with_info(some_io, some_service, info);
+    ///
+    /// if let Err(e) = conn.await {
+    ///     eprintln!("server connection error: {}", e);
+    /// }
+    /// # }
+    /// # fn main() {}
+    /// ```
+    pub fn serve_connection_with_info<I, S, T>(
+        &self,
+        io: I,
+        service: S,
+        info: T,
+    ) -> Connection<I, S>
+    where
+        S: HttpService<IncomingBody>,
+        S::Error: Into<Box<dyn StdError + Send + Sync>>,
+        S::ResBody: 'static,
+        <S::ResBody as Body>::Error: Into<Box<dyn StdError + Send + Sync>>,
+        I: Read + Write + Stats + Unpin,
+        T: Clone + Send + Sync + 'static,
+    {
+        let mut extensions = http::Extensions::new();
+        extensions.insert(info);
+        self.serve(io, service, Some(extensions))
+    }
+
+    fn serve<I, S>(
+        &self,
+        io: I,
+        service: S,
+        connection_info: Option<http::Extensions>,
+    ) -> Connection<I, S>
     where
         S: HttpService<IncomingBody>,
         S::Error: Into<Box<dyn StdError + Send + Sync>>,
@@ -455,6 +928,9 @@ impl Builder {
         if self.h1_preserve_header_case {
             conn.set_preserve_header_case();
         }
+        if self.h1_allow_invalid_request_target {
+            conn.set_allow_invalid_request_target();
+        }
         if let Some(max_headers) = self.h1_max_headers {
             conn.set_http1_max_headers(max_headers);
         }
@@ -464,6 +940,31 @@ impl Builder {
         {
             conn.set_http1_header_read_timeout(dur);
         };
+        if let Some(dur) = self
+            .timer
+            .check(self.h1_keep_alive_timeout, "keep_alive_timeout")
+        {
+            conn.set_keep_alive_timeout(dur);
+        }
+        if let Some(max) = self.h1_max_requests {
+            conn.set_max_requests(max);
+        }
+        if self.h1_expect_continue == ExpectContinue::Reject {
+            conn.set_reject_expect_continue();
+        }
+        conn.set_conflicting_framing(self.h1_conflicting_framing);
+        if let Some(max) = self.h1_max_chunk_extension_size {
+            conn.set_max_chunk_extension_size(max as u64);
+        }
+        if self.h1_reject_chunk_extensions {
+            conn.set_reject_chunk_extensions();
+        }
+        if let Some(max) = self.max_request_body_size {
+            conn.set_max_request_body_size(max);
+        }
+        if let Some(ref responder) = self.error_responder {
+            conn.set_error_responder(responder.clone());
+        }
         if let Some(writev) = self.h1_writev {
             if writev {
                 conn.set_write_strategy_queue();
@@ -478,9 +979,25 @@ impl Builder {
         if !self.date_header {
             conn.disable_date_header();
         }
-        let sd = proto::h1::dispatch::Server::new(service);
+        conn.set_date_source(self.date_source.clone());
+        // The server dispatch has no use for the client-side connection
+        // stats, so don't pay for collecting them.
+        conn.disable_stats();
+        let mut sd = proto::h1::dispatch::Server::new(service);
+        if self.record_stats {
+            sd.enable_stats(self.on_stats.clone(), self.stats_clock.clone());
+        }
+        if let Some(connection_info) = connection_info {
+            sd.set_connection_info(connection_info);
+        }
         let proto = proto::h1::Dispatcher::new(sd, conn);
-        Connection { conn: proto }
+        Connection {
+            conn: proto,
+            timer: self.timer.clone(),
+            upgrade_grace_period: self
+                .timer
+                .check(self.h1_upgrade_grace_period, "upgrade_grace_period"),
+        }
     }
 }
 
@@ -492,6 +1009,16 @@ where
     S: HttpService<IncomingBody>,
 {
     pub(super) inner: Option<Connection<T, S>>,
+    upgraded: Option<TrackedUpgrade>,
+    shutting_down: bool,
+}
+
+/// An upgraded connection an `UpgradeableConnection` waits for.
+struct TrackedUpgrade {
+    tracker: Tracker,
+    timer: Time,
+    grace_period: Duration,
+    deadline: Option<Pin<Box<dyn Sleep>>>,
 }
 
 impl<I, B, S> UpgradeableConnection<I, S>
@@ -506,7 +1033,12 @@ where
     ///
     /// This `Connection` should continue to be polled until shutdown
     /// can finish.
+    ///
+    /// If the connection was upgraded, and an
+    /// [`upgrade_grace_period`](Builder::upgrade_grace_period) is set, this
+    /// starts the wait for the upgraded connection to be dropped.
     pub fn graceful_shutdown(mut self: Pin<&mut Self>) {
+        self.shutting_down = true;
         // Connection (`inner`) is `None` if it was upgraded (and `poll` is `Ready`).
         // In that case, we don't need to call `graceful_shutdown`.
         if let Some(conn) = self.inner.as_mut() {
@@ -528,17 +1060,45 @@ where
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         if let Some(conn) = self.inner.as_mut() {
             match ready!(Pin::new(&mut conn.conn).poll(cx)) {
-                Ok(proto::Dispatched::Shutdown) => Poll::Ready(Ok(())),
+                Ok(proto::Dispatched::Shutdown) => return Poll::Ready(Ok(())),
                 Ok(proto::Dispatched::Upgrade(pending)) => {
-                    let (io, buf, _) = self.inner.take().unwrap().conn.into_inner();
-                    pending.fulfill(Upgraded::new(io, buf));
-                    Poll::Ready(Ok(()))
+                    let conn = self.inner.take().unwrap();
+                    let (io, buf, _) = conn.conn.into_inner();
+                    let mut upgraded = Upgraded::new(io, buf);
+                    if let Some(grace_period) = conn.upgrade_grace_period {
+                        self.upgraded = Some(TrackedUpgrade {
+                            tracker: upgraded.track(),
+                            timer: conn.timer,
+                            grace_period,
+                            deadline: None,
+                        });
+                    }
+                    pending.fulfill(upgraded);
                 }
-                Err(e) => Poll::Ready(Err(e)),
+                Err(e) => return Poll::Ready(Err(e)),
             }
-        } else {
-            // inner is `None`, meaning the connection was upgraded, thus it's `Poll::Ready(Ok(()))`
-            Poll::Ready(Ok(()))
         }
+
+        // inner is `None`, meaning the connection was upgraded, so it's
+        // done once the upgraded connection is, if it's being tracked.
+        let shutting_down = self.shutting_down;
+        let upgraded = match self.upgraded {
+            Some(ref mut upgraded) => upgraded,
+            None => return Poll::Ready(Ok(())),
+        };
+        if upgraded.tracker.poll_released(cx).is_ready() {
+            return Poll::Ready(Ok(()));
+        }
+        if !shutting_down {
+            return Poll::Pending;
+        }
+        if upgraded.deadline.is_none() {
+            let deadline = Instant::now() + upgraded.grace_period;
+            upgraded.deadline = Some(upgraded.timer.sleep_until(deadline));
+        }
+        ready!(upgraded.deadline.as_mut().unwrap().as_mut().poll(cx));
+        debug!("upgraded connection still open after grace period, closing it");
+        upgraded.tracker.force_close();
+        Poll::Ready(Ok(()))
     }
 }
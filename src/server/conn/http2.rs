@@ -9,14 +9,18 @@ use std::task::{Context, Poll};
 use std::time::Duration;
 
 use crate::rt::{Read, Write};
+use bytes::Bytes;
 use futures_core::ready;
 use pin_project_lite::pin_project;
 
+use super::DateProvider;
 use crate::body::{Body, Incoming as IncomingBody};
+use crate::common::date::DateSource;
+use crate::ext::AltSvc;
 use crate::proto;
 use crate::rt::bounds::Http2ServerConnExec;
 use crate::service::HttpService;
-use crate::{common::time::Time, rt::Timer};
+use crate::{common::time::Time, rt::Timer, H2Settings};
 
 pin_project! {
     /// A [`Future`](core::future::Future) representing an HTTP/2 connection, bound to a
@@ -45,6 +49,30 @@ pub struct Builder<E> {
     h2_builder: proto::h2::server::Config,
 }
 
+/// How [`Connection::go_away`] shuts down a connection.
+#[derive(Clone, Debug)]
+pub struct GoAway {
+    abrupt: Option<u32>,
+    debug_data: Bytes,
+}
+
+/// What a server does with a client that resets streams too fast.
+///
+/// See [`Builder::max_reset_stream_rate`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ResetStreamAction {
+    /// Sends a `GOAWAY` with `ENHANCE_YOUR_CALM`, and closes the connection
+    /// once it is written.
+    #[default]
+    GoAway,
+    /// Closes the connection right away, without a `GOAWAY`.
+    ///
+    /// This spends the least on a client that is likely an attacker, but a
+    /// well-behaved client isn't told why it was cut off.
+    Drop,
+}
+
 // ===== impl Connection =====
 
 impl<I, S, E> fmt::Debug for Connection<I, S, E>
@@ -78,6 +106,73 @@ where
     pub fn graceful_shutdown(mut self: Pin<&mut Self>) {
         self.conn.graceful_shutdown();
     }
+
+    /// Start shutting down this connection by sending the peer a `GOAWAY`.
+    ///
+    /// Unlike [`graceful_shutdown`](Connection::graceful_shutdown), this
+    /// picks how the connection is shut down, and can attach debug data for
+    /// the peer to log. See [`GoAway`] for the options.
+    ///
+    /// This `Connection` should continue to be polled until shutdown
+    /// can finish.
+    pub fn go_away(mut self: Pin<&mut Self>, goaway: GoAway) {
+        self.conn.go_away(goaway.abrupt, goaway.debug_data);
+    }
+
+    /// Sets the max size of received header frames for this connection,
+    /// in place of the one set on the [`Builder`].
+    ///
+    /// This is both advertised to the client as
+    /// `SETTINGS_MAX_HEADER_LIST_SIZE` and enforced on the headers it sends.
+    /// It lets one `Builder` serve peers that are trusted with larger headers
+    /// alongside ones that aren't.
+    ///
+    /// # Note
+    ///
+    /// Settings are sent as the connection starts, so this must be called
+    /// before the `Connection` is first polled. If called after, this does
+    /// nothing.
+    pub fn max_header_list_size(mut self: Pin<&mut Self>, max: u32) {
+        self.conn.max_header_list_size(max);
+    }
+
+    /// Returns the settings the client has sent on this connection.
+    ///
+    /// Every SETTINGS frame received so far is applied, so this is what the
+    /// client currently allows. It's `None` until the client's first
+    /// SETTINGS frame, which follows its connection preface, has been read.
+    ///
+    /// The same settings are also added to the extensions of each request
+    /// handed to the service.
+    pub fn peer_settings(&self) -> Option<H2Settings> {
+        self.conn.peer_settings()
+    }
+
+    /// Returns whether this connection lets the client use the [extended
+    /// CONNECT protocol][rfc], such as for WebSockets.
+    ///
+    /// This is what the server advertised as `SETTINGS_ENABLE_CONNECT_PROTOCOL`
+    /// in its first SETTINGS frame, as configured with
+    /// [`enable_connect_protocol`](Builder::enable_connect_protocol).
+    ///
+    /// [rfc]: https://datatracker.ietf.org/doc/html/rfc8441
+    pub fn is_extended_connect_enabled(&self) -> bool {
+        self.conn.is_extended_connect_enabled()
+    }
+
+    /// Sends the client a `PING` and measures the round trip time.
+    ///
+    /// The returned future resolves once the client acknowledges the
+    /// `PING`, and fails if the connection closes first. If a `PING` is
+    /// already in flight, such as one sent for keep-alive or the adaptive
+    /// window, its acknowledgement is used instead of sending another.
+    ///
+    /// Asked for before the handshake finishes, the `PING` is sent right
+    /// after. This `Connection` must keep being polled for the
+    /// acknowledgement to be read.
+    pub fn ping(mut self: Pin<&mut Self>) -> impl Future<Output = crate::Result<Duration>> {
+        self.conn.ping()
+    }
 }
 
 impl<I, B, S, E> Future for Connection<I, S, E>
@@ -103,6 +198,50 @@ where
     }
 }
 
+// ===== impl GoAway =====
+
+impl GoAway {
+    /// Shuts down in two phases, with a `NO_ERROR` code.
+    ///
+    /// A first `GOAWAY` with the maximum stream ID tells the peer to stop
+    /// opening streams. After a round trip, a final `GOAWAY` names the last
+    /// stream that will be processed, so that requests already in flight
+    /// aren't lost. The connection closes once the remaining streams are
+    /// done.
+    ///
+    /// This is what [`graceful_shutdown`](Connection::graceful_shutdown)
+    /// does.
+    pub fn graceful() -> Self {
+        GoAway {
+            abrupt: None,
+            debug_data: Bytes::new(),
+        }
+    }
+
+    /// Shuts down at once, with the given HTTP/2 error code.
+    ///
+    /// A single `GOAWAY` names the last stream that was processed, and the
+    /// connection closes as soon as it is written, without waiting for open
+    /// streams. If the code isn't `NO_ERROR` (0), the `Connection` resolves
+    /// with an error.
+    pub fn immediate(error_code: u32) -> Self {
+        GoAway {
+            abrupt: Some(error_code),
+            debug_data: Bytes::new(),
+        }
+    }
+
+    /// Sets opaque debug data to send with the `GOAWAY`, such as why the
+    /// connection is being drained.
+    ///
+    /// It is sent on a copy of the first `GOAWAY`, right after it. Data that
+    /// wouldn't fit in a minimum size frame (16,384 bytes) is cut short.
+    pub fn debug_data(mut self, data: impl Into<Bytes>) -> Self {
+        self.debug_data = data.into();
+        self
+    }
+}
+
 // ===== impl Builder =====
 
 impl<E> Builder<E> {
@@ -126,6 +265,8 @@ impl<E> Builder<E> {
     /// As of v0.4.0, it is 20.
     ///
     /// See <https://github.com/hyperium/hyper/issues/2877> for more information.
+    /// To also limit how fast a client may reset streams that were accepted,
+    /// see [`max_reset_stream_rate`](Builder::max_reset_stream_rate).
     pub fn max_pending_accept_reset_streams(&mut self, max: impl Into<Option<usize>>) -> &mut Self {
         self.h2_builder.max_pending_accept_reset_streams = max.into();
         self
@@ -146,6 +287,39 @@ impl<E> Builder<E> {
         self
     }
 
+    /// Configures the maximum number of streams a client may reset in each
+    /// `interval`.
+    ///
+    /// Every `RST_STREAM` frame from the client counts, whether or not the
+    /// stream had been handed to the service yet. Once a client resets more
+    /// than `max` streams within one interval, the connection is closed as
+    /// [`reset_stream_rate_action`](Builder::reset_stream_rate_action) says,
+    /// and resolves with an error. This protects against "rapid reset"
+    /// attacks, where streams are opened and reset right away so that the
+    /// server keeps starting work that is thrown out.
+    ///
+    /// If `None` is supplied, there is no limit, which is the default.
+    pub fn max_reset_stream_rate(
+        &mut self,
+        max: impl Into<Option<u32>>,
+        interval: Duration,
+    ) -> &mut Self {
+        self.h2_builder.max_reset_stream_rate = max.into().map(|max| (max, interval));
+        self
+    }
+
+    /// Sets what is done with a client that goes over the
+    /// [`max_reset_stream_rate`](Builder::max_reset_stream_rate).
+    ///
+    /// The limit on pending accept reset streams is enforced by h2, and
+    /// always sends a `GOAWAY`.
+    ///
+    /// Default is [`ResetStreamAction::GoAway`].
+    pub fn reset_stream_rate_action(&mut self, action: ResetStreamAction) -> &mut Self {
+        self.h2_builder.reset_stream_rate_action = action;
+        self
+    }
+
     /// Sets the [`SETTINGS_INITIAL_WINDOW_SIZE`][spec] option for HTTP2
     /// stream-level flow control.
     ///
@@ -179,7 +353,10 @@ impl<E> Builder<E> {
     ///
     /// Enabling this will override the limits set in
     /// `initial_stream_window_size` and
-    /// `initial_connection_window_size`.
+    /// `initial_connection_window_size`. Windows then start at the spec
+    /// default and grow with the measured bandwidth-delay product, see
+    /// [`adaptive_window_max`](Self::adaptive_window_max) and
+    /// [`adaptive_window_ping_interval`](Self::adaptive_window_ping_interval).
     pub fn adaptive_window(&mut self, enabled: bool) -> &mut Self {
         use proto::h2::SPEC_WINDOW_SIZE;
 
@@ -191,6 +368,35 @@ impl<E> Builder<E> {
         self
     }
 
+    /// Sets the largest window adaptive flow control will grow to.
+    ///
+    /// Windows sized to the bandwidth-delay product of a fast, distant link
+    /// can take a lot of memory per connection; this caps them. It has no
+    /// effect unless [`adaptive_window`](Self::adaptive_window) is enabled.
+    ///
+    /// Passing `None` restores the default, which is currently 16MB.
+    pub fn adaptive_window_max(&mut self, max: impl Into<Option<u32>>) -> &mut Self {
+        self.h2_builder.adaptive_window_max = max.into();
+        self
+    }
+
+    /// Sets the shortest interval between the PING frames adaptive flow
+    /// control sends to estimate the bandwidth-delay product.
+    ///
+    /// Pings start this far apart, and are sent less often, up to every 10
+    /// seconds, while the estimate holds steady. It has no effect unless
+    /// [`adaptive_window`](Self::adaptive_window) is enabled.
+    ///
+    /// Passing `None` restores the default: pings start 100ms apart, and may
+    /// be sent more often while the estimate is still growing.
+    pub fn adaptive_window_ping_interval(
+        &mut self,
+        interval: impl Into<Option<Duration>>,
+    ) -> &mut Self {
+        self.h2_builder.adaptive_window_ping_interval = interval.into();
+        self
+    }
+
     /// Sets the maximum frame size to use for HTTP2.
     ///
     /// Passing `None` will do nothing.
@@ -239,6 +445,20 @@ impl<E> Builder<E> {
         self
     }
 
+    /// Sets whether HTTP2 keep-alive should apply while the connection is idle.
+    ///
+    /// If enabled, pings are also sent when no streams are active, so dead
+    /// connections are closed more aggressively, such as ones silently
+    /// dropped by a NAT. If disabled, keep-alive pings are only sent while
+    /// there are open request/responses streams. Does nothing if
+    /// `keep_alive_interval` is disabled.
+    ///
+    /// Default is `true`.
+    pub fn keep_alive_while_idle(&mut self, enabled: bool) -> &mut Self {
+        self.h2_builder.keep_alive_while_idle = enabled;
+        self
+    }
+
     /// Set the maximum write buffer size for each HTTP/2 stream.
     ///
     /// Default is currently ~400KB, but may change.
@@ -263,11 +483,79 @@ impl<E> Builder<E> {
     /// Sets the max size of received header frames.
     ///
     /// Default is currently 16KB, but can change.
+    ///
+    /// To use another limit for some connections, see
+    /// [`Connection::max_header_list_size`].
     pub fn max_header_list_size(&mut self, max: u32) -> &mut Self {
         self.h2_builder.max_header_list_size = max;
         self
     }
 
+    /// Sets the maximum number of headers a request may have.
+    ///
+    /// A request with more is answered with "431 Request Header Fields Too
+    /// Large", and isn't handed to the service. Pseudo-headers, such as
+    /// `:path`, aren't counted.
+    ///
+    /// Default is no limit, other than the size set with
+    /// [`max_header_list_size`](Builder::max_header_list_size).
+    pub fn max_headers(&mut self, max: usize) -> &mut Self {
+        self.h2_builder.max_headers = Some(max);
+        self
+    }
+
+    /// Sets the maximum number of requests served on a connection.
+    ///
+    /// Once the last one is received, the connection is shut down
+    /// gracefully: a `GOAWAY` tells the client to open a new connection,
+    /// and requests in flight are finished. Requests the client sent before
+    /// the `GOAWAY` reached it are refused with `REFUSED_STREAM`, so they can
+    /// be retried elsewhere. This spreads clients across backends behind a
+    /// load balancer, and bounds the state a long-lived connection builds up.
+    ///
+    /// Default is no limit.
+    pub fn max_requests(&mut self, max: usize) -> &mut Self {
+        self.h2_builder.max_requests = Some(max);
+        self
+    }
+
+    /// Sets the maximum size of a request body, in bytes.
+    ///
+    /// A request with a larger `content-length` is answered with "413
+    /// Payload Too Large", and isn't handed to the service. A body that
+    /// grows past the limit ends with an error for which
+    /// [`Error::is_body_too_large`](crate::Error::is_body_too_large)
+    /// returns true.
+    ///
+    /// Default is no limit.
+    pub fn max_request_body_size(&mut self, max: u64) -> &mut Self {
+        self.h2_builder.max_request_body_size = Some(max);
+        self
+    }
+
+    /// Sets the maximum number of requests whose service futures may run at
+    /// the same time on a connection.
+    ///
+    /// Past the limit, new streams are left unaccepted until one of the
+    /// futures resolves with a response. Their bodies aren't read meanwhile,
+    /// so flow control holds the client back. The connection itself is still
+    /// served, so the responses being sent make progress.
+    ///
+    /// Unlike [`max_concurrent_streams`](Builder::max_concurrent_streams),
+    /// this isn't sent to the client, and doesn't count responses whose
+    /// bodies are still being sent.
+    ///
+    /// Default is no limit.
+    ///
+    /// # Panics
+    ///
+    /// The limit must be at least 1.
+    pub fn max_concurrent_requests(&mut self, max: usize) -> &mut Self {
+        assert!(max > 0, "the concurrent request limit must be at least 1");
+        self.h2_builder.max_concurrent_requests = Some(max);
+        self
+    }
+
     /// Set the timer used in background tasks.
     pub fn timer<M>(&mut self, timer: M) -> &mut Self
     where
@@ -277,6 +565,44 @@ impl<E> Builder<E> {
         self
     }
 
+    /// Sets the origins to advertise in an [`ORIGIN` frame][rfc].
+    ///
+    /// The frame is sent right after the server's SETTINGS, and tells
+    /// clients which origins this connection is authoritative for, so that
+    /// they can reuse it for requests to those origins instead of risking a
+    /// misdirected request. Each origin is serialized as a scheme, host and
+    /// optional port, such as `https://example.com`.
+    ///
+    /// Clients only act on `ORIGIN` frames on TLS connections, and ignore
+    /// them if they don't support them.
+    ///
+    /// Default is to send no `ORIGIN` frame.
+    ///
+    /// [rfc]: https://www.rfc-editor.org/rfc/rfc8336
+    pub fn origin_set<I>(&mut self, origins: I) -> &mut Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.h2_builder.origin_set = origins.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Adds an alternative service to advertise in an [`ALTSVC` frame][rfc].
+    ///
+    /// Each one is sent in its own frame on stream 0, right after the
+    /// server's SETTINGS, so that clients can discover another endpoint for
+    /// the origin, such as over HTTP/3, without an `Alt-Svc` header on
+    /// every response.
+    ///
+    /// Default is to send no `ALTSVC` frames.
+    ///
+    /// [rfc]: https://www.rfc-editor.org/rfc/rfc7838#section-4
+    pub fn alt_svc(&mut self, alt_svc: AltSvc) -> &mut Self {
+        self.h2_builder.alt_svc.push(alt_svc);
+        self
+    }
+
     /// Set whether the `date` header should be included in HTTP responses.
     ///
     /// Note that including the `date` header is recommended by RFC 7231.
@@ -287,6 +613,17 @@ impl<E> Builder<E> {
         self
     }
 
+    /// Set where the `date` header of responses comes from.
+    ///
+    /// Default is the [`SystemDate`](super::SystemDate).
+    pub fn date_provider<P>(&mut self, provider: P) -> &mut Self
+    where
+        P: DateProvider + 'static,
+    {
+        self.h2_builder.date_source = DateSource::new(provider);
+        self
+    }
+
     /// Bind a connection together with a [`Service`](crate::service::Service).
     ///
     /// This returns a Future that must be polled in order for HTTP to be
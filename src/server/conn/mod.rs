@@ -8,13 +8,18 @@
 //! This module is split by HTTP version, providing a connection builder for
 //! each. They work similarly, but they each have specific options.
 //!
-//! If your server needs to support both versions, an auto-connection builder is
-//! provided in the [`hyper-util`](https://github.com/hyperium/hyper-util/tree/master)
-//! crate. This builder wraps the HTTP/1 and HTTP/2 connection builders from this
-//! module, allowing you to set configuration for both. The builder will then check
-//! the version of the incoming connection and serve it accordingly.
+//! If your server needs to support both versions, the [`auto`] module
+//! provides a builder that wraps the HTTP/1 and HTTP/2 connection builders
+//! from this module, allowing you to set configuration for both. The builder
+//! will then check the version of the incoming connection and serve it
+//! accordingly.
 
+#[cfg(all(feature = "http1", feature = "http2"))]
+pub mod auto;
 #[cfg(feature = "http1")]
 pub mod http1;
 #[cfg(feature = "http2")]
 pub mod http2;
+
+#[cfg(any(feature = "http1", feature = "http2"))]
+pub use crate::common::date::{DateProvider, SystemDate};
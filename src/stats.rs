@@ -0,0 +1,699 @@
+//! Aggregation of request stats.
+//!
+//! An [`Aggregator`] takes the [`RequestStats`] of many requests and keeps a
+//! [`Histogram`] for each of the main request phases, which can then be
+//! queried for percentiles.
+//!
+//! Histograms have a fixed size. Recording a request never allocates, and
+//! any reported percentile is within 12.5% of the true value.
+//!
+//! [`AnchoredRequestStats`] holds the timestamps of a [`RequestStats`] as
+//! wall-clock times, for exporting them out of the process.
+//!
+//! A [`ConnectionObserver`] installed on a client connection builder is told
+//! about each step of a request as it happens, rather than only at the end.
+//!
+//! Connections read the time for their stats from a [`StatsClock`], which is
+//! the [`SystemClock`] unless a builder is given another one.
+//!
+//! [`RequestStats::phases`] splits a single request into the
+//! [`RequestPhase`]s it went through.
+
+use std::fmt;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::rt::ConnectionStats;
+use crate::{
+    H2StreamStats, HttpConnectionStats, RedirectStats, RequestStats, RetryReason, RetryStats,
+    StatusCode, Uri, Version,
+};
+
+// Every power of two is split into `1 << SUB_BITS` linear sub-buckets.
+const SUB_BITS: u32 = 3;
+const SUB_COUNT: usize = 1 << SUB_BITS;
+// Enough buckets to cover every `u64`.
+const BUCKETS: usize = (64 - SUB_BITS as usize + 1) * SUB_COUNT;
+
+/// Receives the stats events of a client connection as they happen.
+///
+/// Install one with `observer` on the client connection builders. Every
+/// method has an empty default, so implementors only need the events they
+/// care about. Each event carries the instant it happened at.
+///
+/// The observer is called from the task driving the connection, so it should
+/// return quickly.
+pub trait ConnectionObserver: Send + Sync {
+    /// The transport started connecting.
+    ///
+    /// This and the other transport events happen before hyper is handed the
+    /// connection, so they are reported during the handshake, from the
+    /// transport's [`ConnectionStats`].
+    fn on_connect_start(&self, _at: Instant) {}
+
+    /// The transport finished connecting.
+    fn on_connect_done(&self, _at: Instant) {}
+
+    /// The transport finished its TLS handshake.
+    fn on_tls_done(&self, _at: Instant) {}
+
+    /// The connection picked up a request.
+    fn on_request_start(&self, _at: Instant) {}
+
+    /// The request head was written out.
+    fn on_headers_written(&self, _at: Instant) {}
+
+    /// The request body was written out.
+    fn on_body_written(&self, _at: Instant) {}
+
+    /// The first byte of the response head was received.
+    fn on_first_header_byte(&self, _at: Instant) {}
+
+    /// The response head was received, with the stats of the whole request.
+    fn on_complete(&self, _stats: &RequestStats) {}
+}
+
+/// Where a connection reads the time from when it records stats.
+///
+/// Install one with `stats_clock` on the connection builders, to make the
+/// recorded timestamps deterministic in tests and simulations. Timeouts and
+/// keep-alive timers are not affected, and neither are timestamps recorded
+/// outside the connection, such as [`body::Incoming::last_body_byte_time`].
+///
+/// [`body::Incoming::last_body_byte_time`]: crate::body::Incoming::last_body_byte_time
+pub trait StatsClock: Send + Sync {
+    /// Returns the current instant.
+    fn now(&self) -> Instant;
+}
+
+/// The real clock, reading [`Instant::now`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl StatsClock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A request phase tracked by an [`Aggregator`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Phase {
+    /// Connecting the socket.
+    Connect,
+    /// The TLS handshake.
+    Tls,
+    /// Time to the first byte of the response head.
+    Ttfb,
+    /// The whole request, up until the response head.
+    Total,
+}
+
+/// A step of a single request, as returned by [`RequestStats::phases`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum RequestPhase {
+    /// Waiting for a connection, not counting any time spent setting one up.
+    Queue,
+    /// Resolving the host name.
+    Dns,
+    /// Connecting the socket.
+    Connect,
+    /// The TLS handshake.
+    Tls,
+    /// Writing the request head and body.
+    RequestWrite,
+    /// Waiting for the first byte of the response, after the request was
+    /// written.
+    Waiting,
+    /// Receiving the response, from its first byte to the end of its body.
+    Download,
+}
+
+impl RequestPhase {
+    /// Returns a stable name for this phase, for logs and metrics.
+    pub fn name(&self) -> &'static str {
+        match self {
+            RequestPhase::Queue => "queue",
+            RequestPhase::Dns => "dns",
+            RequestPhase::Connect => "connect",
+            RequestPhase::Tls => "tls",
+            RequestPhase::RequestWrite => "request_write",
+            RequestPhase::Waiting => "waiting",
+            RequestPhase::Download => "download",
+        }
+    }
+}
+
+impl fmt::Display for RequestPhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// When a [`RequestPhase`] started, and how long it took.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PhaseTiming {
+    /// The phase.
+    pub phase: RequestPhase,
+
+    /// The approximate instant the phase started.
+    pub start: Instant,
+
+    /// How long the phase took.
+    pub duration: Duration,
+}
+
+impl PhaseTiming {
+    /// Returns the approximate instant the phase ended.
+    pub fn end(&self) -> Instant {
+        self.start + self.duration
+    }
+}
+
+/// Per-phase histograms built from many [`RequestStats`].
+///
+/// # Example
+///
+/// ```
+/// use hyper::stats::{Aggregator, Phase};
+///
+/// let mut agg = Aggregator::new();
+/// agg.record(&hyper::RequestStats::empty());
+///
+/// assert_eq!(agg.get(Phase::Total).count(), 1);
+/// assert!(agg.percentile(Phase::Total, 99.0).is_some());
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Aggregator {
+    connect: Histogram,
+    tls: Histogram,
+    ttfb: Histogram,
+    total: Histogram,
+}
+
+impl Aggregator {
+    /// Creates an empty aggregator.
+    pub fn new() -> Aggregator {
+        Aggregator::default()
+    }
+
+    /// Records the phases of one request.
+    ///
+    /// Phases that weren't recorded for the request are skipped. Connecting
+    /// is only counted for the request the connection was set up for, not
+    /// for later ones that reused it.
+    pub fn record(&mut self, stats: &RequestStats) {
+        let conn = stats
+            .http_stats
+            .connection_stats
+            .as_ref()
+            .filter(|_| stats.http_stats.connection_request_count <= 1);
+        if let Some(d) = conn.and_then(|c| c.get_connect_duration()) {
+            self.connect.record(d);
+        }
+        if let Some(d) = conn.and_then(|c| c.get_tls_duration()) {
+            self.tls.record(d);
+        }
+        if let Some(d) = stats.get_header_ttfb() {
+            self.ttfb.record(d);
+        }
+        self.total.record(stats.get_request_end());
+    }
+
+    /// Returns the histogram for a phase.
+    pub fn get(&self, phase: Phase) -> &Histogram {
+        match phase {
+            Phase::Connect => &self.connect,
+            Phase::Tls => &self.tls,
+            Phase::Ttfb => &self.ttfb,
+            Phase::Total => &self.total,
+        }
+    }
+
+    /// Returns the given percentile of a phase.
+    ///
+    /// See [`Histogram::percentile`].
+    pub fn percentile(&self, phase: Phase, percentile: f64) -> Option<Duration> {
+        self.get(phase).percentile(percentile)
+    }
+
+    /// Adds everything recorded by `other` into this aggregator.
+    pub fn merge(&mut self, other: &Aggregator) {
+        self.connect.merge(&other.connect);
+        self.tls.merge(&other.tls);
+        self.ttfb.merge(&other.ttfb);
+        self.total.merge(&other.total);
+    }
+}
+
+/// A histogram of durations, with microsecond resolution.
+#[derive(Clone)]
+pub struct Histogram {
+    buckets: Box<[u64]>,
+    count: u64,
+    sum: u64,
+    min: u64,
+    max: u64,
+}
+
+impl Histogram {
+    /// Creates an empty histogram.
+    pub fn new() -> Histogram {
+        Histogram {
+            buckets: vec![0; BUCKETS].into_boxed_slice(),
+            count: 0,
+            sum: 0,
+            min: u64::MAX,
+            max: 0,
+        }
+    }
+
+    /// Records a single duration.
+    pub fn record(&mut self, dur: Duration) {
+        let micros = u64::try_from(dur.as_micros()).unwrap_or(u64::MAX);
+        self.buckets[bucket_index(micros)] += 1;
+        self.count += 1;
+        self.sum = self.sum.saturating_add(micros);
+        self.min = self.min.min(micros);
+        self.max = self.max.max(micros);
+    }
+
+    /// Returns how many durations were recorded.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Returns the smallest recorded duration.
+    pub fn min(&self) -> Option<Duration> {
+        self.non_empty(self.min)
+    }
+
+    /// Returns the largest recorded duration.
+    pub fn max(&self) -> Option<Duration> {
+        self.non_empty(self.max)
+    }
+
+    /// Returns the mean of the recorded durations.
+    pub fn mean(&self) -> Option<Duration> {
+        self.non_empty(self.sum / self.count.max(1))
+    }
+
+    /// Returns the duration below which `percentile` percent of the recorded
+    /// durations fall.
+    ///
+    /// `percentile` is clamped to `0.0..=100.0`. Returns `None` if nothing
+    /// has been recorded.
+    pub fn percentile(&self, percentile: f64) -> Option<Duration> {
+        if self.count == 0 {
+            return None;
+        }
+        let percentile = percentile.clamp(0.0, 100.0);
+        let rank = ((percentile / 100.0 * self.count as f64).ceil() as u64).max(1);
+
+        let mut seen = 0;
+        for (idx, n) in self.buckets.iter().enumerate() {
+            seen += n;
+            if seen >= rank {
+                let value = bucket_upper_bound(idx).max(self.min).min(self.max);
+                return Some(Duration::from_micros(value));
+            }
+        }
+        self.max()
+    }
+
+    /// Adds everything recorded by `other` into this histogram.
+    pub fn merge(&mut self, other: &Histogram) {
+        for (a, b) in self.buckets.iter_mut().zip(other.buckets.iter()) {
+            *a += b;
+        }
+        self.count += other.count;
+        self.sum = self.sum.saturating_add(other.sum);
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+
+    fn non_empty(&self, micros: u64) -> Option<Duration> {
+        if self.count == 0 {
+            None
+        } else {
+            Some(Duration::from_micros(micros))
+        }
+    }
+}
+
+impl Default for Histogram {
+    fn default() -> Histogram {
+        Histogram::new()
+    }
+}
+
+impl fmt::Debug for Histogram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Histogram")
+            .field("count", &self.count)
+            .field("min", &self.min())
+            .field("max", &self.max())
+            .finish()
+    }
+}
+
+fn bucket_index(value: u64) -> usize {
+    if value < SUB_COUNT as u64 {
+        return value as usize;
+    }
+    let exp = 63 - value.leading_zeros();
+    let shift = exp - SUB_BITS;
+    let sub = (value >> shift) as usize - SUB_COUNT;
+    (shift as usize + 1) * SUB_COUNT + sub
+}
+
+fn bucket_upper_bound(idx: usize) -> u64 {
+    if idx < SUB_COUNT {
+        return idx as u64;
+    }
+    let shift = (idx / SUB_COUNT - 1) as u32;
+    let sub = (idx % SUB_COUNT) as u64;
+    let lower = (SUB_COUNT as u64 + sub) << shift;
+    lower + ((1u64 << shift) - 1)
+}
+
+/// The timestamps of a [`RequestStats`], as wall-clock times.
+///
+/// Created by [`RequestStats::anchored`]. Durations between any two of these
+/// timestamps are the same as between the `Instant`s they came from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AnchoredRequestStats {
+    /// Connection-level timestamps.
+    pub http_stats: AnchoredHttpConnectionStats,
+
+    /// Timestamps for all the redirects (save the final request.)
+    pub redirects: Vec<AnchoredRedirectStats>,
+
+    /// Timestamps for every earlier attempt at this request.
+    pub retries: Vec<AnchoredRetryStats>,
+
+    /// When we started this request.
+    pub poll_start: SystemTime,
+
+    /// When we delivered the response to the caller.
+    pub finish: SystemTime,
+}
+
+/// The timestamps of a [`HttpConnectionStats`], as wall-clock times.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AnchoredHttpConnectionStats {
+    /// When the first body byte was received.
+    pub first_body_byte_time: Option<SystemTime>,
+
+    /// When the first header byte was received.
+    pub first_header_byte_time: Option<SystemTime>,
+
+    /// When the final body frame (or trailers) was received.
+    pub last_body_byte_time: Option<SystemTime>,
+
+    /// The connection timestamps, if the connection was not pooled.
+    pub connection_stats: Option<AnchoredConnectionStats>,
+
+    /// When the request started waiting for a connection.
+    pub checkout_start: Option<SystemTime>,
+
+    /// When the connection picked up the request.
+    pub checkout_end: Option<SystemTime>,
+
+    /// When the request head was written out.
+    pub headers_written: Option<SystemTime>,
+
+    /// When the last byte of the request body was written out.
+    pub body_written: Option<SystemTime>,
+
+    /// When the frames of the HTTP/2 stream were received.
+    pub h2_stream: Option<AnchoredH2StreamStats>,
+}
+
+/// The timestamps of a [`H2StreamStats`], as wall-clock times.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AnchoredH2StreamStats {
+    /// When the response HEADERS frame was received.
+    pub headers: Option<SystemTime>,
+
+    /// When the first DATA frame was received.
+    pub first_data: Option<SystemTime>,
+
+    /// When the frame ending the stream was received.
+    pub end_stream: Option<SystemTime>,
+}
+
+/// The timestamps of a [`ConnectionStats`], as wall-clock times.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AnchoredConnectionStats {
+    /// When we started to process this connection.
+    pub start_time: Option<SystemTime>,
+
+    /// When DNS resolution started.
+    pub dns_resolve_start: Option<SystemTime>,
+
+    /// When DNS resolution finished.
+    pub dns_resolve_end: Option<SystemTime>,
+
+    /// When we started establishing the TCP connection.
+    pub connect_start: Option<SystemTime>,
+
+    /// When the TCP connection was established.
+    pub connect_end: Option<SystemTime>,
+
+    /// When the TLS handshake started.
+    pub tls_connect_start: Option<SystemTime>,
+
+    /// When the TLS handshake finished.
+    pub tls_connect_end: Option<SystemTime>,
+}
+
+/// The timestamps of a [`RedirectStats`], as wall-clock times.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AnchoredRedirectStats {
+    /// When the redirect finished.
+    pub finished: SystemTime,
+
+    /// HTTP timestamps.
+    pub connection_stats: AnchoredHttpConnectionStats,
+
+    /// The status code of the redirect response.
+    pub status: StatusCode,
+
+    /// The HTTP version of the redirect response.
+    pub version: Version,
+
+    /// Where the redirect pointed to.
+    pub location: Option<Uri>,
+}
+
+/// The timestamps of a [`RetryStats`], as wall-clock times.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AnchoredRetryStats {
+    /// Why the attempt was retried.
+    pub reason: RetryReason,
+
+    /// When the attempt failed.
+    pub finished: SystemTime,
+
+    /// HTTP timestamps of the failed attempt.
+    pub connection_stats: AnchoredHttpConnectionStats,
+}
+
+/// Maps `Instant`s onto the wall clock, relative to a known pair of both.
+pub(crate) struct Anchor {
+    instant: Instant,
+    system: SystemTime,
+}
+
+impl Anchor {
+    pub(crate) fn new(instant: Instant, system: SystemTime) -> Anchor {
+        Anchor { instant, system }
+    }
+
+    fn at(&self, instant: Instant) -> SystemTime {
+        let system = match instant.checked_duration_since(self.instant) {
+            Some(after) => self.system.checked_add(after),
+            None => self.system.checked_sub(self.instant - instant),
+        };
+        system.unwrap_or(self.system)
+    }
+
+    fn opt(&self, instant: Option<Instant>) -> Option<SystemTime> {
+        instant.map(|i| self.at(i))
+    }
+
+    pub(crate) fn request(&self, stats: &RequestStats) -> AnchoredRequestStats {
+        AnchoredRequestStats {
+            http_stats: self.http(&stats.http_stats),
+            redirects: stats.redirects.iter().map(|r| self.redirect(r)).collect(),
+            retries: stats.retries.iter().map(|r| self.retry(r)).collect(),
+            poll_start: self.at(stats.poll_start),
+            finish: self.at(stats.finish),
+        }
+    }
+
+    fn http(&self, stats: &HttpConnectionStats) -> AnchoredHttpConnectionStats {
+        AnchoredHttpConnectionStats {
+            first_body_byte_time: self.opt(stats.first_body_byte_time),
+            first_header_byte_time: self.opt(stats.first_header_byte_time),
+            last_body_byte_time: self.opt(stats.last_body_byte_time),
+            connection_stats: stats.connection_stats.as_ref().map(|c| self.connection(c)),
+            checkout_start: self.opt(stats.checkout_start),
+            checkout_end: self.opt(stats.checkout_end),
+            headers_written: self.opt(stats.headers_written),
+            body_written: self.opt(stats.body_written),
+            h2_stream: stats.h2_stream.as_ref().map(|s| self.h2_stream(s)),
+        }
+    }
+
+    fn h2_stream(&self, stats: &H2StreamStats) -> AnchoredH2StreamStats {
+        AnchoredH2StreamStats {
+            headers: self.opt(stats.headers),
+            first_data: self.opt(stats.first_data),
+            end_stream: self.opt(stats.end_stream),
+        }
+    }
+
+    fn connection(&self, stats: &ConnectionStats) -> AnchoredConnectionStats {
+        AnchoredConnectionStats {
+            start_time: self.opt(stats.start_time),
+            dns_resolve_start: self.opt(stats.dns_resolve_start),
+            dns_resolve_end: self.opt(stats.dns_resolve_end),
+            connect_start: self.opt(stats.connect_start),
+            connect_end: self.opt(stats.connect_end),
+            tls_connect_start: self.opt(stats.tls_connect_start),
+            tls_connect_end: self.opt(stats.tls_connect_end),
+        }
+    }
+
+    fn redirect(&self, stats: &RedirectStats) -> AnchoredRedirectStats {
+        AnchoredRedirectStats {
+            finished: self.at(stats.finished),
+            connection_stats: self.http(&stats.connection_stats),
+            status: stats.status,
+            version: stats.version,
+            location: stats.location.clone(),
+        }
+    }
+
+    fn retry(&self, stats: &RetryStats) -> AnchoredRetryStats {
+        AnchoredRetryStats {
+            reason: stats.reason,
+            finished: self.at(stats.finished),
+            connection_stats: self.http(&stats.connection_stats),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant, SystemTime};
+
+    use super::{bucket_index, bucket_upper_bound, Aggregator, Histogram, Phase, BUCKETS};
+    use crate::rt::ConnectionStats;
+    use crate::{H2StreamStats, HttpConnectionStats, RequestStats};
+
+    #[test]
+    fn buckets_cover_all_values() {
+        assert_eq!(bucket_index(0), 0);
+        assert_eq!(bucket_index(7), 7);
+        assert_eq!(bucket_index(8), 8);
+        assert_eq!(bucket_index(u64::MAX), BUCKETS - 1);
+        assert_eq!(bucket_upper_bound(BUCKETS - 1), u64::MAX);
+
+        for value in [1, 9, 100, 1_000, 123_456, 10_000_000] {
+            let idx = bucket_index(value);
+            assert!(value <= bucket_upper_bound(idx));
+            assert!(value > bucket_upper_bound(idx - 1));
+        }
+    }
+
+    #[test]
+    fn histogram_percentiles() {
+        let mut hist = Histogram::new();
+        assert_eq!(hist.percentile(50.0), None);
+
+        for ms in 1..=100 {
+            hist.record(Duration::from_millis(ms));
+        }
+
+        assert_eq!(hist.count(), 100);
+        assert_eq!(hist.min(), Some(Duration::from_millis(1)));
+        assert_eq!(hist.max(), Some(Duration::from_millis(100)));
+        assert_eq!(hist.percentile(100.0), Some(Duration::from_millis(100)));
+
+        let p50 = hist.percentile(50.0).unwrap();
+        assert!(p50 >= Duration::from_millis(50), "{:?}", p50);
+        assert!(p50 <= Duration::from_micros(56_250), "{:?}", p50);
+
+        let mut other = Histogram::new();
+        other.record(Duration::from_secs(1));
+        hist.merge(&other);
+        assert_eq!(hist.count(), 101);
+        assert_eq!(hist.max(), Some(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn aggregator_counts_connect_once_per_connection() {
+        let start = Instant::now();
+        let request = |count| RequestStats {
+            http_stats: HttpConnectionStats {
+                connection_stats: Some(ConnectionStats {
+                    connect_start: Some(start),
+                    connect_end: Some(start + Duration::from_millis(5)),
+                    ..Default::default()
+                }),
+                connection_request_count: count,
+                ..Default::default()
+            },
+            ..RequestStats::empty()
+        };
+
+        let mut agg = Aggregator::new();
+        for count in 1..=3 {
+            agg.record(&request(count));
+        }
+
+        assert_eq!(agg.get(Phase::Connect).count(), 1);
+        assert_eq!(agg.get(Phase::Total).count(), 3);
+    }
+
+    #[test]
+    fn anchored_keeps_durations() {
+        let start = Instant::now();
+        let ms = Duration::from_millis;
+        let stats = RequestStats {
+            http_stats: HttpConnectionStats {
+                first_header_byte_time: Some(start + ms(40)),
+                h2_stream: Some(H2StreamStats {
+                    headers: Some(start + ms(40)),
+                    first_data: Some(start + ms(45)),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            redirects: Vec::new(),
+            retries: Vec::new(),
+            poll_start: start,
+            finish: start + ms(50),
+        };
+
+        let wall = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+        let anchored = stats.anchored_at(start + ms(20), wall);
+
+        assert_eq!(anchored.poll_start, wall - ms(20));
+        assert_eq!(anchored.finish, wall + ms(30));
+        assert_eq!(
+            anchored.http_stats.first_header_byte_time,
+            Some(wall + ms(20))
+        );
+        assert_eq!(anchored.http_stats.first_body_byte_time, None);
+        assert_eq!(anchored.http_stats.connection_stats, None);
+        let h2_stream = anchored.http_stats.h2_stream.unwrap();
+        assert_eq!(h2_stream.headers, Some(wall + ms(20)));
+        assert_eq!(h2_stream.first_data, Some(wall + ms(25)));
+        assert_eq!(h2_stream.end_stream, None);
+    }
+}
@@ -45,10 +45,15 @@ use std::fmt;
 use std::future::Future;
 use std::io;
 use std::pin::Pin;
+#[cfg(all(feature = "server", feature = "http1"))]
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use std::task::{Context, Poll};
+use std::time::Instant;
 
-use crate::rt::{Read, ReadBufCursor, Write};
+use crate::rt::{Read, ReadBuf, ReadBufCursor, Write};
+#[cfg(all(feature = "server", feature = "http1"))]
+use atomic_waker::AtomicWaker;
 use bytes::Bytes;
 use tokio::sync::oneshot;
 
@@ -64,6 +69,49 @@ use crate::common::io::Rewind;
 /// into its parts.
 pub struct Upgraded {
     io: Rewind<Box<dyn Io + Send>>,
+    stats: UpgradeStats,
+    tunnel_start: Option<Instant>,
+    #[cfg(all(feature = "server", feature = "http1"))]
+    tracked: Option<TrackedIo>,
+}
+
+/// Stats for an upgraded connection, counted from the moment of the upgrade.
+///
+/// The `RequestStats` of the request that caused the upgrade stop at the
+/// response head, so these pick up where those leave off.
+#[derive(Clone, Copy, Debug)]
+pub struct UpgradeStats {
+    /// The approximate instant the connection was upgraded.
+    pub upgraded_at: Instant,
+
+    /// The approximate instant the upgraded connection was shut down.
+    pub shutdown_at: Option<Instant>,
+
+    /// Number of bytes read from the upgraded connection.
+    pub bytes_read: u64,
+
+    /// Number of bytes written to the upgraded connection.
+    pub bytes_written: u64,
+}
+
+/// Stats for a tunnel the client opened with `CONNECT`.
+#[derive(Clone, Copy, Debug)]
+pub struct TunnelStats {
+    /// The approximate instant the connection picked up the `CONNECT`
+    /// request.
+    pub connect_start: Instant,
+
+    /// The approximate instant the tunnel was established.
+    pub established_at: Instant,
+
+    /// The approximate instant the tunnel was shut down.
+    pub closed_at: Option<Instant>,
+
+    /// Number of bytes received through the tunnel.
+    pub bytes_in: u64,
+
+    /// Number of bytes sent through the tunnel.
+    pub bytes_out: u64,
 }
 
 /// A future for a possible HTTP upgrade.
@@ -92,6 +140,26 @@ pub struct Parts<T> {
     /// You will want to check for any existing bytes if you plan to continue
     /// communicating on the IO object.
     pub read_buf: Bytes,
+    /// Holds up the graceful shutdown of the server connection this upgrade
+    /// came from, if it is tracked by one, while the IO is in use.
+    #[cfg(all(feature = "server", feature = "http1"))]
+    #[cfg_attr(docsrs, doc(cfg(all(feature = "server", feature = "http1"))))]
+    pub shutdown_guard: Option<ShutdownGuard>,
+}
+
+/// Holds up a graceful shutdown of the server connection an upgrade came
+/// from, until dropped.
+///
+/// An [`Upgraded`] connection that a graceful shutdown waits for, see
+/// `upgrade_grace_period` on the HTTP/1 server builder, hands this out in
+/// its [`Parts`] when taken apart with [`downcast`](Upgraded::downcast).
+/// hyper can't close the IO itself anymore then, so keep this for as long as
+/// the IO is in use, and close the IO once
+/// [`poll_close`](ShutdownGuard::poll_close) is ready.
+#[cfg(all(feature = "server", feature = "http1"))]
+#[cfg_attr(docsrs, doc(cfg(all(feature = "server", feature = "http1"))))]
+pub struct ShutdownGuard {
+    tracked: TrackedIo,
 }
 
 /// Gets a pending HTTP upgrade from this message.
@@ -128,6 +196,29 @@ pub(super) fn pending() -> (Pending, OnUpgrade) {
     )
 }
 
+/// Lets a server connection wait for the connection it was upgraded to, and
+/// close it once a graceful shutdown runs out of time.
+#[cfg(all(feature = "server", feature = "http1"))]
+pub(super) struct Tracker {
+    shared: Arc<Tracked>,
+}
+
+/// The `Upgraded` side of a `Tracker`, or that of the `ShutdownGuard` the
+/// `Upgraded` was taken apart into, releasing it when dropped.
+#[cfg(all(feature = "server", feature = "http1"))]
+struct TrackedIo {
+    shared: Arc<Tracked>,
+}
+
+#[cfg(all(feature = "server", feature = "http1"))]
+struct Tracked {
+    released: AtomicBool,
+    closed: AtomicBool,
+    on_release: AtomicWaker,
+    read_waker: AtomicWaker,
+    write_waker: AtomicWaker,
+}
+
 // ===== impl Upgraded =====
 
 impl Upgraded {
@@ -141,9 +232,57 @@ impl Upgraded {
     {
         Upgraded {
             io: Rewind::new_buffered(Box::new(io), read_buf),
+            stats: UpgradeStats::new(),
+            tunnel_start: None,
+            #[cfg(all(feature = "server", feature = "http1"))]
+            tracked: None,
         }
     }
 
+    /// Starts tracking this connection, for the server connection it came
+    /// from.
+    #[cfg(all(feature = "server", feature = "http1"))]
+    pub(super) fn track(&mut self) -> Tracker {
+        let shared = Arc::new(Tracked {
+            released: AtomicBool::new(false),
+            closed: AtomicBool::new(false),
+            on_release: AtomicWaker::new(),
+            read_waker: AtomicWaker::new(),
+            write_waker: AtomicWaker::new(),
+        });
+        self.tracked = Some(TrackedIo {
+            shared: shared.clone(),
+        });
+        Tracker { shared }
+    }
+
+    /// Marks this connection as a tunnel, opened by a `CONNECT` request
+    /// that was picked up at `start`.
+    #[cfg(all(feature = "client", any(feature = "http1", feature = "http2")))]
+    pub(super) fn set_tunnel_start(&mut self, start: Instant) {
+        self.tunnel_start = Some(start);
+    }
+
+    /// Returns the stats recorded since this connection was upgraded.
+    pub fn stats(&self) -> UpgradeStats {
+        self.stats
+    }
+
+    /// Returns the stats of the tunnel, if this connection is a client
+    /// `CONNECT` tunnel.
+    ///
+    /// This is `None` for other upgrades, and if stats collection was
+    /// disabled on the client connection.
+    pub fn tunnel_stats(&self) -> Option<TunnelStats> {
+        self.tunnel_start.map(|connect_start| TunnelStats {
+            connect_start,
+            established_at: self.stats.upgraded_at,
+            closed_at: self.stats.shutdown_at,
+            bytes_in: self.stats.bytes_read,
+            bytes_out: self.stats.bytes_written,
+        })
+    }
+
     /// Tries to downcast the internal trait object to the type passed.
     ///
     /// On success, returns the downcasted parts. On error, returns the
@@ -154,21 +293,60 @@ impl Upgraded {
             Ok(t) => Ok(Parts {
                 io: *t,
                 read_buf: buf,
+                #[cfg(all(feature = "server", feature = "http1"))]
+                shutdown_guard: self.tracked.map(|tracked| ShutdownGuard { tracked }),
             }),
             Err(io) => Err(Upgraded {
                 io: Rewind::new_buffered(io, buf),
+                stats: self.stats,
+                tunnel_start: self.tunnel_start,
+                #[cfg(all(feature = "server", feature = "http1"))]
+                tracked: self.tracked,
             }),
         }
     }
+
+    /// Fails once a graceful shutdown has closed this connection, dropping
+    /// the transport so that it is closed right away.
+    #[cfg(all(feature = "server", feature = "http1"))]
+    fn check_tracked(&mut self, read: bool, cx: &mut Context<'_>) -> io::Result<()> {
+        let tracked = match self.tracked {
+            Some(ref tracked) => tracked,
+            None => return Ok(()),
+        };
+        let waker = if read {
+            &tracked.shared.read_waker
+        } else {
+            &tracked.shared.write_waker
+        };
+        let res = tracked.check(waker, cx);
+        if res.is_err() {
+            self.io = Rewind::new_buffered(Box::new(Closed), Bytes::new());
+        }
+        res
+    }
 }
 
 impl Read for Upgraded {
     fn poll_read(
         mut self: Pin<&mut Self>,
         cx: &mut Context<'_>,
-        buf: ReadBufCursor<'_>,
+        mut buf: ReadBufCursor<'_>,
     ) -> Poll<io::Result<()>> {
-        Pin::new(&mut self.io).poll_read(cx, buf)
+        #[cfg(all(feature = "server", feature = "http1"))]
+        self.check_tracked(true, cx)?;
+        // Read through a buffer of our own, to see how much was filled.
+        let n = unsafe {
+            let mut tbuf = ReadBuf::uninit(buf.as_mut());
+            match Pin::new(&mut self.io).poll_read(cx, tbuf.unfilled()) {
+                Poll::Ready(Ok(())) => tbuf.filled().len(),
+                other => return other,
+            }
+        };
+        // SAFETY: the inner read initialized the `n` bytes it filled.
+        unsafe { buf.advance(n) };
+        self.stats.bytes_read += n as u64;
+        Poll::Ready(Ok(()))
     }
 }
 
@@ -178,7 +356,13 @@ impl Write for Upgraded {
         cx: &mut Context<'_>,
         buf: &[u8],
     ) -> Poll<io::Result<usize>> {
-        Pin::new(&mut self.io).poll_write(cx, buf)
+        #[cfg(all(feature = "server", feature = "http1"))]
+        self.check_tracked(false, cx)?;
+        let res = Pin::new(&mut self.io).poll_write(cx, buf);
+        if let Poll::Ready(Ok(n)) = res {
+            self.stats.bytes_written += n as u64;
+        }
+        res
     }
 
     fn poll_write_vectored(
@@ -186,15 +370,29 @@ impl Write for Upgraded {
         cx: &mut Context<'_>,
         bufs: &[io::IoSlice<'_>],
     ) -> Poll<io::Result<usize>> {
-        Pin::new(&mut self.io).poll_write_vectored(cx, bufs)
+        #[cfg(all(feature = "server", feature = "http1"))]
+        self.check_tracked(false, cx)?;
+        let res = Pin::new(&mut self.io).poll_write_vectored(cx, bufs);
+        if let Poll::Ready(Ok(n)) = res {
+            self.stats.bytes_written += n as u64;
+        }
+        res
     }
 
     fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        #[cfg(all(feature = "server", feature = "http1"))]
+        self.check_tracked(false, cx)?;
         Pin::new(&mut self.io).poll_flush(cx)
     }
 
     fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
-        Pin::new(&mut self.io).poll_shutdown(cx)
+        let res = Pin::new(&mut self.io).poll_shutdown(cx);
+        if let Poll::Ready(Ok(())) = res {
+            if self.stats.shutdown_at.is_none() {
+                self.stats.shutdown_at = Some(Instant::now());
+            }
+        }
+        res
     }
 
     fn is_write_vectored(&self) -> bool {
@@ -208,6 +406,71 @@ impl fmt::Debug for Upgraded {
     }
 }
 
+// ===== impl UpgradeStats =====
+
+impl UpgradeStats {
+    #[cfg(all(
+        any(feature = "client", feature = "server"),
+        any(feature = "http1", feature = "http2")
+    ))]
+    fn new() -> Self {
+        UpgradeStats {
+            upgraded_at: Instant::now(),
+            shutdown_at: None,
+            bytes_read: 0,
+            bytes_written: 0,
+        }
+    }
+
+    /// Returns how long the connection has been upgraded for, up until it
+    /// was shut down.
+    pub fn get_lifetime(&self) -> core::time::Duration {
+        self.shutdown_at
+            .unwrap_or_else(Instant::now)
+            .saturating_duration_since(self.upgraded_at)
+    }
+}
+
+impl fmt::Display for UpgradeStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_fmt(format_args!(
+            "upgraded: {:?}, bytes read/written: {}/{}\n",
+            self.get_lifetime(),
+            self.bytes_read,
+            self.bytes_written
+        ))
+    }
+}
+
+// ===== impl TunnelStats =====
+
+impl TunnelStats {
+    /// Returns how long it took to establish the tunnel.
+    pub fn get_establish_duration(&self) -> core::time::Duration {
+        self.established_at
+            .saturating_duration_since(self.connect_start)
+    }
+
+    /// Returns how long the tunnel has been open, up until it was shut down.
+    pub fn get_duration(&self) -> core::time::Duration {
+        self.closed_at
+            .unwrap_or_else(Instant::now)
+            .saturating_duration_since(self.established_at)
+    }
+}
+
+impl fmt::Display for TunnelStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_fmt(format_args!(
+            "tunnel: established in {:?}, open {:?}, bytes in/out: {}/{}\n",
+            self.get_establish_duration(),
+            self.get_duration(),
+            self.bytes_in,
+            self.bytes_out
+        ))
+    }
+}
+
 // ===== impl OnUpgrade =====
 
 impl OnUpgrade {
@@ -268,6 +531,129 @@ impl Pending {
     }
 }
 
+// ===== impl Tracker =====
+
+#[cfg(all(feature = "server", feature = "http1"))]
+impl Tracker {
+    /// Resolves once the upgraded connection has been dropped, along with
+    /// the `ShutdownGuard` it may have been taken apart into.
+    pub(super) fn poll_released(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.shared.on_release.register(cx.waker());
+        if self.shared.released.load(Ordering::Acquire) {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+
+    /// Makes reads and writes on the upgraded connection fail and drop its
+    /// transport, waking any task waiting on them, or any task waiting on
+    /// the `ShutdownGuard` of a taken apart connection.
+    pub(super) fn force_close(&self) {
+        self.shared.closed.store(true, Ordering::Release);
+        self.shared.read_waker.wake();
+        self.shared.write_waker.wake();
+    }
+}
+
+#[cfg(all(feature = "server", feature = "http1"))]
+impl TrackedIo {
+    fn check(&self, waker: &AtomicWaker, cx: &mut Context<'_>) -> io::Result<()> {
+        waker.register(cx.waker());
+        if self.is_closed() {
+            Err(closed_by_shutdown())
+        } else {
+            Ok(())
+        }
+    }
+
+    fn is_closed(&self) -> bool {
+        self.shared.closed.load(Ordering::Acquire)
+    }
+}
+
+#[cfg(all(feature = "server", feature = "http1"))]
+impl Drop for TrackedIo {
+    fn drop(&mut self) {
+        self.shared.released.store(true, Ordering::Release);
+        self.shared.on_release.wake();
+    }
+}
+
+// ===== impl ShutdownGuard =====
+
+#[cfg(all(feature = "server", feature = "http1"))]
+impl ShutdownGuard {
+    /// Returns whether the graceful shutdown has run out of time, and the IO
+    /// should be closed.
+    pub fn is_closed(&self) -> bool {
+        self.tracked.is_closed()
+    }
+
+    /// Resolves once the graceful shutdown has run out of time, and the IO
+    /// should be closed.
+    pub fn poll_close(&self, cx: &mut Context<'_>) -> Poll<()> {
+        // Taken apart, the upgraded connection doesn't use this waker
+        // anymore.
+        self.tracked.shared.read_waker.register(cx.waker());
+        if self.is_closed() {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+#[cfg(all(feature = "server", feature = "http1"))]
+impl fmt::Debug for ShutdownGuard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ShutdownGuard")
+            .field("closed", &self.is_closed())
+            .finish()
+    }
+}
+
+// ===== impl Closed =====
+
+/// Stands in for the transport of an upgraded connection a graceful shutdown
+/// has closed.
+#[cfg(all(feature = "server", feature = "http1"))]
+struct Closed;
+
+#[cfg(all(feature = "server", feature = "http1"))]
+impl Read for Closed {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        _: &mut Context<'_>,
+        _: ReadBufCursor<'_>,
+    ) -> Poll<io::Result<()>> {
+        Poll::Ready(Err(closed_by_shutdown()))
+    }
+}
+
+#[cfg(all(feature = "server", feature = "http1"))]
+impl Write for Closed {
+    fn poll_write(self: Pin<&mut Self>, _: &mut Context<'_>, _: &[u8]) -> Poll<io::Result<usize>> {
+        Poll::Ready(Err(closed_by_shutdown()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(all(feature = "server", feature = "http1"))]
+fn closed_by_shutdown() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::ConnectionAborted,
+        "upgraded connection closed by graceful shutdown",
+    )
+}
+
 // ===== impl UpgradeExpected =====
 
 /// Error cause returned when an upgrade was expected but canceled
@@ -373,6 +759,27 @@ mod tests {
         upgraded.downcast::<Mock>().unwrap();
     }
 
+    #[test]
+    fn upgraded_stats_count_bytes() {
+        let mut upgraded = Upgraded::new(Mock, Bytes::from_static(b"hello"));
+        let waker = futures_util::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+
+        let mut raw = [0u8; 16];
+        let mut buf = ReadBuf::new(&mut raw);
+        let res = Pin::new(&mut upgraded).poll_read(&mut cx, buf.unfilled());
+        assert!(matches!(res, Poll::Ready(Ok(()))));
+        assert_eq!(buf.filled(), b"hello");
+
+        let res = Pin::new(&mut upgraded).poll_write(&mut cx, b"world!");
+        assert!(matches!(res, Poll::Ready(Ok(6))));
+
+        let stats = upgraded.stats();
+        assert_eq!(stats.bytes_read, 5);
+        assert_eq!(stats.bytes_written, 6);
+        assert!(stats.shutdown_at.is_none());
+    }
+
     // TODO: replace with tokio_test::io when it can test write_buf
     struct Mock;
 
@@ -704,6 +704,50 @@ test! {
             body: None,
 }
 
+test! {
+    name: client_post_req_body_chunked_with_undeclared_trailer,
+
+    server:
+        expected: "\
+            POST / HTTP/1.1\r\n\
+            trailer: chunky-trailer\r\n\
+            host: {addr}\r\n\
+            transfer-encoding: chunked\r\n\
+            \r\n\
+            5\r\n\
+            hello\r\n\
+            0\r\n\
+            chunky-trailer: header data\r\n\
+            \r\n\
+            ",
+        reply: REPLY_OK,
+
+    client:
+        request: {
+            method: POST,
+            url: "http://{addr}/",
+            headers: {
+                "trailer" => "chunky-trailer",
+            },
+            body_stream_with_trailers: (
+                (futures_util::stream::once(async { Ok::<_, Infallible>(Bytes::from("hello"))})),
+                HeaderMap::from_iter(vec![
+                    (
+                        HeaderName::from_static("chunky-trailer"),
+                        HeaderValue::from_static("header data"),
+                    ),
+                    (
+                        HeaderName::from_static("undeclared-trailer"),
+                        HeaderValue::from_static("dropped"),
+                    ),
+                ].into_iter())),
+        },
+        response:
+            status: OK,
+            headers: {},
+            body: None,
+}
+
 test! {
     name: client_res_body_chunked_with_trailer,
 
@@ -1484,6 +1528,30 @@ test! {
             body: None,
 }
 
+test! {
+    name: client_rejects_obs_fold_headers_by_default,
+
+    server:
+        expected: "\
+            GET / HTTP/1.1\r\n\
+            host: {addr}\r\n\
+            \r\n\
+            ",
+        reply: "\
+            HTTP/1.1 200 OK\r\n\
+            Content-Length: 0\r\n\
+            Fold: just\r\n some\r\n\t folding\r\n\
+            \r\n\
+            ",
+
+    client:
+        request: {
+            method: GET,
+            url: "http://{addr}/",
+        },
+        error: |err| err.is_parse(),
+}
+
 mod conn {
     use std::error::Error;
     use std::io::{self, Read, Write};
@@ -1491,7 +1559,7 @@ mod conn {
     use std::pin::Pin;
     use std::task::{Context, Poll};
     use std::thread;
-    use std::time::Duration;
+    use std::time::{Duration, Instant};
 
     use bytes::{Buf, Bytes};
     use futures_channel::{mpsc, oneshot};
@@ -1504,7 +1572,7 @@ mod conn {
     use hyper::body::{Body, Frame};
     use hyper::client::conn;
     use hyper::upgrade::OnUpgrade;
-    use hyper::{Method, Request, Response, StatusCode};
+    use hyper::{Method, Request, Response, StatusCode, Uri};
 
     use super::{concat, s, support, tcp_connect, FutureHyperExt};
 
@@ -1622,6 +1690,53 @@ mod conn {
         future::join(server, client).await;
     }
 
+    #[tokio::test]
+    async fn get_raw_headers() {
+        let (listener, addr) = setup_tk_test_server().await;
+
+        let server = async move {
+            let mut sock = listener.accept().await.unwrap().0;
+            let mut buf = [0; 4096];
+            let n = sock.read(&mut buf).await.expect("read 1");
+            assert_eq!(s(&buf[..n]), "GET /a HTTP/1.1\r\n\r\n");
+
+            sock.write_all(b"HTTP/1.1 200 OK\r\nX-A: 1\r\nx-a:  2\r\nContent-Length: 0\r\n\r\n")
+                .await
+                .unwrap();
+        };
+
+        let client = async move {
+            let tcp = tcp_connect(&addr).await.expect("connect");
+            let (mut client, conn) = conn::http1::Builder::new()
+                .preserve_raw_headers(true)
+                .handshake(tcp)
+                .await
+                .expect("handshake");
+
+            tokio::task::spawn(async move {
+                conn.await.expect("http conn");
+            });
+
+            let req = Request::builder()
+                .uri("/a")
+                .body(Empty::<Bytes>::new())
+                .unwrap();
+            let res = client.send_request(req).await.expect("send_request").1;
+            assert_eq!(res.headers().get_all("x-a").iter().count(), 2);
+            let raw = res
+                .extensions()
+                .get::<hyper::ext::RawHeaders>()
+                .expect("raw headers are present");
+            let lines = raw.iter().map(|line| line.as_bytes()).collect::<Vec<_>>();
+            assert_eq!(
+                lines,
+                [&b"X-A: 1\r\n"[..], b"x-a:  2\r\n", b"Content-Length: 0\r\n"]
+            );
+        };
+
+        future::join(server, client).await;
+    }
+
     #[test]
     fn incoming_content_length() {
         let (server, addr) = setup_std_test_server();
@@ -1769,6 +1884,171 @@ mod conn {
         rt.block_on(future::join(res, rx).map(|r| r.0)).unwrap();
     }
 
+    #[tokio::test]
+    async fn uri_absolute_form_to_proxy() {
+        let (listener, addr) = setup_tk_test_server().await;
+
+        let server = tokio::spawn(async move {
+            let mut sock = listener.accept().await.unwrap().0;
+            let head = read_head(&mut sock).await;
+            // The target is sent as given, as a forward proxy needs it.
+            let expected = "GET http://example.com:8080/path?q=1 HTTP/1.1\r\n\
+                            host: example.com:8080\r\n\r\n";
+            assert_eq!(s(&head), expected);
+            sock.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                .await
+                .unwrap();
+        });
+
+        let io = tcp_connect(&addr).await.expect("tcp connect");
+        let (mut client, conn) = conn::http1::handshake(io).await.expect("http handshake");
+        tokio::spawn(async move {
+            let _ = conn.await;
+        });
+
+        let req = Request::get("http://example.com:8080/path?q=1")
+            .header("host", "example.com:8080")
+            .body(Empty::<Bytes>::new())
+            .unwrap();
+        let (_stats, res) = client.send_request(req).await.expect("send_request");
+        assert_eq!(res.status(), StatusCode::OK);
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn http1_verbatim_framing() {
+        let (listener, addr) = setup_tk_test_server().await;
+
+        let server = tokio::spawn(async move {
+            let mut sock = listener.accept().await.unwrap().0;
+            let head = read_head(&mut sock).await;
+            let expected = "POST / HTTP/1.1\r\n\
+                            content-length: 5\r\n\
+                            transfer-encoding: chunked\r\n\r\n";
+            assert_eq!(s(&head), expected);
+            let mut body = [0; 15];
+            sock.read_exact(&mut body).await.expect("read body");
+            assert_eq!(s(&body), "5\r\nhello\r\n0\r\n\r\n");
+            sock.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                .await
+                .unwrap();
+        });
+
+        let io = tcp_connect(&addr).await.expect("tcp connect");
+        let (mut client, conn) = conn::http1::handshake(io).await.expect("http handshake");
+        tokio::spawn(async move {
+            let _ = conn.await;
+        });
+
+        let mut req = Request::post("/")
+            .header("content-length", "5")
+            .header("transfer-encoding", "chunked")
+            .body(Full::new(Bytes::from("hello")))
+            .unwrap();
+        hyper::ext::set_verbatim_framing(&mut req);
+        let (_stats, res) = client.send_request(req).await.expect("send_request");
+        assert_eq!(res.status(), StatusCode::OK);
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn http1_drain_peer_closed() {
+        let (listener, addr) = setup_tk_test_server().await;
+
+        let server = tokio::spawn(async move {
+            let mut sock = listener.accept().await.unwrap().0;
+            let _ = read_head(&mut sock).await;
+            sock.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                .await
+                .unwrap();
+        });
+
+        let io = tcp_connect(&addr).await.expect("tcp connect");
+        let (mut client, conn) = conn::http1::handshake(io).await.expect("http handshake");
+        let drain = tokio::spawn(conn.drain());
+
+        let req = Request::get("/").body(Empty::<Bytes>::new()).unwrap();
+        let (_stats, res) = client.send_request(req).await.expect("send_request");
+        assert_eq!(res.status(), StatusCode::OK);
+        server.await.unwrap();
+
+        let reason = drain.await.unwrap();
+        assert!(
+            matches!(reason, conn::http1::CloseReason::PeerClosed),
+            "{:?}",
+            reason
+        );
+        drop(client);
+    }
+
+    #[tokio::test]
+    async fn http1_drain_keep_alive_disabled() {
+        let (listener, addr) = setup_tk_test_server().await;
+
+        let server = tokio::spawn(async move {
+            let mut sock = listener.accept().await.unwrap().0;
+            let _ = read_head(&mut sock).await;
+            sock.write_all(b"HTTP/1.1 200 OK\r\nconnection: close\r\ncontent-length: 0\r\n\r\n")
+                .await
+                .unwrap();
+            // Hold the socket open, the client closes it.
+            let mut buf = [0; 16];
+            let _ = sock.read(&mut buf).await;
+        });
+
+        let io = tcp_connect(&addr).await.expect("tcp connect");
+        let (mut client, conn) = conn::http1::handshake(io).await.expect("http handshake");
+        let drain = tokio::spawn(conn.drain());
+
+        let req = Request::get("/").body(Empty::<Bytes>::new()).unwrap();
+        let (_stats, res) = client.send_request(req).await.expect("send_request");
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let reason = drain.await.unwrap();
+        assert!(
+            matches!(reason, conn::http1::CloseReason::KeepAliveDisabled),
+            "{:?}",
+            reason
+        );
+        server.await.unwrap();
+        drop(client);
+    }
+
+    #[tokio::test]
+    async fn http1_drain_graceful_shutdown() {
+        let (listener, addr) = setup_tk_test_server().await;
+
+        let server = tokio::spawn(async move {
+            let mut sock = listener.accept().await.unwrap().0;
+            let _ = read_head(&mut sock).await;
+            sock.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                .await
+                .unwrap();
+            let mut buf = [0; 16];
+            let _ = sock.read(&mut buf).await;
+        });
+
+        let io = tcp_connect(&addr).await.expect("tcp connect");
+        let (mut client, mut conn) = conn::http1::handshake(io).await.expect("http handshake");
+
+        let req = Request::get("/").body(Empty::<Bytes>::new()).unwrap();
+        let res = tokio::select! {
+            res = client.send_request(req) => res.expect("send_request").1,
+            _ = &mut conn => panic!("connection closed early"),
+        };
+        assert_eq!(res.status(), StatusCode::OK);
+
+        conn.graceful_shutdown();
+        let reason = conn.drain().await;
+        assert!(
+            matches!(reason, conn::http1::CloseReason::LocalShutdown),
+            "{:?}",
+            reason
+        );
+        server.await.unwrap();
+        drop(client);
+    }
+
     #[test]
     fn http1_conn_coerces_http2_request() {
         let (server, addr) = setup_std_test_server();
@@ -2139,62 +2419,672 @@ mod conn {
     }
 
     #[tokio::test]
-    async fn test_try_send_request() {
-        use std::future::Future;
-        let (done_tx, done_rx) = tokio::sync::oneshot::channel::<()>();
-        let (io_srv, io_cli) = tokio_test::io::Builder::new()
-            .write(b"GET / HTTP/1.1\r\n\r\n")
-            .read(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
-            .build_with_handle();
+    async fn client_on_stats_callback() {
+        use std::sync::{Arc, Mutex};
+        let (server, addr) = setup_std_test_server();
 
-        tokio::spawn(async move {
-            let _io = io_cli;
-            let _ = done_rx.await;
+        thread::spawn(move || {
+            let mut sock = server.accept().unwrap().0;
+            sock.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            sock.set_write_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            let mut buf = [0; 4096];
+            sock.read(&mut buf).expect("read 1");
+            sock.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 5\r\n\r\nhello")
+                .unwrap();
         });
 
-        // make polling fair by putting both in spawns
+        let tcp = tcp_connect(&addr).await.unwrap();
+
+        let reported = Arc::new(Mutex::new(Vec::new()));
+        let reported2 = reported.clone();
+        let (mut client, conn) = conn::http1::Builder::new()
+            .on_stats(move |stats| reported2.lock().unwrap().push(stats))
+            .handshake(tcp)
+            .await
+            .unwrap();
+
         tokio::spawn(async move {
-            let io = TokioIo::new(io_srv);
-            let (mut client, mut conn) = conn::http1::Builder::new()
-                .handshake::<_, Empty<Bytes>>(io)
-                .await
-                .expect("http handshake");
+            let _ = conn.await;
+        });
 
-            // get the conn ready
-            assert!(
-                future::poll_fn(|cx| Poll::Ready(Pin::new(&mut conn).poll(cx)))
-                    .await
-                    .is_pending()
-            );
-            assert!(client.is_ready());
+        let req = Request::builder()
+            .uri("/a")
+            .body(Empty::<Bytes>::new())
+            .unwrap();
+        // drop the response without reading the body
+        let _ = client.send_request(req).await.expect("send_request");
 
-            // use the connection once
-            let mut fut1 = std::pin::pin!(client.send_request(http::Request::new(Empty::new())));
-            let _res1 = future::poll_fn(|cx| loop {
-                if let Poll::Ready(res) = fut1.as_mut().poll(cx) {
-                    return Poll::Ready(res);
-                }
-                return match Pin::new(&mut conn).poll(cx) {
-                    Poll::Ready(_) => panic!("ruh roh"),
-                    Poll::Pending => Poll::Pending,
-                };
-            })
-            .await
-            .expect("resp 1");
+        let reported = reported.lock().unwrap();
+        assert_eq!(reported.len(), 1);
+        assert!(reported[0].finish >= reported[0].poll_start);
+    }
 
-            assert!(client.is_ready());
+    #[tokio::test]
+    async fn client_on_stats_callback_after_body() {
+        use std::sync::{Arc, Mutex};
+        let (server, addr) = setup_std_test_server();
 
-            // simulate the server dropping the conn
-            let _ = done_tx.send(());
-            // let the server task die
-            tokio::task::yield_now().await;
+        thread::spawn(move || {
+            let mut sock = server.accept().unwrap().0;
+            sock.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            sock.set_write_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            let mut buf = [0; 4096];
+            sock.read(&mut buf).expect("read 1");
+            sock.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 5\r\n\r\nhello")
+                .unwrap();
+            sock.read(&mut buf).expect("read 2");
+            // close without responding to the second request
+        });
 
-            let mut fut2 =
-                std::pin::pin!(client.try_send_request(http::Request::new(Empty::new())));
-            let poll1 = future::poll_fn(|cx| Poll::Ready(fut2.as_mut().poll(cx))).await;
-            assert!(poll1.is_pending(), "not already known to error");
+        let tcp = tcp_connect(&addr).await.unwrap();
 
-            let mut conn_opt = Some(conn);
+        let reported = Arc::new(Mutex::new(Vec::new()));
+        let reported2 = reported.clone();
+        let (mut client, conn) = conn::http1::Builder::new()
+            .on_stats(move |stats| reported2.lock().unwrap().push(stats))
+            .handshake(tcp)
+            .await
+            .unwrap();
+
+        tokio::spawn(async move {
+            let _ = conn.await;
+        });
+
+        let req = Request::builder()
+            .uri("/a")
+            .body(Empty::<Bytes>::new())
+            .unwrap();
+        let (_, res) = client.send_request(req).await.expect("send_request");
+        assert!(reported.lock().unwrap().is_empty());
+
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "hello");
+        {
+            let reported = reported.lock().unwrap();
+            assert_eq!(reported.len(), 1);
+            assert_eq!(reported[0].http_stats.response_body_bytes, 5);
+            assert!(reported[0].http_stats.last_body_byte_time.is_some());
+        }
+
+        let req = Request::builder()
+            .uri("/b")
+            .body(Empty::<Bytes>::new())
+            .unwrap();
+        client.send_request(req).await.unwrap_err();
+        assert_eq!(reported.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn client_on_stats_with_instrumented_body() {
+        use hyper::body::{Body, Instrumented};
+        use std::sync::{Arc, Mutex};
+        let (server, addr) = setup_std_test_server();
+
+        thread::spawn(move || {
+            let mut sock = server.accept().unwrap().0;
+            sock.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            sock.set_write_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            let mut buf = [0; 4096];
+            sock.read(&mut buf).expect("read 1");
+            sock.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 5\r\n\r\nhello")
+                .unwrap();
+            // keep the connection open
+            let _ = sock.read(&mut buf);
+        });
+
+        let tcp = tcp_connect(&addr).await.unwrap();
+
+        let reported = Arc::new(Mutex::new(Vec::new()));
+        let reported2 = reported.clone();
+        let (mut client, conn) = conn::http1::Builder::new()
+            .on_stats(move |stats| reported2.lock().unwrap().push(stats))
+            .handshake(tcp)
+            .await
+            .unwrap();
+
+        tokio::spawn(async move {
+            let _ = conn.await;
+        });
+
+        let req = Request::builder()
+            .uri("/a")
+            .body(Empty::<Bytes>::new())
+            .unwrap();
+        let (_, res) = client.send_request(req).await.expect("send_request");
+        let mut progress = Vec::new();
+        let mut body = Instrumented::new(res.into_body(), |bytes, _| progress.push(bytes));
+
+        // stop once the body says it has ended, without polling for the end
+        while !body.is_end_stream() {
+            body.frame().await.unwrap().unwrap();
+        }
+        assert!(body.last_body_byte_time().is_some());
+
+        let reported = reported.lock().unwrap();
+        assert_eq!(reported.len(), 1);
+        let stats = reported[0].http_stats;
+        assert_eq!(stats.response_body_bytes, 5);
+        assert!(stats.first_body_byte_time.is_some());
+        assert!(stats.last_body_byte_time.is_some());
+        drop(reported);
+        drop(body);
+        assert_eq!(progress, [5]);
+    }
+
+    #[tokio::test]
+    async fn client_stats_pool_wait() {
+        let (server, addr) = setup_std_test_server();
+
+        thread::spawn(move || {
+            let mut sock = server.accept().unwrap().0;
+            sock.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            sock.set_write_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            let mut buf = [0; 4096];
+            sock.read(&mut buf).expect("read 1");
+            sock.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                .unwrap();
+        });
+
+        let tcp = tcp_connect(&addr).await.unwrap();
+        let (mut client, conn) = conn::http1::handshake(tcp).await.unwrap();
+
+        let req = Request::builder()
+            .uri("/a")
+            .body(Empty::<Bytes>::new())
+            .unwrap();
+        // queue the request before the connection task is even running
+        let res = client.send_request(req);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        tokio::spawn(async move {
+            let _ = conn.await;
+        });
+
+        let (stats, _res) = res.await.expect("send_request");
+        let wait = stats.get_pool_wait().expect("pool wait recorded");
+        assert!(wait >= Duration::from_millis(10), "{:?}", wait);
+    }
+
+    #[tokio::test]
+    async fn client_stats_request_written() {
+        let (server, addr) = setup_std_test_server();
+
+        thread::spawn(move || {
+            let mut sock = server.accept().unwrap().0;
+            sock.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            sock.set_write_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            let mut buf = [0; 4096];
+            let mut n = 0;
+            while !buf[..n].ends_with(b"hello") {
+                n += sock.read(&mut buf[n..]).expect("read");
+            }
+            sock.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                .unwrap();
+        });
+
+        let tcp = tcp_connect(&addr).await.unwrap();
+        let (mut client, conn) = conn::http1::handshake(tcp).await.unwrap();
+        tokio::spawn(async move {
+            let _ = conn.await;
+        });
+
+        let req = Request::builder()
+            .method("POST")
+            .uri("/a")
+            .body(Full::<Bytes>::from("hello"))
+            .unwrap();
+        let (stats, _res) = client.send_request(req).await.expect("send_request");
+
+        let headers_written = stats.headers_written.expect("headers_written");
+        let body_written = stats.body_written.expect("body_written");
+        assert!(headers_written <= body_written);
+        assert!(stats.get_upload_duration().is_some());
+    }
+
+    #[tokio::test]
+    async fn client_stats_byte_sizes() {
+        const HEAD: &[u8] = b"HTTP/1.1 200 OK\r\ncontent-length: 5\r\n\r\n";
+        let (server, addr) = setup_std_test_server();
+        let (tx, rx) = oneshot::channel();
+
+        thread::spawn(move || {
+            let mut sock = server.accept().unwrap().0;
+            sock.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            sock.set_write_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            let mut buf = [0; 4096];
+            let mut n = 0;
+            while !buf[..n].ends_with(b"hello") {
+                n += sock.read(&mut buf[n..]).expect("read");
+            }
+            sock.write_all(HEAD).unwrap();
+            sock.write_all(b"world").unwrap();
+            let _ = tx.send(n);
+        });
+
+        let tcp = tcp_connect(&addr).await.unwrap();
+        let (mut client, conn) = conn::http1::handshake(tcp).await.unwrap();
+        tokio::spawn(async move {
+            let _ = conn.await;
+        });
+
+        let req = Request::builder()
+            .method("POST")
+            .uri("/a")
+            .body(Full::<Bytes>::from("hello"))
+            .unwrap();
+        let (mut stats, res) = client.send_request(req).await.expect("send_request");
+        let mut body = res.into_body();
+        while let Some(frame) = body.frame().await {
+            frame.expect("body frame");
+        }
+        stats.response_body_bytes = body.body_bytes_received();
+
+        let sent = rx.await.expect("server sent request size") as u64;
+        assert_eq!(stats.request_header_bytes + stats.request_body_bytes, sent);
+        assert_eq!(stats.request_body_bytes, 5);
+        assert_eq!(stats.response_header_bytes, HEAD.len() as u64);
+        assert_eq!(stats.response_body_bytes, 5);
+
+        // counted even though the transport has no stats of its own
+        let conn = stats.connection_stats.expect("connection stats");
+        assert_eq!(conn.bytes_written, sent);
+        assert!(conn.bytes_read >= HEAD.len() as u64, "{:?}", conn);
+    }
+
+    #[tokio::test]
+    async fn client_stats_clock() {
+        struct FixedClock(std::time::Instant);
+
+        impl hyper::stats::StatsClock for FixedClock {
+            fn now(&self) -> std::time::Instant {
+                self.0
+            }
+        }
+
+        let (server, addr) = setup_std_test_server();
+
+        thread::spawn(move || {
+            let mut sock = server.accept().unwrap().0;
+            sock.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            sock.set_write_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            let mut buf = [0; 4096];
+            sock.read(&mut buf).expect("read 1");
+            sock.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                .unwrap();
+        });
+
+        let fixed = std::time::Instant::now() - Duration::from_secs(60);
+        let tcp = tcp_connect(&addr).await.unwrap();
+        let (mut client, conn) = conn::http1::Builder::new()
+            .stats_clock(FixedClock(fixed))
+            .handshake(tcp)
+            .await
+            .unwrap();
+        tokio::spawn(async move {
+            let _ = conn.await;
+        });
+
+        let req = Request::builder()
+            .uri("/a")
+            .body(Empty::<Bytes>::new())
+            .unwrap();
+        let (stats, _res) = client.send_request(req).await.expect("send_request");
+
+        assert_eq!(stats.checkout_start, Some(fixed));
+        assert_eq!(stats.checkout_end, Some(fixed));
+        assert_eq!(stats.headers_written, Some(fixed));
+        assert_eq!(stats.first_header_byte_time, Some(fixed));
+        assert_eq!(stats.get_pool_wait(), Some(Duration::ZERO));
+    }
+
+    #[tokio::test]
+    async fn client_stats_observer() {
+        use hyper::stats::ConnectionObserver;
+        use std::sync::{Arc, Mutex};
+        use std::time::Instant;
+
+        #[derive(Clone, Default)]
+        struct Events(Arc<Mutex<Vec<&'static str>>>);
+
+        impl ConnectionObserver for Events {
+            fn on_request_start(&self, _at: Instant) {
+                self.0.lock().unwrap().push("request_start");
+            }
+            fn on_headers_written(&self, _at: Instant) {
+                self.0.lock().unwrap().push("headers_written");
+            }
+            fn on_body_written(&self, _at: Instant) {
+                self.0.lock().unwrap().push("body_written");
+            }
+            fn on_first_header_byte(&self, _at: Instant) {
+                self.0.lock().unwrap().push("first_header_byte");
+            }
+            fn on_complete(&self, _stats: &hyper::RequestStats) {
+                self.0.lock().unwrap().push("complete");
+            }
+        }
+
+        let (server, addr) = setup_std_test_server();
+
+        thread::spawn(move || {
+            let mut sock = server.accept().unwrap().0;
+            sock.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            sock.set_write_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            let mut buf = [0; 4096];
+            sock.read(&mut buf).expect("read 1");
+            sock.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                .unwrap();
+        });
+
+        let tcp = tcp_connect(&addr).await.unwrap();
+
+        let events = Events::default();
+        let (mut client, conn) = conn::http1::Builder::new()
+            .observer(events.clone())
+            .handshake(tcp)
+            .await
+            .unwrap();
+
+        tokio::spawn(async move {
+            let _ = conn.await;
+        });
+
+        let req = Request::builder()
+            .uri("/a")
+            .body(Empty::<Bytes>::new())
+            .unwrap();
+        client.send_request(req).await.expect("send_request");
+
+        assert_eq!(
+            *events.0.lock().unwrap(),
+            [
+                "request_start",
+                "headers_written",
+                "body_written",
+                "first_header_byte",
+                "complete"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn client_connect_tunnel_stats() {
+        let (server, addr) = setup_std_test_server();
+
+        thread::spawn(move || {
+            let mut sock = server.accept().unwrap().0;
+            sock.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            sock.set_write_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            let mut buf = [0; 4096];
+            sock.read(&mut buf).expect("read 1");
+            sock.write_all(b"HTTP/1.1 200 OK\r\n\r\n").unwrap();
+            let n = sock.read(&mut buf).expect("read 2");
+            assert_eq!(&buf[..n], b"foo=bar");
+            sock.write_all(b"bar=foo").expect("write 2");
+        });
+
+        let tcp = tcp_connect(&addr).await.unwrap();
+        let (mut client, conn) = conn::http1::handshake(tcp).await.unwrap();
+        tokio::spawn(async move {
+            let _ = conn.with_upgrades().await;
+        });
+
+        let req = Request::builder()
+            .method("CONNECT")
+            .uri(addr.to_string())
+            .body(Empty::<Bytes>::new())
+            .unwrap();
+        let (_, res) = client.send_request(req).await.expect("send_request");
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let mut upgraded = TokioIo::new(hyper::upgrade::on(res).await.expect("on_upgrade"));
+        upgraded.write_all(b"foo=bar").await.unwrap();
+        let mut buf = [0; 7];
+        upgraded.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"bar=foo");
+
+        let tunnel = upgraded.inner().tunnel_stats().expect("tunnel stats");
+        assert_eq!(tunnel.bytes_in, 7);
+        assert_eq!(tunnel.bytes_out, 7);
+        assert!(tunnel.connect_start <= tunnel.established_at);
+        assert!(tunnel.closed_at.is_none());
+    }
+
+    #[tokio::test]
+    async fn client_connect_helper() {
+        let (server, addr) = setup_std_test_server();
+
+        thread::spawn(move || {
+            let mut sock = server.accept().unwrap().0;
+            sock.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            sock.set_write_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            let mut buf = [0; 4096];
+            let n = sock.read(&mut buf).expect("read 1");
+            let expected = format!("CONNECT {0} HTTP/1.1\r\nhost: {0}\r\n\r\n", "hyper.rs:443");
+            assert_eq!(s(&buf[..n]), expected);
+            sock.write_all(b"HTTP/1.1 200 Connection Established\r\nx-tunnel: yes\r\n\r\n")
+                .unwrap();
+            let n = sock.read(&mut buf).expect("read 2");
+            assert_eq!(&buf[..n], b"foo=bar");
+            sock.write_all(b"bar=foo").expect("write 2");
+        });
+
+        let tcp = tcp_connect(&addr).await.unwrap();
+        let (mut client, conn) = conn::http1::handshake::<_, Empty<Bytes>>(tcp)
+            .await
+            .unwrap();
+        tokio::spawn(async move {
+            let _ = conn.with_upgrades().await;
+        });
+
+        let (res, upgraded) = client
+            .connect(Uri::from_static("hyper.rs:443"))
+            .await
+            .expect("connect");
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()["x-tunnel"], "yes");
+
+        let mut upgraded = TokioIo::new(upgraded);
+        upgraded.write_all(b"foo=bar").await.unwrap();
+        let mut buf = [0; 7];
+        upgraded.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"bar=foo");
+    }
+
+    #[tokio::test]
+    async fn client_connect_helper_rejected() {
+        let (server, addr) = setup_std_test_server();
+
+        thread::spawn(move || {
+            let mut sock = server.accept().unwrap().0;
+            sock.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            sock.set_write_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            let mut buf = [0; 4096];
+            let n = sock.read(&mut buf).expect("read 1");
+            assert!(s(&buf[..n]).starts_with("CONNECT hyper.rs:443 "));
+            sock.write_all(
+                b"HTTP/1.1 407 Proxy Authentication Required\r\ncontent-length: 0\r\n\r\n",
+            )
+            .unwrap();
+        });
+
+        let tcp = tcp_connect(&addr).await.unwrap();
+        let (mut client, conn) = conn::http1::handshake::<_, Empty<Bytes>>(tcp)
+            .await
+            .unwrap();
+        tokio::spawn(async move {
+            let _ = conn.with_upgrades().await;
+        });
+
+        let err = client
+            .connect(Uri::from_static("hyper.rs:443"))
+            .await
+            .expect_err("connect should be rejected");
+        assert!(err.is_connect_rejected(), "{:?}", err);
+
+        let err = client
+            .connect(Uri::from_static("http://hyper.rs/"))
+            .await
+            .expect_err("absolute-form uri");
+        assert!(err.is_user(), "{:?}", err);
+    }
+
+    #[tokio::test]
+    async fn client_collect_stats_disabled() {
+        use std::sync::{Arc, Mutex};
+        let (server, addr) = setup_std_test_server();
+
+        thread::spawn(move || {
+            let mut sock = server.accept().unwrap().0;
+            sock.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            sock.set_write_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            let mut buf = [0; 4096];
+            sock.read(&mut buf).expect("read 1");
+            sock.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 5\r\n\r\nhello")
+                .unwrap();
+        });
+
+        let tcp = tcp_connect(&addr).await.unwrap();
+
+        let reported = Arc::new(Mutex::new(Vec::new()));
+        let reported2 = reported.clone();
+        let (mut client, conn) = conn::http1::Builder::new()
+            .collect_stats(false)
+            .on_stats(move |stats| reported2.lock().unwrap().push(stats))
+            .handshake(tcp)
+            .await
+            .unwrap();
+
+        tokio::spawn(async move {
+            let _ = conn.await;
+        });
+
+        let req = Request::builder()
+            .uri("/a")
+            .body(Empty::<Bytes>::new())
+            .unwrap();
+        let (stats, res) = client.send_request(req).await.expect("send_request");
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "hello");
+
+        assert!(stats.first_header_byte_time.is_none());
+        assert!(stats.checkout_start.is_none());
+        assert!(stats.get_pool_wait().is_none());
+        assert!(reported.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn client_stats_connection_reuse() {
+        let (server, addr) = setup_std_test_server();
+
+        thread::spawn(move || {
+            let mut sock = server.accept().unwrap().0;
+            sock.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            sock.set_write_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            let mut buf = [0; 4096];
+            sock.read(&mut buf).expect("read 1");
+            sock.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                .unwrap();
+            sock.read(&mut buf).expect("read 2");
+            sock.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                .unwrap();
+        });
+
+        let tcp = tcp_connect(&addr).await.unwrap();
+        let (mut client, conn) = conn::http1::handshake(tcp).await.unwrap();
+        tokio::spawn(async move {
+            let _ = conn.await;
+        });
+
+        let mut seen = Vec::new();
+        for _ in 0..2 {
+            let req = Request::builder()
+                .uri("/a")
+                .body(Empty::<Bytes>::new())
+                .unwrap();
+            let (stats, _res) = client.send_request(req).await.expect("send_request");
+            seen.push(stats);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+
+        assert!(seen[0].connection_id.is_some());
+        assert_eq!(seen[0].connection_id, seen[1].connection_id);
+        assert_eq!(seen[0].connection_request_count, 1);
+        assert_eq!(seen[1].connection_request_count, 2);
+        assert_eq!(seen[0].idle_before_reuse, None);
+        let idle = seen[1].idle_before_reuse.expect("idle before reuse");
+        assert!(idle >= Duration::from_millis(20), "{:?}", idle);
+    }
+
+    #[tokio::test]
+    async fn test_try_send_request() {
+        use std::future::Future;
+        let (done_tx, done_rx) = tokio::sync::oneshot::channel::<()>();
+        let (io_srv, io_cli) = tokio_test::io::Builder::new()
+            .write(b"GET / HTTP/1.1\r\n\r\n")
+            .read(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+            .build_with_handle();
+
+        tokio::spawn(async move {
+            let _io = io_cli;
+            let _ = done_rx.await;
+        });
+
+        // make polling fair by putting both in spawns
+        tokio::spawn(async move {
+            let io = TokioIo::new(io_srv);
+            let (mut client, mut conn) = conn::http1::Builder::new()
+                .handshake::<_, Empty<Bytes>>(io)
+                .await
+                .expect("http handshake");
+
+            // get the conn ready
+            assert!(
+                future::poll_fn(|cx| Poll::Ready(Pin::new(&mut conn).poll(cx)))
+                    .await
+                    .is_pending()
+            );
+            assert!(client.is_ready());
+
+            // use the connection once
+            let mut fut1 = std::pin::pin!(client.send_request(http::Request::new(Empty::new())));
+            let _res1 = future::poll_fn(|cx| loop {
+                if let Poll::Ready(res) = fut1.as_mut().poll(cx) {
+                    return Poll::Ready(res);
+                }
+                return match Pin::new(&mut conn).poll(cx) {
+                    Poll::Ready(_) => panic!("ruh roh"),
+                    Poll::Pending => Poll::Pending,
+                };
+            })
+            .await
+            .expect("resp 1");
+
+            assert!(client.is_ready());
+
+            // simulate the server dropping the conn
+            let _ = done_tx.send(());
+            // let the server task die
+            tokio::task::yield_now().await;
+
+            let mut fut2 =
+                std::pin::pin!(client.try_send_request(http::Request::new(Empty::new())));
+            let poll1 = future::poll_fn(|cx| Poll::Ready(fut2.as_mut().poll(cx))).await;
+            assert!(poll1.is_pending(), "not already known to error");
+
+            let mut conn_opt = Some(conn);
             // wasn't a known error, req is in queue, and now the next poll, the
             // conn will be noticed as errored
             let mut err = future::poll_fn(|cx| {
@@ -2211,302 +3101,1576 @@ mod conn {
                         };
                     }
                 }
-            })
+            })
+            .await
+            .expect_err("resp 2");
+
+            let req = err.take_message().expect("request was returned");
+
+            // sent again on another connection, it reports the failed attempt
+            let (io, io_cli) = tokio_test::io::Builder::new()
+                .write(b"GET / HTTP/1.1\r\n\r\n")
+                .read(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                .build_with_handle();
+            let (stats_tx, stats_rx) = tokio::sync::oneshot::channel();
+            let mut stats_tx = Some(stats_tx);
+            let (mut client, conn) = conn::http1::Builder::new()
+                .on_stats(move |stats| {
+                    if let Some(tx) = stats_tx.take() {
+                        let _ = tx.send(stats);
+                    }
+                })
+                .handshake::<_, Empty<Bytes>>(TokioIo::new(io))
+                .await
+                .expect("http handshake");
+            tokio::spawn(async move {
+                let _io = io_cli;
+                let _ = conn.await;
+            });
+
+            client.send_request(req).await.expect("resp 3");
+            let stats = stats_rx.await.expect("stats");
+            assert_eq!(stats.get_retry_count(), 1);
+            assert_eq!(
+                stats.retries[0].reason,
+                hyper::RetryReason::ConnectionClosed
+            );
+            assert!(stats.retries[0].connection_stats.connection_id.is_some());
+        })
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn http2_detect_conn_eof() {
+        use futures_util::future;
+
+        let (listener, addr) = setup_tk_test_server().await;
+
+        let (shdn_tx, mut shdn_rx) = tokio::sync::watch::channel(false);
+        tokio::task::spawn(async move {
+            use hyper::server::conn::http2;
+            use hyper::service::service_fn;
+
+            loop {
+                tokio::select! {
+                    res = listener.accept() => {
+                        let (stream, _) = res.unwrap();
+                        let stream = TokioIo::new(stream);
+
+                        let service = service_fn(|_:Request<hyper::body::Incoming>| future::ok::<_, hyper::Error>(Response::new(Empty::<Bytes>::new())));
+
+                        let mut shdn_rx = shdn_rx.clone();
+                        tokio::task::spawn(async move {
+                            let mut conn = http2::Builder::new(TokioExecutor)
+                                .serve_connection(stream, service);
+
+                            tokio::select! {
+                                res = &mut conn => {
+                                    res.unwrap();
+                                }
+                                _ = shdn_rx.changed() => {
+                                    Pin::new(&mut conn).graceful_shutdown();
+                                    conn.await.unwrap();
+                                }
+                            }
+                        });
+                    }
+                    _ = shdn_rx.changed() => {
+                        break;
+                    }
+                }
+            }
+        });
+
+        let io = tcp_connect(&addr).await.expect("tcp connect");
+        let (mut client, conn) = conn::http2::Builder::new(TokioExecutor)
+            .handshake(io)
+            .await
+            .expect("http handshake");
+
+        tokio::task::spawn(async move {
+            conn.await.expect("client conn");
+        });
+
+        // Sanity check that client is ready
+        future::poll_fn(|ctx| client.poll_ready(ctx))
+            .await
+            .expect("client poll ready sanity");
+
+        let req = Request::builder()
+            .uri(format!("http://{}/", addr))
+            .body(Empty::<Bytes>::new())
+            .expect("request builder");
+
+        client.send_request(req).await.expect("req1 send");
+
+        // Sanity check that client is STILL ready
+        future::poll_fn(|ctx| client.poll_ready(ctx))
+            .await
+            .expect("client poll ready after");
+
+        // Trigger the server shutdown...
+        let _ = shdn_tx.send(true);
+
+        // Allow time for graceful shutdown roundtrips...
+        TokioTimer.sleep(Duration::from_millis(100)).await;
+
+        // After graceful shutdown roundtrips, the client should be closed...
+        future::poll_fn(|ctx| client.poll_ready(ctx))
+            .await
+            .expect_err("client should be closed");
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn http2_connect_detect_close() {
+        // Regression test for failure to fully close connections when using HTTP2 CONNECT
+        // We send 2 requests and then drop them. We should see the connection gracefully close.
+        use futures_util::future;
+        let (listener, addr) = setup_tk_test_server().await;
+        let (tx, rxx) = oneshot::channel::<()>();
+
+        tokio::task::spawn(async move {
+            use hyper::server::conn::http2;
+            use hyper::service::service_fn;
+
+            let res = listener.accept().await;
+            let (stream, _) = res.unwrap();
+            let stream = TokioIo::new(stream);
+
+            let service = service_fn(move |req: Request<hyper::body::Incoming>| {
+                tokio::task::spawn(async move {
+                    let io = &mut TokioIo::new(hyper::upgrade::on(req).await.unwrap());
+                    io.write_all(b"hello\n").await.unwrap();
+                });
+
+                future::ok::<_, hyper::Error>(Response::new(Empty::<Bytes>::new()))
+            });
+
+            tokio::task::spawn(async move {
+                let conn = http2::Builder::new(TokioExecutor).serve_connection(stream, service);
+                let _ = conn.await;
+                tx.send(()).unwrap();
+            });
+        });
+
+        let io = tcp_connect(&addr).await.expect("tcp connect");
+        let (mut client, conn) = conn::http2::Builder::new(TokioExecutor)
+            .handshake(io)
+            .await
+            .expect("http handshake");
+
+        tokio::task::spawn(async move {
+            conn.await.expect("client conn");
+        });
+
+        // Sanity check that client is ready
+        future::poll_fn(|ctx| client.poll_ready(ctx))
+            .await
+            .expect("client poll ready sanity");
+        let requests = 2;
+        let mut clients = vec![client.clone(), client];
+        let (tx, rx) = oneshot::channel::<()>();
+        let (tx2, rx2) = oneshot::channel::<()>();
+        let mut rxs = vec![rx, rx2];
+        for _i in 0..requests {
+            let mut client = clients.pop().unwrap();
+            let rx = rxs.pop().unwrap();
+            let req = Request::builder()
+                .method(Method::CONNECT)
+                .uri(format!("{}", addr))
+                .body(Empty::<Bytes>::new())
+                .expect("request builder");
+
+            let resp = client.send_request(req).await.expect("req1 send").1;
+            assert_eq!(resp.status(), 200);
+            let upgrade = hyper::upgrade::on(resp).await.unwrap();
+            tokio::task::spawn(async move {
+                let _ = rx.await;
+                drop(upgrade);
+            });
+        }
+        drop(tx);
+        drop(tx2);
+        tokio::time::timeout(Duration::from_secs(1), rxx)
+            .await
+            .expect("drop with 1s")
+            .expect("tx dropped without sending");
+    }
+
+    #[tokio::test]
+    async fn http2_keep_alive_detects_unresponsive_server() {
+        let (listener, addr) = setup_tk_test_server().await;
+
+        // spawn a server that reads but doesn't write
+        tokio::spawn(async move {
+            let mut sock = listener.accept().await.unwrap().0;
+            let mut buf = [0u8; 1024];
+            loop {
+                let n = sock.read(&mut buf).await.expect("server read");
+                if n == 0 {
+                    // server closed, lets go!
+                    break;
+                }
+            }
+        });
+
+        let io = tcp_connect(&addr).await.expect("tcp connect");
+        let (_client, conn) = conn::http2::Builder::new(TokioExecutor)
+            .timer(TokioTimer)
+            .keep_alive_interval(Duration::from_secs(1))
+            .keep_alive_timeout(Duration::from_secs(1))
+            // enable while idle since we aren't sending requests
+            .keep_alive_while_idle(true)
+            .handshake::<_, hyper::body::Incoming>(io)
+            .await
+            .expect("http handshake");
+
+        conn.await.expect_err("conn should time out");
+    }
+
+    #[tokio::test]
+    async fn http2_keep_alive_not_while_idle() {
+        // This tests that not setting `http2_keep_alive_while_idle(true)`
+        // will use the default behavior which will NOT detect the server
+        // is unresponsive while no streams are active.
+
+        let (listener, addr) = setup_tk_test_server().await;
+
+        // spawn a server that reads but doesn't write
+        tokio::spawn(async move {
+            let sock = listener.accept().await.unwrap().0;
+            drain_til_eof(sock).await.expect("server read");
+        });
+
+        let io = tcp_connect(&addr).await.expect("tcp connect");
+        let (mut client, conn) = conn::http2::Builder::new(TokioExecutor)
+            .timer(TokioTimer)
+            .keep_alive_interval(Duration::from_secs(1))
+            .keep_alive_timeout(Duration::from_secs(1))
+            .handshake::<_, hyper::body::Incoming>(io)
+            .await
+            .expect("http handshake");
+
+        tokio::spawn(async move {
+            conn.await.expect("client conn shouldn't error");
+        });
+
+        // sleep longer than keepalive would trigger
+        TokioTimer.sleep(Duration::from_secs(4)).await;
+
+        future::poll_fn(|ctx| client.poll_ready(ctx))
+            .await
+            .expect("client should be open");
+    }
+
+    #[tokio::test]
+    async fn http2_keep_alive_closes_open_streams() {
+        let (listener, addr) = setup_tk_test_server().await;
+
+        // spawn a server that reads but doesn't write
+        tokio::spawn(async move {
+            let sock = listener.accept().await.unwrap().0;
+            drain_til_eof(sock).await.expect("server read");
+        });
+
+        let io = tcp_connect(&addr).await.expect("tcp connect");
+        let (mut client, conn) = conn::http2::Builder::new(TokioExecutor)
+            .timer(TokioTimer)
+            .keep_alive_interval(Duration::from_secs(1))
+            .keep_alive_timeout(Duration::from_secs(1))
+            .handshake(io)
+            .await
+            .expect("http handshake");
+
+        tokio::spawn(async move {
+            let err = conn.await.expect_err("client conn should timeout");
+            assert!(err.is_timeout());
+        });
+
+        let req = http::Request::new(Empty::<Bytes>::new());
+        let err = client
+            .send_request(req)
+            .await
+            .expect_err("request should timeout");
+        assert!(err.is_timeout());
+
+        let err = future::poll_fn(|ctx| client.poll_ready(ctx))
+            .await
+            .expect_err("client should be closed");
+        assert!(
+            err.is_closed(),
+            "poll_ready error should be closed: {:?}",
+            err
+        );
+    }
+
+    #[tokio::test]
+    async fn http2_keep_alive_with_responsive_server() {
+        // Test that a responsive server works just when client keep
+        // alive is enabled
+        use hyper::service::service_fn;
+
+        let (listener, addr) = setup_tk_test_server().await;
+
+        // Spawn an HTTP2 server that reads the whole body and responds
+        tokio::spawn(async move {
+            let sock = TokioIo::new(listener.accept().await.unwrap().0);
+            hyper::server::conn::http2::Builder::new(TokioExecutor)
+                .timer(TokioTimer)
+                .serve_connection(
+                    sock,
+                    service_fn(|req| async move {
+                        tokio::spawn(async move {
+                            let _ = concat(req.into_body())
+                                .await
+                                .expect("server req body aggregate");
+                        });
+                        Ok::<_, hyper::Error>(http::Response::new(Empty::<Bytes>::new()))
+                    }),
+                )
+                .await
+                .expect("serve_connection");
+        });
+
+        let io = tcp_connect(&addr).await.expect("tcp connect");
+        let (mut client, conn) = conn::http2::Builder::new(TokioExecutor)
+            .timer(TokioTimer)
+            .keep_alive_interval(Duration::from_secs(1))
+            .keep_alive_timeout(Duration::from_secs(1))
+            .handshake(io)
+            .await
+            .expect("http handshake");
+
+        tokio::spawn(async move {
+            conn.await.expect("client conn shouldn't error");
+        });
+
+        // Use a channel to keep request stream open
+        let (_tx, recv) = mpsc::channel::<Result<Frame<Bytes>, Box<dyn Error + Send + Sync>>>(0);
+        let req = http::Request::new(StreamBody::new(recv));
+
+        let _resp = client.send_request(req).await.expect("send_request");
+
+        // sleep longer than keepalive would trigger
+        TokioTimer.sleep(Duration::from_secs(4)).await;
+
+        future::poll_fn(|ctx| client.poll_ready(ctx))
+            .await
+            .expect("client should be open");
+    }
+
+    #[tokio::test]
+    async fn http2_connection_stats() {
+        use hyper::service::service_fn;
+
+        let (listener, addr) = setup_tk_test_server().await;
+
+        tokio::spawn(async move {
+            let sock = TokioIo::new(listener.accept().await.unwrap().0);
+            hyper::server::conn::http2::Builder::new(TokioExecutor)
+                .max_concurrent_streams(50)
+                .serve_connection(
+                    sock,
+                    service_fn(|_req| async move {
+                        Ok::<_, hyper::Error>(Response::new(Full::new(Bytes::from("hello"))))
+                    }),
+                )
+                .await
+                .expect("serve_connection");
+        });
+
+        let io = tcp_connect(&addr).await.expect("tcp connect");
+        let (mut client, conn) = conn::http2::Builder::new(TokioExecutor)
+            .handshake(io)
+            .await
+            .expect("http handshake");
+
+        tokio::spawn(async move {
+            conn.await.expect("client conn shouldn't error");
+        });
+
+        let req = Request::post("/a")
+            .body(Full::new(Bytes::from("hello")))
+            .unwrap();
+        let (stats, res) = client.send_request(req).await.expect("send_request");
+
+        let h2 = stats.h2.expect("h2 stats");
+        assert_eq!(h2.peer_settings.max_concurrent_streams, Some(50));
+        // the client raises the connection window right away
+        assert!(h2.window_updates_sent >= 1);
+        assert!(h2.recv_window > 65_535);
+        assert_eq!(stats.idle_before_reuse, None);
+        // counted even though the transport has no stats of its own
+        let first = stats.connection_stats.expect("connection stats");
+        assert!(first.bytes_written > 0);
+        assert!(first.bytes_read > 0);
+
+        drop(res);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        let req = Request::get("/b").body(Full::new(Bytes::new())).unwrap();
+        let (stats, _res) = client.send_request(req).await.expect("send_request");
+        let idle = stats.idle_before_reuse.expect("idle before reuse");
+        assert!(idle >= Duration::from_millis(20), "{:?}", idle);
+        let second = stats.connection_stats.expect("connection stats");
+        assert!(second.bytes_written > first.bytes_written);
+        assert!(second.bytes_read > first.bytes_read);
+    }
+
+    #[tokio::test]
+    async fn http2_on_stats_callback_after_body() {
+        use hyper::service::service_fn;
+        use std::sync::{Arc, Mutex};
+
+        let (listener, addr) = setup_tk_test_server().await;
+
+        tokio::spawn(async move {
+            let sock = TokioIo::new(listener.accept().await.unwrap().0);
+            hyper::server::conn::http2::Builder::new(TokioExecutor)
+                .serve_connection(
+                    sock,
+                    service_fn(|_req| async move {
+                        Ok::<_, hyper::Error>(Response::new(Full::new(Bytes::from("hello"))))
+                    }),
+                )
+                .await
+                .expect("serve_connection");
+        });
+
+        let reported = Arc::new(Mutex::new(Vec::new()));
+        let reported2 = reported.clone();
+        let io = tcp_connect(&addr).await.expect("tcp connect");
+        let (mut client, conn) = conn::http2::Builder::new(TokioExecutor)
+            .on_stats(move |stats| reported2.lock().unwrap().push(stats))
+            .handshake(io)
+            .await
+            .expect("http handshake");
+
+        tokio::spawn(async move {
+            conn.await.expect("client conn shouldn't error");
+        });
+
+        let req = Request::get("/a").body(Full::new(Bytes::new())).unwrap();
+        let (_, res) = client.send_request(req).await.expect("send_request");
+        assert!(reported.lock().unwrap().is_empty());
+
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "hello");
+        let reported = reported.lock().unwrap();
+        assert_eq!(reported.len(), 1);
+        assert_eq!(reported[0].http_stats.response_body_bytes, 5);
+        assert!(reported[0].http_stats.last_body_byte_time.is_some());
+    }
+
+    #[tokio::test]
+    async fn http2_connection_set_up_once() {
+        use hyper::service::service_fn;
+        use hyper::stats::RequestPhase;
+        use std::sync::{Arc, Mutex};
+
+        let (listener, addr) = setup_tk_test_server().await;
+
+        tokio::spawn(async move {
+            let sock = TokioIo::new(listener.accept().await.unwrap().0);
+            hyper::server::conn::http2::Builder::new(TokioExecutor)
+                .serve_connection(
+                    sock,
+                    service_fn(|_req| async move {
+                        Ok::<_, hyper::Error>(Response::new(Full::new(Bytes::from("hello"))))
+                    }),
+                )
+                .await
+                .expect("serve_connection");
+        });
+
+        let reported = Arc::new(Mutex::new(Vec::new()));
+        let reported2 = reported.clone();
+        let io = StatsIo::connect(&addr).await;
+        let (mut client, conn) = conn::http2::Builder::new(TokioExecutor)
+            .on_stats(move |stats| reported2.lock().unwrap().push(stats))
+            .handshake(io)
+            .await
+            .expect("http handshake");
+
+        tokio::spawn(async move {
+            conn.await.expect("client conn shouldn't error");
+        });
+
+        for path in ["/a", "/b"] {
+            let req = Request::get(path).body(Full::new(Bytes::new())).unwrap();
+            let (_, res) = client.send_request(req).await.expect("send_request");
+            res.into_body().collect().await.unwrap();
+        }
+
+        let reported = reported.lock().unwrap();
+        assert_eq!(reported.len(), 2);
+        let phases = |i: usize| {
+            reported[i]
+                .phases()
+                .into_iter()
+                .map(|p| p.phase)
+                .collect::<Vec<_>>()
+        };
+
+        let first = reported[0].http_stats.connection_stats.expect("first");
+        assert!(first.connect_end.is_some());
+        assert_eq!(first.alpn, Some("h2"));
+        assert_eq!(first.peer_addr, Some(addr));
+        assert!(
+            phases(0).contains(&RequestPhase::Connect),
+            "{:?}",
+            phases(0)
+        );
+
+        // the second stream didn't wait for the connection
+        let second = reported[1].http_stats.connection_stats.expect("second");
+        assert_eq!(second.connect_start, None);
+        // but it still went over the same transport
+        assert_eq!(second.alpn, Some("h2"));
+        assert_eq!(second.peer_addr, Some(addr));
+        assert!(
+            !phases(1).contains(&RequestPhase::Connect),
+            "{:?}",
+            phases(1)
+        );
+    }
+
+    #[tokio::test]
+    async fn http2_aggregator_connects_once() {
+        use hyper::service::service_fn;
+        use hyper::stats::{Aggregator, Phase};
+        use std::sync::{Arc, Mutex};
+
+        let (listener, addr) = setup_tk_test_server().await;
+
+        tokio::spawn(async move {
+            let sock = TokioIo::new(listener.accept().await.unwrap().0);
+            hyper::server::conn::http2::Builder::new(TokioExecutor)
+                .serve_connection(
+                    sock,
+                    service_fn(|_req| async move {
+                        Ok::<_, hyper::Error>(Response::new(Full::new(Bytes::from("hello"))))
+                    }),
+                )
+                .await
+                .expect("serve_connection");
+        });
+
+        let agg = Arc::new(Mutex::new(Aggregator::new()));
+        let agg2 = agg.clone();
+        let io = StatsIo::connect(&addr).await;
+        let (mut client, conn) = conn::http2::Builder::new(TokioExecutor)
+            .on_stats(move |stats| agg2.lock().unwrap().record(&stats))
+            .handshake(io)
+            .await
+            .expect("http handshake");
+
+        tokio::spawn(async move {
+            conn.await.expect("client conn shouldn't error");
+        });
+
+        for _ in 0..5 {
+            let req = Request::get("/").body(Full::new(Bytes::new())).unwrap();
+            let (_, res) = client.send_request(req).await.expect("send_request");
+            res.into_body().collect().await.unwrap();
+        }
+
+        let agg = agg.lock().unwrap();
+        assert_eq!(agg.get(Phase::Connect).count(), 1);
+        assert_eq!(agg.get(Phase::Total).count(), 5);
+    }
+
+    #[tokio::test]
+    async fn http2_stream_counts() {
+        use hyper::service::service_fn;
+
+        let (listener, addr) = setup_tk_test_server().await;
+
+        tokio::spawn(async move {
+            let sock = TokioIo::new(listener.accept().await.unwrap().0);
+            hyper::server::conn::http2::Builder::new(TokioExecutor)
+                .max_concurrent_streams(2)
+                .serve_connection(
+                    sock,
+                    service_fn(|req: Request<hyper::body::Incoming>| async move {
+                        if req.uri().path() == "/slow" {
+                            tokio::time::sleep(Duration::from_millis(200)).await;
+                        }
+                        Ok::<_, hyper::Error>(Response::new(Full::new(Bytes::from("hello"))))
+                    }),
+                )
+                .await
+                .expect("serve_connection");
+        });
+
+        let io = tcp_connect(&addr).await.expect("tcp connect");
+        let (mut client, conn) = conn::http2::Builder::new(TokioExecutor)
+            .handshake(io)
+            .await
+            .expect("http handshake");
+        tokio::spawn(async move {
+            conn.await.expect("client conn shouldn't error");
+        });
+        assert_eq!(client.current_streams(), 0);
+
+        // the server's settings have arrived by the time it responds
+        let req = Request::get("/").body(Empty::<Bytes>::new()).unwrap();
+        let (_stats, res) = client.send_request(req).await.expect("send_request");
+        assert_eq!(client.max_concurrent_streams(), 2);
+        drop(res);
+        assert_eq!(client.current_streams(), 0);
+
+        let req = Request::get("/slow").body(Empty::<Bytes>::new()).unwrap();
+        let slow = tokio::spawn(client.send_request(req));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(client.current_streams(), 1);
+
+        let (_stats, res) = slow.await.unwrap().expect("send_request");
+        assert_eq!(client.current_streams(), 1);
+        drop(res);
+        assert_eq!(client.current_streams(), 0);
+    }
+
+    async fn expect_continue_client(
+        addr: SocketAddr,
+        timeout: Duration,
+        abort: bool,
+    ) -> conn::http1::SendRequest<Full<Bytes>> {
+        let io = tcp_connect(&addr).await.expect("tcp connect");
+        let (client, conn) = conn::http1::Builder::new()
+            .timer(TokioTimer)
+            .expect_continue_timeout(timeout)
+            .abort_on_expect_continue_timeout(abort)
+            .handshake(io)
+            .await
+            .expect("http handshake");
+        tokio::spawn(async move {
+            let _ = conn.await;
+        });
+        client
+    }
+
+    fn expect_continue_request() -> Request<Full<Bytes>> {
+        Request::post("/upload")
+            .header("expect", "100-continue")
+            .body(Full::new(Bytes::from("hello")))
+            .unwrap()
+    }
+
+    async fn read_head(sock: &mut TcpStream) -> Vec<u8> {
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            head.push(sock.read_u8().await.expect("read head"));
+        }
+        head
+    }
+
+    #[tokio::test]
+    async fn expect_continue_waits_for_100() {
+        let (listener, addr) = setup_tk_test_server().await;
+
+        let server = tokio::spawn(async move {
+            let mut sock = listener.accept().await.unwrap().0;
+            read_head(&mut sock).await;
+            let mut buf = [0; 5];
+            tokio::time::timeout(Duration::from_millis(50), sock.read(&mut buf))
+                .await
+                .expect_err("body sent before 100 Continue");
+
+            sock.write_all(b"HTTP/1.1 100 Continue\r\n\r\n")
+                .await
+                .unwrap();
+            sock.read_exact(&mut buf).await.expect("read body");
+            assert_eq!(&buf, b"hello");
+            sock.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                .await
+                .unwrap();
+        });
+
+        let mut client = expect_continue_client(addr, Duration::from_secs(5), false).await;
+        let (_stats, res) = client
+            .send_request(expect_continue_request())
+            .await
+            .expect("send_request");
+        assert_eq!(res.status(), StatusCode::OK);
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn expect_continue_timeout_sends_body() {
+        let (listener, addr) = setup_tk_test_server().await;
+
+        tokio::spawn(async move {
+            let mut sock = listener.accept().await.unwrap().0;
+            read_head(&mut sock).await;
+            let mut buf = [0; 5];
+            sock.read_exact(&mut buf).await.expect("read body");
+            assert_eq!(&buf, b"hello");
+            sock.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                .await
+                .unwrap();
+        });
+
+        let mut client = expect_continue_client(addr, Duration::from_millis(50), false).await;
+        let (_stats, res) = client
+            .send_request(expect_continue_request())
+            .await
+            .expect("send_request");
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn expect_continue_timeout_aborts() {
+        let (listener, addr) = setup_tk_test_server().await;
+
+        tokio::spawn(async move {
+            let mut sock = listener.accept().await.unwrap().0;
+            read_head(&mut sock).await;
+            let mut buf = [0; 5];
+            let n = sock.read(&mut buf).await.expect("read");
+            assert_eq!(n, 0, "body sent after aborting");
+        });
+
+        let mut client = expect_continue_client(addr, Duration::from_millis(50), true).await;
+        let err = client
+            .send_request(expect_continue_request())
+            .await
+            .expect_err("send_request");
+        assert!(err.is_timeout(), "{:?}", err);
+    }
+
+    #[tokio::test]
+    async fn expect_continue_final_response_skips_body() {
+        let (listener, addr) = setup_tk_test_server().await;
+
+        let server = tokio::spawn(async move {
+            let mut sock = listener.accept().await.unwrap().0;
+            read_head(&mut sock).await;
+            sock.write_all(b"HTTP/1.1 417 Expectation Failed\r\ncontent-length: 0\r\n\r\n")
+                .await
+                .unwrap();
+            let mut buf = [0; 5];
+            let n = sock.read(&mut buf).await.expect("read");
+            assert_eq!(n, 0, "body sent after final response");
+        });
+
+        let mut client = expect_continue_client(addr, Duration::from_secs(5), false).await;
+        let (_stats, res) = client
+            .send_request(expect_continue_request())
+            .await
+            .expect("send_request");
+        assert_eq!(res.status(), StatusCode::EXPECTATION_FAILED);
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn auto_expect_continue_large_body() {
+        let (listener, addr) = setup_tk_test_server().await;
+
+        let server = tokio::spawn(async move {
+            let mut sock = listener.accept().await.unwrap().0;
+
+            let head = read_head(&mut sock).await;
+            let head = String::from_utf8(head).unwrap().to_ascii_lowercase();
+            assert!(head.contains("expect: 100-continue\r\n"), "{}", head);
+            let mut buf = [0; 5];
+            tokio::time::timeout(Duration::from_millis(50), sock.read(&mut buf))
+                .await
+                .expect_err("body sent before 100 Continue");
+            sock.write_all(b"HTTP/1.1 100 Continue\r\n\r\n")
+                .await
+                .unwrap();
+            sock.read_exact(&mut buf).await.expect("read body");
+            assert_eq!(&buf, b"hello");
+            sock.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                .await
+                .unwrap();
+
+            let head = read_head(&mut sock).await;
+            let head = String::from_utf8(head).unwrap().to_ascii_lowercase();
+            assert!(!head.contains("expect:"), "{}", head);
+            sock.read_exact(&mut buf[..2]).await.expect("read body");
+            assert_eq!(&buf[..2], b"hi");
+            sock.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                .await
+                .unwrap();
+        });
+
+        let io = tcp_connect(&addr).await.expect("tcp connect");
+        let (mut client, conn) = conn::http1::Builder::new()
+            .timer(TokioTimer)
+            .auto_expect_continue(5)
+            .handshake(io)
+            .await
+            .expect("http handshake");
+        tokio::spawn(async move {
+            let _ = conn.await;
+        });
+
+        let req = Request::post("/upload")
+            .body(Full::new(Bytes::from("hello")))
+            .unwrap();
+        let (_stats, res) = client.send_request(req).await.expect("send_request");
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let req = Request::post("/upload")
+            .body(Full::new(Bytes::from("hi")))
+            .unwrap();
+        let (_stats, res) = client.send_request(req).await.expect("send_request");
+        assert_eq!(res.status(), StatusCode::OK);
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn response_header_timeout() {
+        let (listener, addr) = setup_tk_test_server().await;
+
+        let server = tokio::spawn(async move {
+            let mut sock = listener.accept().await.unwrap().0;
+            let _ = read_head(&mut sock).await;
+            // Never respond, the client gives up and closes.
+            let mut buf = [0; 16];
+            let n = sock.read(&mut buf).await.expect("read eof");
+            assert_eq!(n, 0);
+        });
+
+        let io = tcp_connect(&addr).await.expect("tcp connect");
+        let (mut client, conn) = conn::http1::Builder::new()
+            .timer(TokioTimer)
+            .response_header_timeout(Duration::from_millis(50))
+            .handshake(io)
+            .await
+            .expect("http handshake");
+        tokio::spawn(async move {
+            let _ = conn.await;
+        });
+
+        let req = Request::get("/").body(Empty::<Bytes>::new()).unwrap();
+        let err = client.send_request(req).await.expect_err("send_request");
+        assert!(err.is_timeout(), "{:?}", err);
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn response_header_timeout_allows_slow_body() {
+        let (listener, addr) = setup_tk_test_server().await;
+
+        let server = tokio::spawn(async move {
+            let mut sock = listener.accept().await.unwrap().0;
+            let _ = read_head(&mut sock).await;
+            sock.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 5\r\n\r\n")
+                .await
+                .unwrap();
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            sock.write_all(b"hello").await.unwrap();
+
+            // The second request overrides the timeout.
+            let _ = read_head(&mut sock).await;
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            sock.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                .await
+                .unwrap();
+        });
+
+        let io = tcp_connect(&addr).await.expect("tcp connect");
+        let (mut client, conn) = conn::http1::Builder::new()
+            .timer(TokioTimer)
+            .response_header_timeout(Duration::from_millis(50))
+            .handshake(io)
+            .await
+            .expect("http handshake");
+        tokio::spawn(async move {
+            let _ = conn.await;
+        });
+
+        let req = Request::get("/").body(Empty::<Bytes>::new()).unwrap();
+        let (_stats, res) = client.send_request(req).await.expect("send_request");
+        let body = res.into_body().collect().await.expect("body").to_bytes();
+        assert_eq!(body, "hello");
+
+        let mut req = Request::get("/").body(Empty::<Bytes>::new()).unwrap();
+        hyper::ext::set_response_header_timeout(&mut req, None);
+        let (_stats, res) = client.send_request(req).await.expect("send_request");
+        assert_eq!(res.status(), StatusCode::OK);
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn http1_pipelining() {
+        let (listener, addr) = setup_tk_test_server().await;
+        let (respond_tx, respond_rx) = oneshot::channel::<()>();
+
+        let server = tokio::spawn(async move {
+            let mut sock = listener.accept().await.unwrap().0;
+            // Both requests arrive before either is answered.
+            let head = read_head(&mut sock).await;
+            assert!(s(&head).starts_with("GET /a "), "{}", s(&head));
+            let head = read_head(&mut sock).await;
+            assert!(s(&head).starts_with("HEAD /b "), "{}", s(&head));
+            respond_rx.await.unwrap();
+            sock.write_all(
+                b"HTTP/1.1 200 OK\r\ncontent-length: 1\r\n\r\na\
+                  HTTP/1.1 200 OK\r\ncontent-length: 5\r\n\r\n",
+            )
+            .await
+            .unwrap();
+
+            let head = read_head(&mut sock).await;
+            assert!(s(&head).starts_with("GET /c "), "{}", s(&head));
+            sock.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 1\r\n\r\nc")
+                .await
+                .unwrap();
+        });
+
+        let io = tcp_connect(&addr).await.expect("tcp connect");
+        let (mut client, conn) = conn::http1::Builder::new()
+            .max_pipelined_requests(1)
+            .handshake(io)
+            .await
+            .expect("http handshake");
+        tokio::spawn(async move {
+            let _ = conn.await;
+        });
+
+        let req = Request::get("/a").body(Empty::<Bytes>::new()).unwrap();
+        let res1 = client.send_request(req);
+        client.ready().await.expect("ready");
+        let req = Request::head("/b").body(Empty::<Bytes>::new()).unwrap();
+        let res2 = client.send_request(req);
+        tokio::time::timeout(Duration::from_millis(50), client.ready())
             .await
-            .expect_err("resp 2");
+            .expect_err("ready beyond max_pipelined_requests");
+        respond_tx.send(()).unwrap();
 
-            assert!(err.take_message().is_some(), "request was returned");
-        })
-        .await
-        .unwrap();
+        let (stats, res) = res1.await.expect("send_request a");
+        assert_eq!(
+            stats.request_header_bytes,
+            "GET /a HTTP/1.1\r\n\r\n".len() as u64
+        );
+        let body = res.into_body().collect().await.expect("body").to_bytes();
+        assert_eq!(body, "a");
+        let (stats, res) = res2.await.expect("send_request b");
+        assert_eq!(
+            stats.request_header_bytes,
+            "HEAD /b HTTP/1.1\r\n\r\n".len() as u64
+        );
+        assert_eq!(res.headers()["content-length"], "5");
+        let body = res.into_body().collect().await.expect("body").to_bytes();
+        assert!(body.is_empty());
+
+        client.ready().await.expect("ready");
+        let req = Request::get("/c").body(Empty::<Bytes>::new()).unwrap();
+        let (_stats, res) = client.send_request(req).await.expect("send_request c");
+        let body = res.into_body().collect().await.expect("body").to_bytes();
+        assert_eq!(body, "c");
+        server.await.unwrap();
     }
 
     #[tokio::test]
-    async fn http2_detect_conn_eof() {
-        use futures_util::future;
-
+    async fn http1_pipelining_server_closes() {
         let (listener, addr) = setup_tk_test_server().await;
 
-        let (shdn_tx, mut shdn_rx) = tokio::sync::watch::channel(false);
-        tokio::task::spawn(async move {
-            use hyper::server::conn::http2;
-            use hyper::service::service_fn;
+        let server = tokio::spawn(async move {
+            let mut sock = listener.accept().await.unwrap().0;
+            for _ in 0..3 {
+                let _ = read_head(&mut sock).await;
+            }
+            sock.write_all(b"HTTP/1.1 200 OK\r\nconnection: close\r\ncontent-length: 0\r\n\r\n")
+                .await
+                .unwrap();
+        });
 
-            loop {
-                tokio::select! {
-                    res = listener.accept() => {
-                        let (stream, _) = res.unwrap();
-                        let stream = TokioIo::new(stream);
+        let io = tcp_connect(&addr).await.expect("tcp connect");
+        let (mut client, conn) = conn::http1::Builder::new()
+            .max_pipelined_requests(2)
+            .handshake(io)
+            .await
+            .expect("http handshake");
+        tokio::spawn(async move {
+            let _ = conn.await;
+        });
 
-                        let service = service_fn(|_:Request<hyper::body::Incoming>| future::ok::<_, hyper::Error>(Response::new(Empty::<Bytes>::new())));
+        let mut responses = Vec::new();
+        for _ in 0..3 {
+            client.ready().await.expect("ready");
+            let req = Request::get("/").body(Empty::<Bytes>::new()).unwrap();
+            responses.push(client.send_request(req));
+        }
+        let mut responses = responses.into_iter();
 
-                        let mut shdn_rx = shdn_rx.clone();
-                        tokio::task::spawn(async move {
-                            let mut conn = http2::Builder::new(TokioExecutor)
-                                .serve_connection(stream, service);
+        let (_stats, res) = responses.next().unwrap().await.expect("send_request");
+        assert_eq!(res.status(), StatusCode::OK);
+        for res in responses {
+            let err = res.await.expect_err("unanswered pipelined request");
+            assert!(err.is_canceled(), "{:?}", err);
+        }
+        server.await.unwrap();
+    }
 
-                            tokio::select! {
-                                res = &mut conn => {
-                                    res.unwrap();
-                                }
-                                _ = shdn_rx.changed() => {
-                                    Pin::new(&mut conn).graceful_shutdown();
-                                    conn.await.unwrap();
-                                }
-                            }
-                        });
-                    }
-                    _ = shdn_rx.changed() => {
-                        break;
-                    }
-                }
-            }
+    async fn read_h2_frame(sock: &mut TcpStream) -> (u8, u8, u32, Vec<u8>) {
+        let mut head = [0; 9];
+        sock.read_exact(&mut head).await.expect("read frame head");
+        let len = u32::from_be_bytes([0, head[0], head[1], head[2]]) as usize;
+        let stream_id = u32::from_be_bytes([head[5], head[6], head[7], head[8]]);
+        let mut payload = vec![0; len];
+        sock.read_exact(&mut payload).await.expect("read frame");
+        (head[3], head[4], stream_id, payload)
+    }
+
+    fn h2_frame(kind: u8, flags: u8, stream_id: u32, payload: &[u8]) -> Vec<u8> {
+        let len = (payload.len() as u32).to_be_bytes();
+        let mut buf = vec![len[1], len[2], len[3], kind, flags];
+        buf.extend_from_slice(&stream_id.to_be_bytes());
+        buf.extend_from_slice(payload);
+        buf
+    }
+
+    #[tokio::test]
+    async fn h2c_upgrade() {
+        const HEADERS: u8 = 0x1;
+        const SETTINGS: u8 = 0x4;
+        const END_STREAM_AND_HEADERS: u8 = 0x5;
+
+        let (listener, addr) = setup_tk_test_server().await;
+
+        let server = tokio::spawn(async move {
+            let mut sock = listener.accept().await.unwrap().0;
+            let head = read_head(&mut sock).await;
+            let head = String::from_utf8(head).unwrap().to_ascii_lowercase();
+            assert!(head.starts_with("post /upload http/1.1\r\n"), "{}", head);
+            assert!(head.contains("upgrade: h2c\r\n"), "{}", head);
+            assert!(head.contains("http2-settings: "), "{}", head);
+            let mut body = [0; 5];
+            sock.read_exact(&mut body).await.expect("read body");
+            assert_eq!(&body, b"hello");
+            sock.write_all(
+                b"HTTP/1.1 101 Switching Protocols\r\n\
+                connection: upgrade\r\n\
+                upgrade: h2c\r\n\r\n",
+            )
+            .await
+            .unwrap();
+
+            let mut preface = [0; 24];
+            sock.read_exact(&mut preface).await.unwrap();
+            assert_eq!(&preface, b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n");
+            let (kind, flags, stream_id, payload) = read_h2_frame(&mut sock).await;
+            assert_eq!(kind, SETTINGS);
+
+            // A real h2 server decodes what the client sends, after taking
+            // the upgrade request as stream 1 the way an h2c server does,
+            // without touching its HPACK table.
+            let mut upstream = preface.to_vec();
+            upstream.extend(h2_frame(kind, flags, stream_id, &payload));
+            // :method: GET, :path: /, :scheme: http
+            upstream.extend(h2_frame(
+                HEADERS,
+                END_STREAM_AND_HEADERS,
+                1,
+                &[0x82, 0x84, 0x86],
+            ));
+            let (bridge, io) = tokio::io::duplex(64 * 1024);
+            let (mut bridge_read, mut bridge_write) = tokio::io::split(bridge);
+            let (mut sock_read, mut sock_write) = sock.into_split();
+            tokio::spawn(async move {
+                bridge_write.write_all(&upstream).await.unwrap();
+                let _ = tokio::io::copy(&mut sock_read, &mut bridge_write).await;
+            });
+            tokio::spawn(async move {
+                let _ = tokio::io::copy(&mut bridge_read, &mut sock_write).await;
+            });
+
+            let mut h2 = h2::server::handshake(io).await.unwrap();
+            let (_, mut respond) = h2.accept().await.unwrap().unwrap();
+            respond.send_response(Response::new(()), true).unwrap();
+
+            let (req, mut respond) = h2.accept().await.unwrap().unwrap();
+            tokio::spawn(async move {
+                poll_fn(|cx| h2.poll_closed(cx)).await.unwrap();
+            });
+            // The upgrade request isn't sent again on stream 1.
+            assert_eq!(u32::from(respond.stream_id()), 3);
+            assert_eq!(req.uri().path(), "/again");
+            // Indexed by the client when it was sent before, so this only
+            // decodes if both ends' tables agree.
+            assert_eq!(req.headers()["x-repeated"], "same");
+            let res = Response::builder()
+                .status(StatusCode::NO_CONTENT)
+                .body(())
+                .unwrap();
+            respond.send_response(res, true).unwrap();
         });
 
         let io = tcp_connect(&addr).await.expect("tcp connect");
-        let (mut client, conn) = conn::http2::Builder::new(TokioExecutor)
-            .handshake(io)
+        let req = Request::post("/upload")
+            .header("host", "localhost")
+            .header("x-repeated", "same")
+            .body(Full::new(Bytes::from("hello")))
+            .unwrap();
+        let upgrade = conn::http2::Builder::new(TokioExecutor)
+            .handshake_h2c(io, req)
             .await
-            .expect("http handshake");
+            .expect("h2c handshake");
+        let (mut client, conn, (_stats, res)) = match upgrade {
+            conn::http2::H2cUpgrade::Upgraded {
+                sender,
+                conn,
+                response,
+            } => (sender, conn, response),
+            other => panic!("not upgraded: {:?}", other),
+        };
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.version(), hyper::Version::HTTP_2);
+        tokio::spawn(async move {
+            let _ = conn.await;
+        });
 
-        tokio::task::spawn(async move {
-            conn.await.expect("client conn");
+        let req = Request::get("http://localhost/again")
+            .header("x-repeated", "same")
+            .body(Full::default())
+            .unwrap();
+        let (_stats, res) = client.send_request(req).await.expect("send_request");
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn h2c_upgrade_ignored() {
+        let (listener, addr) = setup_tk_test_server().await;
+
+        tokio::spawn(async move {
+            let mut sock = listener.accept().await.unwrap().0;
+            read_head(&mut sock).await;
+            sock.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nok")
+                .await
+                .unwrap();
+            read_head(&mut sock).await;
+            sock.write_all(b"HTTP/1.1 204 No Content\r\n\r\n")
+                .await
+                .unwrap();
         });
 
-        // Sanity check that client is ready
-        future::poll_fn(|ctx| client.poll_ready(ctx))
+        let io = tcp_connect(&addr).await.expect("tcp connect");
+        let req = Request::get("/").body(Empty::<Bytes>::new()).unwrap();
+        let upgrade = conn::http2::Builder::new(TokioExecutor)
+            .handshake_h2c(io, req)
             .await
-            .expect("client poll ready sanity");
+            .expect("h2c handshake");
+        let (mut client, conn, (_stats, res)) = match upgrade {
+            conn::http2::H2cUpgrade::NotUpgraded {
+                sender,
+                conn,
+                response,
+            } => (sender, conn, response),
+            other => panic!("upgraded: {:?}", other),
+        };
+        tokio::spawn(async move {
+            let _ = conn.await;
+        });
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(concat(res).await.unwrap(), "ok");
 
-        let req = Request::builder()
-            .uri(format!("http://{}/", addr))
-            .body(Empty::<Bytes>::new())
-            .expect("request builder");
+        let req = Request::get("/").body(Empty::<Bytes>::new()).unwrap();
+        let (_stats, res) = client.send_request(req).await.expect("send_request");
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+    }
 
-        client.send_request(req).await.expect("req1 send");
+    #[tokio::test]
+    async fn h2c_upgrade_not_offered_for_head() {
+        let (listener, addr) = setup_tk_test_server().await;
 
-        // Sanity check that client is STILL ready
-        future::poll_fn(|ctx| client.poll_ready(ctx))
+        tokio::spawn(async move {
+            let mut sock = listener.accept().await.unwrap().0;
+            let head = read_head(&mut sock).await;
+            let head = String::from_utf8(head).unwrap().to_ascii_lowercase();
+            assert!(head.starts_with("head / http/1.1\r\n"), "{}", head);
+            assert!(!head.contains("upgrade"), "{}", head);
+            sock.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\n")
+                .await
+                .unwrap();
+        });
+
+        let io = tcp_connect(&addr).await.expect("tcp connect");
+        let req = Request::head("/").body(Empty::<Bytes>::new()).unwrap();
+        let upgrade = conn::http2::Builder::new(TokioExecutor)
+            .handshake_h2c(io, req)
             .await
-            .expect("client poll ready after");
+            .expect("h2c handshake");
+        match upgrade {
+            conn::http2::H2cUpgrade::NotUpgraded { response, .. } => {
+                assert_eq!(response.1.status(), StatusCode::OK);
+            }
+            other => panic!("upgraded: {:?}", other),
+        }
+    }
 
-        // Trigger the server shutdown...
-        let _ = shdn_tx.send(true);
+    #[tokio::test]
+    async fn http1_max_response_header_size() {
+        let (listener, addr) = setup_tk_test_server().await;
 
-        // Allow time for graceful shutdown roundtrips...
-        TokioTimer.sleep(Duration::from_millis(100)).await;
+        tokio::spawn(async move {
+            let mut sock = listener.accept().await.unwrap().0;
+            read_head(&mut sock).await;
+            let cookie = "a".repeat(2000);
+            let res = format!(
+                "HTTP/1.1 200 OK\r\nset-cookie: {}\r\ncontent-length: 0\r\n\r\n",
+                cookie
+            );
+            sock.write_all(res.as_bytes()).await.unwrap();
+        });
 
-        // After graceful shutdown roundtrips, the client should be closed...
-        future::poll_fn(|ctx| client.poll_ready(ctx))
+        let io = tcp_connect(&addr).await.expect("tcp connect");
+        let (mut client, conn) = conn::http1::Builder::new()
+            .max_response_header_size(1024)
+            .handshake(io)
             .await
-            .expect_err("client should be closed");
+            .expect("http handshake");
+        tokio::spawn(async move {
+            let _ = conn.await;
+        });
+
+        let req = Request::get("/").body(Empty::<Bytes>::new()).unwrap();
+        let err = client.send_request(req).await.expect_err("send_request");
+        assert!(err.is_parse_header_size_too_large(), "{:?}", err);
     }
 
-    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
-    async fn http2_connect_detect_close() {
-        // Regression test for failure to fully close connections when using HTTP2 CONNECT
-        // We send 2 requests and then drop them. We should see the connection gracefully close.
-        use futures_util::future;
+    #[tokio::test]
+    async fn http1_read_buf_sizes() {
         let (listener, addr) = setup_tk_test_server().await;
-        let (tx, rxx) = oneshot::channel::<()>();
 
-        tokio::task::spawn(async move {
-            use hyper::server::conn::http2;
-            use hyper::service::service_fn;
+        tokio::spawn(async move {
+            let mut sock = listener.accept().await.unwrap().0;
+            read_head(&mut sock).await;
+            let body = "x".repeat(100_000);
+            let res = format!(
+                "HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            sock.write_all(res.as_bytes()).await.unwrap();
 
-            let res = listener.accept().await;
-            let (stream, _) = res.unwrap();
-            let stream = TokioIo::new(stream);
+            read_head(&mut sock).await;
+            let cookie = "a".repeat(10_000);
+            let res = format!(
+                "HTTP/1.1 200 OK\r\nset-cookie: {}\r\ncontent-length: 0\r\n\r\n",
+                cookie
+            );
+            sock.write_all(res.as_bytes()).await.unwrap();
+        });
 
-            let service = service_fn(move |req: Request<hyper::body::Incoming>| {
-                tokio::task::spawn(async move {
-                    let io = &mut TokioIo::new(hyper::upgrade::on(req).await.unwrap());
-                    io.write_all(b"hello\n").await.unwrap();
-                });
+        let io = tcp_connect(&addr).await.expect("tcp connect");
+        let (mut client, conn) = conn::http1::Builder::new()
+            .initial_read_buf_size(64)
+            .max_read_buf_size(8192)
+            .handshake(io)
+            .await
+            .expect("http handshake");
+        tokio::spawn(async move {
+            let _ = conn.await;
+        });
 
-                future::ok::<_, hyper::Error>(Response::new(Empty::<Bytes>::new()))
-            });
+        // bodies stream through however small the buffer starts
+        let req = Request::get("/").body(Empty::<Bytes>::new()).unwrap();
+        let (_stats, res) = client.send_request(req).await.expect("send_request");
+        let body = res.into_body().collect().await.expect("body").to_bytes();
+        assert_eq!(body.len(), 100_000);
+
+        // but a head has to fit in the largest buffer
+        client.ready().await.expect("ready");
+        let req = Request::get("/").body(Empty::<Bytes>::new()).unwrap();
+        let err = client.send_request(req).await.expect_err("send_request");
+        assert!(err.is_parse(), "{:?}", err);
+        assert!(!err.is_parse_header_size_too_large(), "{:?}", err);
+    }
 
-            tokio::task::spawn(async move {
-                let conn = http2::Builder::new(TokioExecutor).serve_connection(stream, service);
-                let _ = conn.await;
-                tx.send(()).unwrap();
-            });
+    #[tokio::test]
+    async fn http1_forwards_preserved_header_case() {
+        let (listener, addr) = setup_tk_test_server().await;
+
+        let server = tokio::spawn(async move {
+            let mut sock = listener.accept().await.unwrap().0;
+            read_head(&mut sock).await;
+            sock.write_all(b"HTTP/1.1 200 OK\r\nX-Legacy-TOKEN: 1\r\ncontent-length: 0\r\n\r\n")
+                .await
+                .unwrap();
+            let head = read_head(&mut sock).await;
+            let head = String::from_utf8(head).unwrap();
+            assert!(head.contains("\r\nX-Legacy-TOKEN: abc\r\n"), "{:?}", head);
+            sock.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                .await
+                .unwrap();
         });
 
         let io = tcp_connect(&addr).await.expect("tcp connect");
-        let (mut client, conn) = conn::http2::Builder::new(TokioExecutor)
+        let (mut client, conn) = conn::http1::Builder::new()
+            .preserve_header_case(true)
             .handshake(io)
             .await
             .expect("http handshake");
+        tokio::spawn(async move {
+            let _ = conn.await;
+        });
 
-        tokio::task::spawn(async move {
-            conn.await.expect("client conn");
+        let req = Request::get("/").body(Empty::<Bytes>::new()).unwrap();
+        let (_stats, res) = client.send_request(req).await.expect("send_request");
+
+        // Forwarding the extensions carries the original casing with them,
+        // the way a proxy would.
+        let mut req = Request::get("/")
+            .header("x-legacy-token", "abc")
+            .body(Empty::<Bytes>::new())
+            .unwrap();
+        req.extensions_mut().extend(res.extensions().clone());
+        client.ready().await.expect("ready");
+        let (_stats, res) = client.send_request(req).await.expect("send_request");
+        assert_eq!(res.status(), StatusCode::OK);
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn http1_deadline_before_response() {
+        let (listener, addr) = setup_tk_test_server().await;
+
+        tokio::spawn(async move {
+            let mut sock = listener.accept().await.unwrap().0;
+            read_head(&mut sock).await;
+            // never respond
+            let mut buf = [0; 1];
+            let _ = sock.read(&mut buf).await;
         });
 
-        // Sanity check that client is ready
-        future::poll_fn(|ctx| client.poll_ready(ctx))
+        let io = tcp_connect(&addr).await.expect("tcp connect");
+        let (mut client, conn) = conn::http1::Builder::new()
+            .timer(TokioTimer)
+            .handshake(io)
             .await
-            .expect("client poll ready sanity");
-        let requests = 2;
-        let mut clients = vec![client.clone(), client];
-        let (tx, rx) = oneshot::channel::<()>();
-        let (tx2, rx2) = oneshot::channel::<()>();
-        let mut rxs = vec![rx, rx2];
-        for _i in 0..requests {
-            let mut client = clients.pop().unwrap();
-            let rx = rxs.pop().unwrap();
-            let req = Request::builder()
-                .method(Method::CONNECT)
-                .uri(format!("{}", addr))
-                .body(Empty::<Bytes>::new())
-                .expect("request builder");
+            .expect("http handshake");
+        let conn = tokio::spawn(conn);
 
-            let resp = client.send_request(req).await.expect("req1 send").1;
-            assert_eq!(resp.status(), 200);
-            let upgrade = hyper::upgrade::on(resp).await.unwrap();
-            tokio::task::spawn(async move {
-                let _ = rx.await;
-                drop(upgrade);
-            });
-        }
-        drop(tx);
-        drop(tx2);
-        tokio::time::timeout(Duration::from_secs(1), rxx)
+        let req = Request::get("/").body(Empty::<Bytes>::new()).unwrap();
+        let deadline = Instant::now() + Duration::from_millis(50);
+        let err = client
+            .send_request_with_deadline(req, deadline)
             .await
-            .expect("drop with 1s")
-            .expect("tx dropped without sending");
+            .expect_err("send_request_with_deadline");
+        assert!(err.is_timeout(), "{:?}", err);
+        let stats = err.stats().expect("stats");
+        assert!(stats.headers_written.is_some());
+        assert!(stats.first_header_byte_time.is_none());
+
+        // the connection is closed
+        conn.await.unwrap().expect("conn");
     }
 
     #[tokio::test]
-    async fn http2_keep_alive_detects_unresponsive_server() {
+    async fn http2_deadline_resets_stream() {
+        use hyper::service::service_fn;
+
         let (listener, addr) = setup_tk_test_server().await;
 
-        // spawn a server that reads but doesn't write
         tokio::spawn(async move {
-            let mut sock = listener.accept().await.unwrap().0;
-            let mut buf = [0u8; 1024];
-            loop {
-                let n = sock.read(&mut buf).await.expect("server read");
-                if n == 0 {
-                    // server closed, lets go!
-                    break;
-                }
-            }
+            let sock = TokioIo::new(listener.accept().await.unwrap().0);
+            hyper::server::conn::http2::Builder::new(TokioExecutor)
+                .serve_connection(
+                    sock,
+                    service_fn(|req: Request<hyper::body::Incoming>| async move {
+                        if req.uri().path() == "/slow" {
+                            tokio::time::sleep(Duration::from_secs(5)).await;
+                        }
+                        Ok::<_, hyper::Error>(Response::new(Full::new(Bytes::from("hello"))))
+                    }),
+                )
+                .await
+                .expect("serve_connection");
         });
 
         let io = tcp_connect(&addr).await.expect("tcp connect");
-        let (_client, conn) = conn::http2::Builder::new(TokioExecutor)
+        let (mut client, conn) = conn::http2::Builder::new(TokioExecutor)
             .timer(TokioTimer)
-            .keep_alive_interval(Duration::from_secs(1))
-            .keep_alive_timeout(Duration::from_secs(1))
-            // enable while idle since we aren't sending requests
-            .keep_alive_while_idle(true)
-            .handshake::<_, hyper::body::Incoming>(io)
+            .handshake(io)
             .await
             .expect("http handshake");
+        tokio::spawn(async move {
+            conn.await.expect("client conn shouldn't error");
+        });
 
-        conn.await.expect_err("conn should time out");
+        let req = Request::get("/slow").body(Empty::<Bytes>::new()).unwrap();
+        let deadline = Instant::now() + Duration::from_millis(50);
+        let err = client
+            .send_request_with_deadline(req, deadline)
+            .await
+            .expect_err("send_request_with_deadline");
+        assert!(err.is_timeout(), "{:?}", err);
+        assert!(err.stats().is_some());
+
+        // other requests on the connection still work
+        let req = Request::get("/fast").body(Empty::<Bytes>::new()).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let (_stats, res) = client
+            .send_request_with_deadline(req, deadline)
+            .await
+            .expect("send_request_with_deadline");
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = res.into_body().collect().await.expect("body").to_bytes();
+        assert_eq!(body, "hello");
     }
 
     #[tokio::test]
-    async fn http2_keep_alive_not_while_idle() {
-        // This tests that not setting `http2_keep_alive_while_idle(true)`
-        // will use the default behavior which will NOT detect the server
-        // is unresponsive while no streams are active.
+    async fn pool_reuses_http1_connection() {
+        use hyper::client::pool::Pool;
+
+        let (server, addr) = setup_std_test_server();
+
+        thread::spawn(move || {
+            let mut sock = server.accept().unwrap().0;
+            sock.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            sock.set_write_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            let mut buf = [0; 4096];
+            for _ in 0..2 {
+                sock.read(&mut buf).expect("read");
+                sock.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                    .unwrap();
+            }
+        });
+
+        let pool = Pool::<&str, conn::http1::SendRequest<Empty<Bytes>>>::new();
+        let checkout = pool.checkout("a");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        let tcp = tcp_connect(&addr).await.unwrap();
+        let (client, conn) = conn::http1::handshake(tcp).await.unwrap();
+        tokio::spawn(async move {
+            let _ = conn.await;
+        });
+
+        let mut client = checkout.connected(client);
+        assert!(!client.is_reused());
+        let req = Request::get("/a").body(Empty::<Bytes>::new()).unwrap();
+        let (stats, _res) = client.send_request(req).await.expect("send_request");
+        let wait = stats.get_pool_wait().expect("pool wait");
+        assert!(wait >= Duration::from_millis(10), "{:?}", wait);
+        assert_eq!(pool.idle_count(&"a"), 0);
+        drop(client);
+        assert_eq!(pool.idle_count(&"a"), 1);
+
+        let mut client = pool.checkout("a").await;
+        assert!(client.is_reused());
+        client.ready().await.expect("ready");
+        let req = Request::get("/b").body(Empty::<Bytes>::new()).unwrap();
+        let (stats, _res) = client.send_request(req).await.expect("send_request");
+        assert_eq!(stats.connection_request_count, 2);
+    }
+
+    #[tokio::test]
+    async fn pool_shares_http2_connection() {
+        use hyper::client::pool::Pool;
+        use hyper::service::service_fn;
 
         let (listener, addr) = setup_tk_test_server().await;
 
-        // spawn a server that reads but doesn't write
         tokio::spawn(async move {
-            let sock = listener.accept().await.unwrap().0;
-            drain_til_eof(sock).await.expect("server read");
+            let sock = TokioIo::new(listener.accept().await.unwrap().0);
+            hyper::server::conn::http2::Builder::new(TokioExecutor)
+                .serve_connection(
+                    sock,
+                    service_fn(|_req| async move {
+                        Ok::<_, hyper::Error>(Response::new(Full::new(Bytes::from("hello"))))
+                    }),
+                )
+                .await
+                .expect("serve_connection");
         });
 
         let io = tcp_connect(&addr).await.expect("tcp connect");
-        let (mut client, conn) = conn::http2::Builder::new(TokioExecutor)
-            .timer(TokioTimer)
-            .keep_alive_interval(Duration::from_secs(1))
-            .keep_alive_timeout(Duration::from_secs(1))
-            .handshake::<_, hyper::body::Incoming>(io)
+        let (client, conn) = conn::http2::Builder::new(TokioExecutor)
+            .handshake(io)
             .await
             .expect("http handshake");
-
         tokio::spawn(async move {
             conn.await.expect("client conn shouldn't error");
         });
 
-        // sleep longer than keepalive would trigger
-        TokioTimer.sleep(Duration::from_secs(4)).await;
-
-        future::poll_fn(|ctx| client.poll_ready(ctx))
-            .await
-            .expect("client should be open");
+        let pool = Pool::<&str, conn::http2::SendRequest<Full<Bytes>>>::new();
+        let mut first = pool.insert("a", client);
+        let mut second = pool.checkout("a").await;
+        assert!(second.is_reused());
+        assert_eq!(pool.idle_count(&"a"), 1);
+
+        let req = || Request::get("/").body(Full::new(Bytes::new())).unwrap();
+        let (a, b) = future::join(first.send_request(req()), second.send_request(req())).await;
+        let (a, b) = (a.expect("first").0, b.expect("second").0);
+        assert_eq!(a.connection_id, b.connection_id);
+        assert!(b.get_pool_wait().is_some());
     }
 
     #[tokio::test]
-    async fn http2_keep_alive_closes_open_streams() {
+    async fn http2_stream_frame_stats() {
+        use hyper::service::service_fn;
+
         let (listener, addr) = setup_tk_test_server().await;
 
-        // spawn a server that reads but doesn't write
         tokio::spawn(async move {
-            let sock = listener.accept().await.unwrap().0;
-            drain_til_eof(sock).await.expect("server read");
+            let sock = TokioIo::new(listener.accept().await.unwrap().0);
+            hyper::server::conn::http2::Builder::new(TokioExecutor)
+                .serve_connection(
+                    sock,
+                    service_fn(|_req| async move {
+                        Ok::<_, hyper::Error>(Response::new(Full::new(Bytes::from("hello"))))
+                    }),
+                )
+                .await
+                .expect("serve_connection");
         });
 
         let io = tcp_connect(&addr).await.expect("tcp connect");
         let (mut client, conn) = conn::http2::Builder::new(TokioExecutor)
-            .timer(TokioTimer)
-            .keep_alive_interval(Duration::from_secs(1))
-            .keep_alive_timeout(Duration::from_secs(1))
             .handshake(io)
             .await
             .expect("http handshake");
 
         tokio::spawn(async move {
-            let err = conn.await.expect_err("client conn should timeout");
-            assert!(err.is_timeout());
+            conn.await.expect("client conn shouldn't error");
         });
 
-        let req = http::Request::new(Empty::<Bytes>::new());
-        let err = client
-            .send_request(req)
-            .await
-            .expect_err("request should timeout");
-        assert!(err.is_timeout());
+        let req = Request::get("/a").body(Empty::<Bytes>::new()).unwrap();
+        let (stats, res) = client.send_request(req).await.expect("send_request");
 
-        let err = future::poll_fn(|ctx| client.poll_ready(ctx))
-            .await
-            .expect_err("client should be closed");
-        assert!(
-            err.is_closed(),
-            "poll_ready error should be closed: {:?}",
-            err
-        );
+        let headers = stats.h2_stream.expect("h2 stream stats").headers;
+        assert!(headers.is_some());
+        assert_eq!(stats.first_header_byte_time, headers);
+
+        let mut body = res.into_body();
+        while let Some(frame) = body.frame().await {
+            frame.expect("body frame");
+        }
+        let frames = body.h2_stream_stats().expect("h2 stream stats");
+        assert!(frames.first_data.is_some());
+        assert!(frames.end_stream.is_some());
+        assert!(stats.request_header_bytes > 0);
+        assert_eq!(stats.request_body_bytes, 0);
+        assert!(stats.response_header_bytes > 0);
+        assert!(body.body_bytes_received() > 0);
     }
 
     #[tokio::test]
-    async fn http2_keep_alive_with_responsive_server() {
-        // Test that a responsive server works just when client keep
-        // alive is enabled
+    async fn http2_send_stall_stats() {
         use hyper::service::service_fn;
 
         let (listener, addr) = setup_tk_test_server().await;
 
-        // Spawn an HTTP2 server that reads the whole body and responds
+        // A server with a tiny window, that waits a while before reading.
         tokio::spawn(async move {
             let sock = TokioIo::new(listener.accept().await.unwrap().0);
             hyper::server::conn::http2::Builder::new(TokioExecutor)
                 .timer(TokioTimer)
+                .initial_stream_window_size(1024)
                 .serve_connection(
                     sock,
                     service_fn(|req| async move {
-                        tokio::spawn(async move {
-                            let _ = concat(req.into_body())
-                                .await
-                                .expect("server req body aggregate");
-                        });
-                        Ok::<_, hyper::Error>(http::Response::new(Empty::<Bytes>::new()))
+                        TokioTimer.sleep(Duration::from_millis(50)).await;
+                        concat(req.into_body()).await?;
+                        Ok::<_, hyper::Error>(Response::new(Empty::<Bytes>::new()))
                     }),
                 )
                 .await
@@ -2515,9 +4679,6 @@ mod conn {
 
         let io = tcp_connect(&addr).await.expect("tcp connect");
         let (mut client, conn) = conn::http2::Builder::new(TokioExecutor)
-            .timer(TokioTimer)
-            .keep_alive_interval(Duration::from_secs(1))
-            .keep_alive_timeout(Duration::from_secs(1))
             .handshake(io)
             .await
             .expect("http handshake");
@@ -2526,18 +4687,16 @@ mod conn {
             conn.await.expect("client conn shouldn't error");
         });
 
-        // Use a channel to keep request stream open
-        let (_tx, recv) = mpsc::channel::<Result<Frame<Bytes>, Box<dyn Error + Send + Sync>>>(0);
-        let req = http::Request::new(StreamBody::new(recv));
-
-        let _resp = client.send_request(req).await.expect("send_request");
-
-        // sleep longer than keepalive would trigger
-        TokioTimer.sleep(Duration::from_secs(4)).await;
+        let chunks = (0..32).map(|_| {
+            Ok::<_, std::convert::Infallible>(Frame::data(Bytes::from(vec![0; 16 * 1024])))
+        });
+        let body = StreamBody::new(futures_util::stream::iter(chunks));
+        let req = Request::post("/a").body(body).unwrap();
+        let (stats, _res) = client.send_request(req).await.expect("send_request");
 
-        future::poll_fn(|ctx| client.poll_ready(ctx))
-            .await
-            .expect("client should be open");
+        let stalled = stats.h2_stream.expect("h2 stream stats").send_stalled;
+        assert!(stalled >= Duration::from_millis(20), "{:?}", stalled);
+        assert_eq!(stats.h2.expect("h2 stats").send_stalled, stalled);
     }
 
     #[tokio::test]
@@ -2649,6 +4808,71 @@ mod conn {
         upgraded.write_all(b"Baguette!").await.unwrap();
 
         upgraded.shutdown().await.unwrap();
+
+        let tunnel = upgraded.inner().tunnel_stats().expect("tunnel stats");
+        assert_eq!(tunnel.bytes_in, 6);
+        assert_eq!(tunnel.bytes_out, 9);
+        assert!(tunnel.connect_start <= tunnel.established_at);
+        assert!(tunnel.closed_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn h2_connect_multiplexed() {
+        use hyper::service::service_fn;
+
+        let (listener, addr) = setup_tk_test_server().await;
+
+        // Spawn an HTTP2 server that echoes back whatever goes through a
+        // tunnel.
+        tokio::spawn(async move {
+            let sock = TokioIo::new(listener.accept().await.unwrap().0);
+            hyper::server::conn::http2::Builder::new(TokioExecutor)
+                .serve_connection(
+                    sock,
+                    service_fn(|req| async move {
+                        tokio::spawn(async move {
+                            let upgraded = hyper::upgrade::on(req).await.expect("server upgrade");
+                            let (mut rd, mut wr) = tokio::io::split(TokioIo::new(upgraded));
+                            tokio::io::copy(&mut rd, &mut wr)
+                                .await
+                                .expect("server echo");
+                            wr.shutdown().await.expect("server shutdown");
+                        });
+                        Ok::<_, hyper::Error>(Response::new(Empty::<Bytes>::new()))
+                    }),
+                )
+                .await
+                .expect("serve_connection");
+        });
+
+        let io = tcp_connect(&addr).await.expect("tcp connect");
+        let (client, conn) = conn::http2::Builder::new(TokioExecutor)
+            .handshake::<_, Empty<Bytes>>(io)
+            .await
+            .expect("http handshake");
+
+        tokio::spawn(async move {
+            conn.await.expect("client conn shouldn't error");
+        });
+
+        let tunnel = |mut client: conn::http2::SendRequest<Empty<Bytes>>, msg: &'static str| async move {
+            let req = Request::connect("localhost").body(Empty::new()).unwrap();
+            let res = client.send_request(req).await.expect("send_request").1;
+            assert_eq!(res.status(), StatusCode::OK);
+            let mut upgraded = TokioIo::new(hyper::upgrade::on(res).await.unwrap());
+            upgraded.write_all(msg.as_bytes()).await.unwrap();
+            upgraded.shutdown().await.unwrap();
+            let mut vec = vec![];
+            upgraded.read_to_end(&mut vec).await.unwrap();
+            assert_eq!(s(&vec), msg);
+        };
+
+        // both tunnels are open on the one connection at once
+        future::join(
+            tunnel(client.clone(), "Bread?"),
+            tunnel(client.clone(), "Baguette!"),
+        )
+        .await;
     }
 
     #[tokio::test]
@@ -2701,6 +4925,39 @@ mod conn {
         done_tx.send(()).unwrap();
     }
 
+    #[tokio::test]
+    async fn h2_refused_stream_reason() {
+        let (listener, addr) = setup_tk_test_server().await;
+
+        tokio::spawn(async move {
+            let sock = listener.accept().await.unwrap().0;
+            let mut h2 = h2::server::handshake(sock).await.unwrap();
+
+            let (_req, mut respond) = h2.accept().await.unwrap().unwrap();
+            respond.send_reset(h2::Reason::REFUSED_STREAM);
+            poll_fn(|cx| h2.poll_closed(cx)).await.unwrap();
+        });
+
+        let io = tcp_connect(&addr).await.expect("tcp connect");
+        let (mut client, conn) = conn::http2::Builder::new(TokioExecutor)
+            .handshake::<_, Empty<Bytes>>(io)
+            .await
+            .expect("http handshake");
+
+        tokio::spawn(async move {
+            conn.await.expect("client conn shouldn't error");
+        });
+
+        let req = Request::get("http://localhost/")
+            .body(Empty::new())
+            .unwrap();
+        let err = client
+            .send_request(req)
+            .await
+            .expect_err("stream was refused");
+        assert_eq!(err.h2_reason(), Some(0x7));
+    }
+
     #[tokio::test]
     async fn test_body_panics() {
         let (listener, addr) = setup_tk_test_server().await;
@@ -2745,6 +5002,68 @@ mod conn {
         }
     }
 
+    /// A transport that reports how it was set up, as TLS glue would.
+    struct StatsIo {
+        tcp: TokioIo<TcpStream>,
+        stats: hyper::rt::ConnectionStats,
+    }
+
+    impl StatsIo {
+        async fn connect(addr: &SocketAddr) -> StatsIo {
+            let start = std::time::Instant::now();
+            let tcp = tcp_connect(addr).await.expect("tcp connect");
+            let mut stats = hyper::rt::ConnectionStats {
+                start_time: Some(start),
+                connect_start: Some(start),
+                connect_end: Some(std::time::Instant::now()),
+                peer_addr: Some(*addr),
+                ..Default::default()
+            };
+            stats.set_alpn(b"h2");
+            StatsIo { tcp, stats }
+        }
+    }
+
+    impl hyper::rt::Stats for StatsIo {
+        fn stats(&mut self) -> Option<hyper::rt::ConnectionStats> {
+            Some(self.stats)
+        }
+    }
+
+    impl hyper::rt::Write for StatsIo {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<Result<usize, io::Error>> {
+            Pin::new(&mut self.tcp).poll_write(cx, buf)
+        }
+
+        fn poll_flush(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+        ) -> Poll<Result<(), io::Error>> {
+            Pin::new(&mut self.tcp).poll_flush(cx)
+        }
+
+        fn poll_shutdown(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+        ) -> Poll<Result<(), io::Error>> {
+            Pin::new(&mut self.tcp).poll_shutdown(cx)
+        }
+    }
+
+    impl hyper::rt::Read for StatsIo {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: hyper::rt::ReadBufCursor<'_>,
+        ) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.tcp).poll_read(cx, buf)
+        }
+    }
+
     struct DebugStream {
         tcp: TokioIo<TcpStream>,
         shutdown_called: bool,
@@ -6,12 +6,12 @@ use std::io::{self, Read, Write};
 use std::net::TcpListener as StdTcpListener;
 use std::net::{Shutdown, SocketAddr, TcpStream};
 use std::pin::Pin;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::mpsc;
 use std::sync::{Arc, Mutex};
 use std::task::{Context, Poll};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use bytes::Bytes;
 use futures_channel::oneshot;
@@ -27,7 +27,7 @@ use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::{TcpListener as TkTcpListener, TcpListener, TcpStream as TkTcpStream};
 
 use hyper::body::{Body, Incoming as IncomingBody};
-use hyper::server::conn::{http1, http2};
+use hyper::server::conn::{auto, http1, http2};
 use hyper::service::{service_fn, Service};
 use hyper::{Method, Request, Response, StatusCode, Uri, Version};
 use tokio::pin;
@@ -1053,6 +1053,136 @@ async fn expect_continue_waits_for_body_poll() {
     child.join().expect("client thread");
 }
 
+#[tokio::test]
+async fn expect_continue_reject_sends_417() {
+    let (listener, addr) = setup_tcp_listener();
+
+    let child = thread::spawn(move || {
+        let mut tcp = connect(&addr);
+
+        tcp.write_all(
+            b"\
+            POST /foo HTTP/1.1\r\n\
+            Host: example.domain\r\n\
+            Expect: 100-continue\r\n\
+            Content-Length: 100\r\n\
+            \r\n\
+        ",
+        )
+        .expect("write");
+
+        let mut resp = String::new();
+        tcp.read_to_string(&mut resp).expect("read");
+        resp
+    });
+
+    let (socket, _) = listener.accept().await.expect("accept");
+    let socket = TokioIo::new(socket);
+
+    let err = http1::Builder::new()
+        .expect_continue(http1::ExpectContinue::Reject)
+        .serve_connection(
+            socket,
+            service_fn(|_req| async move {
+                panic!("service called for a rejected expectation");
+                #[allow(unreachable_code)]
+                Ok::<_, hyper::Error>(Response::new(Empty::<Bytes>::new()))
+            }),
+        )
+        .await
+        .expect_err("serve_connection");
+    assert!(!err.is_parse(), "{:?}", err);
+
+    let resp = child.join().expect("client thread");
+    let expected = "HTTP/1.1 417 Expectation Failed\r\n";
+    assert_eq!(&resp[..expected.len()], expected);
+    assert!(!resp.contains("100 Continue"), "{:?}", resp);
+}
+
+#[tokio::test]
+async fn options_asterisk_form() {
+    let (listener, addr) = setup_tcp_listener();
+
+    let child = thread::spawn(move || {
+        let mut tcp = connect(&addr);
+
+        tcp.write_all(
+            b"\
+            OPTIONS * HTTP/1.1\r\n\
+            Host: example.domain\r\n\
+            Connection: close\r\n\
+            \r\n\
+        ",
+        )
+        .expect("write");
+
+        let expected = "HTTP/1.1 200 OK\r\n";
+        let mut resp = String::new();
+        tcp.read_to_string(&mut resp).expect("read");
+
+        assert_eq!(&resp[..expected.len()], expected);
+    });
+
+    let (socket, _) = listener.accept().await.expect("accept");
+    let socket = TokioIo::new(socket);
+
+    http1::Builder::new()
+        .serve_connection(
+            socket,
+            service_fn(|req| {
+                assert_eq!(req.method(), Method::OPTIONS);
+                assert_eq!(req.uri(), "*");
+                future::ok::<_, hyper::Error>(Response::new(Empty::<Bytes>::new()))
+            }),
+        )
+        .await
+        .expect("serve_connection");
+
+    child.join().expect("client thread");
+}
+
+#[tokio::test]
+async fn asterisk_form_rejected_for_other_methods() {
+    let (listener, addr) = setup_tcp_listener();
+
+    let child = thread::spawn(move || {
+        let mut tcp = connect(&addr);
+
+        tcp.write_all(
+            b"\
+            GET * HTTP/1.1\r\n\
+            Host: example.domain\r\n\
+            \r\n\
+        ",
+        )
+        .expect("write");
+
+        let expected = "HTTP/1.1 400 Bad Request\r\n";
+        let mut resp = String::new();
+        tcp.read_to_string(&mut resp).expect("read");
+
+        assert_eq!(&resp[..expected.len()], expected);
+    });
+
+    let (socket, _) = listener.accept().await.expect("accept");
+    let socket = TokioIo::new(socket);
+
+    let err = http1::Builder::new()
+        .serve_connection(
+            socket,
+            service_fn(
+                |_| -> future::Ready<Result<Response<Empty<Bytes>>, hyper::Error>> {
+                    panic!("service should not be called")
+                },
+            ),
+        )
+        .await
+        .expect_err("serve_connection");
+    assert!(err.is_parse(), "{:?}", err);
+
+    child.join().expect("client thread");
+}
+
 #[test]
 fn pipeline_disabled() {
     let server = serve();
@@ -1187,6 +1317,292 @@ fn http_11_uri_too_long() {
     assert_eq!(s(&buf[..expected.len()]), expected);
 }
 
+#[tokio::test]
+async fn http1_allow_invalid_request_target() {
+    let (listener, addr) = setup_tcp_listener();
+    let (tx, rx) = mpsc::channel();
+
+    thread::spawn(move || {
+        let mut tcp = connect(&addr);
+        tcp.write_all(
+            b"\
+            GET http://[::1/foo HTTP/1.1\r\n\
+            Host: example.domain\r\n\
+            \r\n\
+            GET /bar HTTP/1.1\r\n\
+            Host: example.domain\r\n\
+            Connection: close\r\n\
+            \r\n\
+        ",
+        )
+        .expect("write");
+        let mut buf = Vec::new();
+        tcp.read_to_end(&mut buf).expect("read");
+        tx.send(String::from_utf8(buf).expect("utf8")).unwrap();
+    });
+
+    let (socket, _) = listener.accept().await.unwrap();
+    http1::Builder::new()
+        .allow_invalid_request_target(true)
+        .serve_connection(
+            TokioIo::new(socket),
+            service_fn(|req: Request<IncomingBody>| {
+                let raw = req.extensions().get::<hyper::ext::RawRequestTarget>();
+                let body = match raw {
+                    Some(raw) => {
+                        assert_eq!(req.uri(), "/");
+                        raw.as_bytes().to_vec()
+                    }
+                    None => req.uri().path().as_bytes().to_vec(),
+                };
+                future::ok::<_, hyper::Error>(Response::new(Full::new(Bytes::from(body))))
+            }),
+        )
+        .await
+        .unwrap();
+
+    let res = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(res.matches("HTTP/1.1 200 OK\r\n").count(), 2, "{}", res);
+    assert!(res.contains("\r\n\r\nhttp://[::1/fooHTTP/1.1"), "{}", res);
+    assert!(res.ends_with("\r\n\r\n/bar"), "{}", res);
+}
+
+#[tokio::test]
+async fn http1_lenient_request_parsing() {
+    async fn serve_lenient(lenient: bool) -> String {
+        let (listener, addr) = setup_tcp_listener();
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            let mut tcp = connect(&addr);
+            tcp.write_all(
+                b"\
+                GET  /foo  HTTP/1.1\r\n \
+                Host: example.domain\r\n\
+                not a header\r\n\
+                Connection: close\n\
+                \n\
+            ",
+            )
+            .expect("write");
+            let mut buf = Vec::new();
+            tcp.read_to_end(&mut buf).expect("read");
+            tx.send(String::from_utf8(buf).expect("utf8")).unwrap();
+        });
+
+        let (socket, _) = listener.accept().await.unwrap();
+        let _ = http1::Builder::new()
+            .allow_multiple_spaces_in_request_line_delimiters(lenient)
+            .allow_space_before_first_header_name(lenient)
+            .ignore_invalid_headers(lenient)
+            .serve_connection(
+                TokioIo::new(socket),
+                service_fn(|req: Request<IncomingBody>| {
+                    assert_eq!(req.uri(), "/foo");
+                    assert_eq!(req.headers()["host"], "example.domain");
+                    assert_eq!(req.headers().len(), 2);
+                    future::ok::<_, hyper::Error>(Response::new(Empty::<Bytes>::new()))
+                }),
+            )
+            .await;
+
+        rx.recv_timeout(Duration::from_secs(5)).unwrap()
+    }
+
+    let res = serve_lenient(true).await;
+    assert!(res.starts_with("HTTP/1.1 200 OK\r\n"), "{}", res);
+
+    let res = serve_lenient(false).await;
+    assert!(res.starts_with("HTTP/1.1 400 Bad Request\r\n"), "{}", res);
+}
+
+#[tokio::test]
+async fn http1_response_header_case() {
+    use hyper::ext::HeaderCaseMap;
+
+    let (listener, addr) = setup_tcp_listener();
+    let (tx, rx) = mpsc::channel();
+
+    thread::spawn(move || {
+        let mut tcp = connect(&addr);
+        tcp.write_all(
+            b"\
+            GET / HTTP/1.1\r\n\
+            x-REQUEST-id: 42\r\n\
+            Connection: close\r\n\
+            \r\n\
+        ",
+        )
+        .expect("write");
+        let mut buf = Vec::new();
+        tcp.read_to_end(&mut buf).expect("read");
+        tx.send(String::from_utf8(buf).expect("utf8")).unwrap();
+    });
+
+    let (socket, _) = listener.accept().await.unwrap();
+    http1::Builder::new()
+        .preserve_header_case(true)
+        .title_case_headers(true)
+        .serve_connection(
+            TokioIo::new(socket),
+            service_fn(|req: Request<IncomingBody>| {
+                let received = req.extensions().get::<HeaderCaseMap>().unwrap();
+                let spelling = received.get_all(&"x-request-id".parse().unwrap()).next();
+                assert_eq!(spelling, Some(&b"x-REQUEST-id"[..]));
+
+                let mut case = HeaderCaseMap::new();
+                case.append_spelling("X-REQUEST-ID").unwrap();
+                case.append_spelling("etag").unwrap();
+                assert!(case.append_spelling("bad name").is_err());
+                let res = Response::builder()
+                    .header("x-request-id", "42")
+                    .header("etag", "\"1\"")
+                    .header("cache-control", "no-store")
+                    .extension(case)
+                    .body(Empty::<Bytes>::new())
+                    .unwrap();
+                future::ok::<_, hyper::Error>(res)
+            }),
+        )
+        .await
+        .unwrap();
+
+    let res = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert!(res.starts_with("HTTP/1.1 200 OK\r\n"), "{}", res);
+    assert!(res.contains("\r\nX-REQUEST-ID: 42\r\n"), "{}", res);
+    assert!(res.contains("\r\netag: \"1\"\r\n"), "{}", res);
+    assert!(res.contains("\r\nCache-Control: no-store\r\n"), "{}", res);
+}
+
+#[tokio::test]
+async fn http1_conflicting_framing_passthrough() {
+    use hyper::ext::ConflictingContentLength;
+    use hyper::server::conn::http1::ConflictingFraming;
+
+    let (listener, addr) = setup_tcp_listener();
+    let (tx, rx) = mpsc::channel();
+
+    thread::spawn(move || {
+        let mut tcp = connect(&addr);
+        tcp.write_all(
+            b"\
+            POST / HTTP/1.1\r\n\
+            Content-Length: 3\r\n\
+            Transfer-Encoding: chunked\r\n\
+            \r\n\
+            5\r\n\
+            hello\r\n\
+            0\r\n\
+            \r\n\
+        ",
+        )
+        .expect("write");
+        let mut buf = Vec::new();
+        tcp.read_to_end(&mut buf).expect("read");
+        tx.send(String::from_utf8(buf).expect("utf8")).unwrap();
+    });
+
+    let (socket, _) = listener.accept().await.unwrap();
+    http1::Builder::new()
+        .conflicting_framing(ConflictingFraming::Passthrough)
+        .serve_connection(
+            TokioIo::new(socket),
+            service_fn(|req: Request<IncomingBody>| async move {
+                assert!(!req.headers().contains_key("content-length"));
+                let conflict = req.extensions().get::<ConflictingContentLength>().unwrap();
+                assert_eq!(conflict.values(), ["3"]);
+                let body = req.into_body().collect().await?.to_bytes();
+                assert_eq!(body, "hello");
+                Ok::<_, hyper::Error>(Response::new(Empty::<Bytes>::new()))
+            }),
+        )
+        .await
+        .unwrap();
+
+    // the connection is closed after the response
+    let res = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert!(res.starts_with("HTTP/1.1 200 OK\r\n"), "{}", res);
+}
+
+#[tokio::test]
+async fn http1_serve_connection_with_info() {
+    #[derive(Clone)]
+    struct ConnInfo(SocketAddr);
+
+    let (listener, addr) = setup_tcp_listener();
+
+    thread::spawn(move || {
+        let mut tcp = connect(&addr);
+        tcp.write_all(
+            b"\
+            GET /a HTTP/1.1\r\n\
+            \r\n\
+            GET /b HTTP/1.1\r\n\
+            Connection: close\r\n\
+            \r\n\
+        ",
+        )
+        .unwrap();
+        let mut buf = Vec::new();
+        tcp.read_to_end(&mut buf).unwrap();
+    });
+
+    let (socket, peer_addr) = listener.accept().await.unwrap();
+    let served = Arc::new(AtomicUsize::new(0));
+    let served2 = served.clone();
+    http1::Builder::new()
+        .serve_connection_with_info(
+            TokioIo::new(socket),
+            service_fn(move |req: Request<IncomingBody>| {
+                let info = req.extensions().get::<ConnInfo>().expect("connection info");
+                assert_eq!(info.0, peer_addr);
+                served2.fetch_add(1, Ordering::SeqCst);
+                future::ok::<_, hyper::Error>(Response::new(Empty::<Bytes>::new()))
+            }),
+            ConnInfo(peer_addr),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(served.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn http1_segments_body() {
+    let (listener, addr) = setup_tcp_listener();
+
+    thread::spawn(move || {
+        let mut tcp = connect(&addr);
+        tcp.write_all(
+            b"\
+            GET / HTTP/1.1\r\n\
+            Connection: close\r\n\
+            \r\n\
+        ",
+        )
+        .unwrap();
+        let mut buf = String::new();
+        tcp.read_to_string(&mut buf).unwrap();
+        assert!(buf.ends_with("\r\n\r\nhello world"), "{:?}", buf);
+    });
+
+    let (socket, _) = listener.accept().await.unwrap();
+    http1::Builder::new()
+        .serve_connection(
+            TokioIo::new(socket),
+            service_fn(|_| {
+                let data = hyper::body::Segments::from(vec![
+                    Bytes::from("hello"),
+                    Bytes::from(" "),
+                    Bytes::from("world"),
+                ]);
+                future::ok::<_, hyper::Error>(Response::new(hyper::body::Full::new(data)))
+            }),
+        )
+        .await
+        .unwrap();
+}
+
 #[tokio::test]
 async fn disable_keep_alive_mid_request() {
     let (listener, addr) = setup_tcp_listener();
@@ -1413,31 +1829,364 @@ async fn http1_allow_half_close() {
 
 #[cfg(feature = "http1")]
 #[tokio::test]
-async fn disconnect_after_reading_request_before_responding() {
+async fn http1_half_close_after_request_body() {
     let (listener, addr) = setup_tcp_listener();
+    let (tx, rx) = mpsc::channel();
 
     thread::spawn(move || {
         let mut tcp = connect(&addr);
-        tcp.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+        tcp.write_all(
+            b"\
+            POST /jobs HTTP/1.1\r\n\
+            Content-Length: 5\r\n\
+            \r\n\
+            hello\
+        ",
+        )
+        .unwrap();
+        tcp.shutdown(::std::net::Shutdown::Write).expect("SHDN_WR");
+
+        let mut buf = Vec::new();
+        tcp.read_to_end(&mut buf).unwrap();
+        tx.send(buf).unwrap();
     });
 
     let (socket, _) = listener.accept().await.unwrap();
     let socket = TokioIo::new(socket);
     http1::Builder::new()
-        .half_close(false)
+        .half_close(true)
         .serve_connection(
             socket,
-            service_fn(|_| {
-                TokioTimer.sleep(Duration::from_secs(2)).map(
-                    |_| -> Result<Response<IncomingBody>, hyper::Error> {
-                        panic!("response future should have been dropped");
-                    },
-                )
+            service_fn(|req: Request<IncomingBody>| async move {
+                let body = req.into_body().collect().await?.to_bytes();
+                assert_eq!(body, "hello");
+                TokioTimer.sleep(Duration::from_millis(50)).await;
+                let chunks = (0..64).map(|_| {
+                    Ok::<_, hyper::Error>(hyper::body::Frame::data(Bytes::from(vec![
+                        b'x';
+                        16 * 1024
+                    ])))
+                });
+                let body = StreamBody::new(futures_util::stream::iter(chunks));
+                Ok::<_, hyper::Error>(Response::new(body))
             }),
         )
         .await
-        .expect_err("socket disconnected");
-}
+        .unwrap();
+
+    let buf = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    let res = String::from_utf8(buf).unwrap();
+    assert!(res.starts_with("HTTP/1.1 200 OK\r\n"), "{:?}", &res[..64]);
+    let body = &res[res.find("\r\n\r\n").unwrap() + 4..];
+    assert!(body.ends_with("0\r\n\r\n"));
+    assert_eq!(body.matches('x').count(), 64 * 16 * 1024);
+}
+
+#[cfg(feature = "http1")]
+#[tokio::test]
+async fn disconnect_after_reading_request_before_responding() {
+    let (listener, addr) = setup_tcp_listener();
+
+    thread::spawn(move || {
+        let mut tcp = connect(&addr);
+        tcp.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+    });
+
+    let (socket, _) = listener.accept().await.unwrap();
+    let socket = TokioIo::new(socket);
+    http1::Builder::new()
+        .half_close(false)
+        .serve_connection(
+            socket,
+            service_fn(|_| {
+                TokioTimer.sleep(Duration::from_secs(2)).map(
+                    |_| -> Result<Response<IncomingBody>, hyper::Error> {
+                        panic!("response future should have been dropped");
+                    },
+                )
+            }),
+        )
+        .await
+        .expect_err("socket disconnected");
+}
+
+#[tokio::test]
+async fn http1_record_stats() {
+    let (listener, addr) = setup_tcp_listener();
+
+    thread::spawn(move || {
+        let mut tcp = connect(&addr);
+        tcp.write_all(
+            b"\
+            POST /stats HTTP/1.1\r\n\
+            Content-Length: 5\r\n\
+            Connection: close\r\n\
+            \r\n\
+            hello\
+        ",
+        )
+        .unwrap();
+        let mut buf = Vec::new();
+        tcp.read_to_end(&mut buf).unwrap();
+    });
+
+    let (socket, _) = listener.accept().await.unwrap();
+    let socket = TokioIo::new(socket);
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let seen2 = seen.clone();
+    http1::Builder::new()
+        .record_stats(true)
+        .on_stats(move |stats| seen2.lock().unwrap().push(stats))
+        .serve_connection(
+            socket,
+            service_fn(|req: Request<IncomingBody>| async move {
+                let stats = req
+                    .extensions()
+                    .get::<hyper::ServerRequestStats>()
+                    .cloned()
+                    .expect("stats extension");
+                assert!(stats.body_complete.is_none());
+                assert!(stats.response_flushed.is_none());
+                let body = req.into_body().collect().await?.to_bytes();
+                assert_eq!(body, "hello");
+                Ok::<_, hyper::Error>(Response::new(Full::new(Bytes::from("world"))))
+            }),
+        )
+        .await
+        .expect("serve_connection");
+
+    let seen = seen.lock().unwrap();
+    assert_eq!(seen.len(), 1);
+    let stats = &seen[0];
+    assert_eq!(stats.method, Method::POST);
+    assert_eq!(stats.uri, "/stats");
+    assert_eq!(stats.status, Some(StatusCode::OK));
+    let body_complete = stats.body_complete.expect("body_complete");
+    let flushed = stats.response_flushed.expect("response_flushed");
+    assert!(stats.headers_parsed <= body_complete);
+    assert!(body_complete <= flushed);
+}
+
+#[tokio::test]
+async fn http1_chunk_extension_stats() {
+    let (listener, addr) = setup_tcp_listener();
+
+    thread::spawn(move || {
+        let mut tcp = connect(&addr);
+        tcp.write_all(
+            b"\
+            POST / HTTP/1.1\r\n\
+            Transfer-Encoding: chunked\r\n\
+            Connection: close\r\n\
+            \r\n\
+            3;a=b\r\n\
+            foo\r\n\
+            3;cd\r\n\
+            bar\r\n\
+            0\r\n\
+            \r\n\
+        ",
+        )
+        .unwrap();
+        let mut buf = Vec::new();
+        tcp.read_to_end(&mut buf).unwrap();
+    });
+
+    let (socket, _) = listener.accept().await.unwrap();
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let seen2 = seen.clone();
+    http1::Builder::new()
+        .max_chunk_extension_size(3)
+        .record_stats(true)
+        .on_stats(move |stats| seen2.lock().unwrap().push(stats))
+        .serve_connection(
+            TokioIo::new(socket),
+            service_fn(|req: Request<IncomingBody>| async move {
+                let body = req.into_body().collect().await?.to_bytes();
+                assert_eq!(body, "foobar");
+                Ok::<_, hyper::Error>(Response::new(Empty::<Bytes>::new()))
+            }),
+        )
+        .await
+        .expect("serve_connection");
+
+    let seen = seen.lock().unwrap();
+    assert_eq!(seen.len(), 1);
+    assert_eq!(seen[0].chunk_extension_bytes, 5);
+}
+
+#[tokio::test]
+async fn http1_reject_chunk_extensions() {
+    let (listener, addr) = setup_tcp_listener();
+
+    thread::spawn(move || {
+        let mut tcp = connect(&addr);
+        tcp.write_all(
+            b"\
+            POST / HTTP/1.1\r\n\
+            Transfer-Encoding: chunked\r\n\
+            \r\n\
+            3;a=b\r\n\
+            foo\r\n\
+            0\r\n\
+            \r\n\
+        ",
+        )
+        .unwrap();
+        let mut buf = Vec::new();
+        tcp.read_to_end(&mut buf).unwrap();
+    });
+
+    let (socket, _) = listener.accept().await.unwrap();
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let _ = http1::Builder::new()
+        .reject_chunk_extensions(true)
+        .serve_connection(
+            TokioIo::new(socket),
+            service_fn(move |req: Request<IncomingBody>| {
+                let tx = tx.clone();
+                async move {
+                    let res = req.into_body().collect().await;
+                    tx.send(res.is_err()).unwrap();
+                    Ok::<_, hyper::Error>(Response::new(Empty::<Bytes>::new()))
+                }
+            }),
+        )
+        .await;
+
+    assert!(rx.recv().await.expect("service called"), "body errored");
+}
+
+#[tokio::test]
+async fn http1_max_request_body_size_content_length() {
+    let (listener, addr) = setup_tcp_listener();
+    let (tx, rx) = mpsc::channel();
+
+    thread::spawn(move || {
+        let mut tcp = connect(&addr);
+        tcp.write_all(
+            b"\
+            POST / HTTP/1.1\r\n\
+            Content-Length: 11\r\n\
+            \r\n\
+            hello world\
+        ",
+        )
+        .unwrap();
+        let mut buf = Vec::new();
+        tcp.read_to_end(&mut buf).unwrap();
+        tx.send(buf).unwrap();
+    });
+
+    let (socket, _) = listener.accept().await.unwrap();
+    let _ = http1::Builder::new()
+        .max_request_body_size(10)
+        .serve_connection(
+            TokioIo::new(socket),
+            service_fn(|_: Request<IncomingBody>| async move {
+                panic!("service shouldn't be called");
+                #[allow(unreachable_code)]
+                Ok::<_, hyper::Error>(Response::new(Empty::<Bytes>::new()))
+            }),
+        )
+        .await;
+
+    let buf = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    let expected = "HTTP/1.1 413 Payload Too Large\r\n";
+    assert_eq!(s(&buf[..expected.len()]), expected);
+}
+
+#[tokio::test]
+async fn http1_max_request_body_size_chunked() {
+    let (listener, addr) = setup_tcp_listener();
+    let (tx, rx) = mpsc::channel();
+
+    thread::spawn(move || {
+        let mut tcp = connect(&addr);
+        tcp.write_all(
+            b"\
+            POST / HTTP/1.1\r\n\
+            Transfer-Encoding: chunked\r\n\
+            \r\n\
+            6\r\n\
+            hello \r\n\
+            5\r\n\
+            world\r\n\
+            0\r\n\
+            \r\n\
+        ",
+        )
+        .unwrap();
+        let mut buf = Vec::new();
+        tcp.read_to_end(&mut buf).unwrap();
+        tx.send(buf).unwrap();
+    });
+
+    let (socket, _) = listener.accept().await.unwrap();
+    http1::Builder::new()
+        .max_request_body_size(10)
+        .serve_connection(
+            TokioIo::new(socket),
+            service_fn(|req: Request<IncomingBody>| async move {
+                let err = req.into_body().collect().await.unwrap_err();
+                assert!(err.is_body_too_large(), "{:?}", err);
+                let mut res = Response::new(Empty::<Bytes>::new());
+                *res.status_mut() = StatusCode::PAYLOAD_TOO_LARGE;
+                Ok::<_, hyper::Error>(res)
+            }),
+        )
+        .await
+        .expect("serve_connection");
+
+    let buf = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    let expected = "HTTP/1.1 413 Payload Too Large\r\n";
+    assert_eq!(s(&buf[..expected.len()]), expected);
+}
+
+#[tokio::test]
+async fn http1_record_stats_with_clock() {
+    struct FixedClock(Instant);
+
+    impl hyper::stats::StatsClock for FixedClock {
+        fn now(&self) -> Instant {
+            self.0
+        }
+    }
+
+    let (listener, addr) = setup_tcp_listener();
+
+    thread::spawn(move || {
+        let mut tcp = connect(&addr);
+        tcp.write_all(b"GET / HTTP/1.1\r\nConnection: close\r\n\r\n")
+            .unwrap();
+        let mut buf = Vec::new();
+        tcp.read_to_end(&mut buf).unwrap();
+    });
+
+    let fixed = Instant::now() - Duration::from_secs(60);
+    let (socket, _) = listener.accept().await.unwrap();
+    let socket = TokioIo::new(socket);
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let seen2 = seen.clone();
+    http1::Builder::new()
+        .record_stats(true)
+        .stats_clock(FixedClock(fixed))
+        .on_stats(move |stats| seen2.lock().unwrap().push(stats))
+        .serve_connection(
+            socket,
+            service_fn(
+                |_| async move { Ok::<_, hyper::Error>(Response::new(Empty::<Bytes>::new())) },
+            ),
+        )
+        .await
+        .expect("serve_connection");
+
+    let seen = seen.lock().unwrap();
+    assert_eq!(seen.len(), 1);
+    assert_eq!(seen[0].headers_parsed, fixed);
+    assert_eq!(seen[0].body_complete, Some(fixed));
+    assert_eq!(seen[0].response_flushed, Some(fixed));
+}
 
 #[tokio::test]
 async fn returning_1xx_response_is_error() {
@@ -1488,6 +2237,79 @@ fn header_name_too_long() {
     assert!(s(&buf[..n]).starts_with("HTTP/1.1 431 Request Header Fields Too Large\r\n"));
 }
 
+#[tokio::test]
+async fn http1_max_headers() {
+    let (listener, addr) = setup_tcp_listener();
+
+    thread::spawn(move || {
+        let mut tcp = connect(&addr);
+        tcp.write_all(
+            b"\
+            GET / HTTP/1.1\r\n\
+            Host: example.domain\r\n\
+            X-One: 1\r\n\
+            X-Two: 2\r\n\
+            \r\n\
+        ",
+        )
+        .expect("write");
+        let mut buf = Vec::new();
+        tcp.read_to_end(&mut buf).expect("read");
+        assert!(buf.starts_with(b"HTTP/1.1 431 Request Header Fields Too Large\r\n"));
+    });
+
+    let (socket, _) = listener.accept().await.unwrap();
+    let socket = TokioIo::new(socket);
+    let err = http1::Builder::new()
+        .max_headers(2)
+        .serve_connection(socket, unreachable_service())
+        .await
+        .unwrap_err();
+    assert!(err.is_parse_too_large());
+}
+
+#[tokio::test]
+async fn http1_max_requests() {
+    let (listener, addr) = setup_tcp_listener();
+
+    thread::spawn(move || {
+        let mut tcp = connect(&addr);
+        tcp.write_all(
+            b"\
+            GET / HTTP/1.1\r\n\
+            Host: example.domain\r\n\
+            \r\n\
+            GET / HTTP/1.1\r\n\
+            Host: example.domain\r\n\
+            \r\n\
+        ",
+        )
+        .expect("write");
+        let mut buf = Vec::new();
+        tcp.read_to_end(&mut buf).expect("read");
+        let s = String::from_utf8(buf).expect("utf8");
+        assert_eq!(s.matches("HTTP/1.1 200 OK").count(), 2);
+        assert_eq!(s.matches("connection: close").count(), 1);
+    });
+
+    let (socket, _) = listener.accept().await.unwrap();
+    let socket = TokioIo::new(socket);
+    let count = Arc::new(Mutex::new(0));
+    let served = count.clone();
+    http1::Builder::new()
+        .max_requests(2)
+        .serve_connection(
+            socket,
+            service_fn(move |_| {
+                *served.lock().unwrap() += 1;
+                future::ready(Ok::<_, hyper::Error>(Response::new(Empty::<Bytes>::new())))
+            }),
+        )
+        .await
+        .unwrap();
+    assert_eq!(*count.lock().unwrap(), 2);
+}
+
 #[tokio::test]
 async fn header_read_timeout_slow_writes() {
     let (listener, addr) = setup_tcp_listener();
@@ -1677,47 +2499,334 @@ async fn header_read_timeout_as_idle_timeout() {
 }
 
 #[tokio::test]
-async fn upgrades() {
+async fn header_read_timeout_allows_slow_body() {
     let (listener, addr) = setup_tcp_listener();
-    let (tx, rx) = oneshot::channel();
 
     thread::spawn(move || {
         let mut tcp = connect(&addr);
+
         tcp.write_all(
             b"\
-            GET / HTTP/1.1\r\n\
-            Upgrade: foobar\r\n\
-            Connection: upgrade\r\n\
+            POST / HTTP/1.1\r\n\
+            Content-Length: 10\r\n\
+            Connection: close\r\n\
             \r\n\
-            eagerly optimistic\
+            hello\
         ",
         )
         .expect("write 1");
-        let mut buf = [0; 256];
-        tcp.read(&mut buf).expect("read 1");
-
-        let expected = "HTTP/1.1 101 Switching Protocols\r\n";
-        assert_eq!(s(&buf[..expected.len()]), expected);
-        let _ = tx.send(());
+        // only the head has to arrive in time
+        thread::sleep(Duration::from_secs(2));
+        tcp.write_all(b"world").expect("write 2");
 
-        let n = tcp.read(&mut buf).expect("read 2");
-        assert_eq!(s(&buf[..n]), "foo=bar");
-        tcp.write_all(b"bar=foo").expect("write 2");
+        let mut buf = Vec::new();
+        tcp.read_to_end(&mut buf).expect("read");
+        assert!(buf.starts_with(b"HTTP/1.1 200 OK\r\n"));
     });
 
     let (socket, _) = listener.accept().await.unwrap();
     let socket = TokioIo::new(socket);
-    let conn = http1::Builder::new().serve_connection(
-        socket,
-        service_fn(|_| {
-            let res = Response::builder()
-                .status(101)
-                .header("upgrade", "foobar")
-                .body(Empty::<Bytes>::new())
-                .unwrap();
-            future::ready(Ok::<_, hyper::Error>(res))
-        }),
-    );
+    let conn = http1::Builder::new()
+        .timer(TokioTimer)
+        .header_read_timeout(Duration::from_secs(1))
+        .serve_connection(
+            socket,
+            service_fn(|req: Request<IncomingBody>| async move {
+                let body = req.into_body().collect().await?.to_bytes();
+                assert_eq!(body, "helloworld");
+                Ok::<_, hyper::Error>(Response::new(Empty::<Bytes>::new()))
+            }),
+        );
+    conn.await.unwrap();
+}
+
+#[tokio::test]
+async fn keep_alive_timeout_closes_idle_connection() {
+    let (listener, addr) = setup_tcp_listener();
+    let (tx, rx) = mpsc::channel();
+
+    thread::spawn(move || {
+        let mut tcp = connect(&addr);
+        tcp.write_all(
+            b"\
+            GET / HTTP/1.1\r\n\
+            \r\n\
+        ",
+        )
+        .expect("request 1");
+
+        // the connection is closed once it has been idle long enough
+        let mut buf = Vec::new();
+        tcp.read_to_end(&mut buf).expect("read");
+        tx.send(buf).unwrap();
+    });
+
+    let (socket, _) = listener.accept().await.unwrap();
+    let socket = TokioIo::new(socket);
+    let mut conn = http1::Builder::new()
+        .timer(TokioTimer)
+        .keep_alive_timeout(Duration::from_millis(200))
+        .serve_connection(socket, HelloWorld);
+    future::poll_fn(|cx| conn.poll_without_shutdown(cx))
+        .await
+        .expect("idle close is graceful");
+    assert!(conn.keep_alive_timed_out());
+    drop(conn);
+
+    let buf = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert!(buf.starts_with(b"HTTP/1.1 200 OK\r\n"));
+}
+
+#[cfg(all(feature = "sendfile", unix))]
+fn file_body_fixture(name: &str) -> (std::path::PathBuf, Vec<u8>) {
+    // Larger than one chunk of the fallback path.
+    let contents = (0..200_000u32).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+    let path = std::env::temp_dir().join(format!("hyper-{}-{}", name, std::process::id()));
+    std::fs::write(&path, &contents).unwrap();
+    (path, contents)
+}
+
+#[cfg(all(feature = "sendfile", unix))]
+fn file_body_request(addr: SocketAddr, tx: mpsc::Sender<Vec<u8>>) {
+    thread::spawn(move || {
+        let mut tcp = connect(&addr);
+        tcp.write_all(b"GET / HTTP/1.1\r\nConnection: close\r\n\r\n")
+            .unwrap();
+        let mut buf = Vec::new();
+        tcp.read_to_end(&mut buf).unwrap();
+        tx.send(buf).unwrap();
+    });
+}
+
+#[cfg(all(feature = "sendfile", unix))]
+fn split_response(res: &[u8]) -> (&str, &[u8]) {
+    let pos = res
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .expect("end of head");
+    (s(&res[..pos]), &res[pos + 4..])
+}
+
+#[cfg(all(feature = "sendfile", unix))]
+#[tokio::test]
+async fn file_body_is_read_without_write_file() {
+    let (path, contents) = file_body_fixture("file-body-read");
+    let (listener, addr) = setup_tcp_listener();
+    let (tx, rx) = mpsc::channel();
+    file_body_request(addr, tx);
+
+    let (socket, _) = listener.accept().await.unwrap();
+    let file_path = path.clone();
+    let svc = service_fn(move |_| {
+        let file = std::fs::File::open(&file_path).unwrap();
+        future::ready(hyper::body::FileBody::new(file).map(Response::new))
+    });
+    http1::Builder::new()
+        .serve_connection(TokioIo::new(socket), svc)
+        .await
+        .unwrap();
+
+    let res = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    let (head, body) = split_response(&res);
+    assert!(head.contains("content-length: 200000"), "{}", head);
+    assert_eq!(body, &contents[..]);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[cfg(all(feature = "sendfile", target_os = "linux"))]
+#[tokio::test]
+async fn file_body_uses_write_file() {
+    file_body_write_file("file-body-sendfile", |body| body).await;
+}
+
+#[cfg(all(feature = "sendfile", target_os = "linux"))]
+#[tokio::test]
+async fn boxed_file_body_uses_write_file() {
+    file_body_write_file("file-body-sendfile-boxed", hyper::body::BoxBody::new).await;
+}
+
+#[cfg(all(feature = "sendfile", target_os = "linux"))]
+async fn file_body_write_file<B, F>(name: &str, wrap: F)
+where
+    B: Body<Data = hyper::body::FileData> + Send + 'static,
+    B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    F: Fn(hyper::body::FileBody) -> B,
+{
+    let (path, contents) = file_body_fixture(name);
+    let (listener, addr) = setup_tcp_listener();
+    let (tx, rx) = mpsc::channel();
+    file_body_request(addr, tx);
+
+    let (socket, _) = listener.accept().await.unwrap();
+    let files_sent = Arc::new(AtomicUsize::new(0));
+    let io = SendfileIo {
+        io: TokioIo::new(socket),
+        files_sent: files_sent.clone(),
+    };
+    let file_path = path.clone();
+    let svc = service_fn(move |_| {
+        let file = std::fs::File::open(&file_path).unwrap();
+        let body = hyper::body::FileBody::new(file).map(&wrap);
+        future::ready(body.map(Response::new))
+    });
+    http1::Builder::new()
+        .serve_connection(io, svc)
+        .await
+        .unwrap();
+
+    let res = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    let (head, body) = split_response(&res);
+    assert!(head.contains("content-length: 200000"), "{}", head);
+    assert_eq!(body, &contents[..]);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(files_sent.load(Ordering::SeqCst), contents.len());
+}
+
+#[cfg(all(feature = "sendfile", unix))]
+#[tokio::test]
+async fn file_body_over_h2_sends_no_empty_data() {
+    let (path, contents) = file_body_fixture("file-body-h2");
+    let (listener, addr) = setup_tcp_listener();
+
+    let file_path = path.clone();
+    tokio::spawn(async move {
+        let (socket, _) = listener.accept().await.unwrap();
+        let svc = service_fn(move |_| {
+            let file = std::fs::File::open(&file_path).unwrap();
+            future::ready(hyper::body::FileBody::new(file).map(Response::new))
+        });
+        http2::Builder::new(TokioExecutor)
+            .serve_connection(TokioIo::new(socket), svc)
+            .await
+            .unwrap();
+    });
+
+    // h2 clients hide empty DATA frames, so read the frames as sent.
+    let mut conn = connect_async(addr).await;
+    conn.write_all(b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n")
+        .await
+        .expect("client preface");
+    conn.write_all(&[0, 0, 0, 4, 0, 0, 0, 0, 0])
+        .await
+        .expect("client settings");
+    // HEADERS with END_STREAM | END_HEADERS on stream 1, of
+    // :method: GET, :path: /, :scheme: http
+    conn.write_all(&[0, 0, 3, 1, 5, 0, 0, 0, 1, 0x82, 0x84, 0x86])
+        .await
+        .expect("client request");
+
+    let mut received = Vec::new();
+    // Up to the initial window, the rest waits for a WINDOW_UPDATE.
+    while received.len() < 65_535 {
+        let mut head = [0; 9];
+        conn.read_exact(&mut head).await.expect("frame head");
+        let len = usize::from(head[0]) << 16 | usize::from(head[1]) << 8 | usize::from(head[2]);
+        let mut payload = vec![0; len];
+        conn.read_exact(&mut payload).await.expect("frame payload");
+        if head[3] == 0x0 {
+            assert_ne!(len, 0, "empty DATA frame");
+            received.extend_from_slice(&payload);
+        }
+    }
+    assert_eq!(received, contents[..65_535]);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn auto_serves_http1() {
+    let (listener, addr) = setup_tcp_listener();
+
+    thread::spawn(move || {
+        let mut tcp = connect(&addr);
+        tcp.write_all(b"GET / HTTP/1.1\r\nConnection: close\r\n\r\n")
+            .unwrap();
+        let mut buf = Vec::new();
+        tcp.read_to_end(&mut buf).unwrap();
+        assert!(buf.starts_with(b"HTTP/1.1 200 OK\r\n"));
+    });
+
+    let (socket, _) = listener.accept().await.unwrap();
+    let conn = auto::Builder::new(TokioExecutor).serve_connection(TokioIo::new(socket), HelloWorld);
+    pin!(conn);
+    conn.as_mut().await.unwrap();
+    assert_eq!(conn.version(), Some(Version::HTTP_11));
+}
+
+#[tokio::test]
+async fn auto_serves_http2() {
+    let (listener, addr) = setup_tcp_listener();
+
+    tokio::spawn(async move {
+        let (socket, _) = listener.accept().await.unwrap();
+        let mut builder = auto::Builder::new(TokioExecutor);
+        builder.http2().max_concurrent_streams(10);
+        builder
+            .serve_connection(TokioIo::new(socket), HelloWorld)
+            .await
+            .unwrap();
+    });
+
+    let io = TokioIo::new(connect_async(addr).await);
+    let (mut client, conn) = hyper::client::conn::http2::Builder::new(TokioExecutor)
+        .handshake(io)
+        .await
+        .unwrap();
+    tokio::spawn(async move {
+        conn.await.unwrap();
+    });
+
+    let req = Request::get("http://localhost/")
+        .body(Empty::<Bytes>::new())
+        .unwrap();
+    let (_, res) = client.send_request(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.version(), Version::HTTP_2);
+    let body = res.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(body, HELLO);
+}
+
+#[tokio::test]
+async fn upgrades() {
+    let (listener, addr) = setup_tcp_listener();
+    let (tx, rx) = oneshot::channel();
+
+    thread::spawn(move || {
+        let mut tcp = connect(&addr);
+        tcp.write_all(
+            b"\
+            GET / HTTP/1.1\r\n\
+            Upgrade: foobar\r\n\
+            Connection: upgrade\r\n\
+            \r\n\
+            eagerly optimistic\
+        ",
+        )
+        .expect("write 1");
+        let mut buf = [0; 256];
+        tcp.read(&mut buf).expect("read 1");
+
+        let expected = "HTTP/1.1 101 Switching Protocols\r\n";
+        assert_eq!(s(&buf[..expected.len()]), expected);
+        let _ = tx.send(());
+
+        let n = tcp.read(&mut buf).expect("read 2");
+        assert_eq!(s(&buf[..n]), "foo=bar");
+        tcp.write_all(b"bar=foo").expect("write 2");
+    });
+
+    let (socket, _) = listener.accept().await.unwrap();
+    let socket = TokioIo::new(socket);
+    let conn = http1::Builder::new().serve_connection(
+        socket,
+        service_fn(|_| {
+            let res = Response::builder()
+                .status(101)
+                .header("upgrade", "foobar")
+                .body(Empty::<Bytes>::new())
+                .unwrap();
+            future::ready(Ok::<_, hyper::Error>(res))
+        }),
+    );
 
     let parts = conn.without_shutdown().await.unwrap();
     assert_eq!(parts.read_buf, "eagerly optimistic");
@@ -1855,94 +2964,287 @@ async fn upgrades_new() {
 }
 
 #[tokio::test]
-async fn upgrades_ignored() {
+async fn upgrades_graceful_shutdown_waits_for_upgraded() {
+    use tokio::io::AsyncReadExt;
+
     let (listener, addr) = setup_tcp_listener();
+    let (client_tx, client_rx) = mpsc::channel();
 
-    tokio::spawn(async move {
-        let svc = service_fn(move |req: Request<IncomingBody>| {
-            assert_eq!(req.headers()["upgrade"], "yolo");
-            future::ok::<_, hyper::Error>(Response::new(Empty::<Bytes>::new()))
-        });
+    thread::spawn(move || {
+        let mut tcp = connect(&addr);
+        tcp.write_all(
+            b"\
+            GET / HTTP/1.1\r\n\
+            Upgrade: foobar\r\n\
+            Connection: upgrade\r\n\
+            \r\n\
+        ",
+        )
+        .expect("write");
+        let mut buf = [0; 256];
+        tcp.read(&mut buf).expect("read 101");
+        // Ends once the server closes the upgraded connection.
+        let n = tcp.read(&mut buf).expect("read eof");
+        client_tx.send(n).unwrap();
+    });
 
-        loop {
-            let (socket, _) = listener.accept().await.unwrap();
-            let socket = TokioIo::new(socket);
-            tokio::task::spawn(async move {
-                http1::Builder::new()
-                    .serve_connection(socket, svc)
-                    .with_upgrades()
-                    .await
-                    .expect("server task");
-            });
-        }
+    let (upgraded_tx, mut upgraded_rx) = tokio::sync::mpsc::unbounded_channel();
+    let svc = service_fn(move |req: Request<IncomingBody>| {
+        let on_upgrade = hyper::upgrade::on(req);
+        let upgraded_tx = upgraded_tx.clone();
+        tokio::spawn(async move {
+            let mut upgraded = TokioIo::new(on_upgrade.await.expect("on_upgrade"));
+            let mut buf = [0; 16];
+            let res = upgraded.read(&mut buf).await;
+            upgraded_tx.send(res.map_err(|e| e.kind())).unwrap();
+            // Still holding on to the upgraded connection, its transport
+            // is closed anyway.
+            TokioTimer.sleep(Duration::from_secs(30)).await;
+            drop(upgraded);
+        });
+        future::ok::<_, hyper::Error>(
+            Response::builder()
+                .status(101)
+                .header("upgrade", "foobar")
+                .body(Empty::<Bytes>::new())
+                .unwrap(),
+        )
     });
 
-    let client = TestClient::new();
-    let url = format!("http://{}/", addr);
+    let (socket, _) = listener.accept().await.unwrap();
+    let conn = http1::Builder::new()
+        .timer(TokioTimer)
+        .upgrade_grace_period(Duration::from_millis(100))
+        .serve_connection(TokioIo::new(socket), svc)
+        .with_upgrades();
+    pin!(conn);
 
-    let make_req = || {
-        hyper::Request::builder()
-            .uri(&*url)
-            .header("upgrade", "yolo")
-            .header("connection", "upgrade")
-            .body(Empty::<Bytes>::new())
-            .expect("make_req")
-    };
+    // The upgraded connection is still open, so the connection isn't done.
+    tokio::time::timeout(Duration::from_millis(100), conn.as_mut())
+        .await
+        .expect_err("connection done while upgraded connection is open");
 
-    let res1 = client.request(make_req()).await.expect("req 1");
-    assert_eq!(res1.status(), 200);
-    drop(res1);
+    conn.as_mut().graceful_shutdown();
+    let start = Instant::now();
+    conn.await.unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(50));
 
-    let res2 = client.request(make_req()).await.expect("req 2");
-    assert_eq!(res2.status(), 200);
+    let res = upgraded_rx.recv().await.unwrap();
+    assert_eq!(res, Err(io::ErrorKind::ConnectionAborted));
+    assert_eq!(client_rx.recv_timeout(Duration::from_secs(5)).unwrap(), 0);
 }
 
 #[tokio::test]
-async fn http_connect_new() {
+async fn upgrades_graceful_shutdown_downcast_upgraded() {
     let (listener, addr) = setup_tcp_listener();
-    let (read_200_tx, read_200_rx) = oneshot::channel();
+    let (client_tx, client_rx) = mpsc::channel();
 
     thread::spawn(move || {
         let mut tcp = connect(&addr);
         tcp.write_all(
             b"\
-            CONNECT localhost HTTP/1.1\r\n\
+            GET / HTTP/1.1\r\n\
+            Upgrade: foobar\r\n\
+            Connection: upgrade\r\n\
             \r\n\
-            eagerly optimistic\
         ",
         )
-        .expect("write 1");
+        .expect("write");
         let mut buf = [0; 256];
-        tcp.read(&mut buf).expect("read 1");
-
-        let expected = "HTTP/1.1 200 OK\r\n";
-        assert_eq!(s(&buf[..expected.len()]), expected);
-        let _ = read_200_tx.send(());
-
-        let n = tcp.read(&mut buf).expect("read 2");
-        assert_eq!(s(&buf[..n]), "foo=bar");
-        tcp.write_all(b"bar=foo").expect("write 2");
+        tcp.read(&mut buf).expect("read 101");
+        let n = tcp.read(&mut buf).expect("read eof");
+        client_tx.send(n).unwrap();
     });
 
-    let (upgrades_tx, upgrades_rx) = mpsc::channel();
+    let (closed_tx, mut closed_rx) = tokio::sync::mpsc::unbounded_channel();
     let svc = service_fn(move |req: Request<IncomingBody>| {
         let on_upgrade = hyper::upgrade::on(req);
-        let _ = upgrades_tx.send(on_upgrade);
+        let closed_tx = closed_tx.clone();
+        tokio::spawn(async move {
+            let upgraded = on_upgrade.await.expect("on_upgrade");
+            let parts = upgraded
+                .downcast::<TokioIo<TkTcpStream>>()
+                .expect("downcast");
+            let guard = parts.shutdown_guard.expect("shutdown_guard");
+            assert!(!guard.is_closed());
+            future::poll_fn(|cx| guard.poll_close(cx)).await;
+            // The IO is closed once the guard says so.
+            drop(parts.io);
+            closed_tx.send(()).unwrap();
+        });
         future::ok::<_, hyper::Error>(
             Response::builder()
-                .status(200)
+                .status(101)
+                .header("upgrade", "foobar")
                 .body(Empty::<Bytes>::new())
                 .unwrap(),
         )
     });
 
     let (socket, _) = listener.accept().await.unwrap();
-    let socket = TokioIo::new(socket);
-    http1::Builder::new()
-        .serve_connection(socket, svc)
-        .with_upgrades()
+    let conn = http1::Builder::new()
+        .timer(TokioTimer)
+        .upgrade_grace_period(Duration::from_millis(100))
+        .serve_connection(TokioIo::new(socket), svc)
+        .with_upgrades();
+    pin!(conn);
+
+    // Taken apart, the upgraded connection is still tracked by its guard.
+    tokio::time::timeout(Duration::from_millis(100), conn.as_mut())
         .await
-        .unwrap();
+        .expect_err("connection done while downcast connection is open");
+
+    conn.as_mut().graceful_shutdown();
+    let start = Instant::now();
+    conn.await.unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(50));
+
+    tokio::time::timeout(Duration::from_secs(5), closed_rx.recv())
+        .await
+        .expect("guard told to close")
+        .unwrap();
+    assert_eq!(client_rx.recv_timeout(Duration::from_secs(5)).unwrap(), 0);
+}
+
+#[tokio::test]
+async fn upgrades_graceful_shutdown_upgraded_dropped() {
+    let (listener, addr) = setup_tcp_listener();
+
+    thread::spawn(move || {
+        let mut tcp = connect(&addr);
+        tcp.write_all(
+            b"\
+            GET / HTTP/1.1\r\n\
+            Upgrade: foobar\r\n\
+            Connection: upgrade\r\n\
+            \r\n\
+        ",
+        )
+        .expect("write");
+        let mut buf = [0; 256];
+        tcp.read(&mut buf).expect("read 101");
+    });
+
+    let svc = service_fn(move |req: Request<IncomingBody>| {
+        let on_upgrade = hyper::upgrade::on(req);
+        tokio::spawn(async move {
+            let upgraded = on_upgrade.await.expect("on_upgrade");
+            TokioTimer.sleep(Duration::from_millis(50)).await;
+            drop(upgraded);
+        });
+        future::ok::<_, hyper::Error>(
+            Response::builder()
+                .status(101)
+                .header("upgrade", "foobar")
+                .body(Empty::<Bytes>::new())
+                .unwrap(),
+        )
+    });
+
+    let (socket, _) = listener.accept().await.unwrap();
+    let conn = http1::Builder::new()
+        .timer(TokioTimer)
+        .upgrade_grace_period(Duration::from_secs(30))
+        .serve_connection(TokioIo::new(socket), svc)
+        .with_upgrades();
+
+    // Done once the upgraded connection is dropped, not after the grace
+    // period.
+    tokio::time::timeout(Duration::from_secs(5), conn)
+        .await
+        .expect("connection done after upgraded connection dropped")
+        .unwrap();
+}
+
+#[tokio::test]
+async fn upgrades_ignored() {
+    let (listener, addr) = setup_tcp_listener();
+
+    tokio::spawn(async move {
+        let svc = service_fn(move |req: Request<IncomingBody>| {
+            assert_eq!(req.headers()["upgrade"], "yolo");
+            future::ok::<_, hyper::Error>(Response::new(Empty::<Bytes>::new()))
+        });
+
+        loop {
+            let (socket, _) = listener.accept().await.unwrap();
+            let socket = TokioIo::new(socket);
+            tokio::task::spawn(async move {
+                http1::Builder::new()
+                    .serve_connection(socket, svc)
+                    .with_upgrades()
+                    .await
+                    .expect("server task");
+            });
+        }
+    });
+
+    let client = TestClient::new();
+    let url = format!("http://{}/", addr);
+
+    let make_req = || {
+        hyper::Request::builder()
+            .uri(&*url)
+            .header("upgrade", "yolo")
+            .header("connection", "upgrade")
+            .body(Empty::<Bytes>::new())
+            .expect("make_req")
+    };
+
+    let res1 = client.request(make_req()).await.expect("req 1");
+    assert_eq!(res1.status(), 200);
+    drop(res1);
+
+    let res2 = client.request(make_req()).await.expect("req 2");
+    assert_eq!(res2.status(), 200);
+}
+
+#[tokio::test]
+async fn http_connect_new() {
+    let (listener, addr) = setup_tcp_listener();
+    let (read_200_tx, read_200_rx) = oneshot::channel();
+
+    thread::spawn(move || {
+        let mut tcp = connect(&addr);
+        tcp.write_all(
+            b"\
+            CONNECT localhost HTTP/1.1\r\n\
+            \r\n\
+            eagerly optimistic\
+        ",
+        )
+        .expect("write 1");
+        let mut buf = [0; 256];
+        tcp.read(&mut buf).expect("read 1");
+
+        let expected = "HTTP/1.1 200 OK\r\n";
+        assert_eq!(s(&buf[..expected.len()]), expected);
+        let _ = read_200_tx.send(());
+
+        let n = tcp.read(&mut buf).expect("read 2");
+        assert_eq!(s(&buf[..n]), "foo=bar");
+        tcp.write_all(b"bar=foo").expect("write 2");
+    });
+
+    let (upgrades_tx, upgrades_rx) = mpsc::channel();
+    let svc = service_fn(move |req: Request<IncomingBody>| {
+        let on_upgrade = hyper::upgrade::on(req);
+        let _ = upgrades_tx.send(on_upgrade);
+        future::ok::<_, hyper::Error>(
+            Response::builder()
+                .status(200)
+                .body(Empty::<Bytes>::new())
+                .unwrap(),
+        )
+    });
+
+    let (socket, _) = listener.accept().await.unwrap();
+    let socket = TokioIo::new(socket);
+    http1::Builder::new()
+        .serve_connection(socket, svc)
+        .with_upgrades()
+        .await
+        .unwrap();
 
     let on_upgrade = upgrades_rx.recv().unwrap();
 
@@ -2136,147 +3438,1126 @@ async fn h2_connect_multiplex() {
         .unwrap();
 }
 
-#[tokio::test]
-async fn h2_connect_large_body() {
-    let (listener, addr) = setup_tcp_listener();
+#[tokio::test]
+async fn h2_connect_large_body() {
+    let (listener, addr) = setup_tcp_listener();
+    let conn = connect_async(addr).await;
+
+    let (h2, connection) = h2::client::handshake(conn).await.unwrap();
+    tokio::spawn(async move {
+        connection.await.unwrap();
+    });
+    let mut h2 = h2.ready().await.unwrap();
+
+    const NO_BREAD: &str = "All work and no bread makes nox a dull boy.\n";
+
+    async fn connect_and_recv_bread(
+        h2: &mut SendRequest<Bytes>,
+    ) -> (RecvStream, SendStream<Bytes>) {
+        let request = Request::connect("localhost").body(()).unwrap();
+        let (response, send_stream) = h2.send_request(request, false).unwrap();
+        let response = response.await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let mut body = response.into_body();
+        let bytes = body.data().await.unwrap().unwrap();
+        assert_eq!(&bytes[..], b"Bread?");
+        let _ = body.flow_control().release_capacity(bytes.len());
+
+        (body, send_stream)
+    }
+
+    tokio::spawn(async move {
+        let (mut recv_stream, mut send_stream) = connect_and_recv_bread(&mut h2).await;
+
+        let large_body = Bytes::from(NO_BREAD.repeat(9000));
+
+        send_stream.send_data(large_body.clone(), false).unwrap();
+        send_stream.send_data(large_body, true).unwrap();
+
+        assert!(recv_stream.data().await.unwrap().unwrap().is_empty());
+    });
+
+    let svc = service_fn(move |req: Request<IncomingBody>| {
+        let on_upgrade = hyper::upgrade::on(req);
+
+        tokio::spawn(async move {
+            let mut upgraded = TokioIo::new(on_upgrade.await.expect("on_upgrade"));
+            upgraded.write_all(b"Bread?").await.unwrap();
+
+            let mut vec = vec![];
+            if upgraded.read_to_end(&mut vec).await.is_err() {
+                return;
+            }
+            assert_eq!(vec.len(), NO_BREAD.len() * 9000 * 2);
+
+            upgraded.shutdown().await.unwrap();
+        });
+
+        future::ok::<_, hyper::Error>(
+            Response::builder()
+                .status(200)
+                .body(Empty::<Bytes>::new())
+                .unwrap(),
+        )
+    });
+
+    let (socket, _) = listener.accept().await.unwrap();
+    let socket = TokioIo::new(socket);
+    http2::Builder::new(TokioExecutor)
+        .serve_connection(socket, svc)
+        //.with_upgrades()
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn h2_connect_empty_frames() {
+    let (listener, addr) = setup_tcp_listener();
+    let conn = connect_async(addr).await;
+
+    let (h2, connection) = h2::client::handshake(conn).await.unwrap();
+    tokio::spawn(async move {
+        connection.await.unwrap();
+    });
+    let mut h2 = h2.ready().await.unwrap();
+
+    async fn connect_and_recv_bread(
+        h2: &mut SendRequest<Bytes>,
+    ) -> (RecvStream, SendStream<Bytes>) {
+        let request = Request::connect("localhost").body(()).unwrap();
+        let (response, send_stream) = h2.send_request(request, false).unwrap();
+        let response = response.await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let mut body = response.into_body();
+        let bytes = body.data().await.unwrap().unwrap();
+        assert_eq!(&bytes[..], b"Bread?");
+        let _ = body.flow_control().release_capacity(bytes.len());
+
+        (body, send_stream)
+    }
+
+    tokio::spawn(async move {
+        let (mut recv_stream, mut send_stream) = connect_and_recv_bread(&mut h2).await;
+
+        send_stream.send_data("".into(), false).unwrap();
+        send_stream.send_data("".into(), false).unwrap();
+        send_stream.send_data("".into(), false).unwrap();
+        send_stream.send_data("Baguette!".into(), false).unwrap();
+        send_stream.send_data("".into(), true).unwrap();
+
+        assert!(recv_stream.data().await.unwrap().unwrap().is_empty());
+    });
+
+    let svc = service_fn(move |req: Request<IncomingBody>| {
+        let on_upgrade = hyper::upgrade::on(req);
+
+        tokio::spawn(async move {
+            let mut upgraded = TokioIo::new(on_upgrade.await.expect("on_upgrade"));
+            upgraded.write_all(b"Bread?").await.unwrap();
+
+            let mut vec = vec![];
+            upgraded.read_to_end(&mut vec).await.unwrap();
+            assert_eq!(s(&vec), "Baguette!");
+
+            upgraded.shutdown().await.unwrap();
+        });
+
+        future::ok::<_, hyper::Error>(
+            Response::builder()
+                .status(200)
+                .body(Empty::<Bytes>::new())
+                .unwrap(),
+        )
+    });
+
+    let (socket, _) = listener.accept().await.unwrap();
+    let socket = TokioIo::new(socket);
+    http2::Builder::new(TokioExecutor)
+        .serve_connection(socket, svc)
+        //.with_upgrades()
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn h2_extended_connect() {
+    use hyper::ext::Protocol;
+
+    let (listener, addr) = setup_tcp_listener();
+
+    let svc = service_fn(move |req: Request<IncomingBody>| {
+        if req.method() != Method::CONNECT {
+            return future::ok::<_, hyper::Error>(Response::new(Empty::<Bytes>::new()));
+        }
+        assert_eq!(
+            req.extensions()
+                .get::<Protocol>()
+                .expect("protocol extension")
+                .as_str(),
+            "websocket"
+        );
+        assert_eq!(req.uri().path(), "/chat");
+        let on_upgrade = hyper::upgrade::on(req);
+
+        tokio::spawn(async move {
+            let mut upgraded = TokioIo::new(on_upgrade.await.expect("on_upgrade"));
+            upgraded.write_all(b"Bread?").await.unwrap();
+
+            let mut vec = vec![];
+            upgraded.read_to_end(&mut vec).await.unwrap();
+            assert_eq!(s(&vec), "Baguette!");
+
+            upgraded.shutdown().await.unwrap();
+        });
+
+        future::ok::<_, hyper::Error>(Response::new(Empty::<Bytes>::new()))
+    });
+
+    tokio::spawn(async move {
+        let (socket, _) = listener.accept().await.unwrap();
+        let socket = TokioIo::new(socket);
+        http2::Builder::new(TokioExecutor)
+            .enable_connect_protocol()
+            .serve_connection(socket, svc)
+            .await
+            .unwrap();
+    });
+
+    let io = TokioIo::new(connect_async(addr).await);
+    let (mut client, conn) = hyper::client::conn::http2::Builder::new(TokioExecutor)
+        .handshake(io)
+        .await
+        .unwrap();
+    tokio::spawn(async move {
+        conn.await.unwrap();
+    });
+
+    // a first round trip makes sure the server's SETTINGS were received
+    let req = Request::get("http://localhost/")
+        .body(Empty::<Bytes>::new())
+        .unwrap();
+    client.send_request(req).await.unwrap();
+
+    let mut req = Request::connect("http://localhost/chat")
+        .body(Empty::<Bytes>::new())
+        .unwrap();
+    req.extensions_mut()
+        .insert(Protocol::from_static("websocket"));
+    let (_, res) = client.send_request(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let mut upgraded = TokioIo::new(hyper::upgrade::on(res).await.expect("on_upgrade"));
+    let mut buf = [0; 6];
+    upgraded.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"Bread?");
+    upgraded.write_all(b"Baguette!").await.unwrap();
+    upgraded.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn h2_extended_connect_not_enabled() {
+    let (listener, addr) = setup_tcp_listener();
+
+    tokio::spawn(async move {
+        let (socket, _) = listener.accept().await.unwrap();
+        let socket = TokioIo::new(socket);
+        let _ = http2::Builder::new(TokioExecutor)
+            .serve_connection(socket, HelloWorld)
+            .await;
+    });
+
+    let io = TokioIo::new(connect_async(addr).await);
+    let (mut client, conn) = hyper::client::conn::http2::Builder::new(TokioExecutor)
+        .handshake(io)
+        .await
+        .unwrap();
+    tokio::spawn(async move {
+        let _ = conn.await;
+    });
+
+    let req = Request::get("http://localhost/")
+        .body(Empty::<Bytes>::new())
+        .unwrap();
+    client.send_request(req).await.unwrap();
+
+    let mut req = Request::connect("http://localhost/chat")
+        .body(Empty::<Bytes>::new())
+        .unwrap();
+    req.extensions_mut()
+        .insert(hyper::ext::Protocol::from_static("websocket"));
+    client
+        .send_request(req)
+        .await
+        .expect_err("extended CONNECT without the peer's SETTINGS");
+}
+
+#[tokio::test]
+async fn h2_priority_signal() {
+    use hyper::ext::Priority;
+
+    let (listener, addr) = setup_tcp_listener();
+
+    tokio::spawn(async move {
+        let (socket, _) = listener.accept().await.unwrap();
+        let socket = TokioIo::new(socket);
+        let svc = service_fn(|req: Request<IncomingBody>| {
+            let priority = req.extensions().get::<Priority>().copied();
+            let expected = match req.uri().path() {
+                "/prefetch" => Some(Priority::new(6, true)),
+                "/explicit" => Some(Priority::new(1, false)),
+                _ => None,
+            };
+            assert_eq!(priority, expected, "{}", req.uri());
+            future::ok::<_, hyper::Error>(Response::new(Empty::<Bytes>::new()))
+        });
+        http2::Builder::new(TokioExecutor)
+            .serve_connection(socket, svc)
+            .await
+            .unwrap();
+    });
+
+    let io = TokioIo::new(connect_async(addr).await);
+    let (mut client, conn) = hyper::client::conn::http2::Builder::new(TokioExecutor)
+        .handshake(io)
+        .await
+        .unwrap();
+    tokio::spawn(async move {
+        conn.await.unwrap();
+    });
+
+    let mut req = Request::get("http://localhost/prefetch")
+        .body(Empty::<Bytes>::new())
+        .unwrap();
+    req.extensions_mut().insert(Priority::new(6, true));
+    let (_, res) = client.send_request(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    // an explicit header wins over the extension
+    let mut req = Request::get("http://localhost/explicit")
+        .header("priority", "u=1")
+        .body(Empty::<Bytes>::new())
+        .unwrap();
+    req.extensions_mut().insert(Priority::new(6, true));
+    let (_, res) = client.send_request(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let req = Request::get("http://localhost/none")
+        .body(Empty::<Bytes>::new())
+        .unwrap();
+    let (_, res) = client.send_request(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn h2_peer_settings() {
+    use hyper::H2Settings;
+
+    let (listener, addr) = setup_tcp_listener();
+
+    let server = tokio::spawn(async move {
+        let (socket, _) = listener.accept().await.unwrap();
+        let socket = TokioIo::new(socket);
+        let svc = service_fn(|req: Request<IncomingBody>| {
+            let settings = req
+                .extensions()
+                .get::<H2Settings>()
+                .copied()
+                .expect("request settings");
+            assert_eq!(settings.initial_window_size, Some(100_000));
+            assert_eq!(settings.max_header_list_size, Some(4096));
+            future::ok::<_, hyper::Error>(Response::new(Empty::<Bytes>::new()))
+        });
+        let conn = http2::Builder::new(TokioExecutor)
+            .max_concurrent_streams(7)
+            .max_frame_size(32_768)
+            .serve_connection(socket, svc);
+        tokio::pin!(conn);
+        (&mut conn).await.unwrap();
+        conn.peer_settings().expect("connection settings")
+    });
+
+    let io = TokioIo::new(connect_async(addr).await);
+    let (mut client, conn) = hyper::client::conn::http2::Builder::new(TokioExecutor)
+        .initial_stream_window_size(100_000)
+        .max_header_list_size(4096)
+        .handshake(io)
+        .await
+        .unwrap();
+    tokio::spawn(async move {
+        conn.await.unwrap();
+    });
+
+    let req = Request::get("http://localhost/")
+        .body(Empty::<Bytes>::new())
+        .unwrap();
+    let (_, res) = client.send_request(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let settings = res
+        .extensions()
+        .get::<H2Settings>()
+        .copied()
+        .expect("response settings");
+    assert_eq!(settings.max_concurrent_streams, Some(7));
+    assert_eq!(settings.max_frame_size, Some(32_768));
+    assert_eq!(client.peer_settings(), Some(settings));
+
+    drop(res);
+    drop(client);
+    let settings = server.await.unwrap();
+    assert_eq!(settings.initial_window_size, Some(100_000));
+}
+
+#[tokio::test]
+async fn h2_connection_max_header_list_size() {
+    let (listener, addr) = setup_tcp_listener();
+
+    tokio::spawn(async move {
+        let (socket, _) = listener.accept().await.unwrap();
+        let socket = TokioIo::new(socket);
+        let conn = http2::Builder::new(TokioExecutor)
+            .max_header_list_size(16_384)
+            .serve_connection(socket, HelloWorld);
+        pin!(conn);
+        conn.as_mut().max_header_list_size(1024);
+        conn.await.unwrap();
+    });
+
+    let io = TokioIo::new(connect_async(addr).await);
+    let (mut client, conn) = hyper::client::conn::http2::Builder::new(TokioExecutor)
+        .handshake(io)
+        .await
+        .unwrap();
+    tokio::spawn(async move {
+        conn.await.unwrap();
+    });
+
+    let req = Request::get("http://localhost/")
+        .body(Empty::<Bytes>::new())
+        .unwrap();
+    let (_, res) = client.send_request(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let settings = client.peer_settings().expect("peer settings");
+    assert_eq!(settings.max_header_list_size, Some(1024));
+    drop(res);
+
+    let req = Request::get("http://localhost/")
+        .header("x-large", "a".repeat(2048))
+        .body(Empty::<Bytes>::new())
+        .unwrap();
+    let (_, res) = client.send_request(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE);
+}
+
+#[tokio::test]
+async fn h2_max_headers() {
+    let (listener, addr) = setup_tcp_listener();
+
+    tokio::spawn(async move {
+        let (socket, _) = listener.accept().await.unwrap();
+        let socket = TokioIo::new(socket);
+        http2::Builder::new(TokioExecutor)
+            .max_headers(2)
+            .serve_connection(socket, HelloWorld)
+            .await
+            .unwrap();
+    });
+
+    let io = TokioIo::new(connect_async(addr).await);
+    let (mut client, conn) = hyper::client::conn::http2::Builder::new(TokioExecutor)
+        .handshake(io)
+        .await
+        .unwrap();
+    tokio::spawn(async move {
+        conn.await.unwrap();
+    });
+
+    let req = Request::get("http://localhost/")
+        .header("x-one", "1")
+        .header("x-two", "2")
+        .body(Empty::<Bytes>::new())
+        .unwrap();
+    let (_, res) = client.send_request(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    drop(res);
+
+    let req = Request::get("http://localhost/")
+        .header("x-one", "1")
+        .header("x-two", "2")
+        .header("x-three", "3")
+        .body(Empty::<Bytes>::new())
+        .unwrap();
+    let (_, res) = client.send_request(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE);
+}
+
+#[tokio::test]
+async fn h2_max_request_body_size() {
+    let (listener, addr) = setup_tcp_listener();
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+
+    tokio::spawn(async move {
+        let (socket, _) = listener.accept().await.unwrap();
+        let socket = TokioIo::new(socket);
+        http2::Builder::new(TokioExecutor)
+            .max_request_body_size(10)
+            .serve_connection(
+                socket,
+                service_fn(move |req: Request<IncomingBody>| {
+                    let tx = tx.clone();
+                    async move {
+                        let res = req.into_body().collect().await;
+                        let too_large = res.map_err(|e| e.is_body_too_large()).err();
+                        tx.send(too_large).unwrap();
+                        Ok::<_, hyper::Error>(Response::new(Empty::<Bytes>::new()))
+                    }
+                }),
+            )
+            .await
+            .unwrap();
+    });
+
+    let io = TokioIo::new(connect_async(addr).await);
+    let (mut client, conn) = hyper::client::conn::http2::Builder::new(TokioExecutor)
+        .handshake(io)
+        .await
+        .unwrap();
+    tokio::spawn(async move {
+        conn.await.unwrap();
+    });
+
+    let req = Request::post("http://localhost/")
+        .body(BoxBody::new(Full::new(Bytes::from("hello world"))))
+        .unwrap();
+    let (_, res) = client.send_request(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    let chunks = ["hello ", "world"]
+        .iter()
+        .map(|&c| Ok(hyper::body::Frame::data(Bytes::from(c))));
+    let req = Request::post("http://localhost/")
+        .body(BoxBody::new(StreamBody::new(futures_util::stream::iter(
+            chunks,
+        ))))
+        .unwrap();
+    let _ = client.send_request(req).await;
+    assert_eq!(rx.recv().await.expect("service called"), Some(true));
+}
+
+#[tokio::test]
+async fn h2_max_request_body_size_ends_body() {
+    let (listener, addr) = setup_tcp_listener();
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+
+    tokio::spawn(async move {
+        let (socket, _) = listener.accept().await.unwrap();
+        let socket = TokioIo::new(socket);
+        http2::Builder::new(TokioExecutor)
+            .max_request_body_size(10)
+            .serve_connection(
+                socket,
+                service_fn(move |req: Request<IncomingBody>| {
+                    let tx = tx.clone();
+                    async move {
+                        let mut body = req.into_body();
+                        let mut seen = Vec::new();
+                        while let Some(frame) = body.frame().await {
+                            match frame {
+                                Ok(frame) => seen.push(format!("{:?}", frame.into_data())),
+                                Err(e) => {
+                                    assert!(e.is_body_too_large(), "{:?}", e);
+                                    seen.push("too large".to_owned());
+                                }
+                            }
+                        }
+                        tx.send(seen).unwrap();
+                        Ok::<_, hyper::Error>(Response::new(Empty::<Bytes>::new()))
+                    }
+                }),
+            )
+            .await
+            .unwrap();
+    });
+
+    let io = TokioIo::new(connect_async(addr).await);
+    let (mut client, conn) = hyper::client::conn::http2::Builder::new(TokioExecutor)
+        .handshake(io)
+        .await
+        .unwrap();
+    tokio::spawn(async move {
+        let _ = conn.await;
+    });
+
+    // The last chunk would fit in what is left of the limit, but the body
+    // must not carry on after the error.
+    let (body_tx, body_rx) = futures_channel::mpsc::unbounded();
+    let req = Request::post("http://localhost/")
+        .body(BoxBody::new(StreamBody::new(body_rx)))
+        .unwrap();
+    let res = tokio::spawn(client.send_request(req));
+    for chunk in ["hello ", "world!!", "x"] {
+        body_tx
+            .unbounded_send(Ok::<_, std::convert::Infallible>(hyper::body::Frame::data(
+                Bytes::from(chunk),
+            )))
+            .unwrap();
+    }
+    drop(body_tx);
+
+    let seen = rx.recv().await.expect("service called");
+    assert_eq!(seen, ["Ok(b\"hello \")", "too large"]);
+    let _ = res.await;
+}
+
+#[tokio::test]
+async fn h2_max_concurrent_requests() {
+    let (listener, addr) = setup_tcp_listener();
+    let (calls_tx, mut calls_rx) = tokio::sync::mpsc::unbounded_channel();
+    let (release_tx, release_rx) = oneshot::channel::<()>();
+    let release_rx = Arc::new(Mutex::new(Some(release_rx)));
+
+    tokio::spawn(async move {
+        let (socket, _) = listener.accept().await.unwrap();
+        let socket = TokioIo::new(socket);
+        http2::Builder::new(TokioExecutor)
+            .max_concurrent_requests(1)
+            .serve_connection(
+                socket,
+                service_fn(move |req: Request<IncomingBody>| {
+                    calls_tx.send(req.uri().path().to_owned()).unwrap();
+                    let release = if req.uri().path() == "/slow" {
+                        release_rx.lock().unwrap().take()
+                    } else {
+                        None
+                    };
+                    async move {
+                        if let Some(release) = release {
+                            release.await.unwrap();
+                        }
+                        Ok::<_, hyper::Error>(Response::new(Empty::<Bytes>::new()))
+                    }
+                }),
+            )
+            .await
+            .unwrap();
+    });
+
+    let io = TokioIo::new(connect_async(addr).await);
+    let (client, conn) = hyper::client::conn::http2::Builder::new(TokioExecutor)
+        .handshake(io)
+        .await
+        .unwrap();
+    tokio::spawn(async move {
+        conn.await.unwrap();
+    });
+
+    let send = |path: &'static str| {
+        let mut client = client.clone();
+        tokio::spawn(async move {
+            let req = Request::get(format!("http://localhost{}", path))
+                .body(Empty::<Bytes>::new())
+                .unwrap();
+            client.send_request(req).await.unwrap().1.status()
+        })
+    };
+
+    let slow = send("/slow");
+    assert_eq!(calls_rx.recv().await.unwrap(), "/slow");
+    let fast = send("/fast");
+
+    // The second request waits for the first service future.
+    let waited = tokio::time::timeout(Duration::from_millis(100), calls_rx.recv()).await;
+    assert!(waited.is_err(), "second request was served early");
+
+    release_tx.send(()).unwrap();
+    assert_eq!(calls_rx.recv().await.unwrap(), "/fast");
+    assert_eq!(slow.await.unwrap(), StatusCode::OK);
+    assert_eq!(fast.await.unwrap(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn h2_max_requests() {
+    let (listener, addr) = setup_tcp_listener();
+    let (done_tx, done_rx) = oneshot::channel();
+
+    tokio::spawn(async move {
+        let (socket, _) = listener.accept().await.unwrap();
+        let socket = TokioIo::new(socket);
+        let res = http2::Builder::new(TokioExecutor)
+            .max_requests(2)
+            .serve_connection(socket, HelloWorld)
+            .await;
+        let _ = done_tx.send(res);
+    });
+
+    let io = TokioIo::new(connect_async(addr).await);
+    let (mut client, conn) = hyper::client::conn::http2::Builder::new(TokioExecutor)
+        .handshake(io)
+        .await
+        .unwrap();
+    let draining = conn.on_draining();
+    tokio::spawn(async move {
+        let _ = conn.await;
+    });
+
+    for _ in 0..2 {
+        let req = Request::get("http://localhost/")
+            .body(Empty::<Bytes>::new())
+            .unwrap();
+        let (_, res) = client.send_request(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    let go_away = draining.await.expect("goaway");
+    assert_eq!(go_away.reason(), 0);
+    done_rx.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn h2_service_reset_stream() {
+    use hyper::ext::ResetStream;
+
+    let (listener, addr) = setup_tcp_listener();
+
+    tokio::spawn(async move {
+        let (socket, _) = listener.accept().await.unwrap();
+        let socket = TokioIo::new(socket);
+        let svc = service_fn(|req: Request<IncomingBody>| async move {
+            if req.uri().path() == "/cancel" {
+                let reset = req
+                    .extensions()
+                    .get::<ResetStream>()
+                    .cloned()
+                    .expect("reset stream");
+                reset.reset(0x8);
+                // the stream is gone, so this is never answered
+                future::pending::<()>().await;
+            }
+            Ok::<_, hyper::Error>(Response::new(Empty::<Bytes>::new()))
+        });
+        http2::Builder::new(TokioExecutor)
+            .serve_connection(socket, svc)
+            .await
+            .unwrap();
+    });
+
+    let conn = connect_async(addr).await;
+    let (mut h2, connection) = h2::client::handshake(conn).await.unwrap();
+    tokio::spawn(async move {
+        connection.await.unwrap();
+    });
+
+    let req = Request::get("http://localhost/cancel").body(()).unwrap();
+    let (response, _) = h2.send_request(req, true).unwrap();
+    let err = response.await.expect_err("stream was reset");
+    assert_eq!(err.reason(), Some(h2::Reason::CANCEL));
+
+    // the connection is still good
+    let mut h2 = h2.ready().await.unwrap();
+    let req = Request::get("http://localhost/").body(()).unwrap();
+    let (response, _) = h2.send_request(req, true).unwrap();
+    assert_eq!(response.await.unwrap().status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn h2_stream_id_extension() {
+    use hyper::ext::StreamId;
+
+    let (listener, addr) = setup_tcp_listener();
+
+    tokio::spawn(async move {
+        let (socket, _) = listener.accept().await.unwrap();
+        let socket = TokioIo::new(socket);
+        let svc = service_fn(|req: Request<IncomingBody>| async move {
+            let id = req.extensions().get::<StreamId>().expect("stream id");
+            let res = Response::builder()
+                .header("x-stream-id", id.as_u32())
+                .body(Empty::<Bytes>::new())
+                .unwrap();
+            Ok::<_, hyper::Error>(res)
+        });
+        http2::Builder::new(TokioExecutor)
+            .serve_connection(socket, svc)
+            .await
+            .unwrap();
+    });
+
+    let io = TokioIo::new(connect_async(addr).await);
+    let (mut client, conn) = hyper::client::conn::http2::Builder::new(TokioExecutor)
+        .handshake(io)
+        .await
+        .unwrap();
+    tokio::spawn(async move {
+        conn.await.unwrap();
+    });
+
+    // client streams are odd, and numbered in order
+    for expected in [1, 3] {
+        let req = Request::get("http://localhost/")
+            .body(Empty::<Bytes>::new())
+            .unwrap();
+        let (_, res) = client.send_request(req).await.unwrap();
+        let id = res.extensions().get::<StreamId>().expect("stream id");
+        assert_eq!(id.as_u32(), expected);
+        assert_eq!(res.headers()["x-stream-id"], expected.to_string());
+    }
+}
+
+#[tokio::test]
+async fn h2_extended_connect_enabled() {
+    for enabled in [false, true] {
+        let (listener, addr) = setup_tcp_listener();
+
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let socket = TokioIo::new(socket);
+            let mut builder = http2::Builder::new(TokioExecutor);
+            if enabled {
+                builder.enable_connect_protocol();
+            }
+            let conn = builder.serve_connection(socket, HelloWorld);
+            assert_eq!(conn.is_extended_connect_enabled(), enabled);
+            conn.await.unwrap();
+        });
+
+        let io = TokioIo::new(connect_async(addr).await);
+        let (mut client, conn) = hyper::client::conn::http2::Builder::new(TokioExecutor)
+            .handshake(io)
+            .await
+            .unwrap();
+        tokio::spawn(async move {
+            conn.await.unwrap();
+        });
+
+        // the server's SETTINGS are read ahead of any response
+        let req = Request::get("http://localhost/")
+            .body(Empty::<Bytes>::new())
+            .unwrap();
+        let (_, res) = client.send_request(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(client.is_extended_connect_enabled(), Some(enabled));
+    }
+}
+
+#[tokio::test]
+async fn h2_client_on_draining() {
+    let (listener, addr) = setup_tcp_listener();
+
+    tokio::spawn(async move {
+        let (socket, _) = listener.accept().await.unwrap();
+        let conn =
+            http2::Builder::new(TokioExecutor).serve_connection(TokioIo::new(socket), HelloWorld);
+        pin!(conn);
+        conn.as_mut().graceful_shutdown();
+        conn.await.unwrap();
+
+        // this one is closed without a GOAWAY
+        let (socket, _) = listener.accept().await.unwrap();
+        drop(socket);
+    });
+
+    let io = TokioIo::new(connect_async(addr).await);
+    let (_client, conn) = hyper::client::conn::http2::Builder::new(TokioExecutor)
+        .handshake::<_, Empty<Bytes>>(io)
+        .await
+        .unwrap();
+    let draining = conn.on_draining();
+    tokio::spawn(async move {
+        let _ = conn.await;
+    });
+    let go_away = draining.await.expect("goaway");
+    assert_eq!(go_away.reason(), 0);
+
+    let io = TokioIo::new(connect_async(addr).await);
+    let (_client, conn) = hyper::client::conn::http2::Builder::new(TokioExecutor)
+        .handshake::<_, Empty<Bytes>>(io)
+        .await
+        .unwrap();
+    let draining = conn.on_draining();
+    tokio::spawn(async move {
+        let _ = conn.await;
+    });
+    assert_eq!(draining.await, None);
+}
+
+#[tokio::test]
+async fn h2_origin_set() {
+    let (listener, addr) = setup_tcp_listener();
+
+    tokio::spawn(async move {
+        let (socket, _) = listener.accept().await.unwrap();
+        let socket = TokioIo::new(socket);
+        http2::Builder::new(TokioExecutor)
+            .origin_set(["https://a.example", "https://b.example"])
+            .serve_connection(socket, HelloWorld)
+            .await
+            .unwrap();
+    });
+
+    let io = TokioIo::new(connect_async(addr).await);
+    let (mut client, conn) = hyper::client::conn::http2::Builder::new(TokioExecutor)
+        .handshake(io)
+        .await
+        .unwrap();
+    tokio::spawn(async move {
+        conn.await.unwrap();
+    });
+
+    // the ORIGIN frame is ahead of any response
+    let req = Request::get("http://localhost/")
+        .body(Empty::<Bytes>::new())
+        .unwrap();
+    let (_, res) = client.send_request(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(
+        client.peer_origin_set(),
+        Some(vec![
+            "https://a.example".to_owned(),
+            "https://b.example".to_owned()
+        ])
+    );
+}
+
+#[tokio::test]
+async fn h2_alt_svc() {
+    use hyper::ext::AltSvc;
+
+    let (listener, addr) = setup_tcp_listener();
+
+    tokio::spawn(async move {
+        let (socket, _) = listener.accept().await.unwrap();
+        let socket = TokioIo::new(socket);
+        http2::Builder::new(TokioExecutor)
+            .alt_svc(AltSvc::new("https://a.example", "h3=\":443\""))
+            .serve_connection(socket, HelloWorld)
+            .await
+            .unwrap();
+    });
+
+    let io = TokioIo::new(connect_async(addr).await);
+    let (mut client, conn) = hyper::client::conn::http2::Builder::new(TokioExecutor)
+        .handshake(io)
+        .await
+        .unwrap();
+    tokio::spawn(async move {
+        conn.await.unwrap();
+    });
+
+    let req = Request::get("http://localhost/")
+        .body(Empty::<Bytes>::new())
+        .unwrap();
+    let (_, res) = client.send_request(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(
+        client.peer_alt_svc(),
+        vec![AltSvc::new("https://a.example", "h3=\":443\"")]
+    );
+}
+
+#[tokio::test]
+async fn h2_flow_control_target_window() {
+    use futures_util::future::poll_fn;
+    use hyper::ext::FlowControl;
+
+    let (listener, addr) = setup_tcp_listener();
+
+    tokio::spawn(async move {
+        let (socket, _) = listener.accept().await.unwrap();
+        let socket = TokioIo::new(socket);
+        let svc = service_fn(|req: Request<IncomingBody>| async move {
+            let flow = req
+                .extensions()
+                .get::<FlowControl>()
+                .cloned()
+                .expect("flow control");
+            flow.set_target_window_size(16_384);
+            assert_eq!(flow.target_window_size(), Some(16_384));
+            let body = req.into_body().collect().await?.to_bytes();
+            assert_eq!(body.len(), 65_535);
+            Ok::<_, hyper::Error>(Response::new(Empty::<Bytes>::new()))
+        });
+        http2::Builder::new(TokioExecutor)
+            .initial_stream_window_size(65_535)
+            .serve_connection(socket, svc)
+            .await
+            .unwrap();
+    });
+
     let conn = connect_async(addr).await;
-
     let (h2, connection) = h2::client::handshake(conn).await.unwrap();
     tokio::spawn(async move {
         connection.await.unwrap();
     });
     let mut h2 = h2.ready().await.unwrap();
 
-    const NO_BREAD: &str = "All work and no bread makes nox a dull boy.\n";
-
-    async fn connect_and_recv_bread(
-        h2: &mut SendRequest<Bytes>,
-    ) -> (RecvStream, SendStream<Bytes>) {
-        let request = Request::connect("localhost").body(()).unwrap();
-        let (response, send_stream) = h2.send_request(request, false).unwrap();
-        let response = response.await.unwrap();
-        assert_eq!(response.status(), StatusCode::OK);
-
-        let mut body = response.into_body();
-        let bytes = body.data().await.unwrap().unwrap();
-        assert_eq!(&bytes[..], b"Bread?");
-        let _ = body.flow_control().release_capacity(bytes.len());
+    let req = Request::post("http://localhost/").body(()).unwrap();
+    let (response, mut send_stream) = h2.send_request(req, false).unwrap();
+    send_stream
+        .send_data(Bytes::from(vec![0; 65_535]), false)
+        .unwrap();
 
-        (body, send_stream)
+    // the whole window was used, and the server only gives back its target
+    send_stream.reserve_capacity(65_535);
+    while send_stream.capacity() < 16_384 {
+        poll_fn(|cx| send_stream.poll_capacity(cx))
+            .await
+            .expect("capacity")
+            .expect("capacity");
     }
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(send_stream.capacity(), 16_384);
 
-    tokio::spawn(async move {
-        let (mut recv_stream, mut send_stream) = connect_and_recv_bread(&mut h2).await;
-
-        let large_body = Bytes::from(NO_BREAD.repeat(9000));
+    send_stream.send_data(Bytes::new(), true).unwrap();
+    let response = response.await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
 
-        send_stream.send_data(large_body.clone(), false).unwrap();
-        send_stream.send_data(large_body, true).unwrap();
+#[tokio::test]
+async fn h2_ping_rtt() {
+    let (listener, addr) = setup_tcp_listener();
 
-        assert!(recv_stream.data().await.unwrap().unwrap().is_empty());
+    let server = tokio::spawn(async move {
+        let (socket, _) = listener.accept().await.unwrap();
+        let socket = TokioIo::new(socket);
+        let conn = http2::Builder::new(TokioExecutor).serve_connection(socket, HelloWorld);
+        pin!(conn);
+        // asked for before the handshake, so sent once it's done
+        let rtt = conn.as_mut().ping();
+        let (res, rtt) = future::join(conn, rtt).await;
+        res.unwrap();
+        rtt
     });
 
-    let svc = service_fn(move |req: Request<IncomingBody>| {
-        let on_upgrade = hyper::upgrade::on(req);
+    let io = TokioIo::new(connect_async(addr).await);
+    let (client, conn) = hyper::client::conn::http2::Builder::new(TokioExecutor)
+        .handshake::<_, Empty<Bytes>>(io)
+        .await
+        .unwrap();
+    let conn = tokio::spawn(conn);
 
-        tokio::spawn(async move {
-            let mut upgraded = TokioIo::new(on_upgrade.await.expect("on_upgrade"));
-            upgraded.write_all(b"Bread?").await.unwrap();
+    let rtt = client.ping().await.expect("client ping");
+    assert!(rtt < Duration::from_secs(5));
+    // once acknowledged, another ping can be sent
+    client.ping().await.expect("second client ping");
 
-            let mut vec = vec![];
-            if upgraded.read_to_end(&mut vec).await.is_err() {
-                return;
-            }
-            assert_eq!(vec.len(), NO_BREAD.len() * 9000 * 2);
+    drop(client);
+    conn.await.unwrap().unwrap();
+    let rtt = server.await.unwrap().expect("server ping");
+    assert!(rtt < Duration::from_secs(5));
+}
 
-            upgraded.shutdown().await.unwrap();
-        });
+async fn reset_streams(action: http2::ResetStreamAction) -> (hyper::Error, Result<(), h2::Error>) {
+    let (listener, addr) = setup_tcp_listener();
 
-        future::ok::<_, hyper::Error>(
-            Response::builder()
-                .status(200)
-                .body(Empty::<Bytes>::new())
-                .unwrap(),
-        )
+    let server = tokio::spawn(async move {
+        let (socket, _) = listener.accept().await.unwrap();
+        let socket = TokioIo::new(socket);
+        http2::Builder::new(TokioExecutor)
+            .max_reset_stream_rate(5, Duration::from_secs(10))
+            .reset_stream_rate_action(action)
+            .serve_connection(socket, HelloWorld)
+            .await
+            .expect_err("server should close the connection")
     });
 
-    let (socket, _) = listener.accept().await.unwrap();
-    let socket = TokioIo::new(socket);
-    http2::Builder::new(TokioExecutor)
-        .serve_connection(socket, svc)
-        //.with_upgrades()
-        .await
-        .unwrap();
+    let conn = connect_async(addr).await;
+    let (mut h2, connection) = h2::client::handshake(conn).await.unwrap();
+    let connection = tokio::spawn(connection);
+    for _ in 0..10 {
+        h2 = h2.ready().await.unwrap();
+        let req = Request::post("http://localhost/").body(()).unwrap();
+        let (_, mut send_stream) = h2.send_request(req, false).unwrap();
+        send_stream.send_reset(h2::Reason::CANCEL);
+    }
+
+    (server.await.unwrap(), connection.await.unwrap())
 }
 
 #[tokio::test]
-async fn h2_connect_empty_frames() {
-    let (listener, addr) = setup_tcp_listener();
-    let conn = connect_async(addr).await;
-
-    let (h2, connection) = h2::client::handshake(conn).await.unwrap();
-    tokio::spawn(async move {
-        connection.await.unwrap();
-    });
-    let mut h2 = h2.ready().await.unwrap();
+async fn h2_reset_stream_rate_go_away() {
+    let (server_err, client_res) = reset_streams(http2::ResetStreamAction::GoAway).await;
+    assert!(server_err.to_string().contains("http2"), "{}", server_err);
+    let client_err = client_res.expect_err("client connection should fail");
+    assert!(client_err.is_go_away(), "{}", client_err);
+    assert_eq!(client_err.reason(), Some(h2::Reason::ENHANCE_YOUR_CALM));
+}
 
-    async fn connect_and_recv_bread(
-        h2: &mut SendRequest<Bytes>,
-    ) -> (RecvStream, SendStream<Bytes>) {
-        let request = Request::connect("localhost").body(()).unwrap();
-        let (response, send_stream) = h2.send_request(request, false).unwrap();
-        let response = response.await.unwrap();
-        assert_eq!(response.status(), StatusCode::OK);
+#[tokio::test]
+async fn h2_reset_stream_rate_drop() {
+    let (server_err, client_res) = reset_streams(http2::ResetStreamAction::Drop).await;
+    assert!(server_err.to_string().contains("http2"), "{}", server_err);
+    // closed without a GOAWAY
+    if let Err(client_err) = client_res {
+        assert!(!client_err.is_go_away(), "{}", client_err);
+    }
+}
 
-        let mut body = response.into_body();
-        let bytes = body.data().await.unwrap().unwrap();
-        assert_eq!(&bytes[..], b"Bread?");
-        let _ = body.flow_control().release_capacity(bytes.len());
+async fn read_go_aways(addr: SocketAddr, count: usize) -> Vec<Vec<u8>> {
+    let mut conn = connect_async(addr).await;
+    conn.write_all(b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n")
+        .await
+        .expect("client preface");
+    conn.write_all(&[0, 0, 0, 4, 0, 0, 0, 0, 0])
+        .await
+        .expect("client settings");
 
-        (body, send_stream)
+    let mut go_aways = Vec::new();
+    while go_aways.len() < count {
+        let mut head = [0; 9];
+        conn.read_exact(&mut head).await.expect("frame head");
+        let len = usize::from(head[0]) << 16 | usize::from(head[1]) << 8 | usize::from(head[2]);
+        let mut payload = vec![0; len];
+        conn.read_exact(&mut payload).await.expect("frame payload");
+        if head[3] == 0x7 {
+            go_aways.push(payload);
+        }
     }
+    go_aways
+}
 
-    tokio::spawn(async move {
-        let (mut recv_stream, mut send_stream) = connect_and_recv_bread(&mut h2).await;
+#[tokio::test]
+async fn h2_go_away_immediate_with_debug_data() {
+    use hyper::server::conn::http2::GoAway;
 
-        send_stream.send_data("".into(), false).unwrap();
-        send_stream.send_data("".into(), false).unwrap();
-        send_stream.send_data("".into(), false).unwrap();
-        send_stream.send_data("Baguette!".into(), false).unwrap();
-        send_stream.send_data("".into(), true).unwrap();
+    let (listener, addr) = setup_tcp_listener();
 
-        assert!(recv_stream.data().await.unwrap().unwrap().is_empty());
+    tokio::spawn(async move {
+        let (socket, _) = listener.accept().await.unwrap();
+        let socket = TokioIo::new(socket);
+        let conn = http2::Builder::new(TokioExecutor).serve_connection(socket, HelloWorld);
+        pin!(conn);
+        conn.as_mut()
+            .go_away(GoAway::immediate(0xb).debug_data("draining"));
+        conn.await.expect_err("ENHANCE_YOUR_CALM is an error");
     });
 
-    let svc = service_fn(move |req: Request<IncomingBody>| {
-        let on_upgrade = hyper::upgrade::on(req);
-
-        tokio::spawn(async move {
-            let mut upgraded = TokioIo::new(on_upgrade.await.expect("on_upgrade"));
-            upgraded.write_all(b"Bread?").await.unwrap();
+    let go_aways = read_go_aways(addr, 2).await;
+    assert_eq!(go_aways[0], b"\0\0\0\0\0\0\0\x0b");
+    assert_eq!(go_aways[1], b"\0\0\0\0\0\0\0\x0bdraining");
+}
 
-            let mut vec = vec![];
-            upgraded.read_to_end(&mut vec).await.unwrap();
-            assert_eq!(s(&vec), "Baguette!");
+#[tokio::test]
+async fn h2_go_away_graceful_with_debug_data() {
+    use hyper::server::conn::http2::GoAway;
 
-            upgraded.shutdown().await.unwrap();
-        });
+    let (listener, addr) = setup_tcp_listener();
 
-        future::ok::<_, hyper::Error>(
-            Response::builder()
-                .status(200)
-                .body(Empty::<Bytes>::new())
-                .unwrap(),
-        )
+    tokio::spawn(async move {
+        let (socket, _) = listener.accept().await.unwrap();
+        let socket = TokioIo::new(socket);
+        let conn = http2::Builder::new(TokioExecutor).serve_connection(socket, HelloWorld);
+        pin!(conn);
+        conn.as_mut()
+            .go_away(GoAway::graceful().debug_data("draining"));
+        let _ = conn.await;
     });
 
-    let (socket, _) = listener.accept().await.unwrap();
-    let socket = TokioIo::new(socket);
-    http2::Builder::new(TokioExecutor)
-        .serve_connection(socket, svc)
-        //.with_upgrades()
-        .await
-        .unwrap();
+    // the first phase names the maximum stream ID
+    let go_aways = read_go_aways(addr, 2).await;
+    assert_eq!(go_aways[0], b"\x7f\xff\xff\xff\0\0\0\0");
+    assert_eq!(go_aways[1], b"\x7f\xff\xff\xff\0\0\0\0draining");
 }
 
 #[tokio::test]
@@ -2301,6 +4582,47 @@ async fn parse_errors_send_4xx_response() {
         .expect_err("HTTP parse error");
 }
 
+#[tokio::test]
+async fn parse_errors_send_custom_error_response() {
+    let (listener, addr) = setup_tcp_listener();
+
+    let child = thread::spawn(move || {
+        let mut tcp = connect(&addr);
+        tcp.write_all(b"GE T / HTTP/1.1\r\n\r\n").unwrap();
+        let mut resp = String::new();
+        tcp.read_to_string(&mut resp).expect("read");
+        resp
+    });
+
+    let (socket, _) = listener.accept().await.unwrap();
+    let socket = TokioIo::new(socket);
+    http1::Builder::new()
+        .error_response(|err, status| {
+            assert!(err.is_parse());
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+            Response::builder()
+                .status(StatusCode::IM_A_TEAPOT)
+                .header("x-error-id", "abc123")
+                .header("content-length", "1000")
+                .body(Bytes::from("malformed request"))
+                .unwrap()
+        })
+        .serve_connection(socket, HelloWorld)
+        .await
+        .expect_err("HTTP parse error");
+
+    let resp = child.join().unwrap();
+    assert!(
+        resp.starts_with("HTTP/1.1 418 I'm a teapot\r\n"),
+        "{}",
+        resp
+    );
+    assert!(resp.contains("\r\nx-error-id: abc123\r\n"), "{}", resp);
+    assert!(resp.contains("\r\ncontent-length: 17\r\n"), "{}", resp);
+    assert!(resp.contains("\r\nconnection: close\r\n"), "{}", resp);
+    assert!(resp.ends_with("\r\n\r\nmalformed request"), "{}", resp);
+}
+
 #[tokio::test]
 async fn illegal_request_length_returns_400_response() {
     let (listener, addr) = setup_tcp_listener();
@@ -2614,34 +4936,149 @@ async fn http2_keep_alive_detects_unresponsive_client() {
         }
     });
 
-    let (socket, _) = listener.accept().await.expect("accept");
-    let socket = TokioIo::new(socket);
+    let (socket, _) = listener.accept().await.expect("accept");
+    let socket = TokioIo::new(socket);
+
+    let err = http2::Builder::new(TokioExecutor)
+        .timer(TokioTimer)
+        .keep_alive_interval(Duration::from_secs(1))
+        .keep_alive_timeout(Duration::from_secs(1))
+        .auto_date_header(true)
+        .serve_connection(socket, unreachable_service())
+        .await
+        .expect_err("serve_connection should error");
+
+    assert!(err.is_timeout());
+}
+
+#[tokio::test]
+async fn http2_keep_alive_with_responsive_client() {
+    let (listener, addr) = setup_tcp_listener();
+
+    tokio::spawn(async move {
+        let (socket, _) = listener.accept().await.expect("accept");
+        let socket = TokioIo::new(socket);
+
+        http2::Builder::new(TokioExecutor)
+            .timer(TokioTimer)
+            .keep_alive_interval(Duration::from_secs(1))
+            .keep_alive_timeout(Duration::from_secs(1))
+            .serve_connection(socket, HelloWorld)
+            .await
+            .expect("serve_connection");
+    });
+
+    let tcp = TokioIo::new(connect_async(addr).await);
+    let (mut client, conn) = hyper::client::conn::http2::Builder::new(TokioExecutor)
+        .handshake(tcp)
+        .await
+        .expect("http handshake");
+
+    tokio::spawn(async move {
+        conn.await.expect("client conn");
+    });
+
+    TokioTimer.sleep(Duration::from_secs(4)).await;
+
+    let req = http::Request::new(Empty::<Bytes>::new());
+    client.send_request(req).await.expect("client.send_request");
+}
+
+#[tokio::test]
+async fn http2_check_date_header_disabled() {
+    let (listener, addr) = setup_tcp_listener();
+
+    tokio::spawn(async move {
+        let (socket, _) = listener.accept().await.expect("accept");
+        let socket = TokioIo::new(socket);
+
+        http2::Builder::new(TokioExecutor)
+            .timer(TokioTimer)
+            .keep_alive_interval(Duration::from_secs(1))
+            .auto_date_header(false)
+            .keep_alive_timeout(Duration::from_secs(1))
+            .serve_connection(socket, HelloWorld)
+            .await
+            .expect("serve_connection");
+    });
+
+    let tcp = TokioIo::new(connect_async(addr).await);
+    let (mut client, conn) = hyper::client::conn::http2::Builder::new(TokioExecutor)
+        .handshake(tcp)
+        .await
+        .expect("http handshake");
+
+    tokio::spawn(async move {
+        conn.await.expect("client conn");
+    });
+
+    TokioTimer.sleep(Duration::from_secs(4)).await;
+
+    let req = http::Request::new(Empty::<Bytes>::new());
+    let resp = client
+        .send_request(req)
+        .await
+        .expect("client.send_request")
+        .1;
+
+    assert!(resp.headers().get("Date").is_none());
+}
+
+/// Always answers with the same date.
+struct FixedDate;
+
+impl hyper::server::conn::DateProvider for FixedDate {
+    fn date(&self) -> HeaderValue {
+        HeaderValue::from_static("Sun, 06 Nov 1994 08:49:37 GMT")
+    }
+}
+
+#[tokio::test]
+async fn http1_date_provider() {
+    let (listener, addr) = setup_tcp_listener();
+    let (tx, rx) = mpsc::channel();
 
-    let err = http2::Builder::new(TokioExecutor)
-        .timer(TokioTimer)
-        .keep_alive_interval(Duration::from_secs(1))
-        .keep_alive_timeout(Duration::from_secs(1))
-        .auto_date_header(true)
-        .serve_connection(socket, unreachable_service())
+    thread::spawn(move || {
+        let mut tcp = connect(&addr);
+        tcp.write_all(
+            b"\
+            GET / HTTP/1.1\r\n\
+            Host: example.domain\r\n\
+            Connection: close\r\n\
+            \r\n\
+        ",
+        )
+        .expect("write");
+        let mut buf = Vec::new();
+        tcp.read_to_end(&mut buf).expect("read");
+        tx.send(String::from_utf8(buf).expect("utf8")).unwrap();
+    });
+
+    let (socket, _) = listener.accept().await.unwrap();
+    http1::Builder::new()
+        .date_provider(FixedDate)
+        .serve_connection(TokioIo::new(socket), HelloWorld)
         .await
-        .expect_err("serve_connection should error");
+        .unwrap();
 
-    assert!(err.is_timeout());
+    let res = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(res.matches("date: ").count(), 1, "{}", res);
+    assert!(
+        res.contains("date: Sun, 06 Nov 1994 08:49:37 GMT\r\n"),
+        "{}",
+        res
+    );
 }
 
 #[tokio::test]
-async fn http2_keep_alive_with_responsive_client() {
+async fn http2_date_provider() {
     let (listener, addr) = setup_tcp_listener();
 
     tokio::spawn(async move {
         let (socket, _) = listener.accept().await.expect("accept");
-        let socket = TokioIo::new(socket);
-
         http2::Builder::new(TokioExecutor)
-            .timer(TokioTimer)
-            .keep_alive_interval(Duration::from_secs(1))
-            .keep_alive_timeout(Duration::from_secs(1))
-            .serve_connection(socket, HelloWorld)
+            .date_provider(FixedDate)
+            .serve_connection(TokioIo::new(socket), HelloWorld)
             .await
             .expect("serve_connection");
     });
@@ -2651,55 +5088,153 @@ async fn http2_keep_alive_with_responsive_client() {
         .handshake(tcp)
         .await
         .expect("http handshake");
-
     tokio::spawn(async move {
         conn.await.expect("client conn");
     });
 
-    TokioTimer.sleep(Duration::from_secs(4)).await;
-
     let req = http::Request::new(Empty::<Bytes>::new());
-    client.send_request(req).await.expect("client.send_request");
+    let (_, res) = client.send_request(req).await.expect("client.send_request");
+    assert_eq!(res.headers()["date"], "Sun, 06 Nov 1994 08:49:37 GMT");
+}
+
+fn early_hints() -> Response<()> {
+    Response::builder()
+        .status(StatusCode::EARLY_HINTS)
+        .header("link", "</style.css>; rel=preload; as=style")
+        .body(())
+        .unwrap()
 }
 
 #[tokio::test]
-async fn http2_check_date_header_disabled() {
+async fn http1_send_informational() {
+    let (listener, addr) = setup_tcp_listener();
+    let (tx, rx) = mpsc::channel();
+
+    thread::spawn(move || {
+        let mut tcp = connect(&addr);
+        tcp.write_all(
+            b"\
+            GET / HTTP/1.1\r\n\
+            Host: example.domain\r\n\
+            Connection: close\r\n\
+            \r\n\
+        ",
+        )
+        .expect("write");
+        let mut buf = Vec::new();
+        tcp.read_to_end(&mut buf).expect("read");
+        tx.send(String::from_utf8(buf).expect("utf8")).unwrap();
+    });
+
+    let (handle_tx, handle_rx) = mpsc::channel();
+    let (socket, _) = listener.accept().await.unwrap();
+    http1::Builder::new()
+        .serve_connection(
+            TokioIo::new(socket),
+            service_fn(move |req: Request<IncomingBody>| {
+                let informational = req
+                    .extensions()
+                    .get::<hyper::ext::SendInformational>()
+                    .expect("SendInformational")
+                    .clone();
+                informational.send(early_hints()).unwrap();
+                informational.send(early_hints()).unwrap();
+                assert!(informational.send(Response::new(())).is_err());
+                handle_tx.send(informational).unwrap();
+                async move {
+                    TokioTimer.sleep(Duration::from_millis(10)).await;
+                    Ok::<_, hyper::Error>(Response::new(Full::new(Bytes::from("hello"))))
+                }
+            }),
+        )
+        .await
+        .unwrap();
+
+    let informational = handle_rx.recv().unwrap();
+    assert!(informational.is_closed());
+    assert!(informational.send(early_hints()).is_err());
+
+    let res = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    let hints = "HTTP/1.1 103 Early Hints\r\nlink: </style.css>; rel=preload; as=style\r\n\r\n";
+    assert!(res.starts_with(&hints.repeat(2)), "{}", res);
+    assert!(
+        res[hints.len() * 2..].starts_with("HTTP/1.1 200 OK\r\n"),
+        "{}",
+        res
+    );
+    assert!(res.ends_with("hello"), "{}", res);
+}
+
+#[tokio::test]
+async fn http1_send_informational_not_to_http_10() {
+    let (listener, addr) = setup_tcp_listener();
+
+    thread::spawn(move || {
+        let mut tcp = connect(&addr);
+        tcp.write_all(b"GET / HTTP/1.0\r\n\r\n").expect("write");
+        let mut buf = Vec::new();
+        tcp.read_to_end(&mut buf).expect("read");
+    });
+
+    let (socket, _) = listener.accept().await.unwrap();
+    http1::Builder::new()
+        .serve_connection(
+            TokioIo::new(socket),
+            service_fn(|req: Request<IncomingBody>| {
+                assert!(req
+                    .extensions()
+                    .get::<hyper::ext::SendInformational>()
+                    .is_none());
+                future::ok::<_, hyper::Error>(Response::new(Empty::<Bytes>::new()))
+            }),
+        )
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn h2_send_informational() {
     let (listener, addr) = setup_tcp_listener();
 
     tokio::spawn(async move {
         let (socket, _) = listener.accept().await.expect("accept");
-        let socket = TokioIo::new(socket);
-
         http2::Builder::new(TokioExecutor)
-            .timer(TokioTimer)
-            .keep_alive_interval(Duration::from_secs(1))
-            .auto_date_header(false)
-            .keep_alive_timeout(Duration::from_secs(1))
-            .serve_connection(socket, HelloWorld)
+            .serve_connection(
+                TokioIo::new(socket),
+                service_fn(|req: Request<IncomingBody>| {
+                    let informational = req
+                        .extensions()
+                        .get::<hyper::ext::SendInformational>()
+                        .expect("SendInformational");
+                    informational.send(early_hints()).unwrap();
+                    future::ok::<_, hyper::Error>(Response::new(Empty::<Bytes>::new()))
+                }),
+            )
             .await
             .expect("serve_connection");
     });
 
-    let tcp = TokioIo::new(connect_async(addr).await);
-    let (mut client, conn) = hyper::client::conn::http2::Builder::new(TokioExecutor)
-        .handshake(tcp)
-        .await
-        .expect("http handshake");
-
+    let conn = connect_async(addr).await;
+    let (h2, connection) = h2::client::handshake(conn).await.unwrap();
     tokio::spawn(async move {
-        conn.await.expect("client conn");
+        connection.await.unwrap();
     });
+    let mut h2 = h2.ready().await.unwrap();
 
-    TokioTimer.sleep(Duration::from_secs(4)).await;
-
-    let req = http::Request::new(Empty::<Bytes>::new());
-    let resp = client
-        .send_request(req)
+    let req = Request::get("https://localhost/").body(()).unwrap();
+    let (mut response, _) = h2.send_request(req, true).unwrap();
+    let hints = future::poll_fn(|cx| response.poll_informational(cx))
         .await
-        .expect("client.send_request")
-        .1;
+        .expect("informational")
+        .unwrap();
+    assert_eq!(hints.status(), StatusCode::EARLY_HINTS);
+    assert_eq!(
+        hints.headers()["link"],
+        "</style.css>; rel=preload; as=style"
+    );
 
-    assert!(resp.headers().get("Date").is_none());
+    let response = response.await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
 }
 
 fn is_ping_frame(buf: &[u8]) -> bool {
@@ -2788,6 +5323,54 @@ async fn http2_keep_alive_count_server_pings() {
         .expect("timed out waiting for pings");
 }
 
+#[tokio::test]
+async fn http2_keep_alive_not_while_idle() {
+    let (listener, addr) = setup_tcp_listener();
+
+    tokio::spawn(async move {
+        let (socket, _) = listener.accept().await.expect("accept");
+        let socket = TokioIo::new(socket);
+
+        http2::Builder::new(TokioExecutor)
+            .timer(TokioTimer)
+            .keep_alive_interval(Duration::from_secs(1))
+            .keep_alive_timeout(Duration::from_secs(1))
+            .keep_alive_while_idle(false)
+            .serve_connection(socket, unreachable_service())
+            .await
+            .expect("serve_connection");
+    });
+
+    let mut conn = connect_async(addr).await;
+
+    // write h2 magic preface and settings frame
+    conn.write_all(b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n")
+        .await
+        .expect("client preface");
+    conn.write_all(&[
+        0, 0, 0, // len
+        4, // kind
+        0, // flag
+        0, 0, 0, 0, // stream id
+    ])
+    .await
+    .expect("client settings");
+
+    let read_frames = async {
+        let mut buf = [0u8; 1024];
+        loop {
+            let n = conn.read(&mut buf).await.expect("client.read");
+            assert!(n != 0, "connection closed");
+            assert!(!is_ping_frame(&buf), "ping while idle");
+        }
+    };
+
+    // No pings, and the connection stays open, past the interval and timeout
+    tokio::time::timeout(Duration::from_secs(3), read_frames)
+        .await
+        .expect_err("idle connection");
+}
+
 #[test]
 fn http1_trailer_send_fields() {
     let body = futures_util::stream::once(async move { Ok("hello".into()) });
@@ -2876,6 +5459,103 @@ fn http1_trailer_fields_not_allowed() {
     assert_eq!(body, expected_body);
 }
 
+#[tokio::test]
+async fn http1_with_trailers_body() {
+    use hyper::body::WithTrailers;
+
+    let (listener, addr) = setup_tcp_listener();
+
+    tokio::spawn(async move {
+        let (socket, _) = listener.accept().await.unwrap();
+        let socket = TokioIo::new(socket);
+        let svc = service_fn(|_req| async move {
+            let (body, sender) = WithTrailers::new(Full::new(Bytes::from("hello")));
+            // the trailers can come after the response has started
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                let mut trailers = HeaderMap::new();
+                trailers.insert("chunky-trailer", "header data".parse().unwrap());
+                sender.send_trailers(trailers).unwrap();
+            });
+            let res = Response::builder()
+                .header("trailer", "chunky-trailer")
+                .body(body)
+                .unwrap();
+            Ok::<_, hyper::Error>(res)
+        });
+        http1::Builder::new()
+            .serve_connection(socket, svc)
+            .await
+            .unwrap();
+    });
+
+    let mut req = connect_async(addr).await;
+    req.write_all(
+        b"\
+        GET / HTTP/1.1\r\n\
+        Host: example.domain\r\n\
+        Connection: close\r\n\
+        TE: trailers\r\n\
+        \r\n\
+    ",
+    )
+    .await
+    .expect("writing");
+
+    let mut res = Vec::new();
+    req.read_to_end(&mut res).await.expect("reading");
+    let sres = s(&res);
+    assert!(sres.contains("transfer-encoding: chunked\r\n"), "{}", sres);
+    assert!(
+        sres.ends_with("5\r\nhello\r\n0\r\nchunky-trailer: header data\r\n\r\n"),
+        "{}",
+        sres
+    );
+}
+
+#[tokio::test]
+async fn h2_with_trailers_body() {
+    use hyper::body::WithTrailers;
+
+    let (listener, addr) = setup_tcp_listener();
+
+    tokio::spawn(async move {
+        let (socket, _) = listener.accept().await.unwrap();
+        let socket = TokioIo::new(socket);
+        let svc = service_fn(|_req| async move {
+            let (body, sender) = WithTrailers::new(Full::new(Bytes::from("hello")));
+            let mut trailers = HeaderMap::new();
+            trailers.insert("grpc-status", "0".parse().unwrap());
+            sender.send_trailers(trailers).unwrap();
+            Ok::<_, hyper::Error>(Response::new(body))
+        });
+        http2::Builder::new(TokioExecutor)
+            .serve_connection(socket, svc)
+            .await
+            .unwrap();
+    });
+
+    let io = TokioIo::new(connect_async(addr).await);
+    let (mut client, conn) = hyper::client::conn::http2::Builder::new(TokioExecutor)
+        .handshake(io)
+        .await
+        .unwrap();
+    tokio::spawn(async move {
+        conn.await.unwrap();
+    });
+
+    let req = Request::get("http://localhost/")
+        .body(Empty::<Bytes>::new())
+        .unwrap();
+    let (_, res) = client.send_request(req).await.unwrap();
+    let collected = res.into_body().collect().await.unwrap();
+    assert_eq!(
+        collected.trailers().and_then(|t| t.get("grpc-status")),
+        Some(&HeaderValue::from_static("0"))
+    );
+    assert_eq!(collected.to_bytes(), "hello");
+}
+
 #[test]
 fn http1_trailer_recv_fields() {
     let server = serve();
@@ -3410,6 +6090,80 @@ impl<T: AsyncRead + Unpin, D: Unpin> AsyncRead for DebugStream<T, D> {
     }
 }
 
+/// Writes file bodies with `sendfile`, counting the bytes sent that way.
+#[cfg(all(feature = "sendfile", target_os = "linux"))]
+struct SendfileIo {
+    io: TokioIo<TkTcpStream>,
+    files_sent: Arc<AtomicUsize>,
+}
+
+#[cfg(all(feature = "sendfile", target_os = "linux"))]
+impl Stats for SendfileIo {
+    fn stats(&mut self) -> Option<hyper::rt::ConnectionStats> {
+        None
+    }
+}
+
+#[cfg(all(feature = "sendfile", target_os = "linux"))]
+impl AsyncRead for SendfileIo {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: hyper::rt::ReadBufCursor<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.io).poll_read(cx, buf)
+    }
+}
+
+#[cfg(all(feature = "sendfile", target_os = "linux"))]
+impl AsyncWrite for SendfileIo {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        Pin::new(&mut self.io).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        Pin::new(&mut self.io).poll_flush(cx)
+    }
+
+    fn poll_shutdown(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), io::Error>> {
+        Pin::new(&mut self.io).poll_shutdown(cx)
+    }
+
+    fn is_write_file(&self) -> bool {
+        true
+    }
+
+    fn poll_write_file(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        file: &hyper::body::FileRegion,
+    ) -> Poll<Result<usize, io::Error>> {
+        use std::os::unix::io::AsRawFd;
+
+        let tcp = self.io.get_ref();
+        loop {
+            futures_util::ready!(tcp.poll_write_ready(cx))?;
+            match tcp.try_io(tokio::io::Interest::WRITABLE, || {
+                file.send_to(tcp.as_raw_fd())
+            }) {
+                Ok(n) => {
+                    self.files_sent.fetch_add(n, Ordering::SeqCst);
+                    return Poll::Ready(Ok(n));
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+                Err(e) => return Poll::Ready(Err(e)),
+            }
+        }
+    }
+}
+
 #[derive(Clone)]
 struct Dropped(Arc<AtomicBool>);
 